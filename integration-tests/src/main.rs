@@ -1,7 +1,9 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 mod perf_test_multi_transport;
+mod replay;
 
 #[derive(Parser)]
 #[command(name = "throttlecrab-integration-tests")]
@@ -31,6 +33,38 @@ enum Commands {
         #[arg(short = 'T', long, default_value = "http")]
         transport: String,
     },
+    /// Replay a recorded request trace against a running server
+    Replay {
+        /// Trace CSV file, e.g. saved with export-trace
+        #[arg(long)]
+        trace: PathBuf,
+
+        /// Replay speed relative to the recording (2.0 = twice as fast)
+        #[arg(short, long, default_value = "1.0")]
+        speed: f64,
+
+        /// Maximum requests in flight
+        #[arg(short, long, default_value = "64")]
+        concurrency: usize,
+
+        /// Server port
+        #[arg(short, long, default_value = "58080")]
+        port: u16,
+
+        /// Transport type (http, grpc, redis)
+        #[arg(short = 'T', long, default_value = "http")]
+        transport: String,
+    },
+    /// Save a server's recent requests as a trace, from GET /admin/trace
+    ExportTrace {
+        /// File to write the trace to
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// HTTP port of the server
+        #[arg(short, long, default_value = "58080")]
+        port: u16,
+    },
 }
 
 #[tokio::main]
@@ -55,6 +89,18 @@ async fn main() -> Result<()> {
             perf_test_multi_transport::run_performance_test(threads, requests, port, &transport)
                 .await?;
         }
+        Commands::Replay {
+            trace,
+            speed,
+            concurrency,
+            port,
+            transport,
+        } => {
+            replay::run_replay(&trace, port, &transport, speed, concurrency).await?;
+        }
+        Commands::ExportTrace { output, port } => {
+            replay::export_trace(port, &output).await?;
+        }
     }
 
     Ok(())
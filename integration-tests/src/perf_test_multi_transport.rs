@@ -141,6 +141,12 @@ async fn grpc_worker(
             count_per_period: 10,
             period: 60,
             quantity: 1,
+            retry_hints: false,
+            policy: String::new(),
+            algorithm: String::new(),
+            operation: String::new(),
+            namespace: String::new(),
+            windows: vec![],
         });
     }
 
@@ -231,7 +237,7 @@ async fn redis_worker(
         let result: Result<Value, _> = cmd.query_async(&mut con).await;
 
         match result {
-            Ok(Value::Array(ref values)) if values.len() == 5 => {
+            Ok(Value::Array(ref values)) if values.len() >= 5 => {
                 let latency = start.elapsed();
                 latencies.push(latency);
                 stats.total_requests.fetch_add(1, Ordering::Relaxed);
@@ -433,7 +439,7 @@ pub async fn run_performance_test(
     Ok(())
 }
 
-fn percentile(sorted_values: &[Duration], p: f64) -> Duration {
+pub(crate) fn percentile(sorted_values: &[Duration], p: f64) -> Duration {
     if sorted_values.is_empty() {
         return Duration::ZERO;
     }
@@ -0,0 +1,271 @@
+//! Replay recorded request traces against a server
+//!
+//! Traces are CSV files in the format of `throttlecrab_server::trace`, as
+//! exported from a server's `GET /admin/trace` with `export-trace`. Each
+//! request is sent at its recorded offset from the first one, divided by
+//! the speed factor, so `--speed 2` replays an hour of traffic in half an
+//! hour.
+
+use crate::perf_test_multi_transport::{Transport, percentile};
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use throttlecrab_server::trace::{self, TraceRecord};
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinSet;
+
+/// Connection used to send replayed requests
+#[derive(Clone)]
+enum Client {
+    Http {
+        client: reqwest::Client,
+        url: String,
+    },
+    Grpc(
+        throttlecrab_server::grpc::rate_limiter_client::RateLimiterClient<
+            tonic::transport::Channel,
+        >,
+    ),
+    Redis(redis::aio::MultiplexedConnection),
+}
+
+impl Client {
+    async fn connect(transport: &Transport, port: u16) -> Result<Self> {
+        Ok(match transport {
+            Transport::Http => Client::Http {
+                client: reqwest::Client::builder()
+                    .pool_max_idle_per_host(64)
+                    .build()?,
+                url: format!("http://127.0.0.1:{port}/throttle"),
+            },
+            Transport::Grpc => Client::Grpc(
+                throttlecrab_server::grpc::rate_limiter_client::RateLimiterClient::connect(
+                    format!("http://127.0.0.1:{port}"),
+                )
+                .await?,
+            ),
+            Transport::Redis => Client::Redis(
+                redis::Client::open(format!("redis://127.0.0.1:{port}/"))?
+                    .get_multiplexed_async_connection()
+                    .await?,
+            ),
+        })
+    }
+
+    /// Send one recorded request, returning whether it was allowed
+    async fn throttle(&mut self, record: &TraceRecord) -> Result<bool> {
+        match self {
+            Client::Http { client, url } => {
+                let body: serde_json::Value = client
+                    .post(url.as_str())
+                    .json(&json!({
+                        "key": record.key,
+                        "max_burst": record.max_burst,
+                        "count_per_period": record.count_per_period,
+                        "period": record.period,
+                        "quantity": record.quantity,
+                    }))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                body["allowed"]
+                    .as_bool()
+                    .context("response has no allowed field")
+            }
+            Client::Grpc(client) => {
+                let request = throttlecrab_server::grpc::ThrottleRequest {
+                    key: record.key.clone(),
+                    max_burst: record.max_burst as i32,
+                    count_per_period: record.count_per_period as i32,
+                    period: record.period as i32,
+                    quantity: record.quantity as i32,
+                    retry_hints: false,
+                    policy: String::new(),
+                    algorithm: String::new(),
+                    operation: String::new(),
+                    namespace: String::new(),
+                    windows: vec![],
+                };
+                Ok(client.throttle(request).await?.into_inner().allowed)
+            }
+            Client::Redis(connection) => {
+                let values: Vec<i64> = redis::cmd("THROTTLE")
+                    .arg(&record.key)
+                    .arg(record.max_burst)
+                    .arg(record.count_per_period)
+                    .arg(record.period)
+                    .arg(record.quantity)
+                    .query_async(connection)
+                    .await?;
+                Ok(values.first() == Some(&1))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ReplayStats {
+    sent: AtomicU64,
+    allowed: AtomicU64,
+    denied: AtomicU64,
+    failed: AtomicU64,
+    /// Requests whose decision matches the recorded one
+    matched: AtomicU64,
+    /// Requests with a recorded decision to compare against
+    compared: AtomicU64,
+}
+
+/// Offset of each record from the start of the replay at `speed`
+fn schedule(records: &[TraceRecord], speed: f64) -> Vec<Duration> {
+    let first = records.first().map_or(0, |record| record.timestamp_ms);
+    records
+        .iter()
+        .map(|record| {
+            let offset_ms = record.timestamp_ms.saturating_sub(first).max(0) as f64;
+            Duration::from_secs_f64(offset_ms / 1000.0 / speed)
+        })
+        .collect()
+}
+
+pub async fn run_replay(
+    trace_path: &Path,
+    port: u16,
+    transport_str: &str,
+    speed: f64,
+    concurrency: usize,
+) -> Result<()> {
+    let transport = Transport::from_str(transport_str)?;
+    anyhow::ensure!(
+        speed.is_finite() && speed > 0.0,
+        "Speed must be greater than 0"
+    );
+    anyhow::ensure!(concurrency > 0, "Concurrency must be greater than 0");
+
+    let input = std::fs::read_to_string(trace_path)
+        .with_context(|| format!("Failed to read {}", trace_path.display()))?;
+    let mut records = trace::parse_csv(&input)?;
+    records.sort_by_key(|record| record.timestamp_ms);
+    let offsets = schedule(&records, speed);
+
+    println!("=== ThrottleCrab Trace Replay ===");
+    println!("Trace: {}", trace_path.display());
+    println!("Requests: {}", records.len());
+    println!("Transport: {transport_str}");
+    println!("Speed: {speed}x");
+    println!("Concurrency: {concurrency}");
+    println!("Target port: {port}\n");
+
+    let client = Client::connect(&transport, port)
+        .await
+        .with_context(|| format!("Failed to connect to the server on port {port}"))?;
+    let stats = Arc::new(ReplayStats::default());
+    let latencies = Arc::new(Mutex::new(Vec::with_capacity(records.len())));
+    let permits = Arc::new(Semaphore::new(concurrency));
+    let mut max_lag = Duration::ZERO;
+    let mut tasks = JoinSet::new();
+
+    let start = Instant::now();
+    for (record, offset) in records.into_iter().zip(offsets) {
+        tokio::time::sleep_until((start + offset).into()).await;
+        let permit = Arc::clone(&permits).acquire_owned().await?;
+        // Requests start late when the server or the concurrency limit can't keep up
+        max_lag = max_lag.max(start.elapsed().saturating_sub(offset));
+
+        let mut client = client.clone();
+        let stats = Arc::clone(&stats);
+        let latencies = Arc::clone(&latencies);
+        tasks.spawn(async move {
+            let sent_at = Instant::now();
+            let result = client.throttle(&record).await;
+            let latency = sent_at.elapsed();
+            drop(permit);
+
+            stats.sent.fetch_add(1, Ordering::Relaxed);
+            match result {
+                Ok(allowed) => {
+                    let counter = if allowed {
+                        &stats.allowed
+                    } else {
+                        &stats.denied
+                    };
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    if let Some(recorded) = record.allowed {
+                        stats.compared.fetch_add(1, Ordering::Relaxed);
+                        if recorded == allowed {
+                            stats.matched.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    latencies.lock().await.push(latency);
+                }
+                Err(_) => {
+                    stats.failed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+
+        // Keep the set from growing with the length of the trace
+        while tasks.try_join_next().is_some() {}
+    }
+    while tasks.join_next().await.is_some() {}
+    let duration = start.elapsed();
+
+    let sent = stats.sent.load(Ordering::Relaxed);
+    let allowed = stats.allowed.load(Ordering::Relaxed);
+    let denied = stats.denied.load(Ordering::Relaxed);
+    let failed = stats.failed.load(Ordering::Relaxed);
+    let compared = stats.compared.load(Ordering::Relaxed);
+    let matched = stats.matched.load(Ordering::Relaxed);
+
+    println!("=== Replay Results ===");
+    println!("Duration: {duration:?}");
+    println!("Requests sent: {sent}");
+    println!(
+        "Throughput: {:.2} requests/sec",
+        sent as f64 / duration.as_secs_f64()
+    );
+    println!("Allowed: {allowed}");
+    println!("Denied: {denied}");
+    println!("Failed: {failed}");
+    println!("Max schedule lag: {max_lag:?}");
+    if compared > 0 {
+        println!(
+            "Matching recorded decisions: {} of {} ({:.2}%)",
+            matched,
+            compared,
+            matched as f64 / compared as f64 * 100.0
+        );
+    }
+
+    let mut latencies = latencies.lock().await;
+    if !latencies.is_empty() {
+        latencies.sort();
+        println!("\nLatency percentiles:");
+        println!("  P50: {:?}", percentile(&latencies, 0.5));
+        println!("  P90: {:?}", percentile(&latencies, 0.9));
+        println!("  P99: {:?}", percentile(&latencies, 0.99));
+        println!("  P99.9: {:?}", percentile(&latencies, 0.999));
+    }
+
+    Ok(())
+}
+
+/// Save a server's recent requests as a trace, from `GET /admin/trace`
+pub async fn export_trace(port: u16, output: &Path) -> Result<()> {
+    let url = format!("http://127.0.0.1:{port}/admin/trace");
+    let csv = reqwest::get(&url)
+        .await
+        .with_context(|| format!("Failed to fetch {url}"))?
+        .error_for_status()
+        .context("Is the server running with --trace-buffer-size?")?
+        .text()
+        .await?;
+    let records = trace::parse_csv(&csv)?.len();
+    std::fs::write(output, csv).with_context(|| format!("Failed to write {}", output.display()))?;
+    println!("Saved {records} requests to {}", output.display());
+    Ok(())
+}
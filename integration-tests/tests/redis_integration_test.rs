@@ -49,7 +49,7 @@ async fn test_redis_throttle_command() {
     // Verify response format
     match result {
         Value::Array(values) => {
-            assert_eq!(values.len(), 5, "Expected 5 elements in response");
+            assert_eq!(values.len(), 9, "Expected 9 elements in response");
 
             // Check allowed (should be 1)
             assert_eq!(values[0], Value::Int(1), "Expected allowed = 1");
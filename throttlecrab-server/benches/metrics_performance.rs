@@ -0,0 +1,72 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::Duration;
+use throttlecrab_server::metrics::{Metrics, Transport};
+
+/// Keys recorded per thread in each iteration
+const KEYS_PER_THREAD: u64 = 1_000;
+
+fn benchmark_record_denied(c: &mut Criterion) {
+    let mut group = c.benchmark_group("record_denied");
+    group.measurement_time(Duration::from_secs(2));
+    group.warm_up_time(Duration::from_millis(200));
+
+    for num_threads in [1, 4, 8].iter() {
+        group.throughput(Throughput::Elements(num_threads * KEYS_PER_THREAD));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("threads_{num_threads}")),
+            num_threads,
+            |b, &num_threads| {
+                let metrics = Arc::new(Metrics::new());
+                let mut round = 0u64;
+
+                b.iter(|| {
+                    round += 1;
+                    let handles: Vec<_> = (0..num_threads)
+                        .map(|thread_id| {
+                            let metrics = Arc::clone(&metrics);
+                            std::thread::spawn(move || {
+                                // High cardinality keeps the top keys table cleaning up
+                                for i in 0..KEYS_PER_THREAD {
+                                    let key = format!("key_{thread_id}_{round}_{i}");
+                                    metrics.record_request_with_key(
+                                        Transport::Http,
+                                        false,
+                                        black_box(&key),
+                                    );
+                                }
+                            })
+                        })
+                        .collect();
+
+                    for handle in handles {
+                        handle.join().unwrap();
+                    }
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn benchmark_export(c: &mut Criterion) {
+    let mut group = c.benchmark_group("export_prometheus");
+    group.measurement_time(Duration::from_secs(1));
+    group.warm_up_time(Duration::from_millis(100));
+
+    let metrics = Metrics::new();
+    for i in 0..10_000 {
+        metrics.record_request_with_key(Transport::Http, false, &format!("key_{}", i % 500));
+    }
+
+    group.bench_function("top_100", |b| {
+        b.iter(|| black_box(metrics.export_prometheus()));
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_record_denied, benchmark_export);
+criterion_main!(benches);
@@ -0,0 +1,105 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::Duration;
+use throttlecrab_server::actor::RateLimiterActor;
+use throttlecrab_server::metrics::{Metrics, Transport};
+use throttlecrab_server::transport::http::HttpThrottleRequest;
+use throttlecrab_server::types::ThrottleRequest;
+
+/// Requests sent per iteration
+const REQUESTS_PER_ITER: u64 = 10_000;
+
+/// Distinct keys the requests are spread over
+const KEYS: u64 = 1_000;
+
+/// Request bodies as a client would send them
+fn bodies() -> Vec<Vec<u8>> {
+    (0..KEYS)
+        .map(|i| {
+            serde_json::to_vec(&serde_json::json!({
+                "key": format!("tenant:{}:user:{i}", i % 10),
+                "max_burst": 1_000_000,
+                "count_per_period": 1_000_000,
+                "period": 60,
+            }))
+            .unwrap()
+        })
+        .collect()
+}
+
+/// Parse, throttle and record metrics the way the HTTP transport does
+///
+/// Covers the per-request work from the parsed body to the store lookup and
+/// back, without the socket and HTTP framing.
+fn benchmark_request_path(c: &mut Criterion) {
+    let mut group = c.benchmark_group("request_path");
+    group.measurement_time(Duration::from_secs(3));
+    group.warm_up_time(Duration::from_millis(500));
+    group.throughput(Throughput::Elements(REQUESTS_PER_ITER));
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let bodies = Arc::new(bodies());
+
+    for tasks in [1u64, 8] {
+        let metrics = Arc::new(Metrics::new());
+        let limiter = runtime.block_on(async {
+            RateLimiterActor::spawn_periodic(
+                100_000,
+                throttlecrab::PeriodicStore::new(),
+                Arc::clone(&metrics),
+            )
+        });
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("tasks_{tasks}")),
+            &tasks,
+            |b, &tasks| {
+                b.iter(|| {
+                    runtime.block_on(async {
+                        let mut handles = Vec::new();
+                        for task in 0..tasks {
+                            let limiter = limiter.clone();
+                            let metrics = Arc::clone(&metrics);
+                            let bodies = Arc::clone(&bodies);
+                            handles.push(tokio::spawn(async move {
+                                for i in (task..REQUESTS_PER_ITER).step_by(tasks as usize) {
+                                    let body = &bodies[(i % KEYS) as usize];
+                                    let req: HttpThrottleRequest =
+                                        serde_json::from_slice(body).unwrap();
+                                    let response = limiter
+                                        .throttle(ThrottleRequest {
+                                            quantity: req.quantity.unwrap_or(1),
+                                            timestamp: limiter.now(),
+                                            ..ThrottleRequest::new(
+                                                Arc::clone(&req.key),
+                                                req.max_burst,
+                                                req.count_per_period,
+                                                req.period,
+                                            )
+                                        })
+                                        .await
+                                        .unwrap();
+                                    metrics.record_request_with_key(
+                                        Transport::Http,
+                                        response.allowed,
+                                        &req.key,
+                                    );
+                                    black_box(response);
+                                }
+                            }));
+                        }
+                        for handle in handles {
+                            handle.await.unwrap();
+                        }
+                    })
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_request_path);
+criterion_main!(benches);
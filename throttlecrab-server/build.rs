@@ -4,7 +4,11 @@ fn main() {
 }
 
 fn compile_protos() {
-    match tonic_prost_build::compile_protos("proto/throttlecrab.proto") {
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    match tonic_prost_build::configure()
+        .file_descriptor_set_path(out_dir.join("throttlecrab_descriptor.bin"))
+        .compile_protos(&["proto/throttlecrab.proto"], &["proto"])
+    {
         Ok(_) => println!("cargo:info=Successfully compiled protobuf"),
         Err(e) => {
             println!("cargo:warning=Failed to compile protobuf: {e}");
@@ -17,10 +21,11 @@ fn compile_protos() {
 
             // Don't fail the build, just skip gRPC support
             std::fs::write(
-                std::env::var("OUT_DIR").unwrap() + "/throttlecrab.rs",
+                out_dir.join("throttlecrab.rs"),
                 "// Protobuf compilation failed, gRPC support disabled\n",
             )
             .ok();
+            std::fs::write(out_dir.join("throttlecrab_descriptor.bin"), []).ok();
         }
     }
 }
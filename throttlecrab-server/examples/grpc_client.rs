@@ -22,6 +22,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             count_per_period: 30,
             period: 60,
             quantity: 1,
+            retry_hints: false,
+            policy: String::new(),
+            algorithm: String::new(),
+            operation: String::new(),
+            namespace: String::new(),
+            windows: vec![],
         });
 
         let response = client.throttle(request).await?;
@@ -11,6 +11,56 @@
 //! - **Async Communication**: Non-blocking message passing via channels
 //! - **Protocol Independence**: All transports use the same interface
 //!
+//! # Sharding
+//!
+//! With `--shards N` the key space is partitioned across N actors by a hash
+//! of the key. Every request for a key reaches the same actor, so per-key
+//! decisions stay exactly as consistent as with one actor while the store
+//! work spreads over N cores. Operations that span keys (cleanup, reports,
+//! snapshots) are sent to every shard and their results merged.
+//!
+//! # Algorithms
+//!
+//! Each request names its [`AlgorithmKind`](crate::types::AlgorithmKind),
+//! which decides it through the [`Algorithm`] trait against the shard's
+//! store. GCRA and the leaky bucket keep one store entry per key; window
+//! algorithms keep one per key and window in the same store (see
+//! [`throttlecrab::core::sliding_window`]), so key limits, eviction,
+//! cleanup, the WAL and snapshots cover them all. Resetting a key clears
+//! its window entries along with the entry under the key itself.
+//!
+//! # Concurrency Leases
+//!
+//! Besides rate limits, each actor keeps a [`ConcurrencyLimiter`] capping
+//! the operations in flight per key (`ACQUIRE` and `RELEASE`). Leases live
+//! in memory only: they are not written to the WAL or snapshots, so a
+//! restart frees every slot.
+//!
+//! # Coalescing
+//!
+//! With a coalescing window, an actor that receives a throttle request
+//! keeps collecting the throttle requests that arrive within the window.
+//! Requests with the same key, parameters and algorithm are then decided
+//! as one request for their total quantity, so a hot key costs one store
+//! operation per window instead of one per request. See
+//! [`handle_coalesced`] for when a group falls back to one at a time.
+//!
+//! # Backpressure
+//!
+//! Each actor's queue holds `buffer_size` messages. When a throttle request
+//! finds it full, the [`Backpressure`] policy decides what happens: wait for
+//! room (`block`), fail with an [`OverloadedError`] straight away
+//! (`fail-fast`), or wait while the actor fails the requests that have
+//! queued longest until senders stop waiting (`shed-oldest`). Requests other
+//! than throttle checks always wait.
+//!
+//! # Redis
+//!
+//! A Redis store's decisions and resets each wait on round trips to Redis,
+//! so the actor hands them to tasks of their own instead of waiting. Those
+//! tasks decide concurrently, even for one key; Redis's compare-and-swap
+//! keeps them consistent.
+//!
 //! # Example
 //!
 //! ```ignore
@@ -21,17 +71,119 @@
 //! let response = limiter.throttle(request).await?;
 //! ```
 
-use crate::metrics::Metrics;
-use crate::types::{ThrottleRequest, ThrottleResponse};
+use crate::auth::{self, ApiKeys, UnauthorizedError};
+use crate::auto_store::AutoStore;
+use crate::budget::{ApiKeyBudgets, BudgetExhaustedError};
+use crate::canary::{Canary, MAX_DIVERGENCES};
+use crate::config::{Backpressure, OnFull, StoreType as ConfigStoreType};
+use crate::denials::DenialAlerts;
+use crate::events::{DecisionEvent, EventPublisher};
+use crate::hooks::{self, DecisionHook};
+use crate::hot_keys::{self, HotKeys};
+use crate::metrics::{Metrics, Transport as MetricsTransport};
+use crate::micro_cache::MicroCache;
+use crate::namespace::{InvalidNamespaceError, NamespaceQuotas};
+use crate::otel::RequestSpans;
+use crate::overrides::KeyOverrides;
+use crate::policy::{Policies, Policy, UnknownOperationError, UnknownPolicyError};
+use crate::shed::LoadShedder;
+use crate::trace::TraceBuffer;
+use crate::types::{
+    AcquireRequest, AcquireResponse, AlgorithmKind, CanaryReport, CleanupReport, HotKey, KeyState,
+    MAX_BATCH_SIZE, MemoryReport, ReloadReport, ResetReport, StoreStats, ThrottleRequest,
+    ThrottleResponse, ValidationError,
+};
+use crate::validation::RequestLimits;
+use crate::wal::{Entry, Wal};
+use crate::windows::WindowsResponse;
 use anyhow::Result;
+use std::borrow::Cow;
+use std::collections::hash_map::{DefaultHasher, Entry as MapEntry};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
-use throttlecrab::{AdaptiveStore, CellError, PeriodicStore, ProbabilisticStore, RateLimiter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use throttlecrab::core::sliding_window::{owner_key, window_key_prefix};
+use throttlecrab::store::estimated_entry_memory;
+use throttlecrab::{
+    AdaptiveStore, Algorithm, CellError, Clock, ConcurrencyLimiter, PeriodicStore,
+    ProbabilisticStore, RateLimitResult, RateLimiter, Store, SystemClock, TimingWheelStore, WarmUp,
+};
+#[cfg(feature = "redis-store")]
+use throttlecrab::{AsyncRateLimiter, AsyncRedisStore};
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::{mpsc, oneshot};
+#[cfg(feature = "redis-store")]
+use tracing::Instrument;
+
+/// Number of shared buckets new keys are folded into in degrade mode
+const DEGRADED_BUCKETS: u64 = 1024;
+
+/// Key prefix for the shared overflow buckets used in degrade mode
+const DEGRADED_KEY_PREFIX: &str = "__throttlecrab_overflow:";
+
+/// Minimum time between expired-entry purges triggered by a full store
+const FULL_STORE_PURGE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Minimum time between sweeps of expired concurrency leases
+///
+/// Leases on a key are dropped whenever the key is used; the sweep catches
+/// keys that are not used again.
+const LEASE_PURGE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Weight of the running key length average; each request moves it 1/N of the way
+const KEY_LEN_SMOOTHING: f64 = 1024.0;
+
+/// Entries scanned between deadline checks in a budgeted cleanup pass
+const CLEANUP_DEADLINE_CHECK_INTERVAL: usize = 1024;
+
+/// How long a store memory estimate is reused before the store is walked again
+const MEMORY_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Error returned when a new key is rejected because the store is full
+///
+/// Transports can detect it with `anyhow::Error::downcast_ref` to report
+/// a capacity problem instead of a generic internal error.
+#[derive(Debug)]
+pub struct StoreFullError;
+
+impl fmt::Display for StoreFullError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "store is full, new keys are rejected")
+    }
+}
+
+impl std::error::Error for StoreFullError {}
+
+/// Error returned when a throttle request is refused because its actor's
+/// queue is full (see [`Backpressure`])
+///
+/// Transports can detect it with `anyhow::Error::downcast_ref` to tell
+/// clients to back off instead of reporting a generic internal error.
+#[derive(Debug)]
+pub struct OverloadedError;
+
+impl OverloadedError {
+    /// `overloaded`: the check was not made and may be retried
+    pub fn code(&self) -> &'static str {
+        "overloaded"
+    }
+}
+
+impl fmt::Display for OverloadedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rate limiter is overloaded, retry later")
+    }
+}
+
+impl std::error::Error for OverloadedError {}
 
 /// Message types for the rate limiter actor
 ///
-/// Currently supports throttle requests, but can be extended with
-/// additional message types like statistics queries or cache clearing.
+/// Supports throttle requests and on-demand store cleanup, and can be
+/// extended with additional message types like statistics queries.
 pub enum RateLimiterMessage {
     /// Check rate limit for a key
     Throttle {
@@ -39,6 +191,126 @@ pub enum RateLimiterMessage {
         request: ThrottleRequest,
         /// Channel to send the response back
         response_tx: oneshot::Sender<Result<ThrottleResponse>>,
+        /// Spans exported for the request
+        spans: RequestSpans,
+    },
+    /// Report a key's rate limit state without consuming tokens
+    Peek {
+        /// The rate limit request to evaluate
+        request: ThrottleRequest,
+        /// Channel to send the response back
+        response_tx: oneshot::Sender<Result<ThrottleResponse>>,
+    },
+    /// Remove a key's state from the store
+    Reset {
+        /// The key to remove
+        key: Arc<str>,
+        /// Channel to send back whether the key existed
+        response_tx: oneshot::Sender<bool>,
+    },
+    /// Take a concurrency lease on a key
+    Acquire {
+        /// The lease request
+        request: AcquireRequest,
+        /// Channel to send the response back
+        response_tx: oneshot::Sender<Result<AcquireResponse>>,
+    },
+    /// Give back a concurrency lease
+    Release {
+        /// The key the lease was taken on
+        key: Arc<str>,
+        /// The lease to release
+        lease_id: u64,
+        /// Current time, used to ignore expired leases
+        now: SystemTime,
+        /// Channel to send back whether the lease was held
+        response_tx: oneshot::Sender<bool>,
+    },
+    /// Check rate limits for several keys in one round trip
+    ThrottleBatch {
+        /// The rate limit requests, in order
+        requests: Vec<ThrottleRequest>,
+        /// Channel to send the responses back, in the same order
+        response_tx: oneshot::Sender<Vec<Result<ThrottleResponse>>>,
+    },
+    /// Check one key against several windows at once
+    ThrottleWindows {
+        /// One request per window, in order
+        requests: Vec<ThrottleRequest>,
+        /// Channel to send each window's decision back, in the same order
+        response_tx: oneshot::Sender<Result<Vec<ThrottleResponse>>>,
+    },
+    /// Remove expired entries now
+    Cleanup {
+        /// Current time, used to decide which entries have expired
+        now: SystemTime,
+        /// Stop scanning after this long (None scans every entry)
+        budget: Option<Duration>,
+        /// Channel to send the report back
+        response_tx: oneshot::Sender<CleanupReport>,
+    },
+    /// Report on the most recent cleanup pass
+    LastCleanup {
+        /// Channel to send the report back (None if no pass has run)
+        response_tx: oneshot::Sender<Option<CleanupReport>>,
+    },
+    /// Report on the canary store comparison
+    CanaryReport {
+        /// Channel to send the report back (None if no canary is configured)
+        response_tx: oneshot::Sender<Option<CanaryReport>>,
+    },
+    /// Estimate the memory held by the primary and canary stores
+    MemoryUsage {
+        /// Current time, recorded when the estimate is refreshed
+        now: SystemTime,
+        /// Channel to send the store figures of the report back
+        response_tx: oneshot::Sender<MemoryReport>,
+    },
+    /// Report the most requested keys
+    HotKeys {
+        /// Most keys to report
+        limit: usize,
+        /// Channel to send the keys back (None if hot keys are not counted)
+        response_tx: oneshot::Sender<Option<Vec<HotKey>>>,
+    },
+    /// Look up a key's entry in the store
+    LookupKey {
+        /// The store key
+        key: Arc<str>,
+        /// Current time, used to tell whether the entry has expired
+        now: SystemTime,
+        /// Channel to send the entry back (None if the store has no entry)
+        response_tx: oneshot::Sender<Option<KeyState>>,
+    },
+    /// Count the entries in the store
+    StoreStats {
+        /// Current time, used to count expired entries
+        now: SystemTime,
+        /// Channel to send the figures back
+        response_tx: oneshot::Sender<StoreStats>,
+    },
+    /// Copy the entries that have not expired, for a snapshot
+    Entries {
+        /// Current time, used to leave out expired entries
+        now: SystemTime,
+        /// Channel to send the entries back
+        response_tx: oneshot::Sender<Vec<(String, i64, Option<SystemTime>)>>,
+    },
+    /// Take the entries changed since the last call, for cluster peers
+    ///
+    /// The first call starts tracking changes and returns nothing.
+    TakeDeltas {
+        /// Channel to send the entries back
+        response_tx: oneshot::Sender<Vec<(String, i64, Option<SystemTime>)>>,
+    },
+    /// Merge entries replicated from a cluster peer into the store
+    Merge {
+        /// The peer's entries; expired ones remove their key
+        entries: Vec<(String, i64, Option<SystemTime>)>,
+        /// Current time, used to tell expired entries apart
+        now: SystemTime,
+        /// Channel to send back how many entries changed the store
+        response_tx: oneshot::Sender<usize>,
     },
     // Future: Stats, Clear, Shutdown, etc.
 }
@@ -49,207 +321,2828 @@ pub enum RateLimiterMessage {
 /// All operations are async and non-blocking.
 #[derive(Clone)]
 pub struct RateLimiterHandle {
-    tx: mpsc::Sender<RateLimiterMessage>,
-    #[allow(dead_code)] // Will be used for future metrics queries
+    shards: Arc<[mpsc::Sender<RateLimiterMessage>]>,
     pub metrics: Arc<Metrics>,
+    events: Option<EventPublisher>,
+    hooks: Arc<[Arc<dyn DecisionHook>]>,
+    trace: Option<Arc<TraceBuffer>>,
+    denial_alerts: Option<Arc<DenialAlerts>>,
+    policies: Option<Arc<Policies>>,
+    api_keys: Option<Arc<ApiKeys>>,
+    budgets: Option<Arc<ApiKeyBudgets>>,
+    overrides: Option<Arc<KeyOverrides>>,
+    shedder: Option<Arc<LoadShedder>>,
+    micro_cache: Option<Arc<MicroCache>>,
+    limits: Option<Arc<RequestLimits>>,
+    store_type: Option<ConfigStoreType>,
+    backpressure: Backpressure,
+    clock: Arc<dyn Clock + Send + Sync>,
 }
 
 impl RateLimiterHandle {
-    /// Check rate limit for a key
-    ///
-    /// Sends a throttle request to the actor and waits for the response.
-    /// This method is cancel-safe and can be used in select! expressions.
+    /// Use `clock` for request timestamps instead of the system clock
     ///
-    /// # Errors
-    ///
-    /// Returns an error if:
-    /// - The actor has shut down
-    /// - The response channel was dropped
-    pub async fn throttle(&self, request: ThrottleRequest) -> Result<ThrottleResponse> {
-        let (response_tx, response_rx) = oneshot::channel();
+    /// Applies to clones made from the returned handle.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock + Send + Sync>) -> Self {
+        self.clock = clock;
+        self
+    }
 
-        self.tx
-            .send(RateLimiterMessage::Throttle {
-                request,
-                response_tx,
-            })
-            .await
-            .map_err(|_| anyhow::anyhow!("Rate limiter actor has shut down"))?;
+    /// Report `store_type` as the kind of store behind the handle
+    ///
+    /// Applies to clones made from the returned handle.
+    pub fn with_store_type(mut self, store_type: ConfigStoreType) -> Self {
+        self.store_type = Some(store_type);
+        self
+    }
 
-        response_rx
-            .await
-            .map_err(|_| anyhow::anyhow!("Rate limiter actor dropped response channel"))?
+    /// The kind of store behind the handle, if it was created from a
+    /// store configuration
+    pub fn store_type(&self) -> Option<ConfigStoreType> {
+        self.store_type
     }
-}
 
-/// The rate limiter actor factory
-///
-/// Provides static methods to spawn rate limiter actors with different store types.
-/// Each actor runs in its own Tokio task and processes messages sequentially.
-pub struct RateLimiterActor;
+    /// Current time according to the handle's clock
+    ///
+    /// Transports use this to timestamp requests.
+    pub fn now(&self) -> SystemTime {
+        self.clock.now()
+    }
 
-impl RateLimiterActor {
-    /// Spawn a new rate limiter actor with a periodic store
+    /// Export a sample of this handle's decisions through `publisher`
     ///
-    /// # Parameters
+    /// Applies to clones made from the returned handle.
+    pub fn with_events(mut self, publisher: EventPublisher) -> Self {
+        self.events = Some(publisher);
+        self
+    }
+
+    /// Call `hooks` after each of this handle's decisions, in order
     ///
-    /// - `buffer_size`: Channel buffer size for backpressure control
-    /// - `store`: The periodic store instance to use
+    /// Replaces any hooks set before. Applies to clones made from the
+    /// returned handle.
+    pub fn with_hooks(mut self, hooks: Vec<Arc<dyn DecisionHook>>) -> Self {
+        self.hooks = hooks.into();
+        self
+    }
+
+    /// Record this handle's requests and decisions in `trace`
     ///
-    /// # Returns
+    /// Applies to clones made from the returned handle.
+    pub fn with_trace(mut self, trace: Arc<TraceBuffer>) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// The request trace, if one is attached
+    pub fn trace(&self) -> Option<&Arc<TraceBuffer>> {
+        self.trace.as_ref()
+    }
+
+    /// Make `alerts` available to subscribers such as `GET /events`
     ///
-    /// A [`RateLimiterHandle`] for communicating with the actor
-    pub fn spawn_periodic(
-        buffer_size: usize,
-        store: PeriodicStore,
-        metrics: Arc<Metrics>,
-    ) -> RateLimiterHandle {
-        let (tx, rx) = mpsc::channel(buffer_size);
-        let metrics_clone = Arc::clone(&metrics);
+    /// Only attaches them: register `alerts` as a hook as well so it sees
+    /// the handle's denials. Applies to clones made from the returned
+    /// handle.
+    pub fn with_denial_alerts(mut self, alerts: Arc<DenialAlerts>) -> Self {
+        self.denial_alerts = Some(alerts);
+        self
+    }
 
-        tokio::spawn(async move {
-            let store_type = StoreType::Periodic(RateLimiter::new(store));
-            run_actor(rx, store_type, metrics_clone).await;
-        });
+    /// The denial alerts, if attached
+    pub fn denial_alerts(&self) -> Option<&Arc<DenialAlerts>> {
+        self.denial_alerts.as_ref()
+    }
 
-        RateLimiterHandle { tx, metrics }
+    /// Handle throttle requests that find their actor's queue full as
+    /// `backpressure` says
+    ///
+    /// Applies to clones made from the returned handle. With `shed-oldest`,
+    /// the actors must have been spawned with the same policy.
+    pub fn with_backpressure(mut self, backpressure: Backpressure) -> Self {
+        self.backpressure = backpressure;
+        self
     }
 
-    /// Spawn a new rate limiter actor with a probabilistic store
+    /// Shed throttle requests beyond the rate `shedder` allows
     ///
-    /// # Parameters
+    /// Applies to clones made from the returned handle, so one shedder
+    /// caps all transports together.
+    pub fn with_load_shedding(mut self, shedder: Arc<LoadShedder>) -> Self {
+        self.shedder = Some(shedder);
+        self
+    }
+
+    /// Decide keys matching `overrides` without consulting the store
     ///
-    /// - `buffer_size`: Channel buffer size for backpressure control
-    /// - `store`: The probabilistic store instance to use
+    /// Applies to clones made from the returned handle, so changes to the
+    /// lists reach all transports.
+    pub fn with_key_overrides(mut self, overrides: Arc<KeyOverrides>) -> Self {
+        self.overrides = Some(overrides);
+        self
+    }
+
+    /// The key overrides, if attached
+    pub fn key_overrides(&self) -> Option<&Arc<KeyOverrides>> {
+        self.overrides.as_ref()
+    }
+
+    /// The response of the [override](crate::overrides) deciding
+    /// `request`, if any
+    fn overridden(&self, request: &ThrottleRequest) -> Option<ThrottleResponse> {
+        let decision = self.overrides.as_ref()?.check(&request.key)?;
+        self.metrics.record_override(decision);
+        Some(decision.response(request))
+    }
+
+    /// Mark `response` as a warning if it passes `warn_percent`, counting
+    /// allowed ones
+    fn warned(&self, response: ThrottleResponse, warn_percent: Option<u8>) -> ThrottleResponse {
+        let response = response.with_warning(warn_percent);
+        if response.warning && response.allowed {
+            self.metrics
+                .soft_limit_warnings
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        response
+    }
+
+    /// Reject requests beyond `limits`, on top of the built-in rules
     ///
-    /// # Returns
+    /// Applies to clones made from the returned handle.
+    pub fn with_limits(mut self, limits: Arc<RequestLimits>) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Check `request` against the built-in rules and the handle's limits,
+    /// counting violations by code
+    fn validate(&self, request: &ThrottleRequest) -> Result<(), ValidationError> {
+        request
+            .validate()
+            .and_then(|()| match &self.limits {
+                Some(limits) => limits.check(request),
+                None => Ok(()),
+            })
+            .inspect_err(|e| self.metrics.record_invalid_request(e.code()))
+    }
+
+    /// Answer identical throttle requests from `cache` while it holds a
+    /// recent decision (see [`crate::micro_cache`])
     ///
-    /// A [`RateLimiterHandle`] for communicating with the actor
-    pub fn spawn_probabilistic(
-        buffer_size: usize,
-        store: ProbabilisticStore,
-        metrics: Arc<Metrics>,
-    ) -> RateLimiterHandle {
-        let (tx, rx) = mpsc::channel(buffer_size);
-        let metrics_clone = Arc::clone(&metrics);
+    /// Applies to clones made from the returned handle.
+    pub fn with_micro_cache(mut self, cache: Arc<MicroCache>) -> Self {
+        self.micro_cache = Some(cache);
+        self
+    }
 
-        tokio::spawn(async move {
-            let store_type = StoreType::Probabilistic(RateLimiter::new(store));
-            run_actor(rx, store_type, metrics_clone).await;
-        });
+    /// Resolve policy names sent by clients against `policies`
+    ///
+    /// Applies to clones made from the returned handle.
+    pub fn with_policies(mut self, policies: Arc<Policies>) -> Self {
+        self.policies = Some(policies);
+        self
+    }
 
-        RateLimiterHandle { tx, metrics }
+    /// The attached policies, if any
+    pub fn policies(&self) -> Option<&Arc<Policies>> {
+        self.policies.as_ref()
     }
 
-    /// Spawn a new rate limiter actor with an adaptive store
+    /// Re-read the policy file, replacing the policies of every handle
     ///
-    /// # Parameters
+    /// # Errors
     ///
-    /// - `buffer_size`: Channel buffer size for backpressure control
-    /// - `store`: The adaptive store instance to use
+    /// Returns an error, keeping the running policies, if no policy file is
+    /// configured or it cannot be read or parsed.
+    pub fn reload_policies(&self) -> Result<ReloadReport> {
+        let policies = self
+            .policies
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No policy file is configured"))?;
+        match policies.reload() {
+            Ok(count) => {
+                self.metrics.policy_reloads.fetch_add(1, Ordering::Relaxed);
+                tracing::info!("Reloaded {} rate limit policies", count);
+                Ok(ReloadReport {
+                    policies: count,
+                    reloaded_at_ms: self
+                        .now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|d| d.as_millis() as i64)
+                        .unwrap_or(0),
+                })
+            }
+            Err(e) => {
+                self.metrics
+                    .policy_reload_failures
+                    .fetch_add(1, Ordering::Relaxed);
+                tracing::error!(
+                    "Policy reload failed, keeping the running policies: {:#}",
+                    e
+                );
+                Err(e)
+            }
+        }
+    }
+
+    /// Look up the policy called `name`
     ///
-    /// # Returns
+    /// # Errors
     ///
-    /// A [`RateLimiterHandle`] for communicating with the actor
-    pub fn spawn_adaptive(
-        buffer_size: usize,
-        store: AdaptiveStore,
-        metrics: Arc<Metrics>,
-    ) -> RateLimiterHandle {
-        let (tx, rx) = mpsc::channel(buffer_size);
-        let metrics_clone = Arc::clone(&metrics);
+    /// Returns [`UnknownPolicyError`] if no such policy is defined, including
+    /// when no policies are attached.
+    pub fn policy(&self, name: &str) -> Result<Arc<Policy>, UnknownPolicyError> {
+        match &self.policies {
+            Some(policies) => policies.get(name),
+            None => Err(UnknownPolicyError(name.to_string())),
+        }
+    }
 
-        tokio::spawn(async move {
-            let store_type = StoreType::Adaptive(RateLimiter::new(store));
-            run_actor(rx, store_type, metrics_clone).await;
-        });
+    /// Apply the `policy` and `operation` a client sent to `request`
+    ///
+    /// A policy replaces the request's rate limit parameters, and an
+    /// operation its quantity with the policy's cost for it. A request
+    /// naming no policy and sending no parameters gets the policy its key
+    /// [matches](crate::policy#key-patterns), if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`UnknownPolicyError`] if the policy is not defined, or
+    /// [`UnknownOperationError`] if it has no cost for the operation or an
+    /// operation is sent without a policy.
+    pub fn resolve(
+        &self,
+        request: &mut ThrottleRequest,
+        policy: Option<&str>,
+        operation: Option<&str>,
+    ) -> Result<()> {
+        let (name, policy) = match policy {
+            Some(name) => (Arc::from(name), self.policy(name)?),
+            None => match self.key_policy(request) {
+                Some(found) => found,
+                None => {
+                    return match operation {
+                        Some(operation) => Err(UnknownOperationError {
+                            operation: operation.to_string(),
+                            policy: None,
+                        }
+                        .into()),
+                        None => Ok(()),
+                    };
+                }
+            },
+        };
+        policy.apply(request);
+        if let Some(operation) = operation {
+            request.quantity = policy
+                .cost(operation)
+                .ok_or_else(|| UnknownOperationError {
+                    operation: operation.to_string(),
+                    policy: Some(name.to_string()),
+                })?;
+        }
+        Ok(())
+    }
 
-        RateLimiterHandle { tx, metrics }
+    /// The policy whose key patterns match `request`, if it sends no rate
+    /// limit parameters of its own
+    fn key_policy(&self, request: &ThrottleRequest) -> Option<(Arc<str>, Arc<Policy>)> {
+        let unlimited =
+            request.max_burst == 0 && request.count_per_period == 0 && request.period == 0;
+        unlimited
+            .then(|| self.policies.as_ref()?.for_key(&request.key))
+            .flatten()
     }
-}
 
-/// Internal enum to handle different store types
-enum StoreType {
-    Periodic(RateLimiter<PeriodicStore>),
-    Probabilistic(RateLimiter<ProbabilisticStore>),
-    Adaptive(RateLimiter<AdaptiveStore>),
-}
+    /// The namespace of a request: the one the client named, else its API
+    /// key's name if keys name namespaces
+    pub fn namespace<'a>(
+        &self,
+        namespace: Option<&'a str>,
+        api_key: Option<&'a str>,
+    ) -> Option<&'a str> {
+        namespace.or_else(|| {
+            api_key.filter(|_| self.api_keys.as_ref().is_some_and(|keys| keys.namespaces()))
+        })
+    }
 
-impl StoreType {
-    fn rate_limit(
-        &mut self,
-        key: &str,
-        max_burst: i64,
-        count_per_period: i64,
-        period: i64,
-        quantity: i64,
-        timestamp: std::time::SystemTime,
-    ) -> Result<(bool, throttlecrab::RateLimitResult), CellError> {
-        match self {
-            StoreType::Periodic(limiter) => limiter.rate_limit(
-                key,
-                max_burst,
-                count_per_period,
-                period,
-                quantity,
-                timestamp,
-            ),
-            StoreType::Probabilistic(limiter) => limiter.rate_limit(
-                key,
-                max_burst,
-                count_per_period,
-                period,
-                quantity,
-                timestamp,
-            ),
-            StoreType::Adaptive(limiter) => limiter.rate_limit(
-                key,
-                max_burst,
-                count_per_period,
-                period,
-                quantity,
-                timestamp,
-            ),
+    /// Put `key` in the namespace of a request, as for [`namespace`](Self::namespace)
+    ///
+    /// Returns the namespace, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`InvalidNamespaceError`] if the namespace is not a valid name.
+    pub fn scope<'a>(
+        &self,
+        key: &mut Arc<str>,
+        namespace: Option<&'a str>,
+        api_key: Option<&'a str>,
+    ) -> Result<Option<&'a str>, InvalidNamespaceError> {
+        let namespace = self.namespace(namespace, api_key);
+        if let Some(namespace) = namespace {
+            *key = crate::namespace::scoped_key(namespace, key)?;
         }
+        Ok(namespace)
     }
-}
 
-async fn run_actor(
-    mut rx: mpsc::Receiver<RateLimiterMessage>,
-    mut store_type: StoreType,
-    _metrics: Arc<Metrics>,
-) {
-    while let Some(msg) = rx.recv().await {
-        match msg {
-            RateLimiterMessage::Throttle {
-                request,
-                response_tx,
-            } => {
-                let response = handle_throttle(&mut store_type, request);
-                // Ignore send errors - receiver may have timed out
-                let _ = response_tx.send(response);
+    /// Require requests to authenticate with one of `api_keys`
+    ///
+    /// Applies to clones made from the returned handle.
+    pub fn with_api_keys(mut self, api_keys: Arc<ApiKeys>) -> Self {
+        self.api_keys = Some(api_keys);
+        self
+    }
+
+    /// The API keys requests must authenticate with, if any
+    pub fn api_keys(&self) -> Option<&Arc<ApiKeys>> {
+        self.api_keys.as_ref()
+    }
+
+    /// Limit the throttle checks of each API key to its budget in `budgets`
+    ///
+    /// Applies to clones made from the returned handle, so one budget
+    /// covers a key's checks over all transports.
+    pub fn with_api_key_budgets(mut self, budgets: Arc<ApiKeyBudgets>) -> Self {
+        self.budgets = Some(budgets);
+        self
+    }
+
+    /// Charge `checks` throttle checks to the [budget](crate::budget) of
+    /// the API key called `api_key`
+    ///
+    /// A no-op without budgets or an API key. Refusals are counted in the
+    /// metrics.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BudgetExhaustedError`] if the key's budget cannot cover
+    /// the checks.
+    pub fn charge(&self, api_key: Option<&str>, checks: usize) -> Result<(), BudgetExhaustedError> {
+        let (Some(budgets), Some(name)) = (&self.budgets, api_key) else {
+            return Ok(());
+        };
+        budgets
+            .charge(name, checks as u64, self.now())
+            .inspect_err(|_| self.metrics.record_api_key_budget_exhausted(name))
+    }
+
+    /// Authenticate a request by the `Authorization` value it was sent with
+    ///
+    /// Returns the name of the API key it carried, or None when no API keys
+    /// are attached. Outcomes are counted in the metrics.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`UnauthorizedError`] if API keys are attached and the value
+    /// is not `Bearer` followed by one of their secrets.
+    pub fn authenticate(
+        &self,
+        authorization: Option<&str>,
+    ) -> Result<Option<Arc<str>>, UnauthorizedError> {
+        let Some(api_keys) = &self.api_keys else {
+            return Ok(None);
+        };
+        match authorization
+            .and_then(auth::bearer_token)
+            .and_then(|secret| api_keys.authenticate(secret))
+        {
+            Some(name) => {
+                self.metrics.record_api_key_request(name);
+                Ok(Some(Arc::clone(name)))
+            }
+            None => {
+                self.metrics.auth_failures.fetch_add(1, Ordering::Relaxed);
+                Err(UnauthorizedError)
             }
         }
     }
 
-    tracing::info!("Rate limiter actor shutting down");
-}
+    /// Check rate limit for a key
+    ///
+    /// Sends a throttle request to the actor and waits for the response.
+    /// This method is cancel-safe and can be used in select! expressions.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The request is invalid ([`ValidationError`](crate::types::ValidationError))
+    /// - The actor has shut down
+    /// - The response channel was dropped
+    pub async fn throttle(&self, request: ThrottleRequest) -> Result<ThrottleResponse> {
+        self.throttle_or_shed(request)
+            .await
+            .map(|(response, _)| response)
+    }
 
-fn handle_throttle(
-    store_type: &mut StoreType,
+    /// Like [`throttle`](Self::throttle), also reporting whether the request
+    /// was shed
+    ///
+    /// A shed request never reaches the store and gets the default decision
+    /// of the [load shedder](crate::shed), which transports mark in their
+    /// responses.
+    ///
+    /// # Errors
+    ///
+    /// As for [`throttle`](Self::throttle).
+    pub async fn throttle_or_shed(
+        &self,
+        request: ThrottleRequest,
+    ) -> Result<(ThrottleResponse, bool)> {
+        // Reject invalid requests without a round trip to the actor
+        self.validate(&request)?;
+
+        if let Some(response) = self.overridden(&request) {
+            return Ok((response, false));
+        }
+
+        if let Some(cache) = &self.micro_cache {
+            if let Some(response) = cache.lookup(&request, Instant::now()) {
+                self.metrics
+                    .micro_cache_hits
+                    .fetch_add(1, Ordering::Relaxed);
+                return Ok((self.warned(response, request.warn_percent), false));
+            }
+            self.metrics
+                .micro_cache_misses
+                .fetch_add(1, Ordering::Relaxed);
+        }
+
+        if let Some(shedder) = &self.shedder
+            && !shedder.admit(Instant::now())
+        {
+            self.metrics.shed_requests.fetch_add(1, Ordering::Relaxed);
+            return Ok((shedder.response(&request), true));
+        }
+
+        let (response_tx, response_rx) = oneshot::channel();
+
+        // Only copy the key when this decision will be exported
+        let sampled = self
+            .events
+            .as_ref()
+            .filter(|events| events.should_sample())
+            .map(|events| (events, request.key.clone(), request.timestamp));
+
+        // Hooks need the request after it has been handed to the actor
+        let hooked = (!self.hooks.is_empty()).then(|| request.clone());
+        let traced = self.trace.as_ref().map(|trace| (trace, request.clone()));
+        let cached = self
+            .micro_cache
+            .as_ref()
+            .map(|cache| (cache, request.clone()));
+
+        // Requests already waiting for the key's actor
+        let tx = self.shard(&request.key);
+        self.metrics
+            .peak_queue_depth
+            .observe(queue_depth(tx) as u64, request.timestamp);
+
+        let (quota, timestamp, warn_percent) =
+            (request.quota(), request.timestamp, request.warn_percent);
+        self.enqueue(
+            tx,
+            RateLimiterMessage::Throttle {
+                request,
+                response_tx,
+                spans: RequestSpans::enqueue(),
+            },
+        )
+        .await?;
+
+        let response = response_rx
+            .await
+            .map_err(|_| anyhow::anyhow!("Rate limiter actor dropped response channel"))??
+            .with_cacheable_until(&quota, timestamp);
+        let response = self.warned(response, warn_percent);
+
+        if let Some((events, key, timestamp)) = sampled {
+            events.publish(DecisionEvent::new(key, &response, timestamp));
+        }
+
+        if let Some(request) = hooked {
+            hooks::dispatch(&self.hooks, &request, &response);
+        }
+
+        if let Some((trace, request)) = traced {
+            trace.record(&request, response.allowed);
+        }
+
+        if let Some((cache, request)) = cached {
+            cache.insert(&request, &response, Instant::now());
+        }
+
+        Ok((response, false))
+    }
+
+    /// Check one key against several windows, one request per window
+    ///
+    /// The request is allowed only if every window allows it; a denial
+    /// consumes nothing from any window. Build `requests` with
+    /// [`windows::requests`](crate::windows::requests). Events, hooks and
+    /// trace see the binding window's decision, and the micro-cache is
+    /// bypassed. Also reports whether the request was shed, as for
+    /// [`throttle_or_shed`](Self::throttle_or_shed).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any window's request is invalid or the actor has
+    /// shut down.
+    pub async fn throttle_windows(
+        &self,
+        requests: Vec<ThrottleRequest>,
+    ) -> Result<(WindowsResponse, bool)> {
+        for request in &requests {
+            // Window keys extend the client's key, which the limits apply to
+            self.validate(&ThrottleRequest {
+                key: Arc::clone(&requests[0].key),
+                ..request.clone()
+            })?;
+        }
+        let first = requests
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("a request has at least one window"))?;
+
+        if let Some(response) = self.overridden(first) {
+            return Ok((
+                WindowsResponse {
+                    response,
+                    window: 0,
+                },
+                false,
+            ));
+        }
+
+        if let Some(shedder) = &self.shedder
+            && !shedder.admit(Instant::now())
+        {
+            self.metrics.shed_requests.fetch_add(1, Ordering::Relaxed);
+            let response = WindowsResponse {
+                response: shedder.response(first),
+                window: 0,
+            };
+            return Ok((response, true));
+        }
+
+        // Every window lives on the actor that owns the key
+        let tx = self.shard(&first.key);
+        self.metrics
+            .peak_queue_depth
+            .observe(queue_depth(tx) as u64, first.timestamp);
+
+        let observed = (!self.hooks.is_empty() || self.trace.is_some() || self.events.is_some())
+            .then(|| requests.clone());
+        let quotas: Vec<_> = requests
+            .iter()
+            .map(|request| (request.quota(), request.timestamp, request.warn_percent))
+            .collect();
+        let (response_tx, response_rx) = oneshot::channel();
+        self.enqueue(
+            tx,
+            RateLimiterMessage::ThrottleWindows {
+                requests,
+                response_tx,
+            },
+        )
+        .await?;
+        let responses: Vec<_> = response_rx
+            .await
+            .map_err(|_| anyhow::anyhow!("Rate limiter actor dropped response channel"))??
+            .into_iter()
+            .zip(&quotas)
+            .map(|(response, (quota, timestamp, warn_percent))| {
+                response
+                    .with_cacheable_until(quota, *timestamp)
+                    .with_warning(*warn_percent)
+            })
+            .collect();
+        // A warning if any window is
+        let warning = responses.iter().any(|response| response.warning);
+        // Cacheable only as long as every window is
+        let cacheable_until_ms = responses
+            .iter()
+            .map(|response| response.cacheable_until_ms)
+            .min()
+            .unwrap_or(0);
+        let mut decided = WindowsResponse::new(responses);
+        decided.response.cacheable_until_ms = cacheable_until_ms;
+        if warning && decided.response.allowed {
+            self.metrics
+                .soft_limit_warnings
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        decided.response.warning = warning;
+
+        if let Some(request) = observed.as_ref().map(|requests| &requests[decided.window]) {
+            if let Some(events) = self.events.as_ref().filter(|e| e.should_sample()) {
+                events.publish(DecisionEvent::new(
+                    request.key.clone(),
+                    &decided.response,
+                    request.timestamp,
+                ));
+            }
+            hooks::dispatch(&self.hooks, request, &decided.response);
+            if let Some(trace) = &self.trace {
+                trace.record(request, decided.response.allowed);
+            }
+        }
+
+        Ok((decided, false))
+    }
+
+    /// Report what [`throttle`](Self::throttle) would decide for `request`,
+    /// without consuming tokens
+    ///
+    /// The store is left untouched: unknown keys are not created, and the
+    /// key limit, WAL, canary, events, hooks and trace all ignore peeks. With
+    /// a `quantity` of 0 the response describes the key's current state.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request is invalid or the actor has shut down.
+    pub async fn peek(&self, request: ThrottleRequest) -> Result<ThrottleResponse> {
+        self.validate(&request)?;
+        self.metrics.peek_requests.fetch_add(1, Ordering::Relaxed);
+
+        let (quota, timestamp, warn_percent) =
+            (request.quota(), request.timestamp, request.warn_percent);
+        ask(self.shard(&request.key), |response_tx| {
+            RateLimiterMessage::Peek {
+                request,
+                response_tx,
+            }
+        })
+        .await?
+        .map(|response| {
+            response
+                .with_cacheable_until(&quota, timestamp)
+                .with_warning(warn_percent)
+        })
+    }
+
+    /// Clear `key`'s rate limit state, as if it had never been seen
+    ///
+    /// For remediation, e.g. after a client was wrongly blocked. Each reset
+    /// is logged as an audit event (target `throttlecrab::audit`) naming
+    /// the transport it came through.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the actor has shut down.
+    pub async fn reset(&self, key: Arc<str>, via: MetricsTransport) -> Result<ResetReport> {
+        let existed = ask(self.shard(&key), |response_tx| RateLimiterMessage::Reset {
+            key: Arc::clone(&key),
+            response_tx,
+        })
+        .await?;
+
+        self.metrics.key_resets.fetch_add(1, Ordering::Relaxed);
+        tracing::info!(
+            target: "throttlecrab::audit",
+            key = %key,
+            existed,
+            transport = via.name(),
+            "Rate limit key reset"
+        );
+
+        Ok(ResetReport {
+            key: key.to_string(),
+            existed,
+            reset_at_ms: self
+                .now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as i64)
+                .unwrap_or(0),
+        })
+    }
+
+    /// Take a lease on `request.key` if it holds fewer than
+    /// `max_concurrent`, to cap operations in flight rather than their rate
+    ///
+    /// The lease expires after `ttl` seconds unless given back with
+    /// [`release`](Self::release) first. Leases are not counted as requests;
+    /// they have their own metrics.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request is invalid or the actor has shut down.
+    pub async fn acquire(&self, request: AcquireRequest) -> Result<AcquireResponse> {
+        request
+            .validate()
+            .and_then(|()| match &self.limits {
+                Some(limits) => limits.check_acquire(&request),
+                None => Ok(()),
+            })
+            .inspect_err(|e| self.metrics.record_invalid_request(e.code()))?;
+
+        let response = ask(self.shard(&request.key), |response_tx| {
+            RateLimiterMessage::Acquire {
+                request,
+                response_tx,
+            }
+        })
+        .await??;
+
+        let outcome = if response.acquired {
+            &self.metrics.leases_acquired
+        } else {
+            &self.metrics.leases_denied
+        };
+        outcome.fetch_add(1, Ordering::Relaxed);
+        Ok(response)
+    }
+
+    /// Give back lease `lease_id` taken on `key` by [`acquire`](Self::acquire)
+    ///
+    /// Returns false if the lease is unknown or has already expired.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the actor has shut down.
+    pub async fn release(&self, key: Arc<str>, lease_id: u64) -> Result<bool> {
+        let now = self.now();
+        let released = ask(self.shard(&key), |response_tx| {
+            RateLimiterMessage::Release {
+                key: Arc::clone(&key),
+                lease_id,
+                now,
+                response_tx,
+            }
+        })
+        .await?;
+
+        if released {
+            self.metrics.leases_released.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(released)
+    }
+
+    /// Check rate limits for several keys with one message per shard
+    ///
+    /// Returns one result per request, in order; an invalid request fails
+    /// on its own without affecting the others. Events, hooks and the trace
+    /// see each decision as if it had been made by [`throttle`](Self::throttle).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the batch holds more than [`MAX_BATCH_SIZE`]
+    /// requests or the actor has shut down.
+    pub async fn throttle_batch(
+        &self,
+        requests: Vec<ThrottleRequest>,
+    ) -> Result<Vec<Result<ThrottleResponse>>> {
+        if requests.len() > MAX_BATCH_SIZE {
+            return Err(anyhow::anyhow!(
+                "batch of {} requests exceeds the limit of {}",
+                requests.len(),
+                MAX_BATCH_SIZE
+            ));
+        }
+
+        // Invalid and overridden requests are answered here; the rest go to the actor
+        let mut results: Vec<Option<Result<ThrottleResponse>>> = Vec::new();
+        let mut valid = Vec::with_capacity(requests.len());
+        for request in requests {
+            match self.validate(&request) {
+                Ok(()) => match self.overridden(&request) {
+                    Some(response) => results.push(Some(Ok(response))),
+                    None => {
+                        results.push(None);
+                        valid.push(request);
+                    }
+                },
+                Err(e) => results.push(Some(Err(e.into()))),
+            }
+        }
+
+        let mut responses = Vec::new();
+        if !valid.is_empty() {
+            let observed =
+                (!self.hooks.is_empty() || self.trace.is_some() || self.events.is_some())
+                    .then(|| valid.clone());
+
+            // Group by shard, remembering where each request came from
+            let timestamp = valid[0].timestamp;
+            let mut groups = vec![Vec::new(); self.shards.len()];
+            let mut order = Vec::with_capacity(valid.len());
+            let mut quotas = Vec::with_capacity(valid.len());
+            for request in valid {
+                let index = shard_index(&request.key, self.shards.len());
+                order.push(index);
+                quotas.push((request.quota(), request.timestamp, request.warn_percent));
+                groups[index].push(request);
+            }
+
+            // Hand every group over before waiting, so the shards work in parallel
+            let mut pending = Vec::new();
+            for (index, requests) in groups.into_iter().enumerate() {
+                if requests.is_empty() {
+                    continue;
+                }
+                let tx = &self.shards[index];
+                self.metrics
+                    .peak_queue_depth
+                    .observe(queue_depth(tx) as u64, timestamp);
+                let (response_tx, response_rx) = oneshot::channel();
+                self.enqueue(
+                    tx,
+                    RateLimiterMessage::ThrottleBatch {
+                        requests,
+                        response_tx,
+                    },
+                )
+                .await?;
+                pending.push((index, response_rx));
+            }
+
+            let mut answers: Vec<_> = (0..self.shards.len()).map(|_| Vec::new()).collect();
+            for (index, response_rx) in pending {
+                answers[index] = response_rx
+                    .await
+                    .map_err(|_| anyhow::anyhow!("Rate limiter actor dropped response channel"))?;
+            }
+            let mut answers: Vec<_> = answers.into_iter().map(Vec::into_iter).collect();
+            responses = order
+                .into_iter()
+                .zip(&quotas)
+                .map(|(index, (quota, timestamp, warn_percent))| {
+                    answers[index]
+                        .next()
+                        .expect("each shard answers every request sent to it")
+                        .map(|response| {
+                            self.warned(
+                                response.with_cacheable_until(quota, *timestamp),
+                                *warn_percent,
+                            )
+                        })
+                })
+                .collect();
+
+            for (request, response) in observed.iter().flatten().zip(&responses) {
+                let Ok(response) = response else { continue };
+                if let Some(events) = self.events.as_ref().filter(|e| e.should_sample()) {
+                    events.publish(DecisionEvent::new(
+                        request.key.clone(),
+                        response,
+                        request.timestamp,
+                    ));
+                }
+                hooks::dispatch(&self.hooks, request, response);
+                if let Some(trace) = &self.trace {
+                    trace.record(request, response.allowed);
+                }
+            }
+        }
+
+        let mut responses = responses.into_iter();
+        Ok(results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| {
+                    responses
+                        .next()
+                        .expect("the actor answers every request in a batch")
+                })
+            })
+            .collect())
+    }
+
+    /// Remove expired entries from the store immediately
+    ///
+    /// With a `budget`, scanning stops once it is used up and the report
+    /// is marked incomplete. Requests queued behind the pass wait for it,
+    /// so large stores should use a budget.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the actor has shut down
+    pub async fn cleanup(&self, budget: Option<Duration>) -> Result<CleanupReport> {
+        let now = self.now();
+        let reports = self
+            .ask_all(|response_tx| RateLimiterMessage::Cleanup {
+                now,
+                budget,
+                response_tx,
+            })
+            .await?;
+        Ok(merge_cleanups(reports).expect("there is at least one shard"))
+    }
+
+    /// Report on the most recent [`cleanup`](Self::cleanup) pass, if any
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the actor has shut down
+    pub async fn last_cleanup(&self) -> Result<Option<CleanupReport>> {
+        let reports = self
+            .ask_all(|response_tx| RateLimiterMessage::LastCleanup { response_tx })
+            .await?;
+        Ok(merge_cleanups(reports.into_iter().flatten()))
+    }
+
+    /// Compare the primary store against the canary store, if configured
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the actor has shut down
+    pub async fn canary_report(&self) -> Result<Option<CanaryReport>> {
+        let reports = self
+            .ask_all(|response_tx| RateLimiterMessage::CanaryReport { response_tx })
+            .await?;
+        Ok(reports
+            .into_iter()
+            .flatten()
+            .reduce(|mut merged, report| {
+                merged.compared += report.compared;
+                merged.agreed += report.agreed;
+                merged.divergences.extend(report.divergences);
+                merged
+            })
+            .map(|mut merged| {
+                merged.agreement_rate = if merged.compared == 0 {
+                    1.0
+                } else {
+                    merged.agreed as f64 / merged.compared as f64
+                };
+                merged
+                    .divergences
+                    .sort_by_key(|divergence| divergence.at_ms);
+                let excess = merged.divergences.len().saturating_sub(MAX_DIVERGENCES);
+                merged.divergences.drain(..excess);
+                merged
+            }))
+    }
+
+    /// The `limit` most requested keys, most requested first
+    ///
+    /// Returns None unless the store counts hot keys (see
+    /// [`crate::hot_keys`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the actor has shut down
+    pub async fn hot_keys(&self, limit: usize) -> Result<Option<Vec<HotKey>>> {
+        let tops = self
+            .ask_all(|response_tx| RateLimiterMessage::HotKeys { limit, response_tx })
+            .await?;
+        // Every shard counts hot keys or none does
+        let Some(tops) = tops.into_iter().collect::<Option<Vec<_>>>() else {
+            return Ok(None);
+        };
+        let mut keys = tops.concat();
+        hot_keys::sort(&mut keys);
+        keys.truncate(limit);
+        Ok(Some(keys))
+    }
+
+    /// The store's entry for `key`, if it has one
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the actor has shut down
+    pub async fn key_state(&self, key: Arc<str>) -> Result<Option<KeyState>> {
+        let now = self.now();
+        ask(self.shard(&key), |response_tx| {
+            RateLimiterMessage::LookupKey {
+                key,
+                now,
+                response_tx,
+            }
+        })
+        .await
+    }
+
+    /// Count the entries in the store
+    ///
+    /// Walks every key on the actors, so meant for occasional inspection.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the actor has shut down
+    pub async fn store_stats(&self) -> Result<StoreStats> {
+        let now = self.now();
+        let shards = self
+            .ask_all(|response_tx| RateLimiterMessage::StoreStats { now, response_tx })
+            .await?;
+        let mut stats = StoreStats {
+            computed_at_ms: now
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as i64)
+                .unwrap_or(0),
+            ..StoreStats::default()
+        };
+        for shard in shards {
+            stats.entries += shard.entries;
+            stats.expired_entries += shard.expired_entries;
+            stats.bytes += shard.bytes;
+            stats.shard_entries.push(shard.entries);
+        }
+        Ok(stats)
+    }
+
+    /// Copy the store's live entries as `(key, value, expiry)`
+    ///
+    /// Walks every key on the actor, so meant for occasional snapshots.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the actor has shut down
+    pub(crate) async fn live_entries(&self) -> Result<Vec<(String, i64, Option<SystemTime>)>> {
+        let now = self.now();
+        let entries = self
+            .ask_all(|response_tx| RateLimiterMessage::Entries { now, response_tx })
+            .await?;
+        Ok(entries.concat())
+    }
+
+    /// Take the entries changed since the last call, for cluster peers
+    ///
+    /// Removed keys are reported as expiring at the Unix epoch, like in the
+    /// WAL. The first call starts tracking changes and returns nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the actor has shut down
+    pub(crate) async fn take_deltas(&self) -> Result<Vec<Entry>> {
+        let deltas = self
+            .ask_all(|response_tx| RateLimiterMessage::TakeDeltas { response_tx })
+            .await?;
+        Ok(deltas.concat())
+    }
+
+    /// Merge entries replicated from a cluster peer, returning how many
+    /// changed the store
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the actor has shut down
+    pub(crate) async fn merge(&self, entries: Vec<Entry>) -> Result<usize> {
+        let now = self.now();
+        let mut by_shard = vec![Vec::new(); self.shards.len()];
+        for entry in entries {
+            by_shard[entry_shard_index(&entry.0, self.shards.len())].push(entry);
+        }
+
+        let mut merged = 0;
+        for (tx, entries) in self.shards.iter().zip(by_shard) {
+            if !entries.is_empty() {
+                merged += ask(tx, |response_tx| RateLimiterMessage::Merge {
+                    entries,
+                    now,
+                    response_tx,
+                })
+                .await?;
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Estimate memory usage by subsystem
+    ///
+    /// The store figures are cached by the actor for up to 10 seconds, since
+    /// computing them walks every key; the rest is computed on each call.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the actor has shut down
+    pub async fn memory_usage(&self) -> Result<MemoryReport> {
+        let now = self.now();
+        let reports = self
+            .ask_all(|response_tx| RateLimiterMessage::MemoryUsage { now, response_tx })
+            .await?;
+        let mut report = reports
+            .into_iter()
+            .reduce(|mut merged, report| {
+                merged.store_entries += report.store_entries;
+                merged.store_bytes += report.store_bytes;
+                merged.canary_store_bytes += report.canary_store_bytes;
+                merged.computed_at_ms = merged.computed_at_ms.min(report.computed_at_ms);
+                merged
+            })
+            .expect("there is at least one shard");
+
+        let queue_depth: usize = self.shards.iter().map(queue_depth).sum();
+        report.queue_bytes = queue_depth * size_of::<RateLimiterMessage>();
+        // Waits on the top keys aggregator
+        let metrics = Arc::clone(&self.metrics);
+        report.metrics_bytes = tokio::task::spawn_blocking(move || metrics.memory_usage()).await?;
+        report.connection_bytes =
+            self.metrics.connection_buffer_bytes.load(Ordering::Relaxed) as usize;
+        report.trace_bytes = self.trace.as_ref().map_or(0, |trace| trace.memory_usage());
+        report.total_bytes = report.store_bytes
+            + report.canary_store_bytes
+            + report.metrics_bytes
+            + report.connection_bytes
+            + report.queue_bytes
+            + report.trace_bytes;
+        Ok(report)
+    }
+
+    /// Number of actors the key space is partitioned across
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// The actor that owns `key`
+    fn shard(&self, key: &str) -> &mpsc::Sender<RateLimiterMessage> {
+        &self.shards[shard_index(key, self.shards.len())]
+    }
+
+    /// Queue a throttle message for the actor behind `tx`, applying the
+    /// backpressure policy if the queue is full
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OverloadedError`] if the queue is full under
+    /// `fail-fast`, or an error if the actor has shut down.
+    async fn enqueue(
+        &self,
+        tx: &mpsc::Sender<RateLimiterMessage>,
+        message: RateLimiterMessage,
+    ) -> Result<()> {
+        let message = match tx.try_send(message) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Closed(_)) => {
+                return Err(anyhow::anyhow!("Rate limiter actor has shut down"));
+            }
+            Err(TrySendError::Full(message)) => message,
+        };
+        self.metrics
+            .queue_full_events
+            .fetch_add(1, Ordering::Relaxed);
+        if self.backpressure == Backpressure::FailFast {
+            self.metrics
+                .overloaded_requests
+                .fetch_add(1, Ordering::Relaxed);
+            return Err(OverloadedError.into());
+        }
+        tx.send(message)
+            .await
+            .map_err(|_| anyhow::anyhow!("Rate limiter actor has shut down"))
+    }
+
+    /// Send a message to every shard and collect the answers in shard order
+    ///
+    /// All messages are sent before any answer is awaited, so the shards
+    /// work in parallel.
+    async fn ask_all<T>(
+        &self,
+        message: impl Fn(oneshot::Sender<T>) -> RateLimiterMessage,
+    ) -> Result<Vec<T>> {
+        let mut pending = Vec::with_capacity(self.shards.len());
+        for tx in self.shards.iter() {
+            let (response_tx, response_rx) = oneshot::channel();
+            tx.send(message(response_tx))
+                .await
+                .map_err(|_| anyhow::anyhow!("Rate limiter actor has shut down"))?;
+            pending.push(response_rx);
+        }
+
+        let mut answers = Vec::with_capacity(pending.len());
+        for response_rx in pending {
+            answers.push(
+                response_rx
+                    .await
+                    .map_err(|_| anyhow::anyhow!("Rate limiter actor dropped response channel"))?,
+            );
+        }
+        Ok(answers)
+    }
+}
+
+/// Send the message built by `message` to one actor and wait for its answer
+async fn ask<T>(
+    tx: &mpsc::Sender<RateLimiterMessage>,
+    message: impl FnOnce(oneshot::Sender<T>) -> RateLimiterMessage,
+) -> Result<T> {
+    let (response_tx, response_rx) = oneshot::channel();
+    tx.send(message(response_tx))
+        .await
+        .map_err(|_| anyhow::anyhow!("Rate limiter actor has shut down"))?;
+
+    response_rx
+        .await
+        .map_err(|_| anyhow::anyhow!("Rate limiter actor dropped response channel"))
+}
+
+/// Index of the shard that owns the store entry under `store_key`
+///
+/// Window entries belong to the shard of the key they were written for,
+/// which is the one that reads them.
+pub(crate) fn entry_shard_index(store_key: &str, shards: usize) -> usize {
+    shard_index(owner_key(store_key), shards)
+}
+
+/// Messages waiting in an actor's queue
+fn queue_depth(tx: &mpsc::Sender<RateLimiterMessage>) -> usize {
+    tx.max_capacity() - tx.capacity()
+}
+
+/// Index of the shard that owns `key` among `shards`
+///
+/// Stable for a given build, so entries restored from a snapshot land on
+/// the shard that will be asked about them.
+pub(crate) fn shard_index(key: &str, shards: usize) -> usize {
+    if shards == 1 {
+        return 0;
+    }
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    (hasher.finish() % shards as u64) as usize
+}
+
+/// Combine per-shard cleanup reports into one for the whole store
+fn merge_cleanups(reports: impl IntoIterator<Item = CleanupReport>) -> Option<CleanupReport> {
+    reports.into_iter().reduce(|mut merged, report| {
+        merged.scanned += report.scanned;
+        merged.removed += report.removed;
+        merged.remaining += report.remaining;
+        merged.complete &= report.complete;
+        // The shards run in parallel, so the pass took as long as the slowest
+        merged.duration_us = merged.duration_us.max(report.duration_us);
+        merged.finished_at_ms = merged.finished_at_ms.max(report.finished_at_ms);
+        merged
+    })
+}
+
+/// The rate limiter actor factory
+///
+/// Provides static methods to spawn rate limiter actors with different store types.
+/// Each actor runs in its own Tokio task and processes messages sequentially.
+pub struct RateLimiterActor;
+
+impl RateLimiterActor {
+    /// Spawn a new rate limiter actor with a periodic store
+    ///
+    /// # Parameters
+    ///
+    /// - `buffer_size`: Channel buffer size for backpressure control
+    /// - `store`: The periodic store instance to use
+    ///
+    /// # Returns
+    ///
+    /// A [`RateLimiterHandle`] for communicating with the actor
+    pub fn spawn_periodic(
+        buffer_size: usize,
+        store: PeriodicStore,
+        metrics: Arc<Metrics>,
+    ) -> RateLimiterHandle {
+        Self::spawn(
+            buffer_size,
+            StoreType::Periodic(RateLimiter::new(store)),
+            KeyAdmission::unbounded(),
+            None,
+            None,
+            None,
+            metrics,
+        )
+    }
+
+    /// Spawn a new rate limiter actor with a probabilistic store
+    ///
+    /// # Parameters
+    ///
+    /// - `buffer_size`: Channel buffer size for backpressure control
+    /// - `store`: The probabilistic store instance to use
+    ///
+    /// # Returns
+    ///
+    /// A [`RateLimiterHandle`] for communicating with the actor
+    pub fn spawn_probabilistic(
+        buffer_size: usize,
+        store: ProbabilisticStore,
+        metrics: Arc<Metrics>,
+    ) -> RateLimiterHandle {
+        Self::spawn(
+            buffer_size,
+            StoreType::Probabilistic(RateLimiter::new(store)),
+            KeyAdmission::unbounded(),
+            None,
+            None,
+            None,
+            metrics,
+        )
+    }
+
+    /// Spawn a new rate limiter actor with an adaptive store
+    ///
+    /// # Parameters
+    ///
+    /// - `buffer_size`: Channel buffer size for backpressure control
+    /// - `store`: The adaptive store instance to use
+    ///
+    /// # Returns
+    ///
+    /// A [`RateLimiterHandle`] for communicating with the actor
+    pub fn spawn_adaptive(
+        buffer_size: usize,
+        store: AdaptiveStore,
+        metrics: Arc<Metrics>,
+    ) -> RateLimiterHandle {
+        Self::spawn(
+            buffer_size,
+            StoreType::Adaptive(RateLimiter::new(store)),
+            KeyAdmission::unbounded(),
+            None,
+            None,
+            None,
+            metrics,
+        )
+    }
+}
+
+impl RateLimiterActor {
+    /// Spawn an actor for an already constructed store
+    ///
+    /// Applies `admission` to new keys and, if given, records every state
+    /// change in the write-ahead log, mirrors sampled keys to the canary and
+    /// lets the auto store selector migrate the store.
+    pub(crate) fn spawn(
+        buffer_size: usize,
+        store_type: StoreType,
+        admission: KeyAdmission,
+        wal: Option<Wal>,
+        canary: Option<Canary>,
+        auto: Option<AutoStore>,
+        metrics: Arc<Metrics>,
+    ) -> RateLimiterHandle {
+        Self::spawn_shards(
+            buffer_size,
+            vec![Shard {
+                store_type,
+                admission,
+                wal,
+                canary,
+                auto,
+                coalesce: None,
+                hot_keys: None,
+                shed_oldest: false,
+            }],
+            metrics,
+        )
+    }
+
+    /// Spawn one actor per shard, each with its own queue of `buffer_size`
+    ///
+    /// Keys are routed to `shards[shard_index(key, shards.len())]`, so each
+    /// shard's store must only hold the keys that hash to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is empty.
+    pub(crate) fn spawn_shards(
+        buffer_size: usize,
+        shards: Vec<Shard>,
+        metrics: Arc<Metrics>,
+    ) -> RateLimiterHandle {
+        assert!(
+            !shards.is_empty(),
+            "a rate limiter needs at least one shard"
+        );
+        let senders = shards
+            .into_iter()
+            .map(|shard| {
+                let (tx, rx) = mpsc::channel(buffer_size);
+                let metrics = Arc::clone(&metrics);
+                tokio::spawn(async move {
+                    run_actor(rx, shard, metrics).await;
+                });
+                tx
+            })
+            .collect();
+
+        RateLimiterHandle {
+            shards: senders,
+            metrics,
+            events: None,
+            hooks: Arc::new([]),
+            trace: None,
+            denial_alerts: None,
+            store_type: None,
+            policies: None,
+            api_keys: None,
+            budgets: None,
+            overrides: None,
+            shedder: None,
+            micro_cache: None,
+            limits: None,
+            backpressure: Backpressure::Block,
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+/// State owned by one actor: a partition of the key space
+pub(crate) struct Shard {
+    pub(crate) store_type: StoreType,
+    pub(crate) admission: KeyAdmission,
+    pub(crate) wal: Option<Wal>,
+    pub(crate) canary: Option<Canary>,
+    pub(crate) auto: Option<AutoStore>,
+    /// How long to collect throttle requests to coalesce (None to decide
+    /// each as it arrives)
+    pub(crate) coalesce: Option<Duration>,
+    /// Most requested keys (None if not counted)
+    pub(crate) hot_keys: Option<HotKeys>,
+    /// Fail the oldest queued throttle requests while senders wait for
+    /// room in the queue (see [`Backpressure::ShedOldest`])
+    pub(crate) shed_oldest: bool,
+}
+
+/// One shard's contribution to the store gauges, which sum over all shards
+#[derive(Default)]
+struct ShardGauges {
+    keys: u64,
+    ttl_capped: u64,
+    queue_depth: u64,
+}
+
+impl ShardGauges {
+    /// Publish the store's current figures, returning the keys held by all shards
+    fn update(&mut self, store_type: &StoreType, metrics: &Metrics) -> u64 {
+        adjust(
+            &metrics.store_ttl_capped,
+            &mut self.ttl_capped,
+            store_type.ttl_capped(),
+        );
+        adjust(&metrics.store_keys, &mut self.keys, store_type.len() as u64)
+    }
+}
+
+/// Fail a throttle message with an [`OverloadedError`] instead of deciding it
+///
+/// Returns other messages for the actor to handle as usual.
+fn shed(msg: RateLimiterMessage, metrics: &Metrics) -> Option<RateLimiterMessage> {
+    let shed = match msg {
+        RateLimiterMessage::Throttle { response_tx, .. } => {
+            let _ = response_tx.send(Err(OverloadedError.into()));
+            1
+        }
+        RateLimiterMessage::ThrottleWindows { response_tx, .. } => {
+            let _ = response_tx.send(Err(OverloadedError.into()));
+            1
+        }
+        RateLimiterMessage::ThrottleBatch {
+            requests,
+            response_tx,
+        } => {
+            let responses = requests
+                .iter()
+                .map(|_| Err(OverloadedError.into()))
+                .collect();
+            let _ = response_tx.send(responses);
+            requests.len() as u64
+        }
+        msg => return Some(msg),
+    };
+    metrics
+        .overloaded_requests
+        .fetch_add(shed, Ordering::Relaxed);
+    None
+}
+
+/// Move `gauge` by the change from `published` to `value`, returning its new total
+fn adjust(gauge: &AtomicU64, published: &mut u64, value: u64) -> u64 {
+    let total = if value >= *published {
+        gauge.fetch_add(value - *published, Ordering::Relaxed) + (value - *published)
+    } else {
+        gauge.fetch_sub(*published - value, Ordering::Relaxed) - (*published - value)
+    };
+    *published = value;
+    total
+}
+
+/// Internal enum to handle different store types
+pub(crate) enum StoreType {
+    Periodic(RateLimiter<PeriodicStore>),
+    Probabilistic(RateLimiter<ProbabilisticStore>),
+    Adaptive(RateLimiter<AdaptiveStore>),
+    TimingWheel(RateLimiter<TimingWheelStore>),
+    /// State kept in Redis, decided outside the actor (see [`detach`]); the
+    /// local store features don't apply
+    #[cfg(feature = "redis-store")]
+    Redis(AsyncRateLimiter<AsyncRedisStore>),
+}
+
+impl StoreType {
+    pub(crate) fn rate_limit(
+        &mut self,
+        key: &str,
+        max_burst: i64,
+        count_per_period: i64,
+        period: i64,
+        quantity: i64,
+        timestamp: std::time::SystemTime,
+    ) -> Result<(bool, throttlecrab::RateLimitResult), CellError> {
+        match self {
+            StoreType::Periodic(limiter) => limiter.rate_limit(
+                key,
+                max_burst,
+                count_per_period,
+                period,
+                quantity,
+                timestamp,
+            ),
+            StoreType::Probabilistic(limiter) => limiter.rate_limit(
+                key,
+                max_burst,
+                count_per_period,
+                period,
+                quantity,
+                timestamp,
+            ),
+            StoreType::Adaptive(limiter) => limiter.rate_limit(
+                key,
+                max_burst,
+                count_per_period,
+                period,
+                quantity,
+                timestamp,
+            ),
+            StoreType::TimingWheel(limiter) => limiter.rate_limit(
+                key,
+                max_burst,
+                count_per_period,
+                period,
+                quantity,
+                timestamp,
+            ),
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => unreachable!("Redis decisions are detached from the actor"),
+        }
+    }
+
+    /// Like [`rate_limit`](Self::rate_limit), but without changing the store
+    pub(crate) fn peek(
+        &self,
+        key: &str,
+        max_burst: i64,
+        count_per_period: i64,
+        period: i64,
+        quantity: i64,
+        timestamp: std::time::SystemTime,
+    ) -> Result<(bool, throttlecrab::RateLimitResult), CellError> {
+        match self {
+            StoreType::Periodic(limiter) => limiter.peek(
+                key,
+                max_burst,
+                count_per_period,
+                period,
+                quantity,
+                timestamp,
+            ),
+            StoreType::Probabilistic(limiter) => limiter.peek(
+                key,
+                max_burst,
+                count_per_period,
+                period,
+                quantity,
+                timestamp,
+            ),
+            StoreType::Adaptive(limiter) => limiter.peek(
+                key,
+                max_burst,
+                count_per_period,
+                period,
+                quantity,
+                timestamp,
+            ),
+            StoreType::TimingWheel(limiter) => limiter.peek(
+                key,
+                max_burst,
+                count_per_period,
+                period,
+                quantity,
+                timestamp,
+            ),
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => unreachable!("Redis decisions are detached from the actor"),
+        }
+    }
+
+    /// Check `request` under `key` with the request's algorithm, warming
+    /// up new keys if the request says so
+    ///
+    /// With `peek` the store is left untouched.
+    pub(crate) fn decide(
+        &mut self,
+        key: &str,
+        request: &ThrottleRequest,
+        peek: bool,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        match request.warm_up {
+            Some(warm_up) => self.decide_with(&warm_up.with(request.algorithm), key, request, peek),
+            None => self.decide_with(&request.algorithm, key, request, peek),
+        }
+    }
+
+    fn decide_with<A: Algorithm>(
+        &mut self,
+        algorithm: &A,
+        key: &str,
+        request: &ThrottleRequest,
+        peek: bool,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let quota = request.quota();
+        let (quantity, timestamp) = (request.quantity, request.timestamp);
+        match self {
+            StoreType::Periodic(limiter) if peek => {
+                limiter.peek_with(algorithm, key, &quota, quantity, timestamp)
+            }
+            StoreType::Periodic(limiter) => {
+                limiter.rate_limit_with(algorithm, key, &quota, quantity, timestamp)
+            }
+            StoreType::Probabilistic(limiter) if peek => {
+                limiter.peek_with(algorithm, key, &quota, quantity, timestamp)
+            }
+            StoreType::Probabilistic(limiter) => {
+                limiter.rate_limit_with(algorithm, key, &quota, quantity, timestamp)
+            }
+            StoreType::Adaptive(limiter) if peek => {
+                limiter.peek_with(algorithm, key, &quota, quantity, timestamp)
+            }
+            StoreType::TimingWheel(limiter) if peek => {
+                limiter.peek_with(algorithm, key, &quota, quantity, timestamp)
+            }
+            StoreType::Adaptive(limiter) => {
+                limiter.rate_limit_with(algorithm, key, &quota, quantity, timestamp)
+            }
+            StoreType::TimingWheel(limiter) => {
+                limiter.rate_limit_with(algorithm, key, &quota, quantity, timestamp)
+            }
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => unreachable!("Redis decisions are detached from the actor"),
+        }
+    }
+
+    /// Whether the store holds state for `key` under the request's algorithm
+    fn tracks(&self, key: &str, request: &ThrottleRequest) -> bool {
+        request
+            .algorithm
+            .state_keys(key, &request.quota(), request.timestamp)
+            .iter()
+            .any(|state| self.contains(state, request.timestamp))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            StoreType::Periodic(limiter) => limiter.store().len(),
+            StoreType::Probabilistic(limiter) => limiter.store().len(),
+            StoreType::Adaptive(limiter) => limiter.store().len(),
+            StoreType::TimingWheel(limiter) => limiter.store().len(),
+            // Redis holds the keys; they aren't counted locally
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => 0,
+        }
+    }
+
+    /// Estimated heap bytes held by the store
+    pub(crate) fn memory_usage(&self) -> usize {
+        match self {
+            StoreType::Periodic(limiter) => limiter.store().memory_usage(),
+            StoreType::Probabilistic(limiter) => limiter.store().memory_usage(),
+            StoreType::Adaptive(limiter) => limiter.store().memory_usage(),
+            StoreType::TimingWheel(limiter) => limiter.store().memory_usage(),
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => 0,
+        }
+    }
+
+    /// Number of writes whose TTL was shortened by the store's TTL cap
+    fn ttl_capped(&self) -> u64 {
+        match self {
+            StoreType::Periodic(limiter) => limiter.store().ttl_capped(),
+            StoreType::Probabilistic(limiter) => limiter.store().ttl_capped(),
+            StoreType::Adaptive(limiter) => limiter.store().ttl_capped(),
+            StoreType::TimingWheel(limiter) => limiter.store().ttl_capped(),
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => 0,
+        }
+    }
+
+    fn contains(&self, key: &str, now: SystemTime) -> bool {
+        let value = match self {
+            StoreType::Periodic(limiter) => limiter.store().get(key, now),
+            StoreType::Probabilistic(limiter) => limiter.store().get(key, now),
+            StoreType::Adaptive(limiter) => limiter.store().get(key, now),
+            StoreType::TimingWheel(limiter) => limiter.store().get(key, now),
+            // Only asked by key limits, which Redis rules out
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => return false,
+        };
+        matches!(value, Ok(Some(_)))
+    }
+
+    pub(crate) fn entry(&self, key: &str) -> Option<(i64, Option<SystemTime>)> {
+        match self {
+            StoreType::Periodic(limiter) => limiter.store().entry(key),
+            StoreType::Probabilistic(limiter) => limiter.store().entry(key),
+            StoreType::Adaptive(limiter) => limiter.store().entry(key),
+            StoreType::TimingWheel(limiter) => limiter.store().entry(key),
+            // Redis holds the entries; admin lookups find none locally
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => None,
+        }
+    }
+
+    /// Number of entries that have expired by `now` but not been removed
+    fn expired_len(&self, now: SystemTime) -> usize {
+        fn count<'a>(
+            iter: impl Iterator<Item = (&'a str, i64, Option<SystemTime>)>,
+            now: SystemTime,
+        ) -> usize {
+            iter.filter(|(_, _, expiry)| expiry.is_some_and(|expiry| expiry <= now))
+                .count()
+        }
+        match self {
+            StoreType::Periodic(limiter) => count(limiter.store().iter(), now),
+            StoreType::Probabilistic(limiter) => count(limiter.store().iter(), now),
+            StoreType::Adaptive(limiter) => count(limiter.store().iter(), now),
+            StoreType::TimingWheel(limiter) => count(limiter.store().iter(), now),
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => 0,
+        }
+    }
+
+    /// Copy out all entries as `(key, value, expiry)`
+    pub(crate) fn entries(&self) -> Vec<(String, i64, Option<SystemTime>)> {
+        fn collect<'a>(
+            iter: impl Iterator<Item = (&'a str, i64, Option<SystemTime>)>,
+        ) -> Vec<(String, i64, Option<SystemTime>)> {
+            iter.map(|(key, value, expiry)| (key.to_string(), value, expiry))
+                .collect()
+        }
+        match self {
+            StoreType::Periodic(limiter) => collect(limiter.store().iter()),
+            StoreType::Probabilistic(limiter) => collect(limiter.store().iter()),
+            StoreType::Adaptive(limiter) => collect(limiter.store().iter()),
+            StoreType::TimingWheel(limiter) => collect(limiter.store().iter()),
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => Vec::new(),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, key: &str, value: i64, expiry: Option<SystemTime>) {
+        match self {
+            StoreType::Periodic(limiter) => limiter.store_mut().insert(key, value, expiry),
+            StoreType::Probabilistic(limiter) => limiter.store_mut().insert(key, value, expiry),
+            StoreType::Adaptive(limiter) => limiter.store_mut().insert(key, value, expiry),
+            StoreType::TimingWheel(limiter) => limiter.store_mut().insert(key, value, expiry),
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => {}
+        }
+    }
+
+    pub(crate) fn remove_expired(&mut self, now: SystemTime) -> usize {
+        match self {
+            StoreType::Periodic(limiter) => limiter.store_mut().remove_expired(now),
+            StoreType::Probabilistic(limiter) => limiter.store_mut().remove_expired(now),
+            StoreType::Adaptive(limiter) => limiter.store_mut().remove_expired(now),
+            StoreType::TimingWheel(limiter) => limiter.store_mut().remove_expired(now),
+            // Redis expires its keys itself
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => 0,
+        }
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) -> bool {
+        match self {
+            StoreType::Periodic(limiter) => limiter.store_mut().remove(key),
+            StoreType::Probabilistic(limiter) => limiter.store_mut().remove(key),
+            StoreType::Adaptive(limiter) => limiter.store_mut().remove(key),
+            StoreType::TimingWheel(limiter) => limiter.store_mut().remove(key),
+            // Resets are detached from the actor, and Redis expires its keys itself
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => false,
+        }
+    }
+
+    /// Remove `key`'s entry and its window entries, returning the keys removed
+    pub(crate) fn reset(&mut self, key: &str) -> Vec<String> {
+        let prefix = window_key_prefix(key);
+        let mut removed = match self {
+            StoreType::Periodic(limiter) => {
+                limiter.store().keys_with_prefix(&prefix, None, usize::MAX)
+            }
+            StoreType::Probabilistic(limiter) => {
+                limiter.store().keys_with_prefix(&prefix, None, usize::MAX)
+            }
+            StoreType::Adaptive(limiter) => {
+                limiter.store().keys_with_prefix(&prefix, None, usize::MAX)
+            }
+            StoreType::TimingWheel(limiter) => {
+                limiter.store().keys_with_prefix(&prefix, None, usize::MAX)
+            }
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => return Vec::new(),
+        }
+        .keys;
+        removed.retain(|key| self.remove(key));
+        if self.remove(key) {
+            removed.push(key.to_string());
+        }
+        removed
+    }
+
+    /// Collect expired keys, stopping early once `deadline` has passed
+    ///
+    /// Returns the number of entries scanned, the expired keys, and whether
+    /// every entry was scanned.
+    fn expired_keys(&self, now: SystemTime, deadline: Instant) -> (usize, Vec<String>, bool) {
+        fn scan<'a>(
+            iter: impl Iterator<Item = (&'a str, i64, Option<SystemTime>)>,
+            now: SystemTime,
+            deadline: Instant,
+        ) -> (usize, Vec<String>, bool) {
+            let mut scanned = 0;
+            let mut expired = Vec::new();
+            for (key, _, expiry) in iter {
+                if scanned % CLEANUP_DEADLINE_CHECK_INTERVAL == 0
+                    && scanned > 0
+                    && Instant::now() >= deadline
+                {
+                    return (scanned, expired, false);
+                }
+                scanned += 1;
+                if expiry.is_some_and(|expiry| expiry <= now) {
+                    expired.push(key.to_string());
+                }
+            }
+            (scanned, expired, true)
+        }
+
+        match self {
+            StoreType::Periodic(limiter) => scan(limiter.store().iter(), now, deadline),
+            StoreType::Probabilistic(limiter) => scan(limiter.store().iter(), now, deadline),
+            StoreType::Adaptive(limiter) => scan(limiter.store().iter(), now, deadline),
+            StoreType::TimingWheel(limiter) => scan(limiter.store().iter(), now, deadline),
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => (0, Vec::new(), true),
+        }
+    }
+
+    /// Run a cleanup pass, bounded by `budget` if given
+    fn cleanup(&mut self, now: SystemTime, budget: Option<Duration>) -> CleanupReport {
+        let started = Instant::now();
+
+        let (scanned, removed, complete) = match budget {
+            None => {
+                let scanned = self.len();
+                (scanned, self.remove_expired(now), true)
+            }
+            Some(budget) => {
+                let (scanned, expired, complete) = self.expired_keys(now, started + budget);
+                let removed = expired.iter().filter(|key| self.remove(key)).count();
+                (scanned, removed, complete)
+            }
+        };
+
+        CleanupReport {
+            scanned,
+            removed,
+            remaining: self.len(),
+            complete,
+            duration_us: started.elapsed().as_micros() as u64,
+            finished_at_ms: now
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as i64)
+                .unwrap_or(0),
+        }
+    }
+
+    fn evict(&mut self, count: usize, now: SystemTime) -> usize {
+        match self {
+            StoreType::Periodic(limiter) => limiter.store_mut().evict(count, now),
+            StoreType::Probabilistic(limiter) => limiter.store_mut().evict(count, now),
+            StoreType::Adaptive(limiter) => limiter.store_mut().evict(count, now),
+            StoreType::TimingWheel(limiter) => limiter.store_mut().evict(count, now),
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => 0,
+        }
+    }
+
+    /// Mark `key` as recently used for LRU eviction
+    fn touch(&mut self, key: &str) {
+        match self {
+            StoreType::Periodic(limiter) => limiter.store_mut().touch(key),
+            StoreType::Probabilistic(limiter) => limiter.store_mut().touch(key),
+            StoreType::Adaptive(limiter) => limiter.store_mut().touch(key),
+            StoreType::TimingWheel(limiter) => limiter.store_mut().touch(key),
+            #[cfg(feature = "redis-store")]
+            StoreType::Redis(_) => {}
+        }
+    }
+}
+
+/// Admission control for new keys when the store has a key limit
+///
+/// Keys that are already tracked are always admitted. Once the store holds
+/// `max_keys` entries, or as many as fit in its memory budget, new keys are
+/// handled according to the [`OnFull`] policy.
+pub(crate) struct KeyAdmission {
+    max_keys: usize,
+    /// Memory budget for entries in bytes (0 for unlimited)
+    max_bytes: usize,
+    /// Moving average of key lengths, to size entries against `max_bytes`
+    mean_key_len: f64,
+    on_full: OnFull,
+    last_purge: Option<SystemTime>,
+    /// Key quotas of namespaces
+    quotas: NamespaceQuotas,
+}
+
+impl KeyAdmission {
+    /// Create admission control limiting the store to `max_keys` (0 for unlimited)
+    pub(crate) fn new(max_keys: usize, on_full: OnFull) -> Self {
+        Self {
+            max_keys,
+            max_bytes: 0,
+            mean_key_len: 0.0,
+            on_full,
+            last_purge: None,
+            quotas: NamespaceQuotas::default(),
+        }
+    }
+
+    /// Also limit the keys of each namespace in `quotas`
+    pub(crate) fn with_namespace_quotas(mut self, quotas: &BTreeMap<String, usize>) -> Self {
+        self.quotas = NamespaceQuotas::new(quotas);
+        self
+    }
+
+    /// Also limit the store to as many entries as fit in `max_bytes`
+    ///
+    /// Entries are sized with [`estimated_entry_memory`] from the average
+    /// key length seen so far, so the budget covers the table slots, keys
+    /// and recency tracking but not the table's spare capacity.
+    pub(crate) fn with_max_memory(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// The current key limit, 0 for unlimited
+    fn limit(&mut self, key: &str) -> usize {
+        if self.max_bytes == 0 {
+            return self.max_keys;
+        }
+
+        self.mean_key_len = if self.mean_key_len == 0.0 {
+            key.len() as f64
+        } else {
+            self.mean_key_len + (key.len() as f64 - self.mean_key_len) / KEY_LEN_SMOOTHING
+        };
+        let entry = estimated_entry_memory(
+            self.mean_key_len.ceil() as usize,
+            self.on_full == OnFull::EvictLru,
+        );
+        let fits = (self.max_bytes / entry).max(1);
+        match self.max_keys {
+            0 => fits,
+            max_keys => max_keys.min(fits),
+        }
+    }
+
+    /// Whether a new key replicated from a cluster peer fits in the store
+    ///
+    /// Unlike client requests, replicated keys never evict or degrade; they
+    /// are dropped while the store is full.
+    fn has_room(&mut self, store_type: &StoreType, key: &str) -> bool {
+        let max_keys = self.limit(key);
+        max_keys == 0 || store_type.len() < max_keys
+    }
+
+    /// Admission control that admits every key
+    pub(crate) fn unbounded() -> Self {
+        Self::new(0, OnFull::Reject)
+    }
+
+    /// Decide which store key a request should use, making room if needed
+    fn admit<'a>(
+        &mut self,
+        store_type: &mut StoreType,
+        request: &'a ThrottleRequest,
+        metrics: &Metrics,
+    ) -> Result<Cow<'a, str>> {
+        let key = &*request.key;
+        let now = request.timestamp;
+
+        // A new key must fit in its namespace's quota before the store's
+        if self.quotas.limits(key)
+            && !store_type.tracks(key, request)
+            && let Err(e) = self.quotas.admit(store_type, key, now)
+        {
+            metrics.record_namespace_rejection(&e.0);
+            return Err(e.into());
+        }
+
+        let max_keys = self.limit(key);
+        if max_keys == 0 || store_type.len() < max_keys || store_type.tracks(key, request) {
+            return Ok(Cow::Borrowed(key));
+        }
+
+        // Expired entries count towards the limit until a cleanup runs, so
+        // purge them first. A purge scans the whole store, so limit how often
+        // a full store can trigger one.
+        let purge_due = self.last_purge.is_none_or(|last| {
+            now.duration_since(last).unwrap_or_default() >= FULL_STORE_PURGE_INTERVAL
+        });
+        if purge_due {
+            self.last_purge = Some(now);
+            if store_type.remove_expired(now) > 0 && store_type.len() < max_keys {
+                return Ok(Cow::Borrowed(key));
+            }
+        }
+
+        match self.on_full {
+            OnFull::Reject => {
+                metrics.store_rejections.fetch_add(1, Ordering::Relaxed);
+                Err(StoreFullError.into())
+            }
+            OnFull::EvictLru => {
+                // Evict in batches so the O(n) scan is amortized over many new keys
+                let batch = (max_keys / 100).max(1);
+                let evicted = store_type.evict(batch.max(store_type.len() + 1 - max_keys), now);
+                metrics
+                    .store_evictions
+                    .fetch_add(evicted as u64, Ordering::Relaxed);
+                Ok(Cow::Borrowed(key))
+            }
+            OnFull::Degrade => {
+                metrics.store_degraded.fetch_add(1, Ordering::Relaxed);
+                let mut hasher = DefaultHasher::new();
+                key.hash(&mut hasher);
+                let bucket = hasher.finish() % DEGRADED_BUCKETS;
+                Ok(Cow::Owned(format!("{DEGRADED_KEY_PREFIX}{bucket}")))
+            }
+        }
+    }
+}
+
+async fn run_actor(
+    mut rx: mpsc::Receiver<RateLimiterMessage>,
+    shard: Shard,
+    metrics: Arc<Metrics>,
+) {
+    let Shard {
+        mut store_type,
+        mut admission,
+        wal,
+        mut canary,
+        mut auto,
+        coalesce,
+        mut hot_keys,
+        shed_oldest,
+    } = shard;
+    let mut changes = Changes { wal, deltas: None };
+    let mut gauges = ShardGauges::default();
+    let mut leases = ConcurrencyLimiter::new();
+    let mut last_lease_purge: Option<SystemTime> = None;
+    let mut last_cleanup = None;
+    let mut memory: Option<(Instant, MemoryReport)> = None;
+
+    // The message that ended a coalescing window, handled next
+    let mut next = None;
+
+    while let Some(msg) = match next.take() {
+        Some(msg) => Some(msg),
+        None => rx.recv().await,
+    } {
+        adjust(
+            &metrics.queue_depth,
+            &mut gauges.queue_depth,
+            rx.len() as u64,
+        );
+        // Senders are waiting for room, so answer the oldest requests unchecked
+        let msg = if shed_oldest && rx.capacity() == 0 {
+            match shed(msg, &metrics) {
+                Some(msg) => msg,
+                None => continue,
+            }
+        } else {
+            msg
+        };
+        #[cfg(feature = "redis-store")]
+        let Some(msg) = detach(&store_type, msg) else {
+            continue;
+        };
+        match msg {
+            RateLimiterMessage::Throttle {
+                request,
+                response_tx,
+                spans,
+            } => {
+                if let Some(hot_keys) = &mut hot_keys {
+                    hot_keys.record(&request.key);
+                }
+                let Some(window) = coalesce else {
+                    let _store = spans.dequeue().entered();
+                    let started = Instant::now();
+                    let response = handle_throttle(
+                        &mut store_type,
+                        &mut admission,
+                        &mut changes,
+                        canary.as_mut(),
+                        &mut gauges,
+                        &metrics,
+                        request,
+                    );
+                    if let Some(auto) = &mut auto {
+                        let now = Instant::now();
+                        auto.observe(now - started, now, &mut store_type, &metrics);
+                    }
+                    // Ignore send errors - receiver may have timed out
+                    let _ = response_tx.send(response);
+                    continue;
+                };
+
+                let throttles;
+                (throttles, next) =
+                    gather(&mut rx, (request, response_tx, spans.dequeue()), window).await;
+                if let Some(hot_keys) = &mut hot_keys {
+                    // The first request was counted before the window opened
+                    for (request, _, _) in &throttles[1..] {
+                        hot_keys.record(&request.key);
+                    }
+                }
+                let started = Instant::now();
+                handle_coalesced(
+                    &mut store_type,
+                    &mut admission,
+                    &mut changes,
+                    canary.as_mut(),
+                    &mut gauges,
+                    &metrics,
+                    throttles,
+                );
+                if let Some(auto) = &mut auto {
+                    let now = Instant::now();
+                    auto.observe(now - started, now, &mut store_type, &metrics);
+                }
+            }
+            RateLimiterMessage::Peek {
+                request,
+                response_tx,
+            } => {
+                let response = store_type
+                    .decide(&request.key, &request, true)
+                    .map(ThrottleResponse::from)
+                    .map_err(|e| anyhow::anyhow!("Rate limit check failed: {}", e));
+                let _ = response_tx.send(response);
+            }
+            RateLimiterMessage::Reset { key, response_tx } => {
+                let removed = store_type.reset(&key);
+                for key in &removed {
+                    changes.removed(key);
+                }
+                if let Some(canary) = &mut canary {
+                    canary.reset(&key);
+                }
+                gauges.update(&store_type, &metrics);
+                let _ = response_tx.send(!removed.is_empty());
+            }
+            RateLimiterMessage::Acquire {
+                request,
+                response_tx,
+            } => {
+                let now = request.timestamp;
+                let purge_due = last_lease_purge.is_none_or(|last| {
+                    now.duration_since(last).unwrap_or_default() >= LEASE_PURGE_INTERVAL
+                });
+                if purge_due {
+                    last_lease_purge = Some(now);
+                    leases.store_mut().remove_expired(now);
+                }
+
+                let response = leases
+                    .acquire(
+                        &request.key,
+                        request.max_concurrent,
+                        Duration::from_secs(request.ttl as u64),
+                        now,
+                    )
+                    .map(AcquireResponse::from)
+                    .map_err(|e| anyhow::anyhow!("Lease request failed: {}", e));
+                let _ = response_tx.send(response);
+            }
+            RateLimiterMessage::Release {
+                key,
+                lease_id,
+                now,
+                response_tx,
+            } => {
+                let _ = response_tx.send(leases.release(&key, lease_id, now));
+            }
+            RateLimiterMessage::ThrottleBatch {
+                requests,
+                response_tx,
+            } => {
+                let mut responses = Vec::with_capacity(requests.len());
+                for request in requests {
+                    if let Some(hot_keys) = &mut hot_keys {
+                        hot_keys.record(&request.key);
+                    }
+                    let started = Instant::now();
+                    responses.push(handle_throttle(
+                        &mut store_type,
+                        &mut admission,
+                        &mut changes,
+                        canary.as_mut(),
+                        &mut gauges,
+                        &metrics,
+                        request,
+                    ));
+                    if let Some(auto) = &mut auto {
+                        let now = Instant::now();
+                        auto.observe(now - started, now, &mut store_type, &metrics);
+                    }
+                }
+                let _ = response_tx.send(responses);
+            }
+            RateLimiterMessage::ThrottleWindows {
+                requests,
+                response_tx,
+            } => {
+                if let Some(hot_keys) = &mut hot_keys {
+                    hot_keys.record(&requests[0].key);
+                }
+                let started = Instant::now();
+                let responses = handle_windows(
+                    &mut store_type,
+                    &mut admission,
+                    &mut changes,
+                    canary.as_mut(),
+                    &mut gauges,
+                    &metrics,
+                    requests,
+                );
+                if let Some(auto) = &mut auto {
+                    let now = Instant::now();
+                    auto.observe(now - started, now, &mut store_type, &metrics);
+                }
+                let _ = response_tx.send(responses);
+            }
+            RateLimiterMessage::Cleanup {
+                now,
+                budget,
+                response_tx,
+            } => {
+                let report = store_type.cleanup(now, budget);
+                leases.store_mut().remove_expired(now);
+                tracing::info!(
+                    "Cleanup pass removed {} of {} scanned entries in {}us",
+                    report.removed,
+                    report.scanned,
+                    report.duration_us
+                );
+                gauges.update(&store_type, &metrics);
+                last_cleanup = Some(report.clone());
+                let _ = response_tx.send(report);
+            }
+            RateLimiterMessage::LastCleanup { response_tx } => {
+                let _ = response_tx.send(last_cleanup.clone());
+            }
+            RateLimiterMessage::CanaryReport { response_tx } => {
+                let _ = response_tx.send(canary.as_ref().map(Canary::report));
+            }
+            RateLimiterMessage::MemoryUsage { now, response_tx } => {
+                let report = match &memory {
+                    Some((at, report)) if at.elapsed() < MEMORY_REFRESH_INTERVAL => report.clone(),
+                    _ => {
+                        let report = MemoryReport {
+                            store_entries: store_type.len(),
+                            store_bytes: store_type.memory_usage(),
+                            canary_store_bytes: canary.as_ref().map_or(0, Canary::memory_usage),
+                            computed_at_ms: now
+                                .duration_since(UNIX_EPOCH)
+                                .map(|d| d.as_millis() as i64)
+                                .unwrap_or(0),
+                            ..MemoryReport::default()
+                        };
+                        memory = Some((Instant::now(), report.clone()));
+                        report
+                    }
+                };
+                let _ = response_tx.send(report);
+            }
+            RateLimiterMessage::HotKeys { limit, response_tx } => {
+                let _ = response_tx.send(hot_keys.as_ref().map(|hot_keys| hot_keys.top(limit)));
+            }
+            RateLimiterMessage::LookupKey {
+                key,
+                now,
+                response_tx,
+            } => {
+                let state = store_type.entry(&key).map(|(tat_ns, expiry)| KeyState {
+                    key: key.to_string(),
+                    tat_ns,
+                    expires_at_ms: expiry.map(|expiry| {
+                        expiry
+                            .duration_since(UNIX_EPOCH)
+                            .map(|d| d.as_millis() as i64)
+                            .unwrap_or(0)
+                    }),
+                    expired: expiry.is_some_and(|expiry| expiry <= now),
+                });
+                let _ = response_tx.send(state);
+            }
+            RateLimiterMessage::StoreStats { now, response_tx } => {
+                let _ = response_tx.send(StoreStats {
+                    entries: store_type.len(),
+                    expired_entries: store_type.expired_len(now),
+                    bytes: store_type.memory_usage(),
+                    ..StoreStats::default()
+                });
+            }
+            RateLimiterMessage::Entries { now, response_tx } => {
+                let mut entries = store_type.entries();
+                entries.retain(|(_, _, expiry)| expiry.is_none_or(|exp| exp > now));
+                let _ = response_tx.send(entries);
+            }
+            RateLimiterMessage::TakeDeltas { response_tx } => {
+                let _ = response_tx.send(changes.take_deltas(&store_type));
+            }
+            RateLimiterMessage::Merge {
+                entries,
+                now,
+                response_tx,
+            } => {
+                let merged = merge(
+                    &mut store_type,
+                    &mut admission,
+                    changes.wal.as_mut(),
+                    entries,
+                    now,
+                );
+                gauges.update(&store_type, &metrics);
+                let _ = response_tx.send(merged);
+            }
+        }
+    }
+
+    tracing::info!("Rate limiter actor shutting down");
+}
+
+/// A throttle request with the channel its response goes to and the span
+/// of its store operation
+type PendingThrottle = (
+    ThrottleRequest,
+    oneshot::Sender<Result<ThrottleResponse>>,
+    tracing::Span,
+);
+
+/// Collect the throttle requests arriving within `window` of `first`, up to
+/// [`MAX_BATCH_SIZE`]
+///
+/// Any other message ends the window early and is returned with the
+/// requests, for the actor to handle next.
+async fn gather(
+    rx: &mut mpsc::Receiver<RateLimiterMessage>,
+    first: PendingThrottle,
+    window: Duration,
+) -> (Vec<PendingThrottle>, Option<RateLimiterMessage>) {
+    let deadline = tokio::time::Instant::now() + window;
+    let mut throttles = vec![first];
+    while throttles.len() < MAX_BATCH_SIZE {
+        match tokio::time::timeout_at(deadline, rx.recv()).await {
+            Ok(Some(RateLimiterMessage::Throttle {
+                request,
+                response_tx,
+                spans,
+            })) => throttles.push((request, response_tx, spans.dequeue())),
+            Ok(msg) => return (throttles, msg),
+            Err(_) => break,
+        }
+    }
+    (throttles, None)
+}
+
+/// Decide gathered throttle requests, checking identical ones together
+///
+/// Requests with the same key, parameters and algorithm form a group,
+/// checked as one request for their total quantity at the latest of their
+/// timestamps. If that request is allowed, so is every request in the
+/// group, and all of them get its response: the key's state after the
+/// whole group. Otherwise the group is decided one request at a time,
+/// exactly as without coalescing, so a group never denies a request that
+/// would have been allowed on its own.
+fn handle_coalesced(
+    store_type: &mut StoreType,
+    admission: &mut KeyAdmission,
+    changes: &mut Changes,
+    mut canary: Option<&mut Canary>,
+    gauges: &mut ShardGauges,
+    metrics: &Metrics,
+    throttles: Vec<PendingThrottle>,
+) {
+    type GroupKey = (Arc<str>, i64, i64, i64, AlgorithmKind, Option<WarmUp>);
+
+    // Groups in the order their first request arrived
+    let mut groups: Vec<Vec<PendingThrottle>> = Vec::new();
+    let mut index: HashMap<GroupKey, usize> = HashMap::new();
+    for pending in throttles {
+        let request = &pending.0;
+        let key = (
+            Arc::clone(&request.key),
+            request.max_burst,
+            request.count_per_period,
+            request.period,
+            request.algorithm,
+            request.warm_up,
+        );
+        match index.entry(key) {
+            MapEntry::Occupied(entry) => groups[*entry.get()].push(pending),
+            MapEntry::Vacant(entry) => {
+                entry.insert(groups.len());
+                groups.push(vec![pending]);
+            }
+        }
+    }
+
+    for group in groups {
+        if group.len() > 1 {
+            // The group's store operation is recorded in its first span
+            let store = group[0].2.clone().entered();
+            let combined = combine(&group);
+            // Peek first: a denied request still touches the key and is
+            // mirrored to the canary, which deciding one at a time repeats
+            let allowed = combined.as_ref().is_some_and(|request| {
+                store_type
+                    .decide(&request.key, request, true)
+                    .is_ok_and(|(allowed, _)| allowed)
+            });
+            if let Some(request) = combined.filter(|_| allowed)
+                && let Ok(response) = handle_throttle(
+                    store_type,
+                    admission,
+                    changes,
+                    canary.as_deref_mut(),
+                    gauges,
+                    metrics,
+                    request,
+                )
+                && response.allowed
+            {
+                metrics
+                    .coalesced_requests
+                    .fetch_add(group.len() as u64, Ordering::Relaxed);
+                for (_, response_tx, _) in group {
+                    let _ = response_tx.send(Ok(response.clone()));
+                }
+                continue;
+            }
+            drop(store);
+        }
+
+        for (request, response_tx, span) in group {
+            let _store = span.entered();
+            let response = handle_throttle(
+                store_type,
+                admission,
+                changes,
+                canary.as_deref_mut(),
+                gauges,
+                metrics,
+                request,
+            );
+            let _ = response_tx.send(response);
+        }
+    }
+}
+
+/// The request for a group's total quantity at its latest timestamp, or
+/// None if the total overflows
+fn combine(group: &[PendingThrottle]) -> Option<ThrottleRequest> {
+    let quantity = group.iter().try_fold(0i64, |total, (request, ..)| {
+        total.checked_add(request.quantity)
+    })?;
+    let timestamp = group.iter().map(|(request, ..)| request.timestamp).max()?;
+    Some(ThrottleRequest {
+        quantity,
+        timestamp,
+        ..group[0].0.clone()
+    })
+}
+
+/// Hand a Redis store's decisions and resets to tasks of their own
+///
+/// Each task works on a clone of the limiter, sharing its connection.
+/// Returns other messages, and every message for other stores, to the
+/// actor.
+#[cfg(feature = "redis-store")]
+fn detach(store_type: &StoreType, msg: RateLimiterMessage) -> Option<RateLimiterMessage> {
+    let StoreType::Redis(limiter) = store_type else {
+        return Some(msg);
+    };
+    match msg {
+        RateLimiterMessage::Throttle {
+            request,
+            response_tx,
+            spans,
+        } => {
+            let mut limiter = limiter.clone();
+            tokio::spawn(
+                async move {
+                    let _ = response_tx.send(decide_remote(&mut limiter, &request, false).await);
+                }
+                .instrument(spans.dequeue()),
+            );
+        }
+        RateLimiterMessage::Peek {
+            request,
+            response_tx,
+        } => {
+            let mut limiter = limiter.clone();
+            tokio::spawn(async move {
+                let _ = response_tx.send(decide_remote(&mut limiter, &request, true).await);
+            });
+        }
+        RateLimiterMessage::ThrottleBatch {
+            requests,
+            response_tx,
+        } => {
+            let mut limiter = limiter.clone();
+            tokio::spawn(async move {
+                let mut responses = Vec::with_capacity(requests.len());
+                for request in &requests {
+                    responses.push(decide_remote(&mut limiter, request, false).await);
+                }
+                let _ = response_tx.send(responses);
+            });
+        }
+        RateLimiterMessage::ThrottleWindows {
+            requests,
+            response_tx,
+        } => {
+            // Other servers may consume tokens between the checks, so the
+            // windows are checked together but not atomically
+            let mut limiter = limiter.clone();
+            tokio::spawn(async move {
+                let _ = response_tx.send(decide_remote_windows(&mut limiter, &requests).await);
+            });
+        }
+        RateLimiterMessage::Reset { key, response_tx } => {
+            let mut limiter = limiter.clone();
+            tokio::spawn(async move {
+                let existed = limiter
+                    .store_mut()
+                    .remove_with_windows(&key)
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::warn!("Failed to remove {} from Redis: {}", key, e);
+                        false
+                    });
+                let _ = response_tx.send(existed);
+            });
+        }
+        msg => return Some(msg),
+    }
+    None
+}
+
+/// Check `request` against Redis with the request's algorithm
+///
+/// With `peek` Redis is left untouched.
+#[cfg(feature = "redis-store")]
+async fn decide_remote(
+    limiter: &mut AsyncRateLimiter<AsyncRedisStore>,
+    request: &ThrottleRequest,
+    peek: bool,
+) -> Result<ThrottleResponse> {
+    match request.warm_up {
+        Some(warm_up) => {
+            decide_remote_with(limiter, &warm_up.with(request.algorithm), request, peek).await
+        }
+        None => decide_remote_with(limiter, &request.algorithm, request, peek).await,
+    }
+}
+
+#[cfg(feature = "redis-store")]
+async fn decide_remote_with<A: Algorithm>(
+    limiter: &mut AsyncRateLimiter<AsyncRedisStore>,
+    algorithm: &A,
+    request: &ThrottleRequest,
+    peek: bool,
+) -> Result<ThrottleResponse> {
+    let quota = request.quota();
+    let (key, quantity, timestamp) = (&*request.key, request.quantity, request.timestamp);
+    let decision = if peek {
+        limiter
+            .peek_with(algorithm, key, &quota, quantity, timestamp)
+            .await
+    } else {
+        limiter
+            .rate_limit_with(algorithm, key, &quota, quantity, timestamp)
+            .await
+    };
+    decision
+        .map(ThrottleResponse::from)
+        .map_err(|e| anyhow::anyhow!("Rate limit check failed: {}", e))
+}
+
+/// Check every window of a request on a Redis store, then consume from
+/// each if all allow it
+#[cfg(feature = "redis-store")]
+async fn decide_remote_windows(
+    limiter: &mut AsyncRateLimiter<AsyncRedisStore>,
+    requests: &[ThrottleRequest],
+) -> Result<Vec<ThrottleResponse>> {
+    let mut peeks = Vec::with_capacity(requests.len());
+    for request in requests {
+        peeks.push(decide_remote(limiter, request, true).await?);
+    }
+    if peeks.iter().any(|peek| !peek.allowed) {
+        return Ok(peeks);
+    }
+    let mut responses = Vec::with_capacity(requests.len());
+    for request in requests {
+        responses.push(decide_remote(limiter, request, false).await?);
+    }
+    Ok(responses)
+}
+
+/// Check every window of a request, then consume from each if all allow it
+///
+/// A denial is answered with the peeked decisions, leaving every window
+/// untouched.
+fn handle_windows(
+    store_type: &mut StoreType,
+    admission: &mut KeyAdmission,
+    changes: &mut Changes,
+    mut canary: Option<&mut Canary>,
+    gauges: &mut ShardGauges,
+    metrics: &Metrics,
+    requests: Vec<ThrottleRequest>,
+) -> Result<Vec<ThrottleResponse>> {
+    let peeks = requests
+        .iter()
+        .map(|request| {
+            store_type
+                .decide(&request.key, request, true)
+                .map(ThrottleResponse::from)
+                .map_err(|e| anyhow::anyhow!("Rate limit check failed: {}", e))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if peeks.iter().any(|peek| !peek.allowed) {
+        return Ok(peeks);
+    }
+
+    requests
+        .into_iter()
+        .map(|request| {
+            handle_throttle(
+                store_type,
+                admission,
+                changes,
+                canary.as_deref_mut(),
+                gauges,
+                metrics,
+                request,
+            )
+        })
+        .collect()
+}
+
+fn handle_throttle(
+    store_type: &mut StoreType,
+    admission: &mut KeyAdmission,
+    changes: &mut Changes,
+    canary: Option<&mut Canary>,
+    gauges: &mut ShardGauges,
+    metrics: &Metrics,
     request: ThrottleRequest,
 ) -> Result<ThrottleResponse> {
+    // Apply the key limit before touching the store
+    let key = admission.admit(store_type, &request, metrics)?;
+
     // Check the rate limit
     let (allowed, result) = store_type
-        .rate_limit(
-            &request.key,
-            request.max_burst,
-            request.count_per_period,
-            request.period,
-            request.quantity,
-            request.timestamp,
-        )
+        .decide(&key, &request, false)
         .map_err(|e| anyhow::anyhow!("Rate limit check failed: {}", e))?;
 
+    let store_keys = gauges.update(store_type, metrics);
+    metrics
+        .peak_store_keys
+        .observe(store_keys, request.timestamp);
+
+    // Only allowed requests change the stored state
+    if allowed && changes.is_enabled() {
+        // The algorithm writes the first of its state keys
+        let state_keys = request
+            .algorithm
+            .state_keys(&key, &request.quota(), request.timestamp);
+        changes.updated(store_type, &state_keys[0]);
+    }
+
+    // A denied request writes nothing, but its key is still in use
+    if !allowed {
+        store_type.touch(&key);
+    }
+
+    // Mirror sampled keys; the primary result is returned regardless
+    if let Some(canary) = canary
+        && canary.selects(&key)
+    {
+        canary.compare(&key, &request, (allowed, &result), metrics);
+    }
+
     Ok(ThrottleResponse::from((allowed, result)))
 }
+
+/// Where an actor reports the keys whose state it changed
+struct Changes {
+    /// Write-ahead log persisting every change
+    wal: Option<Wal>,
+    /// Keys changed since cluster peers were last sent them, tracked once
+    /// the cluster first asks (see [`crate::cluster`])
+    deltas: Option<HashSet<String>>,
+}
+
+impl Changes {
+    /// Whether anything consumes changes
+    fn is_enabled(&self) -> bool {
+        self.wal.is_some() || self.deltas.is_some()
+    }
+
+    /// Record the current state of `key` after it was updated
+    fn updated(&mut self, store_type: &StoreType, key: &str) {
+        if let Some(wal) = &mut self.wal {
+            wal.append(store_type, key);
+        }
+        if let Some(deltas) = &mut self.deltas
+            && !deltas.contains(key)
+        {
+            deltas.insert(key.to_string());
+        }
+    }
+
+    /// Record that `key` was removed from the store
+    fn removed(&mut self, key: &str) {
+        if let Some(wal) = &mut self.wal {
+            wal.remove(key);
+        }
+        if let Some(deltas) = &mut self.deltas {
+            deltas.insert(key.to_string());
+        }
+    }
+
+    /// Current state of the keys changed since the last call
+    fn take_deltas(&mut self, store_type: &StoreType) -> Vec<Entry> {
+        let Some(deltas) = &mut self.deltas else {
+            self.deltas = Some(HashSet::new());
+            return Vec::new();
+        };
+        deltas
+            .drain()
+            .map(|key| match store_type.entry(&key) {
+                Some((value, expiry)) => (key, value, expiry),
+                None => (key, 0, Some(UNIX_EPOCH)),
+            })
+            .collect()
+    }
+}
+
+/// Merge entries replicated from a cluster peer, returning how many changed
+/// the store
+///
+/// The larger value wins. Every algorithm stores a time or count that only
+/// grows as quantity is spent, so a key ends up as limited as the more
+/// limited of the two nodes. Spending on both nodes within one sync interval
+/// is not added up, which is what makes the shared state approximate.
+/// Expired entries remove their key. Merged changes go to the WAL but are
+/// not passed on to other peers, since every node syncs with every other.
+fn merge(
+    store_type: &mut StoreType,
+    admission: &mut KeyAdmission,
+    mut wal: Option<&mut Wal>,
+    entries: Vec<Entry>,
+    now: SystemTime,
+) -> usize {
+    let mut merged = 0;
+    for (key, value, expiry) in entries {
+        if expiry.is_some_and(|exp| exp <= now) {
+            if store_type.remove(&key) {
+                if let Some(wal) = wal.as_deref_mut() {
+                    wal.remove(&key);
+                }
+                merged += 1;
+            }
+            continue;
+        }
+
+        let newer = match store_type.entry(&key) {
+            Some((local, local_expiry)) => {
+                value > local || local_expiry.is_some_and(|exp| exp <= now)
+            }
+            None => admission.has_room(store_type, &key),
+        };
+        if newer {
+            store_type.insert(&key, value, expiry);
+            if let Some(wal) = wal.as_deref_mut() {
+                wal.append(store_type, &key);
+            }
+            merged += 1;
+        }
+    }
+    merged
+}
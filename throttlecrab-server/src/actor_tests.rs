@@ -1,9 +1,36 @@
 #[cfg(test)]
 mod tests {
-    use crate::actor::RateLimiterActor;
-    use crate::types::ThrottleRequest;
+    use crate::actor::{
+        KeyAdmission, OverloadedError, RateLimiterActor, RateLimiterHandle, StoreFullError,
+        StoreType,
+    };
+    use crate::config::{Backpressure, LoadSheddingConfig, OnFull, ShedDecision, ValidationConfig};
+    use crate::namespace::NamespaceFullError;
+    use crate::policy::{Policies, UnknownOperationError};
+    use crate::shed::LoadShedder;
+    use crate::types::{AcquireRequest, AlgorithmKind, ThrottleRequest, ValidationError};
+    use crate::validation::RequestLimits;
+    use crate::windows::{self, Window};
     use std::sync::Arc;
-    use throttlecrab::PeriodicStore;
+    use std::sync::atomic::Ordering;
+    use throttlecrab::{MockClock, PeriodicStore, RateLimiter};
+
+    fn spawn_bounded(
+        max_keys: usize,
+        on_full: OnFull,
+    ) -> (RateLimiterHandle, Arc<crate::metrics::Metrics>) {
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let handle = RateLimiterActor::spawn(
+            100,
+            StoreType::Periodic(RateLimiter::new(PeriodicStore::new())),
+            KeyAdmission::new(max_keys, on_full),
+            None,
+            None,
+            None,
+            Arc::clone(&metrics),
+        );
+        (handle, metrics)
+    }
 
     #[tokio::test]
     async fn test_basic_rate_limiting() {
@@ -15,14 +42,7 @@ mod tests {
         let handle = RateLimiterActor::spawn_periodic(100, store, metrics);
 
         // First request should succeed
-        let req = ThrottleRequest {
-            key: "test".to_string(),
-            max_burst: 5,
-            count_per_period: 10,
-            period: 60,
-            quantity: 1,
-            timestamp: std::time::SystemTime::now(),
-        };
+        let req = ThrottleRequest::new("test", 5, 10, 60);
 
         let resp = handle.throttle(req.clone()).await.unwrap();
         assert!(resp.allowed);
@@ -30,6 +50,328 @@ mod tests {
         assert_eq!(resp.remaining, 4);
     }
 
+    #[tokio::test]
+    async fn test_peek() {
+        let (handle, metrics) = spawn_bounded(100, OnFull::Reject);
+        let mut peek = ThrottleRequest::new("a", 2, 10, 60);
+        peek.quantity = 0;
+
+        // Unknown keys are reported at full capacity and not created
+        let response = handle.peek(peek.clone()).await.unwrap();
+        assert!(response.allowed);
+        assert_eq!(response.remaining, 2);
+        assert_eq!(metrics.store_keys.load(Ordering::Relaxed), 0);
+
+        let throttled = ThrottleRequest::new("a", 2, 10, 60);
+        peek.timestamp = throttled.timestamp;
+        handle.throttle(throttled).await.unwrap();
+        let response = handle.peek(peek.clone()).await.unwrap();
+        assert_eq!(response.remaining, 1);
+        let response = handle.peek(peek).await.unwrap();
+        assert_eq!(response.remaining, 1);
+        assert_eq!(metrics.total_requests.load(Ordering::Relaxed), 0);
+        assert_eq!(metrics.peek_requests.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn test_sliding_window() {
+        let (handle, metrics) = spawn_bounded(1, OnFull::Reject);
+        let sliding = ThrottleRequest {
+            algorithm: AlgorithmKind::SlidingWindow,
+            ..ThrottleRequest::new("a", 2, 10, 60)
+        };
+
+        // The whole window's count is available at once, unlike max_burst
+        for remaining in (0..10).rev() {
+            let response = handle.throttle(sliding.clone()).await.unwrap();
+            assert!(response.allowed);
+            assert_eq!(response.limit, 10);
+            assert_eq!(response.remaining, remaining);
+        }
+        let response = handle.throttle(sliding.clone()).await.unwrap();
+        assert!(!response.allowed);
+        assert!(response.retry_after > 0);
+        assert!(!handle.peek(sliding).await.unwrap().allowed);
+
+        // The window entry counts towards the key limit
+        assert_eq!(metrics.store_keys.load(Ordering::Relaxed), 1);
+        let error = handle
+            .throttle(ThrottleRequest::new("b", 2, 10, 60))
+            .await
+            .unwrap_err();
+        assert!(error.downcast_ref::<StoreFullError>().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_fixed_window_and_leaky_bucket() {
+        let (handle, metrics) = spawn_bounded(100, OnFull::Reject);
+        let fixed = ThrottleRequest {
+            algorithm: AlgorithmKind::FixedWindow,
+            ..ThrottleRequest::new("fixed", 2, 10, 60)
+        };
+        let leaky = ThrottleRequest {
+            algorithm: AlgorithmKind::LeakyBucket,
+            ..ThrottleRequest::new("leaky", 2, 10, 60)
+        };
+
+        for _ in 0..10 {
+            assert!(handle.throttle(fixed.clone()).await.unwrap().allowed);
+        }
+        assert!(!handle.throttle(fixed).await.unwrap().allowed);
+
+        // The bucket holds max_burst
+        for remaining in (0..2).rev() {
+            let response = handle.throttle(leaky.clone()).await.unwrap();
+            assert!(response.allowed);
+            assert_eq!(response.remaining, remaining);
+        }
+        let response = handle.throttle(leaky.clone()).await.unwrap();
+        assert!(!response.allowed);
+        assert!(response.retry_after > 0);
+        assert!(!handle.peek(leaky).await.unwrap().allowed);
+
+        assert_eq!(metrics.store_keys.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reset() {
+        let (handle, metrics) = spawn_bounded(100, OnFull::Reject);
+        handle
+            .throttle(ThrottleRequest::new("a", 2, 10, 60))
+            .await
+            .unwrap();
+        handle
+            .throttle(ThrottleRequest::new("a", 2, 10, 60))
+            .await
+            .unwrap();
+        assert!(
+            !handle
+                .throttle(ThrottleRequest::new("a", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        assert_eq!(metrics.store_keys.load(Ordering::Relaxed), 1);
+
+        let report = handle
+            .reset("a".into(), crate::metrics::Transport::Http)
+            .await
+            .unwrap();
+        assert!(report.existed);
+        assert_eq!(report.key, "a");
+        assert_eq!(metrics.store_keys.load(Ordering::Relaxed), 0);
+        assert_eq!(metrics.key_resets.load(Ordering::Relaxed), 1);
+
+        // The key starts over with its full burst
+        let response = handle
+            .throttle(ThrottleRequest::new("a", 2, 10, 60))
+            .await
+            .unwrap();
+        assert_eq!(response.remaining, 1);
+
+        let report = handle
+            .reset("unknown".into(), crate::metrics::Transport::Http)
+            .await
+            .unwrap();
+        assert!(!report.existed);
+    }
+
+    #[tokio::test]
+    async fn test_reset_sliding_window() {
+        let (handle, metrics) = spawn_bounded(100, OnFull::Reject);
+        let sliding = ThrottleRequest {
+            algorithm: AlgorithmKind::SlidingWindow,
+            max_burst: 10,
+            quantity: 10,
+            ..ThrottleRequest::new("a", 2, 10, 60)
+        };
+        assert!(handle.throttle(sliding.clone()).await.unwrap().allowed);
+        assert!(!handle.throttle(sliding.clone()).await.unwrap().allowed);
+
+        // The window entries go with the key
+        let report = handle
+            .reset("a".into(), crate::metrics::Transport::Http)
+            .await
+            .unwrap();
+        assert!(report.existed);
+        assert_eq!(metrics.store_keys.load(Ordering::Relaxed), 0);
+        assert!(handle.throttle(sliding).await.unwrap().allowed);
+    }
+
+    #[tokio::test]
+    async fn test_reset_fixed_window() {
+        let (handle, metrics) = spawn_bounded(100, OnFull::Reject);
+        let fixed = ThrottleRequest {
+            algorithm: AlgorithmKind::FixedWindow,
+            max_burst: 10,
+            quantity: 10,
+            ..ThrottleRequest::new("b", 2, 10, 60)
+        };
+        assert!(handle.throttle(fixed.clone()).await.unwrap().allowed);
+        assert!(!handle.throttle(fixed.clone()).await.unwrap().allowed);
+
+        let report = handle
+            .reset("b".into(), crate::metrics::Transport::Http)
+            .await
+            .unwrap();
+        assert!(report.existed);
+        assert_eq!(metrics.store_keys.load(Ordering::Relaxed), 0);
+        assert!(handle.throttle(fixed).await.unwrap().allowed);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_release() {
+        let (handle, metrics) = spawn_bounded(100, OnFull::Reject);
+        let acquire = |key: &str| AcquireRequest {
+            key: key.into(),
+            max_concurrent: 2,
+            ttl: 60,
+            timestamp: handle.now(),
+        };
+
+        let first = handle.acquire(acquire("jobs")).await.unwrap();
+        assert!(first.acquired);
+        assert!(handle.acquire(acquire("jobs")).await.unwrap().acquired);
+        let denied = handle.acquire(acquire("jobs")).await.unwrap();
+        assert!(!denied.acquired);
+        assert_eq!(denied.lease_id, None);
+        assert_eq!(denied.active, 2);
+        assert_eq!(denied.retry_after, 60);
+
+        // Releasing frees a slot, once
+        let lease_id = first.lease_id.unwrap();
+        assert!(handle.release("jobs".into(), lease_id).await.unwrap());
+        assert!(!handle.release("jobs".into(), lease_id).await.unwrap());
+        assert!(handle.acquire(acquire("jobs")).await.unwrap().acquired);
+
+        // Leases are not rate limit requests and hold no store entries
+        assert_eq!(metrics.total_requests.load(Ordering::Relaxed), 0);
+        assert_eq!(metrics.store_keys.load(Ordering::Relaxed), 0);
+        assert_eq!(metrics.leases_acquired.load(Ordering::Relaxed), 3);
+        assert_eq!(metrics.leases_denied.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.leases_released.load(Ordering::Relaxed), 1);
+
+        let mut invalid = acquire("jobs");
+        invalid.ttl = 0;
+        let err = handle.acquire(invalid).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ValidationError>().map(|e| e.code()),
+            Some("invalid_ttl")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_throttle_batch() {
+        let (handle, _) = spawn_bounded(100, OnFull::Reject);
+        let mut invalid = ThrottleRequest::new("c", 2, 10, 60);
+        invalid.period = 0;
+
+        let results = handle
+            .throttle_batch(vec![
+                ThrottleRequest::new("a", 2, 10, 60),
+                invalid,
+                ThrottleRequest::new("a", 2, 10, 60),
+                ThrottleRequest::new("a", 2, 10, 60),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 4);
+        assert!(results[0].as_ref().unwrap().allowed);
+        assert!(results[1].is_err());
+        assert!(results[2].as_ref().unwrap().allowed);
+        // The burst of 2 is spent by the earlier requests in the batch
+        assert!(!results[3].as_ref().unwrap().allowed);
+
+        let oversized =
+            vec![ThrottleRequest::new("a", 2, 10, 60); crate::types::MAX_BATCH_SIZE + 1];
+        assert!(handle.throttle_batch(oversized).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_throttle_windows() {
+        let (handle, _) = spawn_bounded(100, OnFull::Reject);
+        // 3 per second and 2 per hour: the hourly window binds
+        let windows = [
+            Window {
+                max_burst: 3,
+                count_per_period: 3,
+                period: 1,
+            },
+            Window {
+                max_burst: 2,
+                count_per_period: 2,
+                period: 3600,
+            },
+        ];
+        let requests =
+            || windows::requests(&ThrottleRequest::new("w", 2, 10, 60), &windows).unwrap();
+
+        let (decided, shed) = handle.throttle_windows(requests()).await.unwrap();
+        assert!(decided.response.allowed && !shed);
+        assert_eq!(decided.window, 1);
+        assert_eq!(decided.response.remaining, 1);
+
+        assert!(
+            handle
+                .throttle_windows(requests())
+                .await
+                .unwrap()
+                .0
+                .response
+                .allowed
+        );
+        let (decided, _) = handle.throttle_windows(requests()).await.unwrap();
+        assert!(!decided.response.allowed);
+        assert_eq!(decided.window, 1);
+
+        // The denial consumed nothing from the per-second window
+        let mut peek = requests().remove(0);
+        peek.quantity = 0;
+        assert_eq!(handle.peek(peek).await.unwrap().remaining, 1);
+    }
+
+    #[tokio::test]
+    async fn test_reset_windows() {
+        let (handle, _) = spawn_bounded(100, OnFull::Reject);
+        let windows = [
+            Window {
+                max_burst: 3,
+                count_per_period: 3,
+                period: 1,
+            },
+            Window {
+                max_burst: 2,
+                count_per_period: 2,
+                period: 3600,
+            },
+        ];
+        let requests =
+            || windows::requests(&ThrottleRequest::new("w", 2, 10, 60), &windows).unwrap();
+        for _ in 0..2 {
+            let (decided, _) = handle.throttle_windows(requests()).await.unwrap();
+            assert!(decided.response.allowed);
+        }
+        let (decided, _) = handle.throttle_windows(requests()).await.unwrap();
+        assert!(!decided.response.allowed);
+
+        // A client key containing `#` is its own key, not a window of `w`
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("w#1", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+
+        // Resetting the key clears every window with it
+        handle
+            .reset("w".into(), crate::metrics::Transport::Http)
+            .await
+            .unwrap();
+        let (decided, _) = handle.throttle_windows(requests()).await.unwrap();
+        assert!(decided.response.allowed);
+    }
+
     #[tokio::test]
     async fn test_concurrent_requests() {
         let store = PeriodicStore::builder()
@@ -39,14 +381,7 @@ mod tests {
         let metrics = Arc::new(crate::metrics::Metrics::new());
         let handle = RateLimiterActor::spawn_periodic(100, store, metrics);
 
-        let req = ThrottleRequest {
-            key: "concurrent_test".to_string(),
-            max_burst: 10,
-            count_per_period: 10,
-            period: 60,
-            quantity: 1,
-            timestamp: std::time::SystemTime::now(),
-        };
+        let req = ThrottleRequest::new("concurrent_test", 10, 10, 60);
 
         // Send multiple concurrent requests
         let mut handles = vec![];
@@ -68,4 +403,855 @@ mod tests {
         // Should allow exactly burst capacity
         assert_eq!(allowed_count, 10);
     }
+
+    #[tokio::test]
+    async fn test_max_keys_reject() {
+        let (handle, metrics) = spawn_bounded(2, OnFull::Reject);
+
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("a", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("b", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+
+        // Known keys keep working, new keys are rejected
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("a", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        let err = handle
+            .throttle(ThrottleRequest::new("c", 2, 10, 60))
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<StoreFullError>().is_some());
+        assert_eq!(metrics.store_rejections.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_max_keys_evict_lru() {
+        let (handle, metrics) = spawn_bounded(2, OnFull::EvictLru);
+
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("a", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("b", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("c", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        assert_eq!(metrics.store_evictions.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_evict_lru_keeps_denied_keys() {
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let store = PeriodicStore::builder().track_recency(true).build();
+        let handle = RateLimiterActor::spawn(
+            100,
+            StoreType::Periodic(RateLimiter::new(store)),
+            KeyAdmission::new(2, OnFull::EvictLru),
+            None,
+            None,
+            None,
+            Arc::clone(&metrics),
+        );
+
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("a", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("a", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("b", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        // Denied, but still counts as use
+        assert!(
+            !handle
+                .throttle(ThrottleRequest::new("a", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+
+        // "b" is the least recently used and makes room for "c"
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("c", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        assert_eq!(metrics.store_evictions.load(Ordering::Relaxed), 1);
+        assert!(
+            !handle
+                .throttle(ThrottleRequest::new("a", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_max_memory_reject() {
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let entry = throttlecrab::store::estimated_entry_memory(1, false);
+        let handle = RateLimiterActor::spawn(
+            100,
+            StoreType::Periodic(RateLimiter::new(PeriodicStore::new())),
+            KeyAdmission::new(0, OnFull::Reject).with_max_memory(2 * entry + 1),
+            None,
+            None,
+            None,
+            Arc::clone(&metrics),
+        );
+
+        // Room for two one-byte keys
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("a", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("b", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        let err = handle
+            .throttle(ThrottleRequest::new("c", 2, 10, 60))
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<StoreFullError>().is_some());
+        assert_eq!(metrics.store_rejections.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_namespace_quota() {
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let quotas = [("checkout".to_string(), 2)].into();
+        let handle = RateLimiterActor::spawn(
+            100,
+            StoreType::Periodic(RateLimiter::new(PeriodicStore::new())),
+            KeyAdmission::new(0, OnFull::Reject).with_namespace_quotas(&quotas),
+            None,
+            None,
+            None,
+            Arc::clone(&metrics),
+        );
+
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("checkout/a", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("checkout/b", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+
+        // Known keys keep working, new keys in the namespace are rejected
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("checkout/a", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        let err = handle
+            .throttle(ThrottleRequest::new("checkout/c", 2, 10, 60))
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<NamespaceFullError>().is_some());
+        assert_eq!(metrics.namespaces()["checkout"].quota_rejections, 1);
+
+        // Other namespaces and keys without one are unaffected
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("search/c", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("c", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_shedding() {
+        let (handle, metrics) = spawn_bounded(0, OnFull::Reject);
+        let handle = handle.with_load_shedding(Arc::new(LoadShedder::new(&LoadSheddingConfig {
+            max_rps: 1,
+            decision: ShedDecision::Deny,
+        })));
+
+        // A second boundary may admit one more, but no more than that
+        let mut shed = 0;
+        for _ in 0..5 {
+            let (response, was_shed) = handle
+                .throttle_or_shed(ThrottleRequest::new("a", 2, 10, 60))
+                .await
+                .unwrap();
+            if was_shed {
+                assert!(!response.allowed);
+                shed += 1;
+            } else {
+                assert!(response.allowed);
+            }
+        }
+        assert!(shed >= 3);
+        assert_eq!(metrics.shed_requests.load(Ordering::Relaxed), shed);
+    }
+
+    #[tokio::test]
+    async fn test_request_limits() {
+        let (handle, metrics) = spawn_bounded(0, OnFull::Reject);
+        let handle = handle.with_limits(Arc::new(
+            RequestLimits::new(&ValidationConfig {
+                max_key_length: Some(8),
+                max_period: Some(3600),
+                max_quantity: None,
+                key_chars: None,
+            })
+            .unwrap(),
+        ));
+
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("a", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+
+        let err = handle
+            .throttle(ThrottleRequest::new("too-long-key", 2, 10, 60))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ValidationError>()
+                .map(ValidationError::code),
+            Some("key_too_long")
+        );
+        let long_period = ThrottleRequest {
+            period: 86_400,
+            ..ThrottleRequest::new("a", 2, 10, 60)
+        };
+        assert!(handle.throttle(long_period).await.is_err());
+        // Built-in rules are counted too
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("", 2, 10, 60))
+                .await
+                .is_err()
+        );
+
+        let invalid = metrics.invalid_requests();
+        assert_eq!(invalid.get("key_too_long"), Some(&1));
+        assert_eq!(invalid.get("period_too_long"), Some(&1));
+        assert_eq!(invalid.get("empty_key"), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn test_micro_cache() {
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let config = crate::config::StoreConfig {
+            micro_cache_ms: 60_000,
+            ..Default::default()
+        };
+        let handle = crate::store::create_rate_limiter(&config, 100, Arc::clone(&metrics))
+            .await
+            .unwrap();
+        let decide = || async {
+            handle
+                .throttle(ThrottleRequest::new("a", 2, 10, 60))
+                .await
+                .unwrap()
+        };
+
+        // The first decision's remaining token is handed out from the cache
+        assert_eq!(decide().await.remaining, 1);
+        assert_eq!(decide().await.remaining, 0);
+
+        // The store hasn't seen that hit, so it allows one more
+        assert!(decide().await.allowed);
+        assert!(!decide().await.allowed);
+        // Denials are reused as they are
+        assert!(!decide().await.allowed);
+
+        assert_eq!(metrics.micro_cache_hits.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.micro_cache_misses.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn test_max_keys_degrade() {
+        let (handle, metrics) = spawn_bounded(1, OnFull::Degrade);
+
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("a", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+
+        // New keys share an overflow bucket, so the same key maps to the
+        // same bucket and its limit still applies
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("b", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        assert!(
+            handle
+                .throttle(ThrottleRequest::new("b", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        assert!(
+            !handle
+                .throttle(ThrottleRequest::new("b", 2, 10, 60))
+                .await
+                .unwrap()
+                .allowed
+        );
+        assert_eq!(metrics.store_degraded.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn test_handle_clock() {
+        let clock = MockClock::new();
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let handle = RateLimiterActor::spawn_periodic(100, PeriodicStore::new(), metrics)
+            .with_clock(Arc::new(clock.clone()));
+        assert_eq!(handle.now(), throttlecrab::Clock::now(&clock));
+
+        // 1 token per 10 seconds, burst of 2
+        let request = || ThrottleRequest {
+            timestamp: handle.now(),
+            ..ThrottleRequest::new("clock", 2, 6, 60)
+        };
+        assert!(handle.throttle(request()).await.unwrap().allowed);
+        assert!(handle.throttle(request()).await.unwrap().allowed);
+        assert!(!handle.throttle(request()).await.unwrap().allowed);
+
+        clock.advance(std::time::Duration::from_secs(10));
+        assert!(handle.throttle(request()).await.unwrap().allowed);
+    }
+
+    #[tokio::test]
+    async fn test_sharded() {
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let config = crate::config::StoreConfig {
+            shards: 4,
+            ..Default::default()
+        };
+        let handle = crate::store::create_rate_limiter(&config, 100, Arc::clone(&metrics))
+            .await
+            .unwrap();
+        assert_eq!(handle.shards(), 4);
+
+        // Every key keeps its own burst, whichever shard owns it
+        let keys: Vec<String> = (0..50).map(|i| format!("user:{i}")).collect();
+        for key in &keys {
+            handle
+                .throttle(ThrottleRequest::new(key.as_str(), 2, 10, 60))
+                .await
+                .unwrap();
+        }
+        let results = handle
+            .throttle_batch(
+                keys.iter()
+                    .flat_map(|key| {
+                        [
+                            ThrottleRequest::new(key.as_str(), 2, 10, 60),
+                            ThrottleRequest::new(key.as_str(), 2, 10, 60),
+                        ]
+                    })
+                    .collect(),
+            )
+            .await
+            .unwrap();
+        for pair in results.chunks(2) {
+            assert!(pair[0].as_ref().unwrap().allowed);
+            assert!(!pair[1].as_ref().unwrap().allowed);
+        }
+
+        // Gauges and reports cover all shards
+        assert_eq!(metrics.store_keys.load(Ordering::Relaxed), 50);
+        assert_eq!(handle.memory_usage().await.unwrap().store_entries, 50);
+        assert_eq!(handle.live_entries().await.unwrap().len(), 50);
+        let report = handle.cleanup(None).await.unwrap();
+        assert_eq!((report.scanned, report.remaining), (50, 50));
+        assert_eq!(handle.last_cleanup().await.unwrap(), Some(report));
+
+        handle
+            .reset("user:7".into(), crate::metrics::Transport::Http)
+            .await
+            .unwrap();
+        assert_eq!(metrics.store_keys.load(Ordering::Relaxed), 49);
+    }
+
+    #[tokio::test]
+    async fn test_coalescing() {
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let config = crate::config::StoreConfig {
+            coalesce_window_us: 20_000,
+            ..Default::default()
+        };
+        let handle = crate::store::create_rate_limiter(&config, 100, Arc::clone(&metrics))
+            .await
+            .unwrap();
+        let throttle_all = |request: ThrottleRequest, count: usize| {
+            let tasks: Vec<_> = (0..count)
+                .map(|_| {
+                    let (handle, request) = (handle.clone(), request.clone());
+                    tokio::spawn(async move { handle.throttle(request).await.unwrap() })
+                })
+                .collect();
+            async move {
+                let mut responses = Vec::new();
+                for task in tasks {
+                    responses.push(task.await.unwrap());
+                }
+                responses
+            }
+        };
+
+        // Identical requests within the window are allowed by one decision
+        let hot = ThrottleRequest {
+            max_burst: 10,
+            ..ThrottleRequest::new("hot", 2, 10, 60)
+        };
+        let responses = throttle_all(hot, 5).await;
+        assert!(responses.iter().all(|r| r.allowed && r.remaining == 5));
+        assert_eq!(metrics.coalesced_requests.load(Ordering::Relaxed), 5);
+
+        // A group too large to allow at once is decided one at a time
+        let responses = throttle_all(ThrottleRequest::new("tight", 2, 10, 60), 3).await;
+        let allowed = responses.iter().filter(|r| r.allowed).count();
+        assert_eq!(allowed, 2);
+        assert_eq!(metrics.coalesced_requests.load(Ordering::Relaxed), 5);
+    }
+
+    #[tokio::test]
+    async fn test_backpressure() {
+        let overloaded = |backpressure| async move {
+            let metrics = Arc::new(crate::metrics::Metrics::new());
+            let config = crate::config::StoreConfig {
+                backpressure,
+                ..Default::default()
+            };
+            let handle = crate::store::create_rate_limiter(&config, 1, Arc::clone(&metrics))
+                .await
+                .unwrap();
+            // Queued before the actor gets to run, so the queue fills up
+            let tasks: Vec<_> = (0..4)
+                .map(|i| {
+                    let handle = handle.clone();
+                    tokio::spawn(async move {
+                        handle
+                            .throttle(ThrottleRequest::new(format!("k{i}"), 2, 10, 60))
+                            .await
+                    })
+                })
+                .collect();
+            let mut overloaded = 0;
+            for task in tasks {
+                match task.await.unwrap() {
+                    Ok(response) => assert!(response.allowed),
+                    Err(e) => {
+                        assert_eq!(
+                            e.downcast_ref::<OverloadedError>().unwrap().code(),
+                            "overloaded"
+                        );
+                        overloaded += 1;
+                    }
+                }
+            }
+            assert!(metrics.queue_full_events.load(Ordering::Relaxed) > 0);
+            assert_eq!(
+                metrics.overloaded_requests.load(Ordering::Relaxed),
+                overloaded
+            );
+            assert_eq!(metrics.queue_depth.load(Ordering::Relaxed), 0);
+            overloaded
+        };
+
+        assert_eq!(overloaded(Backpressure::Block).await, 0);
+        // Fail-fast refuses the requests that find the queue full
+        let failed = overloaded(Backpressure::FailFast).await;
+        assert!((1..4).contains(&failed));
+        // Shed-oldest gives up queued requests, but the newest is decided
+        let shed = overloaded(Backpressure::ShedOldest).await;
+        assert!((1..4).contains(&shed));
+    }
+
+    #[tokio::test]
+    async fn test_cleanup() {
+        let clock = MockClock::new();
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let handle = RateLimiterActor::spawn_periodic(100, PeriodicStore::new(), metrics)
+            .with_clock(Arc::new(clock.clone()));
+        assert_eq!(handle.last_cleanup().await.unwrap(), None);
+
+        let request = |key: &str, period| ThrottleRequest {
+            timestamp: handle.now(),
+            ..ThrottleRequest::new(key, 5, 10, period)
+        };
+        for i in 0..2000 {
+            handle
+                .throttle(request(&format!("short:{i}"), 1))
+                .await
+                .unwrap();
+        }
+        handle.throttle(request("long", 3600)).await.unwrap();
+
+        clock.advance(std::time::Duration::from_secs(60));
+
+        // An exhausted budget stops after the first batch of entries
+        let report = handle
+            .cleanup(Some(std::time::Duration::ZERO))
+            .await
+            .unwrap();
+        assert!(!report.complete);
+        assert!(report.scanned < 2001);
+        assert_eq!(report.remaining, 2001 - report.removed);
+
+        let report = handle.cleanup(None).await.unwrap();
+        assert_eq!(report.remaining, 1);
+        assert!(report.complete);
+        assert_eq!(handle.last_cleanup().await.unwrap(), Some(report));
+
+        // A budgeted pass over a clean store finishes well within budget
+        let report = handle
+            .cleanup(Some(std::time::Duration::from_secs(1)))
+            .await
+            .unwrap();
+        assert_eq!((report.scanned, report.removed), (1, 0));
+        assert!(report.complete);
+    }
+
+    #[tokio::test]
+    async fn test_store_keys_peak() {
+        let clock = MockClock::new();
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let handle = RateLimiterActor::spawn_periodic(100, PeriodicStore::new(), metrics.clone())
+            .with_clock(Arc::new(clock.clone()));
+
+        for i in 0..3 {
+            let request = ThrottleRequest {
+                timestamp: handle.now(),
+                ..ThrottleRequest::new(format!("peak:{i}"), 5, 10, 60)
+            };
+            handle.throttle(request).await.unwrap();
+        }
+
+        let peak = metrics.peaks().store_keys;
+        assert_eq!(peak.value, 3);
+        assert_eq!(
+            peak.at_ms as u128,
+            handle
+                .now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_canary_report() {
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let canary = crate::canary::Canary::new(
+            StoreType::Adaptive(RateLimiter::new(throttlecrab::AdaptiveStore::new())),
+            1.0,
+        );
+        let handle = RateLimiterActor::spawn(
+            100,
+            StoreType::Periodic(RateLimiter::new(PeriodicStore::new())),
+            KeyAdmission::unbounded(),
+            None,
+            Some(canary),
+            None,
+            Arc::clone(&metrics),
+        );
+
+        for _ in 0..5 {
+            handle
+                .throttle(ThrottleRequest::new("canary", 2, 10, 60))
+                .await
+                .unwrap();
+        }
+
+        // Both stores implement the same algorithm, so they agree
+        let report = handle.canary_report().await.unwrap().unwrap();
+        assert_eq!(report.compared, 5);
+        assert_eq!(report.agreed, 5);
+        assert!(report.divergences.is_empty());
+
+        let (plain, _) = spawn_bounded(0, OnFull::Reject);
+        assert_eq!(plain.canary_report().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_ttl_capped_metric() {
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let store = PeriodicStore::builder()
+            .max_ttl(std::time::Duration::from_secs(3600))
+            .build();
+        let handle = RateLimiterActor::spawn_periodic(100, store, Arc::clone(&metrics));
+
+        handle
+            .throttle(ThrottleRequest::new("short", 2, 10, 60))
+            .await
+            .unwrap();
+        assert_eq!(metrics.store_ttl_capped.load(Ordering::Relaxed), 0);
+
+        // A ten year period asks for an entry that outlives the cap
+        let mut long = ThrottleRequest::new("long", 2, 10, 60);
+        long.period = 10 * 365 * 24 * 60 * 60;
+        handle.throttle(long).await.unwrap();
+        assert_eq!(metrics.store_ttl_capped.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_memory_usage() {
+        let (handle, _) = spawn_bounded(0, OnFull::Reject);
+        handle
+            .throttle(ThrottleRequest::new("memory:1", 2, 10, 60))
+            .await
+            .unwrap();
+
+        let report = handle.memory_usage().await.unwrap();
+        assert_eq!(report.store_entries, 1);
+        assert!(report.store_bytes > 0);
+        assert_eq!(report.canary_store_bytes, 0);
+        assert_eq!(report.trace_bytes, 0);
+        // The top denied keys queue is allocated up front
+        assert!(report.metrics_bytes > 0);
+        assert_eq!(
+            report.total_bytes,
+            report.store_bytes
+                + report.metrics_bytes
+                + report.connection_bytes
+                + report.queue_bytes
+        );
+
+        // The store figures are cached between refreshes
+        handle
+            .throttle(ThrottleRequest::new("memory:2", 2, 10, 60))
+            .await
+            .unwrap();
+        let cached = handle.memory_usage().await.unwrap();
+        assert_eq!(cached.store_entries, 1);
+        assert_eq!(cached.computed_at_ms, report.computed_at_ms);
+    }
+
+    #[tokio::test]
+    async fn test_key_introspection() {
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let config = crate::config::StoreConfig {
+            hot_keys: 2,
+            shards: 2,
+            ..Default::default()
+        };
+        let handle = crate::store::create_rate_limiter(&config, 100, metrics)
+            .await
+            .unwrap();
+        for key in ["a", "a", "a", "b", "b", "c"] {
+            handle
+                .throttle(ThrottleRequest::new(key, 2, 10, 60))
+                .await
+                .unwrap();
+        }
+
+        // Each actor's top keys are merged, most requested first
+        let hot: Vec<_> = handle
+            .hot_keys(2)
+            .await
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .map(|k| (k.key, k.requests))
+            .collect();
+        assert_eq!(hot, [("a".to_string(), 3), ("b".to_string(), 2)]);
+
+        let state = handle.key_state("a".into()).await.unwrap().unwrap();
+        assert_eq!(state.key, "a");
+        assert!(state.tat_ns > 0);
+        assert!(state.expires_at_ms.is_some());
+        assert!(!state.expired);
+        assert!(handle.key_state("missing".into()).await.unwrap().is_none());
+
+        let stats = handle.store_stats().await.unwrap();
+        assert_eq!(stats.entries, 3);
+        assert_eq!(stats.expired_entries, 0);
+        assert_eq!(stats.shard_entries.len(), 2);
+        assert_eq!(stats.shard_entries.iter().sum::<usize>(), 3);
+        assert!(stats.bytes > 0);
+
+        // Hot keys are only counted when asked for
+        let (handle, _) = spawn_bounded(0, OnFull::Reject);
+        assert!(handle.hot_keys(10).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reload_policies() {
+        let (handle, metrics) = spawn_bounded(0, OnFull::Reject);
+        assert!(handle.reload_policies().is_err());
+
+        let path = std::env::temp_dir().join(format!(
+            "throttlecrab-actor-policies-{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            "[login]\nmax_burst = 5\ncount_per_period = 10\nperiod = 60\n",
+        )
+        .unwrap();
+        let policies = Arc::new(Policies::load(&path).unwrap());
+        let handle = handle.with_policies(Arc::clone(&policies));
+        let clone = handle.clone();
+
+        std::fs::write(
+            &path,
+            "[login]\nmax_burst = 1\ncount_per_period = 10\nperiod = 60\n",
+        )
+        .unwrap();
+        assert_eq!(handle.reload_policies().unwrap().policies, 1);
+        // Every handle sees the reloaded policies
+        assert_eq!(clone.policy("login").unwrap().max_burst, 1);
+        assert_eq!(metrics.policy_reloads.load(Ordering::Relaxed), 1);
+
+        std::fs::write(&path, "not toml").unwrap();
+        assert!(handle.reload_policies().is_err());
+        assert_eq!(clone.policy("login").unwrap().max_burst, 1);
+        assert_eq!(metrics.policy_reload_failures.load(Ordering::Relaxed), 1);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_operation() {
+        let (handle, _) = spawn_bounded(100, OnFull::Reject);
+        let policies = Policies::parse(
+            "[api]\nmax_burst = 100\ncount_per_period = 1000\nperiod = 60\n\
+             costs = { read = 1, write = 5, export = 50 }\n",
+        )
+        .unwrap();
+        let handle = handle.with_policies(Arc::new(policies));
+
+        // The operation's cost replaces the quantity sent
+        let mut export = ThrottleRequest::new("a", 2, 10, 60);
+        handle
+            .resolve(&mut export, Some("api"), Some("export"))
+            .unwrap();
+        assert_eq!(export.max_burst, 100);
+        assert_eq!(export.quantity, 50);
+        assert_eq!(handle.throttle(export).await.unwrap().remaining, 50);
+
+        let error = handle
+            .resolve(
+                &mut ThrottleRequest::new("a", 2, 10, 60),
+                Some("api"),
+                Some("delete"),
+            )
+            .unwrap_err();
+        let unknown = error.downcast_ref::<UnknownOperationError>().unwrap();
+        assert_eq!(unknown.code(), "unknown_operation");
+        assert_eq!(unknown.policy.as_deref(), Some("api"));
+
+        // Only policies price operations
+        let error = handle
+            .resolve(
+                &mut ThrottleRequest::new("a", 2, 10, 60),
+                None,
+                Some("read"),
+            )
+            .unwrap_err();
+        assert!(error.downcast_ref::<UnknownOperationError>().is_some());
+        let mut plain = ThrottleRequest::new("a", 2, 10, 60);
+        handle.resolve(&mut plain, None, None).unwrap();
+        assert_eq!(plain.quantity, 1);
+    }
+
+    #[tokio::test]
+    async fn test_policy_warm_up() {
+        let (handle, _) = spawn_bounded(100, OnFull::Reject);
+        let policies = Policies::parse(
+            "[api]\nmax_burst = 100\ncount_per_period = 1000\nperiod = 60\n\
+             warm_up = 300\nwarm_up_percent = 10\n",
+        )
+        .unwrap();
+        let handle = handle.with_policies(Arc::new(policies));
+
+        // A new key starts with 10% of the burst
+        let mut new_key = ThrottleRequest::new("a", 2, 10, 60);
+        handle.resolve(&mut new_key, Some("api"), None).unwrap();
+        assert!(new_key.warm_up.is_some());
+        let response = handle.throttle(new_key).await.unwrap();
+        assert!(response.allowed);
+        assert_eq!(response.limit, 10);
+        assert_eq!(response.remaining, 9);
+
+        // Requests without the policy get their full limits
+        let response = handle
+            .throttle(ThrottleRequest::new("b", 2, 10, 60))
+            .await
+            .unwrap();
+        assert_eq!(response.limit, 2);
+    }
 }
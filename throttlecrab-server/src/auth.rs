@@ -0,0 +1,255 @@
+//! API key authentication shared by the transports
+//!
+//! With API keys configured (`--api-key NAME=SECRET` or `--api-keys-file
+//! FILE`), every throttle request must carry one of the secrets:
+//!
+//! - HTTP: an `Authorization: Bearer SECRET` header, else `401`; the `/admin`
+//!   endpoints require it as well
+//! - gRPC: `authorization: Bearer SECRET` metadata, else `UNAUTHENTICATED`
+//! - Redis: `AUTH SECRET` (or `AUTH NAME SECRET`) once per connection, else
+//!   `NOAUTH`
+//!
+//! The key file is TOML mapping names to secrets:
+//!
+//! ```toml
+//! checkout = "3f9a..."
+//! search = "c1d7..."
+//! ```
+//!
+//! Names identify clients in the `throttlecrab_api_key_requests` metric and
+//! are never accepted in place of a secret. With `--api-key-namespaces` they
+//! also name each client's default [namespace](crate::namespace). Secrets are compared in constant
+//! time, against every key, so the time taken reveals neither which key
+//! nearly matched nor how much of it.
+
+use crate::config::ApiKeysConfig;
+use anyhow::{Context, Result, anyhow};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Configured API keys
+pub struct ApiKeys {
+    keys: Vec<ApiKey>,
+    /// Whether key names are their clients' default namespaces
+    namespaces: bool,
+}
+
+struct ApiKey {
+    name: Arc<str>,
+    secret: Box<str>,
+}
+
+impl ApiKeys {
+    /// Keys from `(name, secret)` pairs
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a name or secret is empty or a name is repeated.
+    pub fn new(keys: impl IntoIterator<Item = (String, String)>) -> Result<Self> {
+        let mut api_keys = ApiKeys {
+            keys: Vec::new(),
+            namespaces: false,
+        };
+        for (name, secret) in keys {
+            api_keys.add(name, secret)?;
+        }
+        Ok(api_keys)
+    }
+
+    /// Parse a TOML key file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input is not a table of strings or a key is
+    /// invalid as for [`new`](ApiKeys::new).
+    pub fn parse(input: &str) -> Result<Self> {
+        let keys: BTreeMap<String, String> = toml::from_str(input)?;
+        Self::new(keys)
+    }
+
+    /// The keys in `config`: those given directly, then those in its file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed, or a name is
+    /// defined twice.
+    pub fn from_config(config: &ApiKeysConfig) -> Result<Self> {
+        let mut api_keys = Self::new(config.keys.iter().cloned())?;
+        if let Some(path) = &config.file {
+            for key in Self::load(path)?.keys {
+                api_keys.add(key.name.to_string(), key.secret.into())?;
+            }
+        }
+        api_keys.with_namespaces(config.namespaces)
+    }
+
+    /// Make each key's name the default namespace of its clients' requests
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `namespaces` is set and a key's name is not a
+    /// valid namespace.
+    pub fn with_namespaces(mut self, namespaces: bool) -> Result<Self> {
+        if namespaces {
+            for key in &self.keys {
+                crate::namespace::validate(&key.name)
+                    .map_err(|e| anyhow!("API key {} cannot name a namespace: {e}", key.name))?;
+            }
+        }
+        self.namespaces = namespaces;
+        Ok(self)
+    }
+
+    /// Whether key names are their clients' default namespaces
+    pub fn namespaces(&self) -> bool {
+        self.namespaces
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        let input = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read API keys from {}", path.display()))?;
+        Self::parse(&input).with_context(|| format!("Invalid API key file {}", path.display()))
+    }
+
+    fn add(&mut self, name: String, secret: String) -> Result<()> {
+        if name.is_empty() || secret.is_empty() {
+            return Err(anyhow!("API key names and secrets must not be empty"));
+        }
+        if self.keys.iter().any(|key| *key.name == *name) {
+            return Err(anyhow!("API key {name} is defined twice"));
+        }
+        self.keys.push(ApiKey {
+            name: name.into(),
+            secret: secret.into(),
+        });
+        Ok(())
+    }
+
+    /// Name of the key whose secret is `secret`, if any
+    pub fn authenticate(&self, secret: &str) -> Option<&Arc<str>> {
+        // No early exit: every key is compared whatever matches
+        self.keys.iter().fold(None, |found, key| {
+            let matches = constant_time_eq(secret.as_bytes(), key.secret.as_bytes());
+            if matches && found.is_none() {
+                Some(&key.name)
+            } else {
+                found
+            }
+        })
+    }
+
+    /// The name of the key called `name`, if it is configured
+    pub fn name(&self, name: &str) -> Option<&Arc<str>> {
+        self.keys
+            .iter()
+            .map(|key| &key.name)
+            .find(|key| ***key == *name)
+    }
+
+    /// Secret of the first key, for the server's own probes
+    pub(crate) fn first_secret(&self) -> Option<&str> {
+        self.keys.first().map(|key| &*key.secret)
+    }
+
+    /// Number of keys
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// True if no key is configured
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+impl fmt::Debug for ApiKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.keys.iter().map(|key| &key.name))
+            .finish()
+    }
+}
+
+/// The token of an `Authorization: Bearer TOKEN` header value
+pub fn bearer_token(authorization: &str) -> Option<&str> {
+    let (scheme, token) = authorization.split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| token.trim())
+        .filter(|token| !token.is_empty())
+}
+
+/// A request carried no API key, or one that is not configured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnauthorizedError;
+
+impl UnauthorizedError {
+    /// `unauthorized`, whether the key was missing or unknown
+    pub fn code(&self) -> &'static str {
+        "unauthorized"
+    }
+}
+
+impl fmt::Display for UnauthorizedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("missing or invalid API key")
+    }
+}
+
+impl std::error::Error for UnauthorizedError {}
+
+/// Compare two byte strings without exiting early on the first difference
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authenticate() {
+        let keys = ApiKeys::parse("checkout = \"s3cret\"\nsearch = \"other\"\n").unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(
+            keys.authenticate("s3cret").map(|name| &**name),
+            Some("checkout")
+        );
+        assert_eq!(
+            keys.authenticate("other").map(|name| &**name),
+            Some("search")
+        );
+        assert_eq!(keys.authenticate("s3cre"), None);
+        // Names are not secrets
+        assert_eq!(keys.authenticate("checkout"), None);
+        assert!(!format!("{keys:?}").contains("s3cret"));
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        assert!(ApiKeys::new([("a".to_string(), String::new())]).is_err());
+        assert!(ApiKeys::new([(String::new(), "x".to_string())]).is_err());
+        assert!(
+            ApiKeys::new([
+                ("a".to_string(), "x".to_string()),
+                ("a".to_string(), "y".to_string()),
+            ])
+            .is_err()
+        );
+        assert!(ApiKeys::parse("checkout = 5\n").is_err());
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        assert_eq!(bearer_token("Bearer abc"), Some("abc"));
+        assert_eq!(bearer_token("bearer  abc "), Some("abc"));
+        assert_eq!(bearer_token("Basic abc"), None);
+        assert_eq!(bearer_token("Bearer "), None);
+        assert_eq!(bearer_token("abc"), None);
+    }
+}
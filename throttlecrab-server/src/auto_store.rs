@@ -0,0 +1,237 @@
+//! Latency-aware store selection for `--store auto`
+//!
+//! The auto store starts as an adaptive store. Stores clean up inline, on
+//! the request that triggers the cleanup, so cleanup pauses show up as slow
+//! requests. The actor times every request and hands the timings to
+//! [`AutoStore`], which evaluates them once per observation window:
+//!
+//! - Slowest request within the latency budget: keep the current store
+//! - Over budget on the adaptive store for several windows in a row: migrate
+//!   every entry to a periodic store that cleans up every `max_interval`
+//!   seconds. Under heavy load the adaptive store cleans up as often as every
+//!   `min_interval` seconds, so this makes the pauses as rare as the adaptive
+//!   store would make them at its slowest.
+//! - Over budget on the periodic store: there is no better configuration to
+//!   move to, so the store is kept and this is logged once
+//!
+//! Every decision is logged at `info` with the statistics behind it, and
+//! migrations are counted in `throttlecrab_store_migrations`.
+
+use crate::actor::StoreType as ActorStore;
+use crate::config::{StoreConfig, StoreType};
+use crate::metrics::Metrics;
+use crate::store::build_store;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+/// Length of one observation window
+const WINDOW: Duration = Duration::from_secs(10);
+
+/// Consecutive over-budget windows before acting, so a single stall (e.g.
+/// the process being descheduled) does not trigger a migration
+const OVER_BUDGET_WINDOWS: u32 = 3;
+
+/// Which configuration the auto store is running
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    /// Adaptive store, as configured
+    Adaptive,
+    /// Periodic store cleaning up every `max_interval` seconds
+    Periodic,
+    /// Periodic store that is still over budget; nothing left to try
+    Settled,
+}
+
+/// Request timings for the current window
+#[derive(Debug)]
+struct Window {
+    start: Instant,
+    requests: u64,
+    over_budget: u64,
+    slowest: Duration,
+}
+
+impl Window {
+    fn new(start: Instant) -> Self {
+        Window {
+            start,
+            requests: 0,
+            over_budget: 0,
+            slowest: Duration::ZERO,
+        }
+    }
+}
+
+/// Watches request latency and migrates the store when it exceeds the budget
+#[derive(Debug)]
+pub(crate) struct AutoStore {
+    config: StoreConfig,
+    budget: Duration,
+    stage: Stage,
+    window: Window,
+    strikes: u32,
+}
+
+impl AutoStore {
+    /// Create a selector for an adaptive store built from `config`
+    pub(crate) fn new(config: &StoreConfig, now: Instant) -> Self {
+        AutoStore {
+            config: config.clone(),
+            budget: Duration::from_micros(config.latency_budget_us),
+            stage: Stage::Adaptive,
+            window: Window::new(now),
+            strikes: 0,
+        }
+    }
+
+    /// Record a request that took `elapsed`, migrating `store` if it is due
+    pub(crate) fn observe(
+        &mut self,
+        elapsed: Duration,
+        now: Instant,
+        store: &mut ActorStore,
+        metrics: &Metrics,
+    ) {
+        self.window.requests += 1;
+        if elapsed > self.budget {
+            self.window.over_budget += 1;
+        }
+        self.window.slowest = self.window.slowest.max(elapsed);
+
+        let window_len = now.duration_since(self.window.start);
+        if window_len < WINDOW {
+            return;
+        }
+
+        let window = std::mem::replace(&mut self.window, Window::new(now));
+        if window.slowest <= self.budget {
+            self.strikes = 0;
+            return;
+        }
+        self.strikes += 1;
+
+        let requests_per_sec = window.requests as f64 / window_len.as_secs_f64();
+        tracing::info!(
+            stage = ?self.stage,
+            slowest_us = window.slowest.as_micros() as u64,
+            budget_us = self.budget.as_micros() as u64,
+            over_budget = window.over_budget,
+            requests = window.requests,
+            requests_per_sec = requests_per_sec.round(),
+            strikes = self.strikes,
+            "Auto store: slowest request over the latency budget"
+        );
+        if self.strikes < OVER_BUDGET_WINDOWS {
+            return;
+        }
+        self.strikes = 0;
+
+        match self.stage {
+            Stage::Adaptive => {
+                let started = Instant::now();
+                let keys = self.migrate(store);
+                self.stage = Stage::Periodic;
+                metrics.store_migrations.fetch_add(1, Ordering::Relaxed);
+                tracing::info!(
+                    keys,
+                    cleanup_interval = self.config.max_interval,
+                    migration_us = started.elapsed().as_micros() as u64,
+                    "Auto store: migrated from adaptive to periodic store"
+                );
+            }
+            Stage::Periodic => {
+                self.stage = Stage::Settled;
+                tracing::info!(
+                    "Auto store: periodic store is still over the latency budget; \
+                     keeping it, consider raising --store-latency-budget-us"
+                );
+            }
+            Stage::Settled => {}
+        }
+    }
+
+    /// Replace `store` with a periodic store holding the same entries
+    fn migrate(&self, store: &mut ActorStore) -> usize {
+        let config = StoreConfig {
+            cleanup_interval: self.config.max_interval,
+            ..self.config.clone()
+        };
+        let mut target = build_store(StoreType::Periodic, &config);
+        let entries = store.entries();
+        let keys = entries.len();
+        for (key, value, expiry) in entries {
+            target.insert(&key, value, expiry);
+        }
+        *store = target;
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn over_budget_windows(
+        auto: &mut AutoStore,
+        store: &mut ActorStore,
+        metrics: &Metrics,
+        start: Instant,
+        windows: u32,
+    ) -> Instant {
+        let mut now = start;
+        for _ in 0..windows {
+            auto.observe(Duration::from_millis(50), now, store, metrics);
+            now += WINDOW;
+            auto.observe(Duration::from_micros(10), now, store, metrics);
+        }
+        now
+    }
+
+    #[test]
+    fn test_auto_store_migrates_after_sustained_pauses() {
+        let config = StoreConfig {
+            store_type: StoreType::Auto,
+            latency_budget_us: 1_000,
+            ..Default::default()
+        };
+        let metrics = Metrics::new();
+        let start = Instant::now();
+        let mut store = build_store(StoreType::Auto, &config);
+        let mut auto = AutoStore::new(&config, start);
+
+        let now = SystemTime::now();
+        store
+            .rate_limit("kept", 5, 10, 60, 1, now)
+            .expect("rate limit");
+
+        // Requests within budget never migrate
+        let mut t = start;
+        for _ in 0..10 {
+            t += WINDOW;
+            auto.observe(Duration::from_micros(100), t, &mut store, &metrics);
+        }
+        assert!(matches!(store, ActorStore::Adaptive(_)));
+
+        // One slow window is not enough
+        let t = over_budget_windows(&mut auto, &mut store, &metrics, t, 1);
+        assert!(matches!(store, ActorStore::Adaptive(_)));
+        auto.observe(Duration::from_micros(100), t + WINDOW, &mut store, &metrics);
+
+        // Sustained pauses migrate, keeping the stored state
+        over_budget_windows(
+            &mut auto,
+            &mut store,
+            &metrics,
+            t + WINDOW,
+            OVER_BUDGET_WINDOWS,
+        );
+        assert!(matches!(store, ActorStore::Periodic(_)));
+        assert_eq!(metrics.store_migrations.load(Ordering::Relaxed), 1);
+        assert_eq!(store.len(), 1);
+        let (_, result) = store
+            .rate_limit("kept", 5, 10, 60, 1, now)
+            .expect("rate limit");
+        assert_eq!(result.remaining, 3);
+    }
+}
@@ -0,0 +1,240 @@
+//! Per-API-key request budgets
+//!
+//! With `--api-key-budget NAME=COUNT/SECS` the server limits itself: the
+//! client authenticating with the API key `NAME` may make at most `COUNT`
+//! throttle checks every `SECS` seconds, whatever keys it checks. `*` names
+//! the budget of every key without one of its own:
+//!
+//! ```text
+//! --api-key-budget checkout=50000/60 --api-key-budget '*=1000/60'
+//! ```
+//!
+//! Budgets are enforced with GCRA, like any other limit, so a client may
+//! spend its whole budget in a burst and then earns it back evenly. Checks
+//! beyond the budget are refused before they reach the store:
+//!
+//! - HTTP: status `429` with error code `budget_exhausted`
+//! - gRPC: `RESOURCE_EXHAUSTED`, message prefixed with `budget_exhausted`,
+//!   or a `budget_exhausted` error in a stream's result
+//! - Redis: an `ERR budget_exhausted: ...` reply
+//!
+//! A batch is charged one check per request and refused as a whole.
+//! Peeks, resets and leases are not charged. Refusals are counted in
+//! `throttlecrab_api_key_budget_exhausted`.
+
+use crate::auth::ApiKeys;
+use crate::config::ApiKeyBudget;
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use throttlecrab::{PeriodicStore, RateLimiter};
+
+/// Budget name applying to every key without its own
+pub const DEFAULT_BUDGET: &str = "*";
+
+/// Throttle check budgets by API key name
+pub struct ApiKeyBudgets {
+    budgets: HashMap<Arc<str>, ApiKeyBudget>,
+    default: Option<ApiKeyBudget>,
+    limiter: Mutex<RateLimiter<PeriodicStore>>,
+}
+
+impl ApiKeyBudgets {
+    /// Budgets for the keys in `api_keys`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a budget is empty, names a key that is not
+    /// configured, or is given twice.
+    pub fn new(budgets: &[ApiKeyBudget], api_keys: &ApiKeys) -> Result<Self> {
+        let mut by_name = HashMap::new();
+        let mut default = None;
+        for budget in budgets {
+            if budget.count == 0 || budget.period == 0 {
+                return Err(anyhow!(
+                    "API key budget for {} needs a positive count and period",
+                    budget.name
+                ));
+            }
+            let previous = if budget.name == DEFAULT_BUDGET {
+                default.replace(budget.clone())
+            } else {
+                let name = api_keys
+                    .name(&budget.name)
+                    .ok_or_else(|| anyhow!("API key budget names unknown key {}", budget.name))?;
+                by_name.insert(Arc::clone(name), budget.clone())
+            };
+            if previous.is_some() {
+                return Err(anyhow!("API key budget for {} is given twice", budget.name));
+            }
+        }
+        Ok(ApiKeyBudgets {
+            budgets: by_name,
+            default,
+            limiter: Mutex::new(RateLimiter::new(PeriodicStore::with_capacity(
+                api_keys.len(),
+            ))),
+        })
+    }
+
+    /// The budget of the key called `name`, if it has one
+    pub fn budget(&self, name: &str) -> Option<&ApiKeyBudget> {
+        self.budgets.get(name).or(self.default.as_ref())
+    }
+
+    /// Charge `checks` throttle checks made at `now` to the key `name`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BudgetExhaustedError`] if the key's budget cannot cover
+    /// them; nothing is charged then.
+    pub fn charge(
+        &self,
+        name: &str,
+        checks: u64,
+        now: SystemTime,
+    ) -> Result<(), BudgetExhaustedError> {
+        let Some(budget) = self.budget(name) else {
+            return Ok(());
+        };
+        let count = i64::try_from(budget.count).unwrap_or(i64::MAX);
+        let period = i64::try_from(budget.period).unwrap_or(i64::MAX);
+        let quantity = i64::try_from(checks).unwrap_or(i64::MAX);
+        let result = self
+            .limiter
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .rate_limit(name, count, count, period, quantity, now);
+        match result {
+            Ok((true, _)) => Ok(()),
+            Ok((false, result)) => Err(BudgetExhaustedError {
+                name: name.to_string(),
+                count: budget.count,
+                period: budget.period,
+                retry_after: result.retry_after,
+            }),
+            // Parameters are checked at startup; fail open rather than
+            // refuse a client for the server's own error
+            Err(e) => {
+                tracing::warn!("Failed to charge API key {name}'s budget: {e}");
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ApiKeyBudgets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiKeyBudgets")
+            .field("budgets", &self.budgets)
+            .field("default", &self.default)
+            .finish()
+    }
+}
+
+/// A client's API key has spent its budget of throttle checks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BudgetExhaustedError {
+    /// Name of the API key
+    pub name: String,
+    /// Checks the key may make per period
+    pub count: u64,
+    /// Length of the period in seconds
+    pub period: u64,
+    /// Time until the checks would fit in the budget
+    pub retry_after: Duration,
+}
+
+impl BudgetExhaustedError {
+    /// `budget_exhausted`, distinct from a key being rate limited
+    pub fn code(&self) -> &'static str {
+        "budget_exhausted"
+    }
+}
+
+impl fmt::Display for BudgetExhaustedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "API key {} has used its budget of {} checks per {}s, retry after {}s",
+            self.name,
+            self.count,
+            self.period,
+            self.retry_after.as_secs_f64().ceil()
+        )
+    }
+}
+
+impl std::error::Error for BudgetExhaustedError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(name: &str, count: u64, period: u64) -> ApiKeyBudget {
+        ApiKeyBudget {
+            name: name.to_string(),
+            count,
+            period,
+        }
+    }
+
+    fn keys() -> ApiKeys {
+        ApiKeys::parse("checkout = \"k1\"\nsearch = \"k2\"\n").unwrap()
+    }
+
+    #[test]
+    fn test_charge() {
+        let budgets = ApiKeyBudgets::new(&[budget("checkout", 3, 60)], &keys()).unwrap();
+        let now = SystemTime::now();
+        assert!(budgets.charge("checkout", 2, now).is_ok());
+        let error = budgets.charge("checkout", 2, now).unwrap_err();
+        assert_eq!(error.code(), "budget_exhausted");
+        assert_eq!(error.count, 3);
+        assert!(error.retry_after > Duration::ZERO);
+        // The refused checks were not charged
+        assert!(budgets.charge("checkout", 1, now).is_ok());
+        assert!(budgets.charge("checkout", 1, now).is_err());
+        // One check's worth comes back every 20 seconds
+        assert!(
+            budgets
+                .charge("checkout", 1, now + Duration::from_secs(20))
+                .is_ok()
+        );
+
+        // Keys without a budget are not limited
+        assert!(budgets.charge("search", 1000, now).is_ok());
+    }
+
+    #[test]
+    fn test_default_budget() {
+        let budgets = ApiKeyBudgets::new(
+            &[budget("checkout", 10, 60), budget(DEFAULT_BUDGET, 2, 60)],
+            &keys(),
+        )
+        .unwrap();
+        assert_eq!(budgets.budget("checkout").map(|b| b.count), Some(10));
+        assert_eq!(budgets.budget("search").map(|b| b.count), Some(2));
+
+        let now = SystemTime::now();
+        assert!(budgets.charge("search", 2, now).is_ok());
+        assert!(budgets.charge("search", 1, now).is_err());
+        assert!(budgets.charge("checkout", 5, now).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_budgets() {
+        assert!(ApiKeyBudgets::new(&[budget("unknown", 1, 60)], &keys()).is_err());
+        assert!(ApiKeyBudgets::new(&[budget("checkout", 0, 60)], &keys()).is_err());
+        assert!(ApiKeyBudgets::new(&[budget("checkout", 1, 0)], &keys()).is_err());
+        assert!(
+            ApiKeyBudgets::new(
+                &[budget("checkout", 1, 60), budget("checkout", 2, 60)],
+                &keys()
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! Canary store for comparing store implementations on live traffic
+//!
+//! A configurable fraction of keys is mirrored to a second store. Every
+//! decision for a mirrored key is made by both stores and compared; the
+//! primary store's result is always the one returned. Keys are selected by
+//! hash, so a mirrored key sees its full request history in both stores.
+//!
+//! The canary store is not written to the write-ahead log and starts empty
+//! after a restart, so expect divergences for keys restored from the log.
+
+use crate::actor::StoreType;
+use crate::metrics::Metrics;
+use crate::types::{CanaryDivergence, CanaryReport, ThrottleRequest};
+use std::collections::VecDeque;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::Ordering;
+use std::time::UNIX_EPOCH;
+use throttlecrab::RateLimitResult;
+
+/// Resolution of the mirrored fraction
+const FRACTION_SCALE: u64 = 1_000_000;
+
+/// Number of recent divergences kept for the report
+pub(crate) const MAX_DIVERGENCES: usize = 20;
+
+/// Secondary store mirroring a fraction of keys
+pub(crate) struct Canary {
+    store: StoreType,
+    threshold: u64,
+    compared: u64,
+    agreed: u64,
+    divergences: VecDeque<CanaryDivergence>,
+}
+
+impl Canary {
+    /// Mirror `fraction` of keys (0.0 to 1.0) to `store`
+    pub(crate) fn new(store: StoreType, fraction: f64) -> Self {
+        Canary {
+            store,
+            threshold: (fraction.clamp(0.0, 1.0) * FRACTION_SCALE as f64) as u64,
+            compared: 0,
+            agreed: 0,
+            divergences: VecDeque::with_capacity(MAX_DIVERGENCES),
+        }
+    }
+
+    /// Whether decisions for `key` are mirrored
+    pub(crate) fn selects(&self, key: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish() % FRACTION_SCALE < self.threshold
+    }
+
+    /// Apply `request` to the canary store and compare with the primary result
+    pub(crate) fn compare(
+        &mut self,
+        key: &str,
+        request: &ThrottleRequest,
+        primary: (bool, &RateLimitResult),
+        metrics: &Metrics,
+    ) {
+        // Parameters were already validated by the primary store
+        let Ok((canary_allowed, canary)) = self.store.decide(key, request, false) else {
+            return;
+        };
+
+        self.compared += 1;
+        metrics.canary_compared.fetch_add(1, Ordering::Relaxed);
+
+        let (primary_allowed, primary) = primary;
+        if primary_allowed == canary_allowed && primary.remaining == canary.remaining {
+            self.agreed += 1;
+            return;
+        }
+
+        metrics.canary_diverged.fetch_add(1, Ordering::Relaxed);
+        if self.divergences.len() == MAX_DIVERGENCES {
+            self.divergences.pop_front();
+        }
+        self.divergences.push_back(CanaryDivergence {
+            key: key.into(),
+            primary_allowed,
+            canary_allowed,
+            primary_remaining: primary.remaining,
+            canary_remaining: canary.remaining,
+            at_ms: request
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as i64)
+                .unwrap_or(0),
+        });
+    }
+
+    /// Forget `key` and its windows in the canary store, as the primary
+    /// store just did
+    pub(crate) fn reset(&mut self, key: &str) {
+        self.store.reset(key);
+    }
+
+    /// Estimated heap bytes held by the canary store and divergence log
+    pub(crate) fn memory_usage(&self) -> usize {
+        let divergences = self.divergences.capacity() * size_of::<CanaryDivergence>()
+            + self
+                .divergences
+                .iter()
+                .map(|divergence| divergence.key.capacity())
+                .sum::<usize>();
+        self.store.memory_usage() + divergences
+    }
+
+    /// Summary of all comparisons so far
+    pub(crate) fn report(&self) -> CanaryReport {
+        CanaryReport {
+            compared: self.compared,
+            agreed: self.agreed,
+            agreement_rate: if self.compared == 0 {
+                1.0
+            } else {
+                self.agreed as f64 / self.compared as f64
+            },
+            divergences: self.divergences.iter().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use throttlecrab::{PeriodicStore, RateLimiter};
+
+    #[test]
+    fn test_fraction_selects_keys() {
+        let store = || StoreType::Periodic(RateLimiter::new(PeriodicStore::new()));
+
+        let none = Canary::new(store(), 0.0);
+        let all = Canary::new(store(), 1.0);
+        let some = Canary::new(store(), 0.25);
+
+        let keys: Vec<String> = (0..10_000).map(|i| format!("key:{i}")).collect();
+        assert!(keys.iter().all(|key| !none.selects(key)));
+        assert!(keys.iter().all(|key| all.selects(key)));
+
+        let selected = keys.iter().filter(|key| some.selects(key)).count();
+        assert!((2_000..3_000).contains(&selected), "selected {selected}");
+    }
+
+    #[test]
+    fn test_compare_reports_divergence() {
+        let metrics = Metrics::new();
+        let mut canary = Canary::new(
+            StoreType::Periodic(RateLimiter::new(PeriodicStore::new())),
+            1.0,
+        );
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+
+        // Primary and canary agree on a fresh key
+        let req = ThrottleRequest {
+            timestamp: now,
+            ..ThrottleRequest::new("a", 2, 6, 60)
+        };
+        let mut primary = RateLimiter::new(PeriodicStore::new());
+        let (allowed, result) = primary.rate_limit("a", 2, 6, 60, 1, now).unwrap();
+        canary.compare("a", &req, (allowed, &result), &metrics);
+
+        // Pretend the primary denied a request the canary allows
+        let denied = RateLimitResult {
+            remaining: 0,
+            ..result
+        };
+        let req = ThrottleRequest {
+            key: "b".into(),
+            ..req
+        };
+        canary.compare("b", &req, (false, &denied), &metrics);
+
+        let report = canary.report();
+        assert_eq!(report.compared, 2);
+        assert_eq!(report.agreed, 1);
+        assert_eq!(report.agreement_rate, 0.5);
+        assert_eq!(report.divergences.len(), 1);
+        assert_eq!(report.divergences[0].key, "b");
+        assert!(!report.divergences[0].primary_allowed);
+        assert!(report.divergences[0].canary_allowed);
+        assert_eq!(report.divergences[0].at_ms, 1_000_000);
+        assert_eq!(metrics.canary_diverged.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,626 @@
+//! Cluster mode: replicating rate limit state between servers
+//!
+//! With `--cluster-port`, servers push the rate limit state they change to
+//! each other, so clients can be spread over several instances and keep
+//! being limited when one of them goes down.
+//!
+//! # Consistency
+//!
+//! Replication is asynchronous and the shared state is approximate. A
+//! receiver keeps the larger of its own and the replicated value for each
+//! key: every algorithm stores a time or count that only grows as quantity
+//! is spent, so a key ends up as limited as it is on the most limited node.
+//! Quantity spent on two nodes within one sync interval is not added up, so
+//! a key can briefly allow up to its limit on every node. Nodes converge
+//! once writes stop, including after a partition or restart.
+//!
+//! # Peers
+//!
+//! Peers come from a static list (`--cluster-peer host:port`) and from the
+//! DNS records of `--cluster-dns name:port`, e.g. a Kubernetes headless
+//! service, resolved again every few seconds so peers can come and go.
+//! Every node syncs with every other, so a node can list itself: it
+//! recognizes its own address during the handshake and skips it.
+//!
+//! # Sync
+//!
+//! Every `--cluster-sync-interval-ms` each node takes the entries its actors
+//! changed since the previous sync and pushes them to every peer. A new
+//! connection starts with all live entries, so a peer that was down or
+//! unreachable catches up as soon as it is reached again, and so does one
+//! that falls too far behind.
+//!
+//! # Protocol
+//!
+//! The syncing node opens a TCP connection to the peer and sends the magic
+//! `TCSYNC01` followed by its node id (u64); the peer answers with its own
+//! id. Frames of `length (u32) | records` follow, where the records use the
+//! write-ahead log's format. Integers are little-endian.
+//!
+//! The listener does not authenticate peers, so bind it to a private
+//! network.
+
+use crate::actor::RateLimiterHandle;
+use crate::config::ClusterConfig;
+use crate::metrics::Metrics;
+use crate::wal::{self, Entry};
+use anyhow::{Context, Result, anyhow};
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, lookup_host};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+const MAGIC: &[u8; 8] = b"TCSYNC01";
+
+/// Largest frame accepted from a peer
+const MAX_FRAME_BYTES: usize = 64 * 1024 * 1024;
+
+/// Most entries sent in one frame; larger syncs are split
+const MAX_FRAME_ENTRIES: usize = 16_384;
+
+/// Time allowed to connect and handshake, or to send one frame
+const PEER_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Wait before reconnecting to a peer that could not be reached
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// How often peer addresses are resolved again
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Syncs queued for a peer before it is considered behind
+const PEER_QUEUE: usize = 64;
+
+/// Random id telling this process apart from its peers
+pub(crate) fn node_id() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Accept peer connections and merge the entries they send
+///
+/// # Errors
+///
+/// Returns an error if the listener cannot be bound.
+pub(crate) async fn serve(
+    config: &ClusterConfig,
+    node_id: u64,
+    limiter: RateLimiterHandle,
+    metrics: Arc<Metrics>,
+) -> Result<()> {
+    let listener = TcpListener::bind((config.host.as_str(), config.port))
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to bind the cluster listener to {}:{}",
+                config.host, config.port
+            )
+        })?;
+    tracing::info!("Cluster listener on {}:{}", config.host, config.port);
+
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        let limiter = limiter.clone();
+        let metrics = Arc::clone(&metrics);
+        tokio::spawn(async move {
+            if let Err(e) = receive(socket, node_id, &limiter, &metrics).await {
+                tracing::warn!("Cluster peer {} disconnected: {:#}", addr, e);
+            }
+        });
+    }
+}
+
+/// Answer a peer's handshake, then merge its frames until it hangs up
+async fn receive(
+    mut socket: TcpStream,
+    node_id: u64,
+    limiter: &RateLimiterHandle,
+    metrics: &Metrics,
+) -> Result<()> {
+    let mut hello = [0u8; 16];
+    socket.read_exact(&mut hello).await?;
+    if &hello[..8] != MAGIC {
+        return Err(anyhow!("not a throttlecrab cluster peer"));
+    }
+    socket.write_all(&node_id.to_le_bytes()).await?;
+
+    let mut header = [0u8; 4];
+    loop {
+        match socket.read_exact(&mut header).await {
+            Ok(_) => {}
+            // A node that reached itself hangs up after the handshake
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        let len = u32::from_le_bytes(header) as usize;
+        if len > MAX_FRAME_BYTES {
+            return Err(anyhow!("frame of {len} bytes is too large"));
+        }
+        let mut frame = vec![0u8; len];
+        socket.read_exact(&mut frame).await?;
+
+        let entries = decode(&frame)?;
+        let received = entries.len() as u64;
+        let merged = limiter.merge(entries).await?;
+        metrics
+            .cluster_entries_received
+            .fetch_add(received, Ordering::Relaxed);
+        metrics
+            .cluster_entries_merged
+            .fetch_add(merged as u64, Ordering::Relaxed);
+    }
+}
+
+/// Push the entries changed since the previous sync to every peer, every
+/// sync interval, until the rate limiter shuts down
+pub(crate) async fn replicate(
+    config: ClusterConfig,
+    node_id: u64,
+    limiter: RateLimiterHandle,
+    metrics: Arc<Metrics>,
+) {
+    // Start tracking changes; new connections send the full state anyway
+    if limiter.take_deltas().await.is_err() {
+        return;
+    }
+
+    let mut peers: HashMap<SocketAddr, Peer> = HashMap::new();
+    let mut discovered_at: Option<Instant> = None;
+    let mut interval = tokio::time::interval(Duration::from_millis(config.sync_interval_ms));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        if discovered_at.is_none_or(|at| at.elapsed() >= DISCOVERY_INTERVAL) {
+            discovered_at = Some(Instant::now());
+            let addrs = discover(&config).await;
+            peers.retain(|addr, _| addrs.contains(addr));
+            for addr in addrs {
+                peers.entry(addr).or_insert_with(|| {
+                    Peer::spawn(addr, node_id, limiter.clone(), Arc::clone(&metrics))
+                });
+            }
+        }
+
+        let Ok(deltas) = limiter.take_deltas().await else {
+            return;
+        };
+        if deltas.is_empty() {
+            continue;
+        }
+        let frames: Arc<[Vec<u8>]> = deltas.chunks(MAX_FRAME_ENTRIES).map(encode).collect();
+        for peer in peers.values() {
+            peer.send(Arc::clone(&frames), deltas.len());
+        }
+    }
+}
+
+/// Resolve the configured peers, logging the ones that fail
+async fn discover(config: &ClusterConfig) -> Vec<SocketAddr> {
+    let mut addrs = Vec::new();
+    for name in config.peers.iter().chain(config.dns.as_ref()) {
+        match lookup_host(name.as_str()).await {
+            Ok(resolved) => addrs.extend(resolved),
+            Err(e) => tracing::warn!("Failed to resolve cluster peer {}: {}", name, e),
+        }
+    }
+    addrs.sort();
+    addrs.dedup();
+    addrs
+}
+
+/// Sync frames for one peer: the encoded frames and how many entries they hold
+type Sync = (Arc<[Vec<u8>]>, usize);
+
+/// Connection to one peer, driven by its own task so a slow or unreachable
+/// peer doesn't hold up the others
+struct Peer {
+    tx: mpsc::Sender<Sync>,
+    /// Set when a sync was dropped because the peer fell behind
+    behind: Arc<AtomicBool>,
+    task: JoinHandle<()>,
+}
+
+impl Peer {
+    fn spawn(
+        addr: SocketAddr,
+        node_id: u64,
+        limiter: RateLimiterHandle,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel(PEER_QUEUE);
+        let behind = Arc::new(AtomicBool::new(false));
+        let task = tokio::spawn(run_peer(
+            addr,
+            node_id,
+            rx,
+            Arc::clone(&behind),
+            limiter,
+            metrics,
+        ));
+        Peer { tx, behind, task }
+    }
+
+    /// Queue a sync, or mark the peer behind if its queue is full
+    fn send(&self, frames: Arc<[Vec<u8>]>, entries: usize) {
+        if self.tx.try_send((frames, entries)).is_err() {
+            self.behind.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for Peer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Keep a connection to `addr` open, starting each one with a full sync,
+/// and forward the queued syncs over it
+async fn run_peer(
+    addr: SocketAddr,
+    node_id: u64,
+    mut rx: mpsc::Receiver<Sync>,
+    behind: Arc<AtomicBool>,
+    limiter: RateLimiterHandle,
+    metrics: Arc<Metrics>,
+) {
+    let mut reachable = true;
+    loop {
+        let mut socket = match connect(addr, node_id).await {
+            Ok(Some(socket)) => socket,
+            Ok(None) => {
+                tracing::debug!("Cluster peer {} is this node, skipping it", addr);
+                // Keep draining so the replicator never sees this peer as behind
+                while rx.recv().await.is_some() {}
+                return;
+            }
+            Err(e) => {
+                metrics
+                    .cluster_sync_failures
+                    .fetch_add(1, Ordering::Relaxed);
+                if reachable {
+                    tracing::warn!("Cannot reach cluster peer {}: {:#}", addr, e);
+                    reachable = false;
+                }
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+        reachable = true;
+        metrics.cluster_peers.fetch_add(1, Ordering::Relaxed);
+        tracing::info!("Connected to cluster peer {}", addr);
+
+        let result = forward(&mut socket, &mut rx, &behind, &limiter, &metrics).await;
+        metrics.cluster_peers.fetch_sub(1, Ordering::Relaxed);
+        match result {
+            // The rate limiter shut down
+            Ok(()) => return,
+            Err(e) => {
+                metrics
+                    .cluster_sync_failures
+                    .fetch_add(1, Ordering::Relaxed);
+                tracing::warn!("Lost cluster peer {}: {:#}", addr, e);
+            }
+        }
+    }
+}
+
+/// Open a connection to `addr` and handshake, returning `None` if it turns
+/// out to be this node
+async fn connect(addr: SocketAddr, node_id: u64) -> Result<Option<TcpStream>> {
+    tokio::time::timeout(PEER_TIMEOUT, async {
+        let mut socket = TcpStream::connect(addr).await?;
+        socket.set_nodelay(true)?;
+        let mut hello = Vec::with_capacity(16);
+        hello.extend_from_slice(MAGIC);
+        hello.extend_from_slice(&node_id.to_le_bytes());
+        socket.write_all(&hello).await?;
+
+        let mut peer_id = [0u8; 8];
+        socket.read_exact(&mut peer_id).await?;
+        Ok((u64::from_le_bytes(peer_id) != node_id).then_some(socket))
+    })
+    .await
+    .map_err(|_| anyhow!("handshake timed out"))?
+}
+
+/// Send every live entry, then the queued syncs, until the connection fails
+/// or the queue closes
+///
+/// A peer that fell behind gets every live entry again; replaying older
+/// syncs is harmless, since receivers keep the larger value.
+async fn forward(
+    socket: &mut TcpStream,
+    rx: &mut mpsc::Receiver<Sync>,
+    behind: &AtomicBool,
+    limiter: &RateLimiterHandle,
+    metrics: &Metrics,
+) -> Result<()> {
+    let mut full_sync = true;
+    loop {
+        if full_sync || behind.swap(false, Ordering::Relaxed) {
+            full_sync = false;
+            // Queued syncs are older than the full one
+            while rx.try_recv().is_ok() {}
+            let entries = limiter.live_entries().await?;
+            for chunk in entries.chunks(MAX_FRAME_ENTRIES) {
+                send_frame(socket, &encode(chunk)).await?;
+            }
+            metrics
+                .cluster_entries_sent
+                .fetch_add(entries.len() as u64, Ordering::Relaxed);
+        }
+
+        let Some((frames, entries)) = rx.recv().await else {
+            return Ok(());
+        };
+        for frame in frames.iter() {
+            send_frame(socket, frame).await?;
+        }
+        metrics
+            .cluster_entries_sent
+            .fetch_add(entries as u64, Ordering::Relaxed);
+    }
+}
+
+async fn send_frame(socket: &mut TcpStream, frame: &[u8]) -> Result<()> {
+    tokio::time::timeout(PEER_TIMEOUT, socket.write_all(frame))
+        .await
+        .map_err(|_| anyhow!("sync timed out"))??;
+    Ok(())
+}
+
+/// Encode `entries` as one length-prefixed frame
+fn encode(entries: &[Entry]) -> Vec<u8> {
+    let mut frame = vec![0u8; 4];
+    for (key, value, expiry) in entries {
+        frame.extend_from_slice(&wal::encode_record(key, *value, *expiry));
+    }
+    let len = (frame.len() - 4) as u32;
+    frame[..4].copy_from_slice(&len.to_le_bytes());
+    frame
+}
+
+/// Decode the records of a frame, without its length prefix
+fn decode(records: &[u8]) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    wal::read_records(records, "Cluster frame", |key, value, expiry| {
+        entries.push((key, value, expiry));
+    })?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor::RateLimiterActor;
+    use crate::types::{AlgorithmKind, ThrottleRequest};
+    use std::time::UNIX_EPOCH;
+    use throttlecrab::PeriodicStore;
+
+    fn spawn() -> (RateLimiterHandle, Arc<Metrics>) {
+        let metrics = Arc::new(Metrics::new());
+        let handle =
+            RateLimiterActor::spawn_periodic(100, PeriodicStore::new(), Arc::clone(&metrics));
+        (handle, metrics)
+    }
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let entries = vec![
+            ("a".to_string(), 42, None),
+            (
+                "b".to_string(),
+                -1,
+                Some(UNIX_EPOCH + Duration::from_secs(60)),
+            ),
+        ];
+        let frame = encode(&entries);
+        assert_eq!(
+            u32::from_le_bytes(frame[..4].try_into().unwrap()) as usize,
+            frame.len() - 4
+        );
+        assert_eq!(decode(&frame[4..]).unwrap(), entries);
+        assert!(decode(&frame[4..frame.len() - 1]).unwrap().len() == 1);
+    }
+
+    #[tokio::test]
+    async fn test_merge_keeps_larger_value() {
+        let (handle, _) = spawn();
+        handle
+            .throttle(ThrottleRequest::new("a", 3, 10, 60))
+            .await
+            .unwrap();
+        let (_, local, expiry) = handle.live_entries().await.unwrap().remove(0);
+
+        // An older value changes nothing, a newer one replaces it
+        let merged = handle
+            .merge(vec![("a".to_string(), local - 1, expiry)])
+            .await
+            .unwrap();
+        assert_eq!(merged, 0);
+        let merged = handle
+            .merge(vec![
+                ("a".to_string(), local + 1, expiry),
+                ("b".to_string(), 7, expiry),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(merged, 2);
+        let mut entries = handle.live_entries().await.unwrap();
+        entries.sort();
+        assert_eq!(entries[0].1, local + 1);
+        assert_eq!(entries[1].1, 7);
+
+        // An expired entry removes the key
+        let merged = handle
+            .merge(vec![("b".to_string(), 0, Some(UNIX_EPOCH))])
+            .await
+            .unwrap();
+        assert_eq!(merged, 1);
+        assert_eq!(handle.live_entries().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_merge_window_entries_across_shards() {
+        let config = crate::config::StoreConfig {
+            shards: 4,
+            ..Default::default()
+        };
+        let metrics = Arc::new(Metrics::new());
+        let source = crate::store::create_rate_limiter(&config, 100, Arc::clone(&metrics))
+            .await
+            .unwrap();
+        let replica = crate::store::create_rate_limiter(&config, 100, metrics)
+            .await
+            .unwrap();
+        let window_requests = || {
+            (0..8).flat_map(|i| {
+                [AlgorithmKind::SlidingWindow, AlgorithmKind::FixedWindow].map(|algorithm| {
+                    let key = format!("{}:{i}", algorithm.as_str());
+                    ThrottleRequest {
+                        quantity: 10,
+                        algorithm,
+                        ..ThrottleRequest::new(key, 10, 10, 60)
+                    }
+                })
+            })
+        };
+        for request in window_requests() {
+            assert!(source.throttle(request).await.unwrap().allowed);
+        }
+
+        // Replicated window entries land on the shard that reads them
+        let entries = source.live_entries().await.unwrap();
+        assert_eq!(replica.merge(entries).await.unwrap(), 16);
+        for request in window_requests() {
+            let key = Arc::clone(&request.key);
+            assert!(!replica.throttle(request).await.unwrap().allowed, "{key}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deltas() {
+        let (handle, _) = spawn();
+        // Tracking starts with the first call
+        handle
+            .throttle(ThrottleRequest::new("a", 3, 10, 60))
+            .await
+            .unwrap();
+        assert!(handle.take_deltas().await.unwrap().is_empty());
+
+        handle
+            .throttle(ThrottleRequest::new("b", 3, 10, 60))
+            .await
+            .unwrap();
+        handle
+            .throttle(ThrottleRequest::new("b", 3, 10, 60))
+            .await
+            .unwrap();
+        let deltas = handle.take_deltas().await.unwrap();
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0].0, "b");
+        assert!(handle.take_deltas().await.unwrap().is_empty());
+
+        // Resets are sent as expired entries
+        handle
+            .reset("b".into(), crate::metrics::Transport::Http)
+            .await
+            .unwrap();
+        let deltas = handle.take_deltas().await.unwrap();
+        assert_eq!(deltas, vec![("b".to_string(), 0, Some(UNIX_EPOCH))]);
+    }
+
+    #[tokio::test]
+    async fn test_nodes_converge() {
+        let (first, first_metrics) = spawn();
+        let (second, second_metrics) = spawn();
+        let config = |port: u16, peer: u16| ClusterConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            // Each node also lists itself, as with a shared peer list
+            peers: vec![format!("127.0.0.1:{port}"), format!("127.0.0.1:{peer}")],
+            dns: None,
+            sync_interval_ms: 10,
+        };
+
+        // State from before the cluster started arrives with the full sync
+        first
+            .throttle(ThrottleRequest::new("early", 3, 10, 60))
+            .await
+            .unwrap();
+
+        let mut tasks = Vec::new();
+        for (config, handle, metrics) in [
+            (config(9291, 9292), &first, &first_metrics),
+            (config(9292, 9291), &second, &second_metrics),
+        ] {
+            let id = node_id();
+            let serving = config.clone();
+            let limiter = handle.clone();
+            let serve_metrics = Arc::clone(metrics);
+            tasks.push(tokio::spawn(async move {
+                serve(&serving, id, limiter, serve_metrics).await
+            }));
+            tasks.push(tokio::spawn({
+                let limiter = handle.clone();
+                let metrics = Arc::clone(metrics);
+                async move {
+                    replicate(config, id, limiter, metrics).await;
+                    Ok(())
+                }
+            }));
+        }
+
+        // Spend the whole burst on the first node
+        for _ in 0..3 {
+            assert!(
+                first
+                    .throttle(ThrottleRequest::new("shared", 3, 10, 60))
+                    .await
+                    .unwrap()
+                    .allowed
+            );
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let response = second
+                .peek(ThrottleRequest::new("shared", 3, 10, 60))
+                .await
+                .unwrap();
+            if !response.allowed
+                && second
+                    .peek(ThrottleRequest::new("early", 3, 10, 60))
+                    .await
+                    .unwrap()
+                    .remaining
+                    < 3
+            {
+                break;
+            }
+            assert!(Instant::now() < deadline, "nodes did not converge");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(first_metrics.cluster_peers.load(Ordering::Relaxed), 1);
+        assert!(
+            second_metrics
+                .cluster_entries_merged
+                .load(Ordering::Relaxed)
+                >= 2
+        );
+
+        for task in tasks {
+            task.abort();
+        }
+    }
+}
@@ -3,14 +3,15 @@
 //! This module handles all server configuration through a flexible system that supports:
 //! - Command-line arguments
 //! - Environment variables (with THROTTLECRAB_ prefix)
-//! - Configuration file (future enhancement)
+//! - Configuration file (`--config`, see [`crate::config_file`])
 //!
 //! # Configuration Priority
 //!
 //! The configuration system follows this precedence order:
 //! 1. CLI arguments (highest priority)
 //! 2. Environment variables
-//! 3. Default values (lowest priority)
+//! 3. Configuration file
+//! 4. Default values (lowest priority)
 //!
 //! # Example Usage
 //!
@@ -27,11 +28,25 @@
 //! # Mixed (CLI overrides env)
 //! export THROTTLECRAB_HTTP_PORT=8080
 //! throttlecrab-server --http --http-port 9090  # Uses port 9090
+//!
+//! # Using a configuration file, overridden by env and CLI
+//! throttlecrab-server --config /etc/throttlecrab.toml
 //! ```
 
+use crate::config_file;
+use crate::types::MAX_KEY_LENGTH;
+use crate::validation::RequestLimits;
 use anyhow::{Result, anyhow};
-use clap::Parser;
-use serde::Deserialize;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Host every listener binds to with `--bind-any`: the IPv6 wildcard,
+/// which also accepts IPv4 clients
+pub const BIND_ANY_HOST: &str = "::";
 
 /// Main configuration structure for the server
 ///
@@ -47,8 +62,64 @@ pub struct Config {
     pub buffer_size: usize,
     /// Maximum number of denied keys to track in metrics
     pub max_denied_keys: u32,
+    /// Sampled top requested and allowed keys in metrics (None if disabled)
+    #[serde(default)]
+    pub top_keys: Option<TopKeysConfig>,
+    /// Recent requests to keep for `GET /admin/trace` (0 to disable)
+    #[serde(default)]
+    pub trace_buffer_size: usize,
+    /// Seconds between request latency log lines (0 to disable)
+    #[serde(default)]
+    pub latency_log_interval: u64,
+    /// Server-wide cap on throttle checks (None if disabled)
+    #[serde(default)]
+    pub load_shedding: Option<LoadSheddingConfig>,
+    /// Limits requests must meet on top of the built-in rules (None if disabled)
+    #[serde(default)]
+    pub validation: Option<ValidationConfig>,
+    /// Decision event export configuration (None if disabled)
+    pub events: Option<EventsConfig>,
+    /// Alerts for keys denied repeatedly (None if disabled)
+    #[serde(default)]
+    pub denial_alerts: Option<DenialAlertsConfig>,
+    /// Metrics push configuration (None if disabled)
+    pub metrics_push: Option<MetricsPushConfig>,
+    /// OpenTelemetry span export (None if disabled)
+    #[serde(default)]
+    pub otlp: Option<OtlpConfig>,
+    /// Self-probing through the transports (None if disabled)
+    #[serde(default)]
+    pub probe: Option<ProbeConfig>,
+    /// Dedicated listener for `GET /metrics` (None if disabled)
+    #[serde(default)]
+    pub metrics_listener: Option<MetricsListenerConfig>,
+    /// Dedicated listener for the `/admin` endpoints (None if disabled)
+    #[serde(default)]
+    pub admin_listener: Option<AdminListenerConfig>,
+    /// TOML file of named rate limit policies (None if disabled)
+    #[serde(default)]
+    pub policies: Option<PathBuf>,
+    /// Table of the `policies` file holding the policies, when they are
+    /// inline in a config file (None if the file is a policy file)
+    #[serde(default)]
+    pub policies_table: Option<String>,
+    /// TOML file persisting the key deny- and allow-lists (None to keep
+    /// them in memory)
+    #[serde(default)]
+    pub overrides_file: Option<PathBuf>,
+    /// State replication between servers (None if disabled)
+    #[serde(default)]
+    pub cluster: Option<ClusterConfig>,
+    /// API keys clients must authenticate with (None if disabled)
+    #[serde(default)]
+    pub api_keys: Option<ApiKeysConfig>,
+    /// File to write the startup status to once the server is running
+    pub status_file: Option<PathBuf>,
     /// Logging level (error, warn, info, debug, trace)
     pub log_level: String,
+    /// Log output format
+    #[serde(default)]
+    pub log_format: LogFormat,
 }
 
 /// Transport layer configuration
@@ -63,6 +134,12 @@ pub struct TransportConfig {
     pub grpc: Option<GrpcConfig>,
     /// Redis protocol transport configuration
     pub redis: Option<RedisConfig>,
+    /// Multiplexed transport configuration
+    #[serde(default)]
+    pub mux: Option<MuxConfig>,
+    /// UDP transport configuration
+    #[serde(default)]
+    pub udp: Option<UdpConfig>,
 }
 
 /// HTTP transport configuration
@@ -72,6 +149,118 @@ pub struct HttpConfig {
     pub host: String,
     /// Port number to listen on
     pub port: u16,
+    /// Where the endpoints are mounted
+    #[serde(default)]
+    pub routes: HttpRoutes,
+    /// Serve HTTPS instead of plain HTTP
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Answer denied rate limit requests with 429 instead of 200
+    #[serde(default)]
+    pub use_429: bool,
+    /// Limits on how long client connections are kept open
+    #[serde(default)]
+    pub keep_alive: HttpKeepAlive,
+    /// Compress batch responses with gzip or deflate for clients that
+    /// accept it
+    #[serde(default)]
+    pub compression: bool,
+}
+
+/// Keep-alive limits for HTTP/1.1 client connections
+///
+/// Closing connections after a number of requests spreads clients over
+/// instances behind a load balancer; the idle timeout frees the sockets of
+/// clients that went quiet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub struct HttpKeepAlive {
+    /// Requests served on one connection before it is closed (0 for no limit)
+    #[serde(default)]
+    pub max_requests: u64,
+    /// How long a connection may wait for its next request (None for no limit)
+    #[serde(default)]
+    pub idle_timeout: Option<Duration>,
+}
+
+/// TLS certificate and key for a transport, as PEM files
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TlsConfig {
+    /// Certificate chain presented to clients, leaf first
+    pub cert: PathBuf,
+    /// Private key for the certificate
+    pub key: PathBuf,
+    /// CA certificates client certificates must be signed by; `None`
+    /// accepts clients without a certificate
+    #[serde(default)]
+    pub client_ca: Option<PathBuf>,
+}
+
+/// Paths of the HTTP endpoints
+///
+/// Every route, including the `/admin/*` routes, is mounted under
+/// `base_path`, so the API can live at e.g. `/ratelimit/v1/throttle` behind
+/// an ingress without a rewriting proxy in front.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct HttpRoutes {
+    /// Prefix for every route, e.g. "/ratelimit/v1"; empty for the root
+    pub base_path: String,
+    /// Path of the rate limiting endpoint
+    pub throttle: String,
+    /// Path of the Prometheus metrics endpoint
+    pub metrics: String,
+    /// Path of the health check endpoint
+    pub health: String,
+}
+
+impl Default for HttpRoutes {
+    fn default() -> Self {
+        HttpRoutes {
+            base_path: String::new(),
+            throttle: "/throttle".to_string(),
+            metrics: "/metrics".to_string(),
+            health: "/health".to_string(),
+        }
+    }
+}
+
+impl HttpRoutes {
+    /// Full path of `route` under the base path
+    pub fn path(&self, route: &str) -> String {
+        format!("{}{}", self.base_path, route)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !self.base_path.is_empty()
+            && (!self.base_path.starts_with('/') || self.base_path.ends_with('/'))
+        {
+            return Err(anyhow!(
+                "--http-base-path must start with '/' and not end with '/', got {}",
+                self.base_path
+            ));
+        }
+        let routes = [
+            ("--http-throttle-path", &self.throttle),
+            ("--http-metrics-path", &self.metrics),
+            ("--http-health-path", &self.health),
+        ];
+        for (i, (flag, route)) in routes.iter().enumerate() {
+            if !route.starts_with('/') || route.len() < 2 || route.ends_with('/') {
+                return Err(anyhow!(
+                    "{flag} must start with '/' and not end with '/', got {route}"
+                ));
+            }
+            if route.starts_with("/admin/") || route.contains(['{', '}', '*']) {
+                return Err(anyhow!(
+                    "{flag} must not be under /admin or contain '{{', '}}' or '*', got {route}"
+                ));
+            }
+            if routes[..i].iter().any(|(_, other)| other == route) {
+                return Err(anyhow!("{flag} conflicts with another route: {route}"));
+            }
+        }
+        Ok(())
+    }
 }
 
 /// gRPC transport configuration
@@ -84,12 +273,146 @@ pub struct GrpcConfig {
 }
 
 /// Redis transport configuration
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct RedisConfig {
     /// Host address to bind to (e.g., "0.0.0.0")
     pub host: String,
     /// Port number to listen on
     pub port: u16,
+    /// Password clients must send with `AUTH` before any other command,
+    /// like Redis' `requirepass`. `None` disables authentication.
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Commands a single connection may issue per second (0 for unlimited).
+    /// Reading from a connection pauses once it reaches the cap.
+    #[serde(default)]
+    pub max_commands_per_second: u32,
+    /// Connections held at once (0 for unlimited); more are refused
+    #[serde(default)]
+    pub max_connections: usize,
+    /// Connections held at once from one client IP (0 for unlimited)
+    #[serde(default)]
+    pub max_connections_per_ip: usize,
+}
+
+impl fmt::Debug for RedisConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisConfig")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("max_commands_per_second", &self.max_commands_per_second)
+            .field("max_connections", &self.max_connections)
+            .field("max_connections_per_ip", &self.max_connections_per_ip)
+            .finish()
+    }
+}
+
+/// Where API keys come from
+///
+/// See [`auth`](crate::auth) for how clients present them.
+#[derive(Clone, Default, Deserialize)]
+pub struct ApiKeysConfig {
+    /// `(name, secret)` pairs given on the command line or in the environment
+    #[serde(default)]
+    pub keys: Vec<(String, String)>,
+    /// TOML file of further keys
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    /// Put requests that name no namespace in their key's namespace
+    #[serde(default)]
+    pub namespaces: bool,
+    /// Throttle check budgets by key name (see [`budget`](crate::budget))
+    #[serde(default)]
+    pub budgets: Vec<ApiKeyBudget>,
+}
+
+/// Most throttle checks a client's API key may make per period
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ApiKeyBudget {
+    /// Name of the API key, or `*` for every key without its own budget
+    pub name: String,
+    /// Checks allowed per period
+    pub count: u64,
+    /// Length of the period in seconds
+    pub period: u64,
+}
+
+impl std::str::FromStr for ApiKeyBudget {
+    type Err = anyhow::Error;
+
+    /// Parse `NAME=COUNT/SECS`
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow!("--api-key-budget must be NAME=COUNT/SECS, got {s}");
+        let (name, rate) = s.split_once('=').ok_or_else(invalid)?;
+        let (count, period) = rate.split_once('/').ok_or_else(invalid)?;
+        Ok(ApiKeyBudget {
+            name: name.to_string(),
+            count: count.trim().parse().map_err(|_| invalid())?,
+            period: period.trim().parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl fmt::Debug for ApiKeysConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = self.keys.iter().map(|(name, _)| name).collect();
+        f.debug_struct("ApiKeysConfig")
+            .field("keys", &names)
+            .field("file", &self.file)
+            .field("namespaces", &self.namespaces)
+            .field("budgets", &self.budgets)
+            .finish()
+    }
+}
+
+/// Multiplexed transport configuration
+///
+/// Serves HTTP, gRPC and Redis clients on one port, detecting the protocol
+/// from the first bytes of each connection.
+#[derive(Clone, Deserialize)]
+pub struct MuxConfig {
+    /// Host address to bind to (e.g., "0.0.0.0")
+    pub host: String,
+    /// Port number to listen on
+    pub port: u16,
+    /// Where the HTTP endpoints are mounted
+    #[serde(default)]
+    pub routes: HttpRoutes,
+    /// Answer denied HTTP rate limit requests with 429, as for [`HttpConfig`]
+    #[serde(default)]
+    pub use_429: bool,
+    /// Password Redis clients must send with `AUTH`, as for [`RedisConfig`]
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Per-connection command cap for Redis clients, as for [`RedisConfig`]
+    #[serde(default)]
+    pub max_commands_per_second: u32,
+}
+
+impl fmt::Debug for MuxConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MuxConfig")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("routes", &self.routes)
+            .field("use_429", &self.use_429)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("max_commands_per_second", &self.max_commands_per_second)
+            .finish()
+    }
+}
+
+/// UDP transport configuration
+///
+/// Answers JSON throttle requests in single datagrams; see
+/// [`crate::transport::udp`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct UdpConfig {
+    /// Host address to bind to (e.g., "0.0.0.0")
+    pub host: String,
+    /// Port number to listen on
+    pub port: u16,
 }
 
 /// Rate limiter store configuration
@@ -98,6 +421,9 @@ pub struct RedisConfig {
 /// - **Periodic**: Cleanups at fixed intervals, predictable memory usage
 /// - **Probabilistic**: Random cleanups, lower overhead but less predictable
 /// - **Adaptive**: Adjusts cleanup frequency based on load
+/// - **TimingWheel**: Removes keys as they expire, without full scans
+/// - **Auto**: Starts adaptive and migrates if cleanups exceed the latency budget
+/// - **Redis**: State kept in an external Redis, shared between servers
 #[derive(Debug, Clone, Deserialize)]
 pub struct StoreConfig {
     /// Type of store to use
@@ -109,12 +435,241 @@ pub struct StoreConfig {
     pub cleanup_interval: u64,
     /// Cleanup probability for probabilistic store (1 in N)
     pub cleanup_probability: u64,
+    /// Most expired keys the timing wheel store removes per operation
+    pub cleanup_batch: usize,
     /// Minimum cleanup interval for adaptive store (seconds)
     pub min_interval: u64,
     /// Maximum cleanup interval for adaptive store (seconds)
     pub max_interval: u64,
     /// Maximum operations before cleanup for adaptive store
     pub max_operations: usize,
+    /// Slowest acceptable request for the auto store (microseconds)
+    pub latency_budget_us: u64,
+    /// Upper bound for entry TTLs in seconds (0 for unlimited)
+    pub max_ttl: u64,
+    /// Factor applied to every entry TTL before the cap
+    pub ttl_multiplier: f64,
+    /// Maximum number of keys the store may hold (0 for unlimited)
+    pub max_keys: usize,
+    /// Memory budget for store entries in MiB (0 for unlimited)
+    #[serde(default)]
+    pub max_memory_mb: usize,
+    /// What to do with new keys once `max_keys` or `max_memory_mb` is reached
+    pub on_full: OnFull,
+    /// Time source for request timestamps
+    pub clock: ClockType,
+    /// Write-ahead log for persistence across restarts (None if disabled)
+    pub wal: Option<WalConfig>,
+    /// Periodic snapshots for persistence across restarts (None if disabled)
+    #[serde(default)]
+    pub snapshot: Option<SnapshotConfig>,
+    /// Secondary store that mirrors a fraction of keys (None if disabled)
+    pub canary: Option<CanaryConfig>,
+    /// Number of actors the key space is partitioned across
+    #[serde(default = "default_shards")]
+    pub shards: usize,
+    /// Hash maps each store splits its table into, so growth rehashes and
+    /// cleanup passes touch one at a time
+    #[serde(default = "default_shards")]
+    pub map_shards: usize,
+    /// URL of the Redis holding the state of the Redis store
+    #[serde(default)]
+    pub redis_url: Option<String>,
+    /// How long an actor collects throttle requests to coalesce identical
+    /// ones, in microseconds (0 to decide each as it arrives)
+    #[serde(default)]
+    pub coalesce_window_us: u64,
+    /// What throttle requests do when an actor's queue is full
+    #[serde(default)]
+    pub backpressure: Backpressure,
+    /// How long identical throttle requests are answered from the last
+    /// decision, in milliseconds (0 to disable)
+    #[serde(default)]
+    pub micro_cache_ms: u64,
+    /// How often store statistics are logged, in seconds (0 to disable)
+    #[serde(default)]
+    pub stats_log_interval: u64,
+    /// Most requested keys each actor counts for `GET /admin/keys/hot`
+    /// (0 to disable)
+    #[serde(default)]
+    pub hot_keys: usize,
+    /// Most keys each namespace may hold in the store
+    #[serde(default)]
+    pub namespace_quotas: BTreeMap<String, usize>,
+}
+
+/// Upper bound for [`StoreConfig::shards`] and [`StoreConfig::map_shards`]
+pub const MAX_SHARDS: usize = 1024;
+
+fn default_shards() -> usize {
+    1
+}
+
+/// Canary store configuration
+///
+/// A fraction of keys is mirrored to a second store and the decisions are
+/// compared. Responses always come from the primary store.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CanaryConfig {
+    /// Type of the secondary store; other parameters match the primary
+    pub store_type: StoreType,
+    /// Fraction of keys to mirror (0.0 to 1.0)
+    pub fraction: f64,
+}
+
+impl Default for StoreConfig {
+    /// Same defaults as the command line
+    fn default() -> Self {
+        StoreConfig {
+            store_type: StoreType::Periodic,
+            capacity: 100_000,
+            cleanup_interval: 300,
+            cleanup_probability: 10_000,
+            cleanup_batch: 128,
+            min_interval: 5,
+            max_interval: 300,
+            max_operations: 1_000_000,
+            latency_budget_us: 1_000,
+            max_ttl: 0,
+            ttl_multiplier: 1.0,
+            max_keys: 0,
+            max_memory_mb: 0,
+            on_full: OnFull::Reject,
+            clock: ClockType::Monotonic,
+            wal: None,
+            snapshot: None,
+            canary: None,
+            shards: 1,
+            map_shards: 1,
+            redis_url: None,
+            coalesce_window_us: 0,
+            backpressure: Backpressure::Block,
+            micro_cache_ms: 0,
+            stats_log_interval: 0,
+            hot_keys: 0,
+            namespace_quotas: BTreeMap::new(),
+        }
+    }
+}
+
+/// Time source used to timestamp requests
+///
+/// - **System**: The wall clock; follows NTP and manual adjustments
+/// - **Monotonic**: Starts at the wall clock and never goes backwards
+///   (the default)
+///
+/// Transports always timestamp requests with this clock; no transport
+/// accepts a client-supplied time, so client clock skew cannot move a key's
+/// state.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClockType {
+    /// System wall clock
+    System,
+    /// Monotonic clock anchored at startup
+    Monotonic,
+}
+
+impl std::str::FromStr for ClockType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "system" => Ok(ClockType::System),
+            "monotonic" => Ok(ClockType::Monotonic),
+            _ => Err(anyhow!(
+                "Invalid clock: {}. Valid options are: system, monotonic",
+                s
+            )),
+        }
+    }
+}
+
+/// Log output format
+///
+/// - **Text**: Human-readable lines, one per event
+/// - **Json**: One JSON object per event, for log shippers
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// Newline-delimited JSON
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow!(
+                "Invalid log format: {}. Valid options are: text, json",
+                s
+            )),
+        }
+    }
+}
+
+/// Write-ahead log configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct WalConfig {
+    /// Path of the log file
+    pub path: PathBuf,
+    /// When appended records are synced to disk
+    pub fsync: WalFsync,
+    /// Sync interval for [`WalFsync::Interval`]
+    pub fsync_interval: Duration,
+    /// Log size in bytes that triggers compaction
+    pub compact_bytes: u64,
+}
+
+/// Periodic snapshot configuration
+///
+/// A lighter alternative to the write-ahead log: no work per request, but a
+/// crash loses up to one interval of state.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SnapshotConfig {
+    /// Path of the snapshot file
+    pub path: PathBuf,
+    /// Time between snapshots
+    pub interval: Duration,
+}
+
+/// Write-ahead log sync policy
+///
+/// Trades durability against write throughput:
+/// - **Always**: Sync after every group of writes, loses at most in-flight records
+/// - **Interval**: Sync at a fixed interval, loses at most one interval of records
+/// - **Never**: Leave syncing to the OS, loses whatever the OS had not flushed
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WalFsync {
+    /// Sync after every write batch
+    Always,
+    /// Sync at most once per interval
+    Interval,
+    /// Never sync explicitly
+    Never,
+}
+
+impl std::str::FromStr for WalFsync {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "always" => Ok(WalFsync::Always),
+            "interval" => Ok(WalFsync::Interval),
+            "never" => Ok(WalFsync::Never),
+            _ => Err(anyhow!(
+                "Invalid WAL fsync policy: {}. Valid options are: always, interval, never",
+                s
+            )),
+        }
+    }
 }
 
 /// Available store types for the rate limiter
@@ -123,7 +678,10 @@ pub struct StoreConfig {
 /// - **Periodic**: Best for consistent workloads
 /// - **Probabilistic**: Best for unpredictable workloads
 /// - **Adaptive**: Best for variable workloads
-#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+/// - **TimingWheel**: Best for large stores where cleanup pauses matter
+/// - **Auto**: Best when the workload is unknown and latency matters
+/// - **Redis**: Best when several servers must share exact limits
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum StoreType {
     /// Fixed interval cleanup
@@ -132,6 +690,26 @@ pub enum StoreType {
     Probabilistic,
     /// Dynamic cleanup interval based on load
     Adaptive,
+    /// Incremental cleanup from a timing wheel of expiries
+    TimingWheel,
+    /// Adaptive, migrating to periodic if cleanups exceed the latency budget
+    Auto,
+    /// External Redis (requires the `redis-store` cargo feature)
+    Redis,
+}
+
+impl StoreType {
+    /// Name of the store type, as accepted by `--store`
+    pub fn name(&self) -> &'static str {
+        match self {
+            StoreType::Periodic => "periodic",
+            StoreType::Probabilistic => "probabilistic",
+            StoreType::Adaptive => "adaptive",
+            StoreType::TimingWheel => "timingwheel",
+            StoreType::Auto => "auto",
+            StoreType::Redis => "redis",
+        }
+    }
 }
 
 impl std::str::FromStr for StoreType {
@@ -142,423 +720,3716 @@ impl std::str::FromStr for StoreType {
             "periodic" => Ok(StoreType::Periodic),
             "probabilistic" => Ok(StoreType::Probabilistic),
             "adaptive" => Ok(StoreType::Adaptive),
+            "timingwheel" => Ok(StoreType::TimingWheel),
+            "auto" => Ok(StoreType::Auto),
+            "redis" => Ok(StoreType::Redis),
             _ => Err(anyhow!(
-                "Invalid store type: {}. Valid options are: periodic, probabilistic, adaptive",
+                "Invalid store type: {}. Valid options are: periodic, probabilistic, adaptive, timingwheel, auto, redis",
                 s
             )),
         }
     }
 }
 
-/// Command-line arguments for the server
-///
-/// All arguments can also be set via environment variables with the
-/// THROTTLECRAB_ prefix. CLI arguments take precedence over environment variables.
+/// Admission policy for new keys when the store is full
 ///
-/// # Examples
+/// Only applies when a key limit is configured:
+/// - **Reject**: Fail requests for new keys with a distinct "store full" error
+/// - **EvictLru**: Evict the least recently active keys to make room
+/// - **Degrade**: Fold new keys into a fixed pool of shared buckets (approximate limits)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnFull {
+    /// Reject requests for keys that are not already tracked
+    Reject,
+    /// Evict idle keys to admit new ones
+    EvictLru,
+    /// Share a bounded set of overflow buckets between new keys
+    Degrade,
+}
+
+impl std::str::FromStr for OnFull {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "reject" => Ok(OnFull::Reject),
+            "evict-lru" => Ok(OnFull::EvictLru),
+            "degrade" => Ok(OnFull::Degrade),
+            _ => Err(anyhow!(
+                "Invalid on-full policy: {}. Valid options are: reject, evict-lru, degrade",
+                s
+            )),
+        }
+    }
+}
+
+/// What a throttle request does when its actor's queue is full
 ///
-/// Basic usage with HTTP protocol:
-/// ```bash
-/// throttlecrab-server --http
-/// ```
+/// - **Block**: Wait for room in the queue
+/// - **FailFast**: Fail straight away with an "overloaded" error
+/// - **ShedOldest**: Wait for room, while the actor fails the requests that
+///   have waited longest with an "overloaded" error until the queue drains
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backpressure {
+    /// Wait until the actor catches up
+    #[default]
+    Block,
+    /// Reject requests that find the queue full
+    FailFast,
+    /// Reject the oldest queued requests in favour of new ones
+    ShedOldest,
+}
+
+impl std::str::FromStr for Backpressure {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "block" => Ok(Backpressure::Block),
+            "fail-fast" => Ok(Backpressure::FailFast),
+            "shed-oldest" => Ok(Backpressure::ShedOldest),
+            _ => Err(anyhow!(
+                "Invalid backpressure policy: {}. Valid options are: block, fail-fast, shed-oldest",
+                s
+            )),
+        }
+    }
+}
+
+/// Server-wide load shedding configuration
 ///
-/// Multiple transports with custom ports:
-/// ```bash
-/// throttlecrab-server --http --http-port 8080 --grpc --grpc-port 50051
-/// ```
+/// Above `max_rps` throttle checks per second, requests skip the store and
+/// get `decision` straight away, so a traffic spike cannot back up the
+/// actor queues.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct LoadSheddingConfig {
+    /// Throttle checks per second before requests are shed
+    pub max_rps: u64,
+    /// Decision given to shed requests
+    pub decision: ShedDecision,
+}
+
+/// Decision given to requests shed by `--max-rps`
 ///
-/// Using adaptive store with debug logging:
-/// ```bash
-/// throttlecrab-server --http --store adaptive --log-level debug
-/// ```
-#[derive(Parser, Debug)]
-#[command(
-    name = "throttlecrab-server",
-    version = env!("CARGO_PKG_VERSION"),
-    about = "High-performance rate limiting server",
-    long_about = "A high-performance rate limiting server with multiple protocol support.\n\nAt least one transport must be specified.\n\nEnvironment variables with THROTTLECRAB_ prefix are supported. CLI arguments take precedence over environment variables."
-)]
-pub struct Args {
-    // HTTP Transport
-    #[arg(long, help = "Enable HTTP transport", env = "THROTTLECRAB_HTTP")]
-    pub http: bool,
-    #[arg(
-        long,
-        value_name = "HOST",
-        help = "HTTP host",
-        default_value = "0.0.0.0",
-        env = "THROTTLECRAB_HTTP_HOST"
-    )]
-    pub http_host: String,
-    #[arg(
-        long,
-        value_name = "PORT",
-        help = "HTTP port",
-        default_value_t = 8080,
-        env = "THROTTLECRAB_HTTP_PORT"
-    )]
-    pub http_port: u16,
+/// - **Deny**: Protect the services behind the limiter (fail closed)
+/// - **Allow**: Keep serving traffic without limits (fail open)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ShedDecision {
+    /// Deny shed requests
+    Deny,
+    /// Allow shed requests
+    Allow,
+}
 
-    // gRPC Transport
-    #[arg(long, help = "Enable gRPC transport", env = "THROTTLECRAB_GRPC")]
-    pub grpc: bool,
-    #[arg(
-        long,
-        value_name = "HOST",
-        help = "gRPC host",
-        default_value = "0.0.0.0",
-        env = "THROTTLECRAB_GRPC_HOST"
-    )]
-    pub grpc_host: String,
-    #[arg(
-        long,
-        value_name = "PORT",
-        help = "gRPC port",
-        default_value_t = 8070,
-        env = "THROTTLECRAB_GRPC_PORT"
-    )]
-    pub grpc_port: u16,
+impl std::str::FromStr for ShedDecision {
+    type Err = anyhow::Error;
 
-    // Redis Transport
-    #[arg(
-        long,
-        help = "Enable Redis protocol transport",
-        env = "THROTTLECRAB_REDIS"
-    )]
-    pub redis: bool,
-    #[arg(
-        long,
-        value_name = "HOST",
-        help = "Redis host",
-        default_value = "0.0.0.0",
-        env = "THROTTLECRAB_REDIS_HOST"
-    )]
-    pub redis_host: String,
-    #[arg(
-        long,
-        value_name = "PORT",
-        help = "Redis port",
-        default_value_t = 6379,
-        env = "THROTTLECRAB_REDIS_PORT"
-    )]
-    pub redis_port: u16,
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "deny" => Ok(ShedDecision::Deny),
+            "allow" => Ok(ShedDecision::Allow),
+            _ => Err(anyhow!(
+                "Invalid shed decision: {}. Valid options are: deny, allow",
+                s
+            )),
+        }
+    }
+}
 
-    // Store Configuration
+/// Server-wide request limits
+///
+/// Tightens the rules every request is checked against; see
+/// [`validation`](crate::validation). Unset limits are not checked.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ValidationConfig {
+    /// Longest key in bytes, at most [`MAX_KEY_LENGTH`]
+    #[serde(default)]
+    pub max_key_length: Option<usize>,
+    /// Longest `period` in seconds
+    #[serde(default)]
+    pub max_period: Option<i64>,
+    /// Largest `quantity`
+    #[serde(default)]
+    pub max_quantity: Option<i64>,
+    /// The only characters keys may contain, e.g. `a-zA-Z0-9:_.-`
+    #[serde(default)]
+    pub key_chars: Option<String>,
+}
+
+/// Decision event export configuration
+///
+/// When enabled, a sample of rate limiting decisions is published to
+/// a message broker for downstream consumers.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EventsConfig {
+    /// Broker to publish to
+    pub sink: EventSinkType,
+    /// Broker address (NATS server URL or comma-separated Kafka brokers)
+    pub url: String,
+    /// NATS subject or Kafka topic
+    pub topic: String,
+    /// Wire format of each event
+    pub format: EventFormat,
+    /// Fraction of decisions to export (0.0 to 1.0)
+    pub sample_rate: f64,
+    /// Maximum number of events per batch
+    pub batch_size: usize,
+    /// Maximum time to hold a partial batch (milliseconds)
+    pub flush_interval_ms: u64,
+    /// Number of events buffered before new events are dropped
+    pub buffer_size: usize,
+}
+
+/// Top requested and allowed keys configuration
+///
+/// One request in every `sample_rate` is counted, so the counts reported in
+/// metrics and by `GET /admin/keys/top` are estimates.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TopKeysConfig {
+    /// Keys to track for each list (at most 10,000)
+    pub count: usize,
+    /// Count one request in this many
+    pub sample_rate: u64,
+}
+
+/// Denial alert configuration
+///
+/// When enabled, a [`DenialEvent`](crate::denials::DenialEvent) is emitted
+/// each time a key is denied `threshold` times within `window` seconds; see
+/// [`denials`](crate::denials).
+#[derive(Debug, Clone, Deserialize)]
+pub struct DenialAlertsConfig {
+    /// Denials within the window that trigger an alert
+    pub threshold: u64,
+    /// Window denials are counted in (seconds)
+    pub window: u64,
+    /// URL to POST each alert to (requires the `webhook` feature)
+    #[serde(default)]
+    pub webhook: Option<String>,
+}
+
+/// Message brokers supported by the event exporter
+///
+/// Each sink requires the matching cargo feature (`nats` or `kafka`).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventSinkType {
+    /// NATS core publish
+    Nats,
+    /// Kafka producer
+    Kafka,
+}
+
+impl std::str::FromStr for EventSinkType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "nats" => Ok(EventSinkType::Nats),
+            "kafka" => Ok(EventSinkType::Kafka),
+            _ => Err(anyhow!(
+                "Invalid events sink: {}. Valid options are: nats, kafka",
+                s
+            )),
+        }
+    }
+}
+
+/// Serialization format for exported events
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventFormat {
+    /// One JSON object per event
+    Json,
+    /// `DecisionEvent` message from `proto/throttlecrab.proto`
+    Protobuf,
+}
+
+impl std::str::FromStr for EventFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(EventFormat::Json),
+            "protobuf" => Ok(EventFormat::Protobuf),
+            _ => Err(anyhow!(
+                "Invalid events format: {}. Valid options are: json, protobuf",
+                s
+            )),
+        }
+    }
+}
+
+/// Metrics push configuration
+///
+/// For environments that cannot scrape `/metrics`: a snapshot of the
+/// metrics is POSTed to a collector at a jittered interval instead.
+#[derive(Clone, Deserialize)]
+pub struct MetricsPushConfig {
+    /// Collector endpoint
+    pub url: String,
+    /// Payload format
+    pub format: MetricsPushFormat,
+    /// Time between pushes (seconds)
+    pub interval: u64,
+    /// Bearer token sent in the `Authorization` header (None for no auth)
+    pub token: Option<String>,
+}
+
+impl fmt::Debug for MetricsPushConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetricsPushConfig")
+            .field("url", &self.url)
+            .field("format", &self.format)
+            .field("interval", &self.interval)
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// OpenTelemetry span export configuration
+///
+/// Each throttle request is exported as a trace of its transport, actor
+/// queue and store spans to an OTLP/gRPC collector.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OtlpConfig {
+    /// Collector endpoint
+    pub endpoint: String,
+    /// Fraction of new traces to sample (0.0 to 1.0)
+    pub sample_ratio: f64,
+}
+
+/// Self-probing configuration
+///
+/// The server periodically sends a throttle request for a synthetic key to
+/// each of its own transports over loopback, recording latency and failures
+/// in the metrics even when no client traffic flows.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProbeConfig {
+    /// Time between probe rounds (seconds)
+    pub interval: u64,
+    /// Key the probes are rate limited under
+    pub key: String,
+}
+
+/// Dedicated metrics listener configuration
+///
+/// Serves only `GET /metrics`, so Prometheus can scrape a port that is not
+/// exposed to clients. The HTTP transport keeps serving `/metrics` as well.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MetricsListenerConfig {
+    /// Host address to bind to
+    pub host: String,
+    /// Port number to listen on
+    pub port: u16,
+}
+
+/// Dedicated admin listener configuration
+///
+/// Serves the `/admin` endpoints without the rate limiting API, so they can
+/// be bound to an address clients cannot reach. The HTTP and multiplexed
+/// transports then stop serving them.
+#[derive(Clone, Deserialize)]
+pub struct AdminListenerConfig {
+    /// Host address to bind to
+    pub host: String,
+    /// Port number to listen on
+    pub port: u16,
+    /// Secret `POST` and `DELETE /admin/overrides` require as a bearer token.
+    /// `None` leaves the overrides read-only over HTTP.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+impl fmt::Debug for AdminListenerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdminListenerConfig")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// Cluster replication configuration
+///
+/// Each node listens for its peers and pushes them the rate limit state it
+/// changed, so every node converges on approximately the same limits.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClusterConfig {
+    /// Host address to listen for peers on
+    pub host: String,
+    /// Port to listen for peers on
+    pub port: u16,
+    /// Peer addresses as `host:port`, resolved again every few seconds
+    #[serde(default)]
+    pub peers: Vec<String>,
+    /// `name:port` whose DNS records list the peers (None to use `peers` only)
+    #[serde(default)]
+    pub dns: Option<String>,
+    /// Time between syncs (milliseconds)
+    pub sync_interval_ms: u64,
+}
+
+/// Payload format for pushed metrics
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MetricsPushFormat {
+    /// JSON document listing every sample
+    Json,
+    /// Prometheus remote-write protobuf, snappy compressed
+    RemoteWrite,
+}
+
+impl std::str::FromStr for MetricsPushFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(MetricsPushFormat::Json),
+            "remote-write" => Ok(MetricsPushFormat::RemoteWrite),
+            _ => Err(anyhow!(
+                "Invalid metrics push format: {}. Valid options are: json, remote-write",
+                s
+            )),
+        }
+    }
+}
+
+/// Command-line arguments for the server
+///
+/// All arguments can also be set via environment variables with the
+/// THROTTLECRAB_ prefix. CLI arguments take precedence over environment variables.
+///
+/// # Examples
+///
+/// Basic usage with HTTP protocol:
+/// ```bash
+/// throttlecrab-server --http
+/// ```
+///
+/// Multiple transports with custom ports:
+/// ```bash
+/// throttlecrab-server --http --http-port 8080 --grpc --grpc-port 50051
+/// ```
+///
+/// Using adaptive store with debug logging:
+/// ```bash
+/// throttlecrab-server --http --store adaptive --log-level debug
+/// ```
+#[derive(Parser, Debug)]
+#[command(
+    name = "throttlecrab-server",
+    version = env!("CARGO_PKG_VERSION"),
+    about = "High-performance rate limiting server",
+    long_about = "A high-performance rate limiting server with multiple protocol support.\n\nAt least one transport must be specified.\n\nEnvironment variables with THROTTLECRAB_ prefix are supported. CLI arguments take precedence over environment variables."
+)]
+pub struct Args {
     #[arg(
         long,
-        value_name = "TYPE",
-        help = "Store type: periodic, probabilistic, adaptive",
-        default_value = "periodic",
-        env = "THROTTLECRAB_STORE"
+        help = "Listen on every IPv4 and IPv6 address (::) on all transports and the metrics listener, overriding their hosts",
+        env = "THROTTLECRAB_BIND_ANY"
     )]
-    pub store: StoreType,
+    pub bind_any: bool,
+
+    // HTTP Transport
+    #[arg(long, help = "Enable HTTP transport", env = "THROTTLECRAB_HTTP")]
+    pub http: bool,
     #[arg(
         long,
-        value_name = "SIZE",
-        help = "Initial store capacity",
-        default_value_t = 100_000,
-        env = "THROTTLECRAB_STORE_CAPACITY"
+        value_name = "HOST",
+        help = "HTTP host",
+        default_value = "0.0.0.0",
+        env = "THROTTLECRAB_HTTP_HOST"
     )]
-    pub store_capacity: usize,
-
-    // Store-specific options
+    pub http_host: String,
     #[arg(
         long,
-        value_name = "SECS",
-        help = "Cleanup interval for periodic store (seconds)",
-        default_value_t = 300,
-        env = "THROTTLECRAB_STORE_CLEANUP_INTERVAL"
+        value_name = "PORT",
+        help = "HTTP port",
+        default_value_t = 8080,
+        env = "THROTTLECRAB_HTTP_PORT"
     )]
-    pub store_cleanup_interval: u64,
+    pub http_port: u16,
     #[arg(
         long,
-        value_name = "N",
-        help = "Cleanup probability for probabilistic store (1 in N)",
-        default_value_t = 10_000,
-        env = "THROTTLECRAB_STORE_CLEANUP_PROBABILITY"
+        value_name = "PATH",
+        help = "Prefix for every HTTP route, e.g. /ratelimit/v1",
+        default_value = "",
+        env = "THROTTLECRAB_HTTP_BASE_PATH"
     )]
-    pub store_cleanup_probability: u64,
+    pub http_base_path: String,
     #[arg(
         long,
-        value_name = "SECS",
-        help = "Minimum cleanup interval for adaptive store (seconds)",
-        default_value_t = 5,
-        env = "THROTTLECRAB_STORE_MIN_INTERVAL"
+        value_name = "PATH",
+        help = "Path of the HTTP rate limiting endpoint",
+        default_value = "/throttle",
+        env = "THROTTLECRAB_HTTP_THROTTLE_PATH"
     )]
-    pub store_min_interval: u64,
+    pub http_throttle_path: String,
     #[arg(
         long,
-        value_name = "SECS",
-        help = "Maximum cleanup interval for adaptive store (seconds)",
-        default_value_t = 300,
-        env = "THROTTLECRAB_STORE_MAX_INTERVAL"
+        value_name = "PATH",
+        help = "Path of the HTTP metrics endpoint",
+        default_value = "/metrics",
+        env = "THROTTLECRAB_HTTP_METRICS_PATH"
     )]
-    pub store_max_interval: u64,
+    pub http_metrics_path: String,
     #[arg(
         long,
-        value_name = "N",
-        help = "Maximum operations before cleanup for adaptive store",
-        default_value_t = 1_000_000,
-        env = "THROTTLECRAB_STORE_MAX_OPERATIONS"
+        value_name = "PATH",
+        help = "Path of the HTTP health check endpoint",
+        default_value = "/health",
+        env = "THROTTLECRAB_HTTP_HEALTH_PATH"
     )]
-    pub store_max_operations: usize,
-
-    // General options
+    pub http_health_path: String,
     #[arg(
         long,
-        value_name = "SIZE",
-        help = "Channel buffer size",
-        default_value_t = 100_000,
-        env = "THROTTLECRAB_BUFFER_SIZE"
+        value_name = "FILE",
+        help = "Serve HTTPS with this PEM certificate chain (requires --http-tls-key)",
+        env = "THROTTLECRAB_HTTP_TLS_CERT"
     )]
-    pub buffer_size: usize,
+    pub http_tls_cert: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "PEM private key for --http-tls-cert",
+        env = "THROTTLECRAB_HTTP_TLS_KEY"
+    )]
+    pub http_tls_key: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Require HTTPS clients to present a certificate signed by these PEM CA certificates",
+        env = "THROTTLECRAB_HTTP_TLS_CLIENT_CA"
+    )]
+    pub http_tls_client_ca: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Answer denied HTTP rate limit requests with 429 Too Many Requests instead of 200",
+        env = "THROTTLECRAB_HTTP_USE_429"
+    )]
+    pub http_use_429: bool,
     #[arg(
         long,
         value_name = "COUNT",
-        help = "Maximum number of denied keys to track in metrics (0 to disable, max: 10000)",
-        default_value_t = 100,
-        env = "THROTTLECRAB_MAX_DENIED_KEYS",
-        value_parser = clap::value_parser!(u32).range(0..=10000)
+        help = "Close HTTP connections after this many requests (0 for no limit)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_HTTP_KEEP_ALIVE_MAX_REQUESTS"
     )]
-    pub max_denied_keys: u32,
+    pub http_keep_alive_max_requests: u64,
     #[arg(
         long,
-        value_name = "LEVEL",
-        help = "Log level: error, warn, info, debug, trace",
-        default_value = "info",
-        env = "THROTTLECRAB_LOG_LEVEL"
+        value_name = "SECS",
+        help = "Close HTTP connections that wait this long for their next request (0 for no limit)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_HTTP_KEEP_ALIVE_TIMEOUT"
     )]
-    pub log_level: String,
-
-    // Utility options
+    pub http_keep_alive_timeout: u64,
     #[arg(
         long,
-        help = "List all environment variables and exit",
-        action = clap::ArgAction::SetTrue
+        help = "Compress HTTP batch responses with gzip or deflate when the client accepts it",
+        env = "THROTTLECRAB_HTTP_COMPRESSION"
     )]
-    pub list_env_vars: bool,
-}
-
-impl Config {
-    /// Build configuration from environment variables and CLI arguments
-    ///
-    /// This method:
-    /// 1. Parses CLI arguments (with env var fallback via clap)
-    /// 2. Handles special flags like --list-env-vars
-    /// 3. Builds the configuration structure
-    /// 4. Validates the configuration
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if:
-    /// - No transport is specified
-    /// - Invalid configuration values are provided
-    pub fn from_env_and_args() -> Result<Self> {
-        // Clap automatically handles environment variables with the precedence:
-        // 1. CLI arguments (highest priority)
-        // 2. Environment variables
-        // 3. Default values (lowest priority)
-        let args = Args::parse();
+    pub http_compression: bool,
 
-        // Handle --list-env-vars
-        if args.list_env_vars {
-            Self::print_env_vars();
-            std::process::exit(0);
-        }
+    // gRPC Transport
+    #[arg(long, help = "Enable gRPC transport", env = "THROTTLECRAB_GRPC")]
+    pub grpc: bool,
+    #[arg(
+        long,
+        value_name = "HOST",
+        help = "gRPC host",
+        default_value = "0.0.0.0",
+        env = "THROTTLECRAB_GRPC_HOST"
+    )]
+    pub grpc_host: String,
+    #[arg(
+        long,
+        value_name = "PORT",
+        help = "gRPC port",
+        default_value_t = 8070,
+        env = "THROTTLECRAB_GRPC_PORT"
+    )]
+    pub grpc_port: u16,
 
-        // Build config from parsed args (which already include env vars)
-        let mut config = Config {
-            transports: TransportConfig {
+    // Redis Transport
+    #[arg(
+        long,
+        help = "Enable Redis protocol transport",
+        env = "THROTTLECRAB_REDIS"
+    )]
+    pub redis: bool,
+    #[arg(
+        long,
+        value_name = "HOST",
+        help = "Redis host",
+        default_value = "0.0.0.0",
+        env = "THROTTLECRAB_REDIS_HOST"
+    )]
+    pub redis_host: String,
+    #[arg(
+        long,
+        value_name = "PORT",
+        help = "Redis port",
+        default_value_t = 6379,
+        env = "THROTTLECRAB_REDIS_PORT"
+    )]
+    pub redis_port: u16,
+    #[arg(
+        long,
+        value_name = "PASSWORD",
+        help = "Require clients to AUTH with this password before other commands",
+        env = "THROTTLECRAB_REDIS_PASSWORD",
+        hide_env_values = true
+    )]
+    pub redis_password: Option<String>,
+    #[arg(
+        long,
+        value_name = "COUNT",
+        help = "Commands a single Redis connection may issue per second (0 for unlimited)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_REDIS_MAX_COMMANDS_PER_SECOND"
+    )]
+    pub redis_max_commands_per_second: u32,
+    #[arg(
+        long,
+        value_name = "COUNT",
+        help = "Redis connections held at once; more are refused (0 for unlimited)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_REDIS_MAX_CONNECTIONS"
+    )]
+    pub redis_max_connections: usize,
+    #[arg(
+        long,
+        value_name = "COUNT",
+        help = "Redis connections held at once from one client IP (0 for unlimited)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_REDIS_MAX_CONNECTIONS_PER_IP"
+    )]
+    pub redis_max_connections_per_ip: usize,
+
+    // Multiplexed Transport
+    #[arg(
+        long,
+        help = "Serve HTTP, gRPC and Redis on one port, detecting the protocol per connection",
+        env = "THROTTLECRAB_MUX"
+    )]
+    pub mux: bool,
+    #[arg(
+        long,
+        value_name = "HOST",
+        help = "Multiplexed transport host",
+        default_value = "0.0.0.0",
+        env = "THROTTLECRAB_MUX_HOST"
+    )]
+    pub mux_host: String,
+    #[arg(
+        long,
+        value_name = "PORT",
+        help = "Multiplexed transport port",
+        default_value_t = 8000,
+        env = "THROTTLECRAB_MUX_PORT"
+    )]
+    pub mux_port: u16,
+
+    // UDP Transport
+    #[arg(
+        long,
+        help = "Answer throttle requests in JSON datagrams over UDP",
+        env = "THROTTLECRAB_UDP"
+    )]
+    pub udp: bool,
+    #[arg(
+        long,
+        value_name = "HOST",
+        help = "UDP transport host",
+        default_value = "0.0.0.0",
+        env = "THROTTLECRAB_UDP_HOST"
+    )]
+    pub udp_host: String,
+    #[arg(
+        long,
+        value_name = "PORT",
+        help = "UDP transport port",
+        default_value_t = 8089,
+        env = "THROTTLECRAB_UDP_PORT"
+    )]
+    pub udp_port: u16,
+
+    // Store Configuration
+    #[arg(
+        long,
+        value_name = "TYPE",
+        help = "Store type: periodic, probabilistic, adaptive, timingwheel, auto, redis",
+        default_value = "periodic",
+        env = "THROTTLECRAB_STORE"
+    )]
+    pub store: StoreType,
+    #[arg(
+        long,
+        value_name = "SIZE",
+        help = "Initial store capacity",
+        default_value_t = 100_000,
+        env = "THROTTLECRAB_STORE_CAPACITY"
+    )]
+    pub store_capacity: usize,
+
+    // Store-specific options
+    #[arg(
+        long,
+        value_name = "SECS",
+        help = "Cleanup interval for periodic store (seconds)",
+        default_value_t = 300,
+        env = "THROTTLECRAB_STORE_CLEANUP_INTERVAL"
+    )]
+    pub store_cleanup_interval: u64,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Cleanup probability for probabilistic store (1 in N)",
+        default_value_t = 10_000,
+        env = "THROTTLECRAB_STORE_CLEANUP_PROBABILITY"
+    )]
+    pub store_cleanup_probability: u64,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Most expired keys the timingwheel store removes per operation",
+        default_value_t = 128,
+        env = "THROTTLECRAB_STORE_CLEANUP_BATCH"
+    )]
+    pub store_cleanup_batch: usize,
+    #[arg(
+        long,
+        value_name = "SECS",
+        help = "Minimum cleanup interval for adaptive store (seconds)",
+        default_value_t = 5,
+        env = "THROTTLECRAB_STORE_MIN_INTERVAL"
+    )]
+    pub store_min_interval: u64,
+    #[arg(
+        long,
+        value_name = "SECS",
+        help = "Maximum cleanup interval for adaptive store (seconds)",
+        default_value_t = 300,
+        env = "THROTTLECRAB_STORE_MAX_INTERVAL"
+    )]
+    pub store_max_interval: u64,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Maximum operations before cleanup for adaptive store",
+        default_value_t = 1_000_000,
+        env = "THROTTLECRAB_STORE_MAX_OPERATIONS"
+    )]
+    pub store_max_operations: usize,
+    #[arg(
+        long,
+        value_name = "MICROS",
+        help = "Slowest acceptable request before the auto store migrates (microseconds)",
+        default_value_t = 1_000,
+        env = "THROTTLECRAB_STORE_LATENCY_BUDGET_US"
+    )]
+    pub store_latency_budget_us: u64,
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "Redis URL for the redis store, e.g. redis://127.0.0.1:6379/0",
+        env = "THROTTLECRAB_STORE_REDIS_URL"
+    )]
+    pub store_redis_url: Option<String>,
+    #[arg(
+        long,
+        value_name = "SECS",
+        help = "Cap entry TTLs at this many seconds (0 for unlimited)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_STORE_MAX_TTL"
+    )]
+    pub store_max_ttl: u64,
+    #[arg(
+        long,
+        value_name = "FACTOR",
+        help = "Multiply entry TTLs by this factor before applying the cap",
+        default_value_t = 1.0,
+        env = "THROTTLECRAB_STORE_TTL_MULTIPLIER"
+    )]
+    pub store_ttl_multiplier: f64,
+    #[arg(
+        long,
+        value_name = "N",
+        visible_alias = "store-max-keys",
+        help = "Maximum number of keys in the store (0 for unlimited)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_MAX_KEYS"
+    )]
+    pub max_keys: usize,
+    #[arg(
+        long,
+        value_name = "MB",
+        help = "Memory budget for store entries in MiB (0 for unlimited)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_STORE_MAX_MEMORY_MB"
+    )]
+    pub store_max_memory_mb: usize,
+    #[arg(
+        long,
+        value_name = "POLICY",
+        help = "What to do with new keys when --max-keys or --store-max-memory-mb is reached: reject, evict-lru, degrade",
+        default_value = "reject",
+        env = "THROTTLECRAB_ON_FULL"
+    )]
+    pub on_full: OnFull,
+    #[arg(
+        long,
+        value_name = "CLOCK",
+        help = "Time source: monotonic (immune to wall clock steps), system",
+        default_value = "monotonic",
+        env = "THROTTLECRAB_CLOCK"
+    )]
+    pub clock: ClockType,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Partition keys across N rate limiter actors to use N cores",
+        default_value_t = 1,
+        env = "THROTTLECRAB_SHARDS"
+    )]
+    pub shards: usize,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Split each store's table into N hash maps that grow and are cleaned up one at a time",
+        default_value_t = 1,
+        env = "THROTTLECRAB_STORE_MAP_SHARDS"
+    )]
+    pub store_map_shards: usize,
+    #[arg(
+        long,
+        value_name = "MICROS",
+        help = "Collect throttle requests for this long and decide identical ones together (0 to disable)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_COALESCE_WINDOW_US"
+    )]
+    pub coalesce_window_us: u64,
+    #[arg(
+        long,
+        value_name = "POLICY",
+        help = "What throttle requests do when the actor queue is full: block, fail-fast, shed-oldest",
+        default_value = "block",
+        env = "THROTTLECRAB_BACKPRESSURE"
+    )]
+    pub backpressure: Backpressure,
+    #[arg(
+        long,
+        value_name = "MS",
+        help = "Answer identical throttle requests from the last decision for this long (0 to disable)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_MICRO_CACHE_MS"
+    )]
+    pub micro_cache_ms: u64,
+    #[arg(
+        long,
+        value_name = "SECS",
+        help = "Log store entry counts and estimated size this often (0 to disable)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_STORE_STATS_INTERVAL"
+    )]
+    pub store_stats_interval: u64,
+    #[arg(
+        long,
+        value_name = "COUNT",
+        help = "Most requested keys each actor counts for GET /admin/keys/hot (0 to disable)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_HOT_KEYS"
+    )]
+    pub hot_keys: usize,
+    #[arg(
+        long = "namespace-quota",
+        value_name = "NAME=KEYS",
+        help = "Most keys the namespace NAME may hold in the store (repeatable)",
+        value_delimiter = ',',
+        value_parser = parse_namespace_quota,
+        env = "THROTTLECRAB_NAMESPACE_QUOTAS"
+    )]
+    pub namespace_quotas: Vec<(String, usize)>,
+
+    // Write-ahead log
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Persist store state to a write-ahead log at this path",
+        env = "THROTTLECRAB_WAL_PATH"
+    )]
+    pub wal_path: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "POLICY",
+        help = "WAL sync policy: always, interval, never",
+        default_value = "interval",
+        env = "THROTTLECRAB_WAL_FSYNC"
+    )]
+    pub wal_fsync: WalFsync,
+    #[arg(
+        long,
+        value_name = "MS",
+        help = "WAL sync interval for the interval policy (milliseconds)",
+        default_value_t = 1000,
+        env = "THROTTLECRAB_WAL_FSYNC_INTERVAL_MS"
+    )]
+    pub wal_fsync_interval_ms: u64,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "WAL size that triggers compaction",
+        default_value_t = 64 * 1024 * 1024,
+        env = "THROTTLECRAB_WAL_COMPACT_BYTES"
+    )]
+    pub wal_compact_bytes: u64,
+
+    // Snapshots
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Persist store state to a snapshot at this path, written periodically and on shutdown",
+        env = "THROTTLECRAB_SNAPSHOT_PATH"
+    )]
+    pub snapshot_path: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "SECS",
+        help = "Time between snapshots (seconds)",
+        default_value_t = 60,
+        env = "THROTTLECRAB_SNAPSHOT_INTERVAL"
+    )]
+    pub snapshot_interval: u64,
+
+    // Canary store
+    #[arg(
+        long,
+        value_name = "TYPE",
+        help = "Mirror a fraction of keys to a second store of this type and compare decisions",
+        env = "THROTTLECRAB_CANARY_STORE"
+    )]
+    pub canary_store: Option<StoreType>,
+    #[arg(
+        long,
+        value_name = "FRACTION",
+        help = "Fraction of keys mirrored to the canary store (0.0-1.0)",
+        default_value_t = 0.01,
+        env = "THROTTLECRAB_CANARY_FRACTION"
+    )]
+    pub canary_fraction: f64,
+
+    // Decision event export
+    #[arg(
+        long,
+        value_name = "SINK",
+        help = "Export rate limit decisions to a broker: nats, kafka",
+        env = "THROTTLECRAB_EVENTS_SINK"
+    )]
+    pub events_sink: Option<EventSinkType>,
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "Events broker address (NATS URL or comma-separated Kafka brokers)",
+        env = "THROTTLECRAB_EVENTS_URL"
+    )]
+    pub events_url: Option<String>,
+    #[arg(
+        long,
+        value_name = "TOPIC",
+        help = "NATS subject or Kafka topic for decision events",
+        default_value = "throttlecrab.decisions",
+        env = "THROTTLECRAB_EVENTS_TOPIC"
+    )]
+    pub events_topic: String,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        help = "Decision event format: json, protobuf",
+        default_value = "json",
+        env = "THROTTLECRAB_EVENTS_FORMAT"
+    )]
+    pub events_format: EventFormat,
+    #[arg(
+        long,
+        value_name = "RATE",
+        help = "Fraction of decisions to export (0.0 to 1.0)",
+        default_value_t = 1.0,
+        env = "THROTTLECRAB_EVENTS_SAMPLE_RATE"
+    )]
+    pub events_sample_rate: f64,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Maximum number of decision events per batch",
+        default_value_t = 100,
+        env = "THROTTLECRAB_EVENTS_BATCH_SIZE"
+    )]
+    pub events_batch_size: usize,
+    #[arg(
+        long,
+        value_name = "MS",
+        help = "Maximum time to hold a partial batch of decision events (milliseconds)",
+        default_value_t = 1000,
+        env = "THROTTLECRAB_EVENTS_FLUSH_INTERVAL_MS"
+    )]
+    pub events_flush_interval_ms: u64,
+    #[arg(
+        long,
+        value_name = "SIZE",
+        help = "Decision events buffered before new events are dropped",
+        default_value_t = 10_000,
+        env = "THROTTLECRAB_EVENTS_BUFFER_SIZE"
+    )]
+    pub events_buffer_size: usize,
+
+    // Denial alerts
+    #[arg(
+        long,
+        value_name = "COUNT",
+        help = "Alert when a key is denied this many times within --denial-window (0 to disable)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_DENIAL_THRESHOLD"
+    )]
+    pub denial_threshold: u64,
+    #[arg(
+        long,
+        value_name = "SECS",
+        help = "Window denials are counted in for --denial-threshold (seconds)",
+        default_value_t = 60,
+        env = "THROTTLECRAB_DENIAL_WINDOW"
+    )]
+    pub denial_window: u64,
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "POST denial alerts to this URL (requires the webhook feature)",
+        env = "THROTTLECRAB_DENIAL_WEBHOOK"
+    )]
+    pub denial_webhook: Option<String>,
+
+    // Metrics listener
+    #[arg(
+        long,
+        value_name = "PORT",
+        help = "Serve GET /metrics on a dedicated port",
+        env = "THROTTLECRAB_METRICS_PORT"
+    )]
+    pub metrics_port: Option<u16>,
+    #[arg(
+        long,
+        value_name = "HOST",
+        help = "Metrics listener host",
+        default_value = "0.0.0.0",
+        env = "THROTTLECRAB_METRICS_HOST"
+    )]
+    pub metrics_host: String,
+
+    // Admin listener
+    #[arg(
+        long,
+        value_name = "PORT",
+        help = "Serve the /admin endpoints on a dedicated port instead of the HTTP transport",
+        env = "THROTTLECRAB_ADMIN_PORT"
+    )]
+    pub admin_port: Option<u16>,
+    #[arg(
+        long,
+        value_name = "HOST",
+        help = "Admin listener host",
+        default_value = "127.0.0.1",
+        env = "THROTTLECRAB_ADMIN_HOST"
+    )]
+    pub admin_host: String,
+    #[arg(
+        long,
+        value_name = "SECRET",
+        help = "Bearer token required to change key overrides on the admin listener",
+        env = "THROTTLECRAB_ADMIN_TOKEN"
+    )]
+    pub admin_token: Option<String>,
+
+    // Metrics push
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "POST metrics snapshots to this URL instead of waiting to be scraped",
+        env = "THROTTLECRAB_METRICS_PUSH_URL"
+    )]
+    pub metrics_push_url: Option<String>,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        help = "Metrics push format: json, remote-write",
+        default_value = "json",
+        env = "THROTTLECRAB_METRICS_PUSH_FORMAT"
+    )]
+    pub metrics_push_format: MetricsPushFormat,
+    #[arg(
+        long,
+        value_name = "SECS",
+        help = "Time between metrics pushes (seconds, jittered by 10%)",
+        default_value_t = 15,
+        env = "THROTTLECRAB_METRICS_PUSH_INTERVAL"
+    )]
+    pub metrics_push_interval: u64,
+    #[arg(
+        long,
+        value_name = "TOKEN",
+        help = "Bearer token for the metrics push endpoint",
+        env = "THROTTLECRAB_METRICS_PUSH_TOKEN",
+        hide_env_values = true
+    )]
+    pub metrics_push_token: Option<String>,
+
+    // OpenTelemetry
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "Export request spans to this OTLP/gRPC collector",
+        env = "THROTTLECRAB_OTLP_ENDPOINT"
+    )]
+    pub otlp_endpoint: Option<String>,
+    #[arg(
+        long,
+        value_name = "RATIO",
+        help = "Fraction of traces to sample when the caller did not decide (0.0 to 1.0)",
+        default_value_t = 1.0,
+        env = "THROTTLECRAB_OTLP_SAMPLE_RATIO"
+    )]
+    pub otlp_sample_ratio: f64,
+
+    // Policies
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Load named rate limit policies from this TOML file",
+        env = "THROTTLECRAB_POLICIES"
+    )]
+    pub policies: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Load the key deny- and allow-lists from this TOML file and save changes to it",
+        env = "THROTTLECRAB_OVERRIDES_FILE"
+    )]
+    pub overrides_file: Option<PathBuf>,
+
+    // Cluster
+    #[arg(
+        long,
+        value_name = "PORT",
+        help = "Replicate rate limit state with cluster peers, listening for them on this port",
+        env = "THROTTLECRAB_CLUSTER_PORT"
+    )]
+    pub cluster_port: Option<u16>,
+    #[arg(
+        long,
+        value_name = "HOST",
+        help = "Cluster listener host",
+        default_value = "0.0.0.0",
+        env = "THROTTLECRAB_CLUSTER_HOST"
+    )]
+    pub cluster_host: String,
+    #[arg(
+        long = "cluster-peer",
+        value_name = "HOST:PORT",
+        help = "Cluster peer to replicate with (repeatable)",
+        env = "THROTTLECRAB_CLUSTER_PEERS",
+        value_delimiter = ','
+    )]
+    pub cluster_peers: Vec<String>,
+    #[arg(
+        long,
+        value_name = "NAME:PORT",
+        help = "Discover cluster peers from the DNS records of NAME",
+        env = "THROTTLECRAB_CLUSTER_DNS"
+    )]
+    pub cluster_dns: Option<String>,
+    #[arg(
+        long,
+        value_name = "MS",
+        help = "Time between cluster syncs (milliseconds)",
+        default_value_t = 100,
+        env = "THROTTLECRAB_CLUSTER_SYNC_INTERVAL_MS"
+    )]
+    pub cluster_sync_interval_ms: u64,
+
+    // Authentication
+    #[arg(
+        long = "api-key",
+        value_name = "NAME=SECRET",
+        help = "Require throttle requests to carry this API key (repeatable)",
+        value_delimiter = ',',
+        env = "THROTTLECRAB_API_KEYS"
+    )]
+    pub api_keys: Vec<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Load API keys from this TOML file of name = \"secret\" pairs",
+        env = "THROTTLECRAB_API_KEYS_FILE"
+    )]
+    pub api_keys_file: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Put requests that name no namespace in their API key's namespace",
+        env = "THROTTLECRAB_API_KEY_NAMESPACES"
+    )]
+    pub api_key_namespaces: bool,
+    #[arg(
+        long = "api-key-budget",
+        value_name = "NAME=COUNT/SECS",
+        help = "Allow the API key NAME (* for the rest) at most COUNT throttle checks per SECS seconds (repeatable)",
+        value_delimiter = ',',
+        env = "THROTTLECRAB_API_KEY_BUDGETS"
+    )]
+    pub api_key_budgets: Vec<String>,
+
+    // Self-probing
+    #[arg(
+        long,
+        value_name = "SECS",
+        help = "Probe each transport over loopback at this interval (seconds, 0 to disable)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_PROBE_INTERVAL"
+    )]
+    pub probe_interval: u64,
+    #[arg(
+        long,
+        value_name = "KEY",
+        help = "Synthetic key used by the self-probes",
+        default_value = "__throttlecrab_probe",
+        env = "THROTTLECRAB_PROBE_KEY"
+    )]
+    pub probe_key: String,
+
+    // General options
+    #[arg(
+        long,
+        value_name = "SIZE",
+        help = "Channel buffer size",
+        default_value_t = 100_000,
+        env = "THROTTLECRAB_BUFFER_SIZE"
+    )]
+    pub buffer_size: usize,
+    #[arg(
+        long,
+        value_name = "COUNT",
+        help = "Maximum number of denied keys to track in metrics (0 to disable, max: 10000)",
+        default_value_t = 100,
+        env = "THROTTLECRAB_MAX_DENIED_KEYS",
+        value_parser = clap::value_parser!(u32).range(0..=10000)
+    )]
+    pub max_denied_keys: u32,
+    #[arg(
+        long,
+        value_name = "COUNT",
+        help = "Top requested and allowed keys to track in metrics (0 to disable, max: 10000)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_TOP_KEYS",
+        value_parser = clap::value_parser!(u32).range(0..=10000)
+    )]
+    pub top_keys: u32,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Count one request in N towards --top-keys",
+        default_value_t = 100,
+        env = "THROTTLECRAB_TOP_KEYS_SAMPLE_RATE"
+    )]
+    pub top_keys_sample_rate: u64,
+    #[arg(
+        long,
+        value_name = "COUNT",
+        help = "Recent requests to keep for GET /admin/trace (0 to disable)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_TRACE_BUFFER_SIZE"
+    )]
+    pub trace_buffer_size: usize,
+    #[arg(
+        long,
+        value_name = "SECS",
+        help = "Log each transport's request latency quantiles this often (0 to disable)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_LATENCY_LOG_INTERVAL"
+    )]
+    pub latency_log_interval: u64,
+    #[arg(
+        long,
+        value_name = "RPS",
+        help = "Throttle checks per second before requests are shed (0 to disable)",
+        default_value_t = 0,
+        env = "THROTTLECRAB_MAX_RPS"
+    )]
+    pub max_rps: u64,
+    #[arg(
+        long,
+        value_name = "DECISION",
+        help = "Decision for requests shed by --max-rps: deny, allow",
+        default_value = "deny",
+        env = "THROTTLECRAB_SHED_DECISION"
+    )]
+    pub shed_decision: ShedDecision,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Reject keys longer than this (default: 1024)",
+        env = "THROTTLECRAB_MAX_KEY_LENGTH"
+    )]
+    pub max_key_length: Option<usize>,
+    #[arg(
+        long,
+        value_name = "SECS",
+        help = "Reject requests with a longer period",
+        env = "THROTTLECRAB_MAX_PERIOD"
+    )]
+    pub max_period: Option<i64>,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Reject requests with a larger quantity",
+        env = "THROTTLECRAB_MAX_QUANTITY"
+    )]
+    pub max_quantity: Option<i64>,
+    #[arg(
+        long,
+        value_name = "SET",
+        help = "Reject keys with characters outside this set, e.g. a-zA-Z0-9:_.-",
+        env = "THROTTLECRAB_KEY_CHARS"
+    )]
+    pub key_chars: Option<String>,
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        help = "Log level: error, warn, info, debug, trace",
+        default_value = "info",
+        env = "THROTTLECRAB_LOG_LEVEL"
+    )]
+    pub log_level: String,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        help = "Log format: text, json",
+        default_value = "text",
+        env = "THROTTLECRAB_LOG_FORMAT"
+    )]
+    pub log_format: LogFormat,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write the startup status as JSON to this file once the server is running",
+        env = "THROTTLECRAB_STATUS_FILE"
+    )]
+    pub status_file: Option<PathBuf>,
+
+    // Utility options
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Read options from this TOML file; environment variables and arguments override it",
+        env = "THROTTLECRAB_CONFIG"
+    )]
+    pub config: Option<PathBuf>,
+    /// Table of `policies` holding inline policies, set by [`Args::load`]
+    #[arg(skip)]
+    pub policies_table: Option<String>,
+    #[arg(
+        long,
+        help = "List all environment variables and exit",
+        action = clap::ArgAction::SetTrue
+    )]
+    pub list_env_vars: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Alternative modes of the binary
+///
+/// Without a subcommand the binary runs the server.
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// Explore rate limits interactively with an in-process store and a virtual clock
+    Repl,
+}
+
+impl Args {
+    /// Parse the CLI arguments, layered over the `--config` file if given
+    ///
+    /// The file's values become the defaults, so environment variables and
+    /// arguments override them. Like [`Parser::parse`], exits on invalid
+    /// arguments. See [`crate::config_file`] for the format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or holds an unknown
+    /// option or an invalid value.
+    pub fn load() -> Result<Self> {
+        let args = Args::parse();
+        let Some(path) = args.config.clone() else {
+            return Ok(args);
+        };
+        let (command, inline_policies) = config_file::layer(&path, Args::command())?;
+        let mut args =
+            Args::from_arg_matches(&command.get_matches()).unwrap_or_else(|error| error.exit());
+        // A policy file given by argument or environment wins
+        if inline_policies && args.policies.is_none() {
+            args.policies = Some(path);
+            args.policies_table = Some(config_file::POLICIES_TABLE.to_string());
+        }
+        Ok(args)
+    }
+
+    /// Store configuration from the store flags
+    pub fn store_config(&self) -> StoreConfig {
+        StoreConfig {
+            store_type: self.store,
+            capacity: self.store_capacity,
+            cleanup_interval: self.store_cleanup_interval,
+            cleanup_probability: self.store_cleanup_probability,
+            cleanup_batch: self.store_cleanup_batch,
+            min_interval: self.store_min_interval,
+            max_interval: self.store_max_interval,
+            max_operations: self.store_max_operations,
+            latency_budget_us: self.store_latency_budget_us,
+            max_ttl: self.store_max_ttl,
+            ttl_multiplier: self.store_ttl_multiplier,
+            max_keys: self.max_keys,
+            max_memory_mb: self.store_max_memory_mb,
+            on_full: self.on_full,
+            clock: self.clock,
+            wal: self.wal_path.clone().map(|path| WalConfig {
+                path,
+                fsync: self.wal_fsync,
+                fsync_interval: Duration::from_millis(self.wal_fsync_interval_ms),
+                compact_bytes: self.wal_compact_bytes,
+            }),
+            snapshot: self.snapshot_path.clone().map(|path| SnapshotConfig {
+                path,
+                interval: Duration::from_secs(self.snapshot_interval),
+            }),
+            canary: self.canary_store.map(|store_type| CanaryConfig {
+                store_type,
+                fraction: self.canary_fraction,
+            }),
+            shards: self.shards,
+            map_shards: self.store_map_shards,
+            redis_url: self.store_redis_url.clone(),
+            coalesce_window_us: self.coalesce_window_us,
+            backpressure: self.backpressure,
+            micro_cache_ms: self.micro_cache_ms,
+            stats_log_interval: self.store_stats_interval,
+            hot_keys: self.hot_keys,
+            namespace_quotas: self.namespace_quotas.iter().cloned().collect(),
+        }
+    }
+}
+
+/// Parse a `--namespace-quota` of the form `NAME=KEYS`
+fn parse_namespace_quota(value: &str) -> Result<(String, usize), String> {
+    let (name, keys) = value
+        .split_once('=')
+        .ok_or_else(|| "expected NAME=KEYS".to_string())?;
+    let keys = keys
+        .parse()
+        .map_err(|_| format!("invalid key count: {keys}"))?;
+    Ok((name.to_string(), keys))
+}
+
+impl Config {
+    /// Build configuration from environment variables and CLI arguments
+    ///
+    /// This method:
+    /// 1. Parses CLI arguments (with env var fallback via clap)
+    /// 2. Handles special flags like --list-env-vars
+    /// 3. Builds the configuration structure
+    /// 4. Validates the configuration
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No transport is specified
+    /// - Invalid configuration values are provided
+    pub fn from_env_and_args() -> Result<Self> {
+        // Clap automatically handles environment variables with the precedence:
+        // 1. CLI arguments (highest priority)
+        // 2. Environment variables
+        // 3. Configuration file
+        // 4. Default values (lowest priority)
+        Self::from_args(Args::load()?)
+    }
+
+    /// Build configuration from already parsed arguments
+    ///
+    /// Same as [`Config::from_env_and_args`], for callers that need to
+    /// inspect the arguments first, e.g. to dispatch on [`Args::command`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no transport is specified or invalid
+    /// configuration values are provided.
+    pub fn from_args(args: Args) -> Result<Self> {
+        // Handle --list-env-vars
+        if args.list_env_vars {
+            Self::print_env_vars();
+            std::process::exit(0);
+        }
+
+        // `--bind-any` replaces every listener's host but the admin one's,
+        // which stays private
+        let bind_any = args.bind_any;
+        let host = |host: String| {
+            if bind_any {
+                BIND_ANY_HOST.to_string()
+            } else {
+                host
+            }
+        };
+
+        // Build config from parsed args (which already include env vars)
+        let mut config = Config {
+            transports: TransportConfig {
                 http: None,
                 grpc: None,
                 redis: None,
+                mux: None,
+                udp: None,
+            },
+            store: args.store_config(),
+            buffer_size: args.buffer_size,
+            max_denied_keys: args.max_denied_keys,
+            top_keys: (args.top_keys > 0).then_some(TopKeysConfig {
+                count: args.top_keys as usize,
+                sample_rate: args.top_keys_sample_rate,
+            }),
+            trace_buffer_size: args.trace_buffer_size,
+            latency_log_interval: args.latency_log_interval,
+            load_shedding: (args.max_rps > 0).then_some(LoadSheddingConfig {
+                max_rps: args.max_rps,
+                decision: args.shed_decision,
+            }),
+            validation: (args.max_key_length.is_some()
+                || args.max_period.is_some()
+                || args.max_quantity.is_some()
+                || args.key_chars.is_some())
+            .then(|| ValidationConfig {
+                max_key_length: args.max_key_length,
+                max_period: args.max_period,
+                max_quantity: args.max_quantity,
+                key_chars: args.key_chars.clone(),
+            }),
+            events: args.events_sink.map(|sink| EventsConfig {
+                sink,
+                url: args.events_url.unwrap_or_default(),
+                topic: args.events_topic,
+                format: args.events_format,
+                sample_rate: args.events_sample_rate,
+                batch_size: args.events_batch_size,
+                flush_interval_ms: args.events_flush_interval_ms,
+                buffer_size: args.events_buffer_size,
+            }),
+            denial_alerts: (args.denial_threshold > 0).then(|| DenialAlertsConfig {
+                threshold: args.denial_threshold,
+                window: args.denial_window,
+                webhook: args.denial_webhook.clone(),
+            }),
+            metrics_push: args.metrics_push_url.map(|url| MetricsPushConfig {
+                url,
+                format: args.metrics_push_format,
+                interval: args.metrics_push_interval,
+                token: args.metrics_push_token,
+            }),
+            otlp: args.otlp_endpoint.map(|endpoint| OtlpConfig {
+                endpoint,
+                sample_ratio: args.otlp_sample_ratio,
+            }),
+            probe: (args.probe_interval > 0).then_some(ProbeConfig {
+                interval: args.probe_interval,
+                key: args.probe_key,
+            }),
+            metrics_listener: args.metrics_port.map(|port| MetricsListenerConfig {
+                host: host(args.metrics_host),
+                port,
+            }),
+            admin_listener: args.admin_port.map(|port| AdminListenerConfig {
+                host: args.admin_host,
+                port,
+                token: args.admin_token.clone(),
+            }),
+            policies: args.policies,
+            policies_table: args.policies_table,
+            overrides_file: args.overrides_file,
+            cluster: args.cluster_port.map(|port| ClusterConfig {
+                host: args.cluster_host,
+                port,
+                peers: args.cluster_peers,
+                dns: args.cluster_dns,
+                sync_interval_ms: args.cluster_sync_interval_ms,
+            }),
+            api_keys: None,
+            status_file: args.status_file,
+            log_level: args.log_level,
+            log_format: args.log_format,
+        };
+
+        if !args.api_keys.is_empty() || args.api_keys_file.is_some() {
+            let keys = args
+                .api_keys
+                .iter()
+                .map(|key| {
+                    key.split_once('=')
+                        .map(|(name, secret)| (name.to_string(), secret.to_string()))
+                        .ok_or_else(|| anyhow!("--api-key must be NAME=SECRET"))
+                })
+                .collect::<Result<_>>()?;
+            config.api_keys = Some(ApiKeysConfig {
+                keys,
+                file: args.api_keys_file,
+                namespaces: args.api_key_namespaces,
+                budgets: args
+                    .api_key_budgets
+                    .iter()
+                    .map(|budget| budget.parse())
+                    .collect::<Result<_>>()?,
+            });
+        }
+
+        if args.api_key_namespaces && config.api_keys.is_none() {
+            return Err(anyhow!(
+                "--api-key-namespaces requires --api-key or --api-keys-file"
+            ));
+        }
+        if !args.api_key_budgets.is_empty() && config.api_keys.is_none() {
+            return Err(anyhow!(
+                "--api-key-budget requires --api-key or --api-keys-file"
+            ));
+        }
+        if args.admin_token.is_some() && config.admin_listener.is_none() {
+            return Err(anyhow!("--admin-token requires --admin-port"));
+        }
+
+        // Configure transports based on parsed args
+        let http_routes = HttpRoutes {
+            base_path: args.http_base_path,
+            throttle: args.http_throttle_path,
+            metrics: args.http_metrics_path,
+            health: args.http_health_path,
+        };
+
+        let http_tls = match (args.http_tls_cert, args.http_tls_key) {
+            (Some(cert), Some(key)) => Some(TlsConfig {
+                cert,
+                key,
+                client_ca: args.http_tls_client_ca,
+            }),
+            (None, None) if args.http_tls_client_ca.is_some() => {
+                return Err(anyhow!(
+                    "--http-tls-client-ca requires --http-tls-cert and --http-tls-key"
+                ));
+            }
+            (None, None) => None,
+            _ => {
+                return Err(anyhow!(
+                    "--http-tls-cert and --http-tls-key must be given together"
+                ));
+            }
+        };
+
+        if args.http {
+            config.transports.http = Some(HttpConfig {
+                host: host(args.http_host),
+                port: args.http_port,
+                routes: http_routes.clone(),
+                tls: http_tls,
+                use_429: args.http_use_429,
+                keep_alive: HttpKeepAlive {
+                    max_requests: args.http_keep_alive_max_requests,
+                    idle_timeout: (args.http_keep_alive_timeout > 0)
+                        .then(|| Duration::from_secs(args.http_keep_alive_timeout)),
+                },
+                compression: args.http_compression,
+            });
+        }
+
+        if args.grpc {
+            config.transports.grpc = Some(GrpcConfig {
+                host: host(args.grpc_host),
+                port: args.grpc_port,
+            });
+        }
+
+        if args.redis {
+            config.transports.redis = Some(RedisConfig {
+                host: host(args.redis_host),
+                port: args.redis_port,
+                password: args.redis_password.clone(),
+                max_commands_per_second: args.redis_max_commands_per_second,
+                max_connections: args.redis_max_connections,
+                max_connections_per_ip: args.redis_max_connections_per_ip,
+            });
+        }
+
+        if args.mux {
+            config.transports.mux = Some(MuxConfig {
+                host: host(args.mux_host),
+                port: args.mux_port,
+                routes: http_routes,
+                use_429: args.http_use_429,
+                password: args.redis_password,
+                max_commands_per_second: args.redis_max_commands_per_second,
+            });
+        }
+
+        if args.udp {
+            config.transports.udp = Some(UdpConfig {
+                host: host(args.udp_host),
+                port: args.udp_port,
+            });
+        }
+
+        // Validate configuration
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Check if at least one transport is configured
+    ///
+    /// The server requires at least one transport to be functional.
+    pub fn has_any_transport(&self) -> bool {
+        self.transports.http.is_some()
+            || self.transports.grpc.is_some()
+            || self.transports.redis.is_some()
+            || self.transports.mux.is_some()
+            || self.transports.udp.is_some()
+    }
+
+    /// Validate the configuration
+    ///
+    /// Currently checks that at least one transport is enabled.
+    /// Additional validation can be added here in the future.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration is invalid.
+    pub(crate) fn validate(&self) -> Result<()> {
+        if !self.has_any_transport() {
+            return Err(anyhow!(
+                "At least one transport must be specified.\n\n\
+                Available transports:\n  \
+                --http       Enable HTTP transport\n  \
+                --grpc       Enable gRPC transport\n  \
+                --redis      Enable Redis protocol transport\n  \
+                --mux        Serve HTTP, gRPC and Redis on one port\n  \
+                --udp        Enable UDP transport\n  \
+                Example:\n  \
+                throttlecrab-server --http --http-port 7070\n  \
+                throttlecrab-server --http --grpc --redis\n\n\
+                For more information, try '--help'"
+            ));
+        }
+
+        if let Some(redis) = &self.transports.redis
+            && redis.password.as_deref() == Some("")
+        {
+            return Err(anyhow!("--redis-password must not be empty"));
+        }
+        if let Some(admin) = &self.admin_listener
+            && admin.token.as_deref() == Some("")
+        {
+            return Err(anyhow!("--admin-token must not be empty"));
+        }
+
+        if let Some(http) = &self.transports.http {
+            http.routes.validate()?;
+        }
+
+        let t = &self.transports;
+        let listeners = [
+            ("--http-host", t.http.as_ref().map(|c| (&c.host, c.port))),
+            ("--grpc-host", t.grpc.as_ref().map(|c| (&c.host, c.port))),
+            ("--redis-host", t.redis.as_ref().map(|c| (&c.host, c.port))),
+            ("--mux-host", t.mux.as_ref().map(|c| (&c.host, c.port))),
+            ("--udp-host", t.udp.as_ref().map(|c| (&c.host, c.port))),
+            (
+                "--metrics-host",
+                self.metrics_listener.as_ref().map(|c| (&c.host, c.port)),
+            ),
+            (
+                "--admin-host",
+                self.admin_listener.as_ref().map(|c| (&c.host, c.port)),
+            ),
+        ];
+        for (flag, listener) in listeners {
+            if let Some((host, port)) = listener {
+                crate::transport::socket_addr(host, port)
+                    .map_err(|e| anyhow!("{flag} must be an IP address: {e}"))?;
+            }
+        }
+
+        if let Some(api_keys) = &self.api_keys {
+            // The file is read at startup; check the keys given directly now
+            let keys = crate::auth::ApiKeys::new(api_keys.keys.iter().cloned())
+                .and_then(|keys| keys.with_namespaces(api_keys.namespaces))
+                .map_err(|e| anyhow!("--api-key: {e}"))?;
+            // Budgets may name keys from the file, so check them with it
+            if api_keys.file.is_none() {
+                crate::budget::ApiKeyBudgets::new(&api_keys.budgets, &keys)
+                    .map_err(|e| anyhow!("--api-key-budget: {e}"))?;
+            }
+            // A datagram's source can be forged, so UDP has no way to authenticate
+            if self.transports.udp.is_some() {
+                return Err(anyhow!("--udp cannot be combined with --api-key"));
+            }
+        }
+
+        for (name, &keys) in &self.store.namespace_quotas {
+            crate::namespace::validate(name).map_err(|e| anyhow!("--namespace-quota: {e}"))?;
+            if keys == 0 {
+                return Err(anyhow!(
+                    "--namespace-quota for {name} must be greater than 0"
+                ));
+            }
+        }
+
+        if let Some(mux) = &self.transports.mux {
+            if mux.password.as_deref() == Some("") {
+                return Err(anyhow!("--redis-password must not be empty"));
+            }
+            mux.routes.validate()?;
+            let transports = &self.transports;
+            let ports = [
+                transports.http.as_ref().map(|http| http.port),
+                transports.grpc.as_ref().map(|grpc| grpc.port),
+                transports.redis.as_ref().map(|redis| redis.port),
+            ];
+            if ports.contains(&Some(mux.port)) {
+                return Err(anyhow!(
+                    "--mux-port {} is already used by another transport",
+                    mux.port
+                ));
+            }
+        }
+
+        if !self.store.ttl_multiplier.is_finite() || self.store.ttl_multiplier <= 0.0 {
+            return Err(anyhow!(
+                "--store-ttl-multiplier must be greater than 0, got {}",
+                self.store.ttl_multiplier
+            ));
+        }
+
+        if let Some(canary) = &self.store.canary
+            && !(0.0..=1.0).contains(&canary.fraction)
+        {
+            return Err(anyhow!(
+                "--canary-fraction must be between 0.0 and 1.0, got {}",
+                canary.fraction
+            ));
+        }
+
+        if !(1..=MAX_SHARDS).contains(&self.store.shards) {
+            return Err(anyhow!(
+                "--shards must be between 1 and {}, got {}",
+                MAX_SHARDS,
+                self.store.shards
+            ));
+        }
+
+        if !(1..=MAX_SHARDS).contains(&self.store.map_shards) {
+            return Err(anyhow!(
+                "--store-map-shards must be between 1 and {}, got {}",
+                MAX_SHARDS,
+                self.store.map_shards
+            ));
+        }
+
+        if self.store.cleanup_batch == 0 {
+            return Err(anyhow!("--store-cleanup-batch must be at least 1"));
+        }
+
+        if self.store.shards > 1 && self.store.wal.is_some() {
+            return Err(anyhow!(
+                "--wal-path requires a single shard; use --snapshot-path with --shards"
+            ));
+        }
+
+        if let Some(snapshot) = &self.store.snapshot {
+            if self.store.wal.is_some() {
+                return Err(anyhow!(
+                    "--snapshot-path and --wal-path are alternatives; enable only one"
+                ));
+            }
+            if snapshot.interval.is_zero() {
+                return Err(anyhow!("--snapshot-interval must be greater than 0"));
+            }
+        }
+
+        if let Some(canary) = &self.store.canary
+            && canary.store_type == StoreType::Auto
+        {
+            return Err(anyhow!("--canary-store must not be auto"));
+        }
+
+        if self.store.store_type == StoreType::Redis {
+            if self.store.redis_url.is_none() {
+                return Err(anyhow!("--store-redis-url is required with --store redis"));
+            }
+            // The state lives in Redis, out of reach of the local store features
+            let unsupported = [
+                ("--wal-path", self.store.wal.is_some()),
+                ("--snapshot-path", self.store.snapshot.is_some()),
+                ("--max-keys", self.store.max_keys > 0),
+                ("--store-max-memory-mb", self.store.max_memory_mb > 0),
+                ("--namespace-quota", !self.store.namespace_quotas.is_empty()),
+                ("--cluster-port", self.cluster.is_some()),
+                // Like the canary, hot keys are counted by the actor
+                ("--hot-keys", self.store.hot_keys > 0),
+                // Redis decisions run outside the actor, which feeds the canary
+                ("--canary-store", self.store.canary.is_some()),
+            ];
+            if let Some((flag, _)) = unsupported.iter().find(|(_, set)| *set) {
+                return Err(anyhow!("{flag} is not supported with --store redis"));
+            }
+        }
+
+        if let Some(canary) = &self.store.canary
+            && canary.store_type == StoreType::Redis
+        {
+            return Err(anyhow!("--canary-store must not be redis"));
+        }
+
+        if self.store.store_type == StoreType::Auto && self.store.latency_budget_us == 0 {
+            return Err(anyhow!(
+                "--store-latency-budget-us must be greater than 0 with --store auto"
+            ));
+        }
+
+        if let Some(events) = &self.events {
+            if events.url.is_empty() {
+                return Err(anyhow!(
+                    "--events-url is required when --events-sink is set"
+                ));
+            }
+            if !(0.0..=1.0).contains(&events.sample_rate) {
+                return Err(anyhow!(
+                    "--events-sample-rate must be between 0.0 and 1.0, got {}",
+                    events.sample_rate
+                ));
+            }
+            if events.batch_size == 0 || events.buffer_size == 0 {
+                return Err(anyhow!(
+                    "--events-batch-size and --events-buffer-size must be greater than 0"
+                ));
+            }
+        }
+
+        if let Some(top_keys) = &self.top_keys {
+            if !(1..=10_000).contains(&top_keys.count) {
+                return Err(anyhow!(
+                    "--top-keys must be between 1 and 10000, got {}",
+                    top_keys.count
+                ));
+            }
+            if top_keys.sample_rate == 0 {
+                return Err(anyhow!("--top-keys-sample-rate must be greater than 0"));
+            }
+        }
+
+        if let Some(alerts) = &self.denial_alerts {
+            if alerts.threshold == 0 {
+                return Err(anyhow!("--denial-threshold must be greater than 0"));
+            }
+            if alerts.window == 0 {
+                return Err(anyhow!("--denial-window must be greater than 0"));
+            }
+            if let Some(url) = &alerts.webhook
+                && !url.starts_with("http://")
+                && !url.starts_with("https://")
+            {
+                return Err(anyhow!(
+                    "--denial-webhook must be an http:// or https:// URL, got {url}"
+                ));
+            }
+        }
+
+        if let Some(push) = &self.metrics_push {
+            if !push.url.starts_with("http://") && !push.url.starts_with("https://") {
+                return Err(anyhow!(
+                    "--metrics-push-url must be an http:// or https:// URL, got {}",
+                    push.url
+                ));
+            }
+            if push.interval == 0 {
+                return Err(anyhow!("--metrics-push-interval must be greater than 0"));
+            }
+            if push.token.as_deref() == Some("") {
+                return Err(anyhow!("--metrics-push-token must not be empty"));
+            }
+        }
+
+        if let Some(validation) = &self.validation {
+            if let Some(max) = validation.max_key_length
+                && !(1..=MAX_KEY_LENGTH).contains(&max)
+            {
+                return Err(anyhow!(
+                    "--max-key-length must be between 1 and {MAX_KEY_LENGTH}, got {max}"
+                ));
+            }
+            if let Some(max) = validation.max_period
+                && max <= 0
+            {
+                return Err(anyhow!("--max-period must be positive, got {max}"));
+            }
+            if let Some(max) = validation.max_quantity
+                && max <= 0
+            {
+                return Err(anyhow!("--max-quantity must be positive, got {max}"));
+            }
+            RequestLimits::new(validation)?;
+        }
+
+        if let Some(otlp) = &self.otlp {
+            if !otlp.endpoint.starts_with("http://") && !otlp.endpoint.starts_with("https://") {
+                return Err(anyhow!(
+                    "--otlp-endpoint must be an http:// or https:// URL, got {}",
+                    otlp.endpoint
+                ));
+            }
+            if !(0.0..=1.0).contains(&otlp.sample_ratio) {
+                return Err(anyhow!(
+                    "--otlp-sample-ratio must be between 0.0 and 1.0, got {}",
+                    otlp.sample_ratio
+                ));
+            }
+        }
+
+        if let Some(probe) = &self.probe {
+            if probe.interval == 0 {
+                return Err(anyhow!("--probe-interval must be greater than 0"));
+            }
+            if probe.key.is_empty() || probe.key.len() > MAX_KEY_LENGTH {
+                return Err(anyhow!(
+                    "--probe-key must be between 1 and {MAX_KEY_LENGTH} bytes"
+                ));
+            }
+        }
+
+        if let Some(listener) = &self.metrics_listener {
+            let transports = &self.transports;
+            let ports = [
+                transports.http.as_ref().map(|http| http.port),
+                transports.grpc.as_ref().map(|grpc| grpc.port),
+                transports.redis.as_ref().map(|redis| redis.port),
+                transports.mux.as_ref().map(|mux| mux.port),
+            ];
+            if ports.contains(&Some(listener.port)) {
+                return Err(anyhow!(
+                    "--metrics-port {} is already used by a transport",
+                    listener.port
+                ));
+            }
+        }
+
+        if let Some(listener) = &self.admin_listener {
+            let transports = &self.transports;
+            let ports = [
+                transports.http.as_ref().map(|http| http.port),
+                transports.grpc.as_ref().map(|grpc| grpc.port),
+                transports.redis.as_ref().map(|redis| redis.port),
+                transports.mux.as_ref().map(|mux| mux.port),
+                self.metrics_listener.as_ref().map(|listener| listener.port),
+            ];
+            if ports.contains(&Some(listener.port)) {
+                return Err(anyhow!(
+                    "--admin-port {} is already used by another listener",
+                    listener.port
+                ));
+            }
+        }
+
+        if let Some(cluster) = &self.cluster {
+            if cluster.peers.is_empty() && cluster.dns.is_none() {
+                return Err(anyhow!(
+                    "--cluster-port requires --cluster-peer or --cluster-dns"
+                ));
+            }
+            if cluster.sync_interval_ms == 0 {
+                return Err(anyhow!("--cluster-sync-interval-ms must be greater than 0"));
+            }
+            let transports = &self.transports;
+            let ports = [
+                transports.http.as_ref().map(|http| http.port),
+                transports.grpc.as_ref().map(|grpc| grpc.port),
+                transports.redis.as_ref().map(|redis| redis.port),
+                transports.mux.as_ref().map(|mux| mux.port),
+                self.metrics_listener.as_ref().map(|listener| listener.port),
+                self.admin_listener.as_ref().map(|listener| listener.port),
+            ];
+            if ports.contains(&Some(cluster.port)) {
+                return Err(anyhow!(
+                    "--cluster-port {} is already used by another listener",
+                    cluster.port
+                ));
+            }
+        }
+
+        // Additional validation could be added here in the future
+        // e.g., validate port ranges, check for conflicting options, etc.
+
+        Ok(())
+    }
+
+    /// Print all available environment variables and their descriptions
+    ///
+    /// This is called when the --list-env-vars flag is used.
+    /// It provides a comprehensive reference for all environment variables
+    /// that can be used to configure the server.
+    fn print_env_vars() {
+        println!("ThrottleCrab Environment Variables");
+        println!("==================================");
+        println!();
+        println!("All environment variables use the THROTTLECRAB_ prefix.");
+        println!("CLI arguments take precedence over environment variables.");
+        println!();
+
+        println!("Transport Configuration:");
+        println!(
+            "  THROTTLECRAB_BIND_ANY=true            Listen on :: (IPv4 and IPv6) on every transport"
+        );
+        println!("  THROTTLECRAB_HTTP=true|false          Enable HTTP transport");
+        println!("  THROTTLECRAB_HTTP_HOST=<host>         HTTP host [default: 0.0.0.0]");
+        println!("  THROTTLECRAB_HTTP_PORT=<port>         HTTP port [default: 8080]");
+        println!(
+            "  THROTTLECRAB_HTTP_BASE_PATH=<path>    Prefix for every HTTP route [default: none]"
+        );
+        println!(
+            "  THROTTLECRAB_HTTP_THROTTLE_PATH=<path> Rate limiting endpoint [default: /throttle]"
+        );
+        println!("  THROTTLECRAB_HTTP_METRICS_PATH=<path> Metrics endpoint [default: /metrics]");
+        println!(
+            "  THROTTLECRAB_HTTP_HEALTH_PATH=<path>  Health check endpoint [default: /health]"
+        );
+        println!("  THROTTLECRAB_HTTP_TLS_CERT=<file>     Serve HTTPS with this certificate");
+        println!("  THROTTLECRAB_HTTP_TLS_KEY=<file>      Private key for the certificate");
+        println!(
+            "  THROTTLECRAB_HTTP_TLS_CLIENT_CA=<file> Require client certificates signed by this CA"
+        );
+        println!(
+            "  THROTTLECRAB_HTTP_USE_429=true        Answer denied requests with 429 instead of 200"
+        );
+        println!(
+            "  THROTTLECRAB_HTTP_KEEP_ALIVE_MAX_REQUESTS=<n> Close connections after n requests [default: 0]"
+        );
+        println!(
+            "  THROTTLECRAB_HTTP_KEEP_ALIVE_TIMEOUT=<secs> Close connections idle this long [default: 0]"
+        );
+        println!(
+            "  THROTTLECRAB_HTTP_COMPRESSION=true    Compress batch responses (gzip, deflate)"
+        );
+        println!();
+        println!("  THROTTLECRAB_GRPC=true|false          Enable gRPC transport");
+        println!("  THROTTLECRAB_GRPC_HOST=<host>         gRPC host [default: 0.0.0.0]");
+        println!("  THROTTLECRAB_GRPC_PORT=<port>         gRPC port [default: 8070]");
+        println!();
+        println!("  THROTTLECRAB_REDIS=true|false         Enable Redis protocol transport");
+        println!("  THROTTLECRAB_REDIS_HOST=<host>        Redis host [default: 0.0.0.0]");
+        println!("  THROTTLECRAB_REDIS_PORT=<port>        Redis port [default: 6379]");
+        println!(
+            "  THROTTLECRAB_REDIS_PASSWORD=<pass>    Password required by AUTH [default: none]"
+        );
+        println!(
+            "  THROTTLECRAB_REDIS_MAX_COMMANDS_PER_SECOND=<n>  Per-connection command cap [default: 0]"
+        );
+        println!("  THROTTLECRAB_REDIS_MAX_CONNECTIONS=<n>  Connections held at once [default: 0]");
+        println!(
+            "  THROTTLECRAB_REDIS_MAX_CONNECTIONS_PER_IP=<n>  Connections held at once per client IP [default: 0]"
+        );
+        println!();
+        println!("  THROTTLECRAB_MUX=true|false           Serve HTTP, gRPC and Redis on one port");
+        println!("  THROTTLECRAB_MUX_HOST=<host>          Multiplexed host [default: 0.0.0.0]");
+        println!("  THROTTLECRAB_MUX_PORT=<port>          Multiplexed port [default: 8000]");
+        println!();
+        println!("  THROTTLECRAB_UDP=true|false           Enable UDP transport");
+        println!("  THROTTLECRAB_UDP_HOST=<host>          UDP host [default: 0.0.0.0]");
+        println!("  THROTTLECRAB_UDP_PORT=<port>          UDP port [default: 8089]");
+        println!();
+
+        println!("Store Configuration:");
+        println!(
+            "  THROTTLECRAB_STORE=<type>             Store type: periodic, probabilistic, adaptive, timingwheel, auto [default: periodic]"
+        );
+        println!(
+            "  THROTTLECRAB_STORE_CAPACITY=<size>    Initial store capacity [default: 100000]"
+        );
+        println!();
+        println!("  For periodic store:");
+        println!(
+            "    THROTTLECRAB_STORE_CLEANUP_INTERVAL=<secs>   Cleanup interval in seconds [default: 300]"
+        );
+        println!();
+        println!("  For probabilistic store:");
+        println!(
+            "    THROTTLECRAB_STORE_CLEANUP_PROBABILITY=<n>   Cleanup probability (1 in N) [default: 10000]"
+        );
+        println!();
+        println!("  For timingwheel store:");
+        println!(
+            "    THROTTLECRAB_STORE_CLEANUP_BATCH=<n>         Expired keys removed per operation [default: 128]"
+        );
+        println!();
+        println!("  For adaptive store:");
+        println!(
+            "    THROTTLECRAB_STORE_MIN_INTERVAL=<secs>       Minimum cleanup interval [default: 5]"
+        );
+        println!(
+            "    THROTTLECRAB_STORE_MAX_INTERVAL=<secs>       Maximum cleanup interval [default: 300]"
+        );
+        println!(
+            "    THROTTLECRAB_STORE_MAX_OPERATIONS=<n>        Max operations before cleanup [default: 1000000]"
+        );
+        println!();
+        println!("  For auto store (also uses the adaptive settings):");
+        println!(
+            "    THROTTLECRAB_STORE_LATENCY_BUDGET_US=<us>    Slowest acceptable request [default: 1000]"
+        );
+        println!();
+        println!("  For redis store:");
+        println!("    THROTTLECRAB_STORE_REDIS_URL=<url>           Redis holding the shared state");
+        println!();
+        println!("  Entry TTLs (all store types):");
+        println!(
+            "    THROTTLECRAB_STORE_MAX_TTL=<secs>            Cap entry TTLs, 0=unlimited [default: 0]"
+        );
+        println!(
+            "    THROTTLECRAB_STORE_TTL_MULTIPLIER=<factor>   Multiply entry TTLs before the cap [default: 1.0]"
+        );
+        println!();
+        println!("  Key limits (all store types):");
+        println!(
+            "    THROTTLECRAB_MAX_KEYS=<n>                    Maximum keys in the store, 0=unlimited [default: 0]"
+        );
+        println!(
+            "    THROTTLECRAB_STORE_MAX_MEMORY_MB=<mb>        Memory budget for entries, 0=unlimited [default: 0]"
+        );
+        println!(
+            "    THROTTLECRAB_ON_FULL=<policy>                When full: reject, evict-lru, degrade [default: reject]"
+        );
+        println!(
+            "    THROTTLECRAB_SHARDS=<n>                      Rate limiter actors to partition keys across [default: 1]"
+        );
+        println!(
+            "    THROTTLECRAB_STORE_MAP_SHARDS=<n>            Hash maps each store's table is split into [default: 1]"
+        );
+        println!(
+            "    THROTTLECRAB_COALESCE_WINDOW_US=<us>         Collect and coalesce identical throttle requests [default: 0]"
+        );
+        println!(
+            "    THROTTLECRAB_BACKPRESSURE=<policy>           When a queue is full: block, fail-fast, shed-oldest [default: block]"
+        );
+        println!(
+            "    THROTTLECRAB_MICRO_CACHE_MS=<ms>             Reuse decisions for identical throttle requests [default: 0]"
+        );
+        println!(
+            "    THROTTLECRAB_STORE_STATS_INTERVAL=<secs>     Log store statistics this often [default: 0]"
+        );
+        println!(
+            "    THROTTLECRAB_HOT_KEYS=<count>                Most requested keys counted per actor [default: 0]"
+        );
+        println!(
+            "    THROTTLECRAB_NAMESPACE_QUOTAS=<name=n,...>   Most keys per namespace [default: none]"
+        );
+        println!();
+        println!("  Time source (all store types):");
+        println!(
+            "    THROTTLECRAB_CLOCK=<clock>                   Time source: monotonic, system [default: monotonic]"
+        );
+        println!();
+        println!("  Write-ahead log (all store types):");
+        println!(
+            "    THROTTLECRAB_WAL_PATH=<file>                 Enable the WAL at this path [default: disabled]"
+        );
+        println!(
+            "    THROTTLECRAB_WAL_FSYNC=<policy>              Sync policy: always, interval, never [default: interval]"
+        );
+        println!(
+            "    THROTTLECRAB_WAL_FSYNC_INTERVAL_MS=<ms>      Sync interval for the interval policy [default: 1000]"
+        );
+        println!(
+            "    THROTTLECRAB_WAL_COMPACT_BYTES=<bytes>       Log size that triggers compaction [default: 67108864]"
+        );
+        println!();
+        println!("  Snapshots (all store types):");
+        println!(
+            "    THROTTLECRAB_SNAPSHOT_PATH=<file>            Enable snapshots at this path [default: disabled]"
+        );
+        println!(
+            "    THROTTLECRAB_SNAPSHOT_INTERVAL=<secs>        Time between snapshots [default: 60]"
+        );
+        println!();
+        println!("  Canary store (all store types):");
+        println!(
+            "    THROTTLECRAB_CANARY_STORE=<type>             Mirror keys to a second store type [default: disabled]"
+        );
+        println!(
+            "    THROTTLECRAB_CANARY_FRACTION=<fraction>      Fraction of keys mirrored, 0.0-1.0 [default: 0.01]"
+        );
+        println!();
+
+        println!("Decision Event Export:");
+        println!(
+            "  THROTTLECRAB_EVENTS_SINK=<sink>                Broker: nats, kafka (requires matching build feature)"
+        );
+        println!(
+            "  THROTTLECRAB_EVENTS_URL=<url>                  NATS URL or comma-separated Kafka brokers"
+        );
+        println!(
+            "  THROTTLECRAB_EVENTS_TOPIC=<topic>              Subject/topic [default: throttlecrab.decisions]"
+        );
+        println!(
+            "  THROTTLECRAB_EVENTS_FORMAT=<format>            Format: json, protobuf [default: json]"
+        );
+        println!(
+            "  THROTTLECRAB_EVENTS_SAMPLE_RATE=<rate>         Fraction of decisions to export [default: 1.0]"
+        );
+        println!(
+            "  THROTTLECRAB_EVENTS_BATCH_SIZE=<n>             Events per batch [default: 100]"
+        );
+        println!(
+            "  THROTTLECRAB_EVENTS_FLUSH_INTERVAL_MS=<ms>     Max time to hold a batch [default: 1000]"
+        );
+        println!(
+            "  THROTTLECRAB_EVENTS_BUFFER_SIZE=<n>            Buffered events before dropping [default: 10000]"
+        );
+        println!();
+
+        println!("Denial Alerts:");
+        println!(
+            "  THROTTLECRAB_DENIAL_THRESHOLD=<n>              Denials within the window that alert [default: 0, disabled]"
+        );
+        println!(
+            "  THROTTLECRAB_DENIAL_WINDOW=<secs>              Window denials are counted in [default: 60]"
+        );
+        println!(
+            "  THROTTLECRAB_DENIAL_WEBHOOK=<url>              POST alerts here (requires the webhook feature)"
+        );
+        println!();
+
+        println!("Metrics Listener:");
+        println!(
+            "  THROTTLECRAB_METRICS_PORT=<port>      Serve /metrics on this port [default: none]"
+        );
+        println!(
+            "  THROTTLECRAB_METRICS_HOST=<host>      Metrics listener host [default: 0.0.0.0]"
+        );
+        println!();
+
+        println!("Admin Listener:");
+        println!(
+            "  THROTTLECRAB_ADMIN_PORT=<port>        Serve the /admin endpoints on this port [default: none]"
+        );
+        println!(
+            "  THROTTLECRAB_ADMIN_HOST=<host>        Admin listener host [default: 127.0.0.1]"
+        );
+        println!(
+            "  THROTTLECRAB_ADMIN_TOKEN=<secret>     Bearer token required to change key overrides [default: none]"
+        );
+        println!();
+
+        println!("Metrics Push (requires the metrics-push feature):");
+        println!(
+            "  THROTTLECRAB_METRICS_PUSH_URL=<url>            POST metrics snapshots to this URL"
+        );
+        println!(
+            "  THROTTLECRAB_METRICS_PUSH_FORMAT=<format>      Format: json, remote-write [default: json]"
+        );
+        println!(
+            "  THROTTLECRAB_METRICS_PUSH_INTERVAL=<secs>      Time between pushes [default: 15]"
+        );
+        println!("  THROTTLECRAB_METRICS_PUSH_TOKEN=<token>        Bearer token [default: none]");
+        println!();
+
+        println!("OpenTelemetry (requires the otlp feature):");
+        println!(
+            "  THROTTLECRAB_OTLP_ENDPOINT=<url>               Export request spans to this collector"
+        );
+        println!(
+            "  THROTTLECRAB_OTLP_SAMPLE_RATIO=<ratio>         Fraction of new traces to sample [default: 1.0]"
+        );
+        println!();
+
+        println!("Policies:");
+        println!(
+            "  THROTTLECRAB_POLICIES=<file>          TOML file of named policies [default: none]"
+        );
+        println!(
+            "  THROTTLECRAB_OVERRIDES_FILE=<file>    TOML file persisting the key deny- and allow-lists [default: none]"
+        );
+        println!(
+            "  THROTTLECRAB_CONFIG=<file>            TOML file of options, overridden by env and CLI [default: none]"
+        );
+        println!();
+
+        println!("Cluster:");
+        println!(
+            "  THROTTLECRAB_CLUSTER_PORT=<port>      Listen for cluster peers on this port [default: none]"
+        );
+        println!(
+            "  THROTTLECRAB_CLUSTER_HOST=<host>      Cluster listener host [default: 0.0.0.0]"
+        );
+        println!("  THROTTLECRAB_CLUSTER_PEERS=<list>     Comma-separated peer host:port list");
+        println!("  THROTTLECRAB_CLUSTER_DNS=<name:port>  Discover peers from DNS records");
+        println!("  THROTTLECRAB_CLUSTER_SYNC_INTERVAL_MS=<ms> Time between syncs [default: 100]");
+        println!();
+
+        println!("Authentication:");
+        println!(
+            "  THROTTLECRAB_API_KEYS=<name=secret,...>  API keys clients must send [default: none]"
+        );
+        println!(
+            "  THROTTLECRAB_API_KEYS_FILE=<file>        TOML file of further API keys [default: none]"
+        );
+        println!(
+            "  THROTTLECRAB_API_KEY_NAMESPACES=true     Default each request's namespace to its API key [default: false]"
+        );
+        println!(
+            "  THROTTLECRAB_API_KEY_BUDGETS=<name=count/secs,...>  Throttle checks each API key may make [default: none]"
+        );
+        println!();
+
+        println!("Self-Probing:");
+        println!(
+            "  THROTTLECRAB_PROBE_INTERVAL=<secs>    Probe each transport over loopback [default: 0]"
+        );
+        println!(
+            "  THROTTLECRAB_PROBE_KEY=<key>          Synthetic probe key [default: __throttlecrab_probe]"
+        );
+        println!();
+
+        println!("General Configuration:");
+        println!("  THROTTLECRAB_BUFFER_SIZE=<size>       Channel buffer size [default: 100000]");
+        println!(
+            "  THROTTLECRAB_MAX_DENIED_KEYS=<count>  Maximum denied keys to track (0=disabled, max: 10000) [default: 100]"
+        );
+        println!(
+            "  THROTTLECRAB_TOP_KEYS=<count>         Top requested and allowed keys to track (0=disabled, max: 10000) [default: 0]"
+        );
+        println!(
+            "  THROTTLECRAB_TOP_KEYS_SAMPLE_RATE=<n> Count one request in n towards the top keys [default: 100]"
+        );
+        println!(
+            "  THROTTLECRAB_TRACE_BUFFER_SIZE=<count> Recent requests kept for /admin/trace (0=disabled) [default: 0]"
+        );
+        println!(
+            "  THROTTLECRAB_LATENCY_LOG_INTERVAL=<secs> Log request latency quantiles this often (0=disabled) [default: 0]"
+        );
+        println!(
+            "  THROTTLECRAB_MAX_RPS=<rps>            Throttle checks per second before shedding (0=disabled) [default: 0]"
+        );
+        println!(
+            "  THROTTLECRAB_SHED_DECISION=<decision> Decision for shed requests: deny, allow [default: deny]"
+        );
+        println!("  THROTTLECRAB_MAX_KEY_LENGTH=<bytes>   Reject longer keys [default: 1024]");
+        println!("  THROTTLECRAB_MAX_PERIOD=<secs>        Reject longer periods");
+        println!("  THROTTLECRAB_MAX_QUANTITY=<n>         Reject larger quantities");
+        println!(
+            "  THROTTLECRAB_KEY_CHARS=<set>          Characters keys may contain, e.g. a-zA-Z0-9:_.-"
+        );
+        println!(
+            "  THROTTLECRAB_LOG_LEVEL=<level>        Log level: error, warn, info, debug, trace [default: info]"
+        );
+        println!("  THROTTLECRAB_LOG_FORMAT=<format>      Log format: text, json [default: text]");
+        println!(
+            "  THROTTLECRAB_STATUS_FILE=<path>       Write the startup status as JSON to this file"
+        );
+        println!();
+
+        println!("Examples:");
+        println!("  # Enable HTTP transport on port 8080");
+        println!("  export THROTTLECRAB_HTTP=true");
+        println!("  export THROTTLECRAB_HTTP_PORT=8080");
+        println!();
+        println!("  # Use adaptive store with custom settings");
+        println!("  export THROTTLECRAB_STORE=adaptive");
+        println!("  export THROTTLECRAB_STORE_MIN_INTERVAL=10");
+        println!("  export THROTTLECRAB_STORE_MAX_INTERVAL=600");
+        println!();
+        println!("  # Run server (CLI args override env vars)");
+        println!("  throttlecrab-server --http-port 9090  # Will use port 9090, not 8080");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_store_type_from_str() {
+        assert_eq!(
+            StoreType::from_str("periodic").unwrap(),
+            StoreType::Periodic
+        );
+        assert_eq!(
+            StoreType::from_str("PERIODIC").unwrap(),
+            StoreType::Periodic
+        );
+        assert_eq!(
+            StoreType::from_str("probabilistic").unwrap(),
+            StoreType::Probabilistic
+        );
+        assert_eq!(
+            StoreType::from_str("adaptive").unwrap(),
+            StoreType::Adaptive
+        );
+        assert_eq!(
+            StoreType::from_str("timingwheel").unwrap(),
+            StoreType::TimingWheel
+        );
+        assert_eq!(StoreType::from_str("auto").unwrap(), StoreType::Auto);
+        assert_eq!(StoreType::from_str("redis").unwrap(), StoreType::Redis);
+        assert!(StoreType::from_str("invalid").is_err());
+    }
+
+    #[test]
+    fn test_shed_decision_from_str() {
+        assert_eq!(ShedDecision::from_str("deny").unwrap(), ShedDecision::Deny);
+        assert_eq!(
+            ShedDecision::from_str("ALLOW").unwrap(),
+            ShedDecision::Allow
+        );
+        assert!(ShedDecision::from_str("open").is_err());
+    }
+
+    #[test]
+    fn test_on_full_from_str() {
+        assert_eq!(OnFull::from_str("reject").unwrap(), OnFull::Reject);
+        assert_eq!(OnFull::from_str("evict-lru").unwrap(), OnFull::EvictLru);
+        assert_eq!(OnFull::from_str("DEGRADE").unwrap(), OnFull::Degrade);
+        assert!(OnFull::from_str("evict").is_err());
+    }
+
+    #[test]
+    fn test_backpressure_from_str() {
+        assert_eq!(
+            Backpressure::from_str("block").unwrap(),
+            Backpressure::Block
+        );
+        assert_eq!(
+            Backpressure::from_str("fail-fast").unwrap(),
+            Backpressure::FailFast
+        );
+        assert_eq!(
+            Backpressure::from_str("SHED-OLDEST").unwrap(),
+            Backpressure::ShedOldest
+        );
+        assert!(Backpressure::from_str("drop").is_err());
+    }
+
+    #[test]
+    fn test_log_format_from_str() {
+        assert_eq!(LogFormat::from_str("text").unwrap(), LogFormat::Text);
+        assert_eq!(LogFormat::from_str("JSON").unwrap(), LogFormat::Json);
+        assert!(LogFormat::from_str("logfmt").is_err());
+    }
+
+    #[test]
+    fn test_api_key_budget_from_str() {
+        assert_eq!(
+            ApiKeyBudget::from_str("checkout=50000/60").unwrap(),
+            ApiKeyBudget {
+                name: "checkout".to_string(),
+                count: 50000,
+                period: 60,
+            }
+        );
+        assert_eq!(ApiKeyBudget::from_str("*=10/1").unwrap().name, "*");
+        assert!(ApiKeyBudget::from_str("checkout=50000").is_err());
+        assert!(ApiKeyBudget::from_str("checkout").is_err());
+        assert!(ApiKeyBudget::from_str("checkout=many/60").is_err());
+    }
+
+    #[test]
+    fn test_events_config_validation() {
+        let mut config = Config {
+            transports: TransportConfig {
+                http: Some(HttpConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 8080,
+                    routes: HttpRoutes::default(),
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
+                }),
+                grpc: None,
+                redis: None,
+                mux: None,
+                udp: None,
+            },
+            store: StoreConfig {
+                store_type: StoreType::Periodic,
+                capacity: 100_000,
+                cleanup_interval: 300,
+                cleanup_probability: 10_000,
+                cleanup_batch: 128,
+                min_interval: 5,
+                max_interval: 300,
+                max_operations: 1_000_000,
+                latency_budget_us: 1_000,
+                max_ttl: 0,
+                ttl_multiplier: 1.0,
+                max_keys: 0,
+                max_memory_mb: 0,
+                on_full: OnFull::Reject,
+                clock: ClockType::System,
+                wal: None,
+                snapshot: None,
+                canary: None,
+                shards: 1,
+                redis_url: None,
+                coalesce_window_us: 0,
+                backpressure: Backpressure::Block,
+                micro_cache_ms: 0,
+                stats_log_interval: 0,
+                hot_keys: 0,
+                namespace_quotas: BTreeMap::new(),
+                map_shards: 1,
+            },
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: Some(EventsConfig {
+                sink: EventSinkType::Nats,
+                url: "nats://localhost:4222".to_string(),
+                topic: "throttlecrab.decisions".to_string(),
+                format: EventFormat::Json,
+                sample_rate: 0.5,
+                batch_size: 100,
+                flush_interval_ms: 1000,
+                buffer_size: 10_000,
+            }),
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        assert!(config.validate().is_ok());
+
+        config.events.as_mut().unwrap().sample_rate = 1.5;
+        assert!(config.validate().is_err());
+
+        config.events.as_mut().unwrap().sample_rate = 1.0;
+        config.events.as_mut().unwrap().url.clear();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_canary_config_validation() {
+        let mut config = Config {
+            transports: TransportConfig {
+                http: Some(HttpConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 8080,
+                    routes: HttpRoutes::default(),
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
+                }),
+                grpc: None,
+                redis: None,
+                mux: None,
+                udp: None,
+            },
+            store: StoreConfig {
+                canary: Some(CanaryConfig {
+                    store_type: StoreType::Adaptive,
+                    fraction: 0.05,
+                }),
+                ..Default::default()
+            },
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        assert!(config.validate().is_ok());
+
+        config.store.canary.as_mut().unwrap().fraction = -0.1;
+        assert!(config.validate().is_err());
+
+        config.store.canary = Some(CanaryConfig {
+            store_type: StoreType::Auto,
+            fraction: 0.05,
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_metrics_push_validation() {
+        let mut config = Config {
+            transports: TransportConfig {
+                http: Some(HttpConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 8080,
+                    routes: HttpRoutes::default(),
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
+                }),
+                grpc: None,
+                redis: None,
+                mux: None,
+                udp: None,
+            },
+            store: StoreConfig::default(),
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: Some(MetricsPushConfig {
+                url: "https://metrics.example.com/api/v1/push".to_string(),
+                format: MetricsPushFormat::RemoteWrite,
+                interval: 15,
+                token: Some("secret".to_string()),
+            }),
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        assert!(config.validate().is_ok());
+        assert!(!format!("{config:?}").contains("secret"));
+
+        config.metrics_push.as_mut().unwrap().interval = 0;
+        assert!(config.validate().is_err());
+
+        config.metrics_push.as_mut().unwrap().interval = 15;
+        config.metrics_push.as_mut().unwrap().url = "metrics.example.com".to_string();
+        assert!(config.validate().is_err());
+
+        config.metrics_push.as_mut().unwrap().url = "http://localhost:9091".to_string();
+        config.metrics_push.as_mut().unwrap().token = Some(String::new());
+        assert!(config.validate().is_err());
+
+        config.metrics_push = None;
+        config.denial_alerts = Some(DenialAlertsConfig {
+            threshold: 100,
+            window: 60,
+            webhook: Some("https://alerts.example.com/denials".to_string()),
+        });
+        assert!(config.validate().is_ok());
+
+        config.denial_alerts.as_mut().unwrap().webhook = Some("alerts.example.com".to_string());
+        assert!(config.validate().is_err());
+
+        config.denial_alerts.as_mut().unwrap().webhook = None;
+        config.denial_alerts.as_mut().unwrap().window = 0;
+        assert!(config.validate().is_err());
+
+        config.denial_alerts = None;
+        config.top_keys = Some(TopKeysConfig {
+            count: 100,
+            sample_rate: 100,
+        });
+        assert!(config.validate().is_ok());
+        config.top_keys.as_mut().unwrap().sample_rate = 0;
+        assert!(config.validate().is_err());
+        config.top_keys.as_mut().unwrap().sample_rate = 1;
+        config.top_keys.as_mut().unwrap().count = 10_001;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_otlp_validation() {
+        let mut config = Config {
+            transports: TransportConfig {
+                http: Some(HttpConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 8080,
+                    routes: HttpRoutes::default(),
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
+                }),
+                grpc: None,
+                redis: None,
+                mux: None,
+                udp: None,
+            },
+            store: StoreConfig::default(),
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: Some(OtlpConfig {
+                endpoint: "http://localhost:4317".to_string(),
+                sample_ratio: 0.1,
+            }),
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        assert!(config.validate().is_ok());
+
+        config.otlp.as_mut().unwrap().sample_ratio = 1.5;
+        assert!(config.validate().is_err());
+
+        config.otlp.as_mut().unwrap().sample_ratio = 1.0;
+        config.otlp.as_mut().unwrap().endpoint = "localhost:4317".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_namespace_quota_validation() {
+        assert_eq!(
+            parse_namespace_quota("checkout=1000"),
+            Ok(("checkout".to_string(), 1000))
+        );
+        assert!(parse_namespace_quota("checkout").is_err());
+        assert!(parse_namespace_quota("checkout=many").is_err());
+
+        let mut config = Config {
+            transports: TransportConfig {
+                http: Some(HttpConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 8080,
+                    routes: HttpRoutes::default(),
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
+                }),
+                grpc: None,
+                redis: None,
+                mux: None,
+                udp: None,
+            },
+            store: StoreConfig {
+                namespace_quotas: [("checkout".to_string(), 1000)].into(),
+                ..StoreConfig::default()
+            },
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        assert!(config.validate().is_ok());
+
+        config.store.namespace_quotas = [("checkout".to_string(), 0)].into();
+        assert!(config.validate().is_err());
+
+        config.store.namespace_quotas = [("check out".to_string(), 1000)].into();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_probe_validation() {
+        let mut config = Config {
+            transports: TransportConfig {
+                http: Some(HttpConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 8080,
+                    routes: HttpRoutes::default(),
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
+                }),
+                grpc: None,
+                redis: None,
+                mux: None,
+                udp: None,
+            },
+            store: StoreConfig::default(),
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: Some(ProbeConfig {
+                interval: 10,
+                key: "__throttlecrab_probe".to_string(),
+            }),
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        assert!(config.validate().is_ok());
+
+        config.probe.as_mut().unwrap().interval = 0;
+        assert!(config.validate().is_err());
+
+        config.probe.as_mut().unwrap().interval = 10;
+        config.probe.as_mut().unwrap().key = String::new();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_metrics_listener_validation() {
+        let mut config = Config {
+            transports: TransportConfig {
+                http: Some(HttpConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 8080,
+                    routes: HttpRoutes::default(),
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
+                }),
+                grpc: None,
+                redis: None,
+                mux: None,
+                udp: None,
+            },
+            store: StoreConfig::default(),
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: Some(MetricsListenerConfig {
+                host: "127.0.0.1".to_string(),
+                port: 9100,
+            }),
+            policies: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            cluster: None,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        assert!(config.validate().is_ok());
+
+        // The listener can't share a port with a transport
+        config.metrics_listener.as_mut().unwrap().port = 8080;
+        assert!(config.validate().is_err());
+
+        // Nor can the admin listener, with a transport or the metrics listener
+        config.metrics_listener.as_mut().unwrap().port = 9100;
+        config.admin_listener = Some(AdminListenerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 9101,
+            token: None,
+        });
+        assert!(config.validate().is_ok());
+        config.admin_listener.as_mut().unwrap().port = 9100;
+        assert!(config.validate().is_err());
+        config.admin_listener.as_mut().unwrap().port = 8080;
+        assert!(config.validate().is_err());
+
+        // Its token is redacted, and can't be empty
+        let admin_listener = config.admin_listener.as_mut().unwrap();
+        admin_listener.port = 9101;
+        admin_listener.token = Some("s3cret".to_string());
+        assert!(config.validate().is_ok());
+        assert!(!format!("{config:?}").contains("s3cret"));
+        config.admin_listener.as_mut().unwrap().token = Some(String::new());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_cluster_validation() {
+        let mut config = Config {
+            transports: TransportConfig {
+                http: Some(HttpConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 8080,
+                    routes: HttpRoutes::default(),
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
+                }),
+                grpc: None,
+                redis: None,
+                mux: None,
+                udp: None,
+            },
+            store: StoreConfig::default(),
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: Some(ClusterConfig {
+                host: "0.0.0.0".to_string(),
+                port: 7946,
+                peers: vec!["10.0.0.2:7946".to_string()],
+                dns: None,
+                sync_interval_ms: 100,
+            }),
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        assert!(config.validate().is_ok());
+
+        // Peers must come from somewhere
+        config.cluster.as_mut().unwrap().peers.clear();
+        assert!(config.validate().is_err());
+        config.cluster.as_mut().unwrap().dns = Some("throttlecrab:7946".to_string());
+        assert!(config.validate().is_ok());
+
+        config.cluster.as_mut().unwrap().sync_interval_ms = 0;
+        assert!(config.validate().is_err());
+        config.cluster.as_mut().unwrap().sync_interval_ms = 100;
+
+        // The listener can't share a port with a transport
+        config.cluster.as_mut().unwrap().port = 8080;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_redis_store_validation() {
+        let mut config = Config {
+            transports: TransportConfig {
+                http: Some(HttpConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 8080,
+                    routes: HttpRoutes::default(),
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
+                }),
+                grpc: None,
+                redis: None,
+                mux: None,
+                udp: None,
+            },
+            store: StoreConfig {
+                store_type: StoreType::Redis,
+                redis_url: Some("redis://127.0.0.1:6379".to_string()),
+                ..StoreConfig::default()
+            },
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        assert!(config.validate().is_ok());
+
+        config.store.redis_url = None;
+        assert!(config.validate().is_err());
+        config.store.redis_url = Some("redis://127.0.0.1:6379".to_string());
+
+        // Local store features can't see the state in Redis
+        config.store.max_keys = 1000;
+        let error = config.validate().unwrap_err();
+        assert!(error.to_string().contains("--max-keys"), "{error}");
+        config.store.max_keys = 0;
+
+        config.store.canary = Some(CanaryConfig {
+            store_type: StoreType::Periodic,
+            fraction: 0.1,
+        });
+        let error = config.validate().unwrap_err();
+        assert!(error.to_string().contains("--canary-store"), "{error}");
+
+        config.store.store_type = StoreType::Periodic;
+        config.store.canary = Some(CanaryConfig {
+            store_type: StoreType::Redis,
+            fraction: 0.1,
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_latency_budget_validation() {
+        let mut config = Config {
+            transports: TransportConfig {
+                http: Some(HttpConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 8080,
+                    routes: HttpRoutes::default(),
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
+                }),
+                grpc: None,
+                redis: None,
+                mux: None,
+                udp: None,
+            },
+            store: StoreConfig {
+                store_type: StoreType::Auto,
+                ..Default::default()
+            },
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        assert!(config.validate().is_ok());
+
+        config.store.latency_budget_us = 0;
+        assert!(config.validate().is_err());
+
+        // The budget only matters for the auto store
+        config.store.store_type = StoreType::Adaptive;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_ttl_multiplier_validation() {
+        let mut config = Config {
+            transports: TransportConfig {
+                http: Some(HttpConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 8080,
+                    routes: HttpRoutes::default(),
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
+                }),
+                grpc: None,
+                redis: None,
+                mux: None,
+                udp: None,
+            },
+            store: StoreConfig {
+                max_ttl: 86_400,
+                ttl_multiplier: 1.5,
+                ..Default::default()
+            },
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        assert!(config.validate().is_ok());
+
+        config.store.ttl_multiplier = 0.0;
+        assert!(config.validate().is_err());
+        config.store.ttl_multiplier = f64::NAN;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_snapshot_validation() {
+        let mut config = Config {
+            transports: TransportConfig {
+                http: Some(HttpConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 8080,
+                    routes: HttpRoutes::default(),
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
+                }),
+                grpc: None,
+                redis: None,
+                mux: None,
+                udp: None,
             },
             store: StoreConfig {
-                store_type: args.store,
-                capacity: args.store_capacity,
-                cleanup_interval: args.store_cleanup_interval,
-                cleanup_probability: args.store_cleanup_probability,
-                min_interval: args.store_min_interval,
-                max_interval: args.store_max_interval,
-                max_operations: args.store_max_operations,
+                snapshot: Some(SnapshotConfig {
+                    path: PathBuf::from("/tmp/throttlecrab.snap"),
+                    interval: Duration::from_secs(60),
+                }),
+                ..Default::default()
             },
-            buffer_size: args.buffer_size,
-            max_denied_keys: args.max_denied_keys,
-            log_level: args.log_level,
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
         };
+        assert!(config.validate().is_ok());
 
-        // Configure transports based on parsed args
-        if args.http {
-            config.transports.http = Some(HttpConfig {
-                host: args.http_host,
-                port: args.http_port,
-            });
-        }
+        config.store.snapshot.as_mut().unwrap().interval = Duration::ZERO;
+        assert!(config.validate().is_err());
 
-        if args.grpc {
-            config.transports.grpc = Some(GrpcConfig {
-                host: args.grpc_host,
-                port: args.grpc_port,
-            });
-        }
+        // Snapshots and the write-ahead log would both restore the store
+        config.store.snapshot.as_mut().unwrap().interval = Duration::from_secs(60);
+        config.store.wal = Some(WalConfig {
+            path: PathBuf::from("/tmp/throttlecrab.wal"),
+            fsync: WalFsync::Interval,
+            fsync_interval: Duration::from_secs(1),
+            compact_bytes: 1024,
+        });
+        assert!(config.validate().is_err());
+    }
 
-        if args.redis {
-            config.transports.redis = Some(RedisConfig {
-                host: args.redis_host,
-                port: args.redis_port,
-            });
-        }
+    #[test]
+    fn test_shards_validation() {
+        let mut config = Config {
+            transports: TransportConfig {
+                http: Some(HttpConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 8080,
+                    routes: HttpRoutes::default(),
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
+                }),
+                grpc: None,
+                redis: None,
+                mux: None,
+                udp: None,
+            },
+            store: StoreConfig {
+                shards: 8,
+                ..Default::default()
+            },
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        assert!(config.validate().is_ok());
 
-        // Validate configuration
-        config.validate()?;
+        config.store.shards = 0;
+        assert!(config.validate().is_err());
+        config.store.shards = MAX_SHARDS + 1;
+        assert!(config.validate().is_err());
+        config.store.shards = 1;
+        config.store.map_shards = 0;
+        assert!(config.validate().is_err());
+        config.store.map_shards = 1;
+        config.store.cleanup_batch = 0;
+        assert!(config.validate().is_err());
+        config.store.cleanup_batch = 128;
 
-        Ok(config)
+        // The write-ahead log is a single file for a single store
+        config.store.shards = 2;
+        config.store.wal = Some(WalConfig {
+            path: PathBuf::from("/tmp/throttlecrab.wal"),
+            fsync: WalFsync::Interval,
+            fsync_interval: Duration::from_secs(1),
+            compact_bytes: 1024,
+        });
+        assert!(config.validate().is_err());
+        config.store.shards = 1;
+        assert!(config.validate().is_ok());
     }
 
-    /// Check if at least one transport is configured
-    ///
-    /// The server requires at least one transport to be functional.
-    pub fn has_any_transport(&self) -> bool {
-        self.transports.http.is_some()
-            || self.transports.grpc.is_some()
-            || self.transports.redis.is_some()
+    #[test]
+    fn test_redis_password_validation() {
+        let mut config = Config {
+            transports: TransportConfig {
+                http: None,
+                grpc: None,
+                redis: Some(RedisConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 6379,
+                    password: Some("secret".to_string()),
+                    max_commands_per_second: 0,
+                    max_connections: 0,
+                    max_connections_per_ip: 0,
+                }),
+                mux: None,
+                udp: None,
+            },
+            store: StoreConfig::default(),
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        assert!(config.validate().is_ok());
+
+        // The password never shows up in debug output
+        let debug = format!("{:?}", config.transports.redis);
+        assert!(!debug.contains("secret"));
+
+        config.transports.redis.as_mut().unwrap().password = Some(String::new());
+        assert!(config.validate().is_err());
     }
 
-    /// Validate the configuration
-    ///
-    /// Currently checks that at least one transport is enabled.
-    /// Additional validation can be added here in the future.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if the configuration is invalid.
-    fn validate(&self) -> Result<()> {
-        if !self.has_any_transport() {
-            return Err(anyhow!(
-                "At least one transport must be specified.\n\n\
-                Available transports:\n  \
-                --http       Enable HTTP transport\n  \
-                --grpc       Enable gRPC transport\n  \
-                --redis      Enable Redis protocol transport\n  \
-                Example:\n  \
-                throttlecrab-server --http --http-port 7070\n  \
-                throttlecrab-server --http --grpc --redis\n\n\
-                For more information, try '--help'"
-            ));
+    #[test]
+    fn test_http_routes_validation() {
+        let mut config = Config {
+            transports: TransportConfig {
+                http: Some(HttpConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 8080,
+                    routes: HttpRoutes {
+                        base_path: "/ratelimit/v1".to_string(),
+                        ..Default::default()
+                    },
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
+                }),
+                grpc: None,
+                redis: None,
+                mux: None,
+                udp: None,
+            },
+            store: StoreConfig::default(),
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        assert!(config.validate().is_ok());
+        let routes = &config.transports.http.as_ref().unwrap().routes;
+        assert_eq!(routes.path(&routes.throttle), "/ratelimit/v1/throttle");
+
+        let invalid = [
+            ("ratelimit", "/throttle", "/health"),
+            ("/ratelimit/", "/throttle", "/health"),
+            ("", "throttle", "/health"),
+            ("", "/", "/health"),
+            ("", "/admin/throttle", "/health"),
+            ("", "/throttle/{key}", "/health"),
+            ("", "/check", "/check"),
+        ];
+        for (base_path, throttle, health) in invalid {
+            let routes = &mut config.transports.http.as_mut().unwrap().routes;
+            routes.base_path = base_path.to_string();
+            routes.throttle = throttle.to_string();
+            routes.health = health.to_string();
+            assert!(
+                config.validate().is_err(),
+                "{base_path} {throttle} {health}"
+            );
         }
+    }
 
-        // Additional validation could be added here in the future
-        // e.g., validate port ranges, check for conflicting options, etc.
+    #[test]
+    fn test_mux_validation() {
+        let mut config = Config {
+            transports: TransportConfig {
+                http: None,
+                grpc: None,
+                redis: Some(RedisConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 6379,
+                    password: None,
+                    max_commands_per_second: 0,
+                    max_connections: 0,
+                    max_connections_per_ip: 0,
+                }),
+                mux: Some(MuxConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 8000,
+                    routes: HttpRoutes::default(),
+                    password: Some("secret".to_string()),
+                    max_commands_per_second: 0,
+                    use_429: false,
+                }),
+                udp: None,
+            },
+            store: StoreConfig::default(),
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        assert!(config.validate().is_ok());
+        assert!(!format!("{:?}", config.transports.mux).contains("secret"));
 
-        Ok(())
-    }
+        // The mux counts as a transport on its own
+        config.transports.redis = None;
+        assert!(config.validate().is_ok());
 
-    /// Print all available environment variables and their descriptions
-    ///
-    /// This is called when the --list-env-vars flag is used.
-    /// It provides a comprehensive reference for all environment variables
-    /// that can be used to configure the server.
-    fn print_env_vars() {
-        println!("ThrottleCrab Environment Variables");
-        println!("==================================");
-        println!();
-        println!("All environment variables use the THROTTLECRAB_ prefix.");
-        println!("CLI arguments take precedence over environment variables.");
-        println!();
+        config.transports.mux.as_mut().unwrap().routes.base_path = "api".to_string();
+        assert!(config.validate().is_err());
+        config.transports.mux.as_mut().unwrap().routes.base_path = String::new();
 
-        println!("Transport Configuration:");
-        println!("  THROTTLECRAB_HTTP=true|false          Enable HTTP transport");
-        println!("  THROTTLECRAB_HTTP_HOST=<host>         HTTP host [default: 0.0.0.0]");
-        println!("  THROTTLECRAB_HTTP_PORT=<port>         HTTP port [default: 8080]");
-        println!();
-        println!("  THROTTLECRAB_GRPC=true|false          Enable gRPC transport");
-        println!("  THROTTLECRAB_GRPC_HOST=<host>         gRPC host [default: 0.0.0.0]");
-        println!("  THROTTLECRAB_GRPC_PORT=<port>         gRPC port [default: 8070]");
-        println!();
-        println!("  THROTTLECRAB_REDIS=true|false         Enable Redis protocol transport");
-        println!("  THROTTLECRAB_REDIS_HOST=<host>        Redis host [default: 0.0.0.0]");
-        println!("  THROTTLECRAB_REDIS_PORT=<port>        Redis port [default: 6379]");
-        println!();
+        config.transports.grpc = Some(GrpcConfig {
+            host: "0.0.0.0".to_string(),
+            port: 8000,
+        });
+        assert!(config.validate().is_err());
+    }
 
-        println!("Store Configuration:");
-        println!(
-            "  THROTTLECRAB_STORE=<type>             Store type: periodic, probabilistic, adaptive [default: periodic]"
-        );
-        println!(
-            "  THROTTLECRAB_STORE_CAPACITY=<size>    Initial store capacity [default: 100000]"
-        );
-        println!();
-        println!("  For periodic store:");
-        println!(
-            "    THROTTLECRAB_STORE_CLEANUP_INTERVAL=<secs>   Cleanup interval in seconds [default: 300]"
-        );
-        println!();
-        println!("  For probabilistic store:");
-        println!(
-            "    THROTTLECRAB_STORE_CLEANUP_PROBABILITY=<n>   Cleanup probability (1 in N) [default: 10000]"
-        );
-        println!();
-        println!("  For adaptive store:");
-        println!(
-            "    THROTTLECRAB_STORE_MIN_INTERVAL=<secs>       Minimum cleanup interval [default: 5]"
-        );
-        println!(
-            "    THROTTLECRAB_STORE_MAX_INTERVAL=<secs>       Maximum cleanup interval [default: 300]"
-        );
-        println!(
-            "    THROTTLECRAB_STORE_MAX_OPERATIONS=<n>        Max operations before cleanup [default: 1000000]"
-        );
-        println!();
+    #[test]
+    fn test_udp_validation() {
+        let mut config = Config {
+            transports: TransportConfig {
+                http: None,
+                grpc: None,
+                redis: None,
+                mux: None,
+                udp: Some(UdpConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 8089,
+                }),
+            },
+            store: StoreConfig::default(),
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        // UDP counts as a transport on its own
+        assert!(config.validate().is_ok());
 
-        println!("General Configuration:");
-        println!("  THROTTLECRAB_BUFFER_SIZE=<size>       Channel buffer size [default: 100000]");
-        println!(
-            "  THROTTLECRAB_MAX_DENIED_KEYS=<count>  Maximum denied keys to track (0=disabled, max: 10000) [default: 100]"
-        );
-        println!(
-            "  THROTTLECRAB_LOG_LEVEL=<level>        Log level: error, warn, info, debug, trace [default: info]"
-        );
-        println!();
+        // Datagrams can't be authenticated
+        config.api_keys = Some(ApiKeysConfig {
+            keys: vec![("checkout".to_string(), "3f9a".repeat(8))],
+            file: None,
+            namespaces: false,
+            budgets: Vec::new(),
+        });
+        assert!(config.validate().is_err());
 
-        println!("Examples:");
-        println!("  # Enable HTTP transport on port 8080");
-        println!("  export THROTTLECRAB_HTTP=true");
-        println!("  export THROTTLECRAB_HTTP_PORT=8080");
-        println!();
-        println!("  # Use adaptive store with custom settings");
-        println!("  export THROTTLECRAB_STORE=adaptive");
-        println!("  export THROTTLECRAB_STORE_MIN_INTERVAL=10");
-        println!("  export THROTTLECRAB_STORE_MAX_INTERVAL=600");
-        println!();
-        println!("  # Run server (CLI args override env vars)");
-        println!("  throttlecrab-server --http-port 9090  # Will use port 9090, not 8080");
+        config.api_keys = None;
+        config.validation = Some(ValidationConfig {
+            max_key_length: Some(MAX_KEY_LENGTH + 1),
+            ..ValidationConfig::default()
+        });
+        assert!(config.validate().is_err());
+        config.validation = Some(ValidationConfig {
+            key_chars: Some("z-a".to_string()),
+            ..ValidationConfig::default()
+        });
+        assert!(config.validate().is_err());
+        config.validation = Some(ValidationConfig {
+            max_key_length: Some(64),
+            key_chars: Some("a-z0-9:".to_string()),
+            ..ValidationConfig::default()
+        });
+        assert!(config.validate().is_ok());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::str::FromStr;
+    #[test]
+    fn test_bind_any() {
+        let args = Args::try_parse_from([
+            "throttlecrab-server",
+            "--http",
+            "--udp",
+            "--metrics-port",
+            "9100",
+            "--admin-port",
+            "9101",
+            "--bind-any",
+        ])
+        .unwrap();
+        let config = Config::from_args(args).unwrap();
+        assert_eq!(config.transports.http.unwrap().host, BIND_ANY_HOST);
+        assert_eq!(config.transports.udp.unwrap().host, BIND_ANY_HOST);
+        assert_eq!(config.metrics_listener.unwrap().host, BIND_ANY_HOST);
+        // The admin endpoints stay private
+        assert_eq!(config.admin_listener.unwrap().host, "127.0.0.1");
+
+        let args =
+            Args::try_parse_from(["throttlecrab-server", "--grpc", "--grpc-host", "[::]"]).unwrap();
+        assert!(Config::from_args(args).is_ok());
+        let args = Args::try_parse_from(["throttlecrab-server", "--grpc", "--grpc-host", "local"])
+            .unwrap();
+        let error = Config::from_args(args).unwrap_err();
+        assert!(error.to_string().contains("--grpc-host"), "{error}");
+    }
 
     #[test]
-    fn test_store_type_from_str() {
-        assert_eq!(
-            StoreType::from_str("periodic").unwrap(),
-            StoreType::Periodic
-        );
-        assert_eq!(
-            StoreType::from_str("PERIODIC").unwrap(),
-            StoreType::Periodic
-        );
-        assert_eq!(
-            StoreType::from_str("probabilistic").unwrap(),
-            StoreType::Probabilistic
-        );
+    fn test_http_keep_alive_and_compression() {
+        let args = Args::try_parse_from(["throttlecrab-server", "--http"]).unwrap();
+        let http = Config::from_args(args).unwrap().transports.http.unwrap();
+        assert_eq!(http.keep_alive, HttpKeepAlive::default());
+        assert!(!http.compression);
+
+        let args = Args::try_parse_from([
+            "throttlecrab-server",
+            "--http",
+            "--http-keep-alive-max-requests",
+            "1000",
+            "--http-keep-alive-timeout",
+            "30",
+            "--http-compression",
+        ])
+        .unwrap();
+        let http = Config::from_args(args).unwrap().transports.http.unwrap();
         assert_eq!(
-            StoreType::from_str("adaptive").unwrap(),
-            StoreType::Adaptive
+            http.keep_alive,
+            HttpKeepAlive {
+                max_requests: 1000,
+                idle_timeout: Some(Duration::from_secs(30)),
+            }
         );
-        assert!(StoreType::from_str("invalid").is_err());
+        assert!(http.compression);
     }
 
     #[test]
@@ -568,19 +4439,60 @@ mod tests {
                 http: None,
                 grpc: None,
                 redis: None,
+                mux: None,
+                udp: None,
             },
             store: StoreConfig {
                 store_type: StoreType::Periodic,
                 capacity: 100_000,
                 cleanup_interval: 300,
                 cleanup_probability: 10_000,
+                cleanup_batch: 128,
                 min_interval: 5,
                 max_interval: 300,
                 max_operations: 1_000_000,
+                latency_budget_us: 1_000,
+                max_ttl: 0,
+                ttl_multiplier: 1.0,
+                max_keys: 0,
+                max_memory_mb: 0,
+                on_full: OnFull::Reject,
+                clock: ClockType::System,
+                wal: None,
+                snapshot: None,
+                canary: None,
+                shards: 1,
+                redis_url: None,
+                coalesce_window_us: 0,
+                backpressure: Backpressure::Block,
+                micro_cache_ms: 0,
+                stats_log_interval: 0,
+                hot_keys: 0,
+                namespace_quotas: BTreeMap::new(),
+                map_shards: 1,
             },
             buffer_size: 100_000,
             max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
             log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
         };
 
         assert!(config.validate().is_err());
@@ -594,22 +4506,68 @@ mod tests {
                 http: Some(HttpConfig {
                     host: "0.0.0.0".to_string(),
                     port: 8080,
+                    routes: HttpRoutes::default(),
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
                 }),
                 grpc: None,
                 redis: None,
+                mux: None,
+                udp: None,
             },
             store: StoreConfig {
                 store_type: StoreType::Periodic,
                 capacity: 100_000,
                 cleanup_interval: 300,
                 cleanup_probability: 10_000,
+                cleanup_batch: 128,
                 min_interval: 5,
                 max_interval: 300,
                 max_operations: 1_000_000,
+                latency_budget_us: 1_000,
+                max_ttl: 0,
+                ttl_multiplier: 1.0,
+                max_keys: 0,
+                max_memory_mb: 0,
+                on_full: OnFull::Reject,
+                clock: ClockType::System,
+                wal: None,
+                snapshot: None,
+                canary: None,
+                shards: 1,
+                redis_url: None,
+                coalesce_window_us: 0,
+                backpressure: Backpressure::Block,
+                micro_cache_ms: 0,
+                stats_log_interval: 0,
+                hot_keys: 0,
+                namespace_quotas: BTreeMap::new(),
+                map_shards: 1,
             },
             buffer_size: 100_000,
             max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
             log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
         };
 
         assert!(config.validate().is_ok());
@@ -623,25 +4581,71 @@ mod tests {
                 http: Some(HttpConfig {
                     host: "0.0.0.0".to_string(),
                     port: 8080,
+                    routes: HttpRoutes::default(),
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
                 }),
                 grpc: Some(GrpcConfig {
                     host: "0.0.0.0".to_string(),
                     port: 50051,
                 }),
                 redis: None,
+                mux: None,
+                udp: None,
             },
             store: StoreConfig {
                 store_type: StoreType::Adaptive,
                 capacity: 200_000,
                 cleanup_interval: 300,
                 cleanup_probability: 10_000,
+                cleanup_batch: 128,
                 min_interval: 10,
                 max_interval: 600,
                 max_operations: 2_000_000,
+                latency_budget_us: 1_000,
+                max_ttl: 0,
+                ttl_multiplier: 1.0,
+                max_keys: 0,
+                max_memory_mb: 0,
+                on_full: OnFull::Reject,
+                clock: ClockType::System,
+                wal: None,
+                snapshot: None,
+                canary: None,
+                shards: 1,
+                redis_url: None,
+                coalesce_window_us: 0,
+                backpressure: Backpressure::Block,
+                micro_cache_ms: 0,
+                stats_log_interval: 0,
+                hot_keys: 0,
+                namespace_quotas: BTreeMap::new(),
+                map_shards: 1,
             },
             buffer_size: 50_000,
             max_denied_keys: 100,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            cluster: None,
+            api_keys: None,
+            status_file: None,
             log_level: "debug".to_string(),
+            log_format: LogFormat::Text,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
         };
 
         assert!(config.validate().is_ok());
@@ -0,0 +1,275 @@
+//! TOML configuration files
+//!
+//! With `--config FILE` the server reads its options from a TOML file. Any
+//! command line option can be set by its long name, and options sharing a
+//! prefix can be grouped in a table named after it:
+//!
+//! ```toml
+//! log-level = "debug"
+//! buffer-size = 100000
+//!
+//! [http]
+//! enabled = true
+//! port = 9090
+//!
+//! [store]
+//! type = "adaptive"
+//! capacity = 1000000
+//! cleanup-interval = 60
+//!
+//! [cluster]
+//! peers = ["10.0.0.2:7070", "10.0.0.3:7070"]
+//! ```
+//!
+//! A key in a table names the option made of the table and the key, so
+//! `port` in `[http]` is `--http-port`. `enabled` and `type` name the
+//! option called like the table itself, here `--http` and `--store`. Keys
+//! may use `-` or `_`, and `http-port = 9090` at the top level works as
+//! well. Options taking several values, like `--cluster-peers`, take an
+//! array.
+//!
+//! # Policies
+//!
+//! Named [policies](crate::policy) can live in the same file, one
+//! `[policies.NAME]` table each:
+//!
+//! ```toml
+//! [policies.login]
+//! max_burst = 5
+//! count_per_period = 10
+//! period = 60
+//! ```
+//!
+//! `SIGHUP` and `POST /admin/reload` re-read them from the file. A
+//! `policies = "FILE"` key points to a separate policy file instead.
+//!
+//! # Precedence
+//!
+//! The file's values replace the built-in defaults, so environment
+//! variables and command line arguments still override them:
+//!
+//! 1. CLI arguments (highest priority)
+//! 2. Environment variables
+//! 3. Configuration file
+//! 4. Default values (lowest priority)
+//!
+//! Every value is checked like the option's command line value when the
+//! file is read, and errors name the file, table and key, e.g.
+//! `throttlecrab.toml: [http] port: invalid value 'http' for '--http-port
+//! <PORT>'`. Unknown keys are rejected the same way.
+
+use crate::policy::Policies;
+use anyhow::{Context, Result, anyhow};
+use clap::{ArgAction, Command};
+use std::path::Path;
+use toml::Value;
+
+/// Table holding inline policies
+pub(crate) const POLICIES_TABLE: &str = "policies";
+
+/// Options that only make sense on the command line
+const CLI_ONLY: [&str; 4] = ["config", "list_env_vars", "help", "version"];
+
+/// Make the values in the config file at `path` the defaults of `command`
+///
+/// Returns the updated command and whether the file holds inline
+/// policies, which are checked but left for [`Policies::load_table`].
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or parsed, names an
+/// unknown option, or holds a value its option rejects.
+pub(crate) fn layer(path: &Path, mut command: Command) -> Result<(Command, bool)> {
+    let input = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    let table: toml::Table = toml::from_str(&input)
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+
+    let mut inline_policies = false;
+    for (key, value) in table {
+        match value {
+            Value::Table(policies) if key == POLICIES_TABLE => {
+                Policies::from_value(Value::Table(policies))
+                    .with_context(|| format!("{}: [{POLICIES_TABLE}]", path.display()))?;
+                inline_policies = true;
+            }
+            Value::Table(options) => {
+                for (name, value) in options {
+                    let id = match name.as_str() {
+                        "enabled" | "type" => key.replace('-', "_"),
+                        _ => format!("{key}_{name}").replace('-', "_"),
+                    };
+                    command = set_default(command, &id, value)
+                        .with_context(|| format!("{}: [{key}] {name}", path.display()))?;
+                }
+            }
+            value => {
+                command = set_default(command, &key.replace('-', "_"), value)
+                    .with_context(|| format!("{}: {key}", path.display()))?;
+            }
+        }
+    }
+    Ok((command, inline_policies))
+}
+
+/// Make `value` the default of the option `id`, checking it first
+fn set_default(command: Command, id: &str, value: Value) -> Result<Command> {
+    let arg = command
+        .get_arguments()
+        .find(|arg| arg.get_id() == id && arg.get_long().is_some())
+        .filter(|_| !CLI_ONLY.contains(&id))
+        .ok_or_else(|| anyhow!("unknown option"))?;
+    let long = arg.get_long().unwrap_or(id).to_string();
+
+    let values = match value {
+        Value::Array(values) => values.into_iter().map(scalar).collect(),
+        value => vec![scalar(value)],
+    };
+    let values = values.into_iter().collect::<Result<Vec<_>>>()?;
+
+    if matches!(arg.get_action(), ArgAction::SetTrue) {
+        let [enabled] = values.as_slice() else {
+            return Err(anyhow!("expected true or false"));
+        };
+        if enabled != "true" && enabled != "false" {
+            return Err(anyhow!("expected true or false, not {enabled}"));
+        }
+    } else {
+        if values.len() > 1 && !matches!(arg.get_action(), ArgAction::Append) {
+            return Err(anyhow!("expected a single value, not an array"));
+        }
+        // Parse the values as if they were passed on the command line
+        let args = values.iter().map(|value| format!("--{long}={value}"));
+        if let Err(error) = command
+            .clone()
+            .try_get_matches_from(std::iter::once(command.get_name().to_string()).chain(args))
+        {
+            return Err(anyhow!("{}", first_line(&error.to_string())));
+        }
+    }
+
+    Ok(command.mut_arg(id, |arg| arg.default_values(values)))
+}
+
+/// The command line form of a TOML scalar
+fn scalar(value: Value) -> Result<String> {
+    match value {
+        Value::String(value) => Ok(value),
+        Value::Integer(value) => Ok(value.to_string()),
+        Value::Float(value) => Ok(value.to_string()),
+        Value::Boolean(value) => Ok(value.to_string()),
+        Value::Datetime(value) => Ok(value.to_string()),
+        Value::Array(_) | Value::Table(_) => Err(anyhow!("expected a value or an array of values")),
+    }
+}
+
+/// The message of a clap error, without the `error: ` prefix and usage hints
+fn first_line(error: &str) -> &str {
+    let line = error.lines().next().unwrap_or_default();
+    line.strip_prefix("error: ").unwrap_or(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Args, StoreType};
+    use clap::{CommandFactory, FromArgMatches};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn parse(toml: &str, cli: &[&str]) -> Result<(Args, bool)> {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "throttlecrab-config-{}-{}.toml",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, toml)?;
+        let layered = layer(&path, Args::command());
+        let _ = std::fs::remove_file(&path);
+        let (command, inline_policies) = layered?;
+        let matches = command.try_get_matches_from(
+            std::iter::once("throttlecrab-server").chain(cli.iter().copied()),
+        )?;
+        Ok((Args::from_arg_matches(&matches)?, inline_policies))
+    }
+
+    #[test]
+    fn test_file_values_become_defaults() {
+        let (args, inline_policies) = parse(
+            r#"
+            log-level = "debug"
+            buffer_size = 500
+
+            [http]
+            enabled = true
+            port = 9090
+
+            [store]
+            type = "adaptive"
+            capacity = 5000
+
+            [cluster]
+            peers = ["a:1", "b:2"]
+            "#,
+            &[],
+        )
+        .unwrap();
+        assert!(args.http);
+        assert_eq!(args.http_port, 9090);
+        assert_eq!(args.store, StoreType::Adaptive);
+        assert_eq!(args.store_capacity, 5000);
+        assert_eq!(args.buffer_size, 500);
+        assert_eq!(args.log_level, "debug");
+        assert_eq!(args.cluster_peers, ["a:1", "b:2"]);
+        assert!(!inline_policies);
+        // Untouched options keep their defaults
+        assert_eq!(args.grpc_port, 8070);
+    }
+
+    #[test]
+    fn test_cli_overrides_file() {
+        let (args, _) = parse(
+            "[http]\nenabled = true\nport = 9090\n",
+            &["--http-port", "7070"],
+        )
+        .unwrap();
+        assert!(args.http);
+        assert_eq!(args.http_port, 7070);
+    }
+
+    #[test]
+    fn test_errors_name_the_section() {
+        let error = parse("[http]\nport = \"http\"\n", &[]).unwrap_err();
+        let message = format!("{error:#}");
+        assert!(message.contains("[http] port"), "{message}");
+        assert!(message.contains("--http-port"), "{message}");
+
+        let error = parse("[store]\ncolour = \"red\"\n", &[]).unwrap_err();
+        assert!(format!("{error:#}").contains("[store] colour: unknown option"));
+
+        let error = parse("http = \"yes\"\n", &[]).unwrap_err();
+        assert!(format!("{error:#}").contains("http: expected true or false"));
+
+        let error = parse("config = \"other.toml\"\n", &[]).unwrap_err();
+        assert!(format!("{error:#}").contains("unknown option"));
+    }
+
+    #[test]
+    fn test_inline_policies() {
+        let (_, inline_policies) = parse(
+            "[policies.login]\nmax_burst = 5\ncount_per_period = 10\nperiod = 60\n",
+            &[],
+        )
+        .unwrap();
+        assert!(inline_policies);
+
+        let error = parse(
+            "[policies.login]\nmax_burst = 0\ncount_per_period = 10\nperiod = 60\n",
+            &[],
+        )
+        .unwrap_err();
+        let message = format!("{error:#}");
+        assert!(message.contains("[policies]"), "{message}");
+        assert!(message.contains("policy login"), "{message}");
+    }
+}
@@ -0,0 +1,290 @@
+//! Alerts for keys denied repeatedly
+//!
+//! With `--denial-threshold N` the server counts denials per key and emits a
+//! [`DenialEvent`] when a key is denied `N` times within `--denial-window
+//! SECS`, so abuse can be acted on as it happens:
+//!
+//! ```json
+//! {"key": "login:alice", "namespace": null, "denials": 100, "window": 60,
+//!  "first_denied_ms": 1704067200000, "timestamp_ms": 1704067212345}
+//! ```
+//!
+//! A key's window starts at its first denial, and the key alerts at most
+//! once per window: the count restarts once the window has passed. The
+//! namespace is the part of the stored key before the first `/`, as
+//! [namespaced](crate::namespace) keys are stored; the key is the rest.
+//!
+//! Alerts are delivered to every subscriber:
+//!
+//! - `GET /events` on the HTTP transport streams them as server-sent events
+//! - `--denial-webhook URL` (`webhook` feature) POSTs each one as JSON
+//!
+//! Delivery never blocks the request path. A subscriber that falls behind
+//! by more than [`SUBSCRIBER_BUFFER`] alerts misses the ones that don't fit,
+//! and failed webhook requests are logged and not retried.
+//!
+//! At most [`MAX_TRACKED_KEYS`] keys are counted at once. When the table is
+//! full, keys whose window has passed make room; if none has, denials of
+//! untracked keys are not counted until one does.
+
+use crate::config::DenialAlertsConfig;
+use crate::hooks::{DecisionContext, DecisionHook};
+use crate::namespace::SEPARATOR;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+/// Most keys whose denials are counted at once
+pub const MAX_TRACKED_KEYS: usize = 100_000;
+
+/// Alerts queued for each subscriber before new ones are dropped
+pub const SUBSCRIBER_BUFFER: usize = 1024;
+
+/// A key crossed the denial threshold
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DenialEvent {
+    /// The denied key, without its namespace
+    pub key: String,
+    /// The key's namespace, if it has one
+    pub namespace: Option<String>,
+    /// Denials counted in the window, the threshold when the alert fires
+    pub denials: u64,
+    /// Length of the window in seconds
+    pub window: u64,
+    /// Time of the first denial in the window, in milliseconds since the
+    /// Unix epoch
+    pub first_denied_ms: i64,
+    /// Time of the denial that crossed the threshold, in milliseconds since
+    /// the Unix epoch
+    pub timestamp_ms: i64,
+}
+
+/// Denials of one key in its current window
+#[derive(Debug, Clone, Copy)]
+struct Denials {
+    first_denied: SystemTime,
+    count: u64,
+}
+
+/// Counts denials per key and alerts subscribers when a key crosses the
+/// threshold
+///
+/// A [`DecisionHook`]: register it with the server so it sees every
+/// denial. The server does this itself when denial alerts are configured.
+pub struct DenialAlerts {
+    threshold: u64,
+    window: Duration,
+    keys: Mutex<HashMap<Arc<str>, Denials>>,
+    subscribers: Mutex<Vec<mpsc::Sender<DenialEvent>>>,
+}
+
+impl DenialAlerts {
+    /// Alert after `config.threshold` denials within `config.window` seconds
+    pub fn new(config: &DenialAlertsConfig) -> Self {
+        DenialAlerts {
+            threshold: config.threshold,
+            window: Duration::from_secs(config.window),
+            keys: Mutex::new(HashMap::new()),
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Receive every alert from now on
+    ///
+    /// Alerts are dropped for this subscriber while its queue is full, and
+    /// it is forgotten once the receiver is dropped.
+    pub fn subscribe(&self) -> mpsc::Receiver<DenialEvent> {
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_BUFFER);
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(tx);
+        rx
+    }
+
+    /// Count a denial of `key` at `now`, returning the alert if it crossed
+    /// the threshold
+    pub fn record(&self, key: &Arc<str>, now: SystemTime) -> Option<DenialEvent> {
+        let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        let expired = |denials: &Denials| {
+            now.duration_since(denials.first_denied)
+                .is_ok_and(|elapsed| elapsed >= self.window)
+        };
+        if keys.len() >= MAX_TRACKED_KEYS && !keys.contains_key(key) {
+            keys.retain(|_, denials| !expired(denials));
+            if keys.len() >= MAX_TRACKED_KEYS {
+                return None;
+            }
+        }
+
+        let denials = keys.entry(Arc::clone(key)).or_insert(Denials {
+            first_denied: now,
+            count: 0,
+        });
+        if expired(denials) {
+            *denials = Denials {
+                first_denied: now,
+                count: 0,
+            };
+        }
+        denials.count += 1;
+        (denials.count == self.threshold).then(|| self.event(key, *denials, now))
+    }
+
+    /// Deliver `event` to every subscriber
+    pub fn publish(&self, event: &DenialEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        subscribers.retain(|tx| match tx.try_send(event.clone()) {
+            Ok(()) | Err(mpsc::error::TrySendError::Full(_)) => true,
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        });
+    }
+
+    fn event(&self, key: &str, denials: Denials, now: SystemTime) -> DenialEvent {
+        let (namespace, key) = match key.split_once(SEPARATOR) {
+            Some((namespace, key)) => (Some(namespace.to_string()), key),
+            None => (None, key),
+        };
+        DenialEvent {
+            key: key.to_string(),
+            namespace,
+            denials: denials.count,
+            window: self.window.as_secs(),
+            first_denied_ms: millis_since_epoch(denials.first_denied),
+            timestamp_ms: millis_since_epoch(now),
+        }
+    }
+}
+
+impl DecisionHook for DenialAlerts {
+    fn on_denied(&self, ctx: &DecisionContext) {
+        if let Some(event) = self.record(&ctx.request.key, ctx.request.timestamp) {
+            tracing::warn!(
+                key = %ctx.request.key,
+                denials = event.denials,
+                window = event.window,
+                "Key crossed the denial threshold"
+            );
+            self.publish(&event);
+        }
+    }
+}
+
+fn millis_since_epoch(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// POST every alert `alerts` emits to `url`, from a background task
+///
+/// # Errors
+///
+/// Returns an error if the server was built without the `webhook` feature.
+pub(crate) fn start_webhook(url: &str, alerts: &DenialAlerts) -> Result<()> {
+    #[cfg(feature = "webhook")]
+    {
+        tokio::spawn(webhook::post_all(url.to_string(), alerts.subscribe()));
+        Ok(())
+    }
+    #[cfg(not(feature = "webhook"))]
+    {
+        let _ = (url, alerts);
+        Err(anyhow::anyhow!(
+            "--denial-webhook is not available, rebuild with `--features webhook`"
+        ))
+    }
+}
+
+#[cfg(feature = "webhook")]
+mod webhook {
+    use super::DenialEvent;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub(super) async fn post_all(url: String, mut rx: mpsc::Receiver<DenialEvent>) {
+        let client = match reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::error!("Failed to create denial webhook client: {}", e);
+                return;
+            }
+        };
+
+        while let Some(event) = rx.recv().await {
+            let result = client
+                .post(&url)
+                .json(&event)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = result {
+                tracing::warn!("Failed to deliver denial alert to {}: {}", url, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alerts(threshold: u64, window: u64) -> DenialAlerts {
+        DenialAlerts::new(&DenialAlertsConfig {
+            threshold,
+            window,
+            webhook: None,
+        })
+    }
+
+    #[test]
+    fn test_threshold() {
+        let alerts = alerts(3, 60);
+        let key: Arc<str> = "tenant/login:alice".into();
+        let start = UNIX_EPOCH + Duration::from_secs(1_704_067_200);
+
+        assert_eq!(alerts.record(&key, start), None);
+        assert_eq!(alerts.record(&key, start + Duration::from_secs(1)), None);
+        let event = alerts.record(&key, start + Duration::from_secs(2)).unwrap();
+        assert_eq!(event.key, "login:alice");
+        assert_eq!(event.namespace.as_deref(), Some("tenant"));
+        assert_eq!(event.denials, 3);
+        assert_eq!(event.window, 60);
+        assert_eq!(event.first_denied_ms, 1_704_067_200_000);
+        assert_eq!(event.timestamp_ms, 1_704_067_202_000);
+
+        // Once per window
+        assert_eq!(alerts.record(&key, start + Duration::from_secs(3)), None);
+
+        // The count restarts after the window
+        let later = start + Duration::from_secs(60);
+        assert_eq!(alerts.record(&key, later), None);
+        assert_eq!(alerts.record(&key, later), None);
+        let event = alerts.record(&key, later).unwrap();
+        assert_eq!(event.first_denied_ms, 1_704_067_260_000);
+
+        // Keys without a namespace
+        let single = super::tests::alerts(1, 60);
+        let event = single.record(&"login:bob".into(), start).unwrap();
+        assert_eq!((event.key.as_str(), event.namespace), ("login:bob", None));
+    }
+
+    #[tokio::test]
+    async fn test_subscribers() {
+        let alerts = alerts(1, 60);
+        let mut first = alerts.subscribe();
+        let second = alerts.subscribe();
+        drop(second);
+
+        let event = alerts.record(&"key".into(), SystemTime::now()).unwrap();
+        alerts.publish(&event);
+        assert_eq!(first.recv().await.unwrap(), event);
+        // Dropped subscribers are forgotten
+        assert_eq!(alerts.subscribers.lock().unwrap().len(), 1);
+    }
+}
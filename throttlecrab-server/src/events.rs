@@ -0,0 +1,498 @@
+//! Rate limit decision export to message brokers
+//!
+//! This module publishes a sample of rate limiting decisions to Kafka or NATS
+//! so downstream pipelines (abuse detection, analytics) can consume
+//! throttling signals in near real time.
+//!
+//! # Architecture
+//!
+//! ```text
+//! RateLimiterHandle ──try_send──> [bounded channel] ──> Exporter task ──batch──> Sink
+//! ```
+//!
+//! Publishing never blocks the request path: events are sampled before any
+//! allocation, and if the channel is full the event is dropped and counted
+//! in `throttlecrab_events_dropped`. The exporter task batches events by
+//! size and time before handing them to the sink.
+//!
+//! # Sinks
+//!
+//! - **NATS** (`nats` feature): one message per event on the configured subject
+//! - **Kafka** (`kafka` feature): one record per event, keyed by the rate
+//!   limit key and spread across the topic's partitions by key hash
+//!
+//! The default build includes neither sink; selecting one that was not
+//! compiled in is a configuration error.
+
+use crate::config::{EventFormat, EventSinkType, EventsConfig};
+use crate::metrics::Metrics;
+use crate::transport::grpc::throttlecrab_proto::DecisionEvent as ProtoDecisionEvent;
+use crate::types::ThrottleResponse;
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use prost::Message;
+use serde::Serialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+/// A single rate limiting decision
+#[derive(Debug, Clone, Serialize)]
+pub struct DecisionEvent {
+    /// The rate limited key
+    pub key: Arc<str>,
+    /// Whether the request was allowed
+    pub allowed: bool,
+    /// Maximum burst capacity
+    pub limit: i64,
+    /// Tokens remaining in the bucket
+    pub remaining: i64,
+    /// Seconds until the next request can be made (0 if allowed)
+    pub retry_after: i64,
+    /// Seconds until the bucket fully resets
+    pub reset_after: i64,
+    /// Decision time in milliseconds since the Unix epoch
+    pub timestamp_ms: i64,
+}
+
+impl DecisionEvent {
+    /// Build an event from a throttle response
+    pub fn new(key: Arc<str>, response: &ThrottleResponse, timestamp: SystemTime) -> Self {
+        let timestamp_ms = timestamp
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        DecisionEvent {
+            key,
+            allowed: response.allowed,
+            limit: response.limit,
+            remaining: response.remaining,
+            retry_after: response.retry_after,
+            reset_after: response.reset_after,
+            timestamp_ms,
+        }
+    }
+
+    /// Serialize the event in the given wire format
+    pub fn encode(&self, format: EventFormat) -> Result<Bytes> {
+        match format {
+            EventFormat::Json => Ok(Bytes::from(serde_json::to_vec(self)?)),
+            EventFormat::Protobuf => {
+                let proto = ProtoDecisionEvent {
+                    key: self.key.to_string(),
+                    allowed: self.allowed,
+                    limit: self.limit,
+                    remaining: self.remaining,
+                    retry_after: self.retry_after,
+                    reset_after: self.reset_after,
+                    timestamp_ms: self.timestamp_ms,
+                };
+                Ok(Bytes::from(proto.encode_to_vec()))
+            }
+        }
+    }
+}
+
+/// An event serialized for a sink, with its key kept for partitioning
+#[derive(Debug, Clone)]
+pub struct EncodedEvent {
+    /// The rate limited key
+    pub key: String,
+    /// Decision time in milliseconds since the Unix epoch
+    pub timestamp_ms: i64,
+    /// Serialized event
+    pub payload: Bytes,
+}
+
+/// Destination for batches of decision events
+#[async_trait]
+pub trait EventSink: Send + 'static {
+    /// Publish a batch of events
+    async fn publish(&mut self, batch: Vec<EncodedEvent>) -> Result<()>;
+}
+
+/// Cloneable handle for submitting decision events to the exporter
+///
+/// Sampling is decided with [`EventPublisher::should_sample`] before the
+/// event is built, so unsampled decisions cost a single atomic increment.
+#[derive(Clone)]
+pub struct EventPublisher {
+    tx: mpsc::Sender<DecisionEvent>,
+    sample_threshold: u64,
+    counter: Arc<AtomicU64>,
+    metrics: Arc<Metrics>,
+}
+
+impl EventPublisher {
+    fn new(tx: mpsc::Sender<DecisionEvent>, sample_rate: f64, metrics: Arc<Metrics>) -> Self {
+        let sample_threshold = if sample_rate >= 1.0 {
+            u64::MAX
+        } else {
+            (sample_rate.max(0.0) * u64::MAX as f64) as u64
+        };
+        EventPublisher {
+            tx,
+            sample_threshold,
+            counter: Arc::new(AtomicU64::new(0)),
+            metrics,
+        }
+    }
+
+    /// Decide whether the next decision should be exported
+    pub fn should_sample(&self) -> bool {
+        if self.sample_threshold == u64::MAX {
+            return true;
+        }
+        // Multiplicative hashing of a counter spreads samples uniformly
+        // without a random number generator on the hot path
+        let n = self.counter.fetch_add(1, Ordering::Relaxed);
+        n.wrapping_mul(0x9E37_79B9_7F4A_7C15) < self.sample_threshold
+    }
+
+    /// Submit an event without waiting
+    ///
+    /// The event is dropped if the exporter is falling behind.
+    pub fn publish(&self, event: DecisionEvent) {
+        if self.tx.try_send(event).is_err() {
+            self.metrics.events_dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Connect to the configured sink and start the exporter task
+///
+/// # Errors
+///
+/// Returns an error if the sink cannot be reached or was not compiled in.
+pub async fn start(config: &EventsConfig, metrics: Arc<Metrics>) -> Result<EventPublisher> {
+    let sink = connect(config).await?;
+    Ok(spawn(config, sink, metrics))
+}
+
+async fn connect(config: &EventsConfig) -> Result<Box<dyn EventSink>> {
+    match config.sink {
+        EventSinkType::Nats => {
+            #[cfg(feature = "nats")]
+            return Ok(Box::new(
+                nats::NatsSink::connect(&config.url, &config.topic).await?,
+            ));
+            #[cfg(not(feature = "nats"))]
+            Err(anyhow::anyhow!(
+                "NATS event sink is not available, rebuild with `--features nats`"
+            ))
+        }
+        EventSinkType::Kafka => {
+            #[cfg(feature = "kafka")]
+            return Ok(Box::new(
+                kafka::KafkaSink::connect(&config.url, &config.topic).await?,
+            ));
+            #[cfg(not(feature = "kafka"))]
+            Err(anyhow::anyhow!(
+                "Kafka event sink is not available, rebuild with `--features kafka`"
+            ))
+        }
+    }
+}
+
+/// Start the exporter task for an already connected sink
+pub fn spawn(
+    config: &EventsConfig,
+    sink: Box<dyn EventSink>,
+    metrics: Arc<Metrics>,
+) -> EventPublisher {
+    let (tx, rx) = mpsc::channel(config.buffer_size);
+    let exporter = Exporter {
+        sink,
+        format: config.format,
+        batch_size: config.batch_size,
+        flush_interval: Duration::from_millis(config.flush_interval_ms),
+        metrics: Arc::clone(&metrics),
+    };
+    tokio::spawn(exporter.run(rx));
+    EventPublisher::new(tx, config.sample_rate, metrics)
+}
+
+struct Exporter {
+    sink: Box<dyn EventSink>,
+    format: EventFormat,
+    batch_size: usize,
+    flush_interval: Duration,
+    metrics: Arc<Metrics>,
+}
+
+impl Exporter {
+    async fn run(mut self, mut rx: mpsc::Receiver<DecisionEvent>) {
+        let mut batch = Vec::with_capacity(self.batch_size);
+        let mut ticker = tokio::time::interval(self.flush_interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                event = rx.recv() => {
+                    let Some(event) = event else {
+                        // All publishers are gone, flush what is left and stop
+                        self.flush(&mut batch).await;
+                        return;
+                    };
+                    match event.encode(self.format) {
+                        Ok(payload) => batch.push(EncodedEvent {
+                            key: event.key.to_string(),
+                            timestamp_ms: event.timestamp_ms,
+                            payload,
+                        }),
+                        Err(e) => {
+                            tracing::warn!("Failed to encode decision event: {}", e);
+                            self.metrics.events_dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    if batch.len() >= self.batch_size {
+                        self.flush(&mut batch).await;
+                    }
+                }
+                _ = ticker.tick() => {
+                    self.flush(&mut batch).await;
+                }
+            }
+        }
+    }
+
+    async fn flush(&mut self, batch: &mut Vec<EncodedEvent>) {
+        if batch.is_empty() {
+            return;
+        }
+        let events = std::mem::replace(batch, Vec::with_capacity(self.batch_size));
+        let count = events.len() as u64;
+        match self.sink.publish(events).await {
+            Ok(()) => {
+                self.metrics
+                    .events_published
+                    .fetch_add(count, Ordering::Relaxed);
+            }
+            Err(e) => {
+                tracing::warn!("Failed to publish {} decision events: {}", count, e);
+                self.metrics
+                    .events_dropped
+                    .fetch_add(count, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "nats")]
+mod nats {
+    use super::{EncodedEvent, EventSink};
+    use anyhow::Result;
+    use async_trait::async_trait;
+
+    /// Publishes each event as a NATS message on a fixed subject
+    pub struct NatsSink {
+        client: async_nats::Client,
+        subject: String,
+    }
+
+    impl NatsSink {
+        pub async fn connect(url: &str, subject: &str) -> Result<Self> {
+            let client = async_nats::connect(url).await?;
+            Ok(NatsSink {
+                client,
+                subject: subject.to_string(),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl EventSink for NatsSink {
+        async fn publish(&mut self, batch: Vec<EncodedEvent>) -> Result<()> {
+            for event in batch {
+                self.client
+                    .publish(self.subject.clone(), event.payload)
+                    .await?;
+            }
+            self.client.flush().await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "kafka")]
+mod kafka {
+    use super::{EncodedEvent, EventSink};
+    use anyhow::{Result, anyhow};
+    use async_trait::async_trait;
+    use rskafka::chrono::DateTime;
+    use rskafka::client::ClientBuilder;
+    use rskafka::client::partition::{Compression, PartitionClient, UnknownTopicHandling};
+    use rskafka::record::Record;
+    use std::collections::BTreeMap;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    /// Produces each event as a Kafka record keyed by the rate limit key
+    pub struct KafkaSink {
+        partitions: Vec<PartitionClient>,
+    }
+
+    impl KafkaSink {
+        /// Connect to a comma-separated list of bootstrap brokers
+        pub async fn connect(brokers: &str, topic: &str) -> Result<Self> {
+            let brokers = brokers
+                .split(',')
+                .map(|b| b.trim().to_string())
+                .filter(|b| !b.is_empty())
+                .collect();
+            let client = ClientBuilder::new(brokers).build().await?;
+
+            let partition_ids = client
+                .list_topics()
+                .await?
+                .into_iter()
+                .find(|t| t.name == topic)
+                .map(|t| t.partitions)
+                .ok_or_else(|| anyhow!("Kafka topic '{topic}' does not exist"))?;
+
+            let mut partitions = Vec::with_capacity(partition_ids.len());
+            for id in partition_ids {
+                partitions.push(
+                    client
+                        .partition_client(topic, id, UnknownTopicHandling::Error)
+                        .await?,
+                );
+            }
+            if partitions.is_empty() {
+                return Err(anyhow!("Kafka topic '{topic}' has no partitions"));
+            }
+
+            Ok(KafkaSink { partitions })
+        }
+    }
+
+    #[async_trait]
+    impl EventSink for KafkaSink {
+        async fn publish(&mut self, batch: Vec<EncodedEvent>) -> Result<()> {
+            let mut by_partition: Vec<Vec<Record>> = vec![Vec::new(); self.partitions.len()];
+            for event in batch {
+                let mut hasher = DefaultHasher::new();
+                event.key.hash(&mut hasher);
+                let partition = (hasher.finish() % self.partitions.len() as u64) as usize;
+                by_partition[partition].push(Record {
+                    key: Some(event.key.into_bytes()),
+                    value: Some(event.payload.to_vec()),
+                    headers: BTreeMap::new(),
+                    timestamp: DateTime::from_timestamp_millis(event.timestamp_ms)
+                        .unwrap_or(DateTime::UNIX_EPOCH),
+                });
+            }
+
+            for (client, records) in self.partitions.iter().zip(by_partition) {
+                client.produce(records, Compression::NoCompression).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct CollectingSink {
+        batches: Arc<Mutex<Vec<Vec<EncodedEvent>>>>,
+    }
+
+    #[async_trait]
+    impl EventSink for CollectingSink {
+        async fn publish(&mut self, batch: Vec<EncodedEvent>) -> Result<()> {
+            self.batches.lock().unwrap().push(batch);
+            Ok(())
+        }
+    }
+
+    fn test_config(batch_size: usize, sample_rate: f64) -> EventsConfig {
+        EventsConfig {
+            sink: EventSinkType::Nats,
+            url: String::new(),
+            topic: "test".to_string(),
+            format: EventFormat::Json,
+            sample_rate,
+            batch_size,
+            flush_interval_ms: 60_000,
+            buffer_size: 100,
+        }
+    }
+
+    fn event(key: &str) -> DecisionEvent {
+        let response = ThrottleResponse {
+            allowed: false,
+            limit: 10,
+            remaining: 0,
+            reset_after: 60,
+            retry_after: 6,
+            retry_after_ms: 6_000,
+            reset_after_ms: 60_000,
+            cacheable_until_ms: 0,
+            warning: false,
+        };
+        DecisionEvent::new(key.into(), &response, UNIX_EPOCH + Duration::from_secs(1))
+    }
+
+    #[test]
+    fn test_json_encoding() {
+        let payload = event("user:1").encode(EventFormat::Json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(json["key"], "user:1");
+        assert_eq!(json["allowed"], false);
+        assert_eq!(json["retry_after"], 6);
+        assert_eq!(json["timestamp_ms"], 1000);
+    }
+
+    #[test]
+    fn test_protobuf_encoding() {
+        let payload = event("user:1").encode(EventFormat::Protobuf).unwrap();
+        let decoded = ProtoDecisionEvent::decode(payload).unwrap();
+        assert_eq!(decoded.key, "user:1");
+        assert_eq!(decoded.limit, 10);
+        assert_eq!(decoded.timestamp_ms, 1000);
+    }
+
+    #[test]
+    fn test_sampling_rate() {
+        let (tx, _rx) = mpsc::channel(1);
+        let metrics = Arc::new(Metrics::new());
+
+        let none = EventPublisher::new(tx.clone(), 0.0, Arc::clone(&metrics));
+        assert!((0..1000).all(|_| !none.should_sample()));
+
+        let all = EventPublisher::new(tx.clone(), 1.0, Arc::clone(&metrics));
+        assert!((0..1000).all(|_| all.should_sample()));
+
+        let tenth = EventPublisher::new(tx, 0.1, metrics);
+        let sampled = (0..10_000).filter(|_| tenth.should_sample()).count();
+        assert!((800..1200).contains(&sampled), "sampled {sampled}");
+    }
+
+    #[tokio::test]
+    async fn test_exporter_batches_by_size() {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let sink = CollectingSink {
+            batches: Arc::clone(&batches),
+        };
+        let metrics = Arc::new(Metrics::new());
+        let publisher = spawn(&test_config(2, 1.0), Box::new(sink), Arc::clone(&metrics));
+
+        for key in ["a", "b", "c"] {
+            publisher.publish(event(key));
+        }
+        // Dropping the last publisher flushes the partial batch
+        drop(publisher);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let batches = batches.lock().unwrap();
+        let sizes: Vec<usize> = batches.iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![2, 1]);
+        assert_eq!(batches[1][0].key, "c");
+        assert_eq!(metrics.events_published.load(Ordering::Relaxed), 3);
+    }
+}
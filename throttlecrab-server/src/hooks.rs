@@ -0,0 +1,319 @@
+//! Post-decision hooks
+//!
+//! A [`DecisionHook`] is called after every rate limiting decision, with the
+//! request and the response. Hooks are compiled into the binary that embeds
+//! the server and registered with [`ServerBuilder::hook`], which makes them
+//! the place for custom integrations such as audit trails, bespoke metrics
+//! or feeding a blocklist, without touching transport code.
+//!
+//! Hooks run inline on the request path of whichever transport handled the
+//! request, after the decision is made and before the response is sent.
+//! They must be cheap and must not block: hand slow work to a background
+//! task, as `WebhookHook` does.
+//!
+//! Two reference hooks are included:
+//!
+//! - [`LogHook`] logs decisions with `tracing`
+//! - `WebhookHook` (`webhook` feature) POSTs decisions as JSON to a URL
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::sync::atomic::{AtomicU64, Ordering};
+//! use throttlecrab_server::Server;
+//! use throttlecrab_server::hooks::{DecisionContext, DecisionHook, LogHook};
+//!
+//! #[derive(Default)]
+//! struct DenialCounter(AtomicU64);
+//!
+//! impl DecisionHook for DenialCounter {
+//!     fn on_denied(&self, _ctx: &DecisionContext) {
+//!         self.0.fetch_add(1, Ordering::Relaxed);
+//!     }
+//! }
+//!
+//! # fn build() -> anyhow::Result<()> {
+//! let server = Server::builder()
+//!     .http("127.0.0.1", 8080)
+//!     .hook(LogHook::new())
+//!     .hook(DenialCounter::default())
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`ServerBuilder::hook`]: crate::ServerBuilder::hook
+
+use crate::types::{ThrottleRequest, ThrottleResponse};
+
+#[cfg(feature = "webhook")]
+pub use webhook::WebhookHook;
+
+/// A rate limiting decision, as seen by a [`DecisionHook`]
+#[derive(Debug, Clone, Copy)]
+pub struct DecisionContext<'a> {
+    /// The request that was rate limited
+    pub request: &'a ThrottleRequest,
+    /// The decision made for it
+    pub response: &'a ThrottleResponse,
+}
+
+/// Callback invoked after each rate limiting decision
+///
+/// Both methods default to doing nothing, so a hook only implements the
+/// outcome it cares about. See the [module documentation](self) for the
+/// constraints hooks run under.
+pub trait DecisionHook: Send + Sync + 'static {
+    /// Called after a request was allowed
+    fn on_allowed(&self, _ctx: &DecisionContext) {}
+
+    /// Called after a request was denied
+    fn on_denied(&self, _ctx: &DecisionContext) {}
+}
+
+/// Dispatch a decision to every hook in `hooks`
+pub(crate) fn dispatch(
+    hooks: &[std::sync::Arc<dyn DecisionHook>],
+    request: &ThrottleRequest,
+    response: &ThrottleResponse,
+) {
+    let ctx = DecisionContext { request, response };
+    for hook in hooks {
+        if response.allowed {
+            hook.on_allowed(&ctx);
+        } else {
+            hook.on_denied(&ctx);
+        }
+    }
+}
+
+/// Logs decisions with `tracing`
+///
+/// Denied requests are logged at `info` and allowed requests at `debug`, so
+/// the default log level only shows denials.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LogHook;
+
+impl LogHook {
+    /// Create a logging hook
+    pub fn new() -> Self {
+        LogHook
+    }
+}
+
+impl DecisionHook for LogHook {
+    fn on_allowed(&self, ctx: &DecisionContext) {
+        tracing::debug!(
+            key = %ctx.request.key,
+            quantity = ctx.request.quantity,
+            remaining = ctx.response.remaining,
+            "Request allowed"
+        );
+    }
+
+    fn on_denied(&self, ctx: &DecisionContext) {
+        tracing::info!(
+            key = %ctx.request.key,
+            quantity = ctx.request.quantity,
+            limit = ctx.response.limit,
+            retry_after = ctx.response.retry_after,
+            "Request denied"
+        );
+    }
+}
+
+#[cfg(feature = "webhook")]
+mod webhook {
+    use super::{DecisionContext, DecisionHook};
+    use crate::events::DecisionEvent;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    const DEFAULT_BUFFER_SIZE: usize = 1024;
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// POSTs decisions as JSON to a URL
+    ///
+    /// Each decision is sent as one [`DecisionEvent`] in the request body.
+    /// Only denials are sent unless [`include_allowed`] is set. Requests
+    /// are made by a background task; if it falls behind, decisions are
+    /// dropped and counted in [`dropped`]. Failed requests are logged and
+    /// not retried.
+    ///
+    /// [`include_allowed`]: WebhookHook::include_allowed
+    /// [`dropped`]: WebhookHook::dropped
+    pub struct WebhookHook {
+        tx: mpsc::Sender<DecisionEvent>,
+        include_allowed: bool,
+        dropped: Arc<AtomicU64>,
+    }
+
+    impl WebhookHook {
+        /// Create a hook posting to `url`
+        ///
+        /// Must be called within a Tokio runtime, which runs the sender task.
+        pub fn new(url: impl Into<String>) -> Self {
+            Self::with_buffer_size(url, DEFAULT_BUFFER_SIZE)
+        }
+
+        /// Create a hook that queues up to `buffer_size` unsent decisions
+        pub fn with_buffer_size(url: impl Into<String>, buffer_size: usize) -> Self {
+            let (tx, rx) = mpsc::channel(buffer_size);
+            let dropped = Arc::new(AtomicU64::new(0));
+            tokio::spawn(send_all(url.into(), rx, Arc::clone(&dropped)));
+            WebhookHook {
+                tx,
+                include_allowed: false,
+                dropped,
+            }
+        }
+
+        /// Send allowed decisions as well as denials
+        pub fn include_allowed(mut self, include: bool) -> Self {
+            self.include_allowed = include;
+            self
+        }
+
+        /// Number of decisions that were not delivered
+        pub fn dropped(&self) -> u64 {
+            self.dropped.load(Ordering::Relaxed)
+        }
+
+        fn send(&self, ctx: &DecisionContext) {
+            let event = DecisionEvent::new(
+                Arc::clone(&ctx.request.key),
+                ctx.response,
+                ctx.request.timestamp,
+            );
+            if self.tx.try_send(event).is_err() {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    impl DecisionHook for WebhookHook {
+        fn on_allowed(&self, ctx: &DecisionContext) {
+            if self.include_allowed {
+                self.send(ctx);
+            }
+        }
+
+        fn on_denied(&self, ctx: &DecisionContext) {
+            self.send(ctx);
+        }
+    }
+
+    async fn send_all(url: String, mut rx: mpsc::Receiver<DecisionEvent>, dropped: Arc<AtomicU64>) {
+        let client = match reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::error!("Failed to create webhook client: {}", e);
+                return;
+            }
+        };
+
+        while let Some(event) = rx.recv().await {
+            let result = client
+                .post(&url)
+                .json(&event)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = result {
+                tracing::warn!("Failed to deliver decision to webhook {}: {}", url, e);
+                dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor::RateLimiterActor;
+    use crate::metrics::Metrics;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Recorder {
+        allowed: AtomicU64,
+        denied: Mutex<Vec<(String, i64)>>,
+    }
+
+    impl DecisionHook for Recorder {
+        fn on_allowed(&self, _ctx: &DecisionContext) {
+            self.allowed.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn on_denied(&self, ctx: &DecisionContext) {
+            self.denied
+                .lock()
+                .unwrap()
+                .push((ctx.request.key.to_string(), ctx.response.retry_after));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hooks_see_every_decision() {
+        let recorder = Arc::new(Recorder::default());
+        let limiter = RateLimiterActor::spawn_periodic(
+            100,
+            throttlecrab::PeriodicStore::new(),
+            Arc::new(Metrics::new()),
+        )
+        .with_hooks(vec![recorder.clone(), Arc::new(LogHook::new())]);
+
+        for _ in 0..3 {
+            limiter
+                .throttle(ThrottleRequest::new("hooked", 2, 10, 60))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(recorder.allowed.load(Ordering::Relaxed), 2);
+        let denied = recorder.denied.lock().unwrap();
+        assert_eq!(denied.len(), 1);
+        assert_eq!(denied[0].0, "hooked");
+        assert!(denied[0].1 > 0);
+    }
+
+    #[cfg(feature = "webhook")]
+    #[tokio::test]
+    async fn test_webhook_posts_denials() {
+        use axum::{Json, Router, routing::post};
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<serde_json::Value>();
+        let app = Router::new().route(
+            "/hook",
+            post(move |Json(body): Json<serde_json::Value>| async move {
+                tx.send(body).unwrap();
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let limiter = RateLimiterActor::spawn_periodic(
+            100,
+            throttlecrab::PeriodicStore::new(),
+            Arc::new(Metrics::new()),
+        )
+        .with_hooks(vec![Arc::new(WebhookHook::new(url))]);
+
+        for _ in 0..3 {
+            limiter
+                .throttle(ThrottleRequest::new("webhook", 2, 10, 60))
+                .await
+                .unwrap();
+        }
+
+        // Only the denial is posted
+        let body = rx.recv().await.unwrap();
+        assert_eq!(body["key"], "webhook");
+        assert_eq!(body["allowed"], false);
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,101 @@
+//! Most requested keys, for `GET /admin/keys/hot`
+//!
+//! With `--hot-keys N` each actor counts the throttle requests for its keys
+//! and keeps the `N` most requested. Like the top denied keys in the
+//! metrics, the counts grow to three times `N` before the least requested
+//! are dropped, so the sort is amortized over many requests. A key dropped
+//! this way starts counting from zero again, which makes the counts of keys
+//! near the bottom approximate; the top keys are exact once they stay in.
+//!
+//! A key lives on one actor, so the top keys of all actors together are the
+//! server's top keys.
+
+use crate::types::HotKey;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// One actor's most requested keys
+pub(crate) struct HotKeys {
+    counts: HashMap<Arc<str>, u64>,
+    max_size: usize,
+}
+
+impl HotKeys {
+    /// Keep the `max_size` most requested keys
+    pub(crate) fn new(max_size: usize) -> Self {
+        HotKeys {
+            counts: HashMap::with_capacity(max_size * 2),
+            max_size,
+        }
+    }
+
+    /// Count a request for `key`
+    pub(crate) fn record(&mut self, key: &Arc<str>) {
+        match self.counts.get_mut(key) {
+            Some(count) => *count += 1,
+            None => {
+                self.counts.insert(Arc::clone(key), 1);
+                if self.counts.len() > self.max_size * 3 {
+                    self.trim();
+                }
+            }
+        }
+    }
+
+    /// The `limit` most requested keys, most requested first
+    pub(crate) fn top(&self, limit: usize) -> Vec<HotKey> {
+        let mut top: Vec<_> = self
+            .counts
+            .iter()
+            .map(|(key, &requests)| HotKey {
+                key: key.to_string(),
+                requests,
+            })
+            .collect();
+        sort(&mut top);
+        top.truncate(limit.min(self.max_size));
+        top
+    }
+
+    /// Drop all but the `max_size` most requested keys
+    fn trim(&mut self) {
+        let mut entries: Vec<_> = self.counts.drain().collect();
+        entries.sort_unstable_by_key(|(_, count)| std::cmp::Reverse(*count));
+        entries.truncate(self.max_size);
+        self.counts = entries.into_iter().collect();
+    }
+}
+
+/// Order `keys` most requested first, then by key for a stable listing
+pub(crate) fn sort(keys: &mut [HotKey]) {
+    keys.sort_unstable_by(|a, b| b.requests.cmp(&a.requests).then_with(|| a.key.cmp(&b.key)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_keys() {
+        let mut hot_keys = HotKeys::new(2);
+        let (a, b, c): (Arc<str>, Arc<str>, Arc<str>) = ("a".into(), "b".into(), "c".into());
+        for _ in 0..3 {
+            hot_keys.record(&a);
+        }
+        hot_keys.record(&b);
+        hot_keys.record(&b);
+        hot_keys.record(&c);
+
+        let top = hot_keys.top(10);
+        let top: Vec<_> = top.iter().map(|k| (k.key.as_str(), k.requests)).collect();
+        assert_eq!(top, [("a", 3), ("b", 2)]);
+        assert_eq!(hot_keys.top(1)[0].key, "a");
+
+        // Rarely requested keys are dropped once the counts outgrow the limit
+        for i in 0..10 {
+            hot_keys.record(&Arc::from(format!("once:{i}")));
+        }
+        assert!(hot_keys.counts.len() <= 6);
+        assert_eq!(hot_keys.top(2)[0].key, "a");
+    }
+}
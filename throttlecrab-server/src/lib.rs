@@ -44,7 +44,8 @@
 //!
 //! ## Configuration
 //!
-//! Configure via CLI arguments or environment variables (CLI takes precedence):
+//! Configure via CLI arguments, environment variables or a TOML file given with
+//! `--config` (CLI takes precedence over environment, environment over the file):
 //!
 //! ```bash
 //! # Via CLI
@@ -67,6 +68,7 @@
 //! - **Store Type**: `--store periodic|probabilistic|adaptive`
 //! - **Store Capacity**: `--store-capacity 100000`
 //! - **Log Level**: `--log-level error|warn|info|debug|trace`
+//! - **Log Format**: `--log-format text|json`
 //!
 //! ## How It Works
 //!
@@ -141,13 +143,61 @@
 //!
 //! #### gRPC Protocol
 //! Use any gRPC client library with the provided protobuf definitions.
+//!
+//! ## Embedding
+//!
+//! The full server can also run inside your own binary; see [`Server`].
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! throttlecrab_server::Server::builder()
+//!     .http("127.0.0.1", 8080)
+//!     .build()?
+//!     .serve(async {
+//!         tokio::signal::ctrl_c().await.ok();
+//!     })
+//!     .await
+//! # }
+//! ```
 
 pub mod actor;
+pub mod auth;
+mod auto_store;
+pub mod budget;
+mod canary;
+mod cluster;
 pub mod config;
+pub mod config_file;
+pub mod denials;
+pub mod events;
+pub mod hooks;
+mod hot_keys;
+mod logging;
 pub mod metrics;
+mod metrics_push;
+pub mod micro_cache;
+pub mod namespace;
+pub mod otel;
+pub mod overrides;
+pub mod policy;
+mod probe;
+pub mod repl;
+mod server;
+pub mod shed;
+mod snapshot;
+pub mod status;
 pub mod store;
+pub mod trace;
 pub mod transport;
 pub mod types;
+pub mod validation;
+mod wal;
+pub mod windows;
+
+pub use server::{Server, ServerBuilder};
+
+#[cfg(test)]
+mod actor_tests;
 
 // Re-export grpc types for tests
 pub mod grpc {
@@ -0,0 +1,101 @@
+//! Per-request log events
+//!
+//! Every rate limit decision is logged at `debug` under the
+//! `throttlecrab::requests` target, with these fields:
+//!
+//! - `transport`: `http`, `grpc` or `redis`
+//! - `key_hash`: 16 hex digits hashing the key, so events for one key can be
+//!   correlated without writing the key itself to the logs
+//! - `allowed`: the decision
+//! - `latency_us`: time spent deciding, in microseconds; for a batch, the
+//!   time to decide the whole batch
+//!
+//! With `--log-format json` each event is one JSON object per line:
+//!
+//! ```json
+//! {"timestamp":"2025-01-01T00:00:00.000000Z","level":"DEBUG","fields":{"message":"request",
+//!  "transport":"http","key_hash":"5c3ee8d4a3e4a0b1","allowed":true,"latency_us":42},
+//!  "target":"throttlecrab::requests"}
+//! ```
+//!
+//! The events are off at the default `info` level. Enable them alone with
+//! `RUST_LOG=throttlecrab::requests=debug`.
+
+use crate::metrics::Transport;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+/// Log one rate limit decision
+pub(crate) fn log_request(transport: Transport, key: &str, allowed: bool, latency: Duration) {
+    // The fields are only evaluated when the event is enabled
+    tracing::debug!(
+        target: "throttlecrab::requests",
+        transport = transport.name(),
+        key_hash = %key_hash(key),
+        allowed,
+        latency_us = latency.as_micros() as u64,
+        "request"
+    );
+}
+
+/// A stable, fixed-width hash of `key` for log correlation
+fn key_hash(key: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    /// Collects formatted log output
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_request_event() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            log_request(Transport::Redis, "user:1", false, Duration::from_micros(42));
+        });
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let event: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(event["target"], "throttlecrab::requests");
+        assert_eq!(event["level"], "DEBUG");
+        let fields = &event["fields"];
+        assert_eq!(fields["transport"], "redis");
+        assert_eq!(fields["key_hash"], key_hash("user:1"));
+        assert_eq!(fields["allowed"], false);
+        assert_eq!(fields["latency_us"], 42);
+        assert!(!output.contains("user:1"));
+    }
+
+    #[test]
+    fn test_key_hash() {
+        assert_eq!(key_hash("user:1").len(), 16);
+        assert_eq!(key_hash("user:1"), key_hash("user:1"));
+        assert_ne!(key_hash("user:1"), key_hash("user:2"));
+    }
+}
@@ -6,8 +6,10 @@
 //!
 //! # Configuration
 //!
-//! The server can be configured via command-line arguments or environment variables.
-//! See [`config::Config`] for all available options.
+//! The server can be configured via command-line arguments, environment
+//! variables or a TOML file given with `--config`.
+//! See [`config::Config`] for all available options. To embed the server in
+//! another binary, use [`throttlecrab_server::Server`] instead.
 //!
 //! # Example
 //!
@@ -22,163 +24,80 @@
 //!     --redis --redis-port 6379 \
 //!     --store adaptive \
 //!     --buffer-size 100000 \
-//!     --log-level info
+//!     --log-level info \
+//!     --log-format json
+//!
+//! # Explore rate limits interactively, without any transport
+//! throttlecrab-server --store adaptive repl
 //! ```
 
-mod actor;
-mod config;
-mod metrics;
-mod store;
-mod transport;
-mod types;
-
-#[cfg(test)]
-mod actor_tests;
-
 use anyhow::Result;
-use std::sync::Arc;
 use tokio::signal;
-use tokio::task::JoinSet;
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 
-use crate::config::Config;
-use crate::metrics::Metrics;
-use crate::transport::{
-    Transport, grpc::GrpcTransport, http::HttpTransport, redis::RedisTransport,
-};
+use throttlecrab_server::config::{Args, Command, Config, LogFormat};
+use throttlecrab_server::{Server, otel, repl};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Parse configuration from environment variables and CLI arguments
-    let config = Config::from_env_and_args()?;
-
-    // Initialize logging
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive(format!("throttlecrab={}", config.log_level).parse()?),
-        )
-        .init();
-
-    // Create shared metrics instance
-    let metrics = Arc::new(
-        Metrics::builder()
-            .max_denied_keys(config.max_denied_keys as usize)
-            .build(),
-    );
-
-    // Create the rate limiter actor with the configured store
-    let limiter =
-        store::create_rate_limiter(&config.store, config.buffer_size, Arc::clone(&metrics));
-
-    // Create a set to manage multiple transport tasks
-    let mut transport_tasks = JoinSet::new();
-
-    // Start HTTP transport if enabled
-    if let Some(http_config) = &config.transports.http {
-        let limiter_handle = limiter.clone();
-        let host = http_config.host.clone();
-        let port = http_config.port;
-        let metrics_clone = Arc::clone(&metrics);
-
-        transport_tasks.spawn(async move {
-            tracing::info!("Starting HTTP transport on {}:{}", host, port);
-            let transport = HttpTransport::new(&host, port, metrics_clone);
-            transport.start(limiter_handle).await
-        });
+    let args = Args::load()?;
+    if args.command == Some(Command::Repl) {
+        return repl::run(&args.store_config());
     }
+    let config = Config::from_args(args)?;
 
-    // Start gRPC transport if enabled
-    if let Some(grpc_config) = &config.transports.grpc {
-        let limiter_handle = limiter.clone();
-        let host = grpc_config.host.clone();
-        let port = grpc_config.port;
-        let metrics_clone = Arc::clone(&metrics);
-
-        transport_tasks.spawn(async move {
-            tracing::info!("Starting gRPC transport on {}:{}", host, port);
-            let transport = GrpcTransport::new(&host, port, metrics_clone);
-            transport.start(limiter_handle).await
-        });
-    }
+    // Initialize logging
+    let filter = tracing_subscriber::EnvFilter::from_default_env()
+        .add_directive(format!("throttlecrab={}", config.log_level).parse()?);
+    let logs = match config.log_format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer().json().boxed(),
+    };
 
-    // Start Redis transport if enabled
-    if let Some(redis_config) = &config.transports.redis {
-        let limiter_handle = limiter.clone();
-        let host = redis_config.host.clone();
-        let port = redis_config.port;
-        let metrics_clone = Arc::clone(&metrics);
+    // Export request spans if a collector is configured
+    let (spans, exporter) = match &config.otlp {
+        Some(otlp) => {
+            let (layer, exporter) = otel::layer(otlp)?;
+            (Some(layer), Some(exporter))
+        }
+        None => (None, None),
+    };
+    tracing_subscriber::registry()
+        .with(logs.with_filter(filter))
+        .with(spans)
+        .init();
 
-        transport_tasks.spawn(async move {
-            tracing::info!("Starting Redis transport on {}:{}", host, port);
-            let transport = RedisTransport::new(&host, port, metrics_clone)?;
-            transport.start(limiter_handle).await
-        });
+    let result = Server::from_config(config)?.serve(shutdown_signal()).await;
+    if let Some(exporter) = exporter {
+        exporter.shutdown().await;
     }
+    result
+}
 
-    // Wait for shutdown signal or transport task completion
-    let shutdown_signal = async {
-        let ctrl_c = signal::ctrl_c();
-
-        #[cfg(unix)]
-        let sigterm = async {
-            signal::unix::signal(signal::unix::SignalKind::terminate())
-                .expect("Failed to install SIGTERM handler")
-                .recv()
-                .await;
-        };
-
-        #[cfg(not(unix))]
-        let sigterm = std::future::pending::<()>();
+/// Wait for SIGINT (Ctrl+C) or SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = signal::ctrl_c();
 
-        tokio::select! {
-            _ = ctrl_c => {
-                tracing::info!("Received SIGINT (Ctrl+C), initiating graceful shutdown...");
-            }
-            _ = sigterm => {
-                tracing::info!("Received SIGTERM, initiating graceful shutdown...");
-            }
-        }
+    #[cfg(unix)]
+    let sigterm = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
     };
 
-    tokio::select! {
-        _ = shutdown_signal => {
-            tracing::info!("Shutdown signal received, stopping all transports...");
-            transport_tasks.abort_all();
-
-            // Give tasks a moment to clean up
-            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+    #[cfg(not(unix))]
+    let sigterm = std::future::pending::<()>();
 
-            tracing::info!("ThrottleCrab server shutdown complete");
-            return Ok(());
+    tokio::select! {
+        _ = ctrl_c => {
+            tracing::info!("Received SIGINT (Ctrl+C), initiating graceful shutdown...");
         }
-        result = transport_tasks.join_next() => {
-            if let Some(result) = result {
-                match result {
-                    Ok(Ok(())) => {
-                        tracing::info!("Transport task completed successfully");
-                    }
-                    Ok(Err(e)) => {
-                        tracing::error!("Transport task failed: {}", e);
-                        return Err(e);
-                    }
-                    Err(e) => {
-                        tracing::error!("Transport task panicked: {}", e);
-                        return Err(anyhow::anyhow!("Transport task panicked"));
-                    }
-                }
-            }
+        _ = sigterm => {
+            tracing::info!("Received SIGTERM, initiating graceful shutdown...");
         }
     }
-
-    tracing::info!(
-        "ThrottleCrab server started with store type: {:?}",
-        config.store.store_type
-    );
-    tracing::info!(
-        "Store capacity: {}, Buffer size: {}",
-        config.store.capacity,
-        config.buffer_size
-    );
-
-    Ok(())
 }
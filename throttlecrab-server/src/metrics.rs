@@ -2,31 +2,49 @@
 //!
 //! This module provides lightweight metrics collection using atomic counters.
 //! Designed for minimal overhead and zero allocations in the hot path.
+//!
+//! Top denied keys are aggregated on a dedicated thread. The request path
+//! only hands the key to a bounded channel; counting, sorting and cleanup
+//! happen off the hot path. Top requested and allowed keys are optional and
+//! sampled: one request in every `sample_rate` is handed over, and counts
+//! are scaled back up, so the overhead stays bounded at any request rate.
 
-use std::collections::HashMap;
-use std::sync::Mutex;
+use crate::overrides::Override;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::time::Instant;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-/// Maximum length allowed for rate limit keys
-const MAX_KEY_LENGTH: usize = 256;
+/// Longest key counted in the top keys, well under the
+/// [`MAX_KEY_LENGTH`](crate::types::MAX_KEY_LENGTH) requests are held to,
+/// to bound the aggregator's memory
+const MAX_TRACKED_KEY_LENGTH: usize = 256;
 
 /// Maximum number of denied keys that can be tracked
 /// This prevents excessive memory usage (at 10k keys with 3x growth factor,
 /// we could have up to 30k entries temporarily)
 const MAX_DENIED_KEYS_LIMIT: usize = 10_000;
 
-/// Tracks top N denied keys using HashMap for counts
+/// Denied keys that can be queued for the aggregator before samples are dropped
+const DENIED_KEYS_QUEUE_SIZE: usize = 65_536;
+
+/// Maximum number of sampled requested and allowed keys that can be tracked
+const MAX_TOP_KEYS_LIMIT: usize = 10_000;
+
+/// Tracks top N keys using HashMap for counts
 ///
 /// Uses a grow-then-cleanup strategy where the HashMap can grow to 3x the
 /// configured max_size before triggering cleanup. This amortizes the cost
 /// of sorting operations.
-pub(crate) struct TopDeniedKeys {
+pub(crate) struct TopKeys {
     counts: HashMap<String, u64>,
     max_size: usize,
 }
 
-impl TopDeniedKeys {
+impl TopKeys {
     fn new(max_size: usize) -> Self {
         Self {
             counts: HashMap::with_capacity(max_size * 2),
@@ -36,7 +54,7 @@ impl TopDeniedKeys {
 
     fn update(&mut self, key: String) {
         // Validate key length to prevent memory exhaustion
-        if key.len() > MAX_KEY_LENGTH {
+        if key.len() > MAX_TRACKED_KEY_LENGTH {
             return;
         }
 
@@ -63,6 +81,14 @@ impl TopDeniedKeys {
         self.counts = entries.into_iter().collect();
     }
 
+    /// Estimated heap bytes held by the counts
+    fn memory_usage(&self) -> usize {
+        // The table keeps at most 7/8 of its slots occupied, each with a control byte
+        let slots = self.counts.capacity() * 8 / 7;
+        slots * (size_of::<(String, u64)>() + 1)
+            + self.counts.keys().map(String::capacity).sum::<usize>()
+    }
+
     fn get_top(&self) -> Vec<(String, u64)> {
         let mut entries: Vec<_> = self.counts.iter().map(|(k, v)| (k.clone(), *v)).collect();
 
@@ -75,6 +101,377 @@ impl TopDeniedKeys {
     }
 }
 
+/// Which keys a top keys list ranks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TopKeysKind {
+    /// Keys by denied requests, every denial counted
+    Denied,
+    /// Keys by requests, estimated from sampled requests
+    Requested,
+    /// Keys by allowed requests, estimated from sampled requests
+    Allowed,
+}
+
+/// A key in a top keys list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TopKey {
+    pub key: String,
+    /// Requests counted for the key, scaled by the sample rate if sampled
+    pub count: u64,
+}
+
+/// Messages handled by the top keys aggregator thread
+pub(crate) enum TopKeysMessage {
+    /// A request for this key was denied
+    Denied(String),
+    /// A sampled request for this key was decided
+    Sampled { key: String, allowed: bool },
+    /// Reply with the current top keys of a kind, after all earlier
+    /// messages are counted
+    Snapshot(TopKeysKind, mpsc::Sender<Vec<(String, u64)>>),
+    /// Reply with the estimated heap bytes held by the counts
+    MemoryUsage(mpsc::Sender<usize>),
+}
+
+/// Counts kept by the aggregator thread; None for disabled kinds
+struct TopKeysAggregator {
+    denied: Option<TopKeys>,
+    requested: Option<TopKeys>,
+    allowed: Option<TopKeys>,
+}
+
+impl TopKeysKind {
+    /// Lowercase name, as used in query parameters
+    pub fn name(&self) -> &'static str {
+        match self {
+            TopKeysKind::Denied => "denied",
+            TopKeysKind::Requested => "requested",
+            TopKeysKind::Allowed => "allowed",
+        }
+    }
+}
+
+impl TopKeysAggregator {
+    fn top_keys(&self, kind: TopKeysKind) -> Option<&TopKeys> {
+        match kind {
+            TopKeysKind::Denied => self.denied.as_ref(),
+            TopKeysKind::Requested => self.requested.as_ref(),
+            TopKeysKind::Allowed => self.allowed.as_ref(),
+        }
+    }
+}
+
+/// Spawn the aggregator thread, which exits once the sender is dropped
+fn spawn_top_keys_aggregator(aggregator: TopKeysAggregator) -> SyncSender<TopKeysMessage> {
+    let (tx, rx) = mpsc::sync_channel(DENIED_KEYS_QUEUE_SIZE);
+    thread::Builder::new()
+        .name("throttlecrab-metrics".to_string())
+        .spawn(move || run_top_keys_aggregator(rx, aggregator))
+        .expect("Failed to spawn metrics aggregator thread");
+    tx
+}
+
+fn run_top_keys_aggregator(rx: Receiver<TopKeysMessage>, mut aggregator: TopKeysAggregator) {
+    for message in rx {
+        match message {
+            TopKeysMessage::Denied(key) => {
+                if let Some(denied) = &mut aggregator.denied {
+                    denied.update(key);
+                }
+            }
+            TopKeysMessage::Sampled { key, allowed } => {
+                if allowed && let Some(top_allowed) = &mut aggregator.allowed {
+                    top_allowed.update(key.clone());
+                }
+                if let Some(requested) = &mut aggregator.requested {
+                    requested.update(key);
+                }
+            }
+            TopKeysMessage::Snapshot(kind, reply) => {
+                let top = aggregator.top_keys(kind).map(TopKeys::get_top);
+                let _ = reply.send(top.unwrap_or_default());
+            }
+            TopKeysMessage::MemoryUsage(reply) => {
+                let counts = [
+                    &aggregator.denied,
+                    &aggregator.requested,
+                    &aggregator.allowed,
+                ];
+                let _ = reply.send(
+                    counts
+                        .into_iter()
+                        .flatten()
+                        .map(TopKeys::memory_usage)
+                        .sum(),
+                );
+            }
+        }
+    }
+}
+
+/// Highest value observed since start or the last reset, with its time
+///
+/// Updates are lock-free. When two new peaks race, the value is always the
+/// larger one but the timestamp may belong to either.
+#[derive(Debug, Default)]
+pub struct HighWaterMark {
+    value: AtomicU64,
+    at_ms: AtomicU64,
+}
+
+impl HighWaterMark {
+    /// Record `value`, keeping it if it is a new peak
+    pub fn observe(&self, value: u64, now: SystemTime) {
+        // Cheap early exit: most observations are not new peaks
+        if value <= self.value.load(Ordering::Relaxed) {
+            return;
+        }
+        if self.value.fetch_max(value, Ordering::Relaxed) < value {
+            let at_ms = now
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0);
+            self.at_ms.store(at_ms, Ordering::Relaxed);
+        }
+    }
+
+    /// Current peak
+    pub fn get(&self) -> Peak {
+        Peak {
+            value: self.value.load(Ordering::Relaxed),
+            at_ms: self.at_ms.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Clear the peak, returning its previous value
+    pub fn reset(&self) -> Peak {
+        Peak {
+            value: self.value.swap(0, Ordering::Relaxed),
+            at_ms: self.at_ms.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+/// A peak value and when it was reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Peak {
+    /// The highest value observed
+    pub value: u64,
+    /// When it was observed, in milliseconds since the Unix epoch (0 if never)
+    pub at_ms: u64,
+}
+
+/// Snapshot of all high-water marks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Peaks {
+    /// Most requests received within one wall clock second
+    pub requests_per_second: Peak,
+    /// Most requests waiting for the rate limiter actor
+    pub queue_depth: Peak,
+    /// Most keys held by the store
+    pub store_keys: Peak,
+}
+
+/// Upper bounds of the request duration buckets, in microseconds
+const LATENCY_BUCKETS_US: [u64; 12] = [
+    50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000,
+];
+
+/// Quantiles estimated from the latency histograms for `/metrics` and logs
+const LATENCY_QUANTILES: [f64; 3] = [0.5, 0.95, 0.99];
+
+/// Histogram of request durations
+///
+/// Observations are lock-free: one atomic increment for the bucket and one
+/// for the sum. Buckets are stored per range and made cumulative on export.
+/// Quantiles are estimated by interpolating within the bucket they fall in.
+#[derive(Debug, Default)]
+pub struct LatencyHistogram {
+    /// Observations per bucket, the last one for those above every bound
+    buckets: [AtomicU64; LATENCY_BUCKETS_US.len() + 1],
+    sum_us: AtomicU64,
+}
+
+impl LatencyHistogram {
+    /// Record one request that took `duration`
+    pub fn observe(&self, duration: Duration) {
+        let us = duration.as_micros() as u64;
+        let bucket = LATENCY_BUCKETS_US.partition_point(|&bound| bound < us);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(us, Ordering::Relaxed);
+    }
+
+    /// Number of recorded requests
+    pub fn count(&self) -> u64 {
+        self.buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Observations per bucket, as seen by one pass over the counters
+    pub fn bucket_counts(&self) -> [u64; LATENCY_BUCKETS_US.len() + 1] {
+        std::array::from_fn(|i| self.buckets[i].load(Ordering::Relaxed))
+    }
+
+    /// Estimated duration below which a `q` fraction of requests completed
+    ///
+    /// None before any request is recorded.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        latency_quantile(&self.bucket_counts(), q)
+    }
+
+    /// Append the bucket, sum and count series for `transport`
+    fn export(&self, name: &str, transport: &str, output: &mut String) {
+        let mut cumulative = 0;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let le = LATENCY_BUCKETS_US.get(i).map_or("+Inf".to_string(), |&us| {
+                (us as f64 / 1_000_000.0).to_string()
+            });
+            output.push_str(&format!(
+                "{name}_bucket{{transport=\"{transport}\",le=\"{le}\"}} {cumulative}\n"
+            ));
+        }
+        output.push_str(&format!(
+            "{name}_sum{{transport=\"{transport}\"}} {}\n",
+            self.sum_us.load(Ordering::Relaxed) as f64 / 1_000_000.0
+        ));
+        output.push_str(&format!(
+            "{name}_count{{transport=\"{transport}\"}} {cumulative}\n"
+        ));
+    }
+}
+
+/// Estimate the `q` quantile from per-bucket counts
+///
+/// Assumes durations are spread evenly within a bucket. Requests above
+/// the largest bound are reported at that bound, since the overflow
+/// bucket has no upper edge.
+fn latency_quantile(counts: &[u64], q: f64) -> Option<Duration> {
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return None;
+    }
+    let rank = (q.clamp(0.0, 1.0) * total as f64).ceil().max(1.0);
+    let mut cumulative = 0;
+    for (i, &count) in counts.iter().enumerate() {
+        if count == 0 || ((cumulative + count) as f64) < rank {
+            cumulative += count;
+            continue;
+        }
+        let lower = if i == 0 { 0 } else { LATENCY_BUCKETS_US[i - 1] };
+        let Some(&upper) = LATENCY_BUCKETS_US.get(i) else {
+            return Some(Duration::from_micros(lower));
+        };
+        let fraction = (rank - cumulative as f64) / count as f64;
+        let us = lower as f64 + (upper - lower) as f64 * fraction;
+        return Some(Duration::from_micros(us.round() as u64));
+    }
+    None
+}
+
+/// Log each transport's request count and latency quantiles every `interval`
+///
+/// Quantiles cover the requests since the previous line rather than since
+/// startup, so a latency regression shows up in the next line. Transports
+/// without requests in the interval are left out.
+pub(crate) async fn log_latency(metrics: Arc<Metrics>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut previous = metrics
+        .latency_histograms()
+        .map(|(_, histogram)| histogram.bucket_counts());
+
+    loop {
+        ticker.tick().await;
+        for ((transport, histogram), previous) in
+            metrics.latency_histograms().into_iter().zip(&mut previous)
+        {
+            let current = histogram.bucket_counts();
+            let delta: Vec<u64> = current
+                .iter()
+                .zip(previous.iter())
+                .map(|(now, before)| now.saturating_sub(*before))
+                .collect();
+            *previous = current;
+
+            let requests: u64 = delta.iter().sum();
+            if requests == 0 {
+                continue;
+            }
+            let [p50, p95, p99] =
+                LATENCY_QUANTILES.map(|q| latency_quantile(&delta, q).unwrap_or_default());
+            tracing::info!(
+                transport,
+                requests,
+                p50_us = p50.as_micros() as u64,
+                p95_us = p95.as_micros() as u64,
+                p99_us = p99.as_micros() as u64,
+                "Request latency"
+            );
+        }
+    }
+}
+
+/// Outcomes of the self-probes against one transport endpoint
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeStats {
+    /// Probes answered with a valid throttle response
+    pub successes: u64,
+    /// Probes that failed or timed out
+    pub failures: u64,
+    /// Round trip of the most recent successful probe, in microseconds
+    pub last_latency_us: u64,
+}
+
+/// Decisions and quota rejections in one namespace
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NamespaceStats {
+    /// Requests allowed
+    pub allowed: u64,
+    /// Requests denied
+    pub denied: u64,
+    /// Requests for new keys rejected by the namespace's quota
+    pub quota_rejections: u64,
+}
+
+/// Live counters behind [`NamespaceStats`]
+#[derive(Debug, Default)]
+struct NamespaceCounters {
+    allowed: AtomicU64,
+    denied: AtomicU64,
+    quota_rejections: AtomicU64,
+}
+
+/// Request counter for the current wall clock second
+#[derive(Debug, Default)]
+struct RateWindow {
+    second: AtomicU64,
+    count: AtomicU64,
+}
+
+impl RateWindow {
+    /// Count a request at `now` and return the count for its second
+    fn record(&self, now: SystemTime) -> u64 {
+        let second = now
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let current = self.second.load(Ordering::Relaxed);
+        if current != second
+            && self
+                .second
+                .compare_exchange(current, second, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.count.store(0, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
 /// Core metrics collected by the server
 pub struct Metrics {
     /// Server start time
@@ -87,19 +484,149 @@ pub struct Metrics {
     pub http_requests: AtomicU64,
     pub grpc_requests: AtomicU64,
     pub redis_requests: AtomicU64,
+    pub udp_requests: AtomicU64,
+
+    /// Time from receiving a rate limit request to its decision, by transport
+    pub http_latency: LatencyHistogram,
+    pub grpc_latency: LatencyHistogram,
+    pub redis_latency: LatencyHistogram,
+    pub udp_latency: LatencyHistogram,
 
     /// Rate limiting decisions
     pub requests_allowed: AtomicU64,
     pub requests_denied: AtomicU64,
     pub requests_errors: AtomicU64,
 
-    /// Top denied keys tracking (None if disabled)
-    pub(crate) top_denied_keys: Option<Mutex<TopDeniedKeys>>,
+    /// Peeks at a key's state, which consume nothing and are not requests
+    pub peek_requests: AtomicU64,
+
+    /// Keys whose state was cleared by an admin reset
+    pub key_resets: AtomicU64,
+
+    /// Throttle requests allowed by one decision for a coalesced group
+    pub coalesced_requests: AtomicU64,
+
+    /// Throttle requests shed by `--max-rps` without reaching the store
+    pub shed_requests: AtomicU64,
+    /// Throttle requests that found their actor's queue full
+    pub queue_full_events: AtomicU64,
+    /// Throttle checks failed with an "overloaded" error by `--backpressure`
+    pub overloaded_requests: AtomicU64,
+    /// Messages waiting in the actor queues, summed over all shards
+    pub queue_depth: AtomicU64,
+    /// Throttle requests denied by the key deny-list (see
+    /// [`overrides`](crate::overrides))
+    pub override_denied: AtomicU64,
+    /// Throttle requests allowed by the key allow-list
+    pub override_allowed: AtomicU64,
+    /// Allowed throttle requests past their policy's `warn_percent`
+    pub soft_limit_warnings: AtomicU64,
+
+    /// Throttle requests answered from, or missing, the `--micro-cache-ms` cache
+    pub micro_cache_hits: AtomicU64,
+    pub micro_cache_misses: AtomicU64,
+
+    /// Concurrency lease outcomes (see `ACQUIRE` and `RELEASE`)
+    pub leases_acquired: AtomicU64,
+    pub leases_denied: AtomicU64,
+    pub leases_released: AtomicU64,
+
+    /// Entries currently held by the store, including expired ones not yet removed
+    pub store_keys: AtomicU64,
+
+    /// Key limit enforcement (see `--max-keys`)
+    pub store_evictions: AtomicU64,
+    pub store_rejections: AtomicU64,
+    pub store_degraded: AtomicU64,
+
+    /// Writes whose TTL was shortened by `--store-max-ttl`
+    pub store_ttl_capped: AtomicU64,
+    pub store_migrations: AtomicU64,
+
+    /// Decision event export
+    pub events_published: AtomicU64,
+    pub events_dropped: AtomicU64,
+
+    /// Metrics push (`--metrics-push-url`)
+    pub metrics_pushes: AtomicU64,
+    pub metrics_push_failures: AtomicU64,
+
+    /// Policy file reloads (`SIGHUP`, `POST /admin/reload`)
+    pub policy_reloads: AtomicU64,
+    pub policy_reload_failures: AtomicU64,
+
+    /// Write-ahead log
+    pub wal_size_bytes: AtomicU64,
+    pub wal_lag_records: AtomicU64,
+
+    /// Periodic snapshots (`--snapshot-path`)
+    pub snapshot_size_bytes: AtomicU64,
+    pub snapshot_failures: AtomicU64,
+
+    /// Canary store comparison
+    pub canary_compared: AtomicU64,
+    pub canary_diverged: AtomicU64,
+
+    /// Cluster replication (`--cluster-port`)
+    pub cluster_peers: AtomicU64,
+    pub cluster_entries_sent: AtomicU64,
+    pub cluster_entries_received: AtomicU64,
+    pub cluster_entries_merged: AtomicU64,
+    pub cluster_sync_failures: AtomicU64,
+
+    /// High-water marks for post-incident review (see [`Metrics::peaks`])
+    pub peak_requests_per_second: HighWaterMark,
+    pub peak_queue_depth: HighWaterMark,
+    pub peak_store_keys: HighWaterMark,
+    request_window: RateWindow,
+
+    /// Denied keys not counted because the aggregator fell behind
+    pub top_denied_keys_dropped: AtomicU64,
+    /// Sampled requests not counted because the aggregator fell behind
+    pub top_keys_samples_dropped: AtomicU64,
+
+    /// Open Redis connections and the bytes held by their read buffers
+    pub redis_connections: AtomicU64,
+    pub connection_buffer_bytes: AtomicU64,
+
+    /// Redis commands delayed by `--redis-max-commands-per-second`
+    pub redis_paced_commands: AtomicU64,
+
+    /// Redis connections refused by `--redis-max-connections` or
+    /// `--redis-max-connections-per-ip`
+    pub redis_rejected_connections: AtomicU64,
+
+    /// Requests rejected for a missing or invalid API key or Redis password
+    pub auth_failures: AtomicU64,
+    /// Requests rejected by validation, by error code
+    invalid_requests: RwLock<BTreeMap<&'static str, AtomicU64>>,
+    /// Authenticated requests by API key name (see `--api-key`)
+    api_key_requests: RwLock<BTreeMap<Arc<str>, AtomicU64>>,
+    /// Throttle checks refused by API key name, for exceeding the key's
+    /// budget (see [`budget`](crate::budget))
+    api_key_budget_exhausted: RwLock<BTreeMap<Arc<str>, AtomicU64>>,
+    /// Decisions by namespace (see [`namespace`](crate::namespace))
+    namespaces: RwLock<BTreeMap<Arc<str>, NamespaceCounters>>,
+
+    /// Self-probe outcomes by target (see `--probe-interval`)
+    probes: Mutex<BTreeMap<&'static str, ProbeStats>>,
+
+    /// Channel to the top keys aggregator (None if all tracking is disabled)
+    pub(crate) top_keys: Option<SyncSender<TopKeysMessage>>,
+    /// Whether denied keys are tracked
+    tracks_denied_keys: bool,
+    /// One in this many requests is sampled for top requested and allowed
+    /// keys (0 if disabled)
+    top_keys_sample_rate: u64,
+    /// Requests seen by the sampler
+    top_keys_sampled: AtomicU64,
 }
 
 /// Builder for configuring Metrics
 pub struct MetricsBuilder {
     max_denied_keys: usize,
+    max_top_keys: usize,
+    top_keys_sample_rate: u64,
 }
 
 impl MetricsBuilder {
@@ -107,6 +634,8 @@ impl MetricsBuilder {
     pub fn new() -> Self {
         Self {
             max_denied_keys: 100,
+            max_top_keys: 0,
+            top_keys_sample_rate: 100,
         }
     }
 
@@ -121,6 +650,19 @@ impl MetricsBuilder {
         self
     }
 
+    /// Track the top `count` requested and allowed keys, sampling one
+    /// request in every `sample_rate`
+    ///
+    /// Disabled by default; set `count` to 0 to disable it again. Like
+    /// [`max_denied_keys`](Self::max_denied_keys), `count` is capped at
+    /// 10,000. Counts are estimates: each sampled request counts as
+    /// `sample_rate`, so keys with fewer requests than that may be missed.
+    pub fn top_keys(mut self, count: usize, sample_rate: u64) -> Self {
+        self.max_top_keys = count.clamp(0, MAX_TOP_KEYS_LIMIT);
+        self.top_keys_sample_rate = sample_rate.max(1);
+        self
+    }
+
     /// Build the Metrics instance
     pub fn build(self) -> Metrics {
         Metrics {
@@ -129,14 +671,85 @@ impl MetricsBuilder {
             http_requests: AtomicU64::new(0),
             grpc_requests: AtomicU64::new(0),
             redis_requests: AtomicU64::new(0),
+            udp_requests: AtomicU64::new(0),
+            http_latency: LatencyHistogram::default(),
+            grpc_latency: LatencyHistogram::default(),
+            redis_latency: LatencyHistogram::default(),
+            udp_latency: LatencyHistogram::default(),
             requests_allowed: AtomicU64::new(0),
             requests_denied: AtomicU64::new(0),
             requests_errors: AtomicU64::new(0),
-            top_denied_keys: if self.max_denied_keys == 0 {
+            peek_requests: AtomicU64::new(0),
+            key_resets: AtomicU64::new(0),
+            coalesced_requests: AtomicU64::new(0),
+            shed_requests: AtomicU64::new(0),
+            queue_full_events: AtomicU64::new(0),
+            overloaded_requests: AtomicU64::new(0),
+            queue_depth: AtomicU64::new(0),
+            override_denied: AtomicU64::new(0),
+            override_allowed: AtomicU64::new(0),
+            soft_limit_warnings: AtomicU64::new(0),
+            micro_cache_hits: AtomicU64::new(0),
+            micro_cache_misses: AtomicU64::new(0),
+            leases_acquired: AtomicU64::new(0),
+            leases_denied: AtomicU64::new(0),
+            leases_released: AtomicU64::new(0),
+            store_keys: AtomicU64::new(0),
+            store_evictions: AtomicU64::new(0),
+            store_rejections: AtomicU64::new(0),
+            store_degraded: AtomicU64::new(0),
+            store_ttl_capped: AtomicU64::new(0),
+            store_migrations: AtomicU64::new(0),
+            events_published: AtomicU64::new(0),
+            events_dropped: AtomicU64::new(0),
+            metrics_pushes: AtomicU64::new(0),
+            metrics_push_failures: AtomicU64::new(0),
+            policy_reloads: AtomicU64::new(0),
+            policy_reload_failures: AtomicU64::new(0),
+            wal_size_bytes: AtomicU64::new(0),
+            wal_lag_records: AtomicU64::new(0),
+            snapshot_size_bytes: AtomicU64::new(0),
+            snapshot_failures: AtomicU64::new(0),
+            canary_compared: AtomicU64::new(0),
+            canary_diverged: AtomicU64::new(0),
+            cluster_peers: AtomicU64::new(0),
+            cluster_entries_sent: AtomicU64::new(0),
+            cluster_entries_received: AtomicU64::new(0),
+            cluster_entries_merged: AtomicU64::new(0),
+            cluster_sync_failures: AtomicU64::new(0),
+            peak_requests_per_second: HighWaterMark::default(),
+            peak_queue_depth: HighWaterMark::default(),
+            peak_store_keys: HighWaterMark::default(),
+            request_window: RateWindow::default(),
+            top_denied_keys_dropped: AtomicU64::new(0),
+            top_keys_samples_dropped: AtomicU64::new(0),
+            redis_connections: AtomicU64::new(0),
+            connection_buffer_bytes: AtomicU64::new(0),
+            redis_paced_commands: AtomicU64::new(0),
+            redis_rejected_connections: AtomicU64::new(0),
+            auth_failures: AtomicU64::new(0),
+            invalid_requests: RwLock::new(BTreeMap::new()),
+            api_key_requests: RwLock::new(BTreeMap::new()),
+            api_key_budget_exhausted: RwLock::new(BTreeMap::new()),
+            namespaces: RwLock::new(BTreeMap::new()),
+            probes: Mutex::new(BTreeMap::new()),
+            top_keys: if self.max_denied_keys == 0 && self.max_top_keys == 0 {
                 None
             } else {
-                Some(Mutex::new(TopDeniedKeys::new(self.max_denied_keys)))
+                let sampled = (self.max_top_keys > 0).then(|| TopKeys::new(self.max_top_keys));
+                Some(spawn_top_keys_aggregator(TopKeysAggregator {
+                    denied: (self.max_denied_keys > 0).then(|| TopKeys::new(self.max_denied_keys)),
+                    requested: sampled.as_ref().map(|_| TopKeys::new(self.max_top_keys)),
+                    allowed: sampled,
+                }))
+            },
+            tracks_denied_keys: self.max_denied_keys > 0,
+            top_keys_sample_rate: if self.max_top_keys > 0 {
+                self.top_keys_sample_rate
+            } else {
+                0
             },
+            top_keys_sampled: AtomicU64::new(0),
         }
     }
 }
@@ -158,29 +771,123 @@ impl Metrics {
         MetricsBuilder::new().build()
     }
 
+    /// Estimated heap bytes held by top keys tracking
+    ///
+    /// Includes the aggregator's queue, which is allocated up front. Waits
+    /// for the aggregator to count the queued keys first, blocking the
+    /// thread, so async callers run it with `spawn_blocking`.
+    pub fn memory_usage(&self) -> usize {
+        let Some(top_keys) = &self.top_keys else {
+            return 0;
+        };
+        // Each queue slot holds a message and a sequence stamp
+        let queue = DENIED_KEYS_QUEUE_SIZE * (size_of::<TopKeysMessage>() + size_of::<usize>());
+        let (reply_tx, reply_rx) = mpsc::channel();
+        let counts = if top_keys.send(TopKeysMessage::MemoryUsage(reply_tx)).is_ok() {
+            reply_rx.recv().unwrap_or(0)
+        } else {
+            0
+        };
+        queue + counts
+    }
+
     /// Record a request with key information
     pub fn record_request_with_key(&self, transport: Transport, allowed: bool, key: &str) {
         // Update all the metrics that don't need the key
         self.record_request(transport, allowed);
 
-        // Update top denied keys if request was denied and tracking is enabled
+        let Some(ref top_keys) = self.top_keys else {
+            return;
+        };
+        if key.len() > MAX_TRACKED_KEY_LENGTH {
+            return;
+        }
+
+        // Hand denied keys to the aggregator if tracking is enabled, never blocking
         if !allowed
-            && let Some(ref top_denied_keys) = self.top_denied_keys
-            && let Ok(mut top_keys) = top_denied_keys.lock()
+            && self.tracks_denied_keys
+            && let Err(TrySendError::Full(_)) =
+                top_keys.try_send(TopKeysMessage::Denied(key.to_string()))
+        {
+            self.top_denied_keys_dropped.fetch_add(1, Ordering::Relaxed);
+        }
+
+        // And one request in every sample_rate for the top requested and allowed keys
+        if self.top_keys_sample_rate > 0
+            && self
+                .top_keys_sampled
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(self.top_keys_sample_rate)
+            && let Err(TrySendError::Full(_)) = top_keys.try_send(TopKeysMessage::Sampled {
+                key: key.to_string(),
+                allowed,
+            })
         {
-            top_keys.update(key.to_string());
+            self.top_keys_samples_dropped
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The top keys of `kind`, most requests first
+    ///
+    /// Returns None if tracking of `kind` is disabled. Waits for the
+    /// aggregator to count the queued keys first, blocking the thread, so
+    /// async callers run it with `spawn_blocking`.
+    pub fn top_keys(&self, kind: TopKeysKind) -> Option<Vec<TopKey>> {
+        let scale = match kind {
+            TopKeysKind::Denied if self.tracks_denied_keys => 1,
+            TopKeysKind::Requested | TopKeysKind::Allowed if self.top_keys_sample_rate > 0 => {
+                self.top_keys_sample_rate
+            }
+            _ => return None,
+        };
+        let top_keys = self.top_keys.as_ref()?;
+        let (reply_tx, reply_rx) = mpsc::channel();
+        top_keys
+            .send(TopKeysMessage::Snapshot(kind, reply_tx))
+            .ok()?;
+        let top = reply_rx.recv().ok()?;
+        Some(
+            top.into_iter()
+                .map(|(key, count)| TopKey {
+                    key,
+                    count: count.saturating_mul(scale),
+                })
+                .collect(),
+        )
+    }
+
+    /// Each transport's latency histogram with its label
+    pub fn latency_histograms(&self) -> [(&'static str, &LatencyHistogram); 4] {
+        [
+            (Transport::Http.name(), &self.http_latency),
+            (Transport::Grpc.name(), &self.grpc_latency),
+            (Transport::Redis.name(), &self.redis_latency),
+            (Transport::Udp.name(), &self.udp_latency),
+        ]
+    }
+
+    /// Record how long a rate limit request took to decide
+    pub fn record_latency(&self, transport: Transport, duration: Duration) {
+        match transport {
+            Transport::Http => self.http_latency.observe(duration),
+            Transport::Grpc => self.grpc_latency.observe(duration),
+            Transport::Redis => self.redis_latency.observe(duration),
+            Transport::Udp => self.udp_latency.observe(duration),
         }
     }
 
     /// Record a request
     pub fn record_request(&self, transport: Transport, allowed: bool) {
         self.total_requests.fetch_add(1, Ordering::Relaxed);
+        self.record_rate();
 
         // Record transport-specific counter
         match transport {
             Transport::Http => self.http_requests.fetch_add(1, Ordering::Relaxed),
             Transport::Grpc => self.grpc_requests.fetch_add(1, Ordering::Relaxed),
             Transport::Redis => self.redis_requests.fetch_add(1, Ordering::Relaxed),
+            Transport::Udp => self.udp_requests.fetch_add(1, Ordering::Relaxed),
         };
 
         // Record allow/deny decision
@@ -194,6 +901,7 @@ impl Metrics {
     /// Record an internal error
     pub fn record_error(&self, transport: Transport) {
         self.total_requests.fetch_add(1, Ordering::Relaxed);
+        self.record_rate();
         self.requests_errors.fetch_add(1, Ordering::Relaxed);
 
         // Record transport-specific counter
@@ -201,9 +909,207 @@ impl Metrics {
             Transport::Http => self.http_requests.fetch_add(1, Ordering::Relaxed),
             Transport::Grpc => self.grpc_requests.fetch_add(1, Ordering::Relaxed),
             Transport::Redis => self.redis_requests.fetch_add(1, Ordering::Relaxed),
+            Transport::Udp => self.udp_requests.fetch_add(1, Ordering::Relaxed),
         };
     }
 
+    fn record_rate(&self) {
+        let now = SystemTime::now();
+        let count = self.request_window.record(now);
+        self.peak_requests_per_second.observe(count, now);
+    }
+
+    /// Snapshot of all high-water marks
+    pub fn peaks(&self) -> Peaks {
+        Peaks {
+            requests_per_second: self.peak_requests_per_second.get(),
+            queue_depth: self.peak_queue_depth.get(),
+            store_keys: self.peak_store_keys.get(),
+        }
+    }
+
+    /// Clear all high-water marks, returning their previous values
+    pub fn reset_peaks(&self) -> Peaks {
+        Peaks {
+            requests_per_second: self.peak_requests_per_second.reset(),
+            queue_depth: self.peak_queue_depth.reset(),
+            store_keys: self.peak_store_keys.reset(),
+        }
+    }
+
+    /// Record a self-probe of `target`, with its round trip if it succeeded
+    pub fn record_probe(&self, target: &'static str, latency: Option<Duration>) {
+        let mut probes = self.probes.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = probes.entry(target).or_default();
+        match latency {
+            Some(latency) => {
+                stats.successes += 1;
+                stats.last_latency_us = latency.as_micros() as u64;
+            }
+            None => stats.failures += 1,
+        }
+    }
+
+    /// Count a request authenticated with the API key called `name`
+    pub fn record_api_key_request(&self, name: &Arc<str>) {
+        let counts = self
+            .api_key_requests
+            .read()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = counts.get(name) {
+            count.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        drop(counts);
+        self.api_key_requests
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(Arc::clone(name))
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a throttle request decided by a key override
+    pub fn record_override(&self, decision: Override) {
+        let counter = match decision {
+            Override::Deny => &self.override_denied,
+            Override::Allow => &self.override_allowed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count throttle checks refused for exceeding the budget of the API
+    /// key called `name`
+    pub fn record_api_key_budget_exhausted(&self, name: &str) {
+        let counts = self
+            .api_key_budget_exhausted
+            .read()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = counts.get(name) {
+            count.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        drop(counts);
+        self.api_key_budget_exhausted
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(name.into())
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a request rejected by validation with error `code`
+    pub fn record_invalid_request(&self, code: &'static str) {
+        let counts = self
+            .invalid_requests
+            .read()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = counts.get(code) {
+            count.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        drop(counts);
+        self.invalid_requests
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(code)
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Requests rejected by validation, by error code
+    pub fn invalid_requests(&self) -> BTreeMap<&'static str, u64> {
+        self.invalid_requests
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(code, count)| (*code, count.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Authenticated requests by API key name
+    pub fn api_key_requests(&self) -> BTreeMap<Arc<str>, u64> {
+        self.api_key_requests
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(name, count)| (Arc::clone(name), count.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Requests refused for exceeding their API key's budget, by key name
+    pub fn api_key_budget_exhausted(&self) -> BTreeMap<Arc<str>, u64> {
+        self.api_key_budget_exhausted
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(name, count)| (Arc::clone(name), count.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Count a decision for a request in `namespace`
+    pub fn record_namespace_request(&self, namespace: &str, allowed: bool) {
+        self.with_namespace(namespace, |counters| {
+            let count = if allowed {
+                &counters.allowed
+            } else {
+                &counters.denied
+            };
+            count.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    /// Count a new key rejected by `namespace`'s quota
+    pub fn record_namespace_rejection(&self, namespace: &str) {
+        self.with_namespace(namespace, |counters| {
+            counters.quota_rejections.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    /// Apply `f` to `namespace`'s counters, creating them while fewer than
+    /// [`MAX_TRACKED_NAMESPACES`](crate::namespace::MAX_TRACKED_NAMESPACES)
+    /// namespaces are counted
+    fn with_namespace(&self, namespace: &str, f: impl FnOnce(&NamespaceCounters)) {
+        let namespaces = self.namespaces.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(counters) = namespaces.get(namespace) {
+            f(counters);
+            return;
+        }
+        drop(namespaces);
+        let mut namespaces = self.namespaces.write().unwrap_or_else(|e| e.into_inner());
+        if !namespaces.contains_key(namespace)
+            && namespaces.len() >= crate::namespace::MAX_TRACKED_NAMESPACES
+        {
+            return;
+        }
+        f(namespaces.entry(namespace.into()).or_default());
+    }
+
+    /// Decisions and quota rejections by namespace
+    pub fn namespaces(&self) -> BTreeMap<Arc<str>, NamespaceStats> {
+        self.namespaces
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(namespace, counters)| {
+                let stats = NamespaceStats {
+                    allowed: counters.allowed.load(Ordering::Relaxed),
+                    denied: counters.denied.load(Ordering::Relaxed),
+                    quota_rejections: counters.quota_rejections.load(Ordering::Relaxed),
+                };
+                (Arc::clone(namespace), stats)
+            })
+            .collect()
+    }
+
+    /// Self-probe outcomes by target, empty if probing is disabled
+    pub fn probes(&self) -> BTreeMap<&'static str, ProbeStats> {
+        self.probes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
     /// Get server uptime in seconds
     pub fn uptime_seconds(&self) -> u64 {
         self.start_time.elapsed().as_secs()
@@ -230,6 +1136,8 @@ impl Metrics {
     }
 
     /// Export metrics in Prometheus text format
+    ///
+    /// Blocks on the top keys aggregator like [`top_keys`](Self::top_keys).
     pub fn export_prometheus(&self) -> String {
         // Estimate size: ~50 chars per metric line, ~7 metrics = ~350 chars
         let mut output = String::with_capacity(500);
@@ -264,9 +1172,45 @@ impl Metrics {
             self.grpc_requests.load(Ordering::Relaxed)
         ));
         output.push_str(&format!(
-            "throttlecrab_requests_by_transport{{transport=\"redis\"}} {}\n\n",
+            "throttlecrab_requests_by_transport{{transport=\"redis\"}} {}\n",
             self.redis_requests.load(Ordering::Relaxed)
         ));
+        output.push_str(&format!(
+            "throttlecrab_requests_by_transport{{transport=\"udp\"}} {}\n\n",
+            self.udp_requests.load(Ordering::Relaxed)
+        ));
+
+        // Request durations by transport
+        output.push_str(
+            "# HELP throttlecrab_request_duration_seconds Time from receiving a rate limit request to its decision\n",
+        );
+        output.push_str("# TYPE throttlecrab_request_duration_seconds histogram\n");
+        for (transport, histogram) in self.latency_histograms() {
+            histogram.export(
+                "throttlecrab_request_duration_seconds",
+                transport,
+                &mut output,
+            );
+        }
+        output.push('\n');
+
+        // Quantiles estimated from the histograms, for dashboards without
+        // histogram_quantile; transports without requests are left out
+        output.push_str(
+            "# HELP throttlecrab_request_duration_quantile_seconds Estimated request duration quantiles since start\n",
+        );
+        output.push_str("# TYPE throttlecrab_request_duration_quantile_seconds gauge\n");
+        for (transport, histogram) in self.latency_histograms() {
+            for q in LATENCY_QUANTILES {
+                if let Some(duration) = histogram.quantile(q) {
+                    output.push_str(&format!(
+                        "throttlecrab_request_duration_quantile_seconds{{transport=\"{transport}\",quantile=\"{q}\"}} {}\n",
+                        duration.as_secs_f64()
+                    ));
+                }
+            }
+        }
+        output.push('\n');
 
         // Allow/Deny decisions
         output.push_str("# HELP throttlecrab_requests_allowed Total requests allowed\n");
@@ -290,24 +1234,577 @@ impl Metrics {
             self.requests_errors.load(Ordering::Relaxed)
         ));
 
-        // Top denied keys (only if tracking is enabled)
-        if let Some(ref top_denied_keys) = self.top_denied_keys {
-            output.push_str("# HELP throttlecrab_top_denied_keys Top keys by denial count\n");
-            output.push_str("# TYPE throttlecrab_top_denied_keys gauge\n");
-            if let Ok(top_keys) = top_denied_keys.lock() {
-                for (rank, (key, count)) in top_keys.get_top().iter().enumerate() {
+        output.push_str(
+            "# HELP throttlecrab_peek_requests Peeks at a key's state that consumed no tokens\n",
+        );
+        output.push_str("# TYPE throttlecrab_peek_requests counter\n");
+        output.push_str(&format!(
+            "throttlecrab_peek_requests {}\n\n",
+            self.peek_requests.load(Ordering::Relaxed)
+        ));
+
+        output.push_str("# HELP throttlecrab_key_resets Keys cleared by an admin reset\n");
+        output.push_str("# TYPE throttlecrab_key_resets counter\n");
+        output.push_str(&format!(
+            "throttlecrab_key_resets {}\n\n",
+            self.key_resets.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_coalesced_requests Throttle requests allowed by one decision for their coalesced group\n",
+        );
+        output.push_str("# TYPE throttlecrab_coalesced_requests counter\n");
+        output.push_str(&format!(
+            "throttlecrab_coalesced_requests {}\n\n",
+            self.coalesced_requests.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_shed_requests Throttle requests shed by --max-rps without reaching the store\n",
+        );
+        output.push_str("# TYPE throttlecrab_shed_requests counter\n");
+        output.push_str(&format!(
+            "throttlecrab_shed_requests {}\n\n",
+            self.shed_requests.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_queue_full_events Throttle requests that found the rate limiter actor's queue full\n",
+        );
+        output.push_str("# TYPE throttlecrab_queue_full_events counter\n");
+        output.push_str(&format!(
+            "throttlecrab_queue_full_events {}\n\n",
+            self.queue_full_events.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_overloaded_requests Throttle checks failed as overloaded by --backpressure\n",
+        );
+        output.push_str("# TYPE throttlecrab_overloaded_requests counter\n");
+        output.push_str(&format!(
+            "throttlecrab_overloaded_requests {}\n\n",
+            self.overloaded_requests.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_queue_depth Messages waiting for the rate limiter actors\n",
+        );
+        output.push_str("# TYPE throttlecrab_queue_depth gauge\n");
+        output.push_str(&format!(
+            "throttlecrab_queue_depth {}\n\n",
+            self.queue_depth.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_override_decisions Throttle requests decided by a key override, by list\n",
+        );
+        output.push_str("# TYPE throttlecrab_override_decisions counter\n");
+        output.push_str(&format!(
+            "throttlecrab_override_decisions{{list=\"deny\"}} {}\n",
+            self.override_denied.load(Ordering::Relaxed)
+        ));
+        output.push_str(&format!(
+            "throttlecrab_override_decisions{{list=\"allow\"}} {}\n\n",
+            self.override_allowed.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_soft_limit_warnings Allowed throttle requests past their policy's warn_percent\n",
+        );
+        output.push_str("# TYPE throttlecrab_soft_limit_warnings counter\n");
+        output.push_str(&format!(
+            "throttlecrab_soft_limit_warnings {}\n\n",
+            self.soft_limit_warnings.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_micro_cache_hits Throttle requests answered from the micro-cache\n",
+        );
+        output.push_str("# TYPE throttlecrab_micro_cache_hits counter\n");
+        output.push_str(&format!(
+            "throttlecrab_micro_cache_hits {}\n\n",
+            self.micro_cache_hits.load(Ordering::Relaxed)
+        ));
+        output.push_str(
+            "# HELP throttlecrab_micro_cache_misses Throttle requests the micro-cache passed on to the store\n",
+        );
+        output.push_str("# TYPE throttlecrab_micro_cache_misses counter\n");
+        output.push_str(&format!(
+            "throttlecrab_micro_cache_misses {}\n\n",
+            self.micro_cache_misses.load(Ordering::Relaxed)
+        ));
+
+        // Concurrency leases
+        output.push_str("# HELP throttlecrab_leases_acquired Concurrency leases granted\n");
+        output.push_str("# TYPE throttlecrab_leases_acquired counter\n");
+        output.push_str(&format!(
+            "throttlecrab_leases_acquired {}\n\n",
+            self.leases_acquired.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_leases_denied Lease requests denied because the key was at its limit\n",
+        );
+        output.push_str("# TYPE throttlecrab_leases_denied counter\n");
+        output.push_str(&format!(
+            "throttlecrab_leases_denied {}\n\n",
+            self.leases_denied.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_leases_released Concurrency leases released before they expired\n",
+        );
+        output.push_str("# TYPE throttlecrab_leases_released counter\n");
+        output.push_str(&format!(
+            "throttlecrab_leases_released {}\n\n",
+            self.leases_released.load(Ordering::Relaxed)
+        ));
+
+        // Key limit enforcement
+        output.push_str(
+            "# HELP throttlecrab_store_evictions Keys evicted to admit new keys into a full store\n",
+        );
+        output.push_str("# TYPE throttlecrab_store_evictions counter\n");
+        output.push_str(&format!(
+            "throttlecrab_store_evictions {}\n\n",
+            self.store_evictions.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_store_rejections Requests rejected because the store was full\n",
+        );
+        output.push_str("# TYPE throttlecrab_store_rejections counter\n");
+        output.push_str(&format!(
+            "throttlecrab_store_rejections {}\n\n",
+            self.store_rejections.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_store_keys Entries held by the store, including expired ones not yet removed\n",
+        );
+        output.push_str("# TYPE throttlecrab_store_keys gauge\n");
+        output.push_str(&format!(
+            "throttlecrab_store_keys {}\n\n",
+            self.store_keys.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_store_degraded Requests routed to shared overflow buckets because the store was full\n",
+        );
+        output.push_str("# TYPE throttlecrab_store_degraded counter\n");
+        output.push_str(&format!(
+            "throttlecrab_store_degraded {}\n\n",
+            self.store_degraded.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_store_ttl_capped Store writes whose TTL was shortened by the maximum TTL\n",
+        );
+        output.push_str("# TYPE throttlecrab_store_ttl_capped counter\n");
+        output.push_str(&format!(
+            "throttlecrab_store_ttl_capped {}\n\n",
+            self.store_ttl_capped.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_store_migrations Stores replaced by the auto store selector\n",
+        );
+        output.push_str("# TYPE throttlecrab_store_migrations counter\n");
+        output.push_str(&format!(
+            "throttlecrab_store_migrations {}\n\n",
+            self.store_migrations.load(Ordering::Relaxed)
+        ));
+
+        // Decision event export
+        output.push_str(
+            "# HELP throttlecrab_events_published Decision events delivered to the event sink\n",
+        );
+        output.push_str("# TYPE throttlecrab_events_published counter\n");
+        output.push_str(&format!(
+            "throttlecrab_events_published {}\n\n",
+            self.events_published.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_events_dropped Decision events dropped due to backpressure or sink errors\n",
+        );
+        output.push_str("# TYPE throttlecrab_events_dropped counter\n");
+        output.push_str(&format!(
+            "throttlecrab_events_dropped {}\n\n",
+            self.events_dropped.load(Ordering::Relaxed)
+        ));
+
+        // Metrics push
+        output.push_str(
+            "# HELP throttlecrab_metrics_pushes Metrics snapshots delivered to the push endpoint\n",
+        );
+        output.push_str("# TYPE throttlecrab_metrics_pushes counter\n");
+        output.push_str(&format!(
+            "throttlecrab_metrics_pushes {}\n\n",
+            self.metrics_pushes.load(Ordering::Relaxed)
+        ));
+        output.push_str(
+            "# HELP throttlecrab_metrics_push_failures Metrics snapshots the push endpoint did not accept\n",
+        );
+        output.push_str("# TYPE throttlecrab_metrics_push_failures counter\n");
+        output.push_str(&format!(
+            "throttlecrab_metrics_push_failures {}\n\n",
+            self.metrics_push_failures.load(Ordering::Relaxed)
+        ));
+
+        // Policy reloads
+        output.push_str(
+            "# HELP throttlecrab_policy_reloads Policy file reloads that replaced the policies\n",
+        );
+        output.push_str("# TYPE throttlecrab_policy_reloads counter\n");
+        output.push_str(&format!(
+            "throttlecrab_policy_reloads {}\n\n",
+            self.policy_reloads.load(Ordering::Relaxed)
+        ));
+        output.push_str(
+            "# HELP throttlecrab_policy_reload_failures Policy file reloads rejected, keeping the running policies\n",
+        );
+        output.push_str("# TYPE throttlecrab_policy_reload_failures counter\n");
+        output.push_str(&format!(
+            "throttlecrab_policy_reload_failures {}\n\n",
+            self.policy_reload_failures.load(Ordering::Relaxed)
+        ));
+
+        // Write-ahead log
+        output.push_str("# HELP throttlecrab_wal_size_bytes Current size of the write-ahead log\n");
+        output.push_str("# TYPE throttlecrab_wal_size_bytes gauge\n");
+        output.push_str(&format!(
+            "throttlecrab_wal_size_bytes {}\n\n",
+            self.wal_size_bytes.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_wal_lag_records Records queued for the write-ahead log but not yet written\n",
+        );
+        output.push_str("# TYPE throttlecrab_wal_lag_records gauge\n");
+        output.push_str(&format!(
+            "throttlecrab_wal_lag_records {}\n\n",
+            self.wal_lag_records.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_snapshot_size_bytes Size of the most recent store snapshot\n",
+        );
+        output.push_str("# TYPE throttlecrab_snapshot_size_bytes gauge\n");
+        output.push_str(&format!(
+            "throttlecrab_snapshot_size_bytes {}\n\n",
+            self.snapshot_size_bytes.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_snapshot_failures Store snapshots that could not be written\n",
+        );
+        output.push_str("# TYPE throttlecrab_snapshot_failures counter\n");
+        output.push_str(&format!(
+            "throttlecrab_snapshot_failures {}\n\n",
+            self.snapshot_failures.load(Ordering::Relaxed)
+        ));
+
+        output.push_str("# HELP throttlecrab_redis_connections Open Redis connections\n");
+        output.push_str("# TYPE throttlecrab_redis_connections gauge\n");
+        output.push_str(&format!(
+            "throttlecrab_redis_connections {}\n\n",
+            self.redis_connections.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_redis_paced_commands Redis commands delayed by the per-connection command cap\n",
+        );
+        output.push_str("# TYPE throttlecrab_redis_paced_commands counter\n");
+        output.push_str(&format!(
+            "throttlecrab_redis_paced_commands {}\n\n",
+            self.redis_paced_commands.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_redis_rejected_connections Redis connections refused by the connection limits\n",
+        );
+        output.push_str("# TYPE throttlecrab_redis_rejected_connections counter\n");
+        output.push_str(&format!(
+            "throttlecrab_redis_rejected_connections {}\n\n",
+            self.redis_rejected_connections.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_auth_failures Requests rejected for missing or invalid credentials\n",
+        );
+        output.push_str("# TYPE throttlecrab_auth_failures counter\n");
+        output.push_str(&format!(
+            "throttlecrab_auth_failures {}\n\n",
+            self.auth_failures.load(Ordering::Relaxed)
+        ));
+
+        // Invalid requests by error code (only once one was rejected)
+        let invalid_requests = self.invalid_requests();
+        if !invalid_requests.is_empty() {
+            output.push_str(
+                "# HELP throttlecrab_invalid_requests Requests rejected by validation, by error code\n",
+            );
+            output.push_str("# TYPE throttlecrab_invalid_requests counter\n");
+            for (code, count) in &invalid_requests {
+                output.push_str(&format!(
+                    "throttlecrab_invalid_requests{{code=\"{code}\"}} {count}\n"
+                ));
+            }
+            output.push('\n');
+        }
+
+        // Requests per API key (only if keys are in use)
+        let api_key_requests = self.api_key_requests();
+        if !api_key_requests.is_empty() {
+            output.push_str(
+                "# HELP throttlecrab_api_key_requests Authenticated requests by API key name\n",
+            );
+            output.push_str("# TYPE throttlecrab_api_key_requests counter\n");
+            for (name, count) in &api_key_requests {
+                output.push_str(&format!(
+                    "throttlecrab_api_key_requests{{key=\"{}\"}} {count}\n",
+                    Self::escape_prometheus_label(name)
+                ));
+            }
+            output.push('\n');
+        }
+
+        // Requests over their API key's budget (only once any are refused)
+        let api_key_budget_exhausted = self.api_key_budget_exhausted();
+        if !api_key_budget_exhausted.is_empty() {
+            output.push_str(
+                "# HELP throttlecrab_api_key_budget_exhausted Requests refused for exceeding their API key's budget\n",
+            );
+            output.push_str("# TYPE throttlecrab_api_key_budget_exhausted counter\n");
+            for (name, count) in &api_key_budget_exhausted {
+                output.push_str(&format!(
+                    "throttlecrab_api_key_budget_exhausted{{key=\"{}\"}} {count}\n",
+                    Self::escape_prometheus_label(name)
+                ));
+            }
+            output.push('\n');
+        }
+
+        // Requests per namespace (only if namespaces are in use)
+        let namespaces = self.namespaces();
+        if !namespaces.is_empty() {
+            for (name, help, value) in [
+                (
+                    "namespace_requests_allowed",
+                    "Allowed requests by namespace",
+                    (|stats: &NamespaceStats| stats.allowed) as fn(&NamespaceStats) -> u64,
+                ),
+                (
+                    "namespace_requests_denied",
+                    "Denied requests by namespace",
+                    |stats| stats.denied,
+                ),
+                (
+                    "namespace_quota_rejections",
+                    "New keys rejected by their namespace's quota",
+                    |stats| stats.quota_rejections,
+                ),
+            ] {
+                output.push_str(&format!("# HELP throttlecrab_{name} {help}\n"));
+                output.push_str(&format!("# TYPE throttlecrab_{name} counter\n"));
+                for (namespace, stats) in &namespaces {
                     output.push_str(&format!(
-                        "throttlecrab_top_denied_keys{{key=\"{}\",rank=\"{}\"}} {}\n",
-                        Self::escape_prometheus_label(key),
-                        rank + 1,
-                        count
+                        "throttlecrab_{name}{{namespace=\"{}\"}} {}\n",
+                        Self::escape_prometheus_label(namespace),
+                        value(stats)
                     ));
                 }
+                output.push('\n');
             }
         }
 
+        // Canary store comparison
+        output.push_str(
+            "# HELP throttlecrab_canary_compared Decisions compared against the canary store\n",
+        );
+        output.push_str("# TYPE throttlecrab_canary_compared counter\n");
+        output.push_str(&format!(
+            "throttlecrab_canary_compared {}\n\n",
+            self.canary_compared.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_canary_diverged Decisions where the canary store disagreed\n",
+        );
+        output.push_str("# TYPE throttlecrab_canary_diverged counter\n");
+        output.push_str(&format!(
+            "throttlecrab_canary_diverged {}\n\n",
+            self.canary_diverged.load(Ordering::Relaxed)
+        ));
+
+        output.push_str("# HELP throttlecrab_cluster_peers Cluster peers currently connected to\n");
+        output.push_str("# TYPE throttlecrab_cluster_peers gauge\n");
+        output.push_str(&format!(
+            "throttlecrab_cluster_peers {}\n\n",
+            self.cluster_peers.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_cluster_entries_sent Rate limit entries sent to cluster peers\n",
+        );
+        output.push_str("# TYPE throttlecrab_cluster_entries_sent counter\n");
+        output.push_str(&format!(
+            "throttlecrab_cluster_entries_sent {}\n\n",
+            self.cluster_entries_sent.load(Ordering::Relaxed)
+        ));
+
+        output.push_str("# HELP throttlecrab_cluster_entries_received Rate limit entries received from cluster peers\n");
+        output.push_str("# TYPE throttlecrab_cluster_entries_received counter\n");
+        output.push_str(&format!(
+            "throttlecrab_cluster_entries_received {}\n\n",
+            self.cluster_entries_received.load(Ordering::Relaxed)
+        ));
+
+        output.push_str("# HELP throttlecrab_cluster_entries_merged Entries received from cluster peers that changed the store\n");
+        output.push_str("# TYPE throttlecrab_cluster_entries_merged counter\n");
+        output.push_str(&format!(
+            "throttlecrab_cluster_entries_merged {}\n\n",
+            self.cluster_entries_merged.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP throttlecrab_cluster_sync_failures Failed connections to cluster peers\n",
+        );
+        output.push_str("# TYPE throttlecrab_cluster_sync_failures counter\n");
+        output.push_str(&format!(
+            "throttlecrab_cluster_sync_failures {}\n\n",
+            self.cluster_sync_failures.load(Ordering::Relaxed)
+        ));
+
+        // High-water marks
+        for (name, help, peak) in [
+            (
+                "peak_requests_per_second",
+                "Most requests received within one second",
+                self.peak_requests_per_second.get(),
+            ),
+            (
+                "peak_queue_depth",
+                "Most requests waiting for the rate limiter actor",
+                self.peak_queue_depth.get(),
+            ),
+            (
+                "peak_store_keys",
+                "Most keys held by the store",
+                self.peak_store_keys.get(),
+            ),
+        ] {
+            output.push_str(&format!("# HELP throttlecrab_{name} {help}\n"));
+            output.push_str(&format!("# TYPE throttlecrab_{name} gauge\n"));
+            output.push_str(&format!("throttlecrab_{name} {}\n\n", peak.value));
+
+            output.push_str(&format!(
+                "# HELP throttlecrab_{name}_timestamp_seconds When throttlecrab_{name} was reached\n"
+            ));
+            output.push_str(&format!(
+                "# TYPE throttlecrab_{name}_timestamp_seconds gauge\n"
+            ));
+            output.push_str(&format!(
+                "throttlecrab_{name}_timestamp_seconds {:.3}\n\n",
+                peak.at_ms as f64 / 1000.0
+            ));
+        }
+
+        // Self-probes (only if probing is enabled)
+        let probes = self.probes();
+        if !probes.is_empty() {
+            for (name, help, kind, value) in [
+                (
+                    "probe_successes",
+                    "Self-probes answered through the transport",
+                    "counter",
+                    (|stats: &ProbeStats| stats.successes as f64) as fn(&ProbeStats) -> f64,
+                ),
+                (
+                    "probe_failures",
+                    "Self-probes that failed or timed out",
+                    "counter",
+                    |stats| stats.failures as f64,
+                ),
+                (
+                    "probe_latency_seconds",
+                    "Round trip of the most recent successful self-probe",
+                    "gauge",
+                    |stats| stats.last_latency_us as f64 / 1_000_000.0,
+                ),
+            ] {
+                output.push_str(&format!("# HELP throttlecrab_{name} {help}\n"));
+                output.push_str(&format!("# TYPE throttlecrab_{name} {kind}\n"));
+                for (target, stats) in &probes {
+                    output.push_str(&format!(
+                        "throttlecrab_{name}{{target=\"{target}\"}} {}\n",
+                        value(stats)
+                    ));
+                }
+                output.push('\n');
+            }
+        }
+
+        // Top denied keys (only if tracking is enabled)
+        if let Some(top_keys) = self.top_keys(TopKeysKind::Denied) {
+            output.push_str(
+                "# HELP throttlecrab_top_denied_keys_dropped Denied keys not counted because the aggregator fell behind\n",
+            );
+            output.push_str("# TYPE throttlecrab_top_denied_keys_dropped counter\n");
+            output.push_str(&format!(
+                "throttlecrab_top_denied_keys_dropped {}\n\n",
+                self.top_denied_keys_dropped.load(Ordering::Relaxed)
+            ));
+
+            output.push_str("# HELP throttlecrab_top_denied_keys Top keys by denial count\n");
+            output.push_str("# TYPE throttlecrab_top_denied_keys gauge\n");
+            Self::export_top_keys("throttlecrab_top_denied_keys", &top_keys, &mut output);
+        }
+
+        // Top requested and allowed keys (only if sampling is enabled)
+        if let (Some(requested), Some(allowed)) = (
+            self.top_keys(TopKeysKind::Requested),
+            self.top_keys(TopKeysKind::Allowed),
+        ) {
+            output.push('\n');
+            output.push_str(
+                "# HELP throttlecrab_top_keys_samples_dropped Sampled requests not counted because the aggregator fell behind\n",
+            );
+            output.push_str("# TYPE throttlecrab_top_keys_samples_dropped counter\n");
+            output.push_str(&format!(
+                "throttlecrab_top_keys_samples_dropped {}\n\n",
+                self.top_keys_samples_dropped.load(Ordering::Relaxed)
+            ));
+
+            output.push_str(
+                "# HELP throttlecrab_top_requested_keys Top keys by request count, estimated from sampled requests\n",
+            );
+            output.push_str("# TYPE throttlecrab_top_requested_keys gauge\n");
+            Self::export_top_keys("throttlecrab_top_requested_keys", &requested, &mut output);
+            output.push('\n');
+
+            output.push_str(
+                "# HELP throttlecrab_top_allowed_keys Top keys by allowed request count, estimated from sampled requests\n",
+            );
+            output.push_str("# TYPE throttlecrab_top_allowed_keys gauge\n");
+            Self::export_top_keys("throttlecrab_top_allowed_keys", &allowed, &mut output);
+        }
+
         output
     }
+
+    fn export_top_keys(name: &str, top_keys: &[TopKey], output: &mut String) {
+        for (rank, top_key) in top_keys.iter().enumerate() {
+            output.push_str(&format!(
+                "{}{{key=\"{}\",rank=\"{}\"}} {}\n",
+                name,
+                Self::escape_prometheus_label(&top_key.key),
+                rank + 1,
+                top_key.count
+            ));
+        }
+    }
 }
 
 /// Transport type for metrics tracking
@@ -316,6 +1813,19 @@ pub enum Transport {
     Http,
     Grpc,
     Redis,
+    Udp,
+}
+
+impl Transport {
+    /// Lowercase name, as used in metric labels and logs
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transport::Http => "http",
+            Transport::Grpc => "grpc",
+            Transport::Redis => "redis",
+            Transport::Udp => "udp",
+        }
+    }
 }
 
 impl Default for Metrics {
@@ -379,6 +1889,49 @@ mod tests {
         assert!(output.contains("throttlecrab_requests_by_transport{transport=\"grpc\"} 1"));
     }
 
+    #[test]
+    fn test_latency_histogram() {
+        let metrics = Metrics::new();
+        metrics.record_latency(Transport::Http, Duration::from_micros(40));
+        metrics.record_latency(Transport::Http, Duration::from_micros(100));
+        metrics.record_latency(Transport::Http, Duration::from_micros(300));
+        metrics.record_latency(Transport::Http, Duration::from_secs(1));
+        assert_eq!(metrics.http_latency.count(), 4);
+        assert_eq!(metrics.grpc_latency.count(), 0);
+
+        // Buckets are cumulative and bounds are inclusive
+        let output = metrics.export_prometheus();
+        let series = "throttlecrab_request_duration_seconds";
+        assert!(output.contains(&format!(
+            "{series}_bucket{{transport=\"http\",le=\"0.00005\"}} 1\n"
+        )));
+        assert!(output.contains(&format!(
+            "{series}_bucket{{transport=\"http\",le=\"0.0001\"}} 2\n"
+        )));
+        assert!(output.contains(&format!(
+            "{series}_bucket{{transport=\"http\",le=\"0.25\"}} 3\n"
+        )));
+        assert!(output.contains(&format!(
+            "{series}_bucket{{transport=\"http\",le=\"+Inf\"}} 4\n"
+        )));
+        assert!(output.contains(&format!("{series}_sum{{transport=\"http\"}} 1.00044\n")));
+        assert!(output.contains(&format!("{series}_count{{transport=\"http\"}} 4\n")));
+        assert!(output.contains(&format!("{series}_count{{transport=\"redis\"}} 0\n")));
+
+        // Quantiles interpolate within buckets; the overflow bucket reports
+        // the largest bound
+        let http = &metrics.http_latency;
+        assert_eq!(http.quantile(0.5), Some(Duration::from_micros(100)));
+        assert_eq!(http.quantile(0.75), Some(Duration::from_micros(500)));
+        assert_eq!(http.quantile(0.99), Some(Duration::from_millis(250)));
+        assert_eq!(metrics.grpc_latency.quantile(0.5), None);
+        let series = "throttlecrab_request_duration_quantile_seconds";
+        assert!(output.contains(&format!(
+            "{series}{{transport=\"http\",quantile=\"0.5\"}} 0.0001\n"
+        )));
+        assert!(!output.contains(&format!("{series}{{transport=\"grpc\"")));
+    }
+
     #[test]
     fn test_counter_consistency() {
         let metrics = Metrics::new();
@@ -409,4 +1962,130 @@ mod tests {
         assert_eq!(metrics.requests_denied.load(Ordering::Relaxed), 2);
         assert_eq!(metrics.requests_errors.load(Ordering::Relaxed), 1);
     }
+
+    #[test]
+    fn test_top_denied_keys_aggregated_in_background() {
+        let metrics = Metrics::builder().max_denied_keys(2).build();
+
+        for _ in 0..3 {
+            metrics.record_request_with_key(Transport::Http, false, "first");
+        }
+        metrics.record_request_with_key(Transport::Http, false, "second");
+        metrics.record_request_with_key(
+            Transport::Http,
+            false,
+            &"x".repeat(MAX_TRACKED_KEY_LENGTH + 1),
+        );
+
+        // Export sees every denial recorded before it
+        let output = metrics.export_prometheus();
+        assert!(output.contains("throttlecrab_top_denied_keys{key=\"first\",rank=\"1\"} 3"));
+        assert!(output.contains("throttlecrab_top_denied_keys{key=\"second\",rank=\"2\"} 1"));
+        assert!(output.contains("throttlecrab_top_denied_keys_dropped 0"));
+        assert!(!output.contains("xxx"));
+    }
+
+    #[test]
+    fn test_top_keys_sampled() {
+        let metrics = Metrics::builder()
+            .max_denied_keys(0)
+            .top_keys(10, 2)
+            .build();
+
+        // Every second request is sampled and counts twice
+        for _ in 0..4 {
+            metrics.record_request_with_key(Transport::Http, true, "a");
+        }
+        for _ in 0..2 {
+            metrics.record_request_with_key(Transport::Http, false, "b");
+        }
+
+        let top = |key: &str, count| TopKey {
+            key: key.to_string(),
+            count,
+        };
+        assert_eq!(
+            metrics.top_keys(TopKeysKind::Requested),
+            Some(vec![top("a", 4), top("b", 2)])
+        );
+        assert_eq!(
+            metrics.top_keys(TopKeysKind::Allowed),
+            Some(vec![top("a", 4)])
+        );
+        assert_eq!(metrics.top_keys(TopKeysKind::Denied), None);
+
+        let output = metrics.export_prometheus();
+        assert!(output.contains("throttlecrab_top_requested_keys{key=\"a\",rank=\"1\"} 4"));
+        assert!(output.contains("throttlecrab_top_allowed_keys{key=\"a\",rank=\"1\"} 4"));
+        assert!(!output.contains("throttlecrab_top_denied_keys"));
+
+        assert_eq!(Metrics::new().top_keys(TopKeysKind::Requested), None);
+    }
+
+    #[test]
+    fn test_high_water_mark() {
+        let mark = HighWaterMark::default();
+        let at = |secs| UNIX_EPOCH + std::time::Duration::from_secs(secs);
+
+        mark.observe(5, at(10));
+        mark.observe(3, at(20));
+        assert_eq!(
+            mark.get(),
+            Peak {
+                value: 5,
+                at_ms: 10_000
+            }
+        );
+
+        mark.observe(8, at(30));
+        assert_eq!(
+            mark.get(),
+            Peak {
+                value: 8,
+                at_ms: 30_000
+            }
+        );
+
+        assert_eq!(
+            mark.reset(),
+            Peak {
+                value: 8,
+                at_ms: 30_000
+            }
+        );
+        assert_eq!(mark.get(), Peak { value: 0, at_ms: 0 });
+    }
+
+    #[test]
+    fn test_rate_window() {
+        let window = RateWindow::default();
+        let at = |millis| UNIX_EPOCH + std::time::Duration::from_millis(millis);
+
+        assert_eq!(window.record(at(1_000)), 1);
+        assert_eq!(window.record(at(1_999)), 2);
+        // A new second starts a new count
+        assert_eq!(window.record(at(2_000)), 1);
+    }
+
+    #[test]
+    fn test_peaks_export_and_reset() {
+        let metrics = Metrics::new();
+        metrics.record_request(Transport::Http, true);
+        metrics.record_request(Transport::Http, true);
+        metrics
+            .peak_store_keys
+            .observe(42, UNIX_EPOCH + std::time::Duration::from_millis(1_500));
+
+        let peaks = metrics.peaks();
+        assert!(peaks.requests_per_second.value >= 1);
+        assert_eq!(peaks.store_keys.value, 42);
+
+        let output = metrics.export_prometheus();
+        assert!(output.contains("throttlecrab_peak_store_keys 42\n"));
+        assert!(output.contains("throttlecrab_peak_store_keys_timestamp_seconds 1.500\n"));
+        assert!(output.contains("throttlecrab_peak_queue_depth 0\n"));
+
+        assert_eq!(metrics.reset_peaks(), peaks);
+        assert_eq!(metrics.peaks().store_keys.value, 0);
+    }
 }
@@ -0,0 +1,438 @@
+//! Metrics push to a collector
+//!
+//! Some environments cannot scrape `/metrics`. With `--metrics-push-url` a
+//! background task POSTs a snapshot of the same metrics to a collector
+//! instead, in one of two formats:
+//!
+//! - **JSON**: `{"timestamp_ms": .., "samples": [{"name", "labels", "value"}]}`
+//! - **Remote write**: a Prometheus remote-write `WriteRequest`, snappy
+//!   compressed, accepted by Prometheus, Mimir, Thanos, VictoriaMetrics and
+//!   most hosted collectors
+//!
+//! If a token is configured it is sent as `Authorization: Bearer <token>`.
+//! Pushes are spread by ±10% of the interval so a fleet of servers does not
+//! hit the collector in lockstep. After a failed push the interval doubles
+//! per consecutive failure, up to five minutes, and resets on the next
+//! success. Outcomes are counted in `throttlecrab_metrics_pushes` and
+//! `throttlecrab_metrics_push_failures`.
+//!
+//! Pushing needs the `metrics-push` feature; configuring it in a build
+//! without the feature is a startup error.
+
+use crate::config::MetricsPushConfig;
+use crate::metrics::Metrics;
+use anyhow::Result;
+use std::sync::Arc;
+
+/// Start pushing metrics snapshots in the background
+///
+/// # Errors
+///
+/// Returns an error if the `metrics-push` feature was not compiled in or the
+/// HTTP client cannot be created.
+pub(crate) fn start(config: &MetricsPushConfig, metrics: Arc<Metrics>) -> Result<()> {
+    #[cfg(feature = "metrics-push")]
+    {
+        let pusher = push::Pusher::new(config, metrics)?;
+        tokio::spawn(pusher.run());
+        Ok(())
+    }
+    #[cfg(not(feature = "metrics-push"))]
+    {
+        let _ = (config, metrics);
+        Err(anyhow::anyhow!(
+            "Metrics push is not available, rebuild with `--features metrics-push`"
+        ))
+    }
+}
+
+#[cfg(feature = "metrics-push")]
+mod push {
+    use crate::config::{MetricsPushConfig, MetricsPushFormat};
+    use crate::metrics::Metrics;
+    use anyhow::{Result, anyhow};
+    use prost::Message;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Longest wait between pushes while the endpoint keeps failing
+    const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+    /// One metric value with its labels
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub(super) struct Sample {
+        pub(super) name: String,
+        pub(super) labels: BTreeMap<String, String>,
+        pub(super) value: f64,
+    }
+
+    #[derive(Serialize)]
+    struct JsonSnapshot<'a> {
+        timestamp_ms: i64,
+        samples: &'a [Sample],
+    }
+
+    // Prometheus remote-write 1.0 messages (prometheus/prompb)
+
+    #[derive(Clone, PartialEq, Message)]
+    pub(super) struct WriteRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub(super) timeseries: Vec<TimeSeries>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub(super) struct TimeSeries {
+        #[prost(message, repeated, tag = "1")]
+        pub(super) labels: Vec<Label>,
+        #[prost(message, repeated, tag = "2")]
+        pub(super) samples: Vec<RemoteSample>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub(super) struct Label {
+        #[prost(string, tag = "1")]
+        pub(super) name: String,
+        #[prost(string, tag = "2")]
+        pub(super) value: String,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub(super) struct RemoteSample {
+        #[prost(double, tag = "1")]
+        pub(super) value: f64,
+        #[prost(int64, tag = "2")]
+        pub(super) timestamp: i64,
+    }
+
+    pub(super) struct Pusher {
+        client: reqwest::Client,
+        url: String,
+        format: MetricsPushFormat,
+        interval: Duration,
+        token: Option<String>,
+        metrics: Arc<Metrics>,
+    }
+
+    impl Pusher {
+        pub(super) fn new(config: &MetricsPushConfig, metrics: Arc<Metrics>) -> Result<Self> {
+            let client = reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()?;
+            Ok(Pusher {
+                client,
+                url: config.url.clone(),
+                format: config.format,
+                interval: Duration::from_secs(config.interval),
+                token: config.token.clone(),
+                metrics,
+            })
+        }
+
+        pub(super) async fn run(self) {
+            let mut failures = 0;
+            loop {
+                tokio::time::sleep(next_delay(self.interval, failures, random())).await;
+                match self.push().await {
+                    Ok(()) => {
+                        failures = 0;
+                        self.metrics.metrics_pushes.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(e) => {
+                        failures += 1;
+                        self.metrics
+                            .metrics_push_failures
+                            .fetch_add(1, Ordering::Relaxed);
+                        tracing::warn!(
+                            "Failed to push metrics to {} ({} in a row): {}",
+                            self.url,
+                            failures,
+                            e
+                        );
+                    }
+                }
+            }
+        }
+
+        async fn push(&self) -> Result<()> {
+            // Waits on the top keys aggregator
+            let metrics = Arc::clone(&self.metrics);
+            let exposition =
+                tokio::task::spawn_blocking(move || metrics.export_prometheus()).await?;
+            let samples = parse_exposition(&exposition);
+            let timestamp_ms = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as i64;
+
+            let mut request = self.client.post(&self.url);
+            request = match self.format {
+                MetricsPushFormat::Json => request.json(&JsonSnapshot {
+                    timestamp_ms,
+                    samples: &samples,
+                }),
+                MetricsPushFormat::RemoteWrite => request
+                    .header("Content-Type", "application/x-protobuf")
+                    .header("Content-Encoding", "snappy")
+                    .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+                    .body(encode_remote_write(&samples, timestamp_ms)?),
+            };
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+
+            request.send().await?.error_for_status()?;
+            Ok(())
+        }
+    }
+
+    /// Wait before the next push: the interval, doubled per consecutive
+    /// failure up to [`MAX_BACKOFF`], spread by ±10% using `random`
+    pub(super) fn next_delay(interval: Duration, failures: u32, random: u64) -> Duration {
+        let base = interval
+            .saturating_mul(1 << failures.min(16))
+            .min(MAX_BACKOFF.max(interval));
+        let spread = (random % 2001) as f64 / 1000.0 - 1.0;
+        base.mul_f64(1.0 + 0.1 * spread)
+    }
+
+    fn random() -> u64 {
+        RandomState::new().build_hasher().finish()
+    }
+
+    /// Encode samples as a snappy-compressed remote-write request
+    pub(super) fn encode_remote_write(samples: &[Sample], timestamp_ms: i64) -> Result<Vec<u8>> {
+        let timeseries = samples
+            .iter()
+            .map(|sample| {
+                // Labels must be sorted by name; `__name__` sorts first
+                let mut labels = vec![Label {
+                    name: "__name__".to_string(),
+                    value: sample.name.clone(),
+                }];
+                labels.extend(sample.labels.iter().map(|(name, value)| Label {
+                    name: name.clone(),
+                    value: value.clone(),
+                }));
+                TimeSeries {
+                    labels,
+                    samples: vec![RemoteSample {
+                        value: sample.value,
+                        timestamp: timestamp_ms,
+                    }],
+                }
+            })
+            .collect();
+        let body = WriteRequest { timeseries }.encode_to_vec();
+        Ok(snap::raw::Encoder::new().compress_vec(&body)?)
+    }
+
+    /// Parse the Prometheus text exposition produced by
+    /// [`Metrics::export_prometheus`] into samples
+    pub(super) fn parse_exposition(text: &str) -> Vec<Sample> {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| match parse_line(line) {
+                Ok(sample) => Some(sample),
+                Err(e) => {
+                    tracing::debug!("Skipping metrics line {:?}: {}", line, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn parse_line(line: &str) -> Result<Sample> {
+        let name_end = line
+            .find(['{', ' '])
+            .ok_or_else(|| anyhow!("missing value"))?;
+        let name = line[..name_end].to_string();
+        let mut rest = &line[name_end..];
+
+        let mut labels = BTreeMap::new();
+        if let Some(after_brace) = rest.strip_prefix('{') {
+            rest = after_brace;
+            loop {
+                rest = rest.trim_start_matches(',');
+                if let Some(after) = rest.strip_prefix('}') {
+                    rest = after;
+                    break;
+                }
+                let eq = rest.find("=\"").ok_or_else(|| anyhow!("malformed label"))?;
+                let label = rest[..eq].to_string();
+                let (value, after) = parse_label_value(&rest[eq + 2..])?;
+                labels.insert(label, value);
+                rest = after;
+            }
+        }
+
+        // A timestamp may follow the value; it is replaced by the push time
+        let value = rest
+            .split_whitespace()
+            .next()
+            .ok_or_else(|| anyhow!("missing value"))?
+            .parse()?;
+        Ok(Sample {
+            name,
+            labels,
+            value,
+        })
+    }
+
+    /// Read an escaped label value up to its closing quote
+    ///
+    /// Accepts the escapes written by `escape_prometheus_label`.
+    fn parse_label_value(s: &str) -> Result<(String, &str)> {
+        let mut value = String::new();
+        let mut chars = s.char_indices();
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '"' => return Ok((value, &s[i + 1..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some('t') => value.push('\t'),
+                    Some('x') => {
+                        let hex: String = chars.by_ref().take(2).map(|(_, c)| c).collect();
+                        let byte = u8::from_str_radix(&hex, 16)?;
+                        value.push(char::from(byte));
+                    }
+                    Some(c) => value.push(c),
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        Err(anyhow!("unterminated label value"))
+    }
+}
+
+#[cfg(all(test, feature = "metrics-push"))]
+mod tests {
+    use super::push::*;
+    use super::*;
+    use crate::config::MetricsPushFormat;
+    use crate::metrics::Transport;
+    use prost::Message;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_exposition() {
+        let metrics = Metrics::new();
+        metrics.record_request_with_key(Transport::Http, false, "say \"hi\"\\n");
+        let samples = parse_exposition(&metrics.export_prometheus());
+
+        let total = samples
+            .iter()
+            .find(|s| s.name == "throttlecrab_requests_total")
+            .unwrap();
+        assert_eq!(total.value, 1.0);
+        assert!(total.labels.is_empty());
+
+        let http = samples
+            .iter()
+            .find(|s| {
+                s.name == "throttlecrab_requests_by_transport"
+                    && s.labels.get("transport").map(String::as_str) == Some("http")
+            })
+            .unwrap();
+        assert_eq!(http.value, 1.0);
+
+        let denied = samples
+            .iter()
+            .find(|s| s.name == "throttlecrab_top_denied_keys")
+            .unwrap();
+        assert_eq!(denied.labels["key"], "say \"hi\"\\n");
+        assert_eq!(denied.labels["rank"], "1");
+    }
+
+    #[test]
+    fn test_encode_remote_write() {
+        let samples = parse_exposition("up{job=\"a\",instance=\"b\"} 1\nother 2.5\n");
+        let body = encode_remote_write(&samples, 1_000).unwrap();
+        let decoded = snap::raw::Decoder::new().decompress_vec(&body).unwrap();
+        let request = WriteRequest::decode(decoded.as_slice()).unwrap();
+
+        assert_eq!(request.timeseries.len(), 2);
+        let names: Vec<_> = request.timeseries[0]
+            .labels
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect();
+        assert_eq!(names, ["__name__", "instance", "job"]);
+        assert_eq!(request.timeseries[1].samples[0].value, 2.5);
+        assert_eq!(request.timeseries[1].samples[0].timestamp, 1_000);
+    }
+
+    #[test]
+    fn test_next_delay_jitter_and_backoff() {
+        let interval = Duration::from_secs(10);
+        for random in [0, 1_000, 2_000, u64::MAX] {
+            let delay = next_delay(interval, 0, random);
+            assert!(delay >= Duration::from_secs(9) && delay <= Duration::from_secs(11));
+        }
+        assert_eq!(next_delay(interval, 2, 1_000), Duration::from_secs(40));
+        assert_eq!(next_delay(interval, 30, 1_000), Duration::from_secs(300));
+    }
+
+    #[tokio::test]
+    async fn test_push_with_bearer_token() {
+        use axum::http::{HeaderMap, StatusCode};
+        use axum::{Json, Router, routing::post};
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<serde_json::Value>();
+        let app = Router::new().route(
+            "/push",
+            post(
+                move |headers: HeaderMap, Json(body): Json<serde_json::Value>| async move {
+                    if headers["authorization"] != "Bearer secret" {
+                        return StatusCode::UNAUTHORIZED;
+                    }
+                    tx.send(body).unwrap();
+                    StatusCode::NO_CONTENT
+                },
+            ),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/push", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let metrics = Arc::new(Metrics::new());
+        metrics.record_request(Transport::Redis, true);
+        start(
+            &MetricsPushConfig {
+                url,
+                format: MetricsPushFormat::Json,
+                interval: 1,
+                token: Some("secret".to_string()),
+            },
+            Arc::clone(&metrics),
+        )
+        .unwrap();
+
+        let body = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(body["timestamp_ms"].as_i64().unwrap() > 0);
+        let total = body["samples"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|s| s["name"] == "throttlecrab_requests_total")
+            .unwrap();
+        assert_eq!(total["value"], 1.0);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(metrics.metrics_pushes.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.metrics_push_failures.load(Ordering::Relaxed), 0);
+    }
+}
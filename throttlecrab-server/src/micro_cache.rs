@@ -0,0 +1,202 @@
+//! Micro-cache of recent throttle decisions
+//!
+//! With `--micro-cache-ms N` the server remembers each throttle decision
+//! for `N` milliseconds, keyed by the key and its rate limit parameters.
+//! Identical checks arriving in that time are answered from the cache
+//! without a round trip to the actor:
+//!
+//! - An allowed decision is reused while its `remaining` covers the
+//!   request's `quantity`, and `remaining` is decremented for each reuse.
+//!   Once it runs out the next check goes to the actor and its decision
+//!   replaces the cached one.
+//! - A denied decision is reused as is for checks of at least the denied
+//!   `quantity`; smaller checks go to the actor, which may allow them.
+//!
+//! Cached answers never reach the store, so within one window a key may be
+//! allowed up to `remaining` more requests than the store records, and a
+//! denial may outlast a token refilled during the window. Keep the window
+//! short; a millisecond or two is enough to absorb bursts on hot keys. The
+//! write-ahead log, canary, decision events, hooks and trace see only the
+//! decisions made by the actor.
+//!
+//! Hits and misses are counted in `throttlecrab_micro_cache_hits` and
+//! `throttlecrab_micro_cache_misses`.
+
+use crate::types::{AlgorithmKind, ThrottleRequest, ThrottleResponse};
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use throttlecrab::WarmUp;
+
+/// Locks the cache is split across, so concurrent checks rarely contend
+const SHARDS: usize = 16;
+
+/// Entries a shard may hold before expired ones are swept out
+const SWEEP_THRESHOLD: usize = 1024;
+
+/// A key and the rate limit parameters it was checked with
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    key: Arc<str>,
+    max_burst: i64,
+    count_per_period: i64,
+    period: i64,
+    algorithm: AlgorithmKind,
+    warm_up: Option<WarmUp>,
+}
+
+impl CacheKey {
+    fn new(request: &ThrottleRequest) -> Self {
+        CacheKey {
+            key: Arc::clone(&request.key),
+            max_burst: request.max_burst,
+            count_per_period: request.count_per_period,
+            period: request.period,
+            algorithm: request.algorithm,
+            warm_up: request.warm_up,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    response: ThrottleResponse,
+    /// Quantity of the request the actor decided
+    quantity: i64,
+    expires: Instant,
+}
+
+/// Recent throttle decisions, reused for identical checks
+#[derive(Debug)]
+pub struct MicroCache {
+    ttl: Duration,
+    shards: Box<[Mutex<HashMap<CacheKey, Entry>>]>,
+}
+
+impl MicroCache {
+    /// Remember decisions for `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        MicroCache {
+            ttl,
+            shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    /// The cached answer to `request` at `now`, if there is one
+    pub fn lookup(&self, request: &ThrottleRequest, now: Instant) -> Option<ThrottleResponse> {
+        let key = CacheKey::new(request);
+        let mut shard = self.shard(&key).lock().unwrap_or_else(|e| e.into_inner());
+        let entry = shard.get_mut(&key)?;
+        if entry.expires <= now {
+            shard.remove(&key);
+            return None;
+        }
+        let response = &mut entry.response;
+        if response.allowed {
+            if response.remaining < request.quantity {
+                return None;
+            }
+            response.remaining -= request.quantity;
+        } else if request.quantity < entry.quantity {
+            return None;
+        }
+        Some(response.clone())
+    }
+
+    /// Remember the actor's `response` to `request`, made at `now`
+    pub fn insert(&self, request: &ThrottleRequest, response: &ThrottleResponse, now: Instant) {
+        let key = CacheKey::new(request);
+        let mut shard = self.shard(&key).lock().unwrap_or_else(|e| e.into_inner());
+        if shard.len() >= SWEEP_THRESHOLD {
+            shard.retain(|_, entry| entry.expires > now);
+        }
+        shard.insert(
+            key,
+            Entry {
+                response: response.clone(),
+                quantity: request.quantity,
+                expires: now + self.ttl,
+            },
+        );
+    }
+
+    fn shard(&self, key: &CacheKey) -> &Mutex<HashMap<CacheKey, Entry>> {
+        let mut hasher = DefaultHasher::new();
+        key.key.hash(&mut hasher);
+        &self.shards[(hasher.finish() % SHARDS as u64) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(allowed: bool, remaining: i64) -> ThrottleResponse {
+        ThrottleResponse {
+            allowed,
+            limit: 10,
+            remaining,
+            reset_after: 1,
+            retry_after: if allowed { 0 } else { 1 },
+            retry_after_ms: if allowed { 0 } else { 1000 },
+            reset_after_ms: 1000,
+            cacheable_until_ms: 0,
+            warning: false,
+        }
+    }
+
+    #[test]
+    fn test_reuse_allowed() {
+        let cache = MicroCache::new(Duration::from_millis(1));
+        let now = Instant::now();
+        let request = ThrottleRequest::new("a", 10, 100, 60);
+        assert!(cache.lookup(&request, now).is_none());
+
+        cache.insert(&request, &response(true, 2), now);
+        assert_eq!(cache.lookup(&request, now).unwrap().remaining, 1);
+        assert_eq!(cache.lookup(&request, now).unwrap().remaining, 0);
+        // Out of tokens: the actor decides
+        assert!(cache.lookup(&request, now).is_none());
+
+        // Other parameters are another entry
+        let other = ThrottleRequest {
+            period: 1,
+            ..request
+        };
+        assert!(cache.lookup(&other, now).is_none());
+    }
+
+    #[test]
+    fn test_reuse_denied_until_expiry() {
+        let cache = MicroCache::new(Duration::from_millis(1));
+        let now = Instant::now();
+        let request = ThrottleRequest::new("a", 10, 100, 60);
+        cache.insert(&request, &response(false, 0), now);
+
+        let larger = ThrottleRequest {
+            quantity: 5,
+            ..request.clone()
+        };
+        assert!(!cache.lookup(&larger, now).unwrap().allowed);
+        let later = now + Duration::from_millis(1);
+        assert!(cache.lookup(&request, later).is_none());
+    }
+
+    #[test]
+    fn test_denied_covers_larger_quantities() {
+        let cache = MicroCache::new(Duration::from_millis(1));
+        let now = Instant::now();
+        let request = |quantity| ThrottleRequest {
+            quantity,
+            ..ThrottleRequest::new("a", 10, 100, 60)
+        };
+        cache.insert(&request(5), &response(false, 3), now);
+
+        assert!(!cache.lookup(&request(5), now).unwrap().allowed);
+        assert!(!cache.lookup(&request(8), now).unwrap().allowed);
+        // A smaller check may fit in what is left: the actor decides
+        assert!(cache.lookup(&request(1), now).is_none());
+    }
+}
@@ -0,0 +1,242 @@
+//! Namespaces isolating the keys of different clients
+//!
+//! Several products can share one server without their keys colliding. A
+//! request names its namespace in a `namespace` field (the HTTP JSON body
+//! or query string, or the gRPC message), and the server stores its key as
+//! `NAMESPACE/KEY`, so `user:123` in one namespace never shares state with
+//! `user:123` in another. Peeks and resets name a namespace the same way.
+//!
+//! With `--api-key-namespaces`, a request that names no namespace is put in
+//! the one called after its API key. This is the only way Redis clients get
+//! a namespace: `AUTH NAME SECRET` places the whole connection in `NAME`.
+//!
+//! Namespace names are 1 to [`MAX_NAMESPACE_LENGTH`] ASCII letters, digits,
+//! `-`, `_` or `.`. Any other name fails with the `invalid_namespace` error
+//! code. Keys sent without a namespace are stored as they are, so a client
+//! without one can still reach another namespace's keys by sending
+//! `NAME/KEY`; give every client a namespace for full isolation.
+//!
+//! # Quotas
+//!
+//! `--namespace-quota NAME=KEYS` caps the keys a namespace may hold in the
+//! store, so one product cannot crowd out the others. Keys the namespace
+//! already holds keep working, but requests for new keys fail with the
+//! `namespace_full` error code until expired keys are cleaned up. Like
+//! `--max-keys`, a quota is split evenly between the shards.
+//!
+//! # Metrics
+//!
+//! Decisions are counted per namespace in
+//! `throttlecrab_namespace_requests_allowed` and
+//! `throttlecrab_namespace_requests_denied`, and quota rejections in
+//! `throttlecrab_namespace_quota_rejections`, each labelled with the
+//! `namespace`. Only the first [`MAX_TRACKED_NAMESPACES`] namespaces seen
+//! get counters, so clients cannot grow the metrics without bound.
+
+use crate::actor::StoreType;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Separates the namespace from the key in stored keys
+pub const SEPARATOR: char = '/';
+
+/// Longest namespace name, in bytes
+pub const MAX_NAMESPACE_LENGTH: usize = 64;
+
+/// Most namespaces counted in the metrics
+pub const MAX_TRACKED_NAMESPACES: usize = 1000;
+
+/// Shortest time between recounts of a namespace's keys once it reaches its
+/// quota
+const RECOUNT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Check that `namespace` is a valid namespace name
+///
+/// # Errors
+///
+/// Returns [`InvalidNamespaceError`] if the name is empty, too long, or
+/// holds a character other than an ASCII letter, digit, `-`, `_` or `.`.
+pub fn validate(namespace: &str) -> Result<(), InvalidNamespaceError> {
+    let valid = !namespace.is_empty()
+        && namespace.len() <= MAX_NAMESPACE_LENGTH
+        && namespace
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(InvalidNamespaceError(namespace.to_string()))
+    }
+}
+
+/// The key `key` is stored under in `namespace`
+///
+/// # Errors
+///
+/// Returns [`InvalidNamespaceError`] if `namespace` is not a valid name.
+pub fn scoped_key(namespace: &str, key: &str) -> Result<Arc<str>, InvalidNamespaceError> {
+    validate(namespace)?;
+    Ok(format!("{namespace}{SEPARATOR}{key}").into())
+}
+
+/// A request named a namespace that is not a valid name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidNamespaceError(pub String);
+
+impl InvalidNamespaceError {
+    /// `invalid_namespace`, with the offending name in the message
+    pub fn code(&self) -> &'static str {
+        "invalid_namespace"
+    }
+}
+
+impl fmt::Display for InvalidNamespaceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid namespace: {:?} (expected 1 to {} letters, digits, '-', '_' or '.')",
+            self.0, MAX_NAMESPACE_LENGTH
+        )
+    }
+}
+
+impl std::error::Error for InvalidNamespaceError {}
+
+/// A request for a new key in a namespace that holds its quota of keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceFullError(pub String);
+
+impl NamespaceFullError {
+    /// `namespace_full`: the key was refused, not rate limited
+    pub fn code(&self) -> &'static str {
+        "namespace_full"
+    }
+}
+
+impl fmt::Display for NamespaceFullError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "namespace {} is full, new keys are rejected", self.0)
+    }
+}
+
+impl std::error::Error for NamespaceFullError {}
+
+/// One shard's key quotas by namespace
+///
+/// Keys are counted as they are admitted rather than as they expire, so a
+/// count can run high. A namespace that reaches its quota has its keys
+/// recounted from the store, at most once per [`RECOUNT_INTERVAL`], before
+/// new keys are rejected.
+#[derive(Default)]
+pub(crate) struct NamespaceQuotas {
+    /// Most keys each namespace may hold
+    limits: HashMap<Box<str>, usize>,
+    /// Keys admitted per namespace since the last recount
+    counts: HashMap<Box<str>, usize>,
+    last_recount: Option<SystemTime>,
+}
+
+impl NamespaceQuotas {
+    /// Quotas of `limits` keys per namespace
+    pub(crate) fn new(limits: &BTreeMap<String, usize>) -> Self {
+        NamespaceQuotas {
+            limits: limits
+                .iter()
+                .map(|(namespace, &limit)| (namespace.as_str().into(), limit))
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Count a new `key`, if its namespace has a quota with room for it
+    ///
+    /// # Errors
+    ///
+    /// Returns [`NamespaceFullError`] if the key's namespace holds its
+    /// quota of keys.
+    pub(crate) fn admit(
+        &mut self,
+        store_type: &mut StoreType,
+        key: &str,
+        now: SystemTime,
+    ) -> Result<(), NamespaceFullError> {
+        let Some((namespace, _)) = key.split_once(SEPARATOR) else {
+            return Ok(());
+        };
+        let Some(&limit) = self.limits.get(namespace) else {
+            return Ok(());
+        };
+
+        if self.count(namespace) >= limit {
+            let recount_due = self.last_recount.is_none_or(|last| {
+                now.duration_since(last).unwrap_or_default() >= RECOUNT_INTERVAL
+            });
+            if !recount_due {
+                return Err(NamespaceFullError(namespace.to_string()));
+            }
+            self.last_recount = Some(now);
+            self.recount(store_type, now);
+            if self.count(namespace) >= limit {
+                return Err(NamespaceFullError(namespace.to_string()));
+            }
+        }
+
+        match self.counts.get_mut(namespace) {
+            Some(count) => *count += 1,
+            None => {
+                self.counts.insert(namespace.into(), 1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `key` is in a namespace with a quota
+    pub(crate) fn limits(&self, key: &str) -> bool {
+        !self.limits.is_empty()
+            && key
+                .split_once(SEPARATOR)
+                .is_some_and(|(namespace, _)| self.limits.contains_key(namespace))
+    }
+
+    fn count(&self, namespace: &str) -> usize {
+        self.counts.get(namespace).copied().unwrap_or(0)
+    }
+
+    /// Count the live keys of every namespace with a quota
+    fn recount(&mut self, store_type: &mut StoreType, now: SystemTime) {
+        store_type.remove_expired(now);
+        self.counts.clear();
+        for (key, _, _) in store_type.entries() {
+            if let Some((namespace, _)) = key.split_once(SEPARATOR)
+                && let Some((namespace, _)) = self.limits.get_key_value(namespace)
+            {
+                *self.counts.entry(namespace.clone()).or_default() += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scoped_key() {
+        assert_eq!(
+            &*scoped_key("checkout", "user:1").unwrap(),
+            "checkout/user:1"
+        );
+        assert_eq!(
+            &*scoped_key("search-v2.eu_1", "a").unwrap(),
+            "search-v2.eu_1/a"
+        );
+
+        for invalid in ["", "a/b", "a b", "ünicode", &"n".repeat(65)] {
+            let error = scoped_key(invalid, "key").unwrap_err();
+            assert_eq!(error.code(), "invalid_namespace");
+        }
+        assert!(validate(&"n".repeat(64)).is_ok());
+    }
+}
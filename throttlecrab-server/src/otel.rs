@@ -0,0 +1,210 @@
+//! OpenTelemetry span export
+//!
+//! With `--otlp-endpoint` each throttle request is exported to an OTLP/gRPC
+//! collector as three spans:
+//!
+//! - `throttle`: the request in its transport, with a `transport` attribute
+//! - `queue`: the wait for the key's actor
+//! - `store`: the store operation deciding the request
+//!
+//! A W3C `traceparent` in HTTP headers or gRPC metadata makes `throttle` a
+//! child of the caller's span, so rate limiter latency shows up in the
+//! caller's trace, and the caller's sampling decision is kept. Traces
+//! started here are sampled at `--otlp-sample-ratio`.
+//!
+//! The spans are recorded at `trace` level under the `throttlecrab::otel`
+//! target, so they stay out of the logs at any other level. Without an
+//! endpoint nothing subscribes to them and they cost next to nothing.
+//!
+//! Export needs the `otlp` feature; configuring it in a build without the
+//! feature is a startup error.
+
+use crate::config::OtlpConfig;
+use crate::metrics::Transport;
+use anyhow::Result;
+use axum::http::HeaderMap;
+use tracing::{Span, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Create the layer exporting the request spans, and the exporter behind it
+///
+/// # Errors
+///
+/// Returns an error if the `otlp` feature was not compiled in or the
+/// exporter cannot be created.
+pub fn layer<S>(config: &OtlpConfig) -> Result<(Box<dyn Layer<S> + Send + Sync>, Exporter)>
+where
+    S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+{
+    #[cfg(feature = "otlp")]
+    {
+        let (layer, provider) = export::layer(config)?;
+        Ok((layer, Exporter { provider }))
+    }
+    #[cfg(not(feature = "otlp"))]
+    {
+        let _ = config;
+        Err(anyhow::anyhow!(
+            "OpenTelemetry export is not available, rebuild with `--features otlp`"
+        ))
+    }
+}
+
+/// Exports spans in the background until shut down
+pub struct Exporter {
+    #[cfg(feature = "otlp")]
+    provider: opentelemetry_sdk::trace::SdkTracerProvider,
+}
+
+impl Exporter {
+    /// Export the spans still buffered and stop
+    pub async fn shutdown(self) {
+        #[cfg(feature = "otlp")]
+        {
+            let provider = self.provider;
+            match tokio::task::spawn_blocking(move || provider.shutdown()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => tracing::warn!("Failed to flush OpenTelemetry spans: {}", e),
+                Err(e) => tracing::warn!("Failed to flush OpenTelemetry spans: {}", e),
+            }
+        }
+    }
+}
+
+/// The span of a throttle request in its transport
+///
+/// Continues the caller's trace if `headers` carry a W3C `traceparent`.
+pub(crate) fn request_span(transport: Transport, headers: Option<&HeaderMap>) -> Span {
+    let span = tracing::trace_span!(
+        target: "throttlecrab::otel",
+        "throttle",
+        otel.kind = "server",
+        transport = transport.name()
+    );
+    #[cfg(feature = "otlp")]
+    if let Some(headers) = headers
+        && !span.is_disabled()
+    {
+        export::set_parent(&span, headers);
+    }
+    #[cfg(not(feature = "otlp"))]
+    let _ = headers;
+    span
+}
+
+/// Spans following a throttle request through the actor
+#[derive(Debug)]
+pub struct RequestSpans {
+    caller: Span,
+    queue: Span,
+}
+
+impl RequestSpans {
+    /// Start waiting for the actor, under the current span
+    pub(crate) fn enqueue() -> Self {
+        let caller = Span::current();
+        let queue = tracing::trace_span!(target: "throttlecrab::otel", parent: &caller, "queue");
+        RequestSpans { caller, queue }
+    }
+
+    /// End the wait, returning the span of the store operation
+    pub(crate) fn dequeue(self) -> Span {
+        drop(self.queue);
+        tracing::trace_span!(target: "throttlecrab::otel", parent: &self.caller, "store")
+    }
+}
+
+#[cfg(feature = "otlp")]
+mod export {
+    use crate::config::OtlpConfig;
+    use anyhow::Result;
+    use axum::http::HeaderMap;
+    use opentelemetry::propagation::{Extractor, TextMapPropagator};
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::Resource;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+    use tracing::{Level, Span, Subscriber};
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::Layer;
+    use tracing_subscriber::filter::Targets;
+    use tracing_subscriber::registry::LookupSpan;
+
+    pub(super) fn layer<S>(
+        config: &OtlpConfig,
+    ) -> Result<(Box<dyn Layer<S> + Send + Sync>, SdkTracerProvider)>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+    {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(&config.endpoint)
+            .build()?;
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                config.sample_ratio,
+            ))))
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(env!("CARGO_PKG_NAME"))
+                    .build(),
+            )
+            .build();
+        let layer = tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer("throttlecrab"))
+            .with_filter(Targets::new().with_target("throttlecrab::otel", Level::TRACE))
+            .boxed();
+        Ok((layer, provider))
+    }
+
+    /// Reads W3C trace context from HTTP headers or gRPC metadata
+    pub(super) struct HeaderExtractor<'a>(pub(super) &'a HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(|key| key.as_str()).collect()
+        }
+    }
+
+    pub(super) fn set_parent(span: &Span, headers: &HeaderMap) {
+        if !headers.contains_key("traceparent") {
+            return;
+        }
+        let parent = TraceContextPropagator::new().extract(&HeaderExtractor(headers));
+        let _ = span.set_parent(parent);
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use opentelemetry::trace::TraceContextExt;
+
+        #[test]
+        fn test_extract_trace_context() {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+                    .parse()
+                    .unwrap(),
+            );
+            let parent = TraceContextPropagator::new().extract(&HeaderExtractor(&headers));
+            let span = parent.span();
+            let context = span.span_context();
+            assert!(context.is_remote());
+            assert!(context.is_sampled());
+            assert_eq!(
+                context.trace_id().to_string(),
+                "4bf92f3577b34da6a3ce929d0e0e4736"
+            );
+            assert_eq!(context.span_id().to_string(), "00f067aa0ba902b7");
+        }
+    }
+}
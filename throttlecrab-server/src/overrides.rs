@@ -0,0 +1,343 @@
+//! Deny-list and allow-list overrides for keys
+//!
+//! For incident response, keys can be decided administratively instead of
+//! by their limits. A throttle check whose key matches a pattern on the
+//! deny-list is always denied, and one matching the allow-list is always
+//! allowed, without consuming tokens; the deny-list wins if both match.
+//! Neither reaches the store. Patterns are globs where `*` matches any run
+//! of characters, as for [policy key patterns](crate::policy), and match
+//! keys as stored, i.e. `NAMESPACE/KEY` for a request in a
+//! [namespace](crate::namespace).
+//!
+//! Overridden checks are answered in each transport's usual format with
+//! `limit` set from the request and:
+//!
+//! - denied: `remaining` 0, and `retry_after` and `reset_after` the
+//!   request's period
+//! - allowed: `remaining` the full burst and no wait
+//!
+//! The lists are managed at runtime with `/admin/overrides` (see
+//! [`transport::http`](crate::transport::http)). With
+//! `--overrides-file FILE` they are loaded from the file on startup and
+//! written back after every change, so they survive restarts:
+//!
+//! ```toml
+//! deny = ["bot:*", "tenant-a/user:42"]
+//! allow = ["internal:*"]
+//! ```
+//!
+//! Changes are logged as audit events (target `throttlecrab::audit`), and
+//! overridden checks counted in `throttlecrab_override_decisions`.
+
+use crate::policy::glob_matches;
+use crate::types::{ThrottleRequest, ThrottleResponse};
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+
+/// Patterns on the deny-list and the allow-list
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OverrideLists {
+    /// Patterns of keys that are always denied
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// Patterns of keys that are always allowed
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+impl OverrideLists {
+    /// True if neither list holds a pattern
+    pub fn is_empty(&self) -> bool {
+        self.deny.is_empty() && self.allow.is_empty()
+    }
+
+    /// Check that every pattern is usable
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a pattern is empty.
+    pub fn validate(&self) -> Result<()> {
+        if self.deny.iter().chain(&self.allow).any(String::is_empty) {
+            return Err(anyhow!("override patterns must not be empty"));
+        }
+        Ok(())
+    }
+}
+
+/// How an override decides a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Override {
+    /// The key is on the deny-list
+    Deny,
+    /// The key is on the allow-list
+    Allow,
+}
+
+impl Override {
+    /// The response to an overridden `request`
+    pub fn response(self, request: &ThrottleRequest) -> ThrottleResponse {
+        match self {
+            Override::Deny => ThrottleResponse {
+                allowed: false,
+                limit: request.max_burst,
+                remaining: 0,
+                reset_after: request.period,
+                retry_after: request.period,
+                retry_after_ms: request.period.saturating_mul(1000),
+                reset_after_ms: request.period.saturating_mul(1000),
+                cacheable_until_ms: 0,
+                warning: false,
+            },
+            Override::Allow => ThrottleResponse {
+                allowed: true,
+                limit: request.max_burst,
+                remaining: request.max_burst,
+                reset_after: 0,
+                retry_after: 0,
+                retry_after_ms: 0,
+                reset_after_ms: 0,
+                cacheable_until_ms: 0,
+                warning: false,
+            },
+        }
+    }
+}
+
+/// The override lists, shared by every transport
+#[derive(Debug, Default)]
+pub struct KeyOverrides {
+    lists: RwLock<OverrideLists>,
+    /// Whether any pattern is listed, to skip the lock on every check
+    active: AtomicBool,
+    /// File the lists are persisted to, if any
+    path: Option<PathBuf>,
+    /// Held while a change is written, so changes apply in order
+    changes: Mutex<()>,
+}
+
+impl KeyOverrides {
+    /// Overrides kept in memory only
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a pattern is empty.
+    pub fn new(lists: OverrideLists) -> Result<Self> {
+        lists.validate()?;
+        let overrides = KeyOverrides::default();
+        overrides.replace(lists);
+        Ok(overrides)
+    }
+
+    /// Overrides persisted to the TOML file at `path`, starting with the
+    /// lists it holds
+    ///
+    /// A missing file starts both lists empty; it is created on the first
+    /// change.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed, or holds an
+    /// empty pattern.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let lists = match fs::read_to_string(&path) {
+            Ok(input) => toml::from_str(&input)
+                .with_context(|| format!("Invalid overrides file {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => OverrideLists::default(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read overrides from {}", path.display()));
+            }
+        };
+        let mut overrides = Self::new(lists)
+            .with_context(|| format!("Invalid overrides file {}", path.display()))?;
+        overrides.path = Some(path);
+        Ok(overrides)
+    }
+
+    /// The current lists
+    pub fn lists(&self) -> OverrideLists {
+        self.lists.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// The override deciding `key`, if any
+    pub fn check(&self, key: &str) -> Option<Override> {
+        if !self.active.load(Ordering::Relaxed) {
+            return None;
+        }
+        let lists = self.lists.read().unwrap_or_else(|e| e.into_inner());
+        if lists.deny.iter().any(|pattern| glob_matches(pattern, key)) {
+            Some(Override::Deny)
+        } else if lists.allow.iter().any(|pattern| glob_matches(pattern, key)) {
+            Some(Override::Allow)
+        } else {
+            None
+        }
+    }
+
+    /// Add the patterns in `added` to their lists, returning the new lists
+    ///
+    /// Patterns already listed are left as they are.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a pattern is empty or the lists cannot be
+    /// persisted; the running lists are kept then.
+    pub fn add(&self, added: &OverrideLists) -> Result<OverrideLists> {
+        added.validate()?;
+        self.change("added", added, |lists| {
+            for (list, patterns) in [
+                (&mut lists.deny, &added.deny),
+                (&mut lists.allow, &added.allow),
+            ] {
+                for pattern in patterns {
+                    if !list.contains(pattern) {
+                        list.push(pattern.clone());
+                    }
+                }
+            }
+        })
+    }
+
+    /// Remove the patterns in `removed` from their lists, returning the
+    /// new lists
+    ///
+    /// Patterns that are not listed are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lists cannot be persisted; the running
+    /// lists are kept then.
+    pub fn remove(&self, removed: &OverrideLists) -> Result<OverrideLists> {
+        self.change("removed", removed, |lists| {
+            lists.deny.retain(|pattern| !removed.deny.contains(pattern));
+            lists
+                .allow
+                .retain(|pattern| !removed.allow.contains(pattern));
+        })
+    }
+
+    /// Apply `edit` to a copy of the lists, persist it, then swap it in
+    fn change(
+        &self,
+        action: &str,
+        patterns: &OverrideLists,
+        edit: impl FnOnce(&mut OverrideLists),
+    ) -> Result<OverrideLists> {
+        let _change = self.changes.lock().unwrap_or_else(|e| e.into_inner());
+        let mut lists = self.lists();
+        edit(&mut lists);
+        if let Some(path) = &self.path {
+            save(path, &lists)?;
+        }
+        self.replace(lists.clone());
+        tracing::info!(
+            target: "throttlecrab::audit",
+            deny = ?patterns.deny,
+            allow = ?patterns.allow,
+            "Key overrides {action}"
+        );
+        Ok(lists)
+    }
+
+    fn replace(&self, lists: OverrideLists) {
+        let active = !lists.is_empty();
+        *self.lists.write().unwrap_or_else(|e| e.into_inner()) = lists;
+        self.active.store(active, Ordering::Relaxed);
+    }
+}
+
+/// Write `lists` to a temporary file, then rename it over `path`, so a
+/// crash mid-write keeps the previous lists
+fn save(path: &Path, lists: &OverrideLists) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    let output = toml::to_string(lists)?;
+    let mut file = fs::File::create(&tmp_path)
+        .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+    file.write_all(output.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to replace overrides file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lists(deny: &[&str], allow: &[&str]) -> OverrideLists {
+        OverrideLists {
+            deny: deny.iter().map(|p| p.to_string()).collect(),
+            allow: allow.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let overrides = KeyOverrides::new(lists(&["bot:*", "user:1"], &["user:*"])).unwrap();
+        assert_eq!(overrides.check("bot:crawler"), Some(Override::Deny));
+        // The deny-list wins over the allow-list
+        assert_eq!(overrides.check("user:1"), Some(Override::Deny));
+        assert_eq!(overrides.check("user:2"), Some(Override::Allow));
+        assert_eq!(overrides.check("tenant/user:2"), None);
+
+        assert!(KeyOverrides::new(lists(&[""], &[])).is_err());
+        assert_eq!(KeyOverrides::default().check("user:2"), None);
+    }
+
+    #[test]
+    fn test_add_and_remove() {
+        let overrides = KeyOverrides::default();
+        let added = overrides.add(&lists(&["bot:*"], &["internal:*"])).unwrap();
+        assert_eq!(added, lists(&["bot:*"], &["internal:*"]));
+        // Adding a listed pattern again changes nothing
+        overrides.add(&lists(&["bot:*"], &[])).unwrap();
+        assert_eq!(overrides.lists(), added);
+        assert_eq!(overrides.check("internal:job"), Some(Override::Allow));
+
+        let removed = overrides.remove(&lists(&["bot:*", "other"], &[])).unwrap();
+        assert_eq!(removed, lists(&[], &["internal:*"]));
+        assert_eq!(overrides.check("bot:crawler"), None);
+        assert!(overrides.add(&lists(&[], &[""])).is_err());
+    }
+
+    #[test]
+    fn test_persisted() {
+        let path = std::env::temp_dir().join(format!(
+            "throttlecrab-overrides-{}.toml",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        let overrides = KeyOverrides::load(&path).unwrap();
+        assert!(overrides.lists().is_empty());
+        overrides.add(&lists(&["bot:*"], &["internal:*"])).unwrap();
+
+        // A restart picks the lists up again
+        let restored = KeyOverrides::load(&path).unwrap();
+        assert_eq!(restored.lists(), lists(&["bot:*"], &["internal:*"]));
+        assert_eq!(restored.check("bot:1"), Some(Override::Deny));
+
+        fs::write(&path, "deny = 5\n").unwrap();
+        assert!(KeyOverrides::load(&path).is_err());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_override_response() {
+        let request = ThrottleRequest::new("bot:1", 10, 100, 60);
+        let denied = Override::Deny.response(&request);
+        assert!(!denied.allowed);
+        assert_eq!((denied.limit, denied.remaining), (10, 0));
+        assert_eq!(denied.retry_after, 60);
+
+        let allowed = Override::Allow.response(&request);
+        assert!(allowed.allowed);
+        assert_eq!((allowed.limit, allowed.remaining), (10, 10));
+        assert_eq!(allowed.retry_after, 0);
+    }
+}
@@ -0,0 +1,702 @@
+//! Named rate limit policies configured server-side
+//!
+//! With `--policies FILE` the server loads named sets of rate limit
+//! parameters from a TOML file, one table per policy:
+//!
+//! ```toml
+//! [login]
+//! max_burst = 5
+//! count_per_period = 10
+//! period = 60
+//!
+//! [api-default]
+//! max_burst = 100
+//! count_per_period = 1000
+//! period = 60
+//!
+//! [exports]
+//! max_burst = 10
+//! count_per_period = 10
+//! period = 3600
+//! algorithm = "sliding_window"
+//! ```
+//!
+//! `algorithm` is optional and defaults to `gcra`; see [`AlgorithmKind`].
+//!
+//! Clients then send a policy name instead of the parameters, e.g.
+//! `{"key": "user:123", "policy": "login"}` over HTTP. A request naming a
+//! policy is always limited with the policy's parameters; any it sends
+//! itself are ignored, so a misconfigured client can't loosen a central
+//! limit. Naming a policy that does not exist fails with the
+//! `unknown_policy` error code.
+//!
+//! # Operation Costs
+//!
+//! A policy can charge operations differently with a cost table:
+//!
+//! ```toml
+//! [api-default]
+//! max_burst = 100
+//! count_per_period = 1000
+//! period = 60
+//! costs = { read = 1, write = 5, export = 50 }
+//! ```
+//!
+//! Clients then send an `operation` name alongside the policy instead of a
+//! quantity, e.g. `{"key": "org:42", "policy": "api-default", "operation":
+//! "export"}`, and the server charges the operation's cost. Any quantity
+//! the request sends is ignored. An operation the policy has no cost for,
+//! or an operation sent without a policy, fails with the
+//! `unknown_operation` error code.
+//!
+//! # Key Patterns
+//!
+//! A policy can also be the default for keys matching glob patterns, where
+//! `*` matches any run of characters:
+//!
+//! ```toml
+//! [login]
+//! max_burst = 5
+//! count_per_period = 5
+//! period = 60
+//! keys = ["login:*"]
+//!
+//! [api]
+//! max_burst = 1000
+//! count_per_period = 1000
+//! period = 60
+//! keys = ["api:*", "*:api"]
+//! ```
+//!
+//! A request that names no policy and sends no rate limit parameters then
+//! gets the policy of the first pattern its key matches, e.g. just
+//! `{"key": "login:alice"}`. Patterns are tried longest first, counting
+//! only their literal characters, so `login:admin:*` wins over `login:*`.
+//! Patterns match the key as stored, including any
+//! [namespace](crate::namespace) prefix. A request whose key matches no
+//! pattern is validated as before, and fails for lack of parameters.
+//!
+//! # Warm-Up
+//!
+//! A policy can ramp new keys up to its limits gradually, so that after a
+//! cache flush or restart every client doesn't get its full burst at once:
+//!
+//! ```toml
+//! [api-default]
+//! max_burst = 100
+//! count_per_period = 1000
+//! period = 60
+//! warm_up = 300
+//! warm_up_percent = 10
+//! ```
+//!
+//! A key first checked under the policy starts with `warm_up_percent` of
+//! `max_burst` and `count_per_period`, 10 by default, and grows linearly to
+//! the full values over `warm_up` seconds. A key left idle for longer than
+//! both `warm_up` and `period` warms up again. See [`throttlecrab::WarmUp`].
+//!
+//! # Soft Limits
+//!
+//! A policy can warn clients before it starts denying them:
+//!
+//! ```toml
+//! [api-default]
+//! max_burst = 100
+//! count_per_period = 1000
+//! period = 60
+//! warn_percent = 80
+//! ```
+//!
+//! Once a key has `warn_percent` of `max_burst` in use, i.e. at most 20 of
+//! the 100 tokens remain, its responses carry `warning: true` (gRPC
+//! `warning`, and a 1 as the last element of a Redis `THROTTLE` reply), and
+//! so do its denials. Allowed requests past the threshold are counted in
+//! `throttlecrab_soft_limit_warnings`, so alerts can fire before clients
+//! are actually limited.
+//!
+//! # Reloading
+//!
+//! `SIGHUP` or `POST /admin/reload` re-reads the file and swaps in the new
+//! policies at once. Connections stay open and rate limit state is kept, so
+//! keys limited under a changed policy continue from their current state
+//! with the new parameters. A file that fails to parse leaves the running
+//! policies in place.
+
+use crate::types::{AlgorithmKind, ThrottleRequest};
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use throttlecrab::WarmUp;
+
+/// Rate limit parameters shared by every request naming the policy
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Maximum burst capacity
+    pub max_burst: i64,
+    /// Total requests allowed per period
+    pub count_per_period: i64,
+    /// Time period in seconds
+    pub period: i64,
+    /// Rate limiting algorithm
+    #[serde(default)]
+    pub algorithm: AlgorithmKind,
+    /// Quantity charged per operation name
+    #[serde(default)]
+    pub costs: HashMap<String, i64>,
+    /// Seconds over which new keys ramp up to the full limits
+    #[serde(default)]
+    pub warm_up: Option<u64>,
+    /// Percent of the limits a new key starts with, from 1 to 100
+    #[serde(default = "default_warm_up_percent")]
+    pub warm_up_percent: u8,
+    /// Glob patterns of keys the policy is the default for
+    #[serde(default)]
+    pub keys: Vec<String>,
+    /// Percent of `max_burst` in use from which responses are warnings,
+    /// from 1 to 100
+    #[serde(default)]
+    pub warn_percent: Option<u8>,
+}
+
+fn default_warm_up_percent() -> u8 {
+    10
+}
+
+impl Policy {
+    /// Replace the rate limit parameters of `request` with the policy's
+    pub fn apply(&self, request: &mut ThrottleRequest) {
+        request.max_burst = self.max_burst;
+        request.count_per_period = self.count_per_period;
+        request.period = self.period;
+        request.algorithm = self.algorithm;
+        request.warm_up = self
+            .warm_up
+            .map(|secs| WarmUp::new(Duration::from_secs(secs), self.warm_up_percent));
+        request.warn_percent = self.warn_percent;
+    }
+
+    /// The quantity charged for `operation`, if the policy prices it
+    pub fn cost(&self, operation: &str) -> Option<i64> {
+        self.costs.get(operation).copied()
+    }
+}
+
+/// Policies by name, as loaded from a policy file
+///
+/// Shared by every handle it is attached to; [`reload`](Policies::reload)
+/// replaces the policies for all of them.
+#[derive(Debug, Default)]
+pub struct Policies {
+    policies: RwLock<Table>,
+    /// File the policies were loaded from, if any
+    source: Option<PathBuf>,
+    /// Table of `source` holding the policies, if it is a config file
+    table: Option<String>,
+}
+
+/// Policies by name, and the key patterns naming them
+#[derive(Debug, Default)]
+struct Table {
+    by_name: HashMap<String, Arc<Policy>>,
+    /// Longest first
+    patterns: Vec<(KeyPattern, Arc<str>)>,
+}
+
+impl Policies {
+    /// Parse a TOML policy file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is not valid TOML, a table has missing
+    /// or unknown fields, or a parameter is not positive.
+    pub fn parse(input: &str) -> Result<Self> {
+        Ok(Policies {
+            policies: RwLock::new(parse_table(input)?),
+            source: None,
+            table: None,
+        })
+    }
+
+    /// Parse policies from a table of an already parsed TOML document
+    pub(crate) fn from_value(policies: toml::Value) -> Result<Self> {
+        Ok(Policies {
+            policies: RwLock::new(parse_value(policies)?),
+            source: None,
+            table: None,
+        })
+    }
+
+    /// Read and parse the policy file at `path`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed.
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Policies {
+            policies: RwLock::new(read_file(path, None)?),
+            source: Some(path.to_path_buf()),
+            table: None,
+        })
+    }
+
+    /// Read and parse the policies in `table` of the config file at `path`
+    ///
+    /// Used for policies written into a `--config` file rather than a
+    /// policy file of their own; see [`crate::config_file`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed, or has no
+    /// such table.
+    pub fn load_table(path: &Path, table: &str) -> Result<Self> {
+        Ok(Policies {
+            policies: RwLock::new(read_file(path, Some(table))?),
+            source: Some(path.to_path_buf()),
+            table: Some(table.to_string()),
+        })
+    }
+
+    /// Re-read the file the policies were loaded from, returning how many
+    /// policies it defines
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, keeping the current policies, if the policies were
+    /// not loaded from a file or the file cannot be read or parsed.
+    pub fn reload(&self) -> Result<usize> {
+        let path = self
+            .source
+            .as_deref()
+            .ok_or_else(|| anyhow!("policies were not loaded from a file"))?;
+        let policies = read_file(path, self.table.as_deref())?;
+        let count = policies.by_name.len();
+        *self.policies.write().unwrap_or_else(|e| e.into_inner()) = policies;
+        Ok(count)
+    }
+
+    /// Look up the policy called `name`
+    pub fn get(&self, name: &str) -> Result<Arc<Policy>, UnknownPolicyError> {
+        self.policies
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .by_name
+            .get(name)
+            .cloned()
+            .ok_or_else(|| UnknownPolicyError(name.to_string()))
+    }
+
+    /// The name and policy of the longest pattern matching `key`, if any
+    pub fn for_key(&self, key: &str) -> Option<(Arc<str>, Arc<Policy>)> {
+        let table = self.policies.read().unwrap_or_else(|e| e.into_inner());
+        let (_, name) = table
+            .patterns
+            .iter()
+            .find(|(pattern, _)| pattern.matches(key))?;
+        let policy = Arc::clone(&table.by_name[&**name]);
+        Some((Arc::clone(name), policy))
+    }
+
+    /// Number of policies
+    pub fn len(&self) -> usize {
+        self.policies
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .by_name
+            .len()
+    }
+
+    /// True if no policy is defined
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn read_file(path: &Path, table: Option<&str>) -> Result<Table> {
+    let input = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read policies from {}", path.display()))?;
+    match table {
+        None => {
+            parse_table(&input).with_context(|| format!("Invalid policy file {}", path.display()))
+        }
+        Some(table) => {
+            let mut config: toml::Table = toml::from_str(&input)
+                .with_context(|| format!("Invalid config file {}", path.display()))?;
+            let policies = config
+                .remove(table)
+                .ok_or_else(|| anyhow!("{}: no [{table}] table", path.display()))?;
+            parse_value(policies).with_context(|| format!("{}: [{table}]", path.display()))
+        }
+    }
+}
+
+fn parse_table(input: &str) -> Result<Table> {
+    check_table(toml::from_str(input)?)
+}
+
+/// Parse policies from an already parsed TOML table, e.g. a config file's
+fn parse_value(policies: toml::Value) -> Result<Table> {
+    check_table(policies.try_into()?)
+}
+
+fn check_table(policies: HashMap<String, Policy>) -> Result<Table> {
+    let mut patterns: Vec<(KeyPattern, Arc<str>)> = Vec::new();
+    for (name, policy) in &policies {
+        if policy.max_burst <= 0 || policy.count_per_period <= 0 || policy.period <= 0 {
+            return Err(anyhow!(
+                "policy {name}: max_burst, count_per_period and period must be greater than 0"
+            ));
+        }
+        if let Some((operation, _)) = policy.costs.iter().find(|(_, cost)| **cost < 0) {
+            return Err(anyhow!(
+                "policy {name}: cost of {operation} must not be negative"
+            ));
+        }
+        if policy.warm_up == Some(0) {
+            return Err(anyhow!("policy {name}: warm_up must be greater than 0"));
+        }
+        if !(1..=100).contains(&policy.warm_up_percent) {
+            return Err(anyhow!(
+                "policy {name}: warm_up_percent must be between 1 and 100"
+            ));
+        }
+        if policy
+            .warn_percent
+            .is_some_and(|percent| !(1..=100).contains(&percent))
+        {
+            return Err(anyhow!(
+                "policy {name}: warn_percent must be between 1 and 100"
+            ));
+        }
+        for pattern in &policy.keys {
+            if pattern.is_empty() {
+                return Err(anyhow!("policy {name}: key patterns must not be empty"));
+            }
+            if let Some((_, other)) = patterns.iter().find(|(p, _)| p.0 == *pattern) {
+                return Err(anyhow!(
+                    "policy {name}: key pattern {pattern:?} is already used by policy {other}"
+                ));
+            }
+            patterns.push((KeyPattern(pattern.clone()), name.as_str().into()));
+        }
+    }
+    // Longest first; names break ties, so overlaps resolve the same way on
+    // every load
+    patterns.sort_by(|(a, a_name), (b, b_name)| {
+        b.literal_len()
+            .cmp(&a.literal_len())
+            .then_with(|| a_name.cmp(b_name))
+    });
+    Ok(Table {
+        by_name: policies
+            .into_iter()
+            .map(|(name, policy)| (name, Arc::new(policy)))
+            .collect(),
+        patterns,
+    })
+}
+
+/// A glob pattern of keys, where `*` matches any run of characters
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KeyPattern(String);
+
+impl KeyPattern {
+    /// Number of characters other than `*`
+    fn literal_len(&self) -> usize {
+        self.0.len() - self.0.matches('*').count()
+    }
+
+    fn matches(&self, key: &str) -> bool {
+        glob_matches(&self.0, key)
+    }
+}
+
+/// Whether `key` matches the glob `pattern`, where `*` matches any run of
+/// characters
+pub(crate) fn glob_matches(pattern: &str, key: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // `split` always yields at least one part
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = key.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No `*`: the whole key must match
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// A request named a policy that is not defined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPolicyError(pub String);
+
+impl UnknownPolicyError {
+    /// `unknown_policy`, with the policy name in the message
+    pub fn code(&self) -> &'static str {
+        "unknown_policy"
+    }
+}
+
+impl fmt::Display for UnknownPolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown policy: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownPolicyError {}
+
+/// A request named an operation its policy has no cost for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownOperationError {
+    /// The operation sent
+    pub operation: String,
+    /// The policy named alongside it, if any
+    pub policy: Option<String>,
+}
+
+impl UnknownOperationError {
+    /// `unknown_operation`, whether or not a policy was named
+    pub fn code(&self) -> &'static str {
+        "unknown_operation"
+    }
+}
+
+impl fmt::Display for UnknownOperationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.policy {
+            Some(policy) => write!(
+                f,
+                "unknown operation: {} (policy {} has no cost for it)",
+                self.operation, policy
+            ),
+            None => write!(
+                f,
+                "unknown operation: {} (operations need a policy)",
+                self.operation
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UnknownOperationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_policies() {
+        let policies = Policies::parse(
+            r#"
+            [login]
+            max_burst = 5
+            count_per_period = 10
+            period = 60
+
+            ["api-default"]
+            max_burst = 100
+            count_per_period = 1000
+            period = 60
+
+            [exports]
+            max_burst = 10
+            count_per_period = 10
+            period = 3600
+            algorithm = "sliding_window"
+            costs = { csv = 1, pdf = 5 }
+            warm_up = 300
+            warm_up_percent = 20
+            warn_percent = 80
+            "#,
+        )
+        .unwrap();
+        assert_eq!(policies.len(), 3);
+        assert_eq!(
+            *policies.get("login").unwrap(),
+            Policy {
+                max_burst: 5,
+                count_per_period: 10,
+                period: 60,
+                algorithm: AlgorithmKind::Gcra,
+                costs: HashMap::new(),
+                warm_up: None,
+                warm_up_percent: 10,
+                keys: Vec::new(),
+                warn_percent: None,
+            }
+        );
+        let exports = policies.get("exports").unwrap();
+        assert_eq!(exports.algorithm, AlgorithmKind::SlidingWindow);
+        assert_eq!(exports.cost("pdf"), Some(5));
+        assert_eq!(exports.cost("xlsx"), None);
+        assert_eq!((exports.warm_up, exports.warm_up_percent), (Some(300), 20));
+        assert_eq!(exports.warn_percent, Some(80));
+        let error = policies.get("signup").unwrap_err();
+        assert_eq!(error.to_string(), "unknown policy: signup");
+        assert_eq!(error.code(), "unknown_policy");
+    }
+
+    #[test]
+    fn test_parse_policies_errors() {
+        // Missing, unknown and non-positive parameters
+        assert!(Policies::parse("[login]\nmax_burst = 5\nperiod = 60\n").is_err());
+        assert!(
+            Policies::parse(
+                "[login]\nmax_burst = 5\ncount_per_period = 10\nperiod = 60\nquantity = 2\n"
+            )
+            .is_err()
+        );
+        let error = Policies::parse("[login]\nmax_burst = 5\ncount_per_period = 0\nperiod = 60\n")
+            .unwrap_err();
+        assert!(error.to_string().contains("login"), "{error}");
+        assert!(Policies::parse("login = 5\n").is_err());
+        assert!(
+            Policies::parse(
+                "[login]\nmax_burst = 5\ncount_per_period = 10\nperiod = 60\nalgorithm = \"lottery\"\n"
+            )
+            .is_err()
+        );
+        let error = Policies::parse(
+            "[login]\nmax_burst = 5\ncount_per_period = 10\nperiod = 60\ncosts = { read = -1 }\n",
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("read"), "{error}");
+        let error = Policies::parse(
+            "[login]\nmax_burst = 5\ncount_per_period = 10\nperiod = 60\nwarm_up = 60\nwarm_up_percent = 0\n",
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("warm_up_percent"), "{error}");
+        let error = Policies::parse(
+            "[login]\nmax_burst = 5\ncount_per_period = 10\nperiod = 60\nwarn_percent = 0\n",
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("warn_percent"), "{error}");
+    }
+
+    #[test]
+    fn test_reload_policies() {
+        let path =
+            std::env::temp_dir().join(format!("throttlecrab-policies-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[login]\nmax_burst = 5\ncount_per_period = 10\nperiod = 60\n",
+        )
+        .unwrap();
+        let policies = Policies::load(&path).unwrap();
+        assert_eq!(policies.get("login").unwrap().max_burst, 5);
+
+        std::fs::write(
+            &path,
+            "[login]\nmax_burst = 3\ncount_per_period = 10\nperiod = 60\n\n\
+             [signup]\nmax_burst = 1\ncount_per_period = 1\nperiod = 3600\n",
+        )
+        .unwrap();
+        assert_eq!(policies.reload().unwrap(), 2);
+        assert_eq!(policies.get("login").unwrap().max_burst, 3);
+        assert!(policies.get("signup").is_ok());
+
+        // A broken file keeps the running policies
+        std::fs::write(&path, "[login]\nmax_burst = 3\n").unwrap();
+        assert!(policies.reload().is_err());
+        assert_eq!(policies.len(), 2);
+
+        // Policies parsed from a string have no file to reload
+        assert!(Policies::parse("").unwrap().reload().is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_reload_config_file_policies() {
+        let path =
+            std::env::temp_dir().join(format!("throttlecrab-config-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "log-level = \"info\"\n\n\
+             [policies.login]\nmax_burst = 5\ncount_per_period = 10\nperiod = 60\n",
+        )
+        .unwrap();
+        let policies = Policies::load_table(&path, "policies").unwrap();
+        assert_eq!(policies.get("login").unwrap().max_burst, 5);
+
+        std::fs::write(
+            &path,
+            "log-level = \"info\"\n\n\
+             [policies.login]\nmax_burst = 3\ncount_per_period = 10\nperiod = 60\n",
+        )
+        .unwrap();
+        assert_eq!(policies.reload().unwrap(), 1);
+        assert_eq!(policies.get("login").unwrap().max_burst, 3);
+
+        // Losing the table keeps the running policies
+        std::fs::write(&path, "log-level = \"info\"\n").unwrap();
+        assert!(policies.reload().is_err());
+        assert_eq!(policies.len(), 1);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_key_patterns() {
+        let policies = Policies::parse(
+            r#"
+            [login]
+            max_burst = 5
+            count_per_period = 5
+            period = 60
+            keys = ["login:*"]
+
+            [admin]
+            max_burst = 1
+            count_per_period = 1
+            period = 60
+            keys = ["login:admin:*", "*:root"]
+
+            [api]
+            max_burst = 1000
+            count_per_period = 1000
+            period = 60
+            keys = ["api:*:v?", "health"]
+            "#,
+        )
+        .unwrap();
+        let name = |key| policies.for_key(key).map(|(name, _)| name.to_string());
+        assert_eq!(name("login:alice").as_deref(), Some("login"));
+        assert_eq!(name("login:").as_deref(), Some("login"));
+        // The longer pattern wins
+        assert_eq!(name("login:admin:bob").as_deref(), Some("admin"));
+        assert_eq!(name("ns/user:root").as_deref(), Some("admin"));
+        // Only `*` is special
+        assert_eq!(name("api:orders:v?").as_deref(), Some("api"));
+        assert_eq!(name("api:orders:v1"), None);
+        assert_eq!(name("health").as_deref(), Some("api"));
+        assert_eq!(name("healthz"), None);
+        assert_eq!(name("logins"), None);
+
+        let pattern = KeyPattern("a*b*a".to_string());
+        assert!(pattern.matches("aba"));
+        assert!(pattern.matches("axxbyya"));
+        assert!(!pattern.matches("ab"));
+        assert!(!pattern.matches("a"));
+
+        // A pattern can only name one policy
+        let error = Policies::parse(
+            "[a]\nmax_burst = 1\ncount_per_period = 1\nperiod = 1\nkeys = [\"x:*\"]\n\
+             [b]\nmax_burst = 1\ncount_per_period = 1\nperiod = 1\nkeys = [\"x:*\"]\n",
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("x:*"), "{error}");
+    }
+}
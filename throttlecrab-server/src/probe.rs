@@ -0,0 +1,423 @@
+//! Self-probing through the server's own transports
+//!
+//! With `--probe-interval N` the server sends a throttle request for a
+//! synthetic key (`--probe-key`, default `__throttlecrab_probe`) to each
+//! enabled transport every `N` seconds. Probes connect over loopback like any
+//! client, so they exercise the full path: accept, protocol parsing,
+//! authentication, the actor and the response encoding. The multiplexed
+//! port is probed once per protocol. An HTTP transport serving TLS is not
+//! probed, nor is the UDP transport, whose lost replies would read as
+//! failures. With API keys configured, probes authenticate with the first.
+//!
+//! Outcomes are recorded per target (`http`, `grpc`, `redis`, `mux_http`,
+//! `mux_grpc`, `mux_redis`) and exported as `throttlecrab_probe_successes`,
+//! `throttlecrab_probe_failures` and `throttlecrab_probe_latency_seconds`,
+//! so a transport that stops answering shows up even when no client traffic
+//! flows. Probe requests count towards the request metrics like any other.
+
+use crate::config::{HttpRoutes, ProbeConfig, TransportConfig};
+use crate::metrics::Metrics;
+use crate::transport::grpc::throttlecrab_proto::ThrottleRequest;
+use crate::transport::grpc::throttlecrab_proto::rate_limiter_client::RateLimiterClient;
+use crate::transport::redis::resp::{RespParser, RespSerializer, RespValue};
+use anyhow::{Result, anyhow, ensure};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{MissedTickBehavior, interval, timeout};
+
+/// How long a single probe may take before it counts as failed
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Burst and rate of the probe limit, high enough that probes are never denied
+const PROBE_LIMIT: i64 = 1000;
+
+/// How a target is probed
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Protocol {
+    /// `POST` to the throttle route
+    Http {
+        path: String,
+    },
+    Grpc,
+    /// `THROTTLE`, after `AUTH` if a password or API key is set
+    Redis {
+        password: Option<String>,
+    },
+}
+
+/// A transport endpoint to probe
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Target {
+    name: &'static str,
+    addr: String,
+    protocol: Protocol,
+    /// API key to authenticate with, if keys are required
+    api_key: Option<String>,
+}
+
+/// Loopback address reaching a transport bound to `host:port`
+///
+/// Wildcard binds are reached through the loopback interface of the same
+/// address family; anything else is connected to as configured.
+fn loopback(host: &str, port: u16) -> String {
+    match host.trim_matches(['[', ']']).parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) if ip.is_unspecified() => {
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port).to_string()
+        }
+        Ok(IpAddr::V6(ip)) if ip.is_unspecified() => {
+            SocketAddr::new(Ipv6Addr::LOCALHOST.into(), port).to_string()
+        }
+        Ok(ip) => SocketAddr::new(ip, port).to_string(),
+        Err(_) => format!("{host}:{port}"),
+    }
+}
+
+/// Every endpoint of the enabled transports, probed with `api_key` if given
+pub(crate) fn targets(transports: &TransportConfig, api_key: Option<&str>) -> Vec<Target> {
+    let throttle_path = |routes: &HttpRoutes| routes.path(&routes.throttle);
+    let api_key = api_key.map(str::to_string);
+    let mut targets = Vec::new();
+    // The probe speaks plain HTTP, so an HTTPS transport is left out
+    if let Some(http) = transports.http.as_ref().filter(|http| http.tls.is_none()) {
+        targets.push(Target {
+            name: "http",
+            addr: loopback(&http.host, http.port),
+            protocol: Protocol::Http {
+                path: throttle_path(&http.routes),
+            },
+            api_key: api_key.clone(),
+        });
+    }
+    if let Some(grpc) = &transports.grpc {
+        targets.push(Target {
+            name: "grpc",
+            addr: loopback(&grpc.host, grpc.port),
+            protocol: Protocol::Grpc,
+            api_key: api_key.clone(),
+        });
+    }
+    if let Some(redis) = &transports.redis {
+        targets.push(Target {
+            name: "redis",
+            addr: loopback(&redis.host, redis.port),
+            protocol: Protocol::Redis {
+                password: redis.password.clone(),
+            },
+            api_key: api_key.clone(),
+        });
+    }
+    if let Some(mux) = &transports.mux {
+        let addr = loopback(&mux.host, mux.port);
+        targets.extend([
+            Target {
+                name: "mux_http",
+                addr: addr.clone(),
+                protocol: Protocol::Http {
+                    path: throttle_path(&mux.routes),
+                },
+                api_key: api_key.clone(),
+            },
+            Target {
+                name: "mux_grpc",
+                addr: addr.clone(),
+                protocol: Protocol::Grpc,
+                api_key: api_key.clone(),
+            },
+            Target {
+                name: "mux_redis",
+                addr,
+                protocol: Protocol::Redis {
+                    password: mux.password.clone(),
+                },
+                api_key: api_key.clone(),
+            },
+        ]);
+    }
+    targets
+}
+
+/// Probe `targets` every `config.interval` seconds, forever
+pub(crate) async fn run(config: ProbeConfig, targets: Vec<Target>, metrics: Arc<Metrics>) {
+    let mut ticker = interval(Duration::from_secs(config.interval));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The first tick completes at once, possibly before the transports listen
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+        for target in &targets {
+            let started = Instant::now();
+            let result = match timeout(PROBE_TIMEOUT, target.probe(&config.key)).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow!("no response within {PROBE_TIMEOUT:?}")),
+            };
+            match result {
+                Ok(()) => metrics.record_probe(target.name, Some(started.elapsed())),
+                Err(e) => {
+                    tracing::warn!("Probe of {} at {} failed: {}", target.name, target.addr, e);
+                    metrics.record_probe(target.name, None);
+                }
+            }
+        }
+    }
+}
+
+impl Target {
+    /// Send one throttle request for `key` over a new connection
+    async fn probe(&self, key: &str) -> Result<()> {
+        let api_key = self.api_key.as_deref();
+        match &self.protocol {
+            Protocol::Http { path } => probe_http(&self.addr, path, key, api_key).await,
+            Protocol::Grpc => probe_grpc(&self.addr, key, api_key).await,
+            Protocol::Redis { password } => {
+                // Either authenticates the connection
+                probe_redis(&self.addr, password.as_deref().or(api_key), key).await
+            }
+        }
+    }
+}
+
+async fn probe_http(addr: &str, path: &str, key: &str, api_key: Option<&str>) -> Result<()> {
+    let body = serde_json::json!({
+        "key": key,
+        "max_burst": PROBE_LIMIT,
+        "count_per_period": PROBE_LIMIT,
+        "period": 1,
+    })
+    .to_string();
+    let authorization = api_key
+        .map(|api_key| format!("Authorization: Bearer {api_key}\r\n"))
+        .unwrap_or_default();
+    let request = format!(
+        "POST {path} HTTP/1.1\r\nHost: {addr}\r\nContent-Type: application/json\r\n\
+         {authorization}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+
+    let mut socket = TcpStream::connect(addr).await?;
+    socket.write_all(request.as_bytes()).await?;
+    let mut response = Vec::new();
+    socket.read_to_end(&mut response).await?;
+
+    let response = String::from_utf8_lossy(&response);
+    let status = response.lines().next().unwrap_or_default();
+    ensure!(status.starts_with("HTTP/1.1 200"), "got {status:?}");
+    ensure!(response.contains("\"allowed\""), "response has no decision");
+    Ok(())
+}
+
+async fn probe_grpc(addr: &str, key: &str, api_key: Option<&str>) -> Result<()> {
+    let mut client = RateLimiterClient::connect(format!("http://{addr}")).await?;
+    let mut request = tonic::Request::new(ThrottleRequest {
+        key: key.to_string(),
+        max_burst: PROBE_LIMIT as i32,
+        count_per_period: PROBE_LIMIT as i32,
+        period: 1,
+        quantity: 1,
+        retry_hints: false,
+        policy: String::new(),
+        algorithm: String::new(),
+        operation: String::new(),
+        namespace: String::new(),
+        windows: vec![],
+    });
+    if let Some(api_key) = api_key {
+        request
+            .metadata_mut()
+            .insert("authorization", format!("Bearer {api_key}").parse()?);
+    }
+    client.throttle(request).await?;
+    Ok(())
+}
+
+async fn probe_redis(addr: &str, password: Option<&str>, key: &str) -> Result<()> {
+    let mut connection = RedisConnection {
+        socket: TcpStream::connect(addr).await?,
+        parser: RespParser::new(),
+        buffer: Vec::new(),
+    };
+    if let Some(password) = password {
+        let reply = connection.command(&["AUTH", password]).await?;
+        ensure!(
+            matches!(reply, RespValue::SimpleString(_)),
+            "AUTH got {reply:?}"
+        );
+    }
+    let limit = PROBE_LIMIT.to_string();
+    let reply = connection
+        .command(&["THROTTLE", key, &limit, &limit, "1"])
+        .await?;
+    ensure!(
+        matches!(&reply, RespValue::Array(values) if values.len() >= 5),
+        "THROTTLE got {reply:?}"
+    );
+    Ok(())
+}
+
+/// Minimal RESP client for the Redis probes
+struct RedisConnection {
+    socket: TcpStream,
+    parser: RespParser,
+    buffer: Vec<u8>,
+}
+
+impl RedisConnection {
+    /// Send a command and wait for its reply
+    async fn command(&mut self, args: &[&str]) -> Result<RespValue> {
+        let command = RespValue::Array(
+            args.iter()
+                .map(|arg| RespValue::BulkString(Some(arg.to_string())))
+                .collect(),
+        );
+        self.socket
+            .write_all(&RespSerializer::serialize(&command))
+            .await?;
+
+        let mut chunk = [0; 512];
+        loop {
+            if let Some((reply, consumed)) = self.parser.parse(&self.buffer)? {
+                self.buffer.drain(..consumed);
+                return Ok(reply);
+            }
+            let n = self.socket.read(&mut chunk).await?;
+            ensure!(n > 0, "connection closed");
+            self.buffer.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor::RateLimiterActor;
+    use crate::auth::ApiKeys;
+    use crate::config::{GrpcConfig, HttpConfig, HttpKeepAlive, MuxConfig, RedisConfig};
+    use crate::transport::{
+        Transport, grpc::GrpcTransport, http::HttpTransport, redis::RedisTransport,
+    };
+    use tokio::time::sleep;
+
+    #[test]
+    fn test_probe_targets() {
+        let transports = TransportConfig {
+            http: Some(HttpConfig {
+                host: "0.0.0.0".to_string(),
+                port: 8080,
+                routes: HttpRoutes {
+                    base_path: "/ratelimit".to_string(),
+                    ..HttpRoutes::default()
+                },
+                tls: None,
+                use_429: false,
+                keep_alive: HttpKeepAlive::default(),
+                compression: false,
+            }),
+            grpc: Some(GrpcConfig {
+                host: "::".to_string(),
+                port: 8070,
+            }),
+            redis: Some(RedisConfig {
+                host: "10.0.0.5".to_string(),
+                port: 6379,
+                password: Some("secret".to_string()),
+                max_commands_per_second: 0,
+                max_connections: 0,
+                max_connections_per_ip: 0,
+            }),
+            mux: Some(MuxConfig {
+                host: "localhost".to_string(),
+                port: 8000,
+                routes: HttpRoutes::default(),
+                password: None,
+                max_commands_per_second: 0,
+                use_429: false,
+            }),
+            udp: None,
+        };
+
+        let targets = targets(&transports, None);
+        let names: Vec<_> = targets.iter().map(|target| target.name).collect();
+        assert_eq!(
+            names,
+            ["http", "grpc", "redis", "mux_http", "mux_grpc", "mux_redis"]
+        );
+        assert_eq!(targets[0].addr, "127.0.0.1:8080");
+        assert_eq!(
+            targets[0].protocol,
+            Protocol::Http {
+                path: "/ratelimit/throttle".to_string()
+            }
+        );
+        assert_eq!(targets[1].addr, "[::1]:8070");
+        assert_eq!(targets[2].addr, "10.0.0.5:6379");
+        assert_eq!(targets[3].addr, "localhost:8000");
+    }
+
+    #[tokio::test]
+    async fn test_probe_transports() {
+        let metrics = Arc::new(Metrics::new());
+        let store = throttlecrab::PeriodicStore::builder()
+            .capacity(1000)
+            .cleanup_interval(Duration::from_secs(60))
+            .build();
+        // HTTP and gRPC probes authenticate with the API key, Redis with the password
+        let api_keys = ApiKeys::new([("probe".to_string(), "k3y".to_string())]).unwrap();
+        let limiter = RateLimiterActor::spawn_periodic(1000, store, Arc::clone(&metrics))
+            .with_api_keys(Arc::new(api_keys));
+
+        let http = HttpTransport::new("127.0.0.1", 9184, Arc::clone(&metrics));
+        let grpc = GrpcTransport::new("127.0.0.1", 9185, Arc::clone(&metrics));
+        let redis = RedisTransport::new("127.0.0.1", 9186, Arc::clone(&metrics))
+            .unwrap()
+            .with_password(Some("secret".to_string()));
+        tokio::spawn(http.start(limiter.clone()));
+        tokio::spawn(grpc.start(limiter.clone()));
+        tokio::spawn(redis.start(limiter));
+        sleep(Duration::from_millis(100)).await;
+
+        let transports = TransportConfig {
+            http: Some(HttpConfig {
+                host: "127.0.0.1".to_string(),
+                port: 9184,
+                routes: HttpRoutes::default(),
+                tls: None,
+                use_429: false,
+                keep_alive: HttpKeepAlive::default(),
+                compression: false,
+            }),
+            grpc: Some(GrpcConfig {
+                host: "127.0.0.1".to_string(),
+                port: 9185,
+            }),
+            redis: Some(RedisConfig {
+                host: "127.0.0.1".to_string(),
+                port: 9186,
+                password: Some("secret".to_string()),
+                max_commands_per_second: 0,
+                max_connections: 0,
+                max_connections_per_ip: 0,
+            }),
+            mux: None,
+            udp: None,
+        };
+        for target in targets(&transports, Some("k3y")) {
+            target.probe("__throttlecrab_probe").await.unwrap();
+        }
+        assert_eq!(
+            metrics
+                .total_requests
+                .load(std::sync::atomic::Ordering::Relaxed),
+            3
+        );
+
+        // A wrong password fails the probe
+        let mut target = targets(&transports, Some("k3y")).pop().unwrap();
+        target.protocol = Protocol::Redis {
+            password: Some("wrong".to_string()),
+        };
+        assert!(target.probe("__throttlecrab_probe").await.is_err());
+    }
+}
@@ -0,0 +1,293 @@
+//! Interactive REPL for exploring rate limits
+//!
+//! `throttlecrab-server repl` runs a rate limiter in-process, without any
+//! network transport, and reads commands from stdin. Time comes from a
+//! virtual clock that only moves when told to, so GCRA behavior can be
+//! explored step by step:
+//!
+//! ```text
+//! > throttle user:1 3 10 60
+//! allowed   limit=3 remaining=2 reset_after=12s retry_after=0s
+//! > peek user:1
+//! allowed   limit=3 remaining=1 reset_after=18s retry_after=0s
+//! > advance 30s
+//! t=+30s
+//! ```
+//!
+//! The store type and its parameters come from the usual store flags, e.g.
+//! `throttlecrab-server --store adaptive repl`.
+
+use crate::actor::StoreType;
+use crate::config::{self, StoreConfig};
+use crate::store::build_store;
+use anyhow::{Context, Result, anyhow, bail};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::time::{Duration, SystemTime};
+use throttlecrab::{Clock, MockClock, RateLimitResult};
+
+const HELP: &str = "\
+Commands:
+  throttle KEY BURST COUNT PERIOD [QUANTITY]  Rate limit a request
+  peek KEY [BURST COUNT PERIOD [QUANTITY]]    Show the outcome of a request without consuming it
+  advance DURATION                            Move the clock forward, e.g. 500ms, 30s, 5m, 1h
+  now                                         Show the time elapsed on the clock
+  help                                        Show this message
+  quit                                        Exit
+
+peek reuses the last limits given for KEY when they are omitted.";
+
+/// Limits of a request, remembered per key for `peek`
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    max_burst: i64,
+    count_per_period: i64,
+    period: i64,
+    quantity: i64,
+}
+
+/// REPL state: the store, its virtual clock and the limits last used per key
+pub struct Repl {
+    store: StoreType,
+    clock: MockClock,
+    started: SystemTime,
+    limits: HashMap<String, Limits>,
+}
+
+impl Repl {
+    /// Create a REPL with a store built from `config`
+    ///
+    /// The virtual clock starts at the current wall clock time.
+    pub fn new(config: &StoreConfig) -> Self {
+        let started = SystemTime::now();
+        Repl {
+            store: build_store(config.store_type, config),
+            clock: MockClock::at(started),
+            started,
+            limits: HashMap::new(),
+        }
+    }
+
+    /// Execute one line of input
+    ///
+    /// Returns the text to print, or `None` for blank lines and `quit`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for unknown commands, malformed arguments and
+    /// invalid rate limit parameters.
+    pub fn execute(&mut self, line: &str) -> Result<Option<String>> {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = args.split_first() else {
+            return Ok(None);
+        };
+
+        let output = match command.to_lowercase().as_str() {
+            "throttle" => {
+                let [key, rest @ ..] = args else {
+                    bail!("usage: throttle KEY BURST COUNT PERIOD [QUANTITY]");
+                };
+                let limits = parse_limits(rest)?
+                    .ok_or_else(|| anyhow!("usage: throttle KEY BURST COUNT PERIOD [QUANTITY]"))?;
+                let (allowed, result) = self.store.rate_limit(
+                    key,
+                    limits.max_burst,
+                    limits.count_per_period,
+                    limits.period,
+                    limits.quantity,
+                    self.clock.now(),
+                )?;
+                self.limits.insert(key.to_string(), limits);
+                format_decision(allowed, &result)
+            }
+            "peek" => {
+                let [key, rest @ ..] = args else {
+                    bail!("usage: peek KEY [BURST COUNT PERIOD [QUANTITY]]");
+                };
+                let limits = match parse_limits(rest)? {
+                    Some(limits) => limits,
+                    None => *self.limits.get(*key).ok_or_else(|| {
+                        anyhow!("no limits known for {key}, pass them explicitly")
+                    })?,
+                };
+                let (allowed, result) = self.store.peek(
+                    key,
+                    limits.max_burst,
+                    limits.count_per_period,
+                    limits.period,
+                    limits.quantity,
+                    self.clock.now(),
+                )?;
+                format_decision(allowed, &result)
+            }
+            "advance" => {
+                let [duration] = args else {
+                    bail!("usage: advance DURATION");
+                };
+                self.clock.advance(parse_duration(duration)?);
+                self.elapsed()
+            }
+            "now" => self.elapsed(),
+            "help" => HELP.to_string(),
+            "quit" | "exit" => return Ok(None),
+            _ => bail!("unknown command: {command} (try 'help')"),
+        };
+
+        Ok(Some(output))
+    }
+
+    fn elapsed(&self) -> String {
+        let elapsed = self
+            .clock
+            .now()
+            .duration_since(self.started)
+            .unwrap_or_default();
+        format!("t=+{}", format_duration(elapsed))
+    }
+}
+
+/// Run the REPL on stdin and stdout until `quit` or end of input
+///
+/// # Errors
+///
+/// Returns an error if the store is Redis, or if stdin or stdout fail.
+pub fn run(config: &StoreConfig) -> Result<()> {
+    if config.store_type == config::StoreType::Redis {
+        bail!("The REPL needs an in-memory store, --store redis is not supported");
+    }
+    let mut repl = Repl::new(config);
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+
+    println!(
+        "throttlecrab {} REPL with a {:?} store. Type 'help' for commands.",
+        env!("CARGO_PKG_VERSION"),
+        config.store_type
+    );
+
+    let mut line = String::new();
+    loop {
+        print!("> ");
+        stdout.flush()?;
+
+        line.clear();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        let command = line.trim();
+        match repl.execute(command) {
+            Ok(Some(output)) => println!("{output}"),
+            Ok(None) if matches!(command, "quit" | "exit") => return Ok(()),
+            Ok(None) => {}
+            Err(e) => println!("error: {e}"),
+        }
+    }
+}
+
+/// Parse `BURST COUNT PERIOD [QUANTITY]`, or nothing at all
+fn parse_limits(args: &[&str]) -> Result<Option<Limits>> {
+    let parse = |name: &str, value: &str| -> Result<i64> {
+        value
+            .parse()
+            .with_context(|| format!("invalid {name}: {value}"))
+    };
+
+    match args {
+        [] => Ok(None),
+        [burst, count, period] | [burst, count, period, _] => Ok(Some(Limits {
+            max_burst: parse("burst", burst)?,
+            count_per_period: parse("count", count)?,
+            period: parse("period", period)?,
+            quantity: match args.get(3) {
+                Some(quantity) => parse("quantity", quantity)?,
+                None => 1,
+            },
+        })),
+        _ => bail!("expected BURST COUNT PERIOD [QUANTITY]"),
+    }
+}
+
+/// Parse a duration such as `500ms`, `30s`, `5m` or `1h`
+fn parse_duration(value: &str) -> Result<Duration> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| anyhow!("missing unit in {value}, e.g. 30s"))?;
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .with_context(|| format!("invalid duration: {value}"))?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(amount)),
+        "s" => Ok(Duration::from_secs(amount)),
+        "m" => Ok(Duration::from_secs(amount * 60)),
+        "h" => Ok(Duration::from_secs(amount * 3600)),
+        _ => bail!("unknown unit in {value}, expected ms, s, m or h"),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration.subsec_millis() == 0 {
+        format!("{}s", duration.as_secs())
+    } else {
+        format!("{:.3}s", duration.as_secs_f64())
+    }
+}
+
+fn format_decision(allowed: bool, result: &RateLimitResult) -> String {
+    format!(
+        "{:<9} limit={} remaining={} reset_after={} retry_after={}",
+        if allowed { "allowed" } else { "denied" },
+        result.limit,
+        result.remaining,
+        format_duration(result.reset_after),
+        format_duration(result.retry_after)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(repl: &mut Repl, line: &str) -> String {
+        repl.execute(line).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_repl_session() {
+        let mut repl = Repl::new(&StoreConfig::default());
+
+        // 1 request per 10 seconds with a burst of 2
+        assert!(run(&mut repl, "throttle user:1 2 6 60").starts_with("allowed "));
+        assert_eq!(
+            run(&mut repl, "peek user:1"),
+            "allowed   limit=2 remaining=0 reset_after=20s retry_after=0s"
+        );
+        assert!(run(&mut repl, "throttle user:1 2 6 60").starts_with("allowed "));
+        assert_eq!(
+            run(&mut repl, "throttle user:1 2 6 60"),
+            "denied    limit=2 remaining=0 reset_after=20s retry_after=10s"
+        );
+
+        assert_eq!(run(&mut repl, "advance 10s"), "t=+10s");
+        assert!(run(&mut repl, "peek user:1").starts_with("allowed "));
+        assert_eq!(run(&mut repl, "advance 500ms"), "t=+10.500s");
+        assert_eq!(run(&mut repl, "now"), "t=+10.500s");
+    }
+
+    #[test]
+    fn test_repl_errors() {
+        let mut repl = Repl::new(&StoreConfig::default());
+
+        assert!(repl.execute("").unwrap().is_none());
+        assert!(repl.execute("quit").unwrap().is_none());
+        assert!(repl.execute("bogus").is_err());
+        assert!(repl.execute("throttle user:1").is_err());
+        assert!(repl.execute("throttle user:1 2 x 60").is_err());
+        assert!(repl.execute("throttle user:1 0 6 60").is_err());
+        assert!(repl.execute("peek unknown").is_err());
+        assert!(repl.execute("advance 30").is_err());
+        assert!(repl.execute("advance 30d").is_err());
+    }
+}
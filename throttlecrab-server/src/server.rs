@@ -0,0 +1,1549 @@
+//! Embeddable server API
+//!
+//! [`Server`] runs the same multi-protocol server as the `throttlecrab-server`
+//! binary inside your own application. Configure transports, store and
+//! metrics with [`ServerBuilder`], then call [`Server::serve`] with a future
+//! that resolves when the server should shut down.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use throttlecrab_server::Server;
+//! use throttlecrab_server::config::{StoreConfig, StoreType};
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let server = Server::builder()
+//!     .http("127.0.0.1", 8080)
+//!     .redis("127.0.0.1", 6379)
+//!     .store(StoreConfig {
+//!         store_type: StoreType::Adaptive,
+//!         ..Default::default()
+//!     })
+//!     .build()?;
+//!
+//! server
+//!     .serve(async {
+//!         tokio::signal::ctrl_c().await.ok();
+//!     })
+//!     .await
+//! # }
+//! ```
+
+use crate::auth::ApiKeys;
+use crate::budget::ApiKeyBudgets;
+use crate::cluster;
+use crate::config::{
+    AdminListenerConfig, ApiKeyBudget, ApiKeysConfig, ClusterConfig, Config, DenialAlertsConfig,
+    EventsConfig, GrpcConfig, HttpConfig, HttpKeepAlive, HttpRoutes, LoadSheddingConfig, LogFormat,
+    MetricsListenerConfig, MetricsPushConfig, MuxConfig, ProbeConfig, RedisConfig, StoreConfig,
+    TlsConfig, TopKeysConfig, TransportConfig, UdpConfig, ValidationConfig,
+};
+use crate::denials::{self, DenialAlerts};
+use crate::events;
+use crate::hooks::DecisionHook;
+use crate::metrics::{self, Metrics};
+use crate::metrics_push;
+use crate::overrides::KeyOverrides;
+use crate::policy::Policies;
+use crate::probe;
+use crate::shed::LoadShedder;
+use crate::snapshot;
+use crate::status::StartupStatus;
+use crate::store;
+use crate::trace::TraceBuffer;
+use crate::transport::{
+    Transport, grpc::GrpcTransport, http::HttpTransport, mux::MuxTransport, redis::RedisTransport,
+    tls, udp::UdpTransport,
+};
+use crate::validation::RequestLimits;
+use anyhow::Result;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+/// A configured rate limiting server, ready to [`serve`](Server::serve)
+pub struct Server {
+    config: Config,
+    metrics: Arc<Metrics>,
+    hooks: Vec<Arc<dyn DecisionHook>>,
+}
+
+impl Server {
+    /// Create a builder with no transports and the default store
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::new()
+    }
+
+    /// Create a server from a complete configuration
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration is invalid, e.g. no transport
+    /// is enabled.
+    pub fn from_config(config: Config) -> Result<Self> {
+        config.validate()?;
+        let mut metrics = Metrics::builder().max_denied_keys(config.max_denied_keys as usize);
+        if let Some(top_keys) = &config.top_keys {
+            metrics = metrics.top_keys(top_keys.count, top_keys.sample_rate);
+        }
+        let metrics = Arc::new(metrics.build());
+        Ok(Server {
+            config,
+            metrics,
+            hooks: Vec::new(),
+        })
+    }
+
+    /// Metrics shared by every transport of this server
+    pub fn metrics(&self) -> Arc<Metrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Run all configured transports until `shutdown` resolves
+    ///
+    /// Once the transports are started, a [`StartupStatus`] line is printed
+    /// to stdout and written to the status file, if one is configured.
+    ///
+    /// Returns `Ok(())` after a shutdown or when a transport stops on its
+    /// own, and the transport's error if one fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store, write-ahead log, snapshot, policy file,
+    /// event exporter, metrics push or status file cannot be set up, or if a
+    /// transport fails.
+    pub async fn serve<F>(self, shutdown: F) -> Result<()>
+    where
+        F: Future<Output = ()> + Send,
+    {
+        let Server {
+            config,
+            metrics,
+            mut hooks,
+        } = self;
+
+        // Create the rate limiter actor with the configured store
+        let mut limiter =
+            store::create_rate_limiter(&config.store, config.buffer_size, Arc::clone(&metrics))
+                .await?;
+
+        // Export decision events if a sink is configured
+        if let Some(events_config) = &config.events {
+            tracing::info!(
+                "Exporting decision events to {:?} topic {}",
+                events_config.sink,
+                events_config.topic
+            );
+            let publisher = events::start(events_config, Arc::clone(&metrics)).await?;
+            limiter = limiter.with_events(publisher);
+        }
+
+        // Count denials per key and alert on those crossing the threshold
+        if let Some(alerts_config) = &config.denial_alerts {
+            tracing::info!(
+                "Alerting on keys denied {} times within {}s",
+                alerts_config.threshold,
+                alerts_config.window
+            );
+            let alerts = Arc::new(DenialAlerts::new(alerts_config));
+            if let Some(url) = &alerts_config.webhook {
+                denials::start_webhook(url, &alerts)?;
+            }
+            hooks.push(Arc::clone(&alerts) as Arc<dyn DecisionHook>);
+            limiter = limiter.with_denial_alerts(alerts);
+        }
+
+        if !hooks.is_empty() {
+            limiter = limiter.with_hooks(hooks);
+        }
+
+        if let Some(path) = &config.policies {
+            let policies = match &config.policies_table {
+                Some(table) => Policies::load_table(path, table)?,
+                None => Policies::load(path)?,
+            };
+            tracing::info!(
+                "Loaded {} rate limit policies from {}",
+                policies.len(),
+                path.display()
+            );
+            limiter = limiter.with_policies(Arc::new(policies));
+        }
+
+        // Overrides are always available to the admin API for incident response
+        let overrides = match &config.overrides_file {
+            Some(path) => {
+                let overrides = KeyOverrides::load(path)?;
+                let lists = overrides.lists();
+                tracing::info!(
+                    "Loaded {} denied and {} allowed key patterns from {}",
+                    lists.deny.len(),
+                    lists.allow.len(),
+                    path.display()
+                );
+                overrides
+            }
+            None => KeyOverrides::default(),
+        };
+        limiter = limiter.with_key_overrides(Arc::new(overrides));
+
+        if let Some(api_keys_config) = &config.api_keys {
+            let api_keys = ApiKeys::from_config(api_keys_config)?;
+            tracing::info!("Requiring one of {} API keys", api_keys.len());
+            if !api_keys_config.budgets.is_empty() {
+                let budgets = ApiKeyBudgets::new(&api_keys_config.budgets, &api_keys)?;
+                tracing::info!(
+                    "Limiting API keys to {} budgets",
+                    api_keys_config.budgets.len()
+                );
+                limiter = limiter.with_api_key_budgets(Arc::new(budgets));
+            }
+            limiter = limiter.with_api_keys(Arc::new(api_keys));
+        }
+
+        if config.trace_buffer_size > 0 {
+            let trace = TraceBuffer::new(config.trace_buffer_size);
+            limiter = limiter.with_trace(Arc::new(trace));
+        }
+
+        if let Some(validation) = &config.validation {
+            limiter = limiter.with_limits(Arc::new(RequestLimits::new(validation)?));
+        }
+
+        if let Some(shedding) = &config.load_shedding {
+            tracing::info!(
+                "Shedding throttle checks beyond {} per second ({:?})",
+                shedding.max_rps,
+                shedding.decision
+            );
+            limiter = limiter.with_load_shedding(Arc::new(LoadShedder::new(shedding)));
+        }
+
+        // Push metrics if the collector cannot scrape
+        if let Some(push_config) = &config.metrics_push {
+            tracing::info!(
+                "Pushing metrics to {} every {}s",
+                push_config.url,
+                push_config.interval
+            );
+            metrics_push::start(push_config, Arc::clone(&metrics))?;
+        }
+
+        // Create a set to manage multiple transport tasks
+        let mut transport_tasks = JoinSet::new();
+
+        // Start HTTP transport if enabled
+        if let Some(http_config) = &config.transports.http {
+            let limiter_handle = limiter.clone();
+            let host = http_config.host.clone();
+            let port = http_config.port;
+            let routes = http_config.routes.clone();
+            let use_429 = http_config.use_429;
+            let keep_alive = http_config.keep_alive;
+            let compression = http_config.compression;
+            let admin_routes = config.admin_listener.is_none();
+            // Read the certificate now so a bad file fails startup
+            let tls = http_config
+                .tls
+                .as_ref()
+                .map(tls::server_config)
+                .transpose()?;
+            let metrics_clone = Arc::clone(&metrics);
+
+            transport_tasks.spawn(async move {
+                let mut transport = HttpTransport::new(&host, port, metrics_clone)
+                    .with_routes(routes)
+                    .with_429(use_429)
+                    .with_keep_alive(keep_alive)
+                    .with_compression(compression)
+                    .with_admin_routes(admin_routes);
+                if let Some(tls) = tls {
+                    transport = transport.with_tls(tls);
+                }
+                transport.start(limiter_handle).await
+            });
+        }
+
+        // Start gRPC transport if enabled
+        if let Some(grpc_config) = &config.transports.grpc {
+            let limiter_handle = limiter.clone();
+            let host = grpc_config.host.clone();
+            let port = grpc_config.port;
+            let metrics_clone = Arc::clone(&metrics);
+
+            transport_tasks.spawn(async move {
+                let transport = GrpcTransport::new(&host, port, metrics_clone);
+                transport.start(limiter_handle).await
+            });
+        }
+
+        // Start Redis transport if enabled
+        if let Some(redis_config) = &config.transports.redis {
+            let limiter_handle = limiter.clone();
+            let host = redis_config.host.clone();
+            let port = redis_config.port;
+            let password = redis_config.password.clone();
+            let max_commands_per_second = redis_config.max_commands_per_second;
+            let (max_connections, per_ip) = (
+                redis_config.max_connections,
+                redis_config.max_connections_per_ip,
+            );
+            let metrics_clone = Arc::clone(&metrics);
+
+            transport_tasks.spawn(async move {
+                let transport = RedisTransport::new(&host, port, metrics_clone)?
+                    .with_password(password)
+                    .with_max_commands_per_second(max_commands_per_second)
+                    .with_max_connections(max_connections, per_ip);
+                transport.start(limiter_handle).await
+            });
+        }
+
+        // Start the multiplexed transport if enabled
+        if let Some(mux_config) = &config.transports.mux {
+            let limiter_handle = limiter.clone();
+            let host = mux_config.host.clone();
+            let port = mux_config.port;
+            let routes = mux_config.routes.clone();
+            let use_429 = mux_config.use_429;
+            let password = mux_config.password.clone();
+            let max_commands_per_second = mux_config.max_commands_per_second;
+            let admin_routes = config.admin_listener.is_none();
+            let metrics_clone = Arc::clone(&metrics);
+
+            transport_tasks.spawn(async move {
+                let transport = MuxTransport::new(&host, port, metrics_clone)?
+                    .with_routes(routes)
+                    .with_429(use_429)
+                    .with_admin_routes(admin_routes)
+                    .with_password(password)
+                    .with_max_commands_per_second(max_commands_per_second);
+                transport.start(limiter_handle).await
+            });
+        }
+
+        // Start UDP transport if enabled
+        if let Some(udp_config) = &config.transports.udp {
+            let limiter_handle = limiter.clone();
+            let host = udp_config.host.clone();
+            let port = udp_config.port;
+            let metrics_clone = Arc::clone(&metrics);
+
+            transport_tasks.spawn(async move {
+                let transport = UdpTransport::new(&host, port, metrics_clone)?;
+                transport.start(limiter_handle).await
+            });
+        }
+
+        // Serve metrics on their own port; a bind failure stops the server like a transport's
+        if let Some(listener_config) = &config.metrics_listener {
+            let host = listener_config.host.clone();
+            let port = listener_config.port;
+            let metrics_clone = Arc::clone(&metrics);
+
+            transport_tasks.spawn(async move {
+                crate::transport::http::serve_metrics(&host, port, metrics_clone).await
+            });
+        }
+
+        // Likewise the admin endpoints, for `--admin-port`
+        if let Some(listener_config) = &config.admin_listener {
+            let host = listener_config.host.clone();
+            let port = listener_config.port;
+            let token = listener_config.token.as_deref().map(Arc::from);
+            let limiter = limiter.clone();
+            let metrics_clone = Arc::clone(&metrics);
+
+            transport_tasks.spawn(async move {
+                crate::transport::http::serve_admin(&host, port, token, limiter, metrics_clone)
+                    .await
+            });
+        }
+
+        // Listen for cluster peers like a transport, and push them changes in the background
+        let node_id = cluster::node_id();
+        if let Some(cluster_config) = &config.cluster {
+            let cluster_config = cluster_config.clone();
+            let limiter = limiter.clone();
+            let metrics_clone = Arc::clone(&metrics);
+            transport_tasks.spawn(async move {
+                cluster::serve(&cluster_config, node_id, limiter, metrics_clone).await
+            });
+        }
+
+        // Probe the transports through their own code paths; aborted on return
+        let mut background = JoinSet::new();
+        if let Some(probe_config) = &config.probe {
+            let api_key = limiter.api_keys().and_then(|keys| keys.first_secret());
+            let targets = probe::targets(&config.transports, api_key);
+            tracing::info!(
+                "Probing {} transport endpoint(s) every {}s",
+                targets.len(),
+                probe_config.interval
+            );
+            background.spawn(probe::run(
+                probe_config.clone(),
+                targets,
+                Arc::clone(&metrics),
+            ));
+        }
+
+        if config.latency_log_interval > 0 {
+            tracing::info!(
+                "Logging request latency every {}s",
+                config.latency_log_interval
+            );
+            background.spawn(metrics::log_latency(
+                Arc::clone(&metrics),
+                Duration::from_secs(config.latency_log_interval),
+            ));
+        }
+
+        if config.store.stats_log_interval > 0 {
+            tracing::info!(
+                "Logging store statistics every {}s",
+                config.store.stats_log_interval
+            );
+            background.spawn(store::log_stats(
+                limiter.clone(),
+                Duration::from_secs(config.store.stats_log_interval),
+            ));
+        }
+
+        if let Some(cluster_config) = &config.cluster {
+            tracing::info!(
+                "Syncing with cluster peers every {}ms",
+                cluster_config.sync_interval_ms
+            );
+            background.spawn(cluster::replicate(
+                cluster_config.clone(),
+                node_id,
+                limiter.clone(),
+                Arc::clone(&metrics),
+            ));
+        }
+
+        // Reload the policy file on SIGHUP, like POST /admin/reload
+        #[cfg(unix)]
+        if limiter.policies().is_some() {
+            use tokio::signal::unix::{SignalKind, signal};
+            let mut hangup = signal(SignalKind::hangup())?;
+            let limiter = limiter.clone();
+            background.spawn(async move {
+                while hangup.recv().await.is_some() {
+                    // Failures are logged and counted by the handle
+                    let _ = limiter.reload_policies();
+                }
+            });
+        }
+
+        // Snapshot the store periodically; stopped with a final snapshot on shutdown
+        let snapshots = config.store.snapshot.clone().map(|snapshot_config| {
+            tracing::info!(
+                "Writing snapshots to {} every {:?}",
+                snapshot_config.path.display(),
+                snapshot_config.interval
+            );
+            let (stop_tx, stop_rx) = tokio::sync::oneshot::channel();
+            let task = tokio::spawn(snapshot::run(
+                snapshot_config,
+                limiter.clone(),
+                Arc::clone(&metrics),
+                stop_rx,
+            ));
+            (stop_tx, task)
+        });
+
+        // Announce the running configuration in a machine-readable form
+        let status = StartupStatus::new(&config);
+        println!("{}", status.to_json());
+        if let Some(path) = &config.status_file {
+            status.write(path)?;
+        }
+
+        // Wait for shutdown or transport task completion
+        tokio::select! {
+            _ = shutdown => {
+                tracing::info!("Shutdown signal received, stopping all transports...");
+                transport_tasks.abort_all();
+
+                if let Some((stop_tx, task)) = snapshots {
+                    let _ = stop_tx.send(());
+                    let _ = task.await;
+                }
+
+                // Give tasks a moment to clean up
+                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+                if let Some(path) = &config.status_file {
+                    let _ = std::fs::remove_file(path);
+                }
+
+                tracing::info!("ThrottleCrab server shutdown complete");
+            }
+            result = transport_tasks.join_next() => {
+                if let Some(result) = result {
+                    match result {
+                        Ok(Ok(())) => {
+                            tracing::info!("Transport task completed successfully");
+                        }
+                        Ok(Err(e)) => {
+                            tracing::error!("Transport task failed: {}", e);
+                            return Err(e);
+                        }
+                        Err(e) => {
+                            tracing::error!("Transport task panicked: {}", e);
+                            return Err(anyhow::anyhow!("Transport task panicked"));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Builder for [`Server`]
+///
+/// Starts with no transports enabled; enable at least one before calling
+/// [`build`](ServerBuilder::build). Everything else defaults to the same
+/// values as the command line.
+pub struct ServerBuilder {
+    transports: TransportConfig,
+    store: StoreConfig,
+    buffer_size: usize,
+    max_denied_keys: u32,
+    top_keys: Option<TopKeysConfig>,
+    trace_buffer_size: usize,
+    latency_log_interval: u64,
+    load_shedding: Option<LoadSheddingConfig>,
+    validation: Option<ValidationConfig>,
+    events: Option<EventsConfig>,
+    denial_alerts: Option<DenialAlertsConfig>,
+    metrics_push: Option<MetricsPushConfig>,
+    probe: Option<ProbeConfig>,
+    metrics_listener: Option<MetricsListenerConfig>,
+    admin_listener: Option<AdminListenerConfig>,
+    admin_token: Option<String>,
+    cluster: Option<ClusterConfig>,
+    policies: Option<PathBuf>,
+    overrides_file: Option<PathBuf>,
+    api_keys: Option<ApiKeysConfig>,
+    metrics: Option<Arc<Metrics>>,
+    status_file: Option<PathBuf>,
+    hooks: Vec<Arc<dyn DecisionHook>>,
+    redis_password: Option<String>,
+    redis_max_commands_per_second: u32,
+    redis_max_connections: usize,
+    redis_max_connections_per_ip: usize,
+    http_routes: HttpRoutes,
+    http_tls: Option<TlsConfig>,
+    http_use_429: bool,
+    http_keep_alive: HttpKeepAlive,
+    http_compression: bool,
+}
+
+impl ServerBuilder {
+    /// Create a new builder with default settings
+    pub fn new() -> Self {
+        Self {
+            transports: TransportConfig {
+                http: None,
+                grpc: None,
+                redis: None,
+                mux: None,
+                udp: None,
+            },
+            store: StoreConfig::default(),
+            buffer_size: 100_000,
+            max_denied_keys: 100,
+            top_keys: None,
+            trace_buffer_size: 0,
+            latency_log_interval: 0,
+            load_shedding: None,
+            validation: None,
+            events: None,
+            denial_alerts: None,
+            metrics_push: None,
+            probe: None,
+            metrics_listener: None,
+            admin_listener: None,
+            admin_token: None,
+            cluster: None,
+            policies: None,
+            overrides_file: None,
+            api_keys: None,
+            metrics: None,
+            status_file: None,
+            hooks: Vec::new(),
+            redis_password: None,
+            redis_max_commands_per_second: 0,
+            redis_max_connections: 0,
+            redis_max_connections_per_ip: 0,
+            http_routes: HttpRoutes::default(),
+            http_tls: None,
+            http_use_429: false,
+            http_keep_alive: HttpKeepAlive::default(),
+            http_compression: false,
+        }
+    }
+
+    /// Enable the HTTP transport
+    pub fn http(mut self, host: impl Into<String>, port: u16) -> Self {
+        self.transports.http = Some(HttpConfig {
+            host: host.into(),
+            port,
+            routes: HttpRoutes::default(),
+            tls: None,
+            use_429: false,
+            keep_alive: HttpKeepAlive::default(),
+            compression: false,
+        });
+        self
+    }
+
+    /// Enable the gRPC transport
+    pub fn grpc(mut self, host: impl Into<String>, port: u16) -> Self {
+        self.transports.grpc = Some(GrpcConfig {
+            host: host.into(),
+            port,
+        });
+        self
+    }
+
+    /// Enable the Redis protocol transport
+    pub fn redis(mut self, host: impl Into<String>, port: u16) -> Self {
+        self.transports.redis = Some(RedisConfig {
+            host: host.into(),
+            port,
+            password: None,
+            max_commands_per_second: 0,
+            max_connections: 0,
+            max_connections_per_ip: 0,
+        });
+        self
+    }
+
+    /// Enable the multiplexed transport, serving HTTP, gRPC and Redis on one port
+    ///
+    /// The HTTP routes and Redis password configured for the dedicated
+    /// transports apply to it as well.
+    pub fn mux(mut self, host: impl Into<String>, port: u16) -> Self {
+        self.transports.mux = Some(MuxConfig {
+            host: host.into(),
+            port,
+            routes: HttpRoutes::default(),
+            password: None,
+            max_commands_per_second: 0,
+            use_429: false,
+        });
+        self
+    }
+
+    /// Enable the UDP transport
+    pub fn udp(mut self, host: impl Into<String>, port: u16) -> Self {
+        self.transports.udp = Some(UdpConfig {
+            host: host.into(),
+            port,
+        });
+        self
+    }
+
+    /// Require Redis clients to `AUTH` with `password` before other commands
+    ///
+    /// Only takes effect when the Redis or multiplexed transport is enabled.
+    pub fn redis_password(mut self, password: impl Into<String>) -> Self {
+        self.redis_password = Some(password.into());
+        self
+    }
+
+    /// Limit each Redis connection to `count` commands per second
+    ///
+    /// Connections that reach the cap have their reads paused until the
+    /// next second, so one pipelining client cannot crowd out the others.
+    /// 0, the default, disables the cap. Applies to the Redis and
+    /// multiplexed transports.
+    pub fn redis_max_commands_per_second(mut self, count: u32) -> Self {
+        self.redis_max_commands_per_second = count;
+        self
+    }
+
+    /// Hold at most `max` Redis connections at once, and at most `per_ip`
+    /// from one client IP
+    ///
+    /// Connections over either cap are refused as they are accepted. 0, the
+    /// default, disables a cap. Applies to the Redis transport only.
+    pub fn redis_max_connections(mut self, max: usize, per_ip: usize) -> Self {
+        self.redis_max_connections = max;
+        self.redis_max_connections_per_ip = per_ip;
+        self
+    }
+
+    /// Mount the HTTP endpoints at `routes` instead of the default paths
+    ///
+    /// Only takes effect when the HTTP or multiplexed transport is enabled.
+    pub fn http_routes(mut self, routes: HttpRoutes) -> Self {
+        self.http_routes = routes;
+        self
+    }
+
+    /// Serve the HTTP transport over TLS with the certificate in `tls`
+    ///
+    /// Only takes effect when the HTTP transport is enabled; the
+    /// multiplexed transport stays plaintext.
+    pub fn http_tls(mut self, tls: TlsConfig) -> Self {
+        self.http_tls = Some(tls);
+        self
+    }
+
+    /// Answer denied HTTP rate limit requests with 429 instead of 200
+    ///
+    /// Only takes effect when the HTTP or multiplexed transport is enabled.
+    pub fn http_use_429(mut self, enabled: bool) -> Self {
+        self.http_use_429 = enabled;
+        self
+    }
+
+    /// Limit how long HTTP client connections are kept open
+    ///
+    /// Only takes effect when the HTTP transport is enabled.
+    pub fn http_keep_alive(mut self, keep_alive: HttpKeepAlive) -> Self {
+        self.http_keep_alive = keep_alive;
+        self
+    }
+
+    /// Compress HTTP batch responses for clients that accept gzip or deflate
+    ///
+    /// Only takes effect when the HTTP transport is enabled.
+    pub fn http_compression(mut self, enabled: bool) -> Self {
+        self.http_compression = enabled;
+        self
+    }
+
+    /// Set the store configuration
+    pub fn store(mut self, store: StoreConfig) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Set the channel buffer size for actor communication
+    pub fn buffer_size(mut self, size: usize) -> Self {
+        self.buffer_size = size;
+        self
+    }
+
+    /// Set the maximum number of denied keys to track in metrics
+    ///
+    /// Ignored if a metrics instance is supplied with
+    /// [`metrics`](ServerBuilder::metrics).
+    pub fn max_denied_keys(mut self, count: u32) -> Self {
+        self.max_denied_keys = count;
+        self
+    }
+
+    /// Track the top requested and allowed keys in metrics, sampled
+    ///
+    /// Ignored if a metrics instance is supplied with
+    /// [`metrics`](ServerBuilder::metrics).
+    pub fn top_keys(mut self, top_keys: TopKeysConfig) -> Self {
+        self.top_keys = Some(top_keys);
+        self
+    }
+
+    /// Keep the last `size` requests for `GET /admin/trace` (0 disables)
+    pub fn trace_buffer_size(mut self, size: usize) -> Self {
+        self.trace_buffer_size = size;
+        self
+    }
+
+    /// Log each transport's request latency quantiles every `interval`
+    pub fn latency_log_interval(mut self, interval: Duration) -> Self {
+        self.latency_log_interval = interval.as_secs();
+        self
+    }
+
+    /// Shed throttle checks beyond `load_shedding.max_rps` per second
+    pub fn load_shedding(mut self, load_shedding: LoadSheddingConfig) -> Self {
+        self.load_shedding = Some(load_shedding);
+        self
+    }
+
+    /// Reject requests beyond `validation`'s limits
+    pub fn validation(mut self, validation: ValidationConfig) -> Self {
+        self.validation = Some(validation);
+        self
+    }
+
+    /// Export decision events to a message broker
+    pub fn events(mut self, events: EventsConfig) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Alert on keys denied `denial_alerts.threshold` times within its window
+    ///
+    /// Alerts are streamed by `GET /events` and, with a webhook URL, POSTed
+    /// to it; the webhook requires the `webhook` feature, serving fails
+    /// otherwise.
+    pub fn denial_alerts(mut self, denial_alerts: DenialAlertsConfig) -> Self {
+        self.denial_alerts = Some(denial_alerts);
+        self
+    }
+
+    /// Push metrics snapshots to a collector
+    ///
+    /// Requires the `metrics-push` feature; serving fails otherwise.
+    pub fn metrics_push(mut self, metrics_push: MetricsPushConfig) -> Self {
+        self.metrics_push = Some(metrics_push);
+        self
+    }
+
+    /// Probe each enabled transport over loopback at `probe.interval`
+    ///
+    /// See `--probe-interval` for what is recorded.
+    pub fn probe(mut self, probe: ProbeConfig) -> Self {
+        self.probe = Some(probe);
+        self
+    }
+
+    /// Serve `GET /metrics` on a dedicated port, next to the transports
+    pub fn metrics_listener(mut self, host: impl Into<String>, port: u16) -> Self {
+        self.metrics_listener = Some(MetricsListenerConfig {
+            host: host.into(),
+            port,
+        });
+        self
+    }
+
+    /// Serve the `/admin` endpoints on a dedicated port instead of the transports
+    pub fn admin_listener(mut self, host: impl Into<String>, port: u16) -> Self {
+        self.admin_listener = Some(AdminListenerConfig {
+            host: host.into(),
+            port,
+            token: None,
+        });
+        self
+    }
+
+    /// Allow changing key overrides on the admin listener with `token`
+    ///
+    /// Clients send it as an `Authorization: Bearer` header. Only takes
+    /// effect with [`admin_listener`](Self::admin_listener).
+    pub fn admin_token(mut self, token: impl Into<String>) -> Self {
+        self.admin_token = Some(token.into());
+        self
+    }
+
+    /// Replicate rate limit state with other servers
+    ///
+    /// See `--cluster-port` for how peers are found and how consistent the
+    /// shared state is.
+    pub fn cluster(mut self, cluster: ClusterConfig) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    /// Load named rate limit policies from a TOML file
+    ///
+    /// See [`policy`](crate::policy) for the format.
+    pub fn policies(mut self, path: impl Into<PathBuf>) -> Self {
+        self.policies = Some(path.into());
+        self
+    }
+
+    /// Persist the key deny- and allow-lists to a TOML file
+    ///
+    /// The lists are loaded from the file on startup, if it exists; see
+    /// [`overrides`](crate::overrides).
+    pub fn overrides_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.overrides_file = Some(path.into());
+        self
+    }
+
+    /// Require throttle requests to authenticate with the API key `secret`
+    ///
+    /// `name` identifies the client in the per-key metrics. Repeat for
+    /// several keys; see [`auth`](crate::auth) for how clients send them.
+    pub fn api_key(mut self, name: impl Into<String>, secret: impl Into<String>) -> Self {
+        self.api_keys
+            .get_or_insert_with(ApiKeysConfig::default)
+            .keys
+            .push((name.into(), secret.into()));
+        self
+    }
+
+    /// Load API keys from a TOML file of `name = "secret"` pairs
+    pub fn api_keys_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.api_keys
+            .get_or_insert_with(ApiKeysConfig::default)
+            .file = Some(path.into());
+        self
+    }
+
+    /// Allow the API key `name` at most `count` throttle checks per
+    /// `period`, rounded down to whole seconds
+    ///
+    /// `*` names every key without a budget of its own; see
+    /// [`budget`](crate::budget).
+    pub fn api_key_budget(mut self, name: impl Into<String>, count: u64, period: Duration) -> Self {
+        self.api_keys
+            .get_or_insert_with(ApiKeysConfig::default)
+            .budgets
+            .push(ApiKeyBudget {
+                name: name.into(),
+                count,
+                period: period.as_secs(),
+            });
+        self
+    }
+
+    /// Write the startup status to a file once the server is running
+    ///
+    /// See [`StartupStatus`] for the contents. The file is removed again on
+    /// shutdown.
+    pub fn status_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.status_file = Some(path.into());
+        self
+    }
+
+    /// Register a hook called after every rate limiting decision
+    ///
+    /// Hooks are called in registration order. See [`crate::hooks`] for the
+    /// constraints they run under.
+    pub fn hook(mut self, hook: impl DecisionHook) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Use an existing metrics instance, e.g. to read counters in-process
+    pub fn metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Build the server
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no transport is enabled or the configuration is
+    /// otherwise invalid.
+    pub fn build(mut self) -> Result<Server> {
+        if let Some(mux) = &mut self.transports.mux {
+            mux.password = self.redis_password.clone();
+            mux.max_commands_per_second = self.redis_max_commands_per_second;
+            mux.routes = self.http_routes.clone();
+            mux.use_429 = self.http_use_429;
+        }
+        if let Some(redis) = &mut self.transports.redis {
+            redis.password = self.redis_password;
+            redis.max_commands_per_second = self.redis_max_commands_per_second;
+            redis.max_connections = self.redis_max_connections;
+            redis.max_connections_per_ip = self.redis_max_connections_per_ip;
+        }
+        if let Some(admin) = &mut self.admin_listener {
+            admin.token = self.admin_token;
+        }
+        if let Some(http) = &mut self.transports.http {
+            http.routes = self.http_routes;
+            http.tls = self.http_tls;
+            http.use_429 = self.http_use_429;
+            http.keep_alive = self.http_keep_alive;
+            http.compression = self.http_compression;
+        }
+
+        let config = Config {
+            transports: self.transports,
+            store: self.store,
+            buffer_size: self.buffer_size,
+            max_denied_keys: self.max_denied_keys,
+            top_keys: self.top_keys,
+            trace_buffer_size: self.trace_buffer_size,
+            events: self.events,
+            denial_alerts: self.denial_alerts,
+            metrics_push: self.metrics_push,
+            otlp: None,
+            probe: self.probe,
+            metrics_listener: self.metrics_listener,
+            policies: self.policies,
+            api_keys: self.api_keys,
+            status_file: self.status_file,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            cluster: self.cluster,
+            load_shedding: self.load_shedding,
+            validation: self.validation,
+            admin_listener: self.admin_listener,
+            latency_log_interval: self.latency_log_interval,
+            policies_table: None,
+            overrides_file: self.overrides_file,
+        };
+
+        let mut server = match self.metrics {
+            Some(metrics) => {
+                config.validate()?;
+                Server {
+                    config,
+                    metrics,
+                    hooks: Vec::new(),
+                }
+            }
+            None => Server::from_config(config)?,
+        };
+        server.hooks = self.hooks;
+        Ok(server)
+    }
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_builder_requires_transport() {
+        assert!(Server::builder().build().is_err());
+        assert!(Server::builder().http("127.0.0.1", 8080).build().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_serve_until_shutdown() {
+        let metrics = Arc::new(Metrics::new());
+        let server = Server::builder()
+            .http("127.0.0.1", 9181)
+            .metrics(Arc::clone(&metrics))
+            .build()
+            .unwrap();
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(server.serve(async {
+            shutdown_rx.await.ok();
+        }));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let response: serde_json::Value = reqwest::Client::new()
+            .post("http://127.0.0.1:9181/throttle")
+            .json(&serde_json::json!({
+                "key": "embedded",
+                "max_burst": 5,
+                "count_per_period": 10,
+                "period": 60
+            }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(response["allowed"], true);
+        assert_eq!(
+            metrics
+                .total_requests
+                .load(std::sync::atomic::Ordering::Relaxed),
+            1
+        );
+
+        shutdown_tx.send(()).unwrap();
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_http_throttle_batch() {
+        let server = Server::builder().http("127.0.0.1", 9190).build().unwrap();
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(server.serve(async {
+            shutdown_rx.await.ok();
+        }));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        let request = |key: &str, period: i64| {
+            serde_json::json!({
+                "key": key,
+                "max_burst": 2,
+                "count_per_period": 10,
+                "period": period
+            })
+        };
+        let response: serde_json::Value = client
+            .post("http://127.0.0.1:9190/throttle/batch")
+            .json(&serde_json::json!({
+                "requests": [request("a", 60), request("b", 0), request("a", 60), request("a", 60)]
+            }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let results = response["results"].as_array().unwrap();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0]["allowed"], true);
+        assert_eq!(results[1]["code"], "invalid_period");
+        assert_eq!(results[2]["allowed"], true);
+        assert_eq!(results[3]["allowed"], false);
+
+        let response = client
+            .post("http://127.0.0.1:9190/throttle/batch")
+            .json(&serde_json::json!({ "requests": [] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+
+        shutdown_tx.send(()).unwrap();
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_http_peek_and_reset() {
+        let server = Server::builder().http("127.0.0.1", 9191).build().unwrap();
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(server.serve(async {
+            shutdown_rx.await.ok();
+        }));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        client
+            .post("http://127.0.0.1:9191/throttle")
+            .json(&serde_json::json!({
+                "key": "user/1",
+                "max_burst": 5,
+                "count_per_period": 10,
+                "period": 60
+            }))
+            .send()
+            .await
+            .unwrap();
+
+        let url =
+            "http://127.0.0.1:9191/throttle/user%2F1?max_burst=5&count_per_period=10&period=60";
+        for _ in 0..2 {
+            let response: serde_json::Value =
+                client.get(url).send().await.unwrap().json().await.unwrap();
+            assert_eq!(response["allowed"], true);
+            assert_eq!(response["remaining"], 4);
+        }
+
+        let response = client
+            .get("http://127.0.0.1:9191/throttle/user%2F1?max_burst=5&count_per_period=10&period=0")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+
+        let report: serde_json::Value = client
+            .delete("http://127.0.0.1:9191/throttle/user%2F1")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(report["key"], "user/1");
+        assert_eq!(report["existed"], true);
+        let response: serde_json::Value =
+            client.get(url).send().await.unwrap().json().await.unwrap();
+        assert_eq!(response["remaining"], 5);
+
+        shutdown_tx.send(()).unwrap();
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_http_routes_under_base_path() {
+        let server = Server::builder()
+            .http("127.0.0.1", 9182)
+            .http_routes(HttpRoutes {
+                base_path: "/ratelimit/v1".to_string(),
+                health: "/healthz".to_string(),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(server.serve(async {
+            shutdown_rx.await.ok();
+        }));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        let status = |path: &'static str| {
+            let client = client.clone();
+            async move {
+                client
+                    .get(format!("http://127.0.0.1:9182{path}"))
+                    .send()
+                    .await
+                    .unwrap()
+                    .status()
+            }
+        };
+        assert_eq!(status("/ratelimit/v1/healthz").await, 200);
+        assert_eq!(status("/ratelimit/v1/metrics").await, 200);
+        assert_eq!(status("/ratelimit/v1/admin/peaks").await, 200);
+        assert_eq!(status("/health").await, 404);
+        assert_eq!(status("/ratelimit/v1/health").await, 404);
+
+        let response = client
+            .post("http://127.0.0.1:9182/ratelimit/v1/throttle")
+            .json(&serde_json::json!({
+                "key": "mounted",
+                "max_burst": 5,
+                "count_per_period": 10,
+                "period": 60
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        shutdown_tx.send(()).unwrap();
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_api_keys() {
+        let metrics = Arc::new(Metrics::new());
+        let server = Server::builder()
+            .http("127.0.0.1", 9189)
+            .api_key("checkout", "s3cret")
+            .api_key_budget("checkout", 2, Duration::from_secs(60))
+            .metrics(Arc::clone(&metrics))
+            .build()
+            .unwrap();
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(server.serve(async {
+            shutdown_rx.await.ok();
+        }));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        let throttle = |authorization: Option<&'static str>| {
+            let mut request =
+                client
+                    .post("http://127.0.0.1:9189/throttle")
+                    .json(&serde_json::json!({
+                        "key": "authenticated",
+                        "max_burst": 5,
+                        "count_per_period": 10,
+                        "period": 60
+                    }));
+            if let Some(authorization) = authorization {
+                request = request.header("Authorization", authorization);
+            }
+            request.send()
+        };
+
+        let response = throttle(None).await.unwrap();
+        assert_eq!(response.status(), 401);
+        assert_eq!(response.headers()["www-authenticate"], "Bearer");
+        let error: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(error["code"], "unauthorized");
+        let response = throttle(Some("Bearer wrong")).await.unwrap();
+        assert_eq!(response.status(), 401);
+
+        let response = throttle(Some("Bearer s3cret")).await.unwrap();
+        assert_eq!(response.status(), 200);
+
+        // The key's budget allows two checks a minute
+        let response = throttle(Some("Bearer s3cret")).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let response = throttle(Some("Bearer s3cret")).await.unwrap();
+        assert_eq!(response.status(), 429);
+        let error: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(error["code"], "budget_exhausted");
+
+        // Health checks need no key
+        let response = client
+            .get("http://127.0.0.1:9189/health")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        let exported = metrics.export_prometheus();
+        assert!(exported.contains("throttlecrab_auth_failures 2"));
+        assert!(exported.contains("throttlecrab_api_key_requests{key=\"checkout\"} 3"));
+        assert!(exported.contains("throttlecrab_api_key_budget_exhausted{key=\"checkout\"} 1"));
+
+        // Admin endpoints need a key as well
+        let response = client
+            .post("http://127.0.0.1:9189/admin/cleanup")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        let response = client
+            .post("http://127.0.0.1:9189/admin/cleanup")
+            .header("Authorization", "Bearer s3cret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        shutdown_tx.send(()).unwrap();
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_admin_trace() {
+        let server = Server::builder()
+            .http("127.0.0.1", 9183)
+            .trace_buffer_size(2)
+            .build()
+            .unwrap();
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(server.serve(async {
+            shutdown_rx.await.ok();
+        }));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        for key in ["first", "second", "third"] {
+            client
+                .post("http://127.0.0.1:9183/throttle")
+                .json(&serde_json::json!({
+                    "key": key,
+                    "max_burst": 5,
+                    "count_per_period": 10,
+                    "period": 60
+                }))
+                .send()
+                .await
+                .unwrap();
+        }
+
+        let response = client
+            .get("http://127.0.0.1:9183/admin/trace")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["content-type"], "text/csv");
+        let records = crate::trace::parse_csv(&response.text().await.unwrap()).unwrap();
+        let keys: Vec<_> = records.iter().map(|r| r.key.as_str()).collect();
+        assert_eq!(keys, ["second", "third"]);
+        assert_eq!(records[0].allowed, Some(true));
+
+        shutdown_tx.send(()).unwrap();
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_denial_events() {
+        let server = Server::builder()
+            .http("127.0.0.1", 9198)
+            .denial_alerts(DenialAlertsConfig {
+                threshold: 2,
+                window: 60,
+                webhook: None,
+            })
+            .build()
+            .unwrap();
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(server.serve(async {
+            shutdown_rx.await.ok();
+        }));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        let mut events = client
+            .get("http://127.0.0.1:9198/events")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(events.headers()["content-type"], "text/event-stream");
+
+        // Two allowed, then two denied
+        for _ in 0..4 {
+            client
+                .post("http://127.0.0.1:9198/throttle")
+                .json(&serde_json::json!({
+                    "key": "abuser",
+                    "namespace": "tenant",
+                    "max_burst": 2,
+                    "count_per_period": 1,
+                    "period": 60
+                }))
+                .send()
+                .await
+                .unwrap();
+        }
+
+        let mut body = String::new();
+        while !body.contains("\n\n") {
+            let chunk = tokio::time::timeout(Duration::from_secs(1), events.chunk())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            body.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+        assert!(body.starts_with("event: denial\n"), "{body}");
+        let data = body.lines().find_map(|line| line.strip_prefix("data: "));
+        let event: crate::denials::DenialEvent = serde_json::from_str(data.unwrap()).unwrap();
+        assert_eq!(event.key, "abuser");
+        assert_eq!(event.namespace.as_deref(), Some("tenant"));
+        assert_eq!(event.denials, 2);
+
+        shutdown_tx.send(()).unwrap();
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_admin_port() {
+        let server = Server::builder()
+            .http("127.0.0.1", 9179)
+            .admin_listener("127.0.0.1", 9178)
+            .build()
+            .unwrap();
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(server.serve(async {
+            shutdown_rx.await.ok();
+        }));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let response = reqwest::get("http://127.0.0.1:9178/admin/store")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        // The client listener no longer serves them
+        let response = reqwest::get("http://127.0.0.1:9179/admin/store")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+        let response = reqwest::get("http://127.0.0.1:9179/health").await.unwrap();
+        assert_eq!(response.status(), 200);
+
+        shutdown_tx.send(()).unwrap();
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_admin_overrides() {
+        let server = Server::builder()
+            .http("127.0.0.1", 9180)
+            .admin_listener("127.0.0.1", 9177)
+            .admin_token("s3cret")
+            .build()
+            .unwrap();
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(server.serve(async {
+            shutdown_rx.await.ok();
+        }));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        let throttle = |key: &'static str| {
+            let client = client.clone();
+            async move {
+                let response: serde_json::Value = client
+                    .post("http://127.0.0.1:9180/throttle")
+                    .json(&serde_json::json!({
+                        "key": key,
+                        "max_burst": 2,
+                        "count_per_period": 1,
+                        "period": 60,
+                        "quantity": 2
+                    }))
+                    .send()
+                    .await
+                    .unwrap()
+                    .json()
+                    .await
+                    .unwrap();
+                response["allowed"].as_bool().unwrap()
+            }
+        };
+        assert!(throttle("internal:job").await);
+        assert!(!throttle("internal:job").await);
+
+        // Changing the lists takes the admin token, on the admin listener only
+        let allow_all = serde_json::json!({"allow": ["*"]});
+        let response = client
+            .post("http://127.0.0.1:9180/admin/overrides")
+            .json(&allow_all)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+        let response = client
+            .post("http://127.0.0.1:9177/admin/overrides")
+            .json(&allow_all)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        let response = client
+            .post("http://127.0.0.1:9177/admin/overrides")
+            .bearer_auth("wrong")
+            .json(&allow_all)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+
+        let lists: serde_json::Value = client
+            .post("http://127.0.0.1:9177/admin/overrides")
+            .bearer_auth("s3cret")
+            .json(&serde_json::json!({"deny": ["bot:*"], "allow": ["internal:*"]}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(lists["deny"], serde_json::json!(["bot:*"]));
+        assert!(!throttle("bot:crawler").await);
+        // Overridden checks consume no tokens
+        assert!(throttle("internal:job").await);
+        assert!(throttle("internal:job").await);
+
+        let response = client
+            .post("http://127.0.0.1:9177/admin/overrides")
+            .bearer_auth("s3cret")
+            .json(&serde_json::json!({"deny": [""]}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 422);
+
+        client
+            .delete("http://127.0.0.1:9177/admin/overrides")
+            .bearer_auth("s3cret")
+            .json(&serde_json::json!({"allow": ["internal:*"]}))
+            .send()
+            .await
+            .unwrap();
+        assert!(!throttle("internal:job").await);
+        let lists: serde_json::Value = client
+            .get("http://127.0.0.1:9177/admin/overrides")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(lists, serde_json::json!({"deny": ["bot:*"], "allow": []}));
+
+        shutdown_tx.send(()).unwrap();
+        serving.await.unwrap().unwrap();
+    }
+}
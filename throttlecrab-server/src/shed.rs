@@ -0,0 +1,128 @@
+//! Server-wide load shedding
+//!
+//! With `--max-rps N` the server counts throttle checks across all
+//! transports and shards, and once `N` have arrived within a second the
+//! rest of that second are shed: they skip the actor queues and get the
+//! `--shed-decision` straight away (deny by default). This keeps latency
+//! flat through a traffic spike instead of letting the queues back up,
+//! at the cost of deciding the shed requests without their keys' state.
+//!
+//! Shed requests are answered in each transport's usual format with:
+//!
+//! - `limit` set from the request, `remaining` 0, and `retry_after` and
+//!   `reset_after` 1 second for a denial, 0 otherwise
+//! - status 503 from `POST /throttle`, whatever the decision
+//! - `throttlecrab-shed: true` metadata from the gRPC `Throttle`
+//!
+//! Batches, streams, Redis replies and UDP replies cannot be marked, so
+//! their clients see a plain decision.
+//!
+//! Shed requests are counted in `throttlecrab_shed_requests`. Peeks,
+//! resets and leases are never shed.
+
+use crate::config::{LoadSheddingConfig, ShedDecision};
+use crate::types::{ThrottleRequest, ThrottleResponse};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// Counts throttle checks per second and sheds those over the cap
+#[derive(Debug)]
+pub struct LoadShedder {
+    max_rps: u64,
+    decision: ShedDecision,
+    started: Instant,
+    /// Second since `started` that `count` is for
+    window: AtomicU64,
+    /// Checks admitted in the current window
+    count: AtomicU64,
+}
+
+impl LoadShedder {
+    /// Shed checks beyond `config.max_rps` per second
+    pub fn new(config: &LoadSheddingConfig) -> Self {
+        LoadShedder {
+            max_rps: config.max_rps,
+            decision: config.decision,
+            started: Instant::now(),
+            window: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    /// Count a check arriving at `now`, returning whether it is admitted
+    pub fn admit(&self, now: Instant) -> bool {
+        let window = now.saturating_duration_since(self.started).as_secs();
+        let current = self.window.load(Ordering::Relaxed);
+        // The first check of a new second resets the count; checks racing
+        // with the reset may land in either second
+        if window > current
+            && self
+                .window
+                .compare_exchange(current, window, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.count.store(0, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed) < self.max_rps
+    }
+
+    /// The response to a shed `request`
+    pub fn response(&self, request: &ThrottleRequest) -> ThrottleResponse {
+        let allowed = self.decision == ShedDecision::Allow;
+        let wait = if allowed { 0 } else { 1 };
+        ThrottleResponse {
+            allowed,
+            limit: request.max_burst,
+            remaining: 0,
+            reset_after: wait,
+            retry_after: wait,
+            retry_after_ms: wait * 1000,
+            reset_after_ms: wait * 1000,
+            cacheable_until_ms: 0,
+            warning: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn shedder(decision: ShedDecision) -> LoadShedder {
+        LoadShedder::new(&LoadSheddingConfig {
+            max_rps: 2,
+            decision,
+        })
+    }
+
+    #[test]
+    fn test_admit_per_second() {
+        let shedder = shedder(ShedDecision::Deny);
+        let start = shedder.started;
+
+        assert!(shedder.admit(start));
+        assert!(shedder.admit(start + Duration::from_millis(500)));
+        assert!(!shedder.admit(start + Duration::from_millis(999)));
+
+        // The next second starts a new count
+        let next = start + Duration::from_secs(1);
+        assert!(shedder.admit(next));
+        assert!(shedder.admit(next));
+        assert!(!shedder.admit(next));
+    }
+
+    #[test]
+    fn test_shed_response() {
+        let request = ThrottleRequest::new("user:1", 10, 100, 60);
+
+        let denied = shedder(ShedDecision::Deny).response(&request);
+        assert!(!denied.allowed);
+        assert_eq!((denied.limit, denied.remaining), (10, 0));
+        assert_eq!(denied.retry_after, 1);
+
+        let allowed = shedder(ShedDecision::Allow).response(&request);
+        assert!(allowed.allowed);
+        assert_eq!(allowed.retry_after, 0);
+    }
+}
@@ -0,0 +1,231 @@
+//! Periodic snapshots of the store for persistence across restarts
+//!
+//! A lighter alternative to the write-ahead log. With `--snapshot-path`, the
+//! live entries are written to disk every `--snapshot-interval` seconds and
+//! once more on a clean shutdown, then restored on startup. Nothing is done
+//! per request, but a crash loses up to one interval of state, whereas the
+//! log loses at most its sync interval.
+//!
+//! Snapshots are written in the write-ahead log's format, i.e. a freshly
+//! compacted log: to a temporary file that is synced and renamed over the
+//! previous snapshot, so a crash mid-write keeps the older one intact.
+
+use crate::actor::{RateLimiterHandle, StoreType, entry_shard_index};
+use crate::config::SnapshotConfig;
+use crate::metrics::Metrics;
+use crate::wal;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+use tokio::sync::oneshot;
+
+/// Load the snapshot at `path` into the shard stores, returning the keys restored
+///
+/// Each entry goes to the store of the shard that owns its key, window
+/// entries included, so a snapshot can be restored with a different
+/// `--shards` than it was written with. A missing snapshot is not an error;
+/// the server starts empty.
+pub(crate) fn restore(path: &Path, stores: &mut [StoreType], now: SystemTime) -> Result<usize> {
+    let mut restored = 0;
+    wal::replay(path, |key, value, expiry| {
+        // Entries that expired while the server was down are dead state
+        if expiry.is_none_or(|exp| exp > now) {
+            stores[entry_shard_index(&key, stores.len())].insert(&key, value, expiry);
+            restored += 1;
+        }
+    })?;
+    if restored > 0 {
+        tracing::info!(
+            "Restored {} keys from snapshot {}",
+            restored,
+            path.display()
+        );
+    }
+    Ok(restored)
+}
+
+/// Write the limiter's live entries to `path`, returning the keys written
+pub(crate) async fn write(
+    limiter: &RateLimiterHandle,
+    path: PathBuf,
+    metrics: &Metrics,
+) -> Result<usize> {
+    let entries = limiter.live_entries().await?;
+    let count = entries.len();
+    let written = tokio::task::spawn_blocking(move || wal::write_snapshot(&path, &entries)).await;
+    match written.map_err(anyhow::Error::from).and_then(|size| size) {
+        Ok(size) => {
+            metrics.snapshot_size_bytes.store(size, Ordering::Relaxed);
+            Ok(count)
+        }
+        Err(e) => {
+            metrics.snapshot_failures.fetch_add(1, Ordering::Relaxed);
+            Err(e)
+        }
+    }
+}
+
+/// Snapshot at `config.interval` until `stop` fires, then once more
+///
+/// The final snapshot is taken here rather than by the caller so it can't
+/// race a periodic one still being written.
+pub(crate) async fn run(
+    config: SnapshotConfig,
+    limiter: RateLimiterHandle,
+    metrics: Arc<Metrics>,
+    mut stop: oneshot::Receiver<()>,
+) {
+    let mut interval = tokio::time::interval(config.interval);
+    // The first tick completes immediately; the store was just restored
+    interval.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                match write(&limiter, config.path.clone(), &metrics).await {
+                    Ok(count) => tracing::debug!("Snapshot of {} keys written", count),
+                    Err(e) => tracing::error!("Snapshot failed: {}", e),
+                }
+            }
+            _ = &mut stop => break,
+        }
+    }
+
+    match write(&limiter, config.path.clone(), &metrics).await {
+        Ok(count) => tracing::info!(
+            "Final snapshot of {} keys written to {}",
+            count,
+            config.path.display()
+        ),
+        Err(e) => tracing::error!("Final snapshot failed: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StoreConfig;
+    use crate::store;
+    use crate::types::{AlgorithmKind, ThrottleRequest};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[tokio::test]
+    async fn test_snapshot_roundtrip() {
+        let path =
+            std::env::temp_dir().join(format!("throttlecrab-snapshot-{}.snap", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config = StoreConfig {
+            snapshot: Some(SnapshotConfig {
+                path: path.clone(),
+                interval: Duration::from_secs(60),
+            }),
+            ..StoreConfig::default()
+        };
+
+        let metrics = Arc::new(Metrics::new());
+        let limiter = store::create_rate_limiter(&config, 100, Arc::clone(&metrics))
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            limiter
+                .throttle(ThrottleRequest::new("user:1", 5, 10, 60))
+                .await
+                .unwrap();
+        }
+        limiter
+            .throttle(ThrottleRequest::new("user:2", 5, 10, 60))
+            .await
+            .unwrap();
+        assert_eq!(write(&limiter, path.clone(), &metrics).await.unwrap(), 2);
+        assert!(metrics.snapshot_size_bytes.load(Ordering::Relaxed) > 0);
+
+        // A new server picks up where the old one stopped
+        let metrics = Arc::new(Metrics::new());
+        let limiter = store::create_rate_limiter(&config, 100, metrics)
+            .await
+            .unwrap();
+        let response = limiter
+            .throttle(ThrottleRequest::new("user:1", 5, 10, 60))
+            .await
+            .unwrap();
+        assert_eq!(response.remaining, 1);
+
+        // Keys are spread over the shards of a sharded server
+        let sharded = StoreConfig {
+            shards: 4,
+            ..config.clone()
+        };
+        let limiter = store::create_rate_limiter(&sharded, 100, Arc::new(Metrics::new()))
+            .await
+            .unwrap();
+        let response = limiter
+            .throttle(ThrottleRequest::new("user:1", 5, 10, 60))
+            .await
+            .unwrap();
+        assert_eq!(response.remaining, 1);
+        assert_eq!(limiter.live_entries().await.unwrap().len(), 2);
+
+        // Expired entries are not restored
+        let mut store_type = StoreType::Periodic(throttlecrab::RateLimiter::new(
+            throttlecrab::PeriodicStore::new(),
+        ));
+        let far_future = UNIX_EPOCH + Duration::from_secs(u32::MAX as u64 * 4);
+        assert_eq!(
+            restore(&path, std::slice::from_mut(&mut store_type), far_future).unwrap(),
+            0
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_sharded_window_roundtrip() {
+        let path = std::env::temp_dir().join(format!(
+            "throttlecrab-snapshot-windows-{}.snap",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let config = StoreConfig {
+            shards: 4,
+            snapshot: Some(SnapshotConfig {
+                path: path.clone(),
+                interval: Duration::from_secs(60),
+            }),
+            ..StoreConfig::default()
+        };
+        let window_requests = || {
+            (0..8).flat_map(|i| {
+                [AlgorithmKind::SlidingWindow, AlgorithmKind::FixedWindow].map(|algorithm| {
+                    let key = format!("{}:{i}", algorithm.as_str());
+                    ThrottleRequest {
+                        quantity: 10,
+                        algorithm,
+                        ..ThrottleRequest::new(key, 10, 10, 60)
+                    }
+                })
+            })
+        };
+
+        let metrics = Arc::new(Metrics::new());
+        let limiter = store::create_rate_limiter(&config, 100, Arc::clone(&metrics))
+            .await
+            .unwrap();
+        for request in window_requests() {
+            assert!(limiter.throttle(request).await.unwrap().allowed);
+        }
+        write(&limiter, path.clone(), &metrics).await.unwrap();
+
+        // Every window entry is restored to the shard its key is checked on
+        let limiter = store::create_rate_limiter(&config, 100, Arc::new(Metrics::new()))
+            .await
+            .unwrap();
+        for request in window_requests() {
+            let key = Arc::clone(&request.key);
+            assert!(!limiter.throttle(request).await.unwrap().allowed, "{key}");
+        }
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,356 @@
+//! Startup status
+//!
+//! Once every transport has been started, the server prints a single JSON
+//! line to stdout describing what it is running: listen addresses, enabled
+//! features, store settings and versions. The same document can be written
+//! to a file with `--status-file` so orchestration tools can wait for it and
+//! assert on it without parsing log output.
+//!
+//! # Example
+//!
+//! ```json
+//! {"event":"started","version":"0.4.39","library_version":"0.4.39","pid":4242,
+//!  "started_at_ms":1760000000000,"transports":{"http":"0.0.0.0:8080","grpc":null,
+//!  "redis":"0.0.0.0:6379","mux":null,"udp":null},"features":["wal"],"cargo_features":[],
+//!  "store":{"type":"adaptive","capacity":100000,"max_keys":0,"max_memory_mb":0,
+//!  "on_full":"reject","clock":"system","shards":1,"wal":"/var/lib/throttlecrab/wal",
+//!  "canary":null},
+//!  "buffer_size":100000}
+//! ```
+
+use crate::config::{Backpressure, ClockType, Config, OnFull, StoreType};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Structured description of a running server
+#[derive(Debug, Clone, Serialize)]
+pub struct StartupStatus {
+    /// Always `"started"`
+    pub event: &'static str,
+    /// Version of `throttlecrab-server`
+    pub version: &'static str,
+    /// Version of the `throttlecrab` library
+    pub library_version: &'static str,
+    /// Process ID
+    pub pid: u32,
+    /// Time the server started (Unix epoch milliseconds)
+    pub started_at_ms: u64,
+    /// Listen address of each transport, `null` if disabled
+    pub transports: TransportStatus,
+    /// Optional features enabled by the configuration
+    pub features: Vec<&'static str>,
+    /// Optional cargo features compiled into the binary
+    pub cargo_features: Vec<&'static str>,
+    /// Store settings
+    pub store: StoreStatus,
+    /// Channel buffer size for actor communication
+    pub buffer_size: usize,
+}
+
+/// Listen addresses of the transports
+#[derive(Debug, Clone, Serialize)]
+pub struct TransportStatus {
+    pub http: Option<String>,
+    pub grpc: Option<String>,
+    pub redis: Option<String>,
+    pub mux: Option<String>,
+    pub udp: Option<String>,
+}
+
+/// Store settings of a running server
+#[derive(Debug, Clone, Serialize)]
+pub struct StoreStatus {
+    #[serde(rename = "type")]
+    pub store_type: StoreType,
+    pub capacity: usize,
+    pub max_keys: usize,
+    /// Memory budget for store entries in MiB, 0 if unlimited
+    pub max_memory_mb: usize,
+    pub on_full: OnFull,
+    pub clock: ClockType,
+    /// Actors the key space is partitioned across
+    pub shards: usize,
+    /// Write-ahead log path, `null` if persistence is disabled
+    pub wal: Option<PathBuf>,
+    /// Canary store type, `null` if no canary is configured
+    pub canary: Option<StoreType>,
+}
+
+impl StartupStatus {
+    /// Describe a server started now with `config`
+    pub fn new(config: &Config) -> Self {
+        let transports = &config.transports;
+        let store = &config.store;
+
+        let mut features = Vec::new();
+        if store.max_keys > 0 {
+            features.push("max_keys");
+        }
+        if store.max_memory_mb > 0 {
+            features.push("max_memory");
+        }
+        if transports
+            .http
+            .as_ref()
+            .is_some_and(|http| http.tls.is_some())
+        {
+            features.push("http_tls");
+        }
+        if config.api_keys.is_some() {
+            features.push("api_keys");
+        }
+        if config
+            .api_keys
+            .as_ref()
+            .is_some_and(|api_keys| api_keys.namespaces)
+        {
+            features.push("api_key_namespaces");
+        }
+        if config
+            .api_keys
+            .as_ref()
+            .is_some_and(|api_keys| !api_keys.budgets.is_empty())
+        {
+            features.push("api_key_budgets");
+        }
+        if !store.namespace_quotas.is_empty() {
+            features.push("namespace_quotas");
+        }
+        if config.load_shedding.is_some() {
+            features.push("load_shedding");
+        }
+        if config.overrides_file.is_some() {
+            features.push("key_overrides");
+        }
+        if config.validation.is_some() {
+            features.push("request_limits");
+        }
+        if store.wal.is_some() {
+            features.push("wal");
+        }
+        if store.snapshot.is_some() {
+            features.push("snapshot");
+        }
+        if store.canary.is_some() {
+            features.push("canary");
+        }
+        if config.events.is_some() {
+            features.push("events");
+        }
+        if config.metrics_push.is_some() {
+            features.push("metrics_push");
+        }
+        if config.max_denied_keys > 0 {
+            features.push("top_denied_keys");
+        }
+        if config.trace_buffer_size > 0 {
+            features.push("trace");
+        }
+        if config.probe.is_some() {
+            features.push("probe");
+        }
+        if config.metrics_listener.is_some() {
+            features.push("metrics_listener");
+        }
+        if config.admin_listener.is_some() {
+            features.push("admin_listener");
+        }
+        if store.hot_keys > 0 {
+            features.push("hot_keys");
+        }
+        if config.policies.is_some() {
+            features.push("policies");
+        }
+        if config.cluster.is_some() {
+            features.push("cluster");
+        }
+        if config.store.coalesce_window_us > 0 {
+            features.push("coalescing");
+        }
+        if config.store.backpressure != Backpressure::Block {
+            features.push("backpressure");
+        }
+        if config.store.micro_cache_ms > 0 {
+            features.push("micro_cache");
+        }
+        if transports
+            .redis
+            .as_ref()
+            .is_some_and(|redis| redis.password.is_some())
+        {
+            features.push("redis_auth");
+        }
+
+        StartupStatus {
+            event: "started",
+            version: env!("CARGO_PKG_VERSION"),
+            library_version: throttlecrab::VERSION,
+            pid: std::process::id(),
+            started_at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            transports: TransportStatus {
+                http: transports
+                    .http
+                    .as_ref()
+                    .map(|http| format!("{}:{}", http.host, http.port)),
+                grpc: transports
+                    .grpc
+                    .as_ref()
+                    .map(|grpc| format!("{}:{}", grpc.host, grpc.port)),
+                redis: transports
+                    .redis
+                    .as_ref()
+                    .map(|redis| format!("{}:{}", redis.host, redis.port)),
+                mux: transports
+                    .mux
+                    .as_ref()
+                    .map(|mux| format!("{}:{}", mux.host, mux.port)),
+                udp: transports
+                    .udp
+                    .as_ref()
+                    .map(|udp| format!("{}:{}", udp.host, udp.port)),
+            },
+            features,
+            cargo_features: cargo_features(),
+            store: StoreStatus {
+                store_type: store.store_type,
+                capacity: store.capacity,
+                max_keys: store.max_keys,
+                max_memory_mb: store.max_memory_mb,
+                on_full: store.on_full,
+                clock: store.clock,
+                shards: store.shards,
+                wal: store.wal.as_ref().map(|wal| wal.path.clone()),
+                canary: store.canary.as_ref().map(|canary| canary.store_type),
+            },
+            buffer_size: config.buffer_size,
+        }
+    }
+
+    /// Serialize as a single line of JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("startup status is always serializable")
+    }
+
+    /// Write the status to `path`
+    ///
+    /// The file is written next to `path` and renamed into place, so readers
+    /// never see a partial document.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        std::fs::write(&tmp, self.to_json() + "\n")
+            .with_context(|| format!("Failed to write status file {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to write status file {}", path.display()))
+    }
+}
+
+fn cargo_features() -> Vec<&'static str> {
+    [
+        ("nats", cfg!(feature = "nats")),
+        ("kafka", cfg!(feature = "kafka")),
+        ("webhook", cfg!(feature = "webhook")),
+        ("metrics-push", cfg!(feature = "metrics-push")),
+        ("otlp", cfg!(feature = "otlp")),
+        ("redis-store", cfg!(feature = "redis-store")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{
+        HttpConfig, HttpKeepAlive, HttpRoutes, LogFormat, RedisConfig, StoreConfig, TransportConfig,
+    };
+
+    #[test]
+    fn test_startup_status() {
+        let config = Config {
+            transports: TransportConfig {
+                http: Some(HttpConfig {
+                    host: "127.0.0.1".to_string(),
+                    port: 8080,
+                    routes: HttpRoutes::default(),
+                    tls: None,
+                    use_429: false,
+                    keep_alive: HttpKeepAlive::default(),
+                    compression: false,
+                }),
+                grpc: None,
+                redis: Some(RedisConfig {
+                    host: "0.0.0.0".to_string(),
+                    port: 6379,
+                    password: None,
+                    max_commands_per_second: 0,
+                    max_connections: 0,
+                    max_connections_per_ip: 0,
+                }),
+                mux: None,
+                udp: None,
+            },
+            store: StoreConfig {
+                max_keys: 1000,
+                ..Default::default()
+            },
+            buffer_size: 100_000,
+            max_denied_keys: 0,
+            trace_buffer_size: 0,
+            events: None,
+            metrics_push: None,
+            otlp: None,
+            probe: None,
+            metrics_listener: None,
+            policies: None,
+            api_keys: None,
+            status_file: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            cluster: None,
+            load_shedding: None,
+            admin_listener: None,
+            validation: None,
+            denial_alerts: None,
+            top_keys: None,
+            latency_log_interval: 0,
+            policies_table: None,
+            overrides_file: None,
+        };
+        let status = StartupStatus::new(&config);
+        let json = status.to_json();
+        assert!(!json.contains('\n'));
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["event"], "started");
+        assert_eq!(value["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(value["transports"]["http"], "127.0.0.1:8080");
+        assert_eq!(value["transports"]["grpc"], serde_json::Value::Null);
+        assert_eq!(value["transports"]["redis"], "0.0.0.0:6379");
+        assert_eq!(value["features"], serde_json::json!(["max_keys"]));
+        assert_eq!(value["store"]["type"], "periodic");
+        assert_eq!(value["store"]["on_full"], "reject");
+        assert_eq!(value["store"]["clock"], "monotonic");
+
+        let dir = std::env::temp_dir().join(format!("throttlecrab-status-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("status.json");
+        status.write(&path).unwrap();
+        let written: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written, value);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -5,7 +5,8 @@
 //!
 //! # Store Types
 //!
-//! The server supports three different store implementations:
+//! The server supports three different store implementations, plus an
+//! automatic mode that picks between them:
 //!
 //! ## Periodic Store
 //! - Cleanups occur at fixed intervals
@@ -21,18 +22,54 @@
 //! - Cleanup frequency adjusts based on load
 //! - Balances performance and memory usage
 //! - Best for: Workloads with varying traffic patterns
+//!
+//! ## Auto
+//! - Starts as an adaptive store
+//! - Migrates to a periodic store if cleanups exceed the latency budget
+//! - Best for: Unknown workloads with a latency target
+//!
+//! ## Redis
+//! - State kept in an external Redis, shared by every server using it
+//! - Decisions run outside the actor, each costing round trips to Redis
+//! - Best for: Several servers that must enforce exact shared limits
+//! - Requires the `redis-store` cargo feature
 
-use crate::actor::{RateLimiterActor, RateLimiterHandle};
-use crate::config::{StoreConfig, StoreType};
+use crate::actor::{
+    KeyAdmission, RateLimiterActor, RateLimiterHandle, Shard, StoreType as ActorStore,
+};
+use crate::auto_store::AutoStore;
+use crate::canary::Canary;
+use crate::config::{Backpressure, ClockType, OnFull, StoreConfig, StoreType};
+use crate::hot_keys::HotKeys;
 use crate::metrics::Metrics;
+use crate::micro_cache::MicroCache;
+use crate::snapshot;
+use crate::wal::Wal;
+use anyhow::{Result, anyhow};
 use std::sync::Arc;
 use std::time::Duration;
-use throttlecrab::{AdaptiveStore, PeriodicStore, ProbabilisticStore};
+use throttlecrab::{
+    AdaptiveStore, Clock, MonotonicClock, PeriodicStore, ProbabilisticStore, RateLimiter,
+    SystemClock, TimingWheelStore,
+};
+use tokio::time::MissedTickBehavior;
 
 /// Create a rate limiter actor with the configured store
 ///
 /// This factory function creates the appropriate store type based on
-/// configuration and spawns an actor to manage it.
+/// configuration and spawns an actor to manage it. When `max_keys` or
+/// `max_memory_mb` is set, the actor enforces the key limit using the
+/// configured `on_full` policy; with `evict-lru`, the store tracks how
+/// recently each key was used.
+/// When a write-ahead log or a snapshot is configured, it is loaded into the
+/// store before the actor starts. When a canary store is configured, the
+/// sampled fraction of keys is mirrored to it for comparison. The auto store
+/// starts adaptive and is watched by an [`AutoStore`] selector.
+///
+/// With more than one shard, each shard gets its own store, canary and auto
+/// selector, and an equal share of `capacity`, `max_keys`, `max_memory_mb`
+/// and each namespace quota. A snapshot is split between the shards as it is
+/// restored.
 ///
 /// # Parameters
 ///
@@ -43,6 +80,12 @@ use throttlecrab::{AdaptiveStore, PeriodicStore, ProbabilisticStore};
 ///
 /// A handle to communicate with the spawned rate limiter actor
 ///
+/// # Errors
+///
+/// Returns an error if the write-ahead log cannot be read or created, the
+/// snapshot cannot be read, a write-ahead log is combined with shards, or
+/// the Redis store cannot connect or was not compiled in.
+///
 /// # Example
 ///
 /// ```ignore
@@ -52,36 +95,204 @@ use throttlecrab::{AdaptiveStore, PeriodicStore, ProbabilisticStore};
 ///     // ... other fields
 /// };
 /// let metrics = Arc::new(Metrics::new());
-/// let limiter = create_rate_limiter(&config, 10_000, metrics);
+/// let limiter = create_rate_limiter(&config, 10_000, metrics).await?;
 /// ```
-pub fn create_rate_limiter(
+pub async fn create_rate_limiter(
     config: &StoreConfig,
     buffer_size: usize,
     metrics: Arc<Metrics>,
-) -> RateLimiterHandle {
-    match config.store_type {
+) -> Result<RateLimiterHandle> {
+    if config.shards > 1 && config.wal.is_some() {
+        return Err(anyhow!("the write-ahead log requires a single shard"));
+    }
+
+    // Each shard holds its share of the keys
+    let shards = config.shards.max(1);
+    let max_bytes = config.max_memory_mb.saturating_mul(1024 * 1024) / shards;
+    let shard_config = StoreConfig {
+        capacity: config.capacity.div_ceil(shards),
+        max_keys: config.max_keys.div_ceil(shards),
+        namespace_quotas: config
+            .namespace_quotas
+            .iter()
+            .map(|(namespace, &keys)| (namespace.clone(), keys.div_ceil(shards)))
+            .collect(),
+        ..config.clone()
+    };
+    let config = &shard_config;
+    let mut stores: Vec<_> = match config.store_type {
+        // Each shard gets its own connection
+        StoreType::Redis => {
+            let mut stores = Vec::with_capacity(shards);
+            for _ in 0..shards {
+                stores.push(redis_store(config).await?);
+            }
+            stores
+        }
+        store_type => (0..shards)
+            .map(|_| build_store(store_type, config))
+            .collect(),
+    };
+
+    let clock: Arc<dyn Clock + Send + Sync> = match config.clock {
+        ClockType::System => Arc::new(SystemClock),
+        ClockType::Monotonic => Arc::new(MonotonicClock::new()),
+    };
+
+    let mut wal = match &config.wal {
+        Some(wal_config) => Some(Wal::open(
+            wal_config,
+            &mut stores[0],
+            Arc::clone(&clock),
+            Arc::clone(&metrics),
+        )?),
+        None => None,
+    };
+
+    if let Some(snapshot_config) = &config.snapshot {
+        snapshot::restore(&snapshot_config.path, &mut stores, clock.now())?;
+    }
+
+    let shards = stores
+        .into_iter()
+        .map(|store_type| Shard {
+            store_type,
+            admission: KeyAdmission::new(config.max_keys, config.on_full)
+                .with_max_memory(max_bytes)
+                .with_namespace_quotas(&config.namespace_quotas),
+            wal: wal.take(),
+            canary: config
+                .canary
+                .as_ref()
+                .map(|canary| Canary::new(build_store(canary.store_type, config), canary.fraction)),
+            auto: (config.store_type == StoreType::Auto)
+                .then(|| AutoStore::new(config, std::time::Instant::now())),
+            coalesce: (config.coalesce_window_us > 0)
+                .then(|| Duration::from_micros(config.coalesce_window_us)),
+            hot_keys: (config.hot_keys > 0).then(|| HotKeys::new(config.hot_keys)),
+            shed_oldest: config.backpressure == Backpressure::ShedOldest,
+        })
+        .collect();
+
+    let handle = RateLimiterActor::spawn_shards(buffer_size, shards, metrics)
+        .with_clock(clock)
+        .with_store_type(config.store_type)
+        .with_backpressure(config.backpressure);
+    Ok(match config.micro_cache_ms {
+        0 => handle,
+        ms => handle.with_micro_cache(Arc::new(MicroCache::new(Duration::from_millis(ms)))),
+    })
+}
+
+/// Log the store's statistics every `interval` until the actors shut down
+///
+/// Reports the same figures as `GET /admin/store`, so entry growth and
+/// uncollected expired entries show up in the logs for capacity planning.
+pub(crate) async fn log_stats(limiter: RateLimiterHandle, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The first tick completes immediately; skip the empty startup store
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+        let Ok(stats) = limiter.store_stats().await else {
+            return;
+        };
+        tracing::info!(
+            entries = stats.entries,
+            expired_entries = stats.expired_entries,
+            bytes = stats.bytes,
+            shard_entries = ?stats.shard_entries,
+            "Store statistics"
+        );
+    }
+}
+
+/// Build a store of `store_type` with the parameters from `config`
+pub(crate) fn build_store(store_type: StoreType, config: &StoreConfig) -> ActorStore {
+    match store_type {
         StoreType::Periodic => {
-            let store = PeriodicStore::builder()
+            let mut builder = PeriodicStore::builder()
                 .capacity(config.capacity)
                 .cleanup_interval(Duration::from_secs(config.cleanup_interval))
-                .build();
-            RateLimiterActor::spawn_periodic(buffer_size, store, metrics)
+                .shards(config.map_shards)
+                .ttl_multiplier(config.ttl_multiplier)
+                .track_recency(evicts_lru(config));
+            if let Some(max) = max_ttl(config) {
+                builder = builder.max_ttl(max);
+            }
+            ActorStore::Periodic(RateLimiter::new(builder.build()))
         }
         StoreType::Probabilistic => {
-            let store = ProbabilisticStore::builder()
+            let mut builder = ProbabilisticStore::builder()
                 .capacity(config.capacity)
                 .cleanup_probability(config.cleanup_probability)
-                .build();
-            RateLimiterActor::spawn_probabilistic(buffer_size, store, metrics)
+                .shards(config.map_shards)
+                .ttl_multiplier(config.ttl_multiplier)
+                .track_recency(evicts_lru(config));
+            if let Some(max) = max_ttl(config) {
+                builder = builder.max_ttl(max);
+            }
+            ActorStore::Probabilistic(RateLimiter::new(builder.build()))
         }
-        StoreType::Adaptive => {
-            let store = AdaptiveStore::builder()
+        StoreType::Adaptive | StoreType::Auto => {
+            let mut builder = AdaptiveStore::builder()
                 .capacity(config.capacity)
                 .min_interval(Duration::from_secs(config.min_interval))
                 .max_interval(Duration::from_secs(config.max_interval))
                 .max_operations(config.max_operations)
-                .build();
-            RateLimiterActor::spawn_adaptive(buffer_size, store, metrics)
+                .shards(config.map_shards)
+                .ttl_multiplier(config.ttl_multiplier)
+                .track_recency(evicts_lru(config));
+            if let Some(max) = max_ttl(config) {
+                builder = builder.max_ttl(max);
+            }
+            ActorStore::Adaptive(RateLimiter::new(builder.build()))
+        }
+        StoreType::TimingWheel => {
+            let mut builder = TimingWheelStore::builder()
+                .capacity(config.capacity)
+                .cleanup_batch(config.cleanup_batch)
+                .shards(config.map_shards)
+                .ttl_multiplier(config.ttl_multiplier)
+                .track_recency(evicts_lru(config));
+            if let Some(max) = max_ttl(config) {
+                builder = builder.max_ttl(max);
+            }
+            ActorStore::TimingWheel(RateLimiter::new(builder.build()))
+        }
+        StoreType::Redis => {
+            unreachable!(
+                "Redis stores are built by create_rate_limiter, never as a canary or REPL store"
+            )
         }
     }
 }
+
+/// Connect a Redis store to `config.redis_url`
+async fn redis_store(config: &StoreConfig) -> Result<ActorStore> {
+    let url = config
+        .redis_url
+        .as_deref()
+        .ok_or_else(|| anyhow!("--store-redis-url is required with --store redis"))?;
+    #[cfg(feature = "redis-store")]
+    return throttlecrab::AsyncRedisStore::open(url)
+        .await
+        .map(|store| ActorStore::Redis(throttlecrab::AsyncRateLimiter::new(store)))
+        .map_err(|e| anyhow!(e));
+    #[cfg(not(feature = "redis-store"))]
+    Err(anyhow!(
+        "Redis store for {url} is not available, rebuild with `--features redis-store`"
+    ))
+}
+
+/// Whether a key limit is enforced by evicting the least recently used keys
+fn evicts_lru(config: &StoreConfig) -> bool {
+    config.on_full == OnFull::EvictLru && (config.max_keys > 0 || config.max_memory_mb > 0)
+}
+
+/// The configured TTL cap, if any
+fn max_ttl(config: &StoreConfig) -> Option<Duration> {
+    (config.max_ttl > 0).then(|| Duration::from_secs(config.max_ttl))
+}
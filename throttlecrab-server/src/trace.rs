@@ -0,0 +1,305 @@
+//! Request traces for reproducible benchmarks
+//!
+//! With `--trace-buffer-size N` the server keeps the last `N` rate limit
+//! requests and their decisions in a ring buffer, and `GET /admin/trace`
+//! returns them as CSV. The integration tests' `replay` command sends such a
+//! trace back to a server at its original or a scaled speed, so traffic
+//! observed in production can be reproduced in a benchmark.
+//!
+//! # Format
+//!
+//! ```text
+//! timestamp_ms,key,max_burst,count_per_period,period,quantity,allowed
+//! 1704067200000,user:123,10,100,60,1,true
+//! 1704067200012,"tenant:a,b",10,100,60,1,false
+//! ```
+//!
+//! Keys containing commas, quotes or line breaks are quoted as in RFC 4180.
+//! `allowed` may be left empty in traces written by hand; it is only used
+//! to compare replayed decisions with the recorded ones.
+
+use crate::types::ThrottleRequest;
+use anyhow::{Result, anyhow};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+/// Header line of a trace
+pub const HEADER: &str = "timestamp_ms,key,max_burst,count_per_period,period,quantity,allowed";
+
+/// One recorded rate limit request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// Request time in milliseconds since the Unix epoch
+    pub timestamp_ms: i64,
+    /// The rate limited key
+    pub key: String,
+    /// Maximum burst capacity
+    pub max_burst: i64,
+    /// Total requests allowed per period
+    pub count_per_period: i64,
+    /// Time period in seconds
+    pub period: i64,
+    /// Number of tokens consumed
+    pub quantity: i64,
+    /// The recorded decision, if known
+    pub allowed: Option<bool>,
+}
+
+impl TraceRecord {
+    /// Record `request` and the decision it got
+    pub fn new(request: &ThrottleRequest, allowed: bool) -> Self {
+        let timestamp_ms = request
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        TraceRecord {
+            timestamp_ms,
+            key: request.key.to_string(),
+            max_burst: request.max_burst,
+            count_per_period: request.count_per_period,
+            period: request.period,
+            quantity: request.quantity,
+            allowed: Some(allowed),
+        }
+    }
+}
+
+/// Ring buffer of the most recent requests
+///
+/// Shared by every clone of a [`RateLimiterHandle`](crate::actor::RateLimiterHandle)
+/// it is attached to. Once full, each new request replaces the oldest.
+#[derive(Debug)]
+pub struct TraceBuffer {
+    records: Mutex<VecDeque<TraceRecord>>,
+    capacity: usize,
+}
+
+impl TraceBuffer {
+    /// Create a buffer holding up to `capacity` requests
+    pub fn new(capacity: usize) -> Self {
+        TraceBuffer {
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// Append a decision, dropping the oldest one if the buffer is full
+    pub fn record(&self, request: &ThrottleRequest, allowed: bool) {
+        if self.capacity == 0 {
+            return;
+        }
+        let record = TraceRecord::new(request, allowed);
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Estimated heap bytes held by the buffer
+    pub fn memory_usage(&self) -> usize {
+        let records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        records.capacity() * size_of::<TraceRecord>()
+            + records
+                .iter()
+                .map(|record| record.key.capacity())
+                .sum::<usize>()
+    }
+
+    /// Copy of the buffered requests, oldest first
+    pub fn snapshot(&self) -> Vec<TraceRecord> {
+        let records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        records.iter().cloned().collect()
+    }
+}
+
+/// Write `records` as a CSV trace, header included
+pub fn to_csv(records: &[TraceRecord]) -> String {
+    let mut csv = String::with_capacity(HEADER.len() + 1 + records.len() * 64);
+    csv.push_str(HEADER);
+    csv.push('\n');
+    for record in records {
+        let key = if record.key.contains([',', '"', '\r', '\n']) {
+            format!("\"{}\"", record.key.replace('"', "\"\""))
+        } else {
+            record.key.clone()
+        };
+        let allowed = record.allowed.map(|a| a.to_string()).unwrap_or_default();
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            record.timestamp_ms,
+            key,
+            record.max_burst,
+            record.count_per_period,
+            record.period,
+            record.quantity,
+            allowed
+        ));
+    }
+    csv
+}
+
+/// Parse a CSV trace, header included
+///
+/// # Errors
+///
+/// Returns an error naming the line if the header or a record is malformed.
+pub fn parse_csv(input: &str) -> Result<Vec<TraceRecord>> {
+    let mut rows = split_rows(input)?.into_iter();
+    match rows.next() {
+        Some((_, header)) if header.join(",") == HEADER => {}
+        _ => return Err(anyhow!("trace must start with the header: {HEADER}")),
+    }
+
+    rows.map(|(line, fields)| {
+        parse_record(fields).map_err(|e| anyhow!("invalid trace record on line {line}: {e}"))
+    })
+    .collect()
+}
+
+fn parse_record(fields: Vec<String>) -> Result<TraceRecord> {
+    let [
+        timestamp_ms,
+        key,
+        max_burst,
+        count_per_period,
+        period,
+        quantity,
+        allowed,
+    ]: [String; 7] = fields
+        .try_into()
+        .map_err(|fields: Vec<String>| anyhow!("expected 7 fields, got {}", fields.len()))?;
+    let allowed = match allowed.as_str() {
+        "" => None,
+        "true" => Some(true),
+        "false" => Some(false),
+        other => return Err(anyhow!("allowed must be true, false or empty, got {other}")),
+    };
+    Ok(TraceRecord {
+        timestamp_ms: timestamp_ms.parse()?,
+        key,
+        max_burst: max_burst.parse()?,
+        count_per_period: count_per_period.parse()?,
+        period: period.parse()?,
+        quantity: quantity.parse()?,
+        allowed,
+    })
+}
+
+/// Split CSV input into rows of fields, with the line each row starts on
+///
+/// Skips empty lines. Quoted fields may contain commas, doubled quotes and
+/// line breaks.
+fn split_rows(input: &str) -> Result<Vec<(usize, Vec<String>)>> {
+    let mut rows = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut row_line = 1;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => fields.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                if !fields.is_empty() || !field.is_empty() {
+                    fields.push(std::mem::take(&mut field));
+                    rows.push((row_line, std::mem::take(&mut fields)));
+                }
+                line += 1;
+                row_line = line;
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(anyhow!("unterminated quoted field on line {row_line}"));
+    }
+    if !fields.is_empty() || !field.is_empty() {
+        fields.push(field);
+        rows.push((row_line, fields));
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_trace_buffer_keeps_most_recent() {
+        let request = |key, at_ms| ThrottleRequest {
+            timestamp: UNIX_EPOCH + Duration::from_millis(at_ms),
+            ..ThrottleRequest::new(key, 10, 100, 60)
+        };
+        let buffer = TraceBuffer::new(2);
+        buffer.record(&request("a", 1), true);
+        buffer.record(&request("b", 2), false);
+        buffer.record(&request("c", 3), true);
+
+        let records = buffer.snapshot();
+        let keys: Vec<_> = records.iter().map(|r| r.key.as_str()).collect();
+        assert_eq!(keys, ["b", "c"]);
+        assert_eq!(records[0].timestamp_ms, 2);
+        assert_eq!(records[0].allowed, Some(false));
+
+        // Disabled buffers record nothing
+        let buffer = TraceBuffer::new(0);
+        buffer.record(&request("a", 1), true);
+        assert!(buffer.snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_trace_csv_roundtrip() {
+        let request = |key, at_ms| ThrottleRequest {
+            timestamp: UNIX_EPOCH + Duration::from_millis(at_ms),
+            ..ThrottleRequest::new(key, 10, 100, 60)
+        };
+        let mut records = vec![
+            TraceRecord::new(&request("user:123", 1_704_067_200_000), true),
+            TraceRecord::new(&request("tenant:\"a\",b\nc", 1_704_067_200_012), false),
+        ];
+        records[1].allowed = None;
+
+        let csv = to_csv(&records);
+        assert!(csv.starts_with(&format!(
+            "{HEADER}\n1704067200000,user:123,10,100,60,1,true\n"
+        )));
+        assert_eq!(parse_csv(&csv).unwrap(), records);
+
+        // Windows line endings and trailing blank lines are fine
+        let csv = format!("{HEADER}\r\n5,k,1,2,3,1,\r\n\r\n");
+        let parsed = parse_csv(&csv).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].timestamp_ms, 5);
+        assert_eq!(parsed[0].allowed, None);
+    }
+
+    #[test]
+    fn test_trace_csv_errors() {
+        assert!(parse_csv("1,k,1,2,3,1,true\n").is_err());
+        let error = parse_csv(&format!("{HEADER}\n1,k,1,2,3,1,true\n2,k,x,2,3,1,\n"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("line 3"), "{error}");
+        assert!(parse_csv(&format!("{HEADER}\n1,k,1,2,3\n")).is_err());
+        assert!(parse_csv(&format!("{HEADER}\n1,\"k,1,2,3,1,\n")).is_err());
+    }
+}
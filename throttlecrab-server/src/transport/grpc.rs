@@ -11,6 +11,13 @@
 //! ```protobuf
 //! service RateLimiter {
 //!     rpc Throttle(ThrottleRequest) returns (ThrottleResponse);
+//!     rpc Peek(ThrottleRequest) returns (ThrottleResponse);
+//!     rpc Reset(ResetRequest) returns (ResetResponse);
+//!     rpc ThrottleBatch(ThrottleBatchRequest) returns (ThrottleBatchResponse);
+//!     rpc ThrottleStream(stream ThrottleStreamRequest) returns (stream ThrottleStreamResponse);
+//!     rpc Acquire(AcquireRequest) returns (AcquireResponse);
+//!     rpc Release(ReleaseRequest) returns (ReleaseResponse);
+//!     rpc GetServerInfo(ServerInfoRequest) returns (ServerInfoResponse);
 //! }
 //! ```
 //!
@@ -23,6 +30,12 @@
 //!     int32 count_per_period = 3;  // Requests allowed per period
 //!     int32 period = 4;            // Period in seconds
 //!     int32 quantity = 5;          // Tokens to consume
+//!     bool retry_hints = 6;        // Also return retry_at_ms and retry_at
+//!     string policy = 7;           // Server-side policy, replacing 2-4
+//!     string algorithm = 8;        // Rate limiting algorithm
+//!     string operation = 9;        // Operation priced by the policy, replacing 5
+//!     string namespace = 10;       // Namespace of the key
+//!     repeated Window windows = 11; // Limits replacing 2-4, all applied
 //! }
 //! ```
 //!
@@ -35,16 +48,98 @@
 //!     int32 remaining = 3;    // Tokens remaining
 //!     int32 retry_after = 4;  // Seconds until retry
 //!     int32 reset_after = 5;  // Seconds until reset
+//!     int64 retry_after_ms = 6;  // Milliseconds until retry
+//!     int64 retry_at_ms = 7;     // Retry time, ms since the Unix epoch
+//!     string retry_at = 8;       // Retry time as an HTTP-date
+//!     int32 window = 9;          // Binding window of a multi-window request
+//!     int64 reset_after_ms = 10; // Milliseconds until reset
+//!     int64 cacheable_until_ms = 11; // Caching deadline, ms since the Unix epoch
+//!     bool warning = 12;         // Past the policy's soft limit
 //! }
 //! ```
 //!
+//! A client may allow up to `remaining` requests for the key itself until
+//! `cacheable_until_ms`, then must ask again; 0 means it may not cache the
+//! decision. See [`ThrottleResponse::with_cacheable_until`](crate::types::ThrottleResponse::with_cacheable_until).
+//!
+//! ## Multi-Window Limits
+//!
+//! A `ThrottleRequest` with `windows` is checked against every window and
+//! allowed only if all allow it (see [multi-window limits](crate::windows)).
+//! The response describes the window that bound the decision, whose index
+//! is `window`. Windows are not supported in `ThrottleBatch`.
+//!
+//! ## Peeking
+//!
+//! `Peek` takes a `ThrottleRequest` and answers like `Throttle` without
+//! consuming tokens or creating the key. With `quantity` 0 (the proto3
+//! default) the response describes the key as it is now.
+//!
+//! ## Resetting
+//!
+//! `Reset` clears a key's rate limit state, for remediation after a client
+//! was wrongly blocked, and reports whether the key existed. Resets are
+//! written to the audit log. Set `namespace` to reset a key in a
+//! [namespace](crate::namespace).
+//!
+//! ## Batches
+//!
+//! `ThrottleBatch` takes up to [`MAX_BATCH_SIZE`] `ThrottleRequest`s and
+//! returns one `ThrottleBatchResult` per request, in order: either a
+//! `ThrottleResponse` or, for a request that could not be checked, a
+//! `ThrottleError` with its code and message. An empty or oversized batch
+//! fails the whole call with `INVALID_ARGUMENT`.
+//!
+//! ## Streaming
+//!
+//! `ThrottleStream` lets a client pipeline checks over one HTTP/2 stream
+//! instead of paying for a unary call each. Every `ThrottleStreamRequest`
+//! carries a client-chosen `id` and is answered by a `ThrottleStreamResponse`
+//! with the same `id` holding either a `ThrottleResponse` or a
+//! `ThrottleError`. Up to [`MAX_STREAM_IN_FLIGHT`] checks per stream are
+//! processed concurrently, so responses may arrive out of order. API keys
+//! are checked once, when the stream is opened.
+//!
+//! ## Concurrency Leases
+//!
+//! `Acquire` takes a lease on `key` if it holds fewer than `max_concurrent`,
+//! capping operations in flight rather than their rate. A granted lease has
+//! a non-zero `lease_id`, to pass to `Release` once the operation is done;
+//! otherwise the lease expires after `ttl` seconds. A denied `Acquire` has
+//! `lease_id` 0 and `retry_after` set to the seconds until the oldest lease
+//! expires. Invalid requests fail with `INVALID_ARGUMENT`.
+//!
+//! ## Server Info and Reflection
+//!
+//! `GetServerInfo` returns the server and library versions, uptime, store
+//! type and a [`MetricsSnapshot`](throttlecrab_proto::MetricsSnapshot) of
+//! the request counters. The server also serves the
+//! [gRPC reflection](https://grpc.io/docs/guides/reflection/) API, so tools
+//! like `grpcurl` can call it without a copy of the `.proto` file:
+//!
+//! ```text
+//! grpcurl -plaintext 127.0.0.1:50051 throttlecrab.RateLimiter/GetServerInfo
+//! ```
+//!
+//! ## Load Shedding
+//!
+//! A `Throttle` [shed](crate::shed) by `--max-rps` gets the default decision
+//! with [`SHED_METADATA`] set to `true` in the response metadata.
+//!
+//! ## API Key Budgets
+//!
+//! A `Throttle` or `ThrottleBatch` over its API key's [budget](crate::budget)
+//! fails with `RESOURCE_EXHAUSTED`, message prefixed with
+//! `budget_exhausted`. A `ThrottleStream` check gets a `budget_exhausted`
+//! error in its result instead.
+//!
 //! # Features
 //!
 //! - **HTTP/2 Transport**: Multiplexing, server push, header compression
 //! - **Type Safety**: Strongly typed messages with code generation
 //! - **Service Mesh Ready**: Works with Istio, Linkerd, etc.
 //! - **Cross-Language**: Client libraries for many languages
-//! - **Streaming Support**: Built-in support for streaming (future enhancement)
+//! - **Streaming Support**: Pipelined checks over a bidirectional stream
 //!
 //! # Client Example
 //!
@@ -60,29 +155,70 @@
 //!     count_per_period: 100,
 //!     period: 60,
 //!     quantity: 1,
+//!     retry_hints: false,
+//!     policy: String::new(),
+//!     algorithm: String::new(),
+//!     operation: String::new(),
 //! });
 //!
 //! let response = client.throttle(request).await?;
 //! ```
 
-use crate::actor::RateLimiterHandle;
+use crate::actor::{OverloadedError, RateLimiterHandle, StoreFullError};
+use crate::budget::BudgetExhaustedError;
+use crate::logging::log_request;
 use crate::metrics::{Metrics, Transport as MetricsTransport};
-use crate::transport::Transport;
-use crate::types::ThrottleRequest as ActorRequest;
-use anyhow::Result;
+use crate::namespace::{InvalidNamespaceError, NamespaceFullError};
+use crate::otel;
+use crate::policy::{UnknownOperationError, UnknownPolicyError};
+use crate::transport::{Transport, bind_tcp, socket_addr};
+use crate::types::{
+    AcquireRequest as ActorAcquireRequest, AlgorithmKind, MAX_BATCH_SIZE, RetryHints,
+    ThrottleRequest as ActorRequest, ThrottleResponse as ActorResponse, ValidationError,
+};
+use crate::windows::{self, InvalidWindowsError};
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use std::time::SystemTime;
-use tonic::{Request, Response, Status, transport::Server};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{Semaphore, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::metadata::MetadataValue;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response, Status, Streaming, transport::Server};
+use tracing::Instrument;
 
 // Include the generated protobuf code
 pub mod throttlecrab_proto {
     tonic::include_proto!("throttlecrab");
+
+    /// Encoded descriptors of the throttlecrab protobuf package, for
+    /// [reflection](super::reflection_service)
+    pub const FILE_DESCRIPTOR_SET: &[u8] =
+        tonic::include_file_descriptor_set!("throttlecrab_descriptor");
 }
 
 use throttlecrab_proto::rate_limiter_server::{RateLimiter, RateLimiterServer};
-use throttlecrab_proto::{ThrottleRequest, ThrottleResponse};
+use throttlecrab_proto::throttle_batch_result::Result as BatchResult;
+use throttlecrab_proto::throttle_stream_response::Result as StreamResult;
+use throttlecrab_proto::{
+    AcquireRequest, AcquireResponse, MetricsSnapshot, ReleaseRequest, ReleaseResponse,
+    ResetRequest, ResetResponse, ServerInfoRequest, ServerInfoResponse, ThrottleBatchRequest,
+    ThrottleBatchResponse, ThrottleBatchResult, ThrottleError, ThrottleRequest, ThrottleResponse,
+    ThrottleStreamRequest, ThrottleStreamResponse,
+};
+
+/// Checks processed concurrently on one `ThrottleStream`
+///
+/// Once this many are waiting, the server stops reading the stream until a
+/// response is sent, pushing back on the client through HTTP/2 flow control.
+pub const MAX_STREAM_IN_FLIGHT: usize = 1024;
+
+/// Response metadata marking a `Throttle` answered by the
+/// [load shedder](crate::shed) instead of the store
+pub const SHED_METADATA: &str = "throttlecrab-shed";
 
 /// gRPC transport implementation
 ///
@@ -98,11 +234,11 @@ impl GrpcTransport {
     ///
     /// # Parameters
     ///
-    /// - `host`: The host address to bind to (e.g., "0.0.0.0")
+    /// - `host`: The IP address to bind to (e.g., "0.0.0.0" or "::")
     /// - `port`: The port number to listen on (typically 50051)
     /// - `metrics`: Shared metrics instance
     pub fn new(host: &str, port: u16, metrics: Arc<Metrics>) -> Self {
-        let addr = format!("{host}:{port}").parse().expect("Invalid address");
+        let addr = socket_addr(host, port).expect("Invalid address");
         Self { addr, metrics }
     }
 }
@@ -110,24 +246,42 @@ impl GrpcTransport {
 #[async_trait]
 impl Transport for GrpcTransport {
     async fn start(self, limiter: RateLimiterHandle) -> Result<()> {
-        let service = RateLimiterService {
-            limiter,
-            metrics: Arc::clone(&self.metrics),
-        };
-
+        let listener =
+            bind_tcp(self.addr).with_context(|| format!("Failed to bind to {}", self.addr))?;
         Server::builder()
-            .add_service(RateLimiterServer::new(service))
-            .serve(self.addr)
+            .add_service(service(limiter, self.metrics))
+            .add_service(reflection_service()?)
+            .serve_with_incoming(TcpIncoming::from(listener))
             .await?;
 
         Ok(())
     }
 }
 
+/// The rate limiting gRPC service, ready to add to a tonic server
+pub(crate) fn service(
+    limiter: RateLimiterHandle,
+    metrics: Arc<Metrics>,
+) -> RateLimiterServer<RateLimiterService> {
+    RateLimiterServer::new(RateLimiterService { limiter, metrics })
+}
+
+/// The gRPC reflection service, describing the rate limiting service
+pub(crate) fn reflection_service() -> Result<
+    tonic_reflection::server::v1::ServerReflectionServer<
+        impl tonic_reflection::server::v1::ServerReflection,
+    >,
+> {
+    Ok(tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(throttlecrab_proto::FILE_DESCRIPTOR_SET)
+        .build_v1()?)
+}
+
 /// gRPC service implementation for rate limiting
 ///
 /// This service handles incoming gRPC requests and forwards them
 /// to the rate limiter actor for processing.
+#[derive(Clone)]
 pub struct RateLimiterService {
     limiter: RateLimiterHandle,
     metrics: Arc<Metrics>,
@@ -135,6 +289,8 @@ pub struct RateLimiterService {
 
 #[tonic::async_trait]
 impl RateLimiter for RateLimiterService {
+    type ThrottleStreamStream = ReceiverStream<Result<ThrottleStreamResponse, Status>>;
+
     /// Handle a rate limit check request
     ///
     /// Validates the incoming request, forwards it to the rate limiter actor,
@@ -143,54 +299,549 @@ impl RateLimiter for RateLimiterService {
     /// # Errors
     ///
     /// Returns a gRPC `Status` error if:
+    /// - API keys are required and the request has none or an unknown one
+    ///   (`UNAUTHENTICATED`)
+    /// - The request is invalid (`INVALID_ARGUMENT`, message prefixed with the
+    ///   validation error code)
+    /// - The API key's [budget](crate::budget) is spent (`RESOURCE_EXHAUSTED`)
     /// - The rate limiter actor fails
     /// - Internal processing errors occur
     async fn throttle(
         &self,
         request: Request<ThrottleRequest>,
     ) -> Result<Response<ThrottleResponse>, Status> {
+        let api_key = self.authenticate(&request)?;
+
+        // Continue the caller's trace from the request metadata
+        let span = otel::request_span(MetricsTransport::Grpc, Some(request.metadata().as_ref()));
         let req = request.into_inner();
 
         // Use server timestamp
-        let timestamp = SystemTime::now();
-
-        // Convert to actor request
-        let actor_request = ActorRequest {
-            key: req.key.clone(),
-            max_burst: req.max_burst as i64,
-            count_per_period: req.count_per_period as i64,
-            period: req.period as i64,
+        let timestamp = self.limiter.now();
+
+        self.limiter
+            .charge(api_key.as_deref(), 1)
+            .map_err(|e| self.status(e.into()))?;
+        let actor_request = match self.actor_request(&req, api_key.as_deref(), timestamp) {
+            Ok(actor_request) => actor_request,
+            Err(e) => return Err(self.status(e)),
+        };
+
+        // Call the rate limiter
+        let started = Instant::now();
+        let windows = windows(&req);
+        let result = windows::throttle_or_shed(&self.limiter, actor_request, windows.as_deref())
+            .instrument(span)
+            .await;
+        let latency = started.elapsed();
+        self.metrics.record_latency(MetricsTransport::Grpc, latency);
+        match result {
+            Ok((result, window, shed)) => {
+                let mut response =
+                    self.response(&req, api_key.as_deref(), result, timestamp, latency);
+                response.window = window.unwrap_or(0) as i32;
+                let mut response = Response::new(response);
+                if shed {
+                    response
+                        .metadata_mut()
+                        .insert(SHED_METADATA, MetadataValue::from_static("true"));
+                }
+                Ok(response)
+            }
+            Err(e) => Err(self.status(e)),
+        }
+    }
+
+    /// Report a key's rate limit state without consuming tokens
+    ///
+    /// # Errors
+    ///
+    /// As for [`throttle`](Self::throttle).
+    async fn peek(
+        &self,
+        request: Request<ThrottleRequest>,
+    ) -> Result<Response<ThrottleResponse>, Status> {
+        let api_key = self.authenticate(&request)?;
+
+        let req = request.into_inner();
+        let timestamp = self.limiter.now();
+        let actor_request = match self.actor_request(&req, api_key.as_deref(), timestamp) {
+            Ok(actor_request) => actor_request,
+            Err(e) => return Err(self.status(e)),
+        };
+        match self.limiter.peek(actor_request).await {
+            Ok(result) => Ok(Response::new(grpc_response(&req, result, timestamp))),
+            Err(e) => Err(self.status(e)),
+        }
+    }
+
+    /// Clear a key's rate limit state
+    ///
+    /// # Errors
+    ///
+    /// Returns `UNAUTHENTICATED` as for [`throttle`](Self::throttle), and
+    /// `INTERNAL` if the rate limiter actor fails.
+    async fn reset(
+        &self,
+        request: Request<ResetRequest>,
+    ) -> Result<Response<ResetResponse>, Status> {
+        let api_key = self.authenticate(&request)?;
+
+        let req = request.into_inner();
+        let mut key = req.key.into();
+        let namespace = Some(req.namespace.as_str()).filter(|name| !name.is_empty());
+        if let Err(e) = self.limiter.scope(&mut key, namespace, api_key.as_deref()) {
+            return Err(self.status(e.into()));
+        }
+        match self.limiter.reset(key, MetricsTransport::Grpc).await {
+            Ok(report) => Ok(Response::new(ResetResponse {
+                existed: report.existed,
+                reset_at_ms: report.reset_at_ms,
+            })),
+            Err(e) => Err(Status::internal(format!("Rate limiter error: {e}"))),
+        }
+    }
+
+    /// Handle several rate limit checks in one call
+    ///
+    /// Requests that cannot be checked get a `ThrottleError` in their place
+    /// rather than failing the call.
+    ///
+    /// # Errors
+    ///
+    /// Returns a gRPC `Status` error if:
+    /// - API keys are required and the request has none or an unknown one
+    ///   (`UNAUTHENTICATED`)
+    /// - The batch is empty or larger than [`MAX_BATCH_SIZE`]
+    ///   (`INVALID_ARGUMENT`)
+    /// - The API key's [budget](crate::budget) cannot cover the batch
+    ///   (`RESOURCE_EXHAUSTED`)
+    /// - The rate limiter actor fails
+    async fn throttle_batch(
+        &self,
+        request: Request<ThrottleBatchRequest>,
+    ) -> Result<Response<ThrottleBatchResponse>, Status> {
+        let api_key = self.authenticate(&request)?;
+
+        let requests = request.into_inner().requests;
+        if requests.is_empty() || requests.len() > MAX_BATCH_SIZE {
+            return Err(Status::invalid_argument(format!(
+                "invalid_batch_size: a batch must hold between 1 and {MAX_BATCH_SIZE} requests, got {}",
+                requests.len()
+            )));
+        }
+        self.limiter
+            .charge(api_key.as_deref(), requests.len())
+            .map_err(|e| self.status(e.into()))?;
+
+        // Use server timestamp
+        let timestamp = self.limiter.now();
+
+        // Requests naming an unknown policy fail here; the rest go to the actor
+        let mut results: Vec<Option<Result<ActorResponse>>> = Vec::new();
+        let mut actor_requests = Vec::with_capacity(requests.len());
+        for req in &requests {
+            if !req.windows.is_empty() {
+                results.push(Some(Err(InvalidWindowsError::InBatch.into())));
+                continue;
+            }
+            match self.actor_request(req, api_key.as_deref(), timestamp) {
+                Ok(actor_request) => {
+                    results.push(None);
+                    actor_requests.push(actor_request);
+                }
+                Err(e) => results.push(Some(Err(e))),
+            }
+        }
+
+        let started = Instant::now();
+        let mut responses = self
+            .limiter
+            .throttle_batch(actor_requests)
+            .await
+            .map_err(|e| self.status(e))?
+            .into_iter();
+        let latency = started.elapsed();
+        self.metrics.record_latency(MetricsTransport::Grpc, latency);
+
+        let results = requests
+            .iter()
+            .zip(results)
+            .map(|(req, result)| {
+                let result = result
+                    .or_else(|| responses.next())
+                    .expect("one response per request");
+                let result = match result {
+                    Ok(result) => {
+                        let api_key = api_key.as_deref();
+                        BatchResult::Response(
+                            self.response(req, api_key, result, timestamp, latency),
+                        )
+                    }
+                    Err(e) => BatchResult::Error(self.batch_error(e)),
+                };
+                ThrottleBatchResult {
+                    result: Some(result),
+                }
+            })
+            .collect();
+        Ok(Response::new(ThrottleBatchResponse { results }))
+    }
+
+    /// Handle rate limit checks pipelined over a bidirectional stream
+    ///
+    /// Each request is answered with its `id`; requests that cannot be
+    /// checked get a `ThrottleError` rather than ending the stream. The
+    /// stream ends once the client has finished sending and every check
+    /// has been answered.
+    ///
+    /// # Errors
+    ///
+    /// Returns `UNAUTHENTICATED` if API keys are required and the stream was
+    /// opened without a known one.
+    async fn throttle_stream(
+        &self,
+        request: Request<Streaming<ThrottleStreamRequest>>,
+    ) -> Result<Response<Self::ThrottleStreamStream>, Status> {
+        let api_key = self.authenticate(&request)?;
+
+        let mut inbound = request.into_inner();
+        let (tx, rx) = mpsc::channel(MAX_STREAM_IN_FLIGHT);
+        let service = self.clone();
+        tokio::spawn(async move {
+            let in_flight = Arc::new(Semaphore::new(MAX_STREAM_IN_FLIGHT));
+            loop {
+                let message = match inbound.message().await {
+                    Ok(Some(message)) => message,
+                    Ok(None) => break,
+                    Err(status) => {
+                        tracing::debug!("gRPC throttle stream failed: {}", status);
+                        break;
+                    }
+                };
+                let permit = Arc::clone(&in_flight)
+                    .acquire_owned()
+                    .await
+                    .expect("the semaphore is never closed");
+                let service = service.clone();
+                let api_key = api_key.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let result = service
+                        .stream_result(message.request, api_key.as_deref())
+                        .await;
+                    let response = ThrottleStreamResponse {
+                        id: message.id,
+                        result: Some(result),
+                    };
+                    // The client may have gone away; nothing left to answer
+                    let _ = tx.send(Ok(response)).await;
+                    drop(permit);
+                });
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    /// Take a concurrency lease on a key
+    ///
+    /// # Errors
+    ///
+    /// Returns `UNAUTHENTICATED` as for [`throttle`](Self::throttle),
+    /// `INVALID_ARGUMENT` for an invalid request and `INTERNAL` if the rate
+    /// limiter actor fails.
+    async fn acquire(
+        &self,
+        request: Request<AcquireRequest>,
+    ) -> Result<Response<AcquireResponse>, Status> {
+        self.authenticate(&request)?;
+
+        let req = request.into_inner();
+        let actor_request = ActorAcquireRequest {
+            key: req.key.as_str().into(),
+            max_concurrent: req.max_concurrent as i64,
+            ttl: req.ttl as i64,
+            timestamp: self.limiter.now(),
+        };
+        match self.limiter.acquire(actor_request).await {
+            Ok(response) => Ok(Response::new(AcquireResponse {
+                acquired: response.acquired,
+                lease_id: response.lease_id.unwrap_or(0),
+                limit: response.limit as i32,
+                active: response.active as i32,
+                retry_after: response.retry_after as i32,
+            })),
+            Err(e) => Err(self.status(e)),
+        }
+    }
+
+    /// Give back a lease taken with [`acquire`](Self::acquire)
+    ///
+    /// # Errors
+    ///
+    /// Returns `UNAUTHENTICATED` as for [`throttle`](Self::throttle), and
+    /// `INTERNAL` if the rate limiter actor fails.
+    async fn release(
+        &self,
+        request: Request<ReleaseRequest>,
+    ) -> Result<Response<ReleaseResponse>, Status> {
+        self.authenticate(&request)?;
+
+        let req = request.into_inner();
+        match self.limiter.release(req.key.into(), req.lease_id).await {
+            Ok(released) => Ok(Response::new(ReleaseResponse { released })),
+            Err(e) => Err(Status::internal(format!("Rate limiter error: {e}"))),
+        }
+    }
+
+    /// Describe the server: versions, uptime, store type and counters
+    ///
+    /// # Errors
+    ///
+    /// Returns `UNAUTHENTICATED` as for [`throttle`](Self::throttle).
+    async fn get_server_info(
+        &self,
+        request: Request<ServerInfoRequest>,
+    ) -> Result<Response<ServerInfoResponse>, Status> {
+        self.authenticate(&request)?;
+
+        let metrics = &self.metrics;
+        let count = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        Ok(Response::new(ServerInfoResponse {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            library_version: throttlecrab::VERSION.to_string(),
+            uptime_secs: metrics.uptime_seconds(),
+            store_type: self
+                .limiter
+                .store_type()
+                .map(|store_type| store_type.name().to_string())
+                .unwrap_or_default(),
+            metrics: Some(MetricsSnapshot {
+                total_requests: count(&metrics.total_requests),
+                requests_allowed: count(&metrics.requests_allowed),
+                requests_denied: count(&metrics.requests_denied),
+                requests_errors: count(&metrics.requests_errors),
+                http_requests: count(&metrics.http_requests),
+                grpc_requests: count(&metrics.grpc_requests),
+                redis_requests: count(&metrics.redis_requests),
+                udp_requests: count(&metrics.udp_requests),
+            }),
+        }))
+    }
+}
+
+impl RateLimiterService {
+    /// Check the `authorization` metadata against the configured API keys
+    ///
+    /// Returns the name of the key, or None when no API keys are configured.
+    fn authenticate<T>(&self, request: &Request<T>) -> Result<Option<Arc<str>>, Status> {
+        let authorization = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok());
+        self.limiter
+            .authenticate(authorization)
+            .map_err(|e| Status::unauthenticated(format!("{}: {}", e.code(), e)))
+    }
+
+    /// The request to send to the actor, in its namespace and with
+    /// `req.policy` and `req.operation` applied
+    fn actor_request(
+        &self,
+        req: &ThrottleRequest,
+        api_key: Option<&str>,
+        timestamp: SystemTime,
+    ) -> Result<ActorRequest> {
+        if !req.windows.is_empty() && !req.policy.is_empty() {
+            return Err(InvalidWindowsError::WithPolicy.into());
+        }
+        let mut actor_request = ActorRequest {
             quantity: req.quantity as i64,
             timestamp,
+            algorithm: if req.algorithm.is_empty() {
+                AlgorithmKind::Gcra
+            } else {
+                req.algorithm.parse()?
+            },
+            ..ActorRequest::new(
+                req.key.as_str(),
+                req.max_burst as i64,
+                req.count_per_period as i64,
+                req.period as i64,
+            )
         };
 
-        // Call the rate limiter
-        let result = match self.limiter.throttle(actor_request).await {
-            Ok(result) => {
-                self.metrics.record_request_with_key(
-                    MetricsTransport::Grpc,
-                    result.allowed,
-                    &req.key,
-                );
+        self.limiter
+            .scope(&mut actor_request.key, namespace(req), api_key)?;
+
+        // A named policy replaces the parameters sent by the client
+        self.limiter.resolve(
+            &mut actor_request,
+            Some(req.policy.as_str()).filter(|name| !name.is_empty()),
+            Some(req.operation.as_str()).filter(|name| !name.is_empty()),
+        )?;
+        Ok(actor_request)
+    }
+
+    /// Record an allowed or denied request and convert it to the gRPC format
+    fn response(
+        &self,
+        req: &ThrottleRequest,
+        api_key: Option<&str>,
+        result: ActorResponse,
+        timestamp: SystemTime,
+        latency: Duration,
+    ) -> ThrottleResponse {
+        self.metrics
+            .record_request_with_key(MetricsTransport::Grpc, result.allowed, &req.key);
+        log_request(MetricsTransport::Grpc, &req.key, result.allowed, latency);
+        if let Some(namespace) = self.limiter.namespace(namespace(req), api_key) {
+            self.metrics
+                .record_namespace_request(namespace, result.allowed);
+        }
+        grpc_response(req, result, timestamp)
+    }
+
+    /// Check one request from a `ThrottleStream`
+    async fn stream_result(
+        &self,
+        req: Option<ThrottleRequest>,
+        api_key: Option<&str>,
+    ) -> StreamResult {
+        let Some(req) = req else {
+            self.metrics.record_error(MetricsTransport::Grpc);
+            return StreamResult::Error(ThrottleError {
+                code: "missing_request".to_string(),
+                message: "stream message carries no request".to_string(),
+            });
+        };
+        if let Err(e) = self.limiter.charge(api_key, 1) {
+            return StreamResult::Error(self.batch_error(e.into()));
+        }
+
+        // Use server timestamp
+        let timestamp = self.limiter.now();
+        let started = Instant::now();
+        let result = match self.actor_request(&req, api_key, timestamp) {
+            Ok(actor_request) => {
+                let windows = windows(&req);
+                let result =
+                    windows::throttle_or_shed(&self.limiter, actor_request, windows.as_deref())
+                        .await;
+                self.metrics
+                    .record_latency(MetricsTransport::Grpc, started.elapsed());
                 result
             }
-            Err(e) => {
-                self.metrics.record_error(MetricsTransport::Grpc);
-                return Err(Status::internal(format!("Rate limiter error: {e}")));
-            }
+            Err(e) => Err(e),
         };
+        match result {
+            Ok((result, window, _)) => {
+                let latency = started.elapsed();
+                let mut response = self.response(&req, api_key, result, timestamp, latency);
+                response.window = window.unwrap_or(0) as i32;
+                StreamResult::Response(response)
+            }
+            Err(e) => StreamResult::Error(self.batch_error(e)),
+        }
+    }
 
-        // Convert to gRPC response
-        let response = ThrottleResponse {
-            allowed: result.allowed,
-            limit: result.limit as i32,
-            remaining: result.remaining as i32,
-            retry_after: result.retry_after as i32,
-            reset_after: result.reset_after as i32,
+    /// Record a failed request in a batch or stream and describe it for its result
+    fn batch_error(&self, e: anyhow::Error) -> ThrottleError {
+        let status = self.status(e);
+        let message = status.message();
+        let (code, message) = match status.code() {
+            // Invalid requests carry their code as a message prefix
+            tonic::Code::InvalidArgument => message.split_once(": ").unwrap_or(("", message)),
+            tonic::Code::ResourceExhausted => message
+                .split_once(": ")
+                .filter(|(code, _)| matches!(*code, "namespace_full" | "budget_exhausted"))
+                .unwrap_or(("store_full", message)),
+            _ => ("internal", message),
         };
+        ThrottleError {
+            code: code.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    /// Record a failed request and map its error to a gRPC status
+    fn status(&self, e: anyhow::Error) -> Status {
+        self.metrics.record_error(MetricsTransport::Grpc);
+        if let Some(invalid) = e.downcast_ref::<ValidationError>() {
+            return Status::invalid_argument(format!("{}: {}", invalid.code(), invalid));
+        }
+        if let Some(unknown) = e.downcast_ref::<UnknownPolicyError>() {
+            return Status::invalid_argument(format!("{}: {}", unknown.code(), unknown));
+        }
+        if let Some(unknown) = e.downcast_ref::<UnknownOperationError>() {
+            return Status::invalid_argument(format!("{}: {}", unknown.code(), unknown));
+        }
+        if let Some(invalid) = e.downcast_ref::<InvalidWindowsError>() {
+            return Status::invalid_argument(format!("{}: {}", invalid.code(), invalid));
+        }
+        if let Some(invalid) = e.downcast_ref::<InvalidNamespaceError>() {
+            return Status::invalid_argument(format!("{}: {}", invalid.code(), invalid));
+        }
+        if let Some(exhausted) = e.downcast_ref::<BudgetExhaustedError>() {
+            return Status::resource_exhausted(format!("{}: {}", exhausted.code(), exhausted));
+        }
+        if let Some(full) = e.downcast_ref::<NamespaceFullError>() {
+            return Status::resource_exhausted(format!("{}: {}", full.code(), full));
+        }
+        if let Some(overloaded) = e.downcast_ref::<OverloadedError>() {
+            return Status::unavailable(format!("{}: {}", overloaded.code(), overloaded));
+        }
+        if e.downcast_ref::<StoreFullError>().is_some() {
+            return Status::resource_exhausted(e.to_string());
+        }
+        Status::internal(format!("Rate limiter error: {e}"))
+    }
+}
 
-        Ok(Response::new(response))
+/// The namespace `req` names, if any
+fn namespace(req: &ThrottleRequest) -> Option<&str> {
+    Some(req.namespace.as_str()).filter(|name| !name.is_empty())
+}
+
+/// The windows `req` carries, if any
+fn windows(req: &ThrottleRequest) -> Option<Vec<windows::Window>> {
+    (!req.windows.is_empty()).then(|| {
+        req.windows
+            .iter()
+            .map(|window| windows::Window {
+                max_burst: window.max_burst as i64,
+                count_per_period: window.count_per_period as i64,
+                period: window.period as i64,
+            })
+            .collect()
+    })
+}
+
+/// Convert a rate limit decision to the gRPC format
+fn grpc_response(
+    req: &ThrottleRequest,
+    result: ActorResponse,
+    timestamp: SystemTime,
+) -> ThrottleResponse {
+    let mut response = ThrottleResponse {
+        allowed: result.allowed,
+        limit: result.limit as i32,
+        remaining: result.remaining as i32,
+        retry_after: result.retry_after as i32,
+        reset_after: result.reset_after as i32,
+        retry_after_ms: result.retry_after_ms,
+        reset_after_ms: result.reset_after_ms,
+        cacheable_until_ms: result.cacheable_until_ms,
+        warning: result.warning,
+        ..Default::default()
+    };
+    if req.retry_hints {
+        let hints = RetryHints::new(&result, timestamp);
+        response.retry_at_ms = hints.retry_at_ms;
+        response.retry_at = hints.retry_at;
     }
+    response
 }
 
 #[cfg(test)]
@@ -231,6 +882,12 @@ mod tests {
             count_per_period: 20,
             period: 60,
             quantity: 1,
+            retry_hints: false,
+            policy: String::new(),
+            algorithm: String::new(),
+            operation: String::new(),
+            namespace: String::new(),
+            windows: vec![],
         });
 
         let response = client.throttle(request).await.unwrap();
@@ -276,6 +933,12 @@ mod tests {
                 count_per_period: 10,
                 period: 60,
                 quantity: 1,
+                retry_hints: false,
+                policy: String::new(),
+                algorithm: String::new(),
+                operation: String::new(),
+                namespace: String::new(),
+                windows: vec![],
             });
 
             let response = client.throttle(request).await.unwrap();
@@ -293,4 +956,295 @@ mod tests {
 
         assert_eq!(allowed_count, 5); // Should allow exactly the burst size
     }
+
+    #[tokio::test]
+    async fn test_grpc_invalid_request() {
+        let store = throttlecrab::PeriodicStore::builder()
+            .capacity(1000)
+            .cleanup_interval(std::time::Duration::from_secs(60))
+            .build();
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let limiter = RateLimiterActor::spawn_periodic(1000, store, Arc::clone(&metrics));
+        let transport = GrpcTransport::new("127.0.0.1", 9093, metrics);
+
+        tokio::spawn(async move {
+            transport.start(limiter).await.unwrap();
+        });
+
+        sleep(Duration::from_millis(100)).await;
+
+        let mut client = throttlecrab_proto::rate_limiter_client::RateLimiterClient::connect(
+            "http://127.0.0.1:9093",
+        )
+        .await
+        .unwrap();
+
+        let request = tonic::Request::new(ThrottleRequest {
+            key: "invalid_period".to_string(),
+            max_burst: 5,
+            count_per_period: 10,
+            period: 0,
+            quantity: 1,
+            retry_hints: false,
+            policy: String::new(),
+            algorithm: String::new(),
+            operation: String::new(),
+            namespace: String::new(),
+            windows: vec![],
+        });
+
+        let status = client.throttle(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(status.message().starts_with("invalid_period: "));
+    }
+
+    #[tokio::test]
+    async fn test_grpc_acquire_release() {
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let limiter = RateLimiterActor::spawn_periodic(
+            1000,
+            throttlecrab::PeriodicStore::new(),
+            Arc::clone(&metrics),
+        );
+        let transport = GrpcTransport::new("127.0.0.1", 9097, metrics);
+
+        tokio::spawn(async move {
+            transport.start(limiter).await.unwrap();
+        });
+
+        sleep(Duration::from_millis(100)).await;
+
+        let mut client = throttlecrab_proto::rate_limiter_client::RateLimiterClient::connect(
+            "http://127.0.0.1:9097",
+        )
+        .await
+        .unwrap();
+
+        let acquire = |ttl| AcquireRequest {
+            key: "jobs".to_string(),
+            max_concurrent: 1,
+            ttl,
+        };
+        let lease = client.acquire(acquire(30)).await.unwrap().into_inner();
+        assert!(lease.acquired);
+        assert_ne!(lease.lease_id, 0);
+
+        let denied = client.acquire(acquire(30)).await.unwrap().into_inner();
+        assert!(!denied.acquired);
+        assert_eq!(denied.lease_id, 0);
+        assert_eq!(denied.active, 1);
+        assert_eq!(denied.retry_after, 30);
+
+        let release = ReleaseRequest {
+            key: "jobs".to_string(),
+            lease_id: lease.lease_id,
+        };
+        let response = client.release(release.clone()).await.unwrap().into_inner();
+        assert!(response.released);
+        let response = client.release(release).await.unwrap().into_inner();
+        assert!(!response.released);
+
+        let status = client.acquire(acquire(0)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(status.message().starts_with("invalid_ttl: "));
+    }
+
+    #[tokio::test]
+    async fn test_grpc_throttle_batch() {
+        let store = throttlecrab::PeriodicStore::builder()
+            .capacity(1000)
+            .cleanup_interval(std::time::Duration::from_secs(60))
+            .build();
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let limiter = RateLimiterActor::spawn_periodic(1000, store, Arc::clone(&metrics));
+        let transport = GrpcTransport::new("127.0.0.1", 9095, metrics);
+
+        tokio::spawn(async move {
+            transport.start(limiter).await.unwrap();
+        });
+
+        sleep(Duration::from_millis(100)).await;
+
+        let mut client = throttlecrab_proto::rate_limiter_client::RateLimiterClient::connect(
+            "http://127.0.0.1:9095",
+        )
+        .await
+        .unwrap();
+
+        let request = |key: &str, period| ThrottleRequest {
+            key: key.to_string(),
+            max_burst: 2,
+            count_per_period: 10,
+            period,
+            quantity: 1,
+            retry_hints: false,
+            policy: String::new(),
+            algorithm: String::new(),
+            operation: String::new(),
+            namespace: String::new(),
+            windows: vec![],
+        };
+        let batch = ThrottleBatchRequest {
+            requests: vec![
+                request("a", 60),
+                request("b", 0),
+                request("a", 60),
+                request("a", 60),
+            ],
+        };
+
+        let results = client
+            .throttle_batch(batch)
+            .await
+            .unwrap()
+            .into_inner()
+            .results;
+        let allowed: Vec<_> = results
+            .iter()
+            .map(|result| match &result.result {
+                Some(BatchResult::Response(response)) => Ok(response.allowed),
+                Some(BatchResult::Error(error)) => Err(error.code.as_str()),
+                None => panic!("empty result"),
+            })
+            .collect();
+        assert_eq!(
+            allowed,
+            vec![Ok(true), Err("invalid_period"), Ok(true), Ok(false)]
+        );
+
+        let status = client
+            .throttle_batch(ThrottleBatchRequest::default())
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_grpc_throttle_stream() {
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let limiter = RateLimiterActor::spawn_periodic(
+            1000,
+            throttlecrab::PeriodicStore::new(),
+            Arc::clone(&metrics),
+        );
+        let transport = GrpcTransport::new("127.0.0.1", 9096, Arc::clone(&metrics));
+
+        tokio::spawn(async move {
+            transport.start(limiter).await.unwrap();
+        });
+
+        sleep(Duration::from_millis(100)).await;
+
+        let mut client = throttlecrab_proto::rate_limiter_client::RateLimiterClient::connect(
+            "http://127.0.0.1:9096",
+        )
+        .await
+        .unwrap();
+
+        let request = |id, key: &str, period| ThrottleStreamRequest {
+            id,
+            request: Some(ThrottleRequest {
+                key: key.to_string(),
+                max_burst: 2,
+                count_per_period: 10,
+                period,
+                quantity: 1,
+                retry_hints: false,
+                policy: String::new(),
+                algorithm: String::new(),
+                operation: String::new(),
+                namespace: String::new(),
+                windows: vec![],
+            }),
+        };
+        let requests = vec![
+            request(1, "a", 60),
+            request(2, "b", 0),
+            request(3, "a", 60),
+            request(4, "a", 60),
+            ThrottleStreamRequest {
+                id: 5,
+                request: None,
+            },
+        ];
+
+        let mut stream = client
+            .throttle_stream(tokio_stream::iter(requests))
+            .await
+            .unwrap()
+            .into_inner();
+        let mut results = Vec::new();
+        while let Some(response) = stream.message().await.unwrap() {
+            let result = match response.result {
+                Some(StreamResult::Response(response)) => Ok(response.allowed),
+                Some(StreamResult::Error(error)) => Err(error.code),
+                None => panic!("empty result"),
+            };
+            results.push((response.id, result));
+        }
+        results.sort_by_key(|(id, _)| *id);
+
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[1], (2, Err("invalid_period".to_string())));
+        assert_eq!(results[4], (5, Err("missing_request".to_string())));
+        // Checks on "a" run concurrently, but only its burst of 2 is allowed
+        let allowed = [0, 2, 3]
+            .iter()
+            .filter(|&&i| results[i].1 == Ok(true))
+            .count();
+        assert_eq!(allowed, 2);
+        assert_eq!(
+            metrics
+                .requests_errors
+                .load(std::sync::atomic::Ordering::Relaxed),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_grpc_server_info() {
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let limiter = RateLimiterActor::spawn_periodic(
+            1000,
+            throttlecrab::PeriodicStore::new(),
+            Arc::clone(&metrics),
+        )
+        .with_store_type(crate::config::StoreType::Periodic);
+        let transport = GrpcTransport::new("127.0.0.1", 9199, Arc::clone(&metrics));
+
+        tokio::spawn(async move {
+            transport.start(limiter).await.unwrap();
+        });
+
+        sleep(Duration::from_millis(100)).await;
+
+        let mut client = throttlecrab_proto::rate_limiter_client::RateLimiterClient::connect(
+            "http://127.0.0.1:9199",
+        )
+        .await
+        .unwrap();
+
+        let request = ThrottleRequest {
+            key: "info_key".to_string(),
+            max_burst: 10,
+            count_per_period: 20,
+            period: 60,
+            quantity: 1,
+            ..Default::default()
+        };
+        client.throttle(request).await.unwrap();
+
+        let info = client
+            .get_server_info(ServerInfoRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.library_version, throttlecrab::VERSION);
+        assert_eq!(info.store_type, "periodic");
+        let snapshot = info.metrics.unwrap();
+        assert_eq!(snapshot.total_requests, 1);
+        assert_eq!(snapshot.grpc_requests, 1);
+        assert_eq!(snapshot.requests_allowed, 1);
+    }
 }
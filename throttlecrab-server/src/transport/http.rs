@@ -3,6 +3,9 @@
 //! This transport provides a REST API with JSON payloads, making it easy
 //! to integrate with any programming language or tool that supports HTTP.
 //!
+//! With a certificate configured (`--http-tls-cert`) the same API is served
+//! over HTTPS; see [`tls`](super::tls).
+//!
 //! # API Endpoints
 //!
 //! ## POST /throttle
@@ -22,6 +25,21 @@
 //! ```
 //!
 //! - `quantity` is optional (defaults to 1)
+//! - `algorithm` is optional: `gcra` (default), `sliding_window`,
+//!   `fixed_window` or `leaky_bucket` (see [`AlgorithmKind`])
+//! - `policy` names a server-side [policy](crate::policy) to take
+//!   `max_burst`, `count_per_period`, `period` and `algorithm` from, which
+//!   may then be left out
+//! - `operation` names an entry in the policy's cost table, charged
+//!   instead of `quantity` (see [operation costs](crate::policy#operation-costs))
+//! - `retry_hints` is optional; set it to `true` to also get the retry
+//!   delay in milliseconds and as absolute times
+//! - `namespace` is optional and keeps the key apart from the same key in
+//!   other namespaces (see [namespaces](crate::namespace))
+//! - `windows` is optional: a list of `max_burst`, `count_per_period` and
+//!   `period` limits, replacing those fields, all of which must allow the
+//!   request (see [multi-window limits](crate::windows)). It cannot be
+//!   combined with `policy` or used in a batch.
 //!
 //! ### Response
 //!
@@ -30,46 +48,550 @@
 //!   "allowed": true,
 //!   "limit": 10,
 //!   "remaining": 9,
-//!   "reset_after": 60,
-//!   "retry_after": 0
+//!   "reset_after": 5,
+//!   "retry_after": 0,
+//!   "retry_after_ms": 0,
+//!   "reset_after_ms": 5400
+//! }
+//! ```
+//!
+//! `retry_after` is whole seconds rounded up, as in `Retry-After`, and
+//! `reset_after` whole seconds rounded down; `reset_after_ms` and
+//! `retry_after_ms` are the same delays in milliseconds, rounded up, for
+//! limits whose delays are under a second.
+//!
+//! The decision is also sent in headers, following the IETF RateLimit
+//! header fields draft, so a reverse proxy can act on it without parsing
+//! the body:
+//!
+//! ```text
+//! RateLimit-Limit: 10
+//! RateLimit-Remaining: 9
+//! RateLimit-Reset: 60
+//! ```
+//!
+//! A denied response adds `Retry-After` with the seconds to wait, rounded
+//! up. A request with `windows` also gets the index of the window the
+//! response describes as `window` in the body, and every window as
+//! `RateLimit-Policy: 10;w=1, 1000;w=3600` (`count_per_period` and `period`). The status is 200 either way, or 429 for a denied request with
+//! `--http-use-429`, and 503 for a request [shed](crate::shed) by
+//! `--max-rps`. A request over its API key's [budget](crate::budget) is
+//! refused with 429 and error code `budget_exhausted`.
+//!
+//! With `"retry_hints": true` the response also contains:
+//!
+//! ```json
+//! {
+//!   "retry_at_ms": 1704067200000,
+//!   "retry_at": "Mon, 01 Jan 2024 00:00:00 GMT"
 //! }
 //! ```
 //!
+//! ## GET /throttle/{key}
+//!
+//! Report a key's rate limit state without consuming tokens or creating
+//! the key. The parameters of `/throttle` go in the query string, e.g.
+//! `GET /throttle/user:123?max_burst=10&count_per_period=100&period=60` or
+//! `GET /throttle/user:123?policy=api`, and the response has the same
+//! fields and headers, always with status 200. `quantity` defaults to 0, so `remaining` is what the key has
+//! left now; pass a quantity to ask whether that many would be allowed.
+//! Percent-encode a `/` in the key as `%2F`.
+//!
+//! `namespace` in the query string picks the key's namespace, here and for
+//! `DELETE`.
+//!
+//! ## DELETE /throttle/{key}
+//!
+//! Clear a key's rate limit state, e.g. after a client was wrongly
+//! blocked. The reset is written to the audit log:
+//!
+//! ```json
+//! {"key": "user:123", "existed": true, "reset_at_ms": 1704067200000}
+//! ```
+//!
+//! ## POST /throttle/batch
+//!
+//! Check up to [`MAX_BATCH_SIZE`] rate limits in one round trip, each
+//! request shaped as for `/throttle`:
+//!
+//! ```json
+//! {"requests": [{"key": "user:123", "policy": "api"}, {"key": "user:456", "policy": "api"}]}
+//! ```
+//!
+//! Results come back in request order. A request that is invalid fails on
+//! its own, as an error object in its place, without failing the batch:
+//!
+//! ```json
+//! {"results": [
+//!   {"allowed": true, "limit": 10, "remaining": 9, "reset_after": 60, "retry_after": 0},
+//!   {"error": "unknown policy: ap", "code": "unknown_policy"}
+//! ]}
+//! ```
+//!
+//! An empty batch, or one larger than [`MAX_BATCH_SIZE`], is rejected with
+//! 400.
+//!
+//! ## POST /acquire
+//!
+//! Take a concurrency lease, capping how many operations on a key run at
+//! once rather than their rate:
+//!
+//! ```json
+//! {"key": "reports:org:42", "max_concurrent": 50, "ttl": 300}
+//! ```
+//!
+//! `ttl` is in seconds; a lease not released by then frees its slot on its
+//! own. The response carries the `lease_id` to release, or no `lease_id` if
+//! the key already holds `max_concurrent` leases:
+//!
+//! ```json
+//! {"acquired": true, "lease_id": 42, "limit": 50, "active": 12, "retry_after": 0}
+//! ```
+//!
+//! A denied lease has a `Retry-After` header with the seconds until the
+//! oldest lease expires, and status 429 with `--http-use-429`.
+//!
+//! ## POST /release
+//!
+//! Give back a lease once its operation is done:
+//!
+//! ```json
+//! {"key": "reports:org:42", "lease_id": 42}
+//! ```
+//!
+//! Responds `{"released": true}`, or `false` if the lease is unknown or has
+//! already expired.
+//!
+//! ## GET /events
+//!
+//! Stream [denial alerts](crate::denials) as server-sent events, one
+//! `denial` event per key crossing `--denial-threshold`:
+//!
+//! ```text
+//! event: denial
+//! data: {"key":"login:alice","namespace":null,"denials":100,"window":60,...}
+//! ```
+//!
+//! Requires an API key like `POST /throttle` when keys are configured.
+//! Returns 404 when denial alerts are disabled.
+//!
 //! ## GET /health
 //!
 //! Health check endpoint. Returns "OK" with 200 status.
+//!
+//! ## POST /admin/cleanup
+//!
+//! Remove expired entries from the store now. An optional `budget_ms`
+//! query parameter bounds how long the pass may scan, e.g.
+//! `POST /admin/cleanup?budget_ms=50`.
+//!
+//! ### Response
+//!
+//! ```json
+//! {
+//!   "scanned": 120000,
+//!   "removed": 45000,
+//!   "remaining": 75000,
+//!   "complete": true,
+//!   "duration_us": 8250,
+//!   "finished_at_ms": 1704067200000
+//! }
+//! ```
+//!
+//! ## GET /admin/cleanup/last
+//!
+//! Report of the most recent `/admin/cleanup` pass, or 404 if none has run.
+//!
+//! ## GET /admin/peaks
+//!
+//! High-water marks with the time each was reached (ms since the Unix epoch).
+//!
+//! ```json
+//! {
+//!   "requests_per_second": { "value": 48210, "at_ms": 1704067200000 },
+//!   "queue_depth": { "value": 312, "at_ms": 1704067200120 },
+//!   "store_keys": { "value": 95000, "at_ms": 1704067260000 }
+//! }
+//! ```
+//!
+//! ## POST /admin/peaks/reset
+//!
+//! Clear the high-water marks, returning their values before the reset.
+//!
+//! ## GET /admin/canary
+//!
+//! Agreement between the primary and canary stores, with the most recent
+//! divergent decisions, or 404 if no canary store is configured.
+//!
+//! ## GET /admin/memory
+//!
+//! Estimated memory usage by subsystem, in bytes. The store figures are
+//! recomputed at most every 10 seconds.
+//!
+//! ```json
+//! {
+//!   "total_bytes": 21233664,
+//!   "store_entries": 75000,
+//!   "store_bytes": 16515072,
+//!   "canary_store_bytes": 0,
+//!   "metrics_bytes": 2621440,
+//!   "connection_bytes": 4096,
+//!   "queue_bytes": 1920,
+//!   "trace_bytes": 2091136,
+//!   "computed_at_ms": 1704067200000
+//! }
+//! ```
+//!
+//! ## GET /admin/trace
+//!
+//! The most recent requests and their decisions as a CSV trace (see
+//! [`crate::trace`]), or 404 if `--trace-buffer-size` is 0.
+//!
+//! ## GET /admin/keys/hot
+//!
+//! The most requested keys with their request counts (see
+//! [`crate::hot_keys`]), or 404 unless `--hot-keys` is set. An optional
+//! `limit` query parameter caps the list (default 10).
+//!
+//! ```json
+//! [{"key": "user:123", "requests": 48210}, {"key": "user:456", "requests": 9120}]
+//! ```
+//!
+//! ## GET /admin/keys/top
+//!
+//! The top keys tracked by [metrics](crate::metrics) with their counts, or
+//! 404 if that list is disabled. `kind` picks the list: `denied` (the
+//! default, see `--max-denied-keys`), or `requested` and `allowed`, estimated
+//! from sampled requests (see `--top-keys`). An optional `limit` query
+//! parameter caps the list (default 10).
+//!
+//! ```json
+//! [{"key": "user:123", "count": 48200}, {"key": "user:456", "count": 9100}]
+//! ```
+//!
+//! ## GET /admin/keys/{key}
+//!
+//! The store's entry for a key, with its namespace prefix if it has one,
+//! or 404 if the store holds none:
+//!
+//! ```json
+//! {
+//!   "key": "user:123",
+//!   "tat_ns": 1704067260000000000,
+//!   "expires_at_ms": 1704067260000,
+//!   "expired": false
+//! }
+//! ```
+//!
+//! ## GET /admin/store
+//!
+//! Entry counts and estimated size of the store, computed fresh by walking
+//! every key:
+//!
+//! ```json
+//! {
+//!   "entries": 75000,
+//!   "expired_entries": 1200,
+//!   "bytes": 16515072,
+//!   "shard_entries": [37512, 37488],
+//!   "computed_at_ms": 1704067200000
+//! }
+//! ```
+//!
+//! ## POST /admin/reload
+//!
+//! Re-read the `--policies` file (see [`crate::policy`]) without dropping
+//! connections or rate limit state, like `SIGHUP`. Returns the number of
+//! policies loaded:
+//!
+//! ```json
+//! {"policies": 2, "reloaded_at_ms": 1704067200000}
+//! ```
+//!
+//! Responds 404 if no policy file is configured, and 422 with the parse
+//! error, keeping the running policies, if the file is invalid.
+//!
+//! ## GET /admin/overrides
+//!
+//! The key deny- and allow-lists (see [`crate::overrides`]):
+//!
+//! ```json
+//! {"deny": ["bot:*"], "allow": ["internal:*"]}
+//! ```
+//!
+//! On the `--admin-port` listener, `POST /admin/overrides` adds the
+//! patterns in a body of the same shape, either list optional, and `DELETE
+//! /admin/overrides` removes them. Both require the `--admin-token` as an
+//! `Authorization: Bearer` header (401 without it, 403 if none is set), as
+//! an allow-listed key skips every limit. They respond with the updated
+//! lists, after saving them to `--overrides-file` if set, or 422 for an
+//! empty pattern:
+//!
+//! ```bash
+//! curl -X POST http://localhost:9101/admin/overrides -d '{"deny": ["bot:*"]}' \
+//!   -H 'Content-Type: application/json' -H 'Authorization: Bearer s3cret'
+//! ```
+//!
+//! # Routes
+//!
+//! The paths above are the defaults. `--http-base-path` mounts every route
+//! under a prefix (e.g. `/ratelimit/v1/throttle`), and
+//! `--http-throttle-path`, `--http-metrics-path` and `--http-health-path`
+//! rename the main endpoints; the peek and batch endpoints follow the
+//! throttle path (`{throttle}/{key}` and `{throttle}/batch`) and the lease
+//! and admin routes keep their names under the base path.
+//!
+//! `--admin-port` serves the admin routes, and only those, on a separate
+//! listener at their default paths instead, so they can be kept off the
+//! network that reaches the throttle endpoints.
+//!
+//! # Connections
+//!
+//! HTTP/1.1 clients are served with keep-alive. With
+//! `--http-keep-alive-max-requests N` a connection is closed after its Nth
+//! response, which carries `Connection: close`, and with
+//! `--http-keep-alive-timeout SECS` once it has waited that long for its
+//! next request. `--http-compression` compresses `POST /throttle/batch`
+//! responses with gzip or deflate when the client's `Accept-Encoding` lists
+//! one, trading a little CPU for smaller replies to distant clients.
 
-use super::Transport;
-use crate::actor::RateLimiterHandle;
-use crate::metrics::{Metrics, Transport as MetricsTransport};
-use crate::types::{ThrottleRequest as InternalRequest, ThrottleResponse};
-use anyhow::Result;
+use super::tls::TlsListener;
+use super::{Transport, bind_tcp, socket_addr};
+use crate::actor::{OverloadedError, RateLimiterHandle, StoreFullError};
+use crate::auth;
+use crate::budget::BudgetExhaustedError;
+use crate::config::{HttpKeepAlive, HttpRoutes};
+use crate::logging::log_request;
+use crate::metrics::{Metrics, Peaks, TopKey, TopKeysKind, Transport as MetricsTransport};
+use crate::namespace::{InvalidNamespaceError, NamespaceFullError};
+use crate::otel;
+use crate::overrides::{KeyOverrides, OverrideLists};
+use crate::policy::{UnknownOperationError, UnknownPolicyError};
+use crate::trace;
+use crate::types::{
+    AcquireRequest, AcquireResponse, AlgorithmKind, CanaryReport, CleanupReport, HotKey, KeyState,
+    MAX_BATCH_SIZE, MemoryReport, ReloadReport, ResetReport, RetryHints, StoreStats,
+    ThrottleRequest as InternalRequest, ThrottleResponse, ValidationError,
+};
+use crate::windows::{self, InvalidWindowsError, Window};
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 use axum::{
     Router,
-    extract::State,
-    http::StatusCode,
-    response::Json,
+    extract::{Extension, Path, Query, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, Version, header},
+    middleware::{self, Next},
+    response::{
+        IntoResponse, Json, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
     routing::{get, post},
 };
+use hyper::body::Incoming;
+use hyper::service::service_fn;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
 use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::convert::Infallible;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tower::ServiceExt;
+use tower_http::compression::CompressionLayer;
+use tracing::Instrument;
 
 /// HTTP request format for rate limiting
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HttpThrottleRequest {
     /// The key to rate limit
-    pub key: String,
-    /// Maximum burst capacity
+    #[serde(deserialize_with = "deserialize_key")]
+    pub key: Arc<str>,
+    /// Maximum burst capacity (ignored with `policy`)
+    #[serde(default)]
     pub max_burst: i64,
-    /// Total requests allowed per period
+    /// Total requests allowed per period (ignored with `policy`)
+    #[serde(default)]
     pub count_per_period: i64,
-    /// Time period in seconds
+    /// Time period in seconds (ignored with `policy`)
+    #[serde(default)]
     pub period: i64,
     /// Number of tokens to consume (optional, defaults to 1)
     pub quantity: Option<i64>,
+    /// Rate limiting algorithm (optional, defaults to `gcra`; ignored with `policy`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<AlgorithmKind>,
+    /// Server-side policy supplying the rate limit parameters (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<String>,
+    /// Operation whose cost under `policy` replaces `quantity` (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operation: Option<String>,
+    /// Include [`RetryHints`] in the response (optional, defaults to false)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_hints: Option<bool>,
+    /// Namespace isolating the key from other clients' keys (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Limits that must all allow the request, replacing `max_burst`,
+    /// `count_per_period` and `period` (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub windows: Option<Vec<Window>>,
+}
+
+/// HTTP response format for rate limiting
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HttpThrottleResponse {
+    /// The rate limit decision
+    #[serde(flatten)]
+    pub response: ThrottleResponse,
+    /// Retry delay in additional formats, if requested
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub retry_hints: Option<RetryHints>,
+    /// Index of the window the decision describes, for requests with `windows`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window: Option<usize>,
+}
+
+/// Query parameters for `GET /throttle/{key}`
+///
+/// As for [`HttpThrottleRequest`], except that `quantity` defaults to 0.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HttpPeekParams {
+    /// Maximum burst capacity (ignored with `policy`)
+    #[serde(default)]
+    pub max_burst: i64,
+    /// Total requests allowed per period (ignored with `policy`)
+    #[serde(default)]
+    pub count_per_period: i64,
+    /// Time period in seconds (ignored with `policy`)
+    #[serde(default)]
+    pub period: i64,
+    /// Number of tokens the request would consume (optional, defaults to 0)
+    pub quantity: Option<i64>,
+    /// Rate limiting algorithm (optional, defaults to `gcra`; ignored with `policy`)
+    pub algorithm: Option<AlgorithmKind>,
+    /// Server-side policy supplying the rate limit parameters (optional)
+    pub policy: Option<String>,
+    /// Operation whose cost under `policy` replaces `quantity` (optional)
+    pub operation: Option<String>,
+    /// Include [`RetryHints`] in the response (optional, defaults to false)
+    pub retry_hints: Option<bool>,
+    /// Namespace the key is in (optional)
+    pub namespace: Option<String>,
+}
+
+/// Query parameters for `DELETE /throttle/{key}`
+#[derive(Debug, Deserialize)]
+pub struct HttpResetParams {
+    /// Namespace the key is in (optional)
+    pub namespace: Option<String>,
+}
+
+/// Name of the API key a request authenticated with, None without API keys
+#[derive(Clone)]
+struct ApiKey(Option<Arc<str>>);
+
+/// HTTP request format for `POST /throttle/batch`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HttpThrottleBatchRequest {
+    /// The rate limit requests, at most [`MAX_BATCH_SIZE`]
+    pub requests: Vec<HttpThrottleRequest>,
+}
+
+/// HTTP response format for `POST /throttle/batch`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HttpThrottleBatchResponse {
+    /// One result per request, in request order
+    pub results: Vec<HttpBatchResult>,
+}
+
+/// The outcome of one request in a batch
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum HttpBatchResult {
+    /// The rate limit decision
+    Response(HttpThrottleResponse),
+    /// Why the request could not be checked
+    Error(HttpErrorResponse),
+}
+
+/// HTTP request format for `POST /acquire`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HttpAcquireRequest {
+    /// The key whose concurrent operations are limited
+    #[serde(deserialize_with = "deserialize_key")]
+    pub key: Arc<str>,
+    /// Maximum leases held on the key at once
+    pub max_concurrent: i64,
+    /// Seconds until the lease expires if it is not released
+    pub ttl: i64,
+}
+
+/// HTTP request format for `POST /release`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HttpReleaseRequest {
+    /// The key the lease was taken on
+    pub key: String,
+    /// The lease returned by `POST /acquire`
+    pub lease_id: u64,
+}
+
+/// HTTP response format for `POST /release`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HttpReleaseResponse {
+    /// False if the lease was unknown or had already expired
+    pub released: bool,
+}
+
+/// Query parameters for `GET /admin/keys/hot`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HttpHotKeysParams {
+    /// Number of keys to list (optional, defaults to 10)
+    pub limit: Option<usize>,
+}
+
+/// Query parameters for `GET /admin/keys/top`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HttpTopKeysParams {
+    /// List to return (optional, defaults to denied)
+    pub kind: Option<TopKeysKind>,
+    /// Number of keys to list (optional, defaults to 10)
+    pub limit: Option<usize>,
+}
+
+/// Query parameters for `POST /admin/cleanup`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HttpCleanupParams {
+    /// Maximum time to spend scanning, in milliseconds (optional, unbounded)
+    pub budget_ms: Option<u64>,
+}
+
+/// Deserialize a key straight into an `Arc<str>`
+///
+/// Serde's own `Arc<str>` impl goes through a `Box<str>` and copies the key
+/// twice; borrowing from the request body copies it once.
+fn deserialize_key<'de, D>(deserializer: D) -> Result<Arc<str>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct KeyVisitor;
+
+    impl serde::de::Visitor<'_> for KeyVisitor {
+        type Value = Arc<str>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a string")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Arc<str>, E> {
+            Ok(Arc::from(value))
+        }
+    }
+
+    deserializer.deserialize_str(KeyVisitor)
 }
 
 /// Error response format
@@ -77,6 +599,9 @@ pub struct HttpThrottleRequest {
 pub struct HttpErrorResponse {
     /// Error message
     pub error: String,
+    /// Machine-readable error code, for invalid requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
 }
 
 /// HTTP transport implementation
@@ -85,79 +610,1036 @@ pub struct HttpErrorResponse {
 pub struct HttpTransport {
     addr: SocketAddr,
     metrics: Arc<Metrics>,
+    routes: HttpRoutes,
+    use_429: bool,
+    tls: Option<Arc<rustls::ServerConfig>>,
+    keep_alive: HttpKeepAlive,
+    compression: bool,
+    admin: bool,
 }
 
 impl HttpTransport {
     pub fn new(host: &str, port: u16, metrics: Arc<Metrics>) -> Self {
-        let addr = format!("{host}:{port}").parse().expect("Invalid address");
-        Self { addr, metrics }
+        let addr = socket_addr(host, port).expect("Invalid address");
+        Self {
+            addr,
+            metrics,
+            routes: HttpRoutes::default(),
+            use_429: false,
+            tls: None,
+            keep_alive: HttpKeepAlive::default(),
+            compression: false,
+            admin: true,
+        }
+    }
+
+    /// Mount the endpoints at `routes` instead of the default paths
+    pub fn with_routes(mut self, routes: HttpRoutes) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    /// Answer denied rate limit requests with 429 instead of 200
+    pub fn with_429(mut self, enabled: bool) -> Self {
+        self.use_429 = enabled;
+        self
+    }
+
+    /// Serve HTTPS, terminating TLS with `config`
+    ///
+    /// See [`tls::server_config`](super::tls::server_config) to build it from
+    /// PEM files.
+    pub fn with_tls(mut self, config: Arc<rustls::ServerConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// Close client connections as `keep_alive` says
+    pub fn with_keep_alive(mut self, keep_alive: HttpKeepAlive) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Compress batch responses for clients that accept gzip or deflate
+    pub fn with_compression(mut self, enabled: bool) -> Self {
+        self.compression = enabled;
+        self
+    }
+
+    /// Serve the `/admin` endpoints alongside the API (the default)
+    ///
+    /// Turned off when they have their own listener.
+    pub fn with_admin_routes(mut self, enabled: bool) -> Self {
+        self.admin = enabled;
+        self
     }
 }
 
 #[async_trait]
 impl Transport for HttpTransport {
     async fn start(self, limiter: RateLimiterHandle) -> Result<()> {
-        let metrics = Arc::clone(&self.metrics);
-        let app_state = Arc::new(AppState { limiter, metrics });
-
-        let app = Router::new()
-            .route("/throttle", post(handle_throttle))
-            .route("/health", get(|| async { "OK" }))
-            .route("/metrics", get(handle_metrics))
-            .with_state(app_state);
+        let app = router(
+            &self.routes,
+            self.use_429,
+            self.compression,
+            self.admin,
+            limiter,
+            Arc::clone(&self.metrics),
+        );
 
-        tracing::info!("HTTP server listening on {}", self.addr);
+        tracing::info!(
+            "{} server listening on {}{}",
+            if self.tls.is_some() { "HTTPS" } else { "HTTP" },
+            self.addr,
+            self.routes.base_path
+        );
 
-        let listener = tokio::net::TcpListener::bind(self.addr).await?;
-        axum::serve(listener, app).await?;
+        let listener =
+            bind_tcp(self.addr).with_context(|| format!("Failed to bind to {}", self.addr))?;
+        match self.tls {
+            Some(config) => {
+                serve(TlsListener::new(listener, config), app, self.keep_alive).await;
+            }
+            None => serve(listener, app, self.keep_alive).await,
+        }
 
         Ok(())
     }
 }
 
+/// Serve `app` to the clients `listener` accepts, closing their HTTP/1.1
+/// connections as `keep_alive` says
+///
+/// HTTP/2 clients (over TLS, or with prior knowledge) are served as well,
+/// without the keep-alive limits.
+async fn serve<L: axum::serve::Listener>(mut listener: L, app: Router, keep_alive: HttpKeepAlive) {
+    let mut builder = Builder::new(TokioExecutor::new());
+    // Waiting for the next request counts as reading its headers
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .header_read_timeout(keep_alive.idle_timeout);
+
+    loop {
+        let (io, _) = listener.accept().await;
+        let app = app.clone();
+        let served = Cell::new(0);
+        let service = service_fn(move |request: hyper::Request<Incoming>| {
+            served.set(served.get() + 1);
+            let last =
+                request.version() < Version::HTTP_2 && served.get() == keep_alive.max_requests;
+            let response = app.clone().oneshot(request);
+            async move {
+                let mut response = response.await?;
+                if last {
+                    response
+                        .headers_mut()
+                        .insert(header::CONNECTION, HeaderValue::from_static("close"));
+                }
+                Ok::<_, Infallible>(response)
+            }
+        });
+        let connection = builder
+            .serve_connection(TokioIo::new(io), service)
+            .into_owned();
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                tracing::debug!("HTTP connection closed: {}", e);
+            }
+        });
+    }
+}
+
+/// All HTTP endpoints, mounted at `routes`; the `/admin` ones only if `admin`
+pub(crate) fn router(
+    routes: &HttpRoutes,
+    use_429: bool,
+    compression: bool,
+    admin: bool,
+    limiter: RateLimiterHandle,
+    metrics: Arc<Metrics>,
+) -> Router {
+    let app_state = Arc::new(AppState {
+        limiter,
+        metrics,
+        use_429,
+    });
+    let require_api_key = middleware::from_fn_with_state(Arc::clone(&app_state), require_api_key);
+    let mut batch = post(handle_throttle_batch).layer(require_api_key.clone());
+    if compression {
+        // Only gzip and deflate are compiled in
+        batch = batch.layer(CompressionLayer::new());
+    }
+
+    let mut router = Router::new()
+        .route(
+            &routes.path(&routes.throttle),
+            post(handle_throttle).layer(require_api_key.clone()),
+        )
+        .route(
+            &routes.path(&format!("{}/{{key}}", routes.throttle)),
+            get(handle_peek)
+                .delete(handle_reset)
+                .layer(require_api_key.clone()),
+        )
+        .route(&routes.path(&format!("{}/batch", routes.throttle)), batch)
+        .route(
+            &routes.path("/acquire"),
+            post(handle_acquire).layer(require_api_key.clone()),
+        )
+        .route(
+            &routes.path("/release"),
+            post(handle_release).layer(require_api_key.clone()),
+        )
+        .route(
+            &routes.path("/events"),
+            get(handle_events).layer(require_api_key),
+        )
+        .route(&routes.path(&routes.health), get(|| async { "OK" }))
+        .route(&routes.path(&routes.metrics), get(handle_metrics));
+    if admin {
+        router = router.merge(admin_router(routes, &app_state));
+    }
+    router.with_state(app_state)
+}
+
+/// The `/admin` endpoints, mounted under the base path of `routes`
+///
+/// Like the throttle endpoints, they require an API key when keys are
+/// configured.
+fn admin_router(routes: &HttpRoutes, state: &Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route(&routes.path("/admin/cleanup"), post(handle_cleanup))
+        .route(
+            &routes.path("/admin/cleanup/last"),
+            get(handle_last_cleanup),
+        )
+        .route(&routes.path("/admin/peaks"), get(handle_peaks))
+        .route(&routes.path("/admin/peaks/reset"), post(handle_reset_peaks))
+        .route(&routes.path("/admin/canary"), get(handle_canary))
+        .route(&routes.path("/admin/memory"), get(handle_memory))
+        .route(&routes.path("/admin/trace"), get(handle_trace))
+        .route(&routes.path("/admin/reload"), post(handle_reload))
+        .route(&routes.path("/admin/keys/hot"), get(handle_hot_keys))
+        .route(&routes.path("/admin/keys/top"), get(handle_top_keys))
+        .route(&routes.path("/admin/keys/{key}"), get(handle_key_state))
+        .route(&routes.path("/admin/store"), get(handle_store_stats))
+        .route(&routes.path("/admin/overrides"), get(handle_overrides))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(state),
+            require_api_key,
+        ))
+}
+
+/// Serve only `GET /metrics` on `host:port`, for `--metrics-port`
+pub(crate) async fn serve_metrics(host: &str, port: u16, metrics: Arc<Metrics>) -> Result<()> {
+    let addr = socket_addr(host, port)?;
+    let app = Router::new().route(
+        "/metrics",
+        get(move || export_metrics(Arc::clone(&metrics))),
+    );
+
+    tracing::info!("Metrics listener on {}/metrics", addr);
+
+    let listener = bind_tcp(addr).with_context(|| format!("Failed to bind to {addr}"))?;
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+/// Serve only the `/admin` endpoints on `host:port`, for `--admin-port`
+///
+/// Only this listener changes key overrides, for clients presenting `token`.
+pub(crate) async fn serve_admin(
+    host: &str,
+    port: u16,
+    token: Option<Arc<str>>,
+    limiter: RateLimiterHandle,
+    metrics: Arc<Metrics>,
+) -> Result<()> {
+    let addr = socket_addr(host, port)?;
+    let state = Arc::new(AppState {
+        limiter,
+        metrics,
+        use_429: false,
+    });
+    let app = admin_router(&HttpRoutes::default(), &state)
+        .route(
+            "/admin/overrides",
+            post(handle_add_overrides)
+                .delete(handle_remove_overrides)
+                .layer(middleware::from_fn_with_state(token, require_admin_token)),
+        )
+        .with_state(state);
+
+    tracing::info!("Admin listener on {}/admin", addr);
+
+    let listener = bind_tcp(addr).with_context(|| format!("Failed to bind to {addr}"))?;
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
 struct AppState {
     limiter: RateLimiterHandle,
     metrics: Arc<Metrics>,
+    /// Answer denied requests with 429 instead of 200
+    use_429: bool,
+}
+
+/// Reject requests without a valid `Authorization: Bearer` API key
+///
+/// A no-op unless API keys are configured.
+async fn require_api_key(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    let name = match state.limiter.authenticate(authorization) {
+        Ok(name) => name,
+        Err(e) => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                Json(HttpErrorResponse {
+                    error: e.to_string(),
+                    code: Some(e.code().to_string()),
+                }),
+            )
+                .into_response();
+        }
+    };
+    request.extensions_mut().insert(ApiKey(name));
+    next.run(request).await
+}
+
+/// Reject requests without the admin `token` as an `Authorization: Bearer`
+/// header, or every request if there is none
+async fn require_admin_token(
+    State(token): State<Option<Arc<str>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(token) = token else {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(HttpErrorResponse {
+                error: "Changing key overrides requires --admin-token".to_string(),
+                code: None,
+            }),
+        )
+            .into_response();
+    };
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(auth::bearer_token)
+        .is_some_and(|secret| auth::constant_time_eq(secret.as_bytes(), token.as_bytes()));
+    if !authorized {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            Json(HttpErrorResponse {
+                error: "missing or invalid admin token".to_string(),
+                code: Some(auth::UnauthorizedError.code().to_string()),
+            }),
+        )
+            .into_response();
+    }
+    next.run(request).await
 }
 
 async fn handle_throttle(
     State(state): State<Arc<AppState>>,
+    Extension(ApiKey(api_key)): Extension<ApiKey>,
+    headers: HeaderMap,
     Json(req): Json<HttpThrottleRequest>,
-) -> Result<Json<ThrottleResponse>, (StatusCode, Json<HttpErrorResponse>)> {
+) -> Result<Response, (StatusCode, Json<HttpErrorResponse>)> {
     // Always use server timestamp
-    let timestamp = SystemTime::now();
+    let timestamp = state.limiter.now();
+    let api_key = api_key.as_deref();
+    state
+        .limiter
+        .charge(api_key, 1)
+        .map_err(|e| throttle_error(&state, e.into()))?;
 
-    let internal_req = InternalRequest {
-        key: req.key.clone(),
-        max_burst: req.max_burst,
-        count_per_period: req.count_per_period,
-        period: req.period,
-        quantity: req.quantity.unwrap_or(1),
-        timestamp,
+    let started = Instant::now();
+    let result = match internal_request(&state, &req, api_key, timestamp) {
+        Ok(internal_req) => {
+            let span = otel::request_span(MetricsTransport::Http, Some(&headers));
+            let result =
+                windows::throttle_or_shed(&state.limiter, internal_req, req.windows.as_deref())
+                    .instrument(span)
+                    .await;
+            state
+                .metrics
+                .record_latency(MetricsTransport::Http, started.elapsed());
+            result
+        }
+        Err(e) => Err(e),
     };
 
-    match state.limiter.throttle(internal_req).await {
-        Ok(response) => {
-            state.metrics.record_request_with_key(
-                MetricsTransport::Http,
-                response.allowed,
-                &req.key,
-            );
-            Ok(Json(response))
+    match result {
+        Ok((response, window, shed)) => {
+            let status = if shed {
+                StatusCode::SERVICE_UNAVAILABLE
+            } else if state.use_429 && !response.allowed {
+                StatusCode::TOO_MANY_REQUESTS
+            } else {
+                StatusCode::OK
+            };
+            let mut headers = rate_limit_headers(&response);
+            if let Some(windows) = &req.windows {
+                headers.insert(RATELIMIT_POLICY, rate_limit_policy(windows));
+            }
+            let latency = started.elapsed();
+            let mut body = http_response(&state, &req, api_key, response, timestamp, latency);
+            body.window = window;
+            Ok((status, headers, Json(body)).into_response())
         }
-        Err(e) => {
-            tracing::error!("Rate limiter error: {}", e);
-            state.metrics.record_error(MetricsTransport::Http);
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(HttpErrorResponse {
-                    error: format!("Internal server error: {e}"),
+        Err(e) => Err(throttle_error(&state, e)),
+    }
+}
+
+async fn handle_peek(
+    State(state): State<Arc<AppState>>,
+    Extension(ApiKey(api_key)): Extension<ApiKey>,
+    Path(key): Path<String>,
+    Query(params): Query<HttpPeekParams>,
+) -> Result<(HeaderMap, Json<HttpThrottleResponse>), (StatusCode, Json<HttpErrorResponse>)> {
+    let timestamp = state.limiter.now();
+    let req = HttpThrottleRequest {
+        key: key.into(),
+        max_burst: params.max_burst,
+        count_per_period: params.count_per_period,
+        period: params.period,
+        quantity: Some(params.quantity.unwrap_or(0)),
+        algorithm: params.algorithm,
+        policy: params.policy,
+        operation: params.operation,
+        retry_hints: params.retry_hints,
+        namespace: params.namespace,
+        windows: None,
+    };
+
+    let result = match internal_request(&state, &req, api_key.as_deref(), timestamp) {
+        Ok(internal_req) => state.limiter.peek(internal_req).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(response) => {
+            let retry_hints = req
+                .retry_hints
+                .unwrap_or(false)
+                .then(|| RetryHints::new(&response, timestamp));
+            Ok((
+                rate_limit_headers(&response),
+                Json(HttpThrottleResponse {
+                    response,
+                    retry_hints,
+                    window: None,
                 }),
             ))
         }
+        Err(e) => Err(throttle_error(&state, e)),
     }
 }
 
+async fn handle_reset(
+    State(state): State<Arc<AppState>>,
+    Extension(ApiKey(api_key)): Extension<ApiKey>,
+    Path(key): Path<String>,
+    Query(params): Query<HttpResetParams>,
+) -> Result<Json<ResetReport>, (StatusCode, Json<HttpErrorResponse>)> {
+    let mut scoped = key.as_str().into();
+    state
+        .limiter
+        .scope(&mut scoped, params.namespace.as_deref(), api_key.as_deref())
+        .map_err(|e| throttle_error(&state, e.into()))?;
+    let report = state
+        .limiter
+        .reset(scoped, MetricsTransport::Http)
+        .await
+        .map_err(internal_error)?;
+    // Report the key as the client named it, without its namespace
+    Ok(Json(ResetReport { key, ..report }))
+}
+
+async fn handle_throttle_batch(
+    State(state): State<Arc<AppState>>,
+    Extension(ApiKey(api_key)): Extension<ApiKey>,
+    Json(batch): Json<HttpThrottleBatchRequest>,
+) -> Result<Json<HttpThrottleBatchResponse>, (StatusCode, Json<HttpErrorResponse>)> {
+    if batch.requests.is_empty() || batch.requests.len() > MAX_BATCH_SIZE {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(HttpErrorResponse {
+                error: format!(
+                    "a batch must hold between 1 and {MAX_BATCH_SIZE} requests, got {}",
+                    batch.requests.len()
+                ),
+                code: Some("invalid_batch_size".to_string()),
+            }),
+        ));
+    }
+
+    // Always use server timestamp
+    let timestamp = state.limiter.now();
+    let api_key = api_key.as_deref();
+    state
+        .limiter
+        .charge(api_key, batch.requests.len())
+        .map_err(|e| throttle_error(&state, e.into()))?;
+
+    // Requests naming an unknown policy fail here; the rest go to the actor
+    let mut results: Vec<Option<Result<ThrottleResponse>>> = Vec::new();
+    let mut internal_reqs = Vec::with_capacity(batch.requests.len());
+    for req in &batch.requests {
+        if req.windows.is_some() {
+            results.push(Some(Err(InvalidWindowsError::InBatch.into())));
+            continue;
+        }
+        match internal_request(&state, req, api_key, timestamp) {
+            Ok(internal_req) => {
+                results.push(None);
+                internal_reqs.push(internal_req);
+            }
+            Err(e) => results.push(Some(Err(e))),
+        }
+    }
+
+    let started = Instant::now();
+    let mut responses = state
+        .limiter
+        .throttle_batch(internal_reqs)
+        .await
+        .map_err(|e| throttle_error(&state, e))?
+        .into_iter();
+    let latency = started.elapsed();
+    state
+        .metrics
+        .record_latency(MetricsTransport::Http, latency);
+
+    let results = batch
+        .requests
+        .iter()
+        .zip(results)
+        .map(|(req, result)| {
+            let result = result
+                .or_else(|| responses.next())
+                .expect("one response per request");
+            match result {
+                Ok(response) => HttpBatchResult::Response(http_response(
+                    &state, req, api_key, response, timestamp, latency,
+                )),
+                Err(e) => HttpBatchResult::Error(throttle_error(&state, e).1.0),
+            }
+        })
+        .collect();
+    Ok(Json(HttpThrottleBatchResponse { results }))
+}
+
+async fn handle_acquire(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<HttpAcquireRequest>,
+) -> Result<Response, (StatusCode, Json<HttpErrorResponse>)> {
+    let request = AcquireRequest {
+        key: req.key,
+        max_concurrent: req.max_concurrent,
+        ttl: req.ttl,
+        timestamp: state.limiter.now(),
+    };
+    let response: AcquireResponse = state
+        .limiter
+        .acquire(request)
+        .await
+        .map_err(|e| throttle_error(&state, e))?;
+
+    if response.acquired {
+        return Ok(Json(response).into_response());
+    }
+    let status = if state.use_429 {
+        StatusCode::TOO_MANY_REQUESTS
+    } else {
+        StatusCode::OK
+    };
+    let retry_after = HeaderValue::from(response.retry_after);
+    Ok((status, [(header::RETRY_AFTER, retry_after)], Json(response)).into_response())
+}
+
+async fn handle_release(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<HttpReleaseRequest>,
+) -> Result<Json<HttpReleaseResponse>, (StatusCode, Json<HttpErrorResponse>)> {
+    state
+        .limiter
+        .release(req.key.into(), req.lease_id)
+        .await
+        .map(|released| Json(HttpReleaseResponse { released }))
+        .map_err(internal_error)
+}
+
+/// The request to send to the actor, with `req.policy` and `req.operation`
+/// applied
+fn internal_request(
+    state: &AppState,
+    req: &HttpThrottleRequest,
+    api_key: Option<&str>,
+    timestamp: SystemTime,
+) -> Result<InternalRequest> {
+    if req.windows.is_some() && req.policy.is_some() {
+        return Err(InvalidWindowsError::WithPolicy.into());
+    }
+    let mut internal_req = InternalRequest {
+        quantity: req.quantity.unwrap_or(1),
+        timestamp,
+        algorithm: req.algorithm.unwrap_or_default(),
+        ..InternalRequest::new(
+            Arc::clone(&req.key),
+            req.max_burst,
+            req.count_per_period,
+            req.period,
+        )
+    };
+    state
+        .limiter
+        .scope(&mut internal_req.key, req.namespace.as_deref(), api_key)?;
+    state.limiter.resolve(
+        &mut internal_req,
+        req.policy.as_deref(),
+        req.operation.as_deref(),
+    )?;
+    Ok(internal_req)
+}
+
+/// Record an allowed or denied request and add the retry hints it asked for
+fn http_response(
+    state: &AppState,
+    req: &HttpThrottleRequest,
+    api_key: Option<&str>,
+    response: ThrottleResponse,
+    timestamp: SystemTime,
+    latency: Duration,
+) -> HttpThrottleResponse {
+    state
+        .metrics
+        .record_request_with_key(MetricsTransport::Http, response.allowed, &req.key);
+    log_request(MetricsTransport::Http, &req.key, response.allowed, latency);
+    if let Some(namespace) = state.limiter.namespace(req.namespace.as_deref(), api_key) {
+        state
+            .metrics
+            .record_namespace_request(namespace, response.allowed);
+    }
+    let retry_hints = req
+        .retry_hints
+        .unwrap_or(false)
+        .then(|| RetryHints::new(&response, timestamp));
+    HttpThrottleResponse {
+        response,
+        retry_hints,
+        window: None,
+    }
+}
+
+/// `RateLimit-Limit` header, from the IETF RateLimit header fields draft
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+
+/// `RateLimit-Remaining` header
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+
+/// `RateLimit-Reset` header
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// `RateLimit-Policy` header, listing the windows of a multi-window request
+const RATELIMIT_POLICY: HeaderName = HeaderName::from_static("ratelimit-policy");
+
+/// The decision as `RateLimit-*` headers, plus `Retry-After` if denied
+fn rate_limit_headers(response: &ThrottleResponse) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(RATELIMIT_LIMIT, HeaderValue::from(response.limit));
+    headers.insert(RATELIMIT_REMAINING, HeaderValue::from(response.remaining));
+    headers.insert(RATELIMIT_RESET, HeaderValue::from(response.reset_after));
+    if !response.allowed {
+        // Whole seconds, rounded up so a client never retries too early
+        let retry_after = (response.retry_after_ms + 999) / 1000;
+        headers.insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    }
+    headers
+}
+
+/// Each window as `count;w=period`, e.g. `10;w=1, 1000;w=3600`
+fn rate_limit_policy(windows: &[Window]) -> HeaderValue {
+    let policy = windows
+        .iter()
+        .map(|window| format!("{};w={}", window.count_per_period, window.period))
+        .collect::<Vec<_>>()
+        .join(", ");
+    HeaderValue::from_str(&policy).expect("window limits are numbers")
+}
+
+/// Record a failed request and map its error to a status and body
+fn throttle_error(state: &AppState, e: anyhow::Error) -> (StatusCode, Json<HttpErrorResponse>) {
+    state.metrics.record_error(MetricsTransport::Http);
+    if let Some(invalid) = e.downcast_ref::<ValidationError>() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(HttpErrorResponse {
+                error: invalid.to_string(),
+                code: Some(invalid.code().to_string()),
+            }),
+        );
+    }
+    if let Some(unknown) = e.downcast_ref::<UnknownPolicyError>() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(HttpErrorResponse {
+                error: unknown.to_string(),
+                code: Some(unknown.code().to_string()),
+            }),
+        );
+    }
+    if let Some(unknown) = e.downcast_ref::<UnknownOperationError>() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(HttpErrorResponse {
+                error: unknown.to_string(),
+                code: Some(unknown.code().to_string()),
+            }),
+        );
+    }
+    if let Some(invalid) = e.downcast_ref::<InvalidWindowsError>() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(HttpErrorResponse {
+                error: invalid.to_string(),
+                code: Some(invalid.code().to_string()),
+            }),
+        );
+    }
+    if let Some(invalid) = e.downcast_ref::<InvalidNamespaceError>() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(HttpErrorResponse {
+                error: invalid.to_string(),
+                code: Some(invalid.code().to_string()),
+            }),
+        );
+    }
+    if let Some(exhausted) = e.downcast_ref::<BudgetExhaustedError>() {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(HttpErrorResponse {
+                error: exhausted.to_string(),
+                code: Some(exhausted.code().to_string()),
+            }),
+        );
+    }
+    if let Some(full) = e.downcast_ref::<NamespaceFullError>() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(HttpErrorResponse {
+                error: full.to_string(),
+                code: Some(full.code().to_string()),
+            }),
+        );
+    }
+    if let Some(overloaded) = e.downcast_ref::<OverloadedError>() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(HttpErrorResponse {
+                error: overloaded.to_string(),
+                code: Some(overloaded.code().to_string()),
+            }),
+        );
+    }
+    tracing::error!("Rate limiter error: {}", e);
+    if e.downcast_ref::<StoreFullError>().is_some() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(HttpErrorResponse {
+                error: e.to_string(),
+                code: None,
+            }),
+        );
+    }
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(HttpErrorResponse {
+            error: format!("Internal server error: {e}"),
+            code: None,
+        }),
+    )
+}
+
+async fn handle_cleanup(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HttpCleanupParams>,
+) -> Result<Json<CleanupReport>, (StatusCode, Json<HttpErrorResponse>)> {
+    let budget = params.budget_ms.map(Duration::from_millis);
+    state
+        .limiter
+        .cleanup(budget)
+        .await
+        .map(Json)
+        .map_err(internal_error)
+}
+
+async fn handle_last_cleanup(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<CleanupReport>, (StatusCode, Json<HttpErrorResponse>)> {
+    match state.limiter.last_cleanup().await {
+        Ok(Some(report)) => Ok(Json(report)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(HttpErrorResponse {
+                error: "No cleanup has run yet".to_string(),
+                code: None,
+            }),
+        )),
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+async fn handle_peaks(State(state): State<Arc<AppState>>) -> Json<Peaks> {
+    Json(state.metrics.peaks())
+}
+
+async fn handle_reset_peaks(State(state): State<Arc<AppState>>) -> Json<Peaks> {
+    Json(state.metrics.reset_peaks())
+}
+
+async fn handle_canary(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<CanaryReport>, (StatusCode, Json<HttpErrorResponse>)> {
+    match state.limiter.canary_report().await {
+        Ok(Some(report)) => Ok(Json(report)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(HttpErrorResponse {
+                error: "No canary store is configured".to_string(),
+                code: None,
+            }),
+        )),
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+async fn handle_memory(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<MemoryReport>, (StatusCode, Json<HttpErrorResponse>)> {
+    state
+        .limiter
+        .memory_usage()
+        .await
+        .map(Json)
+        .map_err(internal_error)
+}
+
+async fn handle_trace(
+    State(state): State<Arc<AppState>>,
+) -> Result<([(header::HeaderName, &'static str); 1], String), (StatusCode, Json<HttpErrorResponse>)>
+{
+    match state.limiter.trace() {
+        Some(trace) => Ok((
+            [(header::CONTENT_TYPE, "text/csv")],
+            trace::to_csv(&trace.snapshot()),
+        )),
+        None => Err((
+            StatusCode::NOT_FOUND,
+            Json(HttpErrorResponse {
+                error: "Request tracing is disabled".to_string(),
+                code: None,
+            }),
+        )),
+    }
+}
+
+async fn handle_events(
+    State(state): State<Arc<AppState>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, Json<HttpErrorResponse>)>
+{
+    let Some(alerts) = state.limiter.denial_alerts() else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(HttpErrorResponse {
+                error: "Denial alerts are disabled".to_string(),
+                code: None,
+            }),
+        ));
+    };
+    let events = ReceiverStream::new(alerts.subscribe()).map(|alert| {
+        Ok(Event::default()
+            .event("denial")
+            .json_data(alert)
+            .expect("denial alerts serialize to JSON"))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+async fn handle_hot_keys(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HttpHotKeysParams>,
+) -> Result<Json<Vec<HotKey>>, (StatusCode, Json<HttpErrorResponse>)> {
+    match state.limiter.hot_keys(params.limit.unwrap_or(10)).await {
+        Ok(Some(keys)) => Ok(Json(keys)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(HttpErrorResponse {
+                error: "Hot key tracking is disabled".to_string(),
+                code: None,
+            }),
+        )),
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+async fn handle_top_keys(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HttpTopKeysParams>,
+) -> Result<Json<Vec<TopKey>>, (StatusCode, Json<HttpErrorResponse>)> {
+    let kind = params.kind.unwrap_or(TopKeysKind::Denied);
+    let metrics = Arc::clone(&state.metrics);
+    let top_keys = tokio::task::spawn_blocking(move || metrics.top_keys(kind))
+        .await
+        .map_err(|e| internal_error(e.into()))?;
+    match top_keys {
+        Some(mut keys) => {
+            keys.truncate(params.limit.unwrap_or(10));
+            Ok(Json(keys))
+        }
+        None => Err((
+            StatusCode::NOT_FOUND,
+            Json(HttpErrorResponse {
+                error: format!("Top {} key tracking is disabled", kind.name()),
+                code: None,
+            }),
+        )),
+    }
+}
+
+async fn handle_key_state(
+    State(state): State<Arc<AppState>>,
+    Path(key): Path<String>,
+) -> Result<Json<KeyState>, (StatusCode, Json<HttpErrorResponse>)> {
+    match state.limiter.key_state(Arc::from(key)).await {
+        Ok(Some(entry)) => Ok(Json(entry)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(HttpErrorResponse {
+                error: "Key not found".to_string(),
+                code: None,
+            }),
+        )),
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+async fn handle_store_stats(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<StoreStats>, (StatusCode, Json<HttpErrorResponse>)> {
+    state
+        .limiter
+        .store_stats()
+        .await
+        .map(Json)
+        .map_err(internal_error)
+}
+
+async fn handle_reload(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ReloadReport>, (StatusCode, Json<HttpErrorResponse>)> {
+    if state.limiter.policies().is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(HttpErrorResponse {
+                error: "No policy file is configured".to_string(),
+                code: None,
+            }),
+        ));
+    }
+    state.limiter.reload_policies().map(Json).map_err(|e| {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(HttpErrorResponse {
+                error: format!("{e:#}"),
+                code: None,
+            }),
+        )
+    })
+}
+
+async fn handle_overrides(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<OverrideLists>, (StatusCode, Json<HttpErrorResponse>)> {
+    Ok(Json(key_overrides(&state)?.lists()))
+}
+
+async fn handle_add_overrides(
+    State(state): State<Arc<AppState>>,
+    Json(added): Json<OverrideLists>,
+) -> Result<Json<OverrideLists>, (StatusCode, Json<HttpErrorResponse>)> {
+    let overrides = key_overrides(&state)?;
+    added.validate().map_err(|e| {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(HttpErrorResponse {
+                error: e.to_string(),
+                code: None,
+            }),
+        )
+    })?;
+    overrides.add(&added).map(Json).map_err(internal_error)
+}
+
+async fn handle_remove_overrides(
+    State(state): State<Arc<AppState>>,
+    Json(removed): Json<OverrideLists>,
+) -> Result<Json<OverrideLists>, (StatusCode, Json<HttpErrorResponse>)> {
+    key_overrides(&state)?
+        .remove(&removed)
+        .map(Json)
+        .map_err(internal_error)
+}
+
+/// The limiter's key overrides, or 404 if it has none
+fn key_overrides(
+    state: &AppState,
+) -> Result<&Arc<KeyOverrides>, (StatusCode, Json<HttpErrorResponse>)> {
+    state.limiter.key_overrides().ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(HttpErrorResponse {
+                error: "Key overrides are not enabled".to_string(),
+                code: None,
+            }),
+        )
+    })
+}
+
+fn internal_error(e: anyhow::Error) -> (StatusCode, Json<HttpErrorResponse>) {
+    tracing::error!("Rate limiter error: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(HttpErrorResponse {
+            error: format!("Internal server error: {e}"),
+            code: None,
+        }),
+    )
+}
+
 async fn handle_metrics(State(state): State<Arc<AppState>>) -> Result<String, StatusCode> {
-    Ok(state.metrics.export_prometheus())
+    export_metrics(Arc::clone(&state.metrics)).await
+}
+
+/// Export `metrics` on a blocking thread, which waits on the top keys
+/// aggregator
+async fn export_metrics(metrics: Arc<Metrics>) -> Result<String, StatusCode> {
+    tokio::task::spawn_blocking(move || metrics.export_prometheus())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
 }
@@ -1,7 +1,12 @@
 #[cfg(test)]
 mod tests {
-    use super::super::http::HttpThrottleRequest;
-    use crate::types::ThrottleResponse;
+    use super::super::http::{
+        HttpErrorResponse, HttpThrottleRequest, HttpThrottleResponse, serve_admin, serve_metrics,
+    };
+    use crate::metrics::{Metrics, TopKey, Transport as MetricsTransport};
+    use crate::types::{KeyState, RetryHints, StoreStats, ThrottleResponse};
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
 
     #[tokio::test]
     async fn test_http_transport_basic() {
@@ -9,11 +14,17 @@ mod tests {
 
         // Test request structure
         let request = HttpThrottleRequest {
-            key: "test_key".to_string(),
+            key: "test_key".into(),
             max_burst: 10,
             count_per_period: 20,
             period: 60,
             quantity: Some(1),
+            policy: None,
+            operation: None,
+            retry_hints: None,
+            namespace: None,
+            algorithm: None,
+            windows: None,
         };
 
         // Verify serialization works
@@ -35,6 +46,402 @@ mod tests {
         assert_eq!(response.remaining, 9);
     }
 
+    #[tokio::test]
+    async fn test_metrics_listener() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.record_request(MetricsTransport::Http, true);
+        tokio::spawn(serve_metrics("127.0.0.1", 9187, Arc::clone(&metrics)));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let body = reqwest::get("http://127.0.0.1:9187/metrics")
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(body.contains("throttlecrab_requests_total 1"));
+
+        // Nothing but metrics is served on the port
+        let response = reqwest::get("http://127.0.0.1:9187/health").await.unwrap();
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_admin_listener() {
+        let metrics = Arc::new(Metrics::builder().top_keys(10, 1).build());
+        let limiter = crate::actor::RateLimiterActor::spawn_periodic(
+            100,
+            throttlecrab::PeriodicStore::new(),
+            Arc::clone(&metrics),
+        );
+        let request = crate::types::ThrottleRequest::new("user:1", 10, 10, 60);
+        limiter.throttle(request).await.unwrap();
+        tokio::spawn(serve_admin(
+            "127.0.0.1",
+            9195,
+            None,
+            limiter,
+            Arc::clone(&metrics),
+        ));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let state: KeyState = reqwest::get("http://127.0.0.1:9195/admin/keys/user:1")
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(state.key, "user:1");
+        assert!(!state.expired);
+        let response = reqwest::get("http://127.0.0.1:9195/admin/keys/user:2")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+
+        let stats: StoreStats = reqwest::get("http://127.0.0.1:9195/admin/store")
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(stats.entries, 1);
+
+        // Hot keys aren't counted unless --hot-keys is set
+        let response = reqwest::get("http://127.0.0.1:9195/admin/keys/hot")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+
+        metrics.record_request_with_key(MetricsTransport::Http, true, "user:1");
+        let top: Vec<TopKey> = reqwest::get("http://127.0.0.1:9195/admin/keys/top?kind=allowed")
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!((top[0].key.as_str(), top[0].count), ("user:1", 1));
+        let top: Vec<TopKey> = reqwest::get("http://127.0.0.1:9195/admin/keys/top")
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(top.is_empty());
+
+        // Without an admin token the overrides can't be changed
+        let response = reqwest::Client::new()
+            .post("http://127.0.0.1:9195/admin/overrides")
+            .json(&serde_json::json!({"allow": ["*"]}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+
+        // Nothing but the admin routes is served on the port
+        let response = reqwest::get("http://127.0.0.1:9195/health").await.unwrap();
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_headers_and_429() {
+        use super::super::Transport;
+        use super::super::http::HttpTransport;
+        use crate::config::StoreConfig;
+
+        let metrics = Arc::new(Metrics::new());
+        let limiter =
+            crate::store::create_rate_limiter(&StoreConfig::default(), 100, Arc::clone(&metrics))
+                .await
+                .unwrap();
+        let transport = HttpTransport::new("127.0.0.1", 9192, metrics).with_429(true);
+        tokio::spawn(transport.start(limiter));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        let throttle = || {
+            client
+                .post("http://127.0.0.1:9192/throttle")
+                .json(&serde_json::json!({
+                    "key": "headers", "max_burst": 2, "count_per_period": 6, "period": 60
+                }))
+                .send()
+        };
+        let header = |response: &reqwest::Response, name: &str| {
+            response
+                .headers()
+                .get(name)
+                .map(|value| value.to_str().unwrap().to_string())
+        };
+
+        let response = throttle().await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(header(&response, "ratelimit-limit").as_deref(), Some("2"));
+        assert_eq!(
+            header(&response, "ratelimit-remaining").as_deref(),
+            Some("1")
+        );
+        assert!(header(&response, "ratelimit-reset").is_some());
+        assert_eq!(header(&response, "retry-after"), None);
+
+        assert_eq!(throttle().await.unwrap().status(), 200);
+        let response = throttle().await.unwrap();
+        assert_eq!(response.status(), 429);
+        assert_eq!(
+            header(&response, "ratelimit-remaining").as_deref(),
+            Some("0")
+        );
+        // One token every 10 seconds
+        assert_eq!(header(&response, "retry-after").as_deref(), Some("10"));
+        let body: HttpThrottleResponse = response.json().await.unwrap();
+        assert!(!body.response.allowed);
+
+        // Peeks report the same headers but are not errors
+        let response = client
+            .get("http://127.0.0.1:9192/throttle/headers?max_burst=2&count_per_period=6&period=60&quantity=1")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(header(&response, "retry-after").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_multi_window() {
+        use super::super::Transport;
+        use super::super::http::HttpTransport;
+        use crate::config::StoreConfig;
+
+        let metrics = Arc::new(Metrics::new());
+        let limiter =
+            crate::store::create_rate_limiter(&StoreConfig::default(), 100, Arc::clone(&metrics))
+                .await
+                .unwrap();
+        let transport = HttpTransport::new("127.0.0.1", 9197, metrics);
+        tokio::spawn(transport.start(limiter));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        let throttle = |body: serde_json::Value| {
+            client
+                .post("http://127.0.0.1:9197/throttle")
+                .json(&body)
+                .send()
+        };
+        let windows = serde_json::json!({
+            "key": "windows",
+            "windows": [
+                {"max_burst": 2, "count_per_period": 2, "period": 60},
+                {"max_burst": 5, "count_per_period": 100, "period": 3600}
+            ]
+        });
+
+        let response = throttle(windows.clone()).await.unwrap();
+        assert_eq!(response.headers()["ratelimit-policy"], "2;w=60, 100;w=3600");
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["allowed"], true);
+        assert_eq!(body["window"], 0);
+        assert_eq!(body["remaining"], 1);
+
+        throttle(windows.clone()).await.unwrap();
+        let body: serde_json::Value = throttle(windows.clone())
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body["allowed"], false);
+        assert_eq!(body["window"], 0);
+
+        // Plain requests carry no window
+        let body: serde_json::Value = throttle(serde_json::json!({
+            "key": "plain", "max_burst": 2, "count_per_period": 2, "period": 60
+        }))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+        assert!(body.get("window").is_none());
+
+        let mut with_policy = windows;
+        with_policy["policy"] = "free".into();
+        let response = throttle(with_policy).await.unwrap();
+        assert_eq!(response.status(), 400);
+        let body: HttpErrorResponse = response.json().await.unwrap();
+        assert_eq!(body.code.as_deref(), Some("invalid_windows"));
+    }
+
+    #[tokio::test]
+    async fn test_acquire_release() {
+        use super::super::Transport;
+        use super::super::http::{HttpReleaseResponse, HttpTransport};
+        use crate::config::StoreConfig;
+        use crate::types::AcquireResponse;
+
+        let metrics = Arc::new(Metrics::new());
+        let limiter =
+            crate::store::create_rate_limiter(&StoreConfig::default(), 100, Arc::clone(&metrics))
+                .await
+                .unwrap();
+        let transport = HttpTransport::new("127.0.0.1", 9193, metrics);
+        tokio::spawn(transport.start(limiter));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        let acquire = || {
+            client
+                .post("http://127.0.0.1:9193/acquire")
+                .json(&serde_json::json!({"key": "exports", "max_concurrent": 1, "ttl": 45}))
+                .send()
+        };
+
+        let response = acquire().await.unwrap();
+        assert_eq!(response.status(), 200);
+        let lease: AcquireResponse = response.json().await.unwrap();
+        assert!(lease.acquired);
+        let lease_id = lease.lease_id.unwrap();
+
+        let response = acquire().await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response
+                .headers()
+                .get("retry-after")
+                .and_then(|value| value.to_str().ok()),
+            Some("45")
+        );
+        let denied: AcquireResponse = response.json().await.unwrap();
+        assert!(!denied.acquired);
+        assert_eq!(denied.lease_id, None);
+
+        for released in [true, false] {
+            let response: HttpReleaseResponse = client
+                .post("http://127.0.0.1:9193/release")
+                .json(&serde_json::json!({"key": "exports", "lease_id": lease_id}))
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            assert_eq!(response.released, released);
+        }
+
+        let response = client
+            .post("http://127.0.0.1:9193/acquire")
+            .json(&serde_json::json!({"key": "exports", "max_concurrent": 0, "ttl": 45}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+        let error: HttpErrorResponse = response.json().await.unwrap();
+        assert_eq!(error.code.as_deref(), Some("invalid_max_concurrent"));
+    }
+
+    #[tokio::test]
+    async fn test_https_with_client_certificates() {
+        use super::super::Transport;
+        use super::super::http::HttpTransport;
+        use super::super::tls;
+        use crate::config::{StoreConfig, TlsConfig};
+        use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, IsCa, KeyPair};
+
+        // A CA signing both the server's and the client's certificate
+        let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = CertifiedIssuer::self_signed(ca_params, KeyPair::generate().unwrap()).unwrap();
+        let server_key = KeyPair::generate().unwrap();
+        let server_cert = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .signed_by(&server_key, &ca)
+            .unwrap();
+        let client_key = KeyPair::generate().unwrap();
+        let client_cert = CertificateParams::new(Vec::new())
+            .unwrap()
+            .signed_by(&client_key, &ca)
+            .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("throttlecrab-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+        std::fs::write(dir.join("server.pem"), server_cert.pem()).unwrap();
+        std::fs::write(dir.join("server.key"), server_key.serialize_pem()).unwrap();
+        let config = tls::server_config(&TlsConfig {
+            cert: dir.join("server.pem"),
+            key: dir.join("server.key"),
+            client_ca: Some(dir.join("ca.pem")),
+        })
+        .unwrap();
+
+        let metrics = Arc::new(Metrics::new());
+        let limiter =
+            crate::store::create_rate_limiter(&StoreConfig::default(), 100, Arc::clone(&metrics))
+                .await
+                .unwrap();
+        let transport = HttpTransport::new("127.0.0.1", 9188, metrics).with_tls(config);
+        tokio::spawn(transport.start(limiter));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let ca_cert = reqwest::Certificate::from_pem(ca.pem().as_bytes()).unwrap();
+        let identity = reqwest::Identity::from_pem(
+            format!("{}{}", client_cert.pem(), client_key.serialize_pem()).as_bytes(),
+        )
+        .unwrap();
+        let client = reqwest::Client::builder()
+            .add_root_certificate(ca_cert.clone())
+            .identity(identity)
+            .build()
+            .unwrap();
+        let response = client
+            .get("https://localhost:9188/health")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "OK");
+
+        // Clients without a certificate are turned away
+        let anonymous = reqwest::Client::builder()
+            .add_root_certificate(ca_cert)
+            .build()
+            .unwrap();
+        assert!(
+            anonymous
+                .get("https://localhost:9188/health")
+                .send()
+                .await
+                .is_err()
+        );
+
+        // A key that doesn't match the certificate fails at startup
+        std::fs::write(dir.join("other.key"), client_key.serialize_pem()).unwrap();
+        assert!(
+            tls::server_config(&TlsConfig {
+                cert: dir.join("server.pem"),
+                key: dir.join("other.key"),
+                client_ca: None,
+            })
+            .is_err()
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_http_request_with_policy() {
+        // The rate limit parameters may be left out when naming a policy
+        let request: HttpThrottleRequest =
+            serde_json::from_str(r#"{"key": "user:123", "policy": "login"}"#).unwrap();
+        assert_eq!(request.policy.as_deref(), Some("login"));
+        assert_eq!(request.max_burst, 0);
+        assert_eq!(request.quantity, None);
+        assert_eq!(request.operation, None);
+
+        let request: HttpThrottleRequest =
+            serde_json::from_str(r#"{"key": "org:42", "policy": "api", "operation": "export"}"#)
+                .unwrap();
+        assert_eq!(request.operation.as_deref(), Some("export"));
+    }
+
     #[tokio::test]
     async fn test_http_request_validation() {
         // Test that quantity defaults to 1 if not provided
@@ -47,5 +454,134 @@ mod tests {
 
         let request: HttpThrottleRequest = serde_json::from_str(request_json).unwrap();
         assert_eq!(request.quantity, None);
+        assert_eq!(request.retry_hints, None);
+    }
+
+    #[test]
+    fn test_http_response_retry_hints() {
+        let response = ThrottleResponse {
+            allowed: false,
+            limit: 10,
+            remaining: 0,
+            reset_after: 60,
+            retry_after: 1,
+            retry_after_ms: 1_500,
+            reset_after_ms: 60_000,
+            cacheable_until_ms: 0,
+            warning: false,
+        };
+        let now = UNIX_EPOCH + Duration::from_secs(1_704_067_200);
+
+        // Hints are omitted unless requested; milliseconds never are
+        let json = serde_json::to_value(HttpThrottleResponse {
+            response: response.clone(),
+            retry_hints: None,
+            window: None,
+        })
+        .unwrap();
+        assert_eq!(json["retry_after_ms"], 1_500);
+        assert_eq!(json["reset_after_ms"], 60_000);
+        assert!(json.get("retry_at").is_none());
+
+        let json = serde_json::to_value(HttpThrottleResponse {
+            retry_hints: Some(RetryHints::new(&response, now)),
+            response,
+            window: None,
+        })
+        .unwrap();
+        assert_eq!(json["retry_after"], 1);
+        assert_eq!(json["retry_after_ms"], 1_500);
+        assert_eq!(json["retry_at_ms"], 1_704_067_201_500_i64);
+        assert_eq!(json["retry_at"], "Mon, 01 Jan 2024 00:00:02 GMT");
+    }
+
+    #[test]
+    fn test_http_error_response_code() {
+        // Only validation errors carry a code
+        let json = serde_json::to_value(HttpErrorResponse {
+            error: "Internal server error".to_string(),
+            code: None,
+        })
+        .unwrap();
+        assert!(json.get("code").is_none());
+
+        let json = serde_json::to_value(HttpErrorResponse {
+            error: "period must be positive, got 0".to_string(),
+            code: Some("invalid_period".to_string()),
+        })
+        .unwrap();
+        assert_eq!(json["code"], "invalid_period");
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_and_compression() {
+        use super::super::Transport;
+        use super::super::http::HttpTransport;
+        use crate::config::{HttpKeepAlive, StoreConfig};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpStream;
+
+        let metrics = Arc::new(Metrics::new());
+        let limiter =
+            crate::store::create_rate_limiter(&StoreConfig::default(), 100, Arc::clone(&metrics))
+                .await
+                .unwrap();
+        let transport = HttpTransport::new("127.0.0.1", 9186, metrics)
+            .with_keep_alive(HttpKeepAlive {
+                max_requests: 2,
+                idle_timeout: Some(Duration::from_secs(1)),
+            })
+            .with_compression(true);
+        tokio::spawn(transport.start(limiter));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let body = serde_json::json!({
+            "requests": [
+                {"key": "a", "max_burst": 10, "count_per_period": 20, "period": 60},
+                {"key": "b", "max_burst": 10, "count_per_period": 20, "period": 60}
+            ]
+        })
+        .to_string();
+        let mut stream = TcpStream::connect("127.0.0.1:9186").await.unwrap();
+        let requests = format!(
+            "POST /throttle/batch HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}\
+             GET /health HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\n\r\n",
+            body.len()
+        );
+        stream.write_all(requests.as_bytes()).await.unwrap();
+
+        // The second response is the last on the connection
+        let mut responses = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut responses))
+            .await
+            .unwrap()
+            .unwrap();
+        let responses = String::from_utf8_lossy(&responses);
+        let (batch, health) = responses
+            .split_once("HTTP/1.1 200 OK\r\n")
+            .unwrap()
+            .1
+            .split_once("HTTP/1.1 200 OK\r\n")
+            .unwrap();
+        assert!(batch.contains("content-encoding: gzip"), "{batch}");
+        assert!(!batch.contains("connection: close"), "{batch}");
+        // Only batch responses are compressed
+        assert!(!health.contains("content-encoding"), "{health}");
+        assert!(health.contains("connection: close"), "{health}");
+        assert!(health.ends_with("OK"), "{health}");
+
+        // A connection waiting for its next request is closed after the timeout
+        let mut idle = TcpStream::connect("127.0.0.1:9186").await.unwrap();
+        idle.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = [0; 512];
+        let read = idle.read(&mut response).await.unwrap();
+        assert!(response[..read].ends_with(b"OK"));
+        let read = tokio::time::timeout(Duration::from_secs(5), idle.read(&mut response))
+            .await
+            .unwrap();
+        assert_eq!(read.unwrap(), 0);
     }
 }
@@ -9,10 +9,25 @@
 //! - [`http`]: REST API with JSON (easy integration)
 //! - [`grpc`]: Protocol Buffers over HTTP/2 (service mesh friendly)
 //! - [`redis`]: Redis protocol for native Redis client support
+//! - [`mux`]: All of the above on a single port, detected per connection
+//! - [`udp`]: JSON datagrams, for fire-and-forget checks without connections
+//!
+//! [`tls`] terminates TLS for the HTTP transport.
+//!
+//! # Listen Addresses
+//!
+//! Every transport takes its host as an IP address, IPv6 ones with or
+//! without brackets, e.g. `0.0.0.0`, `::` or `[::1]`. Bound to the IPv6
+//! wildcard `::`, a TCP or UDP listener also accepts IPv4 clients (as
+//! IPv4-mapped addresses), whatever the system's `bindv6only` default, so
+//! one `--bind-any` listener serves both stacks in a container.
 
 pub mod grpc;
 pub mod http;
+pub mod mux;
 pub mod redis;
+pub mod tls;
+pub mod udp;
 
 #[cfg(test)]
 mod http_test;
@@ -24,8 +39,12 @@ mod redis_test;
 mod redis_security_test;
 
 use crate::actor::RateLimiterHandle;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use tokio::net::{TcpListener, UdpSocket};
 
 /// Common interface for all transport implementations
 ///
@@ -46,3 +65,93 @@ pub trait Transport {
     /// The method runs indefinitely until an error occurs or the server shuts down.
     async fn start(self, limiter: RateLimiterHandle) -> Result<()>;
 }
+
+/// The address to listen on at `host` and `port`
+///
+/// # Errors
+///
+/// Returns an error if `host` is not an IP address.
+pub fn socket_addr(host: &str, port: u16) -> Result<SocketAddr> {
+    let ip = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    let ip: IpAddr = ip
+        .parse()
+        .with_context(|| format!("Invalid address: {host}:{port}"))?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// Bind a TCP listener to `addr`, dual-stack if it is the IPv6 wildcard
+pub(crate) fn bind_tcp(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = socket(addr, Type::STREAM, Protocol::TCP)?;
+    // As std and tokio do, so restarts don't wait out TIME_WAIT
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Bind a UDP socket to `addr`, dual-stack if it is the IPv6 wildcard
+pub(crate) fn bind_udp(addr: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = socket(addr, Type::DGRAM, Protocol::UDP)?;
+    socket.bind(&addr.into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+fn socket(addr: SocketAddr, kind: Type, protocol: Protocol) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(addr), kind, Some(protocol))?;
+    if addr.is_ipv6() && addr.ip().is_unspecified() {
+        socket.set_only_v6(false)?;
+    }
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv6Addr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_socket_addr() {
+        assert_eq!(
+            socket_addr("0.0.0.0", 8080).unwrap(),
+            "0.0.0.0:8080".parse().unwrap()
+        );
+        let any = SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 8080);
+        assert_eq!(socket_addr("::", 8080).unwrap(), any);
+        assert_eq!(socket_addr("[::]", 8080).unwrap(), any);
+        assert_eq!(
+            socket_addr("[::1]", 8080).unwrap().to_string(),
+            "[::1]:8080"
+        );
+        assert!(socket_addr("localhost", 8080).is_err());
+        assert!(socket_addr("[::", 8080).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dual_stack() {
+        let listener = bind_tcp(socket_addr("::", 0).unwrap()).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // An IPv4 client reaches the IPv6 wildcard listener
+        let mut client = tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        let socket = bind_udp(socket_addr("::", 0).unwrap()).unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(b"ping", ("127.0.0.1", port)).await.unwrap();
+        let (n, _) = socket.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"ping");
+    }
+}
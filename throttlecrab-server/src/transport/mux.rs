@@ -0,0 +1,378 @@
+//! Multiplexed transport: HTTP, gRPC and Redis on a single port
+//!
+//! Small deployments often want to open one port instead of three. The
+//! multiplexed listener accepts every connection itself, peeks at the first
+//! bytes without consuming them and hands the socket to the matching
+//! protocol handler:
+//!
+//! - The HTTP/2 connection preface (`PRI * HTTP/2.0...`): gRPC
+//! - An HTTP/1.1 request line (`GET `, `POST `, ...): HTTP
+//! - Anything else, i.e. a RESP array or an inline command: Redis
+//!
+//! Clients of all three protocols speak first, so detection adds no round
+//! trip. Connections that send nothing within [`DETECT_TIMEOUT`] are closed.
+//!
+//! The handlers are the same as those of the dedicated transports, with the
+//! same HTTP routes, Redis password and Redis command pacing.
+
+use super::{Transport, bind_tcp, grpc, http, redis, socket_addr};
+use crate::actor::RateLimiterHandle;
+use crate::config::HttpRoutes;
+use crate::metrics::Metrics;
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, error, info};
+
+/// How long a new connection may take to send enough bytes to detect its protocol
+pub const DETECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Detected connections waiting for the HTTP or gRPC server to pick them up
+const HANDOFF_QUEUE: usize = 1024;
+
+/// Protocol spoken on a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Protocol {
+    Http,
+    Grpc,
+    Redis,
+}
+
+/// Opening bytes of the protocols other than Redis
+///
+/// Redis has no fixed opening (inline commands are free text), so it is
+/// what remains once none of these can match.
+const SIGNATURES: &[(&[u8], Protocol)] = &[
+    (b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n", Protocol::Grpc),
+    (b"GET ", Protocol::Http),
+    (b"POST ", Protocol::Http),
+    (b"PUT ", Protocol::Http),
+    (b"HEAD ", Protocol::Http),
+    (b"DELETE ", Protocol::Http),
+    (b"OPTIONS ", Protocol::Http),
+    (b"PATCH ", Protocol::Http),
+];
+
+/// Longest signature, and so the most bytes detection needs
+const MAX_SIGNATURE_LEN: usize = 24;
+
+/// Detect the protocol from the first bytes of a connection
+///
+/// Returns `None` while `bytes` is a strict prefix of some signature and
+/// more bytes are needed to decide.
+fn detect(bytes: &[u8]) -> Option<Protocol> {
+    let mut incomplete = false;
+    for (signature, protocol) in SIGNATURES {
+        let n = bytes.len().min(signature.len());
+        if bytes[..n] == signature[..n] {
+            if n == signature.len() {
+                return Some(*protocol);
+            }
+            incomplete = true;
+        }
+    }
+    if incomplete {
+        None
+    } else {
+        Some(Protocol::Redis)
+    }
+}
+
+/// Wait for enough of the connection's opening bytes to detect its protocol
+async fn sniff(socket: &TcpStream) -> Result<Protocol> {
+    let mut buf = [0; MAX_SIGNATURE_LEN];
+    let sniffing = async {
+        loop {
+            let n = socket.peek(&mut buf).await?;
+            if n == 0 {
+                return Err(anyhow!("connection closed before sending a request"));
+            }
+            if let Some(protocol) = detect(&buf[..n]) {
+                return Ok(protocol);
+            }
+            // `peek` returns at once while the partial bytes are buffered;
+            // clients send the opening in one segment, so this rarely loops
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    };
+    timeout(DETECT_TIMEOUT, sniffing)
+        .await
+        .map_err(|_| anyhow!("no request within {DETECT_TIMEOUT:?}"))?
+}
+
+/// Feeds detected HTTP connections to `axum::serve`
+struct HandoffListener {
+    connections: mpsc::Receiver<(TcpStream, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl axum::serve::Listener for HandoffListener {
+    type Io = TcpStream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (TcpStream, SocketAddr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            // The accept loop is gone; the transport is shutting down
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// Multiplexed transport implementation
+///
+/// Serves HTTP, gRPC and Redis clients on one port.
+pub struct MuxTransport {
+    addr: SocketAddr,
+    metrics: Arc<Metrics>,
+    routes: HttpRoutes,
+    use_429: bool,
+    password: Option<Arc<str>>,
+    max_commands_per_second: u32,
+    admin: bool,
+}
+
+impl MuxTransport {
+    pub fn new(host: &str, port: u16, metrics: Arc<Metrics>) -> Result<Self> {
+        let addr = socket_addr(host, port)?;
+        Ok(Self {
+            addr,
+            metrics,
+            routes: HttpRoutes::default(),
+            use_429: false,
+            password: None,
+            max_commands_per_second: 0,
+            admin: true,
+        })
+    }
+
+    /// Mount the HTTP endpoints at `routes` instead of the default paths
+    pub fn with_routes(mut self, routes: HttpRoutes) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    /// Answer denied HTTP rate limit requests with 429 instead of 200
+    pub fn with_429(mut self, enabled: bool) -> Self {
+        self.use_429 = enabled;
+        self
+    }
+
+    /// Serve the HTTP `/admin` endpoints as well (the default)
+    pub fn with_admin_routes(mut self, enabled: bool) -> Self {
+        self.admin = enabled;
+        self
+    }
+
+    /// Require Redis clients to `AUTH` with `password` before other commands
+    ///
+    /// `None` disables authentication.
+    pub fn with_password(mut self, password: Option<String>) -> Self {
+        self.password = password.map(Arc::from);
+        self
+    }
+
+    /// Limit each Redis connection to `count` commands per second (0 for unlimited)
+    pub fn with_max_commands_per_second(mut self, count: u32) -> Self {
+        self.max_commands_per_second = count;
+        self
+    }
+}
+
+#[async_trait]
+impl Transport for MuxTransport {
+    async fn start(self, limiter: RateLimiterHandle) -> Result<()> {
+        let listener =
+            bind_tcp(self.addr).with_context(|| format!("Failed to bind to {}", self.addr))?;
+        let local_addr = listener.local_addr()?;
+
+        info!(
+            "Multiplexed transport (HTTP, gRPC, Redis) listening on {}",
+            local_addr
+        );
+
+        let (http_tx, http_rx) = mpsc::channel(HANDOFF_QUEUE);
+        let (grpc_tx, grpc_rx) = mpsc::channel::<io::Result<TcpStream>>(HANDOFF_QUEUE);
+
+        let mut servers = JoinSet::new();
+        let app = http::router(
+            &self.routes,
+            self.use_429,
+            false,
+            self.admin,
+            limiter.clone(),
+            Arc::clone(&self.metrics),
+        );
+        servers.spawn(async move {
+            let listener = HandoffListener {
+                connections: http_rx,
+                local_addr,
+            };
+            axum::serve(listener, app)
+                .await
+                .map_err(anyhow::Error::from)
+        });
+        let service = grpc::service(limiter.clone(), Arc::clone(&self.metrics));
+        let reflection = grpc::reflection_service()?;
+        servers.spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(service)
+                .add_service(reflection)
+                .serve_with_incoming(ReceiverStream::new(grpc_rx))
+                .await
+                .map_err(anyhow::Error::from)
+        });
+
+        loop {
+            let (socket, addr) = tokio::select! {
+                accepted = listener.accept() => accepted?,
+                Some(stopped) = servers.join_next() => {
+                    stopped??;
+                    return Err(anyhow!("Multiplexed transport handler stopped"));
+                }
+            };
+            let http_tx = http_tx.clone();
+            let grpc_tx = grpc_tx.clone();
+            let limiter = limiter.clone();
+            let metrics = Arc::clone(&self.metrics);
+            let password = self.password.clone();
+            let max_commands_per_second = self.max_commands_per_second;
+
+            tokio::spawn(async move {
+                let protocol = match sniff(&socket).await {
+                    Ok(protocol) => protocol,
+                    Err(e) => {
+                        debug!("Closing connection from {}: {}", addr, e);
+                        return;
+                    }
+                };
+                debug!("Connection from {} detected as {:?}", addr, protocol);
+                match protocol {
+                    Protocol::Http => {
+                        let _ = http_tx.send((socket, addr)).await;
+                    }
+                    Protocol::Grpc => {
+                        let _ = grpc_tx.send(Ok(socket)).await;
+                    }
+                    Protocol::Redis => {
+                        let pacer = redis::CommandPacer::new(max_commands_per_second);
+                        if let Err(e) = redis::handle_connection(
+                            socket, addr, limiter, metrics, password, pacer,
+                        )
+                        .await
+                        {
+                            error!("Error handling Redis connection from {}: {}", addr, e);
+                        }
+                    }
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor::RateLimiterActor;
+    use crate::transport::grpc::throttlecrab_proto;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::time::sleep;
+
+    #[tokio::test]
+    async fn test_mux_serves_all_protocols() {
+        let metrics = Arc::new(Metrics::new());
+        let store = throttlecrab::PeriodicStore::builder()
+            .capacity(1000)
+            .cleanup_interval(Duration::from_secs(60))
+            .build();
+        let limiter = RateLimiterActor::spawn_periodic(1000, store, Arc::clone(&metrics));
+        let transport = MuxTransport::new("127.0.0.1", 9094, Arc::clone(&metrics)).unwrap();
+        tokio::spawn(async move {
+            transport.start(limiter).await.unwrap();
+        });
+        sleep(Duration::from_millis(100)).await;
+
+        // HTTP/1.1
+        let response = reqwest::Client::new()
+            .post("http://127.0.0.1:9094/throttle")
+            .json(&serde_json::json!({
+                "key": "shared",
+                "max_burst": 10,
+                "count_per_period": 20,
+                "period": 60
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        // gRPC over HTTP/2
+        let mut client = throttlecrab_proto::rate_limiter_client::RateLimiterClient::connect(
+            "http://127.0.0.1:9094",
+        )
+        .await
+        .unwrap();
+        let response = client
+            .throttle(throttlecrab_proto::ThrottleRequest {
+                key: "shared".to_string(),
+                max_burst: 10,
+                count_per_period: 20,
+                period: 60,
+                quantity: 1,
+                retry_hints: false,
+                policy: String::new(),
+                algorithm: String::new(),
+                operation: String::new(),
+                namespace: String::new(),
+                windows: vec![],
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.remaining, 8);
+
+        // RESP, then an inline command on the same connection
+        let mut socket = TcpStream::connect("127.0.0.1:9094").await.unwrap();
+        socket.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+        let mut buf = [0; 64];
+        let n = socket.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"+PONG\r\n");
+
+        let mut socket = TcpStream::connect("127.0.0.1:9094").await.unwrap();
+        socket
+            .write_all(b"THROTTLE shared 10 20 60\r\n")
+            .await
+            .unwrap();
+        let n = socket.read(&mut buf).await.unwrap();
+        assert!(buf[..n].starts_with(b"*9\r\n:1\r\n:10\r\n:7\r\n"));
+    }
+
+    #[test]
+    fn test_detect_protocol() {
+        assert_eq!(
+            detect(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"),
+            Some(Protocol::Grpc)
+        );
+        assert_eq!(detect(b"POST /throttle HTTP/1.1\r\n"), Some(Protocol::Http));
+        assert_eq!(detect(b"GET /health HTTP/1.1\r\n"), Some(Protocol::Http));
+        assert_eq!(detect(b"*5\r\n$8\r\nTHROTTLE\r\n"), Some(Protocol::Redis));
+        assert_eq!(detect(b"THROTTLE key 10 100 60\r\n"), Some(Protocol::Redis));
+        assert_eq!(detect(b"PING\r\n"), Some(Protocol::Redis));
+
+        // Too short to tell HTTP/2 from POST, PUT and PATCH
+        assert_eq!(detect(b"P"), None);
+        assert_eq!(detect(b"PRI * HTTP"), None);
+    }
+}
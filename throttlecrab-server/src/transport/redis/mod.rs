@@ -5,14 +5,84 @@
 //!
 //! # Protocol
 //!
-//! Implements RESP (Redis Serialization Protocol) for communication.
+//! Implements RESP (Redis Serialization Protocol) for communication, plus
+//! inline commands so `redis-cli` and netcat work for manual testing:
+//!
+//! ```bash
+//! printf 'THROTTLE user:123 10 100 60\r\n' | nc localhost 6379
+//! ```
+//!
+//! Malformed input gets an `ERR Protocol error` reply and the connection
+//! is closed.
 //!
 //! # Supported Commands
 //!
 //! - `THROTTLE key max_burst count_per_period period [quantity]` - Check rate limit
+//! - `THROTTLE key POLICY name [quantity]` - Check rate limit with a server-side
+//!   [policy](crate::policy)
+//! - `THROTTLE key POLICY name OPERATION operation` - Check rate limit with a
+//!   policy, charging its [cost](crate::policy#operation-costs) for
+//!   `operation`
+//! - `THROTTLE key [quantity]` - Check rate limit with the policy whose
+//!   [key patterns](crate::policy#key-patterns) match `key`
+//! - `THROTTLE.PEEK key max_burst count_per_period period [quantity]` and
+//!   `THROTTLE.PEEK key POLICY name [quantity | OPERATION operation]` -
+//!   Report what `THROTTLE` would return without consuming tokens; `quantity` defaults to 0, giving
+//!   the key's current state. Peeks are not counted as requests.
+//! - `THROTTLE.RESET key` - Clear a key's state, e.g. after a false-positive
+//!   block; replies 1 if the key existed, else 0. Resets are audit logged
+//!   and not counted as requests.
+//! - `CL.THROTTLE key max_burst count_per_period period [quantity]` - Check
+//!   rate limit with [redis-cell](https://github.com/brandur/redis-cell)'s
+//!   arguments and reply, for existing redis-cell clients (see below)
+//! - `ACQUIRE key max_concurrent ttl` - Take a concurrency lease expiring
+//!   after `ttl` seconds, if `key` holds fewer than `max_concurrent` (see
+//!   below)
+//! - `RELEASE key lease_id` - Give back a lease; replies 1 if it was held,
+//!   0 if it was unknown or had already expired
 //! - `PING` - Health check
+//! - `AUTH [username] password` - Authenticate the connection
+//! - `HELLO [protover [AUTH username password] [SETNAME name]]` - Handshake;
+//!   only RESP2 is spoken, so a `protover` other than 2 gets `NOPROTO`
+//! - `INFO [section]` - Server, client, persistence and stats sections in
+//!   Redis's `field:value` format, filled from the server's metrics
+//! - `COMMAND`, `COMMAND COUNT`, `COMMAND DOCS` and `COMMAND INFO` - Minimal
+//!   replies for clients that introspect the server on connect
 //! - `QUIT` - Close connection
 //!
+//! # Authentication
+//!
+//! With a password configured (`--redis-password`), the transport behaves
+//! like Redis with `requirepass`: every command except `AUTH` and `QUIT` is
+//! answered with `NOAUTH Authentication required.` until the connection
+//! authenticates. ACL-style clients may send `AUTH default password`; other
+//! usernames are rejected. A wrong password gets `WRONGPASS` and leaves the
+//! connection unauthenticated.
+//!
+//! With [API keys](crate::auth) configured, authentication is required as
+//! well, and `AUTH secret` accepts any key's secret. `AUTH name secret`
+//! accepts only the key called `name`. Commands on the connection are then
+//! counted for that key, and `THROTTLE` and `CL.THROTTLE` charged to its
+//! [budget](crate::budget), if any. `HELLO 2 AUTH username password`
+//! authenticates in the same way.
+//!
+//! # Pacing
+//!
+//! Each connection's commands are processed one at a time, so a client
+//! never has more than one request waiting for the rate limiter however
+//! deeply it pipelines. With `--redis-max-commands-per-second N` a
+//! connection that has issued `N` commands within the current second stops
+//! being read from until the next second starts; TCP flow control then
+//! slows the client down without any error.
+//!
+//! # Connection Limits
+//!
+//! With `--redis-max-connections N` the transport holds at most `N`
+//! connections at once, and with `--redis-max-connections-per-ip N` at
+//! most `N` from one client IP. A connection over either cap is answered
+//! with `ERR max number of clients reached`, as Redis does at `maxclients`,
+//! and closed as soon as it is accepted.
+//!
 //! # Example Usage
 //!
 //! ```bash
@@ -21,58 +91,179 @@
 //! 1) (integer) 1    # allowed
 //! 2) (integer) 10   # limit
 //! 3) (integer) 9    # remaining
-//! 4) (integer) 60   # reset_after
+//! 4) (integer) 5    # reset_after
 //! 5) (integer) 0    # retry_after
+//! 6) (integer) 5400 # reset_after_ms
+//! 7) (integer) 0    # retry_after_ms
+//! 8) (integer) 1704067205400 # cacheable_until_ms
+//! 9) (integer) 0    # warning
+//! ```
+//!
+//! The 6th and 7th give the delays in milliseconds, rounded up, for limits
+//! whose delays are under a second. The 8th is when the client must stop
+//! allowing up to `remaining` requests itself and ask again, in
+//! milliseconds since the Unix epoch; 0 if the decision is not cacheable.
+//! The last is 1 once the key is past its policy's
+//! [soft limit](crate::policy#soft-limits).
+//!
+//! # Concurrency Leases
+//!
+//! `ACQUIRE` caps the operations in flight on a key rather than their rate.
+//! Leases are not counted as requests.
+//!
+//! ```bash
+//! > ACQUIRE reports:org:42 50 300
+//! 1) (integer) 1    # acquired
+//! 2) (integer) 42   # lease_id, 0 when not acquired
+//! 3) (integer) 50   # limit
+//! 4) (integer) 12   # active leases, including this one
+//! 5) (integer) 0    # retry_after, seconds until the oldest lease expires
+//! > RELEASE reports:org:42 42
+//! (integer) 1
+//! ```
+//!
+//! # redis-cell Compatibility
+//!
+//! `CL.THROTTLE` behaves like redis-cell's command of the same name. Its
+//! `max_burst` counts requests beyond the first, so `CL.THROTTLE user:123 15
+//! 30 60` allows a burst of 16, and the reply uses redis-cell's order and
+//! conventions:
+//!
+//! ```bash
+//! > CL.THROTTLE user:123 15 30 60
+//! 1) (integer) 0    # limited: 0 allowed, 1 denied
+//! 2) (integer) 16   # limit
+//! 3) (integer) 15   # remaining
+//! 4) (integer) -1   # retry_after, -1 when allowed
+//! 5) (integer) 2    # reset_after
 //! ```
 
 pub mod resp;
 
 use self::resp::{RespParser, RespSerializer, RespValue};
-use super::Transport;
-use crate::actor::RateLimiterHandle;
+use super::{Transport, bind_tcp, socket_addr};
+use crate::actor::{OverloadedError, RateLimiterHandle};
+use crate::auth::{ApiKeys, constant_time_eq};
+use crate::budget::BudgetExhaustedError;
+use crate::logging::log_request;
 use crate::metrics::{Metrics, Transport as MetricsTransport};
-use crate::types::ThrottleRequest;
+use crate::namespace::{self, InvalidNamespaceError, NamespaceFullError};
+use crate::otel;
+use crate::policy::{UnknownOperationError, UnknownPolicyError};
+use crate::types::{AcquireRequest, ThrottleRequest, ThrottleResponse, ValidationError};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
-use std::net::SocketAddr;
-use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::TcpStream;
 use tokio::time::timeout;
-use tracing::{debug, error, info};
+use tracing::{Instrument, debug, error, info};
+
+/// Redis version reported by `HELLO` and `INFO`
+///
+/// Clients compare it against the release that introduced a feature; 6.0 is
+/// the first with `HELLO`, which is as far as the handshake goes.
+const REDIS_VERSION: &str = "6.0.0";
+
+/// Commands understood by the transport, as counted by `COMMAND COUNT`
+const COMMANDS: &[&str] = &[
+    "THROTTLE",
+    "THROTTLE.PEEK",
+    "THROTTLE.RESET",
+    "CL.THROTTLE",
+    "ACQUIRE",
+    "RELEASE",
+    "PING",
+    "AUTH",
+    "HELLO",
+    "INFO",
+    "COMMAND",
+    "QUIT",
+];
 
 /// Redis transport implementation
 pub struct RedisTransport {
     addr: SocketAddr,
     metrics: Arc<Metrics>,
+    password: Option<Arc<str>>,
+    max_commands_per_second: u32,
+    limits: Arc<ConnectionLimits>,
 }
 
 impl RedisTransport {
     pub fn new(host: &str, port: u16, metrics: Arc<Metrics>) -> Result<Self> {
-        let addr = format!("{host}:{port}")
-            .parse()
-            .with_context(|| format!("Invalid address: {host}:{port}"))?;
-        Ok(Self { addr, metrics })
+        let addr = socket_addr(host, port)?;
+        Ok(Self {
+            addr,
+            metrics,
+            password: None,
+            max_commands_per_second: 0,
+            limits: Arc::new(ConnectionLimits::new(0, 0)),
+        })
+    }
+
+    /// Require clients to `AUTH` with `password` before other commands
+    ///
+    /// `None` disables authentication.
+    pub fn with_password(mut self, password: Option<String>) -> Self {
+        self.password = password.map(Arc::from);
+        self
+    }
+
+    /// Limit each connection to `count` commands per second (0 for unlimited)
+    pub fn with_max_commands_per_second(mut self, count: u32) -> Self {
+        self.max_commands_per_second = count;
+        self
+    }
+
+    /// Hold at most `max` connections at once, and at most `per_ip` from
+    /// one client IP (0 for unlimited)
+    pub fn with_max_connections(mut self, max: usize, per_ip: usize) -> Self {
+        self.limits = Arc::new(ConnectionLimits::new(max, per_ip));
+        self
     }
 }
 
 #[async_trait]
 impl Transport for RedisTransport {
     async fn start(self, limiter: RateLimiterHandle) -> Result<()> {
-        let listener = TcpListener::bind(&self.addr)
-            .await
-            .with_context(|| format!("Failed to bind to {}", self.addr))?;
+        let listener =
+            bind_tcp(self.addr).with_context(|| format!("Failed to bind to {}", self.addr))?;
 
         info!("Redis transport listening on {}", self.addr);
 
         loop {
             let (socket, addr) = listener.accept().await?;
+            let Some(slot) = ConnectionLimits::admit(&self.limits, addr.ip()) else {
+                self.metrics
+                    .redis_rejected_connections
+                    .fetch_add(1, Ordering::Relaxed);
+                debug!(
+                    "Refusing Redis connection from {}: too many connections",
+                    addr
+                );
+                // Best effort, and brief: the connection is closed either way
+                tokio::spawn(async move {
+                    let mut socket = socket;
+                    let reply = b"-ERR max number of clients reached\r\n";
+                    let _ = timeout(REFUSAL_TIMEOUT, socket.write_all(reply)).await;
+                });
+                continue;
+            };
             let limiter = limiter.clone();
             let metrics = Arc::clone(&self.metrics);
+            let password = self.password.clone();
+            let pacer = CommandPacer::new(self.max_commands_per_second);
 
             tokio::spawn(async move {
-                if let Err(e) = handle_connection(socket, addr, limiter, metrics).await {
+                let _slot = slot;
+                if let Err(e) =
+                    handle_connection(socket, addr, limiter, metrics, password, pacer).await
+                {
                     error!("Error handling Redis connection from {}: {}", addr, e);
                 }
             });
@@ -82,20 +273,172 @@ impl Transport for RedisTransport {
 
 const MAX_BUFFER_SIZE: usize = 64 * 1024; // 64KB max buffer per connection
 
-async fn handle_connection(
+/// How long a refused connection may take to receive its error reply
+const REFUSAL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Bytes read from the socket at a time
+const READ_CHUNK_SIZE: usize = 1024;
+
+/// Per-connection command budget, in fixed one-second windows
+pub(super) struct CommandPacer {
+    limit: u32,
+    /// Start of the current window, from the first command in it
+    window_start: Option<Instant>,
+    count: u32,
+}
+
+impl CommandPacer {
+    /// Allow `limit` commands per second, or any number if 0
+    pub(super) fn new(limit: u32) -> Self {
+        CommandPacer {
+            limit,
+            window_start: None,
+            count: 0,
+        }
+    }
+
+    /// How long to wait before the next command may run, counting it if it
+    /// may run now
+    pub(super) fn delay(&mut self, now: Instant) -> Option<Duration> {
+        if self.limit == 0 {
+            return None;
+        }
+        let window_start = match self.window_start {
+            Some(start) if now.duration_since(start) < Duration::from_secs(1) => start,
+            _ => {
+                self.count = 0;
+                *self.window_start.insert(now)
+            }
+        };
+        if self.count < self.limit {
+            self.count += 1;
+            return None;
+        }
+        Some(Duration::from_secs(1) - now.duration_since(window_start))
+    }
+}
+
+/// Caps on the connections open at once, overall and per client IP
+pub(super) struct ConnectionLimits {
+    /// Connections allowed at once (0 for unlimited)
+    max: usize,
+    /// Connections allowed at once from one IP (0 for unlimited)
+    per_ip: usize,
+    open: Mutex<OpenConnections>,
+}
+
+#[derive(Default)]
+struct OpenConnections {
+    total: usize,
+    by_ip: HashMap<IpAddr, usize>,
+}
+
+impl ConnectionLimits {
+    /// Allow `max` connections at once and `per_ip` from one IP (0 for unlimited)
+    pub(super) fn new(max: usize, per_ip: usize) -> Self {
+        ConnectionLimits {
+            max,
+            per_ip,
+            open: Mutex::new(OpenConnections::default()),
+        }
+    }
+
+    /// Take a slot for a connection from `ip`, or None if a cap is reached
+    ///
+    /// The slot is given back when dropped.
+    pub(super) fn admit(limits: &Arc<Self>, ip: IpAddr) -> Option<ConnectionSlot> {
+        let mut open = limits.open.lock().unwrap_or_else(|e| e.into_inner());
+        let from_ip = open.by_ip.get(&ip).copied().unwrap_or(0);
+        if (limits.max > 0 && open.total >= limits.max)
+            || (limits.per_ip > 0 && from_ip >= limits.per_ip)
+        {
+            return None;
+        }
+        open.total += 1;
+        open.by_ip.insert(ip, from_ip + 1);
+        Some(ConnectionSlot {
+            limits: Arc::clone(limits),
+            ip,
+        })
+    }
+}
+
+/// A connection's place under the [`ConnectionLimits`]
+pub(super) struct ConnectionSlot {
+    limits: Arc<ConnectionLimits>,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut open = self.limits.open.lock().unwrap_or_else(|e| e.into_inner());
+        open.total -= 1;
+        if let Some(count) = open.by_ip.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                open.by_ip.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// Counts an open connection and its buffer memory in the metrics
+///
+/// Undone on drop, whichever way the connection ends.
+struct ConnectionGauge {
+    metrics: Arc<Metrics>,
+    bytes: u64,
+}
+
+impl ConnectionGauge {
+    fn open(metrics: Arc<Metrics>) -> Self {
+        metrics.redis_connections.fetch_add(1, Ordering::Relaxed);
+        let mut gauge = ConnectionGauge { metrics, bytes: 0 };
+        gauge.track(0);
+        gauge
+    }
+
+    /// Account for the connection's buffer, now `capacity` bytes
+    fn track(&mut self, capacity: usize) {
+        let bytes = (READ_CHUNK_SIZE + capacity) as u64;
+        if bytes != self.bytes {
+            let total = &self.metrics.connection_buffer_bytes;
+            total.fetch_add(bytes, Ordering::Relaxed);
+            total.fetch_sub(self.bytes, Ordering::Relaxed);
+            self.bytes = bytes;
+        }
+    }
+}
+
+impl Drop for ConnectionGauge {
+    fn drop(&mut self) {
+        self.metrics
+            .redis_connections
+            .fetch_sub(1, Ordering::Relaxed);
+        self.metrics
+            .connection_buffer_bytes
+            .fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+pub(super) async fn handle_connection(
     mut socket: TcpStream,
     addr: SocketAddr,
     limiter: RateLimiterHandle,
     metrics: Arc<Metrics>,
+    password: Option<Arc<str>>,
+    mut pacer: CommandPacer,
 ) -> Result<()> {
     debug!("New Redis connection from {}", addr);
 
     let mut buffer = Vec::new();
     let mut parser = RespParser::new();
+    let mut auth = ConnectionAuth::new(password, limiter.api_keys().cloned());
+    let mut gauge = ConnectionGauge::open(Arc::clone(&metrics));
 
     loop {
         // Read data from socket with timeout
-        let mut temp_buf = vec![0; 1024];
+        let mut temp_buf = vec![0; READ_CHUNK_SIZE];
         let read_timeout = Duration::from_secs(300); // 5 minutes timeout
 
         let n = match timeout(read_timeout, socket.read(&mut temp_buf)).await {
@@ -116,6 +459,7 @@ async fn handle_connection(
         }
 
         buffer.extend_from_slice(&temp_buf[..n]);
+        gauge.track(buffer.capacity());
 
         // Check buffer size limit
         if buffer.len() > MAX_BUFFER_SIZE {
@@ -124,7 +468,17 @@ async fn handle_connection(
         }
 
         // Try to parse RESP values
-        while let Some((value, consumed)) = parser.parse(&buffer)? {
+        loop {
+            let (value, consumed) = match parser.parse(&buffer) {
+                Ok(Some(parsed)) => parsed,
+                Ok(None) => break,
+                Err(e) => {
+                    // Tell the client why before dropping the connection
+                    let error = RespValue::Error(format!("ERR Protocol error: {e}"));
+                    let _ = socket.write_all(&RespSerializer::serialize(&error)).await;
+                    return Err(e);
+                }
+            };
             buffer.drain(..consumed);
 
             // Check if this is a QUIT command before processing
@@ -132,8 +486,34 @@ async fn handle_connection(
                 matches!(v, RespValue::BulkString(Some(cmd)) if cmd.to_uppercase() == "QUIT")
             }).unwrap_or(false));
 
-            // Process the command
-            let response = process_command(value, &limiter, &metrics).await;
+            // Stop reading from connections over their command budget
+            while let Some(wait) = pacer.delay(Instant::now()) {
+                metrics.redis_paced_commands.fetch_add(1, Ordering::Relaxed);
+                tokio::time::sleep(wait).await;
+            }
+
+            // Process the command, unless authentication handles it
+            let response = match auth.check(&value) {
+                Some(response) => {
+                    if matches!(&response, RespValue::Error(e)
+                        if e.starts_with("NOAUTH") || e.starts_with("WRONGPASS"))
+                    {
+                        metrics.auth_failures.fetch_add(1, Ordering::Relaxed);
+                    }
+                    response
+                }
+                None => {
+                    if let Some(name) = auth.api_key() {
+                        metrics.record_api_key_request(name);
+                    }
+                    let api_key = auth.api_key().map(|name| &**name);
+                    let namespace = limiter.namespace(None, api_key);
+                    match charge(&value, &limiter, api_key) {
+                        Ok(()) => process_command(value, &limiter, &metrics, namespace).await,
+                        Err(e) => error_reply(e.into()),
+                    }
+                }
+            };
 
             // Serialize and send response
             let response_bytes = RespSerializer::serialize(&response);
@@ -148,10 +528,32 @@ async fn handle_connection(
     }
 }
 
+/// Charge a throttle check command to the budget of the connection's API key
+fn charge(
+    value: &RespValue,
+    limiter: &RateLimiterHandle,
+    api_key: Option<&str>,
+) -> Result<(), BudgetExhaustedError> {
+    let RespValue::Array(command) = value else {
+        return Ok(());
+    };
+    match command.first() {
+        Some(RespValue::BulkString(Some(name)))
+            if name.eq_ignore_ascii_case("THROTTLE")
+                || name.eq_ignore_ascii_case("CL.THROTTLE") =>
+        {
+            limiter.charge(api_key, 1)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Run one command for a connection, with its keys in `namespace` if set
 pub(super) async fn process_command(
     value: RespValue,
     limiter: &RateLimiterHandle,
     metrics: &Arc<Metrics>,
+    namespace: Option<&str>,
 ) -> RespValue {
     // Parse command from array
     let command_array = match value {
@@ -169,20 +571,60 @@ pub(super) async fn process_command(
         _ => return RespValue::Error("ERR invalid command format".to_string()),
     };
 
+    // Set for the throttle commands, logged with their decision
+    let mut latency = Duration::ZERO;
     let (result, key_opt) = match command.as_str() {
         "PING" => (handle_ping(&command_array), None),
-        "THROTTLE" => {
-            // Extract key for metrics
-            let key = if command_array.len() > 1 {
-                match &command_array[1] {
-                    RespValue::BulkString(Some(k)) => Some(k.clone()),
-                    _ => None,
-                }
+        "HELLO" => (handle_hello(&command_array), None),
+        "INFO" => (handle_info(&command_array, metrics), None),
+        "COMMAND" => (handle_command(&command_array), None),
+        "THROTTLE" | "CL.THROTTLE" => {
+            // Shared by the request and the metrics, so the key is copied once
+            let key = command_key(&command_array);
+            let scoped = match scope(key.clone(), namespace) {
+                Ok(scoped) => scoped,
+                Err(error) => return error,
+            };
+            let started = Instant::now();
+            let result = if command == "THROTTLE" {
+                handle_throttle(&command_array, scoped, limiter).await
             } else {
-                None
+                handle_cell_throttle(&command_array, scoped, limiter).await
+            };
+            latency = started.elapsed();
+            metrics.record_latency(MetricsTransport::Redis, latency);
+            (result, key)
+        }
+        "THROTTLE.PEEK" => {
+            // Peeks consume nothing, so they are not counted as requests
+            let key = match scope(command_key(&command_array), namespace) {
+                Ok(key) => key,
+                Err(error) => return error,
+            };
+            return match parse_throttle(&command_array, key, "throttle.peek", 0, limiter) {
+                Ok(request) => respond(limiter.peek(request).await),
+                Err(error) => error,
+            };
+        }
+        "THROTTLE.RESET" => {
+            let [_, RespValue::BulkString(Some(key))] = command_array.as_slice() else {
+                return RespValue::Error(
+                    "ERR wrong number of arguments for 'throttle.reset' command".to_string(),
+                );
+            };
+            let key = match namespace.map(|namespace| namespace::scoped_key(namespace, key)) {
+                Some(Ok(key)) => key,
+                Some(Err(e)) => return error_reply(e.into()),
+                None => key.as_str().into(),
+            };
+            return match limiter.reset(key, MetricsTransport::Redis).await {
+                Ok(report) => RespValue::Integer(report.existed.into()),
+                Err(e) => RespValue::Error(format!("ERR {e}")),
             };
-            (handle_throttle(&command_array, limiter, metrics).await, key)
         }
+        // Leases are not rate limit decisions, so they are not counted as requests
+        "ACQUIRE" => return handle_acquire(&command_array, limiter).await,
+        "RELEASE" => return handle_release(&command_array, limiter).await,
         "QUIT" => (RespValue::SimpleString("OK".to_string()), None),
         _ => (
             RespValue::Error(format!("ERR unknown command '{command}'")),
@@ -191,15 +633,21 @@ pub(super) async fn process_command(
     };
 
     // Check if the request was allowed (for THROTTLE commands)
-    let allowed = match &result {
-        RespValue::Array(values) if values.len() >= 5 => {
-            matches!(&values[0], RespValue::Integer(1))
+    let allowed = match (&result, command.as_str()) {
+        (RespValue::Array(values), "THROTTLE" | "CL.THROTTLE") if values.len() >= 5 => {
+            // redis-cell flags denials, THROTTLE flags allowed requests
+            let allowed_flag = if command == "CL.THROTTLE" { 0 } else { 1 };
+            matches!(&values[0], RespValue::Integer(flag) if *flag == allowed_flag)
         }
         _ => true, // Non-throttle commands are considered allowed
     };
 
     if let Some(key) = key_opt {
         metrics.record_request_with_key(MetricsTransport::Redis, allowed, &key);
+        log_request(MetricsTransport::Redis, &key, allowed, latency);
+        if let Some(namespace) = namespace {
+            metrics.record_namespace_request(namespace, allowed);
+        }
     } else {
         metrics.record_request(MetricsTransport::Redis, allowed);
     }
@@ -207,6 +655,128 @@ pub(super) async fn process_command(
     result
 }
 
+/// Authentication state of one connection
+pub(super) struct ConnectionAuth {
+    password: Option<Arc<str>>,
+    api_keys: Option<Arc<ApiKeys>>,
+    authenticated: bool,
+    /// Name of the API key the connection authenticated with
+    api_key: Option<Arc<str>>,
+}
+
+impl ConnectionAuth {
+    pub(super) fn new(password: Option<Arc<str>>, api_keys: Option<Arc<ApiKeys>>) -> Self {
+        let authenticated = password.is_none() && api_keys.is_none();
+        ConnectionAuth {
+            password,
+            api_keys,
+            authenticated,
+            api_key: None,
+        }
+    }
+
+    /// Name of the API key the connection authenticated with, if any
+    pub(super) fn api_key(&self) -> Option<&Arc<str>> {
+        self.api_key.as_ref()
+    }
+
+    /// Handle `AUTH` and gate other commands
+    ///
+    /// Returns the response for `AUTH` and for commands rejected because the
+    /// connection is not authenticated, or `None` if `command` should be
+    /// processed normally.
+    pub(super) fn check(&mut self, command: &RespValue) -> Option<RespValue> {
+        let args = match command {
+            RespValue::Array(args) => args.as_slice(),
+            _ => &[],
+        };
+        let name = match args.first() {
+            Some(RespValue::BulkString(Some(name))) => name.to_uppercase(),
+            _ => String::new(),
+        };
+
+        match name.as_str() {
+            "AUTH" => Some(self.auth(&args[1..])),
+            "HELLO" => self.hello(&args[1..]),
+            "QUIT" => None,
+            _ if self.authenticated => None,
+            _ => Some(RespValue::Error(
+                "NOAUTH Authentication required.".to_string(),
+            )),
+        }
+    }
+
+    /// Authenticate with `HELLO`'s `AUTH` option, which is allowed before
+    /// the connection is authenticated
+    fn hello(&mut self, args: &[RespValue]) -> Option<RespValue> {
+        let credentials = args.iter().position(
+            |arg| matches!(arg, RespValue::BulkString(Some(word)) if word.eq_ignore_ascii_case("AUTH")),
+        );
+        match credentials {
+            Some(at) if at + 2 < args.len() => match self.auth(&args[at + 1..at + 3]) {
+                RespValue::SimpleString(_) => None,
+                error => Some(error),
+            },
+            // Malformed options are reported by the command itself
+            Some(_) => None,
+            None if self.authenticated => None,
+            None => Some(RespValue::Error(
+                "NOAUTH HELLO must be called with the client already authenticated, otherwise \
+                 the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the \
+                 client and select the RESP protocol version at the same time"
+                    .to_string(),
+            )),
+        }
+    }
+
+    fn auth(&mut self, args: &[RespValue]) -> RespValue {
+        let (username, password) = match args {
+            [RespValue::BulkString(Some(password))] => ("default", password),
+            [
+                RespValue::BulkString(Some(username)),
+                RespValue::BulkString(Some(password)),
+            ] => (username.as_str(), password),
+            _ => {
+                return RespValue::Error(
+                    "ERR wrong number of arguments for 'auth' command".to_string(),
+                );
+            }
+        };
+
+        if self.password.is_none() && self.api_keys.is_none() {
+            return RespValue::Error(
+                "ERR AUTH <password> called without any password configured for the default user. \
+                 Are you sure your configuration is correct?"
+                    .to_string(),
+            );
+        };
+
+        // Compare in constant time, and check the username after the
+        // password so both failures take the same time
+        let password_matches = self
+            .password
+            .as_ref()
+            .is_some_and(|expected| constant_time_eq(password.as_bytes(), expected.as_bytes()));
+        let api_key = self
+            .api_keys
+            .as_ref()
+            .and_then(|api_keys| api_keys.authenticate(password))
+            .filter(|name| username == "default" || username == &***name);
+        if password_matches && username == "default" {
+            self.authenticated = true;
+            RespValue::SimpleString("OK".to_string())
+        } else if let Some(name) = api_key {
+            self.authenticated = true;
+            self.api_key = Some(Arc::clone(name));
+            RespValue::SimpleString("OK".to_string())
+        } else {
+            RespValue::Error(
+                "WRONGPASS invalid username-password pair or user is disabled.".to_string(),
+            )
+        }
+    }
+}
+
 fn handle_ping(args: &[RespValue]) -> RespValue {
     if args.len() == 1 {
         RespValue::SimpleString("PONG".to_string())
@@ -218,60 +788,382 @@ fn handle_ping(args: &[RespValue]) -> RespValue {
     }
 }
 
+/// `HELLO [protover [AUTH username password] [SETNAME name]]`
+///
+/// Authentication is done by [`ConnectionAuth`]; here the options are only
+/// checked. The reply is the RESP2 form, a flat list of field-value pairs.
+fn handle_hello(args: &[RespValue]) -> RespValue {
+    if let Some(protover) = args.get(1) {
+        match parse_integer(protover) {
+            Some(2) => {}
+            Some(_) => {
+                return RespValue::Error(
+                    "NOPROTO sorry, this protocol version is not supported".to_string(),
+                );
+            }
+            None => {
+                return RespValue::Error(
+                    "ERR Protocol version is not an integer or out of range".to_string(),
+                );
+            }
+        }
+    }
+
+    let mut options = args.iter().skip(2);
+    while let Some(option) = options.next() {
+        let arity = match option {
+            RespValue::BulkString(Some(word)) if word.eq_ignore_ascii_case("AUTH") => 2,
+            RespValue::BulkString(Some(word)) if word.eq_ignore_ascii_case("SETNAME") => 1,
+            _ => 0,
+        };
+        if arity == 0 || options.by_ref().take(arity).count() < arity {
+            let name = match option {
+                RespValue::BulkString(Some(word)) => word.as_str(),
+                _ => "",
+            };
+            return RespValue::Error(format!("ERR Syntax error in HELLO option '{name}'"));
+        }
+    }
+
+    let field = |name: &str| RespValue::BulkString(Some(name.to_string()));
+    RespValue::Array(vec![
+        field("server"),
+        field("redis"),
+        field("version"),
+        field(REDIS_VERSION),
+        field("proto"),
+        RespValue::Integer(2),
+        field("mode"),
+        field("standalone"),
+        field("role"),
+        field("master"),
+        field("modules"),
+        RespValue::Array(Vec::new()),
+    ])
+}
+
+/// `INFO [section]`, with the sections clients look at on connect
+fn handle_info(args: &[RespValue], metrics: &Metrics) -> RespValue {
+    let section = match args {
+        [_] => "default".to_string(),
+        [_, RespValue::BulkString(Some(section))] => section.to_lowercase(),
+        _ => {
+            return RespValue::Error(
+                "ERR wrong number of arguments for 'info' command".to_string(),
+            );
+        }
+    };
+    let uptime = metrics.uptime_seconds();
+    let sections = [
+        (
+            "server",
+            format!(
+                "redis_version:{REDIS_VERSION}\r\n\
+                 throttlecrab_version:{}\r\n\
+                 redis_mode:standalone\r\n\
+                 process_id:{}\r\n\
+                 uptime_in_seconds:{uptime}\r\n\
+                 uptime_in_days:{}\r\n",
+                env!("CARGO_PKG_VERSION"),
+                std::process::id(),
+                uptime / 86_400,
+            ),
+        ),
+        (
+            "clients",
+            format!(
+                "connected_clients:{}\r\n",
+                metrics.redis_connections.load(Ordering::Relaxed)
+            ),
+        ),
+        // Rate limit state is restored before the transport starts
+        ("persistence", "loading:0\r\n".to_string()),
+        (
+            "stats",
+            format!(
+                "total_commands_processed:{}\r\n\
+                 throttlecrab_total_requests:{}\r\n\
+                 throttlecrab_requests_allowed:{}\r\n\
+                 throttlecrab_requests_denied:{}\r\n\
+                 throttlecrab_requests_errors:{}\r\n",
+                metrics.redis_requests.load(Ordering::Relaxed),
+                metrics.total_requests.load(Ordering::Relaxed),
+                metrics.requests_allowed.load(Ordering::Relaxed),
+                metrics.requests_denied.load(Ordering::Relaxed),
+                metrics.requests_errors.load(Ordering::Relaxed),
+            ),
+        ),
+    ];
+
+    let all = matches!(section.as_str(), "default" | "all" | "everything");
+    let info = sections
+        .iter()
+        .filter(|(name, _)| all || *name == section)
+        .map(|(name, fields)| {
+            let mut title = name.to_string();
+            title[..1].make_ascii_uppercase();
+            format!("# {title}\r\n{fields}")
+        })
+        .collect::<Vec<_>>()
+        .join("\r\n");
+    RespValue::BulkString(Some(info))
+}
+
+/// `COMMAND [COUNT | DOCS | INFO ...]`
+///
+/// Command metadata is not published: clients get an empty table, which
+/// they treat as "no details available".
+fn handle_command(args: &[RespValue]) -> RespValue {
+    let subcommand = match args.get(1) {
+        None => return RespValue::Array(Vec::new()),
+        Some(RespValue::BulkString(Some(subcommand))) => subcommand.to_uppercase(),
+        Some(_) => String::new(),
+    };
+    match subcommand.as_str() {
+        "COUNT" => RespValue::Integer(COMMANDS.len() as i64),
+        "DOCS" => RespValue::Array(Vec::new()),
+        // One entry per command asked about, nil for each
+        "INFO" => RespValue::Array(vec![RespValue::BulkString(None); args.len() - 2]),
+        _ => RespValue::Error(format!(
+            "ERR unknown subcommand '{}'. Try COMMAND HELP.",
+            subcommand.to_lowercase()
+        )),
+    }
+}
+
 async fn handle_throttle(
     args: &[RespValue],
+    key: Option<Arc<str>>,
     limiter: &RateLimiterHandle,
-    _metrics: &Arc<Metrics>,
 ) -> RespValue {
-    // THROTTLE key max_burst count_per_period period [quantity]
+    match parse_throttle(args, key, "throttle", 1, limiter) {
+        Ok(request) => {
+            let span = otel::request_span(MetricsTransport::Redis, None);
+            respond(limiter.throttle(request).instrument(span).await)
+        }
+        Err(error) => error,
+    }
+}
+
+/// `CL.THROTTLE`, with redis-cell's `max_burst` and reply
+async fn handle_cell_throttle(
+    args: &[RespValue],
+    key: Option<Arc<str>>,
+    limiter: &RateLimiterHandle,
+) -> RespValue {
+    // CL.THROTTLE key max_burst count_per_period period [quantity]
     if args.len() < 5 || args.len() > 6 {
         return RespValue::Error(
-            "ERR wrong number of arguments for 'throttle' command".to_string(),
+            "ERR wrong number of arguments for 'cl.throttle' command".to_string(),
         );
     }
+    let Some(key) = key else {
+        return RespValue::Error("ERR invalid key".to_string());
+    };
+    let Some(max_burst) = parse_integer(&args[2]) else {
+        return RespValue::Error("ERR invalid max_burst".to_string());
+    };
+    let Some(count_per_period) = parse_integer(&args[3]) else {
+        return RespValue::Error("ERR invalid count_per_period".to_string());
+    };
+    let Some(period) = parse_integer(&args[4]) else {
+        return RespValue::Error("ERR invalid period".to_string());
+    };
+    let quantity = match args.get(5) {
+        Some(quantity) => match parse_integer(quantity) {
+            Some(quantity) => quantity,
+            None => return RespValue::Error("ERR invalid quantity".to_string()),
+        },
+        None => 1,
+    };
 
-    // Parse arguments
-    let key = match &args[1] {
-        RespValue::BulkString(Some(s)) => s.clone(),
-        _ => return RespValue::Error("ERR invalid key".to_string()),
+    let request = ThrottleRequest {
+        quantity,
+        timestamp: limiter.now(),
+        // redis-cell allows max_burst requests on top of the first
+        ..ThrottleRequest::new(key, max_burst.saturating_add(1), count_per_period, period)
+    };
+    match limiter.throttle(request).await {
+        Ok(response) => RespValue::Array(vec![
+            RespValue::Integer((!response.allowed).into()),
+            RespValue::Integer(response.limit),
+            RespValue::Integer(response.remaining),
+            RespValue::Integer(if response.allowed {
+                -1
+            } else {
+                response.retry_after
+            }),
+            RespValue::Integer(response.reset_after),
+        ]),
+        Err(e) => error_reply(e),
+    }
+}
+
+/// `ACQUIRE key max_concurrent ttl`
+async fn handle_acquire(args: &[RespValue], limiter: &RateLimiterHandle) -> RespValue {
+    if args.len() != 4 {
+        return RespValue::Error("ERR wrong number of arguments for 'acquire' command".to_string());
+    }
+    let Some(key) = command_key(args) else {
+        return RespValue::Error("ERR invalid key".to_string());
+    };
+    let Some(max_concurrent) = parse_integer(&args[2]) else {
+        return RespValue::Error("ERR invalid max_concurrent".to_string());
+    };
+    let Some(ttl) = parse_integer(&args[3]) else {
+        return RespValue::Error("ERR invalid ttl".to_string());
     };
 
-    let max_burst = match parse_integer(&args[2]) {
-        Some(n) => n,
-        None => return RespValue::Error("ERR invalid max_burst".to_string()),
+    let request = AcquireRequest {
+        key,
+        max_concurrent,
+        ttl,
+        timestamp: limiter.now(),
     };
+    match limiter.acquire(request).await {
+        Ok(response) => RespValue::Array(vec![
+            RespValue::Integer(response.acquired.into()),
+            RespValue::Integer(response.lease_id.map_or(0, |id| id as i64)),
+            RespValue::Integer(response.limit),
+            RespValue::Integer(response.active),
+            RespValue::Integer(response.retry_after),
+        ]),
+        Err(e) => error_reply(e),
+    }
+}
 
-    let count_per_period = match parse_integer(&args[3]) {
-        Some(n) => n,
-        None => return RespValue::Error("ERR invalid count_per_period".to_string()),
+/// `RELEASE key lease_id`
+async fn handle_release(args: &[RespValue], limiter: &RateLimiterHandle) -> RespValue {
+    let [_, RespValue::BulkString(Some(key)), lease_id] = args else {
+        return RespValue::Error("ERR wrong number of arguments for 'release' command".to_string());
     };
+    let Some(lease_id) = parse_integer(lease_id).and_then(|id| u64::try_from(id).ok()) else {
+        return RespValue::Error("ERR invalid lease_id".to_string());
+    };
+    match limiter.release(key.as_str().into(), lease_id).await {
+        Ok(released) => RespValue::Integer(released.into()),
+        Err(e) => RespValue::Error(format!("ERR {e}")),
+    }
+}
 
-    let period = match parse_integer(&args[4]) {
-        Some(n) => n,
-        None => return RespValue::Error("ERR invalid period".to_string()),
+/// Parse the arguments of `THROTTLE` or `THROTTLE.PEEK` into a request
+fn parse_throttle(
+    args: &[RespValue],
+    key: Option<Arc<str>>,
+    command: &str,
+    default_quantity: i64,
+    limiter: &RateLimiterHandle,
+) -> Result<ThrottleRequest, RespValue> {
+    // COMMAND key max_burst count_per_period period [quantity]
+    // COMMAND key POLICY name [quantity | OPERATION operation]
+    // COMMAND key [quantity]
+    let named_policy = is_keyword(args.get(2), "POLICY");
+    let operation = named_policy && is_keyword(args.get(4), "OPERATION");
+    let key_policy = !named_policy && args.len() <= 3;
+    let arity = if named_policy {
+        4
+    } else if key_policy {
+        2
+    } else {
+        5
+    };
+    let valid = if operation {
+        args.len() == arity + 2
+    } else {
+        args.len() == arity || args.len() == arity + 1
     };
+    if !valid {
+        return Err(RespValue::Error(format!(
+            "ERR wrong number of arguments for '{command}' command"
+        )));
+    }
 
-    let quantity = if args.len() == 6 {
-        match parse_integer(&args[5]) {
-            Some(n) => n,
-            None => return RespValue::Error("ERR invalid quantity".to_string()),
-        }
+    // Parse arguments
+    let Some(key) = key else {
+        return Err(RespValue::Error("ERR invalid key".to_string()));
+    };
+
+    let quantity = if args.len() == arity + 1 && !operation {
+        parse_integer(&args[arity])
+            .ok_or_else(|| RespValue::Error("ERR invalid quantity".to_string()))?
     } else {
-        1
+        default_quantity
     };
 
-    // Create throttle request
-    let request = ThrottleRequest {
-        key,
-        max_burst,
-        count_per_period,
-        period,
+    if named_policy {
+        let RespValue::BulkString(Some(name)) = &args[3] else {
+            return Err(RespValue::Error("ERR invalid policy".to_string()));
+        };
+        let operation = match args.get(5) {
+            Some(RespValue::BulkString(Some(operation))) => Some(operation.as_str()),
+            Some(_) => return Err(RespValue::Error("ERR invalid operation".to_string())),
+            None => None,
+        };
+        return resolved(key, quantity, Some(name), operation, limiter);
+    }
+    if key_policy {
+        return resolved(key, quantity, None, None, limiter);
+    }
+
+    let max_burst = parse_integer(&args[2])
+        .ok_or_else(|| RespValue::Error("ERR invalid max_burst".to_string()))?;
+    let count_per_period = parse_integer(&args[3])
+        .ok_or_else(|| RespValue::Error("ERR invalid count_per_period".to_string()))?;
+    let period = parse_integer(&args[4])
+        .ok_or_else(|| RespValue::Error("ERR invalid period".to_string()))?;
+
+    Ok(ThrottleRequest {
+        quantity,
+        timestamp: limiter.now(),
+        ..ThrottleRequest::new(key, max_burst, count_per_period, period)
+    })
+}
+
+/// A request for `key` taking its limits from a policy
+fn resolved(
+    key: Arc<str>,
+    quantity: i64,
+    policy: Option<&str>,
+    operation: Option<&str>,
+    limiter: &RateLimiterHandle,
+) -> Result<ThrottleRequest, RespValue> {
+    let mut request = ThrottleRequest {
         quantity,
-        timestamp: SystemTime::now(),
+        timestamp: limiter.now(),
+        ..ThrottleRequest::new(key, 0, 0, 0)
     };
+    limiter
+        .resolve(&mut request, policy, operation)
+        .map_err(error_reply)?;
+    Ok(request)
+}
 
-    // Check rate limit
-    match limiter.throttle(request).await {
+/// Whether `arg` is the keyword `word`, in any case
+fn is_keyword(arg: Option<&RespValue>, word: &str) -> bool {
+    matches!(arg, Some(RespValue::BulkString(Some(arg))) if arg.eq_ignore_ascii_case(word))
+}
+
+/// The key argument of a command, e.g. `THROTTLE` or `ACQUIRE`
+fn command_key(args: &[RespValue]) -> Option<Arc<str>> {
+    match args.get(1) {
+        Some(RespValue::BulkString(Some(k))) => Some(Arc::from(k.as_str())),
+        _ => None,
+    }
+}
+
+/// `key` as stored in `namespace`, if the connection has one
+fn scope(key: Option<Arc<str>>, namespace: Option<&str>) -> Result<Option<Arc<str>>, RespValue> {
+    match (key, namespace) {
+        (Some(key), Some(namespace)) => namespace::scoped_key(namespace, &key)
+            .map(Some)
+            .map_err(|e| error_reply(e.into())),
+        (key, _) => Ok(key),
+    }
+}
+
+/// The reply to `THROTTLE` or `THROTTLE.PEEK`
+fn respond(result: Result<ThrottleResponse>) -> RespValue {
+    match result {
         Ok(response) => {
             // Return array with response fields
             RespValue::Array(vec![
@@ -280,10 +1172,40 @@ async fn handle_throttle(
                 RespValue::Integer(response.remaining),
                 RespValue::Integer(response.reset_after),
                 RespValue::Integer(response.retry_after),
+                RespValue::Integer(response.reset_after_ms),
+                RespValue::Integer(response.retry_after_ms),
+                RespValue::Integer(response.cacheable_until_ms),
+                RespValue::Integer(i64::from(response.warning)),
             ])
         }
-        Err(e) => RespValue::Error(format!("ERR {e}")),
+        Err(e) => error_reply(e),
+    }
+}
+
+/// The error reply for a failed throttle check or lease request
+fn error_reply(e: anyhow::Error) -> RespValue {
+    if let Some(invalid) = e.downcast_ref::<ValidationError>() {
+        return RespValue::Error(format!("ERR {}: {}", invalid.code(), invalid));
+    }
+    if let Some(unknown) = e.downcast_ref::<UnknownPolicyError>() {
+        return RespValue::Error(format!("ERR {}: {}", unknown.code(), unknown));
+    }
+    if let Some(unknown) = e.downcast_ref::<UnknownOperationError>() {
+        return RespValue::Error(format!("ERR {}: {}", unknown.code(), unknown));
+    }
+    if let Some(invalid) = e.downcast_ref::<InvalidNamespaceError>() {
+        return RespValue::Error(format!("ERR {}: {}", invalid.code(), invalid));
+    }
+    if let Some(full) = e.downcast_ref::<NamespaceFullError>() {
+        return RespValue::Error(format!("ERR {}: {}", full.code(), full));
+    }
+    if let Some(exhausted) = e.downcast_ref::<BudgetExhaustedError>() {
+        return RespValue::Error(format!("ERR {}: {}", exhausted.code(), exhausted));
+    }
+    if let Some(overloaded) = e.downcast_ref::<OverloadedError>() {
+        return RespValue::Error(format!("ERR {}: {}", overloaded.code(), overloaded));
     }
+    RespValue::Error(format!("ERR {e}"))
 }
 
 fn parse_integer(value: &RespValue) -> Option<i64> {
@@ -1,6 +1,11 @@
 //! RESP (Redis Serialization Protocol) implementation
 //!
 //! This module provides parsing and serialization for RESP protocol data types.
+//!
+//! Besides RESP arrays, the parser accepts inline commands: a single line of
+//! space-separated arguments, as sent by `redis-cli` or typed into netcat.
+//! Arguments may be quoted with `"..."` (supporting `\"`, `\\`, `\n`, `\r`,
+//! `\t` and `\xHH` escapes) or `'...'` (supporting `\'`).
 
 use anyhow::{Result, bail};
 use std::str;
@@ -8,6 +13,8 @@ use std::str;
 const MAX_BULK_STRING_SIZE: i64 = 512 * 1024 * 1024; // 512MB max
 const MAX_ARRAY_SIZE: i64 = 1024 * 1024; // 1M elements max
 const MAX_ARRAY_DEPTH: usize = 128; // Max nesting depth
+const MAX_INLINE_SIZE: usize = 4 * 1024; // Max inline command line length
+const MAX_INLINE_ARGS: usize = 16; // Max arguments in an inline command
 
 /// RESP value types
 #[derive(Debug, Clone, PartialEq)]
@@ -48,10 +55,54 @@ impl RespParser {
             b':' => self.parse_integer(data),
             b'$' => self.parse_bulk_string(data),
             b'*' => self.parse_array(data),
+            // Inline commands are only valid at the top level
+            _ if self.depth == 0 => self.parse_inline(data),
             _ => bail!("Invalid RESP type marker: {}", data[0] as char),
         }
     }
 
+    /// Parse an inline command terminated by LF or CRLF
+    ///
+    /// Blank lines are skipped, as Redis does. The command is returned as
+    /// an array of bulk strings so it is handled like its RESP equivalent.
+    fn parse_inline(&self, data: &[u8]) -> Result<Option<(RespValue, usize)>> {
+        let mut skipped = 0;
+
+        loop {
+            let rest = &data[skipped..];
+            let newline = match rest.iter().position(|&b| b == b'\n') {
+                Some(pos) => pos,
+                None if rest.len() > MAX_INLINE_SIZE => {
+                    bail!("Inline command exceeds {} bytes", MAX_INLINE_SIZE)
+                }
+                None => return Ok(None),
+            };
+            if newline > MAX_INLINE_SIZE {
+                bail!("Inline command exceeds {} bytes", MAX_INLINE_SIZE);
+            }
+
+            let line = rest[..newline]
+                .strip_suffix(b"\r")
+                .unwrap_or(&rest[..newline]);
+            let consumed = skipped + newline + 1;
+
+            let args = split_inline_args(line)?;
+            if args.is_empty() {
+                skipped = consumed;
+                if skipped == data.len() {
+                    return Ok(None);
+                }
+                continue;
+            }
+
+            let values = args
+                .into_iter()
+                .map(|arg| RespValue::BulkString(Some(arg)))
+                .collect();
+            return Ok(Some((RespValue::Array(values), consumed)));
+        }
+    }
+
     fn parse_simple_string(&self, data: &[u8]) -> Result<Option<(RespValue, usize)>> {
         if let Some((line, consumed)) = self.read_line(data) {
             let s = str::from_utf8(&line[1..])?.to_string();
@@ -139,7 +190,9 @@ impl RespParser {
         }
 
         let count = count as usize;
-        let mut elements = Vec::with_capacity(count);
+        // Every element takes at least 3 bytes, so don't trust the count
+        // for preallocation beyond what the buffer could hold
+        let mut elements = Vec::with_capacity(count.min((data.len() - consumed) / 3));
 
         // Increment depth for recursive parsing
         self.depth += 1;
@@ -176,6 +229,103 @@ impl RespParser {
     }
 }
 
+/// Split an inline command line into arguments, honoring quotes
+fn split_inline_args(line: &[u8]) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut i = 0;
+
+    loop {
+        while i < line.len() && matches!(line[i], b' ' | b'\t') {
+            i += 1;
+        }
+        if i == line.len() {
+            return Ok(args);
+        }
+        if args.len() == MAX_INLINE_ARGS {
+            bail!("Inline command has more than {} arguments", MAX_INLINE_ARGS);
+        }
+
+        let mut arg = Vec::new();
+        match line[i] {
+            b'"' => {
+                i += 1;
+                loop {
+                    match line.get(i) {
+                        None => bail!("Unbalanced quotes in inline command"),
+                        Some(b'"') => break,
+                        Some(b'\\') => {
+                            let escaped = match line.get(i + 1) {
+                                Some(b'n') => b'\n',
+                                Some(b'r') => b'\r',
+                                Some(b't') => b'\t',
+                                Some(b'x') => {
+                                    let hex = line
+                                        .get(i + 2..i + 4)
+                                        .and_then(|h| str::from_utf8(h).ok())
+                                        .and_then(|h| u8::from_str_radix(h, 16).ok());
+                                    match hex {
+                                        Some(byte) => {
+                                            i += 2;
+                                            byte
+                                        }
+                                        None => bail!("Invalid \\x escape in inline command"),
+                                    }
+                                }
+                                Some(&c) => c,
+                                None => bail!("Unbalanced quotes in inline command"),
+                            };
+                            arg.push(escaped);
+                            i += 2;
+                        }
+                        Some(&c) => {
+                            arg.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+                i += 1;
+            }
+            b'\'' => {
+                i += 1;
+                loop {
+                    match line.get(i) {
+                        None => bail!("Unbalanced quotes in inline command"),
+                        Some(b'\'') => break,
+                        Some(b'\\') if line.get(i + 1) == Some(&b'\'') => {
+                            arg.push(b'\'');
+                            i += 2;
+                        }
+                        Some(&c) => {
+                            arg.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+                i += 1;
+            }
+            _ => {
+                while i < line.len() && !matches!(line[i], b' ' | b'\t') {
+                    if line[i] == b'"' || line[i] == b'\'' {
+                        bail!("Unexpected quote in inline command argument");
+                    }
+                    arg.push(line[i]);
+                    i += 1;
+                }
+            }
+        }
+
+        // A closing quote must end the argument
+        if i < line.len() && !matches!(line[i], b' ' | b'\t') {
+            bail!("Closing quote must be followed by a space in inline command");
+        }
+
+        match String::from_utf8(arg) {
+            Ok(arg) => args.push(arg),
+            Err(_) => bail!("Invalid UTF-8 in inline command"),
+        }
+    }
+}
+
 impl Default for RespParser {
     fn default() -> Self {
         Self::new()
@@ -314,4 +464,48 @@ mod tests {
         let serialized = RespSerializer::serialize(&value);
         assert_eq!(serialized, b"*2\r\n$3\r\nfoo\r\n:42\r\n");
     }
+
+    fn inline(args: &[&str]) -> RespValue {
+        RespValue::Array(
+            args.iter()
+                .map(|arg| RespValue::BulkString(Some(arg.to_string())))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_parse_inline_command() {
+        let mut parser = RespParser::new();
+
+        let data = b"PING\r\n";
+        assert_eq!(parser.parse(data).unwrap(), Some((inline(&["PING"]), 6)));
+
+        // LF only, extra whitespace
+        let data = b"  THROTTLE  user:1\t10 100 60 \n";
+        assert_eq!(
+            parser.parse(data).unwrap(),
+            Some((
+                inline(&["THROTTLE", "user:1", "10", "100", "60"]),
+                data.len()
+            ))
+        );
+
+        // Incomplete line waits for more data
+        assert_eq!(parser.parse(b"THROTTLE user:1").unwrap(), None);
+        assert_eq!(parser.parse(b"\r\n\r\n").unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_inline_quoted_arguments() {
+        let mut parser = RespParser::new();
+
+        let data = br#"THROTTLE "a \"quoted\" key\x21" 'it\'s' "" 60"#;
+        let mut line = data.to_vec();
+        line.extend_from_slice(b"\r\n");
+        let (value, _) = parser.parse(&line).unwrap().unwrap();
+        assert_eq!(
+            value,
+            inline(&["THROTTLE", "a \"quoted\" key!", "it's", "", "60"])
+        );
+    }
 }
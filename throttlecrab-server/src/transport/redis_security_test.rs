@@ -163,3 +163,81 @@ fn test_recursive_array_memory_exhaustion() {
     let result = parser.parse(data.as_bytes());
     assert!(result.is_err(), "Should reject huge array");
 }
+
+#[test]
+fn test_inline_unbalanced_quotes() {
+    for data in [
+        &b"THROTTLE \"key 10 100 60\r\n"[..],
+        b"THROTTLE 'key 10 100 60\r\n",
+        b"THROTTLE \"key\\\r\n",
+    ] {
+        let mut parser = RespParser::new();
+        assert!(parser.parse(data).is_err(), "Should reject {data:?}");
+    }
+}
+
+#[test]
+fn test_inline_malformed_arguments() {
+    for data in [
+        // Closing quote glued to the next argument
+        &b"THROTTLE \"key\"10 100 60\r\n"[..],
+        // Quote in the middle of a bare argument
+        b"THROTTLE ke\"y 10 100 60\r\n",
+        // Bad hex escape
+        b"THROTTLE \"\\xZZ\" 10 100 60\r\n",
+        // Invalid UTF-8
+        b"THROTTLE \xff\xfe 10 100 60\r\n",
+    ] {
+        let mut parser = RespParser::new();
+        assert!(parser.parse(data).is_err(), "Should reject {data:?}");
+    }
+}
+
+#[test]
+fn test_inline_length_limits() {
+    let mut parser = RespParser::new();
+
+    // An unterminated line is rejected once it is too long to be valid
+    let long_line = vec![b'a'; MAX_BUFFER_SIZE];
+    assert!(parser.parse(&long_line).is_err());
+
+    let mut terminated = vec![b'a'; 8 * 1024];
+    terminated.extend_from_slice(b"\r\n");
+    assert!(parser.parse(&terminated).is_err());
+
+    // Too many arguments
+    let many_args = format!("{}\r\n", vec!["x"; 100].join(" "));
+    assert!(parser.parse(many_args.as_bytes()).is_err());
+}
+
+#[test]
+fn test_inline_not_allowed_inside_array() {
+    let mut parser = RespParser::new();
+    let data = b"*1\r\nPING\r\n";
+    assert!(parser.parse(data).is_err());
+}
+
+#[test]
+fn test_random_input_never_panics() {
+    // Deterministic fuzzing: random bytes, biased towards protocol characters
+    let mut rng = fastrand::Rng::with_seed(0x7468_726f_7474_6c65);
+    let alphabet = b"*$:+-\r\n \"'\\x0123456789abcTHROTLE";
+
+    for _ in 0..20_000 {
+        let len = rng.usize(0..64);
+        let data: Vec<u8> = (0..len)
+            .map(|_| {
+                if rng.u8(0..4) == 0 {
+                    rng.u8(..)
+                } else {
+                    alphabet[rng.usize(0..alphabet.len())]
+                }
+            })
+            .collect();
+
+        let mut parser = RespParser::new();
+        if let Ok(Some((_, consumed))) = parser.parse(&data) {
+            assert!(consumed <= data.len());
+        }
+    }
+}
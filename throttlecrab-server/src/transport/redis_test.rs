@@ -1,25 +1,51 @@
 //! Tests for Redis protocol transport
 
 use super::redis::resp::{RespParser, RespSerializer, RespValue};
+use super::redis::{CommandPacer, ConnectionAuth};
 use crate::actor::RateLimiterHandle;
+use crate::auth::ApiKeys;
 use crate::config::StoreType;
 use crate::metrics::Metrics;
+use crate::policy::Policies;
 use crate::store;
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 // Helper function to create a new rate limiter for each test
-fn create_test_rate_limiter() -> (RateLimiterHandle, Arc<Metrics>) {
+async fn create_test_rate_limiter() -> (RateLimiterHandle, Arc<Metrics>) {
     let metrics = Arc::new(Metrics::new());
     let store_config = crate::config::StoreConfig {
         store_type: StoreType::Periodic,
         capacity: 10000,
         cleanup_interval: 300,
         cleanup_probability: 10000,
+        cleanup_batch: 128,
         min_interval: 5,
         max_interval: 300,
         max_operations: 1000000,
+        latency_budget_us: 1_000,
+        max_ttl: 0,
+        ttl_multiplier: 1.0,
+        max_keys: 0,
+        max_memory_mb: 0,
+        on_full: crate::config::OnFull::Reject,
+        clock: crate::config::ClockType::System,
+        wal: None,
+        snapshot: None,
+        canary: None,
+        shards: 1,
+        redis_url: None,
+        coalesce_window_us: 0,
+        backpressure: crate::config::Backpressure::Block,
+        micro_cache_ms: 0,
+        stats_log_interval: 0,
+        hot_keys: 0,
+        namespace_quotas: Default::default(),
+        map_shards: 1,
     };
-    let handle = store::create_rate_limiter(&store_config, 10000, metrics.clone());
+    let handle = store::create_rate_limiter(&store_config, 10000, metrics.clone())
+        .await
+        .unwrap();
     (handle, metrics)
 }
 
@@ -60,13 +86,15 @@ struct ThrottleResponse {
     remaining: i64,
     reset_after: i64,
     retry_after: i64,
+    cacheable_until_ms: i64,
+    warning: bool,
 }
 
 impl ThrottleResponse {
     fn from_resp(response: &RespValue) -> Self {
         match response {
             RespValue::Array(values) => {
-                assert_eq!(values.len(), 5, "Throttle response should have 5 elements");
+                assert_eq!(values.len(), 9, "Throttle response should have 9 elements");
                 Self {
                     allowed: match &values[0] {
                         RespValue::Integer(n) => *n == 1,
@@ -88,6 +116,14 @@ impl ThrottleResponse {
                         RespValue::Integer(n) => *n,
                         _ => panic!("Expected integer for retry_after field"),
                     },
+                    cacheable_until_ms: match &values[7] {
+                        RespValue::Integer(n) => *n,
+                        _ => panic!("Expected integer for cacheable_until_ms field"),
+                    },
+                    warning: match &values[8] {
+                        RespValue::Integer(n) => *n == 1,
+                        _ => panic!("Expected integer for warning field"),
+                    },
                 }
             }
             _ => panic!("Expected array response for throttle command"),
@@ -97,7 +133,7 @@ impl ThrottleResponse {
 
 #[tokio::test]
 async fn test_redis_ping() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     let ping_cmd = create_ping_cmd(None);
     let response = process_command(ping_cmd, &handle, &metrics).await;
@@ -106,7 +142,7 @@ async fn test_redis_ping() {
 
 #[tokio::test]
 async fn test_redis_ping_with_message() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     let ping_cmd = create_ping_cmd(Some("hello"));
     let response = process_command(ping_cmd, &handle, &metrics).await;
@@ -115,7 +151,7 @@ async fn test_redis_ping_with_message() {
 
 #[tokio::test]
 async fn test_redis_throttle_allowed() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     let throttle_cmd = create_throttle_cmd("test_key", 10, 100, 60, None);
     let response = process_command(throttle_cmd, &handle, &metrics).await;
@@ -126,11 +162,19 @@ async fn test_redis_throttle_allowed() {
     assert_eq!(throttle_resp.remaining, 9);
     assert_eq!(throttle_resp.reset_after, 5);
     assert_eq!(throttle_resp.retry_after, 0);
+
+    // 9 tokens at 0.6s each
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64;
+    let cacheable_ms = throttle_resp.cacheable_until_ms - now_ms;
+    assert!((5_000..=5_400).contains(&cacheable_ms), "{cacheable_ms}");
 }
 
 #[tokio::test]
 async fn test_redis_throttle_with_quantity() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     let throttle_cmd = create_throttle_cmd("test_key2", 10, 100, 60, Some(5));
     let response = process_command(throttle_cmd, &handle, &metrics).await;
@@ -143,9 +187,322 @@ async fn test_redis_throttle_with_quantity() {
     assert_eq!(throttle_resp.retry_after, 0);
 }
 
+#[tokio::test]
+async fn test_redis_throttle_policy() {
+    let (handle, metrics) = create_test_rate_limiter().await;
+    let policies = Policies::parse(
+        "[login]\nmax_burst = 3\ncount_per_period = 6\nperiod = 60\nwarn_percent = 60\n",
+    )
+    .unwrap();
+    let handle = handle.with_policies(Arc::new(policies));
+
+    let throttle_cmd = create_invalid_cmd("THROTTLE", vec!["policy_key", "policy", "login"]);
+    let response = process_command(throttle_cmd, &handle, &metrics).await;
+    let throttle_resp = ThrottleResponse::from_resp(&response);
+    assert!(throttle_resp.allowed);
+    assert_eq!(throttle_resp.limit, 3);
+    assert_eq!(throttle_resp.remaining, 2);
+    assert!(!throttle_resp.warning);
+
+    // Past the soft limit of 60% of the burst
+    let throttle_cmd = create_invalid_cmd("THROTTLE", vec!["policy_key", "POLICY", "login", "2"]);
+    let response = process_command(throttle_cmd, &handle, &metrics).await;
+    let throttle_resp = ThrottleResponse::from_resp(&response);
+    assert_eq!(throttle_resp.remaining, 0);
+    assert!(throttle_resp.allowed && throttle_resp.warning);
+    assert_eq!(
+        metrics
+            .soft_limit_warnings
+            .load(std::sync::atomic::Ordering::Relaxed),
+        1
+    );
+
+    let throttle_cmd = create_invalid_cmd("THROTTLE", vec!["policy_key", "POLICY", "signup"]);
+    let response = process_command(throttle_cmd, &handle, &metrics).await;
+    assert_error_response(&response, "unknown_policy");
+
+    let throttle_cmd = create_invalid_cmd("THROTTLE", vec!["policy_key", "POLICY"]);
+    let response = process_command(throttle_cmd, &handle, &metrics).await;
+    assert_error_response(&response, "wrong number of arguments");
+}
+
+#[tokio::test]
+async fn test_redis_throttle_key_pattern() {
+    let (handle, metrics) = create_test_rate_limiter().await;
+    let policies = Policies::parse(
+        "[login]\nmax_burst = 3\ncount_per_period = 6\nperiod = 60\nkeys = [\"login:*\"]\n",
+    )
+    .unwrap();
+    let handle = handle.with_policies(Arc::new(policies));
+
+    let throttle_cmd = create_invalid_cmd("THROTTLE", vec!["login:alice"]);
+    let response = process_command(throttle_cmd, &handle, &metrics).await;
+    let throttle_resp = ThrottleResponse::from_resp(&response);
+    assert!(throttle_resp.allowed);
+    assert_eq!(throttle_resp.limit, 3);
+    assert_eq!(throttle_resp.remaining, 2);
+
+    let throttle_cmd = create_invalid_cmd("THROTTLE", vec!["login:alice", "2"]);
+    let response = process_command(throttle_cmd, &handle, &metrics).await;
+    assert_eq!(ThrottleResponse::from_resp(&response).remaining, 0);
+
+    let peek_cmd = create_invalid_cmd("THROTTLE.PEEK", vec!["login:alice"]);
+    let response = process_command(peek_cmd, &handle, &metrics).await;
+    assert_eq!(ThrottleResponse::from_resp(&response).remaining, 0);
+
+    // Parameters sent with the key take precedence over its pattern
+    let throttle_cmd = create_invalid_cmd("THROTTLE", vec!["login:bob", "10", "20", "60"]);
+    let response = process_command(throttle_cmd, &handle, &metrics).await;
+    assert_eq!(ThrottleResponse::from_resp(&response).limit, 10);
+}
+
+#[tokio::test]
+async fn test_redis_throttle_operation() {
+    let (handle, metrics) = create_test_rate_limiter().await;
+    let policies = Policies::parse(
+        "[api]\nmax_burst = 10\ncount_per_period = 10\nperiod = 60\n\
+         costs = { read = 1, export = 6 }\n",
+    )
+    .unwrap();
+    let handle = handle.with_policies(Arc::new(policies));
+
+    let throttle_cmd = create_invalid_cmd(
+        "THROTTLE",
+        vec!["operation_key", "POLICY", "api", "OPERATION", "export"],
+    );
+    let response = process_command(throttle_cmd, &handle, &metrics).await;
+    let throttle_resp = ThrottleResponse::from_resp(&response);
+    assert!(throttle_resp.allowed);
+    assert_eq!(throttle_resp.remaining, 4);
+
+    let peek_cmd = create_invalid_cmd(
+        "THROTTLE.PEEK",
+        vec!["operation_key", "POLICY", "api", "operation", "export"],
+    );
+    let response = process_command(peek_cmd, &handle, &metrics).await;
+    assert!(!ThrottleResponse::from_resp(&response).allowed);
+
+    let throttle_cmd = create_invalid_cmd(
+        "THROTTLE",
+        vec!["operation_key", "POLICY", "api", "OPERATION", "delete"],
+    );
+    let response = process_command(throttle_cmd, &handle, &metrics).await;
+    assert_error_response(&response, "unknown_operation");
+
+    let throttle_cmd = create_invalid_cmd(
+        "THROTTLE",
+        vec!["operation_key", "POLICY", "api", "OPERATION"],
+    );
+    let response = process_command(throttle_cmd, &handle, &metrics).await;
+    assert_error_response(&response, "wrong number of arguments");
+}
+
+#[tokio::test]
+async fn test_redis_throttle_peek() {
+    let (handle, metrics) = create_test_rate_limiter().await;
+
+    let throttle_cmd = create_throttle_cmd("peek_key", 10, 100, 60, Some(3));
+    process_command(throttle_cmd, &handle, &metrics).await;
+
+    // Peeking twice reports the same state: nothing is consumed
+    for _ in 0..2 {
+        let peek_cmd = create_invalid_cmd("THROTTLE.PEEK", vec!["peek_key", "10", "100", "60"]);
+        let response = process_command(peek_cmd, &handle, &metrics).await;
+        let peek_resp = ThrottleResponse::from_resp(&response);
+        assert!(peek_resp.allowed);
+        assert_eq!(peek_resp.remaining, 7);
+    }
+
+    // With a quantity, whether that many would be allowed
+    let peek_cmd = create_invalid_cmd("THROTTLE.PEEK", vec!["peek_key", "10", "100", "60", "8"]);
+    let response = process_command(peek_cmd, &handle, &metrics).await;
+    assert!(!ThrottleResponse::from_resp(&response).allowed);
+
+    assert_eq!(
+        metrics
+            .total_requests
+            .load(std::sync::atomic::Ordering::Relaxed),
+        1
+    );
+    assert_eq!(
+        metrics
+            .peek_requests
+            .load(std::sync::atomic::Ordering::Relaxed),
+        3
+    );
+
+    let peek_cmd = create_invalid_cmd("THROTTLE.PEEK", vec!["peek_key", "10", "20"]);
+    let response = process_command(peek_cmd, &handle, &metrics).await;
+    assert_error_response(&response, "wrong number of arguments for 'throttle.peek'");
+}
+
+#[tokio::test]
+async fn test_redis_throttle_reset() {
+    let (handle, metrics) = create_test_rate_limiter().await;
+
+    let throttle_cmd = create_throttle_cmd("reset_key", 2, 10, 60, Some(2));
+    let response = process_command(throttle_cmd, &handle, &metrics).await;
+    assert_eq!(ThrottleResponse::from_resp(&response).remaining, 0);
+
+    let reset_cmd = create_invalid_cmd("THROTTLE.RESET", vec!["reset_key"]);
+    let response = process_command(reset_cmd, &handle, &metrics).await;
+    assert_eq!(response, RespValue::Integer(1));
+
+    let throttle_cmd = create_throttle_cmd("reset_key", 2, 10, 60, None);
+    let response = process_command(throttle_cmd, &handle, &metrics).await;
+    assert!(ThrottleResponse::from_resp(&response).allowed);
+
+    let reset_cmd = create_invalid_cmd("THROTTLE.RESET", vec!["unknown_key"]);
+    let response = process_command(reset_cmd, &handle, &metrics).await;
+    assert_eq!(response, RespValue::Integer(0));
+
+    let reset_cmd = create_invalid_cmd("THROTTLE.RESET", vec![]);
+    let response = process_command(reset_cmd, &handle, &metrics).await;
+    assert_error_response(&response, "wrong number of arguments");
+}
+
+#[tokio::test]
+async fn test_redis_acquire_release() {
+    let (handle, metrics) = create_test_rate_limiter().await;
+    let acquire = || create_invalid_cmd("ACQUIRE", vec!["lease_key", "1", "30"]);
+
+    let response = process_command(acquire(), &handle, &metrics).await;
+    let RespValue::Array(values) = response else {
+        panic!("expected an array, got {response:?}");
+    };
+    assert_eq!(values[0], RespValue::Integer(1));
+    let RespValue::Integer(lease_id) = values[1] else {
+        panic!("expected a lease id, got {:?}", values[1]);
+    };
+    assert!(lease_id > 0);
+    assert_eq!(values[2..], [1, 1, 0].map(RespValue::Integer));
+
+    // At the limit: no lease, and the wait for the oldest one to expire
+    let response = process_command(acquire(), &handle, &metrics).await;
+    assert_eq!(
+        response,
+        RespValue::Array([0, 0, 1, 1, 30].map(RespValue::Integer).to_vec())
+    );
+
+    let lease = lease_id.to_string();
+    let release = create_invalid_cmd("RELEASE", vec!["lease_key", &lease]);
+    let response = process_command(release.clone(), &handle, &metrics).await;
+    assert_eq!(response, RespValue::Integer(1));
+    let response = process_command(release, &handle, &metrics).await;
+    assert_eq!(response, RespValue::Integer(0));
+
+    let response = process_command(acquire(), &handle, &metrics).await;
+    assert!(matches!(&response, RespValue::Array(values) if values[0] == RespValue::Integer(1)));
+    assert_eq!(
+        metrics
+            .total_requests
+            .load(std::sync::atomic::Ordering::Relaxed),
+        0
+    );
+
+    let invalid = create_invalid_cmd("ACQUIRE", vec!["lease_key", "0", "30"]);
+    let response = process_command(invalid, &handle, &metrics).await;
+    assert_error_response(&response, "invalid_max_concurrent");
+    let invalid = create_invalid_cmd("RELEASE", vec!["lease_key", "-1"]);
+    let response = process_command(invalid, &handle, &metrics).await;
+    assert_error_response(&response, "invalid lease_id");
+    let invalid = create_invalid_cmd("ACQUIRE", vec!["lease_key", "1"]);
+    let response = process_command(invalid, &handle, &metrics).await;
+    assert_error_response(&response, "wrong number of arguments for 'acquire'");
+}
+
+#[tokio::test]
+async fn test_redis_cell_throttle() {
+    let (handle, metrics) = create_test_rate_limiter().await;
+    let cell_cmd = || create_invalid_cmd("CL.THROTTLE", vec!["cell_key", "1", "10", "60"]);
+
+    // redis-cell's max_burst of 1 allows two requests at once
+    for remaining in [1, 0] {
+        let response = process_command(cell_cmd(), &handle, &metrics).await;
+        let RespValue::Array(values) = response else {
+            panic!("expected array, got {response:?}");
+        };
+        assert_eq!(
+            values[..4],
+            [
+                RespValue::Integer(0),
+                RespValue::Integer(2),
+                RespValue::Integer(remaining),
+                RespValue::Integer(-1),
+            ]
+        );
+        assert!(matches!(values[4], RespValue::Integer(reset) if reset > 0));
+    }
+
+    let response = process_command(cell_cmd(), &handle, &metrics).await;
+    let RespValue::Array(values) = response else {
+        panic!("expected array, got {response:?}");
+    };
+    assert_eq!(values[0], RespValue::Integer(1));
+    assert_eq!(values[2], RespValue::Integer(0));
+    assert!(matches!(values[3], RespValue::Integer(retry) if retry > 0));
+
+    assert_eq!(
+        metrics
+            .requests_denied
+            .load(std::sync::atomic::Ordering::Relaxed),
+        1
+    );
+
+    let cell_cmd = create_invalid_cmd("cl.throttle", vec!["cell_key", "1", "10"]);
+    let response = process_command(cell_cmd, &handle, &metrics).await;
+    assert_error_response(&response, "wrong number of arguments for 'cl.throttle'");
+}
+
+#[tokio::test]
+async fn test_redis_handshake_commands() {
+    let (handle, metrics) = create_test_rate_limiter().await;
+
+    let hello = create_invalid_cmd("HELLO", vec!["2", "SETNAME", "app"]);
+    let RespValue::Array(fields) = process_command(hello, &handle, &metrics).await else {
+        panic!("expected HELLO fields");
+    };
+    let proto = fields
+        .iter()
+        .position(|field| *field == RespValue::BulkString(Some("proto".to_string())))
+        .unwrap();
+    assert_eq!(fields[proto + 1], RespValue::Integer(2));
+
+    let hello = create_invalid_cmd("HELLO", vec!["3"]);
+    let response = process_command(hello, &handle, &metrics).await;
+    assert!(matches!(response, RespValue::Error(e) if e.starts_with("NOPROTO")));
+    let hello = create_invalid_cmd("HELLO", vec!["2", "BOGUS"]);
+    let response = process_command(hello, &handle, &metrics).await;
+    assert_error_response(&response, "Syntax error in HELLO option 'BOGUS'");
+
+    let throttle_cmd = create_throttle_cmd("info_key", 10, 100, 60, None);
+    process_command(throttle_cmd, &handle, &metrics).await;
+    let info = create_invalid_cmd("INFO", vec![]);
+    let RespValue::BulkString(Some(info)) = process_command(info, &handle, &metrics).await else {
+        panic!("expected INFO text");
+    };
+    assert!(info.starts_with("# Server\r\nredis_version:"));
+    assert!(info.contains("\r\nloading:0\r\n"));
+    assert!(info.contains("\r\nthrottlecrab_requests_denied:0\r\n"));
+
+    let info = create_invalid_cmd("INFO", vec!["persistence"]);
+    let response = process_command(info, &handle, &metrics).await;
+    assert_eq!(
+        response,
+        RespValue::BulkString(Some("# Persistence\r\nloading:0\r\n".to_string()))
+    );
+
+    let command = create_invalid_cmd("COMMAND", vec!["DOCS"]);
+    let response = process_command(command, &handle, &metrics).await;
+    assert_eq!(response, RespValue::Array(Vec::new()));
+    let command = create_invalid_cmd("COMMAND", vec!["COUNT"]);
+    let response = process_command(command, &handle, &metrics).await;
+    assert!(matches!(response, RespValue::Integer(count) if count > 0));
+}
+
 #[tokio::test]
 async fn test_redis_unknown_command() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     let unknown_cmd = create_invalid_cmd("UNKNOWN", vec![]);
     let response = process_command(unknown_cmd, &handle, &metrics).await;
@@ -154,12 +511,17 @@ async fn test_redis_unknown_command() {
 
 #[tokio::test]
 async fn test_redis_invalid_throttle_args() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     // Too few arguments
-    let throttle_cmd = create_invalid_cmd("THROTTLE", vec!["test_key"]);
+    let throttle_cmd = create_invalid_cmd("THROTTLE", vec!["test_key", "10", "20"]);
     let response = process_command(throttle_cmd, &handle, &metrics).await;
     assert_error_response(&response, "wrong number of arguments");
+
+    // A bare key needs a policy matching it
+    let throttle_cmd = create_invalid_cmd("THROTTLE", vec!["test_key"]);
+    let response = process_command(throttle_cmd, &handle, &metrics).await;
+    assert_error_response(&response, "max_burst");
 }
 
 #[tokio::test]
@@ -265,12 +627,12 @@ async fn process_command(
     limiter: &RateLimiterHandle,
     metrics: &Arc<Metrics>,
 ) -> RespValue {
-    super::redis::process_command(value, limiter, metrics).await
+    super::redis::process_command(value, limiter, metrics, None).await
 }
 
 #[tokio::test]
 async fn test_redis_throttle_exhaustion() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     let key = "exhaustion_test";
     let max_burst = 3;
@@ -305,7 +667,7 @@ async fn test_redis_throttle_exhaustion() {
 
 #[tokio::test]
 async fn test_redis_multiple_keys() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     // Test with three different keys
     let keys = vec!["user:123", "user:456", "api:endpoint"];
@@ -331,7 +693,7 @@ async fn test_redis_multiple_keys() {
 
 #[tokio::test]
 async fn test_redis_different_limits_same_key() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     let key = "dynamic_limit_key";
 
@@ -382,21 +744,51 @@ async fn test_redis_different_limits_same_key() {
 
 #[tokio::test]
 async fn test_redis_large_quantity() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     // Request with quantity larger than limit
     let throttle_cmd = create_throttle_cmd("large_quantity_key", 10, 100, 60, Some(15));
     let response = process_command(throttle_cmd, &handle, &metrics).await;
 
-    let resp = ThrottleResponse::from_resp(&response);
-    assert!(!resp.allowed); // denied
-    assert_eq!(resp.limit, 10);
-    assert_eq!(resp.remaining, 10); // remaining unchanged
+    // Quantity above max_burst can never be allowed, so it is rejected
+    match response {
+        RespValue::Error(msg) => {
+            assert!(msg.starts_with("ERR quantity_exceeds_burst:"), "{msg}")
+        }
+        _ => panic!("Expected error response"),
+    }
+}
+
+#[tokio::test]
+async fn test_redis_invalid_period() {
+    let (handle, metrics) = create_test_rate_limiter().await;
+
+    let throttle_cmd = create_throttle_cmd("invalid_period_key", 10, 100, 0, None);
+    let response = process_command(throttle_cmd, &handle, &metrics).await;
+
+    match response {
+        RespValue::Error(msg) => assert!(msg.starts_with("ERR invalid_period:"), "{msg}"),
+        _ => panic!("Expected error response"),
+    }
+}
+
+#[tokio::test]
+async fn test_redis_key_too_long() {
+    let (handle, metrics) = create_test_rate_limiter().await;
+
+    let key = "k".repeat(crate::types::MAX_KEY_LENGTH + 1);
+    let throttle_cmd = create_throttle_cmd(&key, 10, 100, 60, None);
+    let response = process_command(throttle_cmd, &handle, &metrics).await;
+
+    match response {
+        RespValue::Error(msg) => assert!(msg.starts_with("ERR key_too_long:"), "{msg}"),
+        _ => panic!("Expected error response"),
+    }
 }
 
 #[tokio::test]
 async fn test_redis_special_characters_in_key() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     // Test keys with special characters
     let special_keys = vec![
@@ -420,7 +812,7 @@ async fn test_redis_special_characters_in_key() {
 
 #[tokio::test]
 async fn test_redis_mixed_commands() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     // PING
     let ping_cmd = RespValue::Array(vec![RespValue::BulkString(Some("PING".to_string()))]);
@@ -474,7 +866,7 @@ async fn test_redis_mixed_commands() {
 
 #[tokio::test]
 async fn test_redis_invalid_numeric_args() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     // Test invalid max_burst
     let throttle_cmd =
@@ -490,7 +882,7 @@ async fn test_redis_invalid_numeric_args() {
 
 #[tokio::test]
 async fn test_redis_zero_quantity() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     // Request with zero quantity
     let throttle_cmd = create_throttle_cmd("zero_quantity_key", 10, 100, 60, Some(0));
@@ -554,7 +946,7 @@ async fn test_resp_integer_args() {
 
 #[tokio::test]
 async fn test_redis_concurrent_same_key() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     let key = "concurrent_key";
     let max_burst = 10;
@@ -605,7 +997,7 @@ async fn test_redis_concurrent_same_key() {
 
 #[tokio::test]
 async fn test_redis_rapid_succession() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     let key = "rapid_key";
     let max_burst = 5;
@@ -631,7 +1023,7 @@ async fn test_redis_rapid_succession() {
 
 #[tokio::test]
 async fn test_redis_empty_key() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     // Test with empty key
     let throttle_cmd = RespValue::Array(vec![
@@ -643,20 +1035,15 @@ async fn test_redis_empty_key() {
     ]);
 
     let response = process_command(throttle_cmd, &handle, &metrics).await;
-    // Empty key should still work
     match response {
-        RespValue::Array(values) => {
-            assert_eq!(values[0], RespValue::Integer(1)); // allowed
-            assert_eq!(values[1], RespValue::Integer(10)); // limit
-            assert_eq!(values[2], RespValue::Integer(9)); // remaining
-        }
-        _ => panic!("Expected array response"),
+        RespValue::Error(msg) => assert!(msg.starts_with("ERR empty_key:"), "{msg}"),
+        _ => panic!("Expected error response"),
     }
 }
 
 #[tokio::test]
 async fn test_redis_null_args() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     // Test with null bulk string
     let throttle_cmd = RespValue::Array(vec![
@@ -676,7 +1063,7 @@ async fn test_redis_null_args() {
 
 #[tokio::test]
 async fn test_redis_boundary_values() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     // Test with max i64 values
     let throttle_cmd = RespValue::Array(vec![
@@ -718,7 +1105,7 @@ async fn test_redis_boundary_values() {
 
 #[tokio::test]
 async fn test_redis_command_case_insensitive() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     // Test various case combinations
     let commands = vec!["ping", "PING", "Ping", "PiNg"];
@@ -762,7 +1149,7 @@ async fn test_redis_command_case_insensitive() {
 
 #[tokio::test]
 async fn test_redis_very_long_key() {
-    let (handle, metrics) = create_test_rate_limiter();
+    let (handle, metrics) = create_test_rate_limiter().await;
 
     // Test with a very long key (1000 characters)
     let long_key = "x".repeat(1000);
@@ -803,3 +1190,211 @@ async fn test_redis_very_long_key() {
         _ => panic!("Expected array response"),
     }
 }
+
+#[tokio::test]
+async fn test_redis_inline_throttle() {
+    let (handle, metrics) = create_test_rate_limiter().await;
+    let mut parser = RespParser::new();
+
+    // As typed into netcat, with a blank line before the command
+    let data = b"\r\nthrottle \"inline key\" 10 100 60\n";
+    let (value, consumed) = parser.parse(data).unwrap().unwrap();
+    assert_eq!(consumed, data.len());
+
+    let response = process_command(value, &handle, &metrics).await;
+    let throttle_resp = ThrottleResponse::from_resp(&response);
+    assert!(throttle_resp.allowed);
+    assert_eq!(throttle_resp.remaining, 9);
+}
+
+fn command(args: &[&str]) -> RespValue {
+    RespValue::Array(
+        args.iter()
+            .map(|arg| RespValue::BulkString(Some(arg.to_string())))
+            .collect(),
+    )
+}
+
+fn error_prefix(response: Option<RespValue>) -> String {
+    match response {
+        Some(RespValue::Error(msg)) => msg.split_whitespace().next().unwrap().to_string(),
+        other => panic!("Expected error response, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_redis_auth_required() {
+    let mut auth = ConnectionAuth::new(Some("secret".into()), None);
+
+    // Everything but AUTH and QUIT is rejected until authenticated
+    let throttle = command(&["THROTTLE", "key", "10", "100", "60"]);
+    assert_eq!(error_prefix(auth.check(&throttle)), "NOAUTH");
+    assert_eq!(error_prefix(auth.check(&command(&["PING"]))), "NOAUTH");
+    assert!(auth.check(&command(&["QUIT"])).is_none());
+
+    assert_eq!(error_prefix(auth.check(&command(&["AUTH"]))), "ERR");
+    assert_eq!(
+        error_prefix(auth.check(&command(&["AUTH", "wrong"]))),
+        "WRONGPASS"
+    );
+    assert_eq!(
+        error_prefix(auth.check(&command(&["AUTH", "admin", "secret"]))),
+        "WRONGPASS"
+    );
+    assert_eq!(error_prefix(auth.check(&throttle)), "NOAUTH");
+
+    assert_eq!(
+        auth.check(&command(&["auth", "secret"])),
+        Some(RespValue::SimpleString("OK".to_string()))
+    );
+    assert!(auth.check(&throttle).is_none());
+    assert!(auth.check(&command(&["PING"])).is_none());
+}
+
+#[test]
+fn test_redis_hello_auth() {
+    let mut auth = ConnectionAuth::new(Some("secret".into()), None);
+    assert_eq!(
+        auth.check(&command(&["HELLO", "2"])),
+        Some(RespValue::Error(
+            "NOAUTH HELLO must be called with the client already authenticated, otherwise the \
+             HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and \
+             select the RESP protocol version at the same time"
+                .to_string()
+        ))
+    );
+    assert_eq!(
+        error_prefix(auth.check(&command(&["HELLO", "2", "AUTH", "default", "wrong"]))),
+        "WRONGPASS"
+    );
+
+    // HELLO AUTH authenticates, then HELLO itself is answered
+    assert!(
+        auth.check(&command(&["HELLO", "2", "AUTH", "default", "secret"]))
+            .is_none()
+    );
+    assert!(auth.check(&command(&["PING"])).is_none());
+    assert!(auth.check(&command(&["HELLO"])).is_none());
+}
+
+#[test]
+fn test_redis_auth_acl_style() {
+    let mut auth = ConnectionAuth::new(Some("secret".into()), None);
+    assert_eq!(
+        auth.check(&command(&["AUTH", "default", "secret"])),
+        Some(RespValue::SimpleString("OK".to_string()))
+    );
+    assert!(auth.check(&command(&["PING"])).is_none());
+}
+
+#[test]
+fn test_redis_auth_without_password() {
+    let mut auth = ConnectionAuth::new(None, None);
+
+    assert!(auth.check(&command(&["PING"])).is_none());
+    assert_eq!(
+        error_prefix(auth.check(&command(&["AUTH", "secret"]))),
+        "ERR"
+    );
+}
+
+#[test]
+fn test_redis_auth_with_api_keys() {
+    let api_keys = ApiKeys::new([
+        ("checkout".to_string(), "k1".to_string()),
+        ("search".to_string(), "k2".to_string()),
+    ])
+    .unwrap();
+    let mut auth = ConnectionAuth::new(None, Some(Arc::new(api_keys)));
+    assert_eq!(error_prefix(auth.check(&command(&["PING"]))), "NOAUTH");
+
+    // A key's name only selects that key
+    assert_eq!(
+        error_prefix(auth.check(&command(&["AUTH", "checkout", "k2"]))),
+        "WRONGPASS"
+    );
+    assert_eq!(
+        error_prefix(auth.check(&command(&["AUTH", "checkout"]))),
+        "WRONGPASS"
+    );
+    assert_eq!(
+        auth.check(&command(&["AUTH", "search", "k2"])),
+        Some(RespValue::SimpleString("OK".to_string()))
+    );
+    assert_eq!(auth.api_key().map(|name| &**name), Some("search"));
+
+    let mut auth = ConnectionAuth::new(
+        None,
+        Some(Arc::new(
+            ApiKeys::new([("checkout".to_string(), "k1".to_string())]).unwrap(),
+        )),
+    );
+    assert_eq!(
+        auth.check(&command(&["AUTH", "k1"])),
+        Some(RespValue::SimpleString("OK".to_string()))
+    );
+    assert!(auth.check(&command(&["PING"])).is_none());
+    assert_eq!(auth.api_key().map(|name| &**name), Some("checkout"));
+}
+
+#[test]
+fn test_command_pacer() {
+    let start = Instant::now();
+    let mut pacer = CommandPacer::new(2);
+    assert_eq!(pacer.delay(start), None);
+    assert_eq!(pacer.delay(start + Duration::from_millis(100)), None);
+
+    // The third command in the same second waits for the next window
+    assert_eq!(
+        pacer.delay(start + Duration::from_millis(250)),
+        Some(Duration::from_millis(750))
+    );
+    assert_eq!(pacer.delay(start + Duration::from_secs(1)), None);
+
+    // 0 disables pacing
+    let mut unlimited = CommandPacer::new(0);
+    assert!((0..1000).all(|_| unlimited.delay(start).is_none()));
+}
+
+#[tokio::test]
+async fn test_redis_connection_limits() {
+    use super::Transport;
+    use super::redis::RedisTransport;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    let (handle, metrics) = create_test_rate_limiter().await;
+    let transport = RedisTransport::new("127.0.0.1", 9194, Arc::clone(&metrics))
+        .unwrap()
+        .with_max_connections(2, 1);
+    tokio::spawn(transport.start(handle));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    async fn ping(socket: &mut TcpStream) -> String {
+        socket.write_all(b"PING\r\n").await.unwrap();
+        let mut reply = vec![0; 64];
+        let n = socket.read(&mut reply).await.unwrap();
+        String::from_utf8_lossy(&reply[..n]).into_owned()
+    }
+
+    let mut first = TcpStream::connect("127.0.0.1:9194").await.unwrap();
+    assert_eq!(ping(&mut first).await, "+PONG\r\n");
+
+    // A second connection from the same IP is over the per-IP cap
+    let mut second = TcpStream::connect("127.0.0.1:9194").await.unwrap();
+    let mut reply = String::new();
+    second.read_to_string(&mut reply).await.unwrap();
+    assert_eq!(reply, "-ERR max number of clients reached\r\n");
+    assert_eq!(
+        metrics
+            .redis_rejected_connections
+            .load(std::sync::atomic::Ordering::Relaxed),
+        1
+    );
+
+    // Closing the first connection frees its slot
+    drop(first);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let mut third = TcpStream::connect("127.0.0.1:9194").await.unwrap();
+    assert_eq!(ping(&mut third).await, "+PONG\r\n");
+}
@@ -0,0 +1,311 @@
+//! UDP transport for fire-and-forget rate limit checks
+//!
+//! For callers where a TCP round trip or connection pool is too costly,
+//! each datagram carries one throttle request as JSON, with the same fields
+//! as `POST /throttle` plus an `id` the reply echoes for correlation:
+//!
+//! ```json
+//! {"id": 42, "key": "user:123", "max_burst": 10, "count_per_period": 100, "period": 60}
+//! ```
+//!
+//! The reply goes back to the sending address as one datagram:
+//!
+//! ```json
+//! {"id": 42, "allowed": true, "limit": 10, "remaining": 9, "reset_after": 5, "retry_after": 0}
+//! ```
+//!
+//! An invalid request is answered with the error and its code instead,
+//! e.g. `{"id": 42, "error": "key must not be empty", "code": "empty_key"}`.
+//! Datagrams that are not a JSON object with an `id` are dropped and
+//! counted as errors.
+//!
+//! Nothing is retransmitted: a lost request or reply is simply lost, and
+//! requests are decided in the order the actors receive them, not the
+//! order they were sent. Clients should time out on a missing reply and
+//! treat the request as they would a failed connection. A lost reply may
+//! still have consumed tokens.
+//!
+//! There is no authentication, since the source address of a datagram can
+//! be forged, so the transport cannot be combined with `--api-keys`; keep
+//! it on a trusted network.
+
+use super::http::{HttpErrorResponse, HttpThrottleRequest, HttpThrottleResponse};
+use super::{Transport, bind_udp, socket_addr};
+use crate::actor::{OverloadedError, RateLimiterHandle};
+use crate::logging::log_request;
+use crate::metrics::{Metrics, Transport as MetricsTransport};
+use crate::namespace::{InvalidNamespaceError, NamespaceFullError};
+use crate::otel;
+use crate::policy::{UnknownOperationError, UnknownPolicyError};
+use crate::types::{RetryHints, ThrottleRequest, ValidationError};
+use crate::windows::{self, InvalidWindowsError};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{Instrument, debug, info};
+
+/// Largest datagram read; anything longer is truncated and fails to parse
+pub const MAX_DATAGRAM_SIZE: usize = 65_507;
+
+/// UDP request format: a `POST /throttle` body with a correlation id
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UdpThrottleRequest {
+    /// Echoed in the reply
+    pub id: u64,
+    /// The rate limit check
+    #[serde(flatten)]
+    pub request: HttpThrottleRequest,
+}
+
+/// UDP reply format for a decided request
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UdpThrottleResponse {
+    /// The request's id
+    pub id: u64,
+    /// The decision, as `POST /throttle` returns it
+    #[serde(flatten)]
+    pub response: HttpThrottleResponse,
+}
+
+/// UDP reply format for a request that could not be decided
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UdpErrorResponse {
+    /// The request's id
+    pub id: u64,
+    /// The error, as `POST /throttle` returns it
+    #[serde(flatten)]
+    pub error: HttpErrorResponse,
+}
+
+/// UDP transport implementation
+///
+/// Answers one JSON datagram with another, without connections.
+pub struct UdpTransport {
+    addr: SocketAddr,
+    metrics: Arc<Metrics>,
+}
+
+impl UdpTransport {
+    pub fn new(host: &str, port: u16, metrics: Arc<Metrics>) -> Result<Self> {
+        let addr = socket_addr(host, port)?;
+        Ok(Self { addr, metrics })
+    }
+}
+
+#[async_trait]
+impl Transport for UdpTransport {
+    async fn start(self, limiter: RateLimiterHandle) -> Result<()> {
+        let socket =
+            bind_udp(self.addr).with_context(|| format!("Failed to bind to {}", self.addr))?;
+        let socket = Arc::new(socket);
+
+        info!("UDP server listening on {}", socket.local_addr()?);
+
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+        loop {
+            let (len, peer) = socket.recv_from(&mut buf).await?;
+            let request = match serde_json::from_slice::<UdpThrottleRequest>(&buf[..len]) {
+                Ok(request) => request,
+                Err(e) => {
+                    debug!("Dropping datagram from {}: {}", peer, e);
+                    self.metrics.record_error(MetricsTransport::Udp);
+                    continue;
+                }
+            };
+
+            // Decide each request on its own task, so a slow actor doesn't
+            // hold up the datagrams behind it
+            let socket = Arc::clone(&socket);
+            let limiter = limiter.clone();
+            let metrics = Arc::clone(&self.metrics);
+            tokio::spawn(async move {
+                let reply = handle_request(request, &limiter, &metrics).await;
+                if let Err(e) = socket.send_to(&reply, peer).await {
+                    debug!("Failed to reply to {}: {}", peer, e);
+                }
+            });
+        }
+    }
+}
+
+/// Decide `request`, returning the reply datagram
+async fn handle_request(
+    request: UdpThrottleRequest,
+    limiter: &RateLimiterHandle,
+    metrics: &Metrics,
+) -> Vec<u8> {
+    let UdpThrottleRequest { id, request: req } = request;
+    // Always use server timestamp
+    let timestamp = limiter.now();
+
+    let started = Instant::now();
+    let result = match internal_request(limiter, &req, timestamp) {
+        Ok(internal_req) => {
+            let span = otel::request_span(MetricsTransport::Udp, None);
+            let result = windows::throttle_or_shed(limiter, internal_req, req.windows.as_deref())
+                .instrument(span)
+                .await;
+            metrics.record_latency(MetricsTransport::Udp, started.elapsed());
+            result
+        }
+        Err(e) => Err(e),
+    };
+
+    let reply = match result {
+        Ok((response, window, _)) => {
+            metrics.record_request_with_key(MetricsTransport::Udp, response.allowed, &req.key);
+            log_request(
+                MetricsTransport::Udp,
+                &req.key,
+                response.allowed,
+                started.elapsed(),
+            );
+            if let Some(namespace) = limiter.namespace(req.namespace.as_deref(), None) {
+                metrics.record_namespace_request(namespace, response.allowed);
+            }
+            let retry_hints = req
+                .retry_hints
+                .unwrap_or(false)
+                .then(|| RetryHints::new(&response, timestamp));
+            serde_json::to_vec(&UdpThrottleResponse {
+                id,
+                response: HttpThrottleResponse {
+                    response,
+                    retry_hints,
+                    window,
+                },
+            })
+        }
+        Err(e) => {
+            metrics.record_error(MetricsTransport::Udp);
+            serde_json::to_vec(&UdpErrorResponse {
+                id,
+                error: error_response(e),
+            })
+        }
+    };
+    reply.expect("replies serialize to JSON")
+}
+
+fn internal_request(
+    limiter: &RateLimiterHandle,
+    req: &HttpThrottleRequest,
+    timestamp: std::time::SystemTime,
+) -> Result<ThrottleRequest> {
+    if req.windows.is_some() && req.policy.is_some() {
+        return Err(InvalidWindowsError::WithPolicy.into());
+    }
+    let mut internal_req = ThrottleRequest {
+        quantity: req.quantity.unwrap_or(1),
+        timestamp,
+        algorithm: req.algorithm.unwrap_or_default(),
+        ..ThrottleRequest::new(
+            Arc::clone(&req.key),
+            req.max_burst,
+            req.count_per_period,
+            req.period,
+        )
+    };
+    limiter.scope(&mut internal_req.key, req.namespace.as_deref(), None)?;
+    limiter.resolve(
+        &mut internal_req,
+        req.policy.as_deref(),
+        req.operation.as_deref(),
+    )?;
+    Ok(internal_req)
+}
+
+/// The error reply for a failed throttle check
+fn error_response(e: anyhow::Error) -> HttpErrorResponse {
+    let code = if let Some(invalid) = e.downcast_ref::<ValidationError>() {
+        Some(invalid.code())
+    } else if let Some(unknown) = e.downcast_ref::<UnknownPolicyError>() {
+        Some(unknown.code())
+    } else if let Some(unknown) = e.downcast_ref::<UnknownOperationError>() {
+        Some(unknown.code())
+    } else if let Some(invalid) = e.downcast_ref::<InvalidWindowsError>() {
+        Some(invalid.code())
+    } else if let Some(invalid) = e.downcast_ref::<InvalidNamespaceError>() {
+        Some(invalid.code())
+    } else if let Some(full) = e.downcast_ref::<NamespaceFullError>() {
+        Some(full.code())
+    } else if let Some(overloaded) = e.downcast_ref::<OverloadedError>() {
+        Some(overloaded.code())
+    } else {
+        tracing::error!("Rate limiter error: {}", e);
+        None
+    };
+    HttpErrorResponse {
+        error: e.to_string(),
+        code: code.map(str::to_string),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor::RateLimiterActor;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+    use tokio::net::UdpSocket;
+    use tokio::time::{sleep, timeout};
+
+    async fn exchange(socket: &UdpSocket, request: &[u8]) -> serde_json::Value {
+        socket.send(request).await.unwrap();
+        let mut buf = [0; 1024];
+        let len = timeout(Duration::from_secs(1), socket.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        serde_json::from_slice(&buf[..len]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_udp_transport() {
+        let metrics = Arc::new(Metrics::new());
+        let limiter = RateLimiterActor::spawn_periodic(
+            1000,
+            throttlecrab::PeriodicStore::new(),
+            Arc::clone(&metrics),
+        );
+        let transport = UdpTransport::new("127.0.0.1", 9196, Arc::clone(&metrics)).unwrap();
+        tokio::spawn(async move {
+            transport.start(limiter).await.unwrap();
+        });
+        sleep(Duration::from_millis(100)).await;
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect("127.0.0.1:9196").await.unwrap();
+
+        let request =
+            br#"{"id": 7, "key": "udp", "max_burst": 2, "count_per_period": 10, "period": 60}"#;
+        let reply = exchange(&socket, request).await;
+        assert_eq!(reply["id"], 7);
+        assert_eq!(reply["allowed"], true);
+        assert_eq!(reply["remaining"], 1);
+
+        // Invalid requests are answered with their error code
+        let reply = exchange(
+            &socket,
+            br#"{"id": 8, "key": "", "max_burst": 2, "count_per_period": 10, "period": 60}"#,
+        )
+        .await;
+        assert_eq!(reply["id"], 8);
+        assert_eq!(reply["code"], "empty_key");
+
+        // Unparseable datagrams get no reply
+        socket.send(b"not json").await.unwrap();
+        let reply = exchange(
+            &socket,
+            br#"{"id": 9, "key": "udp", "max_burst": 2, "count_per_period": 10, "period": 60}"#,
+        )
+        .await;
+        assert_eq!(reply["id"], 9);
+
+        assert_eq!(metrics.udp_requests.load(Ordering::Relaxed), 4);
+        assert_eq!(metrics.requests_errors.load(Ordering::Relaxed), 2);
+    }
+}
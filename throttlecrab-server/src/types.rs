@@ -2,6 +2,8 @@
 //!
 //! This module defines the core request and response types that are
 //! shared between different transport protocols and the actor system.
+//! The request and response types themselves live in [`throttlecrab::types`]
+//! so applications can share them with the server, and are re-exported here.
 //!
 //! # Type Conversions
 //!
@@ -10,88 +12,349 @@
 //!
 //! - **HTTP**: JSON serialization
 //! - **gRPC**: Protocol Buffers
+//!
+//! # Validation
+//!
+//! [`ThrottleRequest::validate`] holds the one rule set every transport
+//! applies before a request reaches the store. Violations are reported as a
+//! [`ValidationError`] with a stable [`code`](ValidationError::code).
 
 use serde::{Deserialize, Serialize};
-use std::time::SystemTime;
-use throttlecrab::RateLimitResult;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub use throttlecrab::types::{
+    AcquireRequest, AcquireResponse, AlgorithmKind, MAX_KEY_LENGTH, ThrottleRequest,
+    ThrottleResponse, ValidationError,
+};
+
+/// Most requests a single batch may hold
+///
+/// Requests queued behind a batch wait for all of it, so batches are kept
+/// small enough not to stall the actor.
+pub const MAX_BATCH_SIZE: usize = 1000;
+
+/// Result of an on-demand store cleanup pass
+///
+/// # Example
+///
+/// ```json
+/// {
+///   "scanned": 120000,
+///   "removed": 45000,
+///   "remaining": 75000,
+///   "complete": true,
+///   "duration_us": 8250,
+///   "finished_at_ms": 1704067200000
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CleanupReport {
+    /// Entries checked for expiry
+    pub scanned: usize,
+    /// Expired entries removed
+    pub removed: usize,
+    /// Entries left in the store after the pass
+    pub remaining: usize,
+    /// False if the time budget ran out before every entry was checked
+    pub complete: bool,
+    /// How long the pass took, in microseconds
+    pub duration_us: u64,
+    /// When the pass finished, in milliseconds since the Unix epoch
+    pub finished_at_ms: i64,
+}
+
+/// Comparison of the primary store against the canary store
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CanaryReport {
+    /// Decisions checked against the canary store
+    pub compared: u64,
+    /// Decisions where both stores returned the same result
+    pub agreed: u64,
+    /// `agreed / compared`, or 1.0 before any comparison
+    pub agreement_rate: f64,
+    /// Most recent divergent decisions, oldest first
+    pub divergences: Vec<CanaryDivergence>,
+}
+
+/// A decision where the canary store disagreed with the primary store
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CanaryDivergence {
+    /// The rate limited key
+    pub key: String,
+    /// Whether the primary store allowed the request (the returned result)
+    pub primary_allowed: bool,
+    /// Whether the canary store allowed the request
+    pub canary_allowed: bool,
+    /// Tokens remaining according to the primary store
+    pub primary_remaining: i64,
+    /// Tokens remaining according to the canary store
+    pub canary_remaining: i64,
+    /// Request time in milliseconds since the Unix epoch
+    pub at_ms: i64,
+}
 
-/// Internal rate limit request structure
+/// Estimated memory usage, broken down by subsystem
 ///
-/// This is the common request format used by all transports
-/// after parsing their protocol-specific formats.
+/// Computed from entry counts and allocation sizes rather than measured, so
+/// it excludes allocator overhead and buffers owned by the HTTP and gRPC
+/// libraries. The store figures walk every key and are refreshed at most
+/// every few seconds; `computed_at_ms` tells how old they are.
 ///
-/// # Fields
+/// # Example
+///
+/// ```json
+/// {
+///   "total_bytes": 21233664,
+///   "store_entries": 75000,
+///   "store_bytes": 16515072,
+///   "canary_store_bytes": 0,
+///   "metrics_bytes": 2621440,
+///   "connection_bytes": 4096,
+///   "queue_bytes": 1920,
+///   "trace_bytes": 2091136,
+///   "computed_at_ms": 1704067200000
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryReport {
+    /// Sum of the figures below
+    pub total_bytes: usize,
+    /// Entries in the primary store, including expired ones not yet removed
+    pub store_entries: usize,
+    /// Primary store: hash table, keys and key index
+    pub store_bytes: usize,
+    /// Canary store, 0 if none is configured
+    pub canary_store_bytes: usize,
+    /// Top denied keys tracking and its queue
+    pub metrics_bytes: usize,
+    /// Redis connection read buffers
+    pub connection_bytes: usize,
+    /// Requests waiting for the rate limiter actor
+    pub queue_bytes: usize,
+    /// Request trace ring buffer
+    pub trace_bytes: usize,
+    /// When the store figures were computed, in milliseconds since the Unix epoch
+    pub computed_at_ms: i64,
+}
+
+/// A key and the throttle requests counted for it
 ///
-/// - `key`: Unique identifier for the rate limit (e.g., "user:123", "api:endpoint")
-/// - `max_burst`: Maximum tokens available at once (burst capacity)
-/// - `count_per_period`: Total tokens replenished per period
-/// - `period`: Time period in seconds for token replenishment
-/// - `quantity`: Number of tokens to consume (typically 1)
-/// - `timestamp`: Request timestamp for consistent rate limiting
-#[derive(Debug, Clone)]
-pub struct ThrottleRequest {
-    /// The key to rate limit (e.g., "user:123", "ip:192.168.1.1")
+/// Listed by `GET /admin/keys/hot`, most requested first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HotKey {
+    /// The key, with its namespace if it has one
     pub key: String,
-    /// Maximum burst capacity (tokens available at once)
-    pub max_burst: i64,
-    /// Tokens replenished per period
-    pub count_per_period: i64,
-    /// Period in seconds for token replenishment
-    pub period: i64,
-    /// Number of tokens to consume (default: 1)
-    pub quantity: i64,
-    /// Request timestamp for consistent rate limiting
-    pub timestamp: SystemTime,
+    /// Throttle requests counted since the key was last among the hottest
+    pub requests: u64,
 }
 
-/// Rate limit response structure
+/// A key's entry in the store
+///
+/// # Example
+///
+/// ```json
+/// {
+///   "key": "user:123",
+///   "tat_ns": 1704067260000000000,
+///   "expires_at_ms": 1704067260000,
+///   "expired": false
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyState {
+    /// The store key
+    pub key: String,
+    /// Theoretical arrival time of a GCRA key, in nanoseconds since the Unix
+    /// epoch; keys of the other algorithms hold their own state here
+    pub tat_ns: i64,
+    /// When the entry expires, in milliseconds since the Unix epoch (None
+    /// if it never does)
+    pub expires_at_ms: Option<i64>,
+    /// Whether the entry has expired and awaits cleanup
+    pub expired: bool,
+}
+
+/// Entry counts and size of the store
+///
+/// Unlike [`MemoryReport`], computed fresh on each request.
 ///
-/// This is the common response format returned by all transports
-/// after checking a rate limit.
+/// # Example
 ///
-/// # Response Interpretation
+/// ```json
+/// {
+///   "entries": 75000,
+///   "expired_entries": 1200,
+///   "bytes": 16515072,
+///   "shard_entries": [37512, 37488],
+///   "computed_at_ms": 1704067200000
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoreStats {
+    /// Entries in the store, including expired ones not yet removed
+    pub entries: usize,
+    /// Entries that have expired and await cleanup
+    pub expired_entries: usize,
+    /// Estimated heap bytes held by the store
+    pub bytes: usize,
+    /// Entries held by each actor, in shard order
+    pub shard_entries: Vec<usize>,
+    /// When the figures were computed, in milliseconds since the Unix epoch
+    pub computed_at_ms: i64,
+}
+
+/// Result of reloading the policy file
 ///
-/// - If `allowed` is true: The request can proceed
-/// - If `allowed` is false: The request should be rejected
-///   - Check `retry_after` to know when to retry
-///   - Check `reset_after` to know when the bucket resets
+/// # Example
+///
+/// ```json
+/// {
+///   "policies": 2,
+///   "reloaded_at_ms": 1704067200000
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReloadReport {
+    /// Policies defined by the reloaded file
+    pub policies: usize,
+    /// When the reload finished, in milliseconds since the Unix epoch
+    pub reloaded_at_ms: i64,
+}
+
+/// Outcome of resetting a key with `DELETE /throttle/{key}`
 ///
 /// # Example
 ///
 /// ```json
 /// {
-///   "allowed": false,
-///   "limit": 10,
-///   "remaining": 0,
-///   "retry_after": 30,
-///   "reset_after": 60
+///   "key": "user:123",
+///   "existed": true,
+///   "reset_at_ms": 1704067200000
 /// }
 /// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResetReport {
+    /// The key that was reset
+    pub key: String,
+    /// Whether the store held state for the key
+    pub existed: bool,
+    /// When the key was reset, in milliseconds since the Unix epoch
+    pub reset_at_ms: i64,
+}
+
+/// Retry time as absolute times
+///
+/// Returned alongside [`ThrottleResponse`] when a client requests retry
+/// hints, so it can pick whichever form avoids clock math on its side.
 ///
-/// This response indicates the request was denied, no tokens remain,
-/// retry in 30 seconds, and the bucket fully resets in 60 seconds.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ThrottleResponse {
-    /// Whether the request is allowed
-    pub allowed: bool,
-    /// Maximum burst capacity
-    pub limit: i64,
-    /// Tokens remaining in the bucket
-    pub remaining: i64,
-    /// Seconds until the bucket fully resets
-    pub reset_after: i64,
-    /// Seconds until the next request can be made (0 if allowed)
-    pub retry_after: i64,
+/// # Example
+///
+/// ```json
+/// {
+///   "retry_at_ms": 1704067201500,
+///   "retry_at": "Mon, 01 Jan 2024 00:00:02 GMT"
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryHints {
+    /// Absolute retry time in milliseconds since the Unix epoch
+    pub retry_at_ms: i64,
+    /// Absolute retry time as an HTTP-date, rounded up to the next second
+    pub retry_at: String,
 }
 
-impl From<(bool, RateLimitResult)> for ThrottleResponse {
-    fn from((allowed, result): (bool, RateLimitResult)) -> Self {
-        ThrottleResponse {
-            allowed,
-            limit: result.limit,
-            remaining: result.remaining,
-            reset_after: result.reset_after.as_secs() as i64,
-            retry_after: result.retry_after.as_secs() as i64,
+impl RetryHints {
+    /// Build retry hints for a response decided at `now`
+    pub fn new(response: &ThrottleResponse, now: SystemTime) -> Self {
+        let now_ms = now
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+        let retry_at_ms = now_ms + response.retry_after_ms;
+        // HTTP-date has second resolution; never point before the real time
+        let retry_at_secs = (retry_at_ms as u64).div_ceil(1000);
+
+        RetryHints {
+            retry_at_ms,
+            retry_at: format_http_date(UNIX_EPOCH + Duration::from_secs(retry_at_secs)),
         }
     }
 }
+
+/// Format a time as an IMF-fixdate (RFC 9110), e.g. `Mon, 01 Jan 2024 00:00:00 GMT`
+fn format_http_date(time: SystemTime) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let days = secs / 86_400;
+    let secs_of_day = secs % 86_400;
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_http_date() {
+        assert_eq!(
+            format_http_date(UNIX_EPOCH),
+            "Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+        assert_eq!(
+            format_http_date(UNIX_EPOCH + Duration::from_secs(1_704_067_200)),
+            "Mon, 01 Jan 2024 00:00:00 GMT"
+        );
+        assert_eq!(
+            format_http_date(UNIX_EPOCH + Duration::from_secs(1_709_210_096)),
+            "Thu, 29 Feb 2024 12:34:56 GMT"
+        );
+    }
+
+    #[test]
+    fn test_retry_hints() {
+        let response = ThrottleResponse {
+            allowed: false,
+            limit: 10,
+            remaining: 0,
+            reset_after: 60,
+            retry_after: 1,
+            retry_after_ms: 1_500,
+            reset_after_ms: 60_000,
+            cacheable_until_ms: 0,
+            warning: false,
+        };
+        let now = UNIX_EPOCH + Duration::from_millis(1_704_067_200_250);
+
+        let hints = RetryHints::new(&response, now);
+        assert_eq!(hints.retry_at_ms, 1_704_067_201_750);
+        assert_eq!(hints.retry_at, "Mon, 01 Jan 2024 00:00:02 GMT");
+    }
+}
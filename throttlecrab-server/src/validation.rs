@@ -0,0 +1,219 @@
+//! Server-wide request limits
+//!
+//! Every request is checked against the rules of
+//! [`ThrottleRequest::validate`] before it reaches the store. The server
+//! can tighten them, as a second line of defense against clients that
+//! send huge keys or periods:
+//!
+//! - `--max-key-length BYTES`: longest key, below the built-in
+//!   [`MAX_KEY_LENGTH`]
+//! - `--max-period SECS`: longest `period`
+//! - `--max-quantity N`: largest `quantity`
+//! - `--key-chars SET`: the only characters keys may contain, as a list of
+//!   ASCII characters and ranges, e.g. `a-zA-Z0-9:_.-`
+//!
+//! The limits apply to the key as stored, so a [namespace](crate::namespace)
+//! prefix counts towards its length, and `/` is always allowed to separate
+//! it. Violations are [`ValidationError`]s, reported by every transport with
+//! their code like any other invalid request, and counted by code in
+//! `throttlecrab_invalid_requests`.
+
+use crate::config::ValidationConfig;
+use crate::namespace::SEPARATOR;
+use crate::types::{AcquireRequest, MAX_KEY_LENGTH, ThrottleRequest, ValidationError};
+use std::fmt;
+use std::str::FromStr;
+
+/// Limits requests must meet on top of the built-in rules
+#[derive(Debug, Clone)]
+pub struct RequestLimits {
+    max_key_length: usize,
+    max_period: Option<i64>,
+    max_quantity: Option<i64>,
+    key_chars: Option<KeyChars>,
+}
+
+impl RequestLimits {
+    /// Limits from `config`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `config.key_chars` is not a valid character set.
+    pub fn new(config: &ValidationConfig) -> Result<Self, InvalidKeyCharsError> {
+        Ok(RequestLimits {
+            max_key_length: config.max_key_length.unwrap_or(MAX_KEY_LENGTH),
+            max_period: config.max_period,
+            max_quantity: config.max_quantity,
+            key_chars: config.key_chars.as_deref().map(str::parse).transpose()?,
+        })
+    }
+
+    /// Check a throttle request against the limits
+    ///
+    /// # Errors
+    ///
+    /// Returns the first limit the request exceeds.
+    pub fn check(&self, request: &ThrottleRequest) -> Result<(), ValidationError> {
+        self.check_key(&request.key)?;
+        if let Some(max) = self.max_period
+            && request.period > max
+        {
+            return Err(ValidationError::PeriodTooLong {
+                period: request.period,
+                max,
+            });
+        }
+        if let Some(max) = self.max_quantity
+            && request.quantity > max
+        {
+            return Err(ValidationError::QuantityTooLarge {
+                quantity: request.quantity,
+                max,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check a lease request's key against the limits
+    ///
+    /// # Errors
+    ///
+    /// Returns the first limit the key exceeds.
+    pub fn check_acquire(&self, request: &AcquireRequest) -> Result<(), ValidationError> {
+        self.check_key(&request.key)
+    }
+
+    fn check_key(&self, key: &str) -> Result<(), ValidationError> {
+        if key.len() > self.max_key_length {
+            return Err(ValidationError::KeyExceedsLimit {
+                len: key.len(),
+                max: self.max_key_length,
+            });
+        }
+        if let Some(chars) = &self.key_chars
+            && let Some(c) = key.chars().find(|&c| c != SEPARATOR && !chars.contains(c))
+        {
+            return Err(ValidationError::InvalidKeyCharacter(c));
+        }
+        Ok(())
+    }
+}
+
+/// The ASCII characters keys may contain
+#[derive(Clone, PartialEq, Eq)]
+pub struct KeyChars([bool; 128]);
+
+impl KeyChars {
+    /// Whether keys may contain `c`
+    pub fn contains(&self, c: char) -> bool {
+        c.is_ascii() && self.0[c as usize]
+    }
+}
+
+impl fmt::Debug for KeyChars {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let chars: String = (0..128u8)
+            .filter(|&b| self.0[b as usize])
+            .map(char::from)
+            .collect();
+        f.debug_tuple("KeyChars").field(&chars).finish()
+    }
+}
+
+impl FromStr for KeyChars {
+    type Err = InvalidKeyCharsError;
+
+    /// Parse a list of characters and `a-z` style ranges; `-` is literal
+    /// at the start or end
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidKeyCharsError(s.to_string());
+        if s.is_empty() || !s.is_ascii() {
+            return Err(invalid());
+        }
+        let bytes = s.as_bytes();
+        let mut allowed = [false; 128];
+        let mut i = 0;
+        while i < bytes.len() {
+            let (first, last) = if i + 2 < bytes.len() && bytes[i + 1] == b'-' {
+                i += 3;
+                (bytes[i - 3], bytes[i - 1])
+            } else {
+                i += 1;
+                (bytes[i - 1], bytes[i - 1])
+            };
+            if first > last {
+                return Err(invalid());
+            }
+            for b in first..=last {
+                allowed[b as usize] = true;
+            }
+        }
+        Ok(KeyChars(allowed))
+    }
+}
+
+/// `--key-chars` is not a list of ASCII characters and ranges
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidKeyCharsError(pub String);
+
+impl fmt::Display for InvalidKeyCharsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid key character set: {:?} (expected ASCII characters and ranges like a-z)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidKeyCharsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_chars() {
+        let chars: KeyChars = "a-z0-9:_-".parse().unwrap();
+        assert!(chars.contains('q'));
+        assert!(chars.contains('7'));
+        assert!(chars.contains(':'));
+        assert!(chars.contains('-'));
+        assert!(!chars.contains('A'));
+        assert!(!chars.contains('é'));
+
+        assert!("".parse::<KeyChars>().is_err());
+        assert!("z-a".parse::<KeyChars>().is_err());
+        assert!("a-zé".parse::<KeyChars>().is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let limits = RequestLimits::new(&ValidationConfig {
+            max_key_length: Some(8),
+            max_period: Some(3600),
+            max_quantity: Some(5),
+            key_chars: Some("a-z:".to_string()),
+        })
+        .unwrap();
+
+        let request = |key| ThrottleRequest::new(key, 10, 10, 60);
+        assert_eq!(limits.check(&request("user:abc")), Ok(()));
+        // The namespace separator is always allowed
+        assert_eq!(limits.check(&request("ns/user")), Ok(()));
+
+        let code = |request| limits.check(&request).unwrap_err().code();
+        assert_eq!(code(request("user:abcd")), "key_too_long");
+        assert_eq!(code(request("user:1")), "invalid_key_character");
+        let long_period = ThrottleRequest {
+            period: 3601,
+            ..request("user")
+        };
+        assert_eq!(code(long_period), "period_too_long");
+        let large = ThrottleRequest {
+            quantity: 6,
+            ..request("user")
+        };
+        assert_eq!(code(large), "quantity_too_large");
+    }
+}
@@ -0,0 +1,554 @@
+//! Write-ahead log for crash-consistent store persistence
+//!
+//! When enabled, every state change made by the rate limiter actor is
+//! appended to a log file. On startup the log is replayed to rebuild the
+//! store, so a restart or crash loses at most the writes that had not been
+//! synced yet.
+//!
+//! # Architecture
+//!
+//! ```text
+//! Actor ──append──> [channel] ──> Writer thread ──> wal file (fsync per policy)
+//! ```
+//!
+//! The actor only enqueues records; a dedicated thread performs the file
+//! I/O so disk latency never stalls rate limit decisions. The number of
+//! queued records is exported as `throttlecrab_wal_lag_records`.
+//!
+//! # Compaction
+//!
+//! The log grows with every allowed request. Once it exceeds the configured
+//! size (and twice the size of the live state), the actor sends a snapshot
+//! of its live entries and the writer replaces the log with it.
+//!
+//! # Format
+//!
+//! An 8-byte header (`TCWAL001`) followed by records of:
+//! `crc32 (u32) | key length (u32) | key | value (i64) | expiry (i64)`,
+//! little-endian, where expiry is nanoseconds since the Unix epoch or
+//! `i64::MIN` for entries without one. Replay stops at the first truncated
+//! or corrupt record.
+//!
+//! A record that has already expired removes its key on replay, so a key
+//! reset by an admin is logged as a record expiring at the Unix epoch.
+
+use crate::actor::StoreType;
+use crate::config::{WalConfig, WalFsync};
+use crate::metrics::Metrics;
+use anyhow::{Context, Result, anyhow};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use throttlecrab::Clock;
+
+const MAGIC: &[u8; 8] = b"TCWAL001";
+
+/// Size of a record without its key
+const RECORD_OVERHEAD: u64 = 24;
+
+/// Expiry value for entries that never expire
+const NO_EXPIRY: i64 = i64::MIN;
+
+pub(crate) type Entry = (String, i64, Option<SystemTime>);
+
+enum WalCommand {
+    Append(Entry),
+    Compact(Vec<Entry>),
+}
+
+/// Actor-side handle to the write-ahead log
+pub(crate) struct Wal {
+    tx: mpsc::Sender<WalCommand>,
+    log_bytes: u64,
+    snapshot_bytes: u64,
+    compact_bytes: u64,
+    clock: Arc<dyn Clock + Send + Sync>,
+    metrics: Arc<Metrics>,
+}
+
+impl Wal {
+    /// Replay the log into `store_type`, compact it, and start the writer
+    ///
+    /// A missing log file is not an error; the server starts empty and
+    /// creates it.
+    pub(crate) fn open(
+        config: &WalConfig,
+        store_type: &mut StoreType,
+        clock: Arc<dyn Clock + Send + Sync>,
+        metrics: Arc<Metrics>,
+    ) -> Result<Self> {
+        let now = clock.now();
+        let replayed = replay(&config.path, |key, value, expiry| {
+            // Entries that expired while the server was down are dead state,
+            // and so are keys that were reset
+            if expiry.is_none_or(|exp| exp > now) {
+                store_type.insert(&key, value, expiry);
+            } else {
+                store_type.remove(&key);
+            }
+        })?;
+        if replayed > 0 {
+            tracing::info!(
+                "Replayed {} WAL records from {}, restored {} keys",
+                replayed,
+                config.path.display(),
+                store_type.len()
+            );
+        }
+
+        // Start from a compacted log holding only the live state
+        let snapshot = store_type.entries();
+        let snapshot_bytes = write_snapshot(&config.path, &snapshot)?;
+        metrics
+            .wal_size_bytes
+            .store(snapshot_bytes, Ordering::Relaxed);
+
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&config.path)
+            .with_context(|| format!("Failed to open WAL {}", config.path.display()))?;
+
+        let (tx, rx) = mpsc::channel();
+        let writer = Writer {
+            path: config.path.clone(),
+            out: BufWriter::new(file),
+            size: snapshot_bytes,
+            fsync: config.fsync,
+            fsync_interval: config.fsync_interval,
+            metrics: Arc::clone(&metrics),
+        };
+        std::thread::Builder::new()
+            .name("throttlecrab-wal".to_string())
+            .spawn(move || writer.run(rx))?;
+
+        Ok(Wal {
+            tx,
+            log_bytes: snapshot_bytes,
+            snapshot_bytes,
+            compact_bytes: config.compact_bytes,
+            clock,
+            metrics,
+        })
+    }
+
+    /// Record the current state of `key` after it was updated
+    pub(crate) fn append(&mut self, store_type: &StoreType, key: &str) {
+        let Some((value, expiry)) = store_type.entry(key) else {
+            return;
+        };
+        self.log_bytes += RECORD_OVERHEAD + key.len() as u64;
+        self.send(WalCommand::Append((key.to_string(), value, expiry)));
+
+        // Compact once the log is large in absolute terms and mostly
+        // superseded records, so a large live state doesn't compact constantly
+        if self.log_bytes > self.compact_bytes.max(self.snapshot_bytes * 2) {
+            let now = self.clock.now();
+            let snapshot: Vec<Entry> = store_type
+                .entries()
+                .into_iter()
+                .filter(|(_, _, expiry)| expiry.is_none_or(|exp| exp > now))
+                .collect();
+            self.snapshot_bytes = MAGIC.len() as u64 + encoded_len(&snapshot);
+            self.log_bytes = self.snapshot_bytes;
+            self.send(WalCommand::Compact(snapshot));
+        }
+    }
+
+    /// Record that `key` was removed from the store
+    pub(crate) fn remove(&mut self, key: &str) {
+        self.log_bytes += RECORD_OVERHEAD + key.len() as u64;
+        self.send(WalCommand::Append((key.to_string(), 0, Some(UNIX_EPOCH))));
+    }
+
+    fn send(&self, command: WalCommand) {
+        self.metrics.wal_lag_records.fetch_add(1, Ordering::Relaxed);
+        if self.tx.send(command).is_err() {
+            self.metrics.wal_lag_records.fetch_sub(1, Ordering::Relaxed);
+            tracing::error!("WAL writer has stopped, state changes are not persisted");
+        }
+    }
+}
+
+struct Writer {
+    path: PathBuf,
+    out: BufWriter<File>,
+    size: u64,
+    fsync: WalFsync,
+    fsync_interval: Duration,
+    metrics: Arc<Metrics>,
+}
+
+impl Writer {
+    fn run(mut self, rx: mpsc::Receiver<WalCommand>) {
+        let mut dirty = false;
+        let mut last_sync = Instant::now();
+
+        loop {
+            // With interval syncing, wake up to sync pending writes even if
+            // no further records arrive
+            let next = match self.fsync {
+                WalFsync::Interval if dirty => {
+                    match rx.recv_timeout(self.fsync_interval.saturating_sub(last_sync.elapsed())) {
+                        Ok(command) => Some(command),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                _ => match rx.recv() {
+                    Ok(command) => Some(command),
+                    Err(_) => break,
+                },
+            };
+
+            if let Some(command) = next {
+                // Group everything already queued into one write and sync
+                let mut batch = vec![command];
+                batch.extend(rx.try_iter());
+                let count = batch.len() as u64;
+                for command in batch {
+                    if let Err(e) = self.apply(command) {
+                        tracing::error!("WAL write failed: {}", e);
+                    }
+                }
+                if let Err(e) = self.out.flush() {
+                    tracing::error!("WAL flush failed: {}", e);
+                }
+                self.metrics
+                    .wal_lag_records
+                    .fetch_sub(count, Ordering::Relaxed);
+                self.metrics
+                    .wal_size_bytes
+                    .store(self.size, Ordering::Relaxed);
+                dirty = true;
+            }
+
+            let sync_due = match self.fsync {
+                WalFsync::Always => dirty,
+                WalFsync::Interval => dirty && last_sync.elapsed() >= self.fsync_interval,
+                WalFsync::Never => false,
+            };
+            if sync_due {
+                if let Err(e) = self.out.get_ref().sync_data() {
+                    tracing::error!("WAL sync failed: {}", e);
+                }
+                dirty = false;
+                last_sync = Instant::now();
+            }
+        }
+
+        // The actor is gone; make everything durable before exiting
+        if let Err(e) = self
+            .out
+            .flush()
+            .and_then(|_| self.out.get_ref().sync_data())
+        {
+            tracing::error!("WAL final sync failed: {}", e);
+        }
+    }
+
+    fn apply(&mut self, command: WalCommand) -> Result<()> {
+        match command {
+            WalCommand::Append((key, value, expiry)) => {
+                let record = encode_record(&key, value, expiry);
+                self.out.write_all(&record)?;
+                self.size += record.len() as u64;
+            }
+            WalCommand::Compact(snapshot) => {
+                self.out.flush()?;
+                self.size = write_snapshot(&self.path, &snapshot)?;
+                let file = OpenOptions::new().append(true).open(&self.path)?;
+                self.out = BufWriter::new(file);
+                tracing::debug!(
+                    "Compacted WAL to {} keys ({} bytes)",
+                    snapshot.len(),
+                    self.size
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Atomically replace the log at `path` with a snapshot, returning its size
+pub(crate) fn write_snapshot(path: &Path, entries: &[Entry]) -> Result<u64> {
+    let tmp_path = path.with_extension("compact");
+    let file = File::create(&tmp_path)
+        .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+    let mut out = BufWriter::new(file);
+    out.write_all(MAGIC)?;
+    for (key, value, expiry) in entries {
+        out.write_all(&encode_record(key, *value, *expiry))?;
+    }
+    let file = out.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to replace WAL {}", path.display()))?;
+    Ok(MAGIC.len() as u64 + encoded_len(entries))
+}
+
+fn encoded_len(entries: &[Entry]) -> u64 {
+    entries
+        .iter()
+        .map(|(key, _, _)| RECORD_OVERHEAD + key.len() as u64)
+        .sum()
+}
+
+pub(crate) fn encode_record(key: &str, value: i64, expiry: Option<SystemTime>) -> Vec<u8> {
+    let expiry = expiry
+        .map(|exp| {
+            exp.duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos().min(i64::MAX as u128) as i64)
+        })
+        .unwrap_or(NO_EXPIRY);
+
+    let mut record = Vec::with_capacity(RECORD_OVERHEAD as usize + key.len());
+    record.extend_from_slice(&[0; 4]);
+    record.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    record.extend_from_slice(key.as_bytes());
+    record.extend_from_slice(&value.to_le_bytes());
+    record.extend_from_slice(&expiry.to_le_bytes());
+    let crc = crc32fast::hash(&record[4..]);
+    record[..4].copy_from_slice(&crc.to_le_bytes());
+    record
+}
+
+/// Read all valid records from the log at `path`, returning how many were read
+pub(crate) fn replay(
+    path: &Path,
+    apply: impl FnMut(String, i64, Option<SystemTime>),
+) -> Result<u64> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to open WAL {}", path.display()));
+        }
+    };
+    let mut reader = BufReader::new(file);
+
+    let mut magic = [0u8; 8];
+    match reader.read_exact(&mut magic) {
+        Ok(()) if &magic == MAGIC => {}
+        Ok(()) => return Err(anyhow!("{} is not a throttlecrab WAL", path.display())),
+        // An empty or truncated header means nothing was ever logged
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(0),
+        Err(e) => return Err(e.into()),
+    }
+
+    read_records(reader, "WAL", apply)
+}
+
+/// Read records from `reader` until it ends or a record is corrupt,
+/// returning how many were read
+///
+/// `source` names the records' origin in warnings.
+pub(crate) fn read_records(
+    mut reader: impl Read,
+    source: &str,
+    mut apply: impl FnMut(String, i64, Option<SystemTime>),
+) -> Result<u64> {
+    let mut count = 0;
+    let mut header = [0u8; 8];
+    loop {
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+        let crc = u32::from_le_bytes(header[..4].try_into().unwrap());
+        let key_len = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+
+        let mut body = vec![0u8; key_len + 16];
+        if let Err(e) = reader.read_exact(&mut body) {
+            if e.kind() == ErrorKind::UnexpectedEof {
+                tracing::warn!("{} ends with a truncated record, ignoring it", source);
+                break;
+            }
+            return Err(e.into());
+        }
+
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&header[4..]);
+        hasher.update(&body);
+        if hasher.finalize() != crc {
+            tracing::warn!(
+                "{} record {} failed its checksum, ignoring the rest",
+                source,
+                count
+            );
+            break;
+        }
+
+        let Ok(key) = String::from_utf8(body[..key_len].to_vec()) else {
+            tracing::warn!(
+                "{} record {} has an invalid key, ignoring the rest",
+                source,
+                count
+            );
+            break;
+        };
+        let value = i64::from_le_bytes(body[key_len..key_len + 8].try_into().unwrap());
+        let expiry = i64::from_le_bytes(body[key_len + 8..].try_into().unwrap());
+        let expiry =
+            (expiry != NO_EXPIRY).then(|| UNIX_EPOCH + Duration::from_nanos(expiry as u64));
+
+        apply(key, value, expiry);
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn temp_wal(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "throttlecrab-wal-{}-{}.log",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn read_all(path: &Path) -> HashMap<String, (i64, Option<SystemTime>)> {
+        let mut entries = HashMap::new();
+        replay(path, |key, value, expiry| {
+            entries.insert(key, (value, expiry));
+        })
+        .unwrap();
+        entries
+    }
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let path = temp_wal("roundtrip");
+        let expiry = UNIX_EPOCH + Duration::from_nanos(1_700_000_000_123_456_789);
+        let entries = vec![
+            ("user:1".to_string(), 42, Some(expiry)),
+            ("user:2".to_string(), -7, None),
+        ];
+
+        let size = write_snapshot(&path, &entries).unwrap();
+        assert_eq!(size, fs::metadata(&path).unwrap().len());
+
+        let restored = read_all(&path);
+        assert_eq!(restored["user:1"], (42, Some(expiry)));
+        assert_eq!(restored["user:2"], (-7, None));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_replay_later_records_win_and_torn_tail_ignored() {
+        let path = temp_wal("torn");
+        write_snapshot(&path, &[("key".to_string(), 1, None)]).unwrap();
+
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&encode_record("key", 2, None)).unwrap();
+        // Simulate a crash in the middle of a write
+        let partial = encode_record("other", 3, None);
+        file.write_all(&partial[..partial.len() - 5]).unwrap();
+        drop(file);
+
+        let restored = read_all(&path);
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored["key"], (2, None));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_state_survives_restart() {
+        use crate::config::{Backpressure, OnFull, StoreConfig, StoreType as ConfigStoreType};
+        use crate::types::ThrottleRequest;
+
+        let path = temp_wal("restart");
+        let config = StoreConfig {
+            store_type: ConfigStoreType::Periodic,
+            capacity: 1000,
+            cleanup_interval: 300,
+            cleanup_probability: 10_000,
+            cleanup_batch: 128,
+            min_interval: 5,
+            max_interval: 300,
+            max_operations: 1_000_000,
+            latency_budget_us: 1_000,
+            max_ttl: 0,
+            ttl_multiplier: 1.0,
+            max_keys: 0,
+            max_memory_mb: 0,
+            on_full: OnFull::Reject,
+            clock: crate::config::ClockType::System,
+            wal: Some(WalConfig {
+                path: path.clone(),
+                fsync: WalFsync::Always,
+                fsync_interval: Duration::from_secs(1),
+                compact_bytes: 1024 * 1024,
+            }),
+            snapshot: None,
+            canary: None,
+            shards: 1,
+            redis_url: None,
+            coalesce_window_us: 0,
+            backpressure: Backpressure::Block,
+            micro_cache_ms: 0,
+            stats_log_interval: 0,
+            hot_keys: 0,
+            namespace_quotas: Default::default(),
+            map_shards: 1,
+        };
+        let request = ThrottleRequest::new("user:1", 5, 5, 3600);
+
+        let metrics = Arc::new(Metrics::new());
+        let limiter = crate::store::create_rate_limiter(&config, 100, Arc::clone(&metrics))
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            assert!(limiter.throttle(request.clone()).await.unwrap().allowed);
+        }
+        while metrics.wal_lag_records.load(Ordering::Relaxed) > 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        // A second server started from the same log continues where the first stopped
+        let metrics = Arc::new(Metrics::new());
+        let restarted = crate::store::create_rate_limiter(&config, 100, Arc::clone(&metrics))
+            .await
+            .unwrap();
+        let response = restarted.throttle(request.clone()).await.unwrap();
+        assert!(response.allowed);
+        assert_eq!(response.remaining, 1);
+
+        // Resets are logged too, so a reset key stays cleared
+        let report = restarted
+            .reset("user:1".into(), crate::metrics::Transport::Http)
+            .await
+            .unwrap();
+        assert!(report.existed);
+        while metrics.wal_lag_records.load(Ordering::Relaxed) > 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        let again = crate::store::create_rate_limiter(&config, 100, Arc::new(Metrics::new()))
+            .await
+            .unwrap();
+        let response = again.throttle(request).await.unwrap();
+        assert_eq!(response.remaining, 4);
+
+        drop(limiter);
+        drop(restarted);
+        drop(again);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_replay_missing_file() {
+        let path = temp_wal("missing");
+        assert!(read_all(&path).is_empty());
+    }
+}
@@ -0,0 +1,237 @@
+//! Multi-window limits
+//!
+//! A throttle request may carry several windows for its key instead of a
+//! single `max_burst`, `count_per_period` and `period`, e.g. 10 per second
+//! and 1000 per hour:
+//!
+//! ```json
+//! {
+//!   "key": "user:123",
+//!   "windows": [
+//!     {"max_burst": 10, "count_per_period": 10, "period": 1},
+//!     {"max_burst": 1000, "count_per_period": 1000, "period": 3600}
+//!   ]
+//! }
+//! ```
+//!
+//! The request is allowed only if every window allows it, and then it
+//! consumes its `quantity` from each. The actor checks all windows before
+//! consuming from any, so a denial leaves every window untouched. The
+//! response describes the window that bound the decision: the first to
+//! deny the request, or, if it was allowed, the one with the fewest
+//! tokens remaining. Its index is reported as `window`.
+//!
+//! The first window keeps its state under the key itself, so adding
+//! windows to an existing limit carries its state over. Window `i` after
+//! it is stored as `key\0#i`, under the key's reserved `\0` prefix, so it
+//! lives on the same actor as the key and is cleared when the key is reset.
+
+use crate::actor::RateLimiterHandle;
+use crate::types::{ThrottleRequest, ThrottleResponse};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+/// Most windows a single request may carry
+pub const MAX_WINDOWS: usize = 8;
+
+/// One rate limit a request is checked against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Window {
+    /// Maximum burst capacity
+    pub max_burst: i64,
+    /// Total requests allowed per period
+    pub count_per_period: i64,
+    /// Time period in seconds
+    pub period: i64,
+}
+
+/// Decision for a request checked against several windows
+#[derive(Debug, Clone)]
+pub struct WindowsResponse {
+    /// Decision of the window that bound the request
+    pub response: ThrottleResponse,
+    /// Index of that window in the request
+    pub window: usize,
+}
+
+impl WindowsResponse {
+    /// The binding window among the decisions for each window, in order
+    ///
+    /// # Panics
+    ///
+    /// Panics if `responses` is empty.
+    pub fn new(mut responses: Vec<ThrottleResponse>) -> Self {
+        let window = responses
+            .iter()
+            .position(|response| !response.allowed)
+            .unwrap_or_else(|| {
+                (0..responses.len())
+                    .min_by_key(|&i| responses[i].remaining)
+                    .expect("a request has at least one window")
+            });
+        WindowsResponse {
+            response: responses.swap_remove(window),
+            window,
+        }
+    }
+}
+
+/// A request's windows could not be checked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidWindowsError {
+    /// The request listed no windows
+    Empty,
+    /// The request listed more than [`MAX_WINDOWS`] windows
+    TooMany(usize),
+    /// The request named a policy as well as windows
+    WithPolicy,
+    /// The request was part of a batch
+    InBatch,
+}
+
+impl InvalidWindowsError {
+    /// `invalid_windows`, shared by every variant
+    pub fn code(&self) -> &'static str {
+        "invalid_windows"
+    }
+}
+
+impl fmt::Display for InvalidWindowsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidWindowsError::Empty => write!(f, "windows must not be empty"),
+            InvalidWindowsError::TooMany(count) => {
+                write!(f, "{count} windows exceeds the limit of {MAX_WINDOWS}")
+            }
+            InvalidWindowsError::WithPolicy => {
+                write!(f, "windows cannot be combined with a policy")
+            }
+            InvalidWindowsError::InBatch => write!(f, "windows are not supported in batches"),
+        }
+    }
+}
+
+impl std::error::Error for InvalidWindowsError {}
+
+/// The store key of window `index` of `key`
+pub fn window_key(key: &Arc<str>, index: usize) -> Arc<str> {
+    match index {
+        0 => Arc::clone(key),
+        _ => format!("{key}\0#{index}").into(),
+    }
+}
+
+/// One request per window, each taking its limits from the window and
+/// everything else from `request`
+///
+/// # Errors
+///
+/// Returns [`InvalidWindowsError`] if `windows` is empty or too long.
+pub fn requests(
+    request: &ThrottleRequest,
+    windows: &[Window],
+) -> Result<Vec<ThrottleRequest>, InvalidWindowsError> {
+    if windows.is_empty() {
+        return Err(InvalidWindowsError::Empty);
+    }
+    if windows.len() > MAX_WINDOWS {
+        return Err(InvalidWindowsError::TooMany(windows.len()));
+    }
+    Ok(windows
+        .iter()
+        .enumerate()
+        .map(|(index, window)| ThrottleRequest {
+            key: window_key(&request.key, index),
+            max_burst: window.max_burst,
+            count_per_period: window.count_per_period,
+            period: window.period,
+            ..request.clone()
+        })
+        .collect())
+}
+
+/// Decide `request`, against each of `windows` if it has them
+///
+/// Returns the decision, the index of the window it describes for requests
+/// with windows, and whether the request was shed.
+pub async fn throttle_or_shed(
+    limiter: &RateLimiterHandle,
+    request: ThrottleRequest,
+    windows: Option<&[Window]>,
+) -> anyhow::Result<(ThrottleResponse, Option<usize>, bool)> {
+    match windows {
+        Some(windows) => {
+            let requests = requests(&request, windows)?;
+            let (decided, shed) = limiter.throttle_windows(requests).await?;
+            Ok((decided.response, Some(decided.window), shed))
+        }
+        None => {
+            let (response, shed) = limiter.throttle_or_shed(request).await?;
+            Ok((response, None, shed))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(allowed: bool, remaining: i64) -> ThrottleResponse {
+        ThrottleResponse {
+            allowed,
+            limit: 10,
+            remaining,
+            reset_after: 1,
+            retry_after: if allowed { 0 } else { 1 },
+            retry_after_ms: if allowed { 0 } else { 1000 },
+            reset_after_ms: 1000,
+            cacheable_until_ms: 0,
+            warning: false,
+        }
+    }
+
+    #[test]
+    fn test_requests() {
+        let request = ThrottleRequest {
+            quantity: 2,
+            ..ThrottleRequest::new("user", 0, 0, 0)
+        };
+        let window = Window {
+            max_burst: 10,
+            count_per_period: 10,
+            period: 1,
+        };
+        let requests = requests(&request, &[window, window]).unwrap();
+        assert_eq!(&*requests[0].key, "user");
+        assert_eq!(&*requests[1].key, "user\0#1");
+        assert_eq!(requests[1].quantity, 2);
+        assert_eq!(requests[1].max_burst, 10);
+
+        assert_eq!(
+            super::requests(&request, &[]).unwrap_err(),
+            InvalidWindowsError::Empty
+        );
+        assert_eq!(
+            super::requests(&request, &[window; MAX_WINDOWS + 1]).unwrap_err(),
+            InvalidWindowsError::TooMany(MAX_WINDOWS + 1)
+        );
+    }
+
+    #[test]
+    fn test_binding_window() {
+        // The first denial binds
+        let decided = WindowsResponse::new(vec![
+            response(true, 5),
+            response(false, 0),
+            response(false, 0),
+        ]);
+        assert_eq!(decided.window, 1);
+        assert!(!decided.response.allowed);
+
+        // Otherwise the tightest window
+        let decided = WindowsResponse::new(vec![response(true, 5), response(true, 2)]);
+        assert_eq!(decided.window, 1);
+        assert_eq!(decided.response.remaining, 2);
+    }
+}
@@ -0,0 +1,157 @@
+//! Pluggable rate limiting algorithms
+//!
+//! An [`Algorithm`] decides requests against a [`Store`] it is handed, so
+//! one store can serve several algorithms and a limiter can switch
+//! algorithm per call with [`RateLimiter::rate_limit_with`]. The algorithms
+//! shipped with the crate are:
+//!
+//! - [`Gcra`]: `max_burst` at once, refilled evenly at `count_per_period`
+//!   per `period` (the default of [`RateLimiter::rate_limit`])
+//! - [`SlidingWindow`]: at most `count_per_period` in any `period` seconds
+//! - [`FixedWindow`]: at most `count_per_period` per window of `period`
+//!   seconds, aligned to the Unix epoch
+//! - [`LeakyBucket`]: a bucket holding `max_burst` that drains at
+//!   `count_per_period` per `period`
+//!
+//! [`RateLimiter::rate_limit_with`]: super::RateLimiter::rate_limit_with
+//! [`RateLimiter::rate_limit`]: super::RateLimiter::rate_limit
+//! [`Gcra`]: super::Gcra
+//! [`SlidingWindow`]: super::SlidingWindow
+//! [`FixedWindow`]: super::FixedWindow
+//! [`LeakyBucket`]: super::LeakyBucket
+
+use super::store::Store;
+use super::{CellError, RateLimitResult};
+use std::borrow::Cow;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Attempts at a compare-and-swap before giving up on a contended key
+pub(crate) const MAX_RETRIES: u32 = 10;
+
+/// Rate limit parameters of a check, as passed to every [`Algorithm`]
+///
+/// Each algorithm reads the fields it needs and documents how it uses them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    /// Maximum burst capacity
+    pub max_burst: i64,
+    /// Requests allowed per period
+    pub count_per_period: i64,
+    /// Period in seconds
+    pub period: i64,
+}
+
+impl Quota {
+    /// Create a quota
+    pub fn new(max_burst: i64, count_per_period: i64, period: i64) -> Self {
+        Quota {
+            max_burst,
+            count_per_period,
+            period,
+        }
+    }
+}
+
+/// A rate limiting algorithm
+///
+/// Implementations keep their state in the [`Store`] they are given, as
+/// `i64` values under keys of their choosing, and are otherwise stateless.
+///
+/// # Example
+///
+/// ```
+/// use std::time::SystemTime;
+/// use throttlecrab::{Algorithm, FixedWindow, PeriodicStore, Quota};
+///
+/// let mut store = PeriodicStore::new();
+/// let quota = Quota::new(10, 100, 60);
+///
+/// let (allowed, result) = FixedWindow.rate_limit(&mut store, "user:123", &quota, 1, SystemTime::now())?;
+/// assert!(allowed);
+/// assert_eq!(result.remaining, 99);
+/// # Ok::<(), throttlecrab::CellError>(())
+/// ```
+pub trait Algorithm {
+    /// Check whether `quantity` is allowed for `key` at `now`, recording it
+    /// if so
+    ///
+    /// # Errors
+    ///
+    /// - [`CellError::NegativeQuantity`]: If quantity is negative
+    /// - [`CellError::InvalidRateLimit`]: If a quota field the algorithm
+    ///   uses is not positive
+    /// - [`CellError::Internal`]: If the store fails
+    fn rate_limit<S: Store + ?Sized>(
+        &self,
+        store: &mut S,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError>;
+
+    /// Decide like [`rate_limit`](Self::rate_limit) without changing the store
+    ///
+    /// # Errors
+    ///
+    /// Same as [`rate_limit`](Self::rate_limit).
+    fn peek<S: Store + ?Sized>(
+        &self,
+        store: &S,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError>;
+
+    /// Store keys holding `key`'s state at `now`
+    ///
+    /// The key [`rate_limit`](Self::rate_limit) writes comes first. The
+    /// default is `key` itself.
+    fn state_keys<'a>(&self, key: &'a str, quota: &Quota, now: SystemTime) -> Vec<Cow<'a, str>> {
+        let _ = (quota, now);
+        vec![Cow::Borrowed(key)]
+    }
+}
+
+/// Reject negative quantities
+pub(crate) fn check_quantity(quantity: i64) -> Result<(), CellError> {
+    if quantity < 0 {
+        return Err(CellError::NegativeQuantity(quantity));
+    }
+    Ok(())
+}
+
+/// `now` in nanoseconds since the Unix epoch
+pub(crate) fn nanos_since_epoch(now: SystemTime) -> Result<i128, CellError> {
+    now.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as i128)
+        .map_err(|e| CellError::Internal(format!("System time error: {e}")))
+}
+
+/// Store `new` under `key` if it still holds `old`, or is still absent
+///
+/// Returns false if another writer got there first.
+pub(crate) fn write<S: Store + ?Sized>(
+    store: &mut S,
+    key: &str,
+    old: Option<i64>,
+    new: i64,
+    ttl: Duration,
+    now: SystemTime,
+) -> Result<bool, CellError> {
+    match old {
+        Some(old) => store.compare_and_swap_with_ttl(key, old, new, ttl, now),
+        None => store.set_if_not_exists_with_ttl(key, new, ttl, now),
+    }
+    .map_err(CellError::Internal)
+}
+
+/// Clamp nanoseconds to a [`Duration`], negative values to zero
+pub(crate) fn to_duration(ns: i128) -> Duration {
+    Duration::from_nanos(ns.clamp(0, u64::MAX as i128) as u64)
+}
+
+pub(crate) fn ceil_div(a: i128, b: i128) -> i128 {
+    (a + b - 1) / b
+}
@@ -0,0 +1,397 @@
+//! Stores with asynchronous operations
+//!
+//! [`Store`] is synchronous, which suits in-memory backends but would block
+//! the caller on every round trip to a remote one. [`AsyncStore`] offers the
+//! same three operations as futures, so backends such as Redis, DynamoDB or
+//! FoundationDB can be awaited instead. [`SyncStore`] adapts any [`Store`]
+//! to it, and [`AsyncRateLimiter`] decides requests against it with any
+//! [`Algorithm`].
+//!
+//! # How Algorithms Run
+//!
+//! Algorithms are written against [`Store`], so [`AsyncRateLimiter`] runs
+//! them in three steps: it reads the key's [state
+//! keys](Algorithm::state_keys) from the async store, runs the algorithm
+//! against those values in memory, then writes back what the algorithm
+//! wrote with the store's compare-and-swap. If another writer changed a key
+//! in between, the swap fails and the whole decision is retried, as the
+//! algorithms themselves do against a contended [`Store`].
+
+use super::algorithm::{Algorithm, MAX_RETRIES, Quota};
+use super::rate_limiter::Gcra;
+use super::store::Store;
+use super::{CellError, RateLimitResult};
+use std::collections::HashMap;
+use std::future::{self, Future};
+use std::time::{Duration, SystemTime};
+
+/// Storage backend with asynchronous operations
+///
+/// The async counterpart of [`Store`], with the same semantics for each
+/// operation. The returned futures must be `Send` so decisions can run on a
+/// multi-threaded runtime.
+///
+/// # Example Implementation
+///
+/// ```ignore
+/// use std::time::{Duration, SystemTime};
+/// use throttlecrab::AsyncStore;
+///
+/// struct MyStore {
+///     // Your client for the remote store
+/// }
+///
+/// impl AsyncStore for MyStore {
+///     async fn compare_and_swap_with_ttl(
+///         &mut self,
+///         key: &str,
+///         old: i64,
+///         new: i64,
+///         ttl: Duration,
+///         now: SystemTime,
+///     ) -> Result<bool, String> {
+///         // Atomically replace `old` with `new`
+///         Ok(true)
+///     }
+///
+///     async fn get(&self, key: &str, now: SystemTime) -> Result<Option<i64>, String> {
+///         Ok(None)
+///     }
+///
+///     async fn set_if_not_exists_with_ttl(
+///         &mut self,
+///         key: &str,
+///         value: i64,
+///         ttl: Duration,
+///         now: SystemTime,
+///     ) -> Result<bool, String> {
+///         Ok(true)
+///     }
+/// }
+/// ```
+pub trait AsyncStore {
+    /// Atomically compare and swap a value with TTL
+    ///
+    /// See [`Store::compare_and_swap_with_ttl`].
+    fn compare_and_swap_with_ttl(
+        &mut self,
+        key: &str,
+        old: i64,
+        new: i64,
+        ttl: Duration,
+        now: SystemTime,
+    ) -> impl Future<Output = Result<bool, String>> + Send;
+
+    /// Get the current value for a key
+    ///
+    /// See [`Store::get`].
+    fn get(
+        &self,
+        key: &str,
+        now: SystemTime,
+    ) -> impl Future<Output = Result<Option<i64>, String>> + Send;
+
+    /// Set a value with TTL if the key doesn't exist
+    ///
+    /// See [`Store::set_if_not_exists_with_ttl`].
+    fn set_if_not_exists_with_ttl(
+        &mut self,
+        key: &str,
+        value: i64,
+        ttl: Duration,
+        now: SystemTime,
+    ) -> impl Future<Output = Result<bool, String>> + Send;
+}
+
+/// Adapter running a synchronous [`Store`] as an [`AsyncStore`]
+///
+/// Every operation completes immediately, so this is for code written
+/// against [`AsyncStore`] that should also work with the in-memory stores.
+///
+/// # Example
+///
+/// ```
+/// use throttlecrab::{AsyncRateLimiter, PeriodicStore, SyncStore};
+///
+/// let limiter = AsyncRateLimiter::new(SyncStore::new(PeriodicStore::new()));
+/// ```
+#[derive(Debug, Default)]
+pub struct SyncStore<S: Store>(S);
+
+impl<S: Store> SyncStore<S> {
+    /// Wrap `store`
+    pub fn new(store: S) -> Self {
+        SyncStore(store)
+    }
+
+    /// Get a shared reference to the wrapped store
+    pub fn get_ref(&self) -> &S {
+        &self.0
+    }
+
+    /// Get a mutable reference to the wrapped store
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.0
+    }
+
+    /// Unwrap the store
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+}
+
+impl<S: Store> AsyncStore for SyncStore<S> {
+    fn compare_and_swap_with_ttl(
+        &mut self,
+        key: &str,
+        old: i64,
+        new: i64,
+        ttl: Duration,
+        now: SystemTime,
+    ) -> impl Future<Output = Result<bool, String>> + Send {
+        future::ready(self.0.compare_and_swap_with_ttl(key, old, new, ttl, now))
+    }
+
+    fn get(
+        &self,
+        key: &str,
+        now: SystemTime,
+    ) -> impl Future<Output = Result<Option<i64>, String>> + Send {
+        future::ready(self.0.get(key, now))
+    }
+
+    fn set_if_not_exists_with_ttl(
+        &mut self,
+        key: &str,
+        value: i64,
+        ttl: Duration,
+        now: SystemTime,
+    ) -> impl Future<Output = Result<bool, String>> + Send {
+        future::ready(self.0.set_if_not_exists_with_ttl(key, value, ttl, now))
+    }
+}
+
+/// Rate limiter deciding requests against an [`AsyncStore`]
+///
+/// Offers the checks of [`RateLimiter`](crate::RateLimiter) as async
+/// methods. See the [module documentation](self) for how an [`Algorithm`]
+/// runs against an async store.
+///
+/// # Example
+///
+/// ```
+/// use std::time::SystemTime;
+/// use throttlecrab::{AsyncRateLimiter, PeriodicStore, SyncStore};
+///
+/// # async fn check() -> Result<(), throttlecrab::CellError> {
+/// let mut limiter = AsyncRateLimiter::new(SyncStore::new(PeriodicStore::new()));
+///
+/// // Allow 100 requests per minute with a burst of 10
+/// let (allowed, result) = limiter
+///     .rate_limit("api_key", 10, 100, 60, 1, SystemTime::now())
+///     .await?;
+/// assert!(allowed);
+/// assert_eq!(result.remaining, 9);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AsyncRateLimiter<S: AsyncStore> {
+    store: S,
+}
+
+impl<S: AsyncStore> AsyncRateLimiter<S> {
+    /// Create a rate limiter with the specified store
+    pub fn new(store: S) -> Self {
+        AsyncRateLimiter { store }
+    }
+
+    /// Get a shared reference to the underlying store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Get a mutable reference to the underlying store
+    pub fn store_mut(&mut self) -> &mut S {
+        &mut self.store
+    }
+
+    /// Check a request with GCRA, like [`RateLimiter::rate_limit`]
+    ///
+    /// # Errors
+    ///
+    /// Same as [`RateLimiter::rate_limit`], with store failures and
+    /// exhausted retries reported as [`CellError::Internal`].
+    ///
+    /// [`RateLimiter::rate_limit`]: crate::RateLimiter::rate_limit
+    pub async fn rate_limit(
+        &mut self,
+        key: &str,
+        max_burst: i64,
+        count_per_period: i64,
+        period: i64,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let quota = Quota::new(max_burst, count_per_period, period);
+        self.rate_limit_with(&Gcra, key, &quota, quantity, now)
+            .await
+    }
+
+    /// Check a request with any [`Algorithm`]
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Algorithm::rate_limit`]. A decision that keeps losing the
+    /// race for its keys to other writers fails with [`CellError::Internal`].
+    pub async fn rate_limit_with<A: Algorithm>(
+        &mut self,
+        algorithm: &A,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        for _ in 0..MAX_RETRIES {
+            let mut snapshot = self.snapshot(algorithm, key, quota, now).await?;
+            let decision = algorithm.rate_limit(&mut snapshot, key, quota, quantity, now)?;
+            if self.commit(snapshot, now).await? {
+                return Ok(decision);
+            }
+        }
+        Err(CellError::Internal("Max retries exceeded".into()))
+    }
+
+    /// Like [`rate_limit_with`](Self::rate_limit_with), but without changing
+    /// the store
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Algorithm::peek`].
+    pub async fn peek_with<A: Algorithm>(
+        &self,
+        algorithm: &A,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let snapshot = self.snapshot(algorithm, key, quota, now).await?;
+        algorithm.peek(&snapshot, key, quota, quantity, now)
+    }
+
+    /// Read the state keys of `key` into a snapshot the algorithm can run on
+    async fn snapshot<A: Algorithm>(
+        &self,
+        algorithm: &A,
+        key: &str,
+        quota: &Quota,
+        now: SystemTime,
+    ) -> Result<Snapshot, CellError> {
+        let mut values = HashMap::new();
+        for state_key in algorithm.state_keys(key, quota, now) {
+            let value = self
+                .store
+                .get(&state_key, now)
+                .await
+                .map_err(CellError::Internal)?;
+            values.insert(state_key.into_owned(), value);
+        }
+        Ok(Snapshot {
+            values,
+            writes: HashMap::new(),
+        })
+    }
+
+    /// Write the snapshot's changes to the store, returning false if another
+    /// writer changed a key since it was read
+    async fn commit(&mut self, snapshot: Snapshot, now: SystemTime) -> Result<bool, CellError> {
+        for (key, write) in snapshot.writes {
+            let written = match write.read {
+                Some(old) => {
+                    self.store
+                        .compare_and_swap_with_ttl(&key, old, write.value, write.ttl, now)
+                        .await
+                }
+                None => {
+                    self.store
+                        .set_if_not_exists_with_ttl(&key, write.value, write.ttl, now)
+                        .await
+                }
+            }
+            .map_err(CellError::Internal)?;
+            if !written {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// In-memory copy of a key's state keys, recording what an algorithm writes
+struct Snapshot {
+    /// Current values, starting from those read from the store
+    values: HashMap<String, Option<i64>>,
+    writes: HashMap<String, Write>,
+}
+
+/// A change to one key, to be applied by compare-and-swap
+struct Write {
+    /// Value read from the store
+    read: Option<i64>,
+    value: i64,
+    ttl: Duration,
+}
+
+impl Snapshot {
+    fn value(&self, key: &str) -> Result<Option<i64>, String> {
+        self.values
+            .get(key)
+            .copied()
+            .ok_or_else(|| format!("{key} is not one of the algorithm's state keys"))
+    }
+
+    fn write(&mut self, key: &str, expected: Option<i64>, value: i64, ttl: Duration) -> bool {
+        let Ok(current) = self.value(key) else {
+            return false;
+        };
+        if current != expected {
+            return false;
+        }
+        self.values.insert(key.to_string(), Some(value));
+        // The first write to a key remembers what the store held
+        let read = self.writes.get(key).map_or(current, |write| write.read);
+        self.writes
+            .insert(key.to_string(), Write { read, value, ttl });
+        true
+    }
+}
+
+impl Store for Snapshot {
+    fn compare_and_swap_with_ttl(
+        &mut self,
+        key: &str,
+        old: i64,
+        new: i64,
+        ttl: Duration,
+        _now: SystemTime,
+    ) -> Result<bool, String> {
+        self.value(key)?;
+        Ok(self.write(key, Some(old), new, ttl))
+    }
+
+    fn get(&self, key: &str, _now: SystemTime) -> Result<Option<i64>, String> {
+        self.value(key)
+    }
+
+    fn set_if_not_exists_with_ttl(
+        &mut self,
+        key: &str,
+        value: i64,
+        ttl: Duration,
+        _now: SystemTime,
+    ) -> Result<bool, String> {
+        self.value(key)?;
+        Ok(self.write(key, None, value, ttl))
+    }
+}
@@ -0,0 +1,211 @@
+//! Time sources for the rate limiter
+//!
+//! [`RateLimiter::rate_limit`](crate::RateLimiter::rate_limit) takes the
+//! current time explicitly. A [`Clock`] lets the limiter read it instead:
+//!
+//! - [`SystemClock`]: The wall clock, equivalent to passing `SystemTime::now()`
+//! - [`MonotonicClock`]: Wall clock time that never goes backwards, immune to
+//!   NTP adjustments and manual clock changes
+//! - [`MockClock`]: A manually advanced clock for deterministic tests
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Source of the current time for rate limit checks
+///
+/// # Example Implementation
+///
+/// ```
+/// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+/// use throttlecrab::Clock;
+///
+/// /// A clock frozen at a fixed point in time
+/// struct FixedClock(SystemTime);
+///
+/// impl Clock for FixedClock {
+///     fn now(&self) -> SystemTime {
+///         self.0
+///     }
+/// }
+///
+/// let clock = FixedClock(UNIX_EPOCH + Duration::from_secs(1_000));
+/// assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_secs(1_000));
+/// ```
+pub trait Clock {
+    /// Get the current time
+    fn now(&self) -> SystemTime;
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> SystemTime {
+        (**self).now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Box<C> {
+    fn now(&self) -> SystemTime {
+        (**self).now()
+    }
+}
+
+/// The system wall clock
+///
+/// This is the default clock for [`RateLimiter`](crate::RateLimiter).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Wall clock time anchored at creation and advanced by a monotonic clock
+///
+/// Reads the system clock once, then measures elapsed time with
+/// [`Instant`]. Time never goes backwards, so wall clock adjustments cannot
+/// reset or extend rate limits. The trade-off is that the reported time
+/// slowly drifts from the system clock if the two run at different rates.
+///
+/// # Example
+///
+/// ```
+/// use throttlecrab::{MonotonicClock, PeriodicStore, RateLimiter};
+///
+/// let mut limiter = RateLimiter::with_clock(PeriodicStore::new(), MonotonicClock::new());
+/// let (allowed, _) = limiter.rate_limit_now("user:123", 10, 100, 60, 1).unwrap();
+/// assert!(allowed);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    base_time: SystemTime,
+    base_instant: Instant,
+}
+
+impl MonotonicClock {
+    /// Create a monotonic clock starting at the current system time
+    pub fn new() -> Self {
+        MonotonicClock {
+            base_time: SystemTime::now(),
+            base_instant: Instant::now(),
+        }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> SystemTime {
+        self.base_time + self.base_instant.elapsed()
+    }
+}
+
+/// A manually controlled clock for tests
+///
+/// Clones share the same time, so a test can keep a clone and advance it
+/// while the rate limiter owns the original.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use throttlecrab::{MockClock, PeriodicStore, RateLimiter};
+///
+/// let clock = MockClock::new();
+/// let mut limiter = RateLimiter::with_clock(PeriodicStore::new(), clock.clone());
+///
+/// // 1 request per 10 seconds with a burst of 2
+/// assert!(limiter.rate_limit_now("key", 2, 6, 60, 1).unwrap().0);
+/// assert!(limiter.rate_limit_now("key", 2, 6, 60, 1).unwrap().0);
+/// assert!(!limiter.rate_limit_now("key", 2, 6, 60, 1).unwrap().0);
+///
+/// clock.advance(Duration::from_secs(10));
+/// assert!(limiter.rate_limit_now("key", 2, 6, 60, 1).unwrap().0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl MockClock {
+    /// Create a mock clock at a fixed, arbitrary point in time
+    pub fn new() -> Self {
+        // 2024-01-01T00:00:00Z, so tests don't depend on the real time
+        Self::at(UNIX_EPOCH + Duration::from_secs(1_704_067_200))
+    }
+
+    /// Create a mock clock at the given time
+    pub fn at(time: SystemTime) -> Self {
+        MockClock {
+            nanos: Arc::new(AtomicU64::new(Self::to_nanos(time))),
+        }
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, duration: Duration) {
+        self.nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::SeqCst);
+    }
+
+    /// Set the clock to the given time, which may be in the past
+    pub fn set(&self, time: SystemTime) {
+        self.nanos.store(Self::to_nanos(time), Ordering::SeqCst);
+    }
+
+    fn to_nanos(time: SystemTime) -> u64 {
+        time.duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_nanos(self.nanos.load(Ordering::SeqCst))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_monotonic_clock_never_goes_backwards() {
+        let clock = MonotonicClock::new();
+        let mut last = clock.now();
+        for _ in 0..1000 {
+            let now = clock.now();
+            assert!(now >= last);
+            last = now;
+        }
+    }
+
+    #[test]
+    fn test_mock_clock_shared_between_clones() {
+        let clock = MockClock::at(UNIX_EPOCH);
+        let other = clock.clone();
+
+        other.advance(Duration::from_secs(5));
+        assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_secs(5));
+
+        clock.set(UNIX_EPOCH + Duration::from_secs(1));
+        assert_eq!(other.now(), UNIX_EPOCH + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_clock_through_arc() {
+        let clock: Arc<dyn Clock + Send + Sync> = Arc::new(MockClock::at(UNIX_EPOCH));
+        assert_eq!(clock.now(), UNIX_EPOCH);
+    }
+}
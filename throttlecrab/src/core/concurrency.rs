@@ -0,0 +1,226 @@
+//! Concurrency limiting with expiring leases
+//!
+//! This module provides [`ConcurrencyLimiter`], which caps how many
+//! operations per key may be in flight at once, like a semaphore shared by
+//! every caller. Each admitted operation holds a lease until it releases it
+//! or the lease's TTL runs out, so a caller that crashes mid-operation only
+//! holds its slot for the TTL.
+
+use super::CellError;
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "ahash")]
+use ahash::AHashMap as HashMap;
+#[cfg(not(feature = "ahash"))]
+use std::collections::HashMap;
+
+/// Result of an acquire attempt
+///
+/// Contains the lease if one was granted and the key's state afterwards.
+#[derive(Debug, Clone)]
+pub struct AcquireResult {
+    /// Identifies the granted lease for [`ConcurrencyLimiter::release`],
+    /// `None` if the key was at its limit
+    pub lease_id: Option<u64>,
+    /// The maximum number of concurrent leases
+    pub limit: i64,
+    /// Leases held on the key, including the one granted
+    pub active: i64,
+    /// Time until the oldest lease expires (0 if a lease was granted)
+    ///
+    /// An upper bound: a holder releasing its lease frees a slot sooner.
+    pub retry_after: Duration,
+}
+
+impl AcquireResult {
+    /// Whether a lease was granted
+    pub fn acquired(&self) -> bool {
+        self.lease_id.is_some()
+    }
+}
+
+/// Storage for the leases held on each key
+///
+/// Keys without live leases are dropped as soon as they are seen, by an
+/// acquire, a release or [`remove_expired`](Self::remove_expired).
+#[derive(Debug, Default)]
+pub struct LeaseStore {
+    /// Lease id to expiry, per key
+    keys: HashMap<String, HashMap<u64, SystemTime>>,
+}
+
+impl LeaseStore {
+    /// Create an empty lease store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of keys holding at least one lease, including expired ones
+    /// not yet removed
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Whether no key holds a lease
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Number of leases held on all keys, including expired ones not yet
+    /// removed
+    pub fn leases(&self) -> usize {
+        self.keys.values().map(|leases| leases.len()).sum()
+    }
+
+    /// Leases on `key` that have not expired at `now`
+    pub fn active(&self, key: &str, now: SystemTime) -> usize {
+        self.keys.get(key).map_or(0, |leases| {
+            leases.values().filter(|&&expiry| expiry > now).count()
+        })
+    }
+
+    /// Remove every expired lease, returning how many were removed
+    pub fn remove_expired(&mut self, now: SystemTime) -> usize {
+        let mut removed = 0;
+        self.keys.retain(|_, leases| {
+            let before = leases.len();
+            leases.retain(|_, expiry| *expiry > now);
+            removed += before - leases.len();
+            !leases.is_empty()
+        });
+        removed
+    }
+
+    /// Remove all leases on `key`, returning whether it held any
+    pub fn remove_key(&mut self, key: &str) -> bool {
+        self.keys.remove(key).is_some()
+    }
+
+    /// Estimated heap bytes held by the store
+    pub fn memory_usage(&self) -> usize {
+        // Slots at about 2/3 load, as for the rate limit stores
+        let key_slot = (size_of::<(String, HashMap<u64, SystemTime>)>() + 1) * 3 / 2;
+        let lease_slot = (size_of::<(u64, SystemTime)>() + 1) * 3 / 2;
+        self.keys
+            .iter()
+            .map(|(key, leases)| key_slot + key.capacity() + leases.capacity() * lease_slot)
+            .sum()
+    }
+}
+
+/// Concurrency limiter handing out expiring leases
+///
+/// # Example
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+/// use throttlecrab::ConcurrencyLimiter;
+///
+/// let mut limiter = ConcurrencyLimiter::new();
+/// let now = SystemTime::now();
+///
+/// // At most 2 report generations at once, each for up to 5 minutes
+/// let ttl = Duration::from_secs(300);
+/// let first = limiter.acquire("reports", 2, ttl, now)?;
+/// limiter.acquire("reports", 2, ttl, now)?;
+/// assert!(!limiter.acquire("reports", 2, ttl, now)?.acquired());
+///
+/// // Finishing one frees its slot
+/// assert!(limiter.release("reports", first.lease_id.unwrap(), now));
+/// assert!(limiter.acquire("reports", 2, ttl, now)?.acquired());
+/// # Ok::<(), throttlecrab::CellError>(())
+/// ```
+#[derive(Debug)]
+pub struct ConcurrencyLimiter {
+    store: LeaseStore,
+    /// Id of the next lease granted
+    next_id: u64,
+}
+
+impl Default for ConcurrencyLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConcurrencyLimiter {
+    /// Create a concurrency limiter with an empty lease store
+    pub fn new() -> Self {
+        ConcurrencyLimiter {
+            store: LeaseStore::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Take a lease on `key` if fewer than `max_concurrent` are held at `now`
+    ///
+    /// The lease expires after `ttl` unless released first. Lease ids are
+    /// unique for the life of the limiter.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CellError::InvalidRateLimit`] if `max_concurrent` is not
+    /// positive or `ttl` is zero.
+    pub fn acquire(
+        &mut self,
+        key: &str,
+        max_concurrent: i64,
+        ttl: Duration,
+        now: SystemTime,
+    ) -> Result<AcquireResult, CellError> {
+        if max_concurrent <= 0 || ttl.is_zero() {
+            return Err(CellError::InvalidRateLimit);
+        }
+
+        if !self.store.keys.contains_key(key) {
+            self.store.keys.insert(key.to_string(), HashMap::new());
+        }
+        let leases = self.store.keys.get_mut(key).expect("the key is present");
+        leases.retain(|_, expiry| *expiry > now);
+        let active = leases.len() as i64;
+        if active >= max_concurrent {
+            let oldest = leases.values().min().copied().unwrap_or(now);
+            return Ok(AcquireResult {
+                lease_id: None,
+                limit: max_concurrent,
+                active,
+                retry_after: oldest.duration_since(now).unwrap_or_default(),
+            });
+        }
+
+        let lease_id = self.next_id;
+        self.next_id += 1;
+        leases.insert(lease_id, now + ttl);
+        Ok(AcquireResult {
+            lease_id: Some(lease_id),
+            limit: max_concurrent,
+            active: active + 1,
+            retry_after: Duration::ZERO,
+        })
+    }
+
+    /// Give back lease `lease_id` on `key`
+    ///
+    /// Returns false if the lease is unknown or had already expired at `now`.
+    pub fn release(&mut self, key: &str, lease_id: u64, now: SystemTime) -> bool {
+        let Some(leases) = self.store.keys.get_mut(key) else {
+            return false;
+        };
+        leases.retain(|_, expiry| *expiry > now);
+        let released = leases.remove(&lease_id).is_some();
+        if leases.is_empty() {
+            self.store.keys.remove(key);
+        }
+        released
+    }
+
+    /// Get a reference to the underlying lease store
+    pub fn store(&self) -> &LeaseStore {
+        &self.store
+    }
+
+    /// Get a mutable reference to the underlying lease store
+    pub fn store_mut(&mut self) -> &mut LeaseStore {
+        &mut self.store
+    }
+}
@@ -0,0 +1,105 @@
+//! Fixed window rate limiting
+//!
+//! This module provides [`FixedWindow`], an [`Algorithm`] that allows up to
+//! `count_per_period` per window of `period` seconds, with windows aligned
+//! to the Unix epoch. It is the simplest and cheapest algorithm, at the
+//! cost of letting a client spend two windows' worth around a boundary.
+//!
+//! # Store Layout
+//!
+//! One entry per key and window holding the quantity allowed in it, under
+//! the same keys as a [`SlidingWindow`](super::SlidingWindow)'s windows
+//! (see [`window_keys`](super::sliding_window::window_keys)), so a key is
+//! reset by removing the entries under
+//! [`window_key_prefix`](super::sliding_window::window_key_prefix). An entry
+//! expires when its window ends.
+
+use super::algorithm::{self, Algorithm, MAX_RETRIES, Quota, to_duration};
+use super::sliding_window::{Window, entry_key, window_keys};
+use super::store::Store;
+use super::{CellError, RateLimitResult};
+use std::borrow::Cow;
+use std::time::{Duration, SystemTime};
+
+/// Fixed window counter as an [`Algorithm`]
+///
+/// Allows up to `count_per_period` per window of `period` seconds;
+/// `max_burst` is not used. Denied requests retry when the window ends.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FixedWindow;
+
+impl Algorithm for FixedWindow {
+    fn rate_limit<S: Store + ?Sized>(
+        &self,
+        store: &mut S,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let window = Window::new(quota, quantity, now)?;
+        let key = entry_key(key, window.index);
+
+        // Retry loop with limit to prevent stack overflow
+        let mut retries = 0;
+
+        loop {
+            let count = store.get(&key, now).map_err(CellError::Internal)?;
+            let allowed = count.unwrap_or(0).saturating_add(quantity) <= window.limit;
+
+            // An empty request leaves the window as it is
+            if allowed && quantity > 0 {
+                let new = count.unwrap_or(0) + quantity;
+                let ttl = to_duration(window.period_ns - window.elapsed_ns);
+                if !algorithm::write(store, &key, count, new, ttl, now)? {
+                    retries += 1;
+                    if retries >= MAX_RETRIES {
+                        return Err(CellError::Internal("Max retries exceeded".into()));
+                    }
+                    continue;
+                }
+            }
+
+            let count = count.unwrap_or(0) + if allowed { quantity } else { 0 };
+            return Ok((allowed, result(&window, allowed, count)));
+        }
+    }
+
+    fn peek<S: Store + ?Sized>(
+        &self,
+        store: &S,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let window = Window::new(quota, quantity, now)?;
+        let count = store
+            .get(&entry_key(key, window.index), now)
+            .map_err(CellError::Internal)?
+            .unwrap_or(0);
+        let allowed = count.saturating_add(quantity) <= window.limit;
+        let count = count + if allowed { quantity } else { 0 };
+        Ok((allowed, result(&window, allowed, count)))
+    }
+
+    fn state_keys<'a>(&self, key: &'a str, quota: &Quota, now: SystemTime) -> Vec<Cow<'a, str>> {
+        let [current, _] = window_keys(key, quota.period, now);
+        vec![Cow::Owned(current)]
+    }
+}
+
+/// The key's state with `count` allowed in the current window
+fn result(window: &Window, allowed: bool, count: i64) -> RateLimitResult {
+    let until_next = to_duration(window.period_ns - window.elapsed_ns);
+    RateLimitResult {
+        limit: window.limit,
+        remaining: (window.limit - count).max(0),
+        reset_after: if count > 0 {
+            until_next
+        } else {
+            Duration::ZERO
+        },
+        retry_after: if allowed { Duration::ZERO } else { until_next },
+    }
+}
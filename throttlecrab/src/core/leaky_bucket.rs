@@ -0,0 +1,144 @@
+//! Leaky bucket rate limiting
+//!
+//! This module provides [`LeakyBucket`], an [`Algorithm`] modelling a
+//! bucket that holds `max_burst` and drains at `count_per_period` per
+//! `period`. A request pours its quantity in and is denied if the bucket
+//! would overflow.
+//!
+//! # Store Layout
+//!
+//! One entry per key, under the key itself, holding the time the bucket
+//! will be empty in nanoseconds since the Unix epoch. It expires when the
+//! bucket is empty.
+
+use super::algorithm::{
+    self, Algorithm, MAX_RETRIES, Quota, check_quantity, nanos_since_epoch, to_duration,
+};
+use super::store::Store;
+use super::{CellError, RateLimitResult};
+use std::time::SystemTime;
+
+/// Leaky bucket (as a meter) as an [`Algorithm`]
+///
+/// Allows `max_burst` at once, then `count_per_period` per `period` as
+/// the bucket drains. Decisions match [`Gcra`](super::Gcra) closely, but
+/// the bucket's level is what is stored, and `remaining` and `reset_after`
+/// report the room left in it and the time until it is empty.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LeakyBucket;
+
+impl Algorithm for LeakyBucket {
+    fn rate_limit<S: Store + ?Sized>(
+        &self,
+        store: &mut S,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let bucket = Bucket::new(quota, quantity, now)?;
+
+        // Retry loop with limit to prevent stack overflow
+        let mut retries = 0;
+
+        loop {
+            let empty_at = store.get(key, now).map_err(CellError::Internal)?;
+            let decision = bucket.decide(empty_at, quantity);
+
+            // An empty request leaves the bucket as it is
+            if decision.allowed && quantity > 0 {
+                let new = (bucket.now_ns + decision.level_ns).min(i64::MAX as i128) as i64;
+                let ttl = to_duration(decision.level_ns);
+                if !algorithm::write(store, key, empty_at, new, ttl, now)? {
+                    retries += 1;
+                    if retries >= MAX_RETRIES {
+                        return Err(CellError::Internal("Max retries exceeded".into()));
+                    }
+                    continue;
+                }
+            }
+
+            return Ok((decision.allowed, bucket.result(&decision)));
+        }
+    }
+
+    fn peek<S: Store + ?Sized>(
+        &self,
+        store: &S,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let bucket = Bucket::new(quota, quantity, now)?;
+        let empty_at = store.get(key, now).map_err(CellError::Internal)?;
+        let decision = bucket.decide(empty_at, quantity);
+        Ok((decision.allowed, bucket.result(&decision)))
+    }
+}
+
+/// Bucket parameters for a single request, in nanoseconds
+struct Bucket {
+    limit: i64,
+    /// Time one unit takes to drain
+    drain_ns: i128,
+    /// Level of a full bucket
+    capacity_ns: i128,
+    now_ns: i128,
+}
+
+/// Outcome of pouring a request into the bucket
+struct Decision {
+    allowed: bool,
+    /// Level after the request, as the time until the bucket is empty
+    level_ns: i128,
+    /// Level the request would need to fit, as the time until empty
+    needed_ns: i128,
+}
+
+impl Bucket {
+    fn new(quota: &Quota, quantity: i64, now: SystemTime) -> Result<Self, CellError> {
+        check_quantity(quantity)?;
+        if quota.max_burst <= 0 || quota.count_per_period <= 0 || quota.period <= 0 {
+            return Err(CellError::InvalidRateLimit);
+        }
+
+        let drain_ns =
+            (quota.period as i128 * 1_000_000_000 / quota.count_per_period as i128).max(1);
+        Ok(Bucket {
+            limit: quota.max_burst,
+            drain_ns,
+            capacity_ns: quota.max_burst as i128 * drain_ns,
+            now_ns: nanos_since_epoch(now)?,
+        })
+    }
+
+    fn decide(&self, empty_at: Option<i64>, quantity: i64) -> Decision {
+        let level_ns = empty_at.map_or(0, |at| (at as i128 - self.now_ns).max(0));
+        let needed_ns = level_ns + quantity as i128 * self.drain_ns;
+        let allowed = needed_ns <= self.capacity_ns;
+        Decision {
+            allowed,
+            level_ns: if allowed { needed_ns } else { level_ns },
+            needed_ns,
+        }
+    }
+
+    fn result(&self, decision: &Decision) -> RateLimitResult {
+        let retry_after = if decision.allowed {
+            0
+        } else if decision.needed_ns - decision.level_ns <= self.capacity_ns {
+            decision.needed_ns - self.capacity_ns
+        } else {
+            // Never fits; report when the bucket is empty
+            decision.level_ns
+        };
+
+        RateLimitResult {
+            limit: self.limit,
+            remaining: ((self.capacity_ns - decision.level_ns) / self.drain_ns).max(0) as i64,
+            reset_after: to_duration(decision.level_ns),
+            retry_after: to_duration(retry_after),
+        }
+    }
+}
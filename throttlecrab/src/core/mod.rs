@@ -1,22 +1,53 @@
 //! Core components of the throttlecrab rate limiting library
 //!
 //! This module contains the fundamental building blocks:
+//! - [`algorithm`]: The [`Algorithm`] trait for pluggable rate limiting algorithms
+//! - [`clock`]: Time sources for rate limit checks
+//! - [`concurrency`]: Concurrency limiting with expiring leases
+//! - [`fixed_window`]: Fixed window rate limiting
+//! - [`leaky_bucket`]: Leaky bucket rate limiting
 //! - [`rate`]: Rate calculation and emission intervals
 //! - [`rate_limiter`]: The main GCRA rate limiter implementation
+//! - [`sharded`]: A thread-safe rate limiter over sharded stores
+//! - [`sliding_window`]: Sliding window counter rate limiting
 //! - [`store`]: Storage backends for rate limit state
+//! - [`warm_up`]: Gradual ramp-up of new keys' quotas
 
+pub mod algorithm;
+pub mod async_store;
+pub mod clock;
+pub mod concurrency;
+pub mod fixed_window;
+pub mod leaky_bucket;
 pub mod rate;
 pub mod rate_limiter;
+pub mod sharded;
+pub mod sliding_window;
 pub mod store;
 #[cfg(test)]
 mod tests;
+pub mod warm_up;
 
+pub use algorithm::{Algorithm, Quota};
+pub use async_store::{AsyncRateLimiter, AsyncStore, SyncStore};
+pub use clock::{Clock, MockClock, MonotonicClock, SystemClock};
+pub use concurrency::{AcquireResult, ConcurrencyLimiter, LeaseStore};
+pub use fixed_window::FixedWindow;
+pub use leaky_bucket::LeakyBucket;
 pub use rate::Rate;
-pub use rate_limiter::{RateLimitResult, RateLimiter};
+pub use rate_limiter::{Gcra, RateLimitResult, RateLimiter};
+pub use sharded::ConcurrentRateLimiter;
+pub use sliding_window::{SlidingWindow, SlidingWindowLimiter};
 pub use store::{
-    AdaptiveStore, AdaptiveStoreBuilder, PeriodicStore, PeriodicStoreBuilder, ProbabilisticStore,
-    ProbabilisticStoreBuilder, Store,
+    AdaptiveStore, AdaptiveStoreBuilder, KeyPage, PeriodicStore, PeriodicStoreBuilder,
+    ProbabilisticStore, ProbabilisticStoreBuilder, Store, TimingWheelStore,
+    TimingWheelStoreBuilder,
 };
+#[cfg(feature = "redis")]
+pub use store::{AsyncRedisStore, RedisStore};
+#[cfg(feature = "serde")]
+pub use store::{StateEntry, StoreState};
+pub use warm_up::{WarmUp, WarmingUp};
 
 use std::error::Error;
 use std::fmt;
@@ -1,9 +1,15 @@
 //! GCRA (Generic Cell Rate Algorithm) rate limiter implementation
 //!
 //! This module provides the main [`RateLimiter`] struct which implements
-//! the GCRA algorithm for smooth, fair rate limiting with burst support.
+//! the GCRA algorithm for smooth, fair rate limiting with burst support,
+//! and [`Gcra`], the same algorithm as an [`Algorithm`].
 
-use super::{CellError, Rate, store::Store};
+use super::{
+    CellError, Rate,
+    algorithm::{self, Algorithm, MAX_RETRIES, Quota},
+    clock::{Clock, SystemClock},
+    store::Store,
+};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Result of a rate limit check
@@ -24,7 +30,8 @@ pub struct RateLimitResult {
 /// GCRA (Generic Cell Rate Algorithm) Rate Limiter
 ///
 /// This rate limiter implements the GCRA algorithm, providing smooth and fair rate limiting
-/// with support for bursts. It requires a [`Store`] implementation to manage rate limit data.
+/// with support for bursts. It requires a [`Store`] implementation to manage rate limit data,
+/// and reads the current time from a [`Clock`] when using [`RateLimiter::rate_limit_now`].
 ///
 /// # Example
 ///
@@ -39,13 +46,16 @@ pub struct RateLimitResult {
 ///     .rate_limit("api_key", 10, 100, 60, 1, SystemTime::now())
 ///     .unwrap();
 /// ```
-pub struct RateLimiter<S: Store> {
+pub struct RateLimiter<S: Store, C: Clock = SystemClock> {
     store: S,
+    clock: C,
 }
 
 impl<S: Store> RateLimiter<S> {
     /// Create a new rate limiter with the specified store
     ///
+    /// Uses the [`SystemClock`] for [`RateLimiter::rate_limit_now`].
+    ///
     /// # Example
     ///
     /// ```
@@ -54,10 +64,92 @@ impl<S: Store> RateLimiter<S> {
     /// let limiter = RateLimiter::new(AdaptiveStore::new());
     /// ```
     pub fn new(store: S) -> Self {
-        RateLimiter { store }
+        RateLimiter {
+            store,
+            clock: SystemClock,
+        }
+    }
+}
+
+impl<S: Store, C: Clock> RateLimiter<S, C> {
+    /// Create a new rate limiter with the specified store and clock
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use throttlecrab::{MonotonicClock, RateLimiter, PeriodicStore};
+    ///
+    /// let limiter = RateLimiter::with_clock(PeriodicStore::new(), MonotonicClock::new());
+    /// ```
+    pub fn with_clock(store: S, clock: C) -> Self {
+        RateLimiter { store, clock }
     }
 
-    /// Check if a request is allowed under the rate limit
+    /// Get the clock used by [`RateLimiter::rate_limit_now`]
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Get a shared reference to the underlying store
+    ///
+    /// Useful for inspecting store state such as the number of tracked keys.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Get a mutable reference to the underlying store
+    ///
+    /// Allows maintenance operations like removing keys or forcing a cleanup
+    /// without tearing down the limiter.
+    pub fn store_mut(&mut self) -> &mut S {
+        &mut self.store
+    }
+
+    /// Check if a request is allowed under the rate limit, using the limiter's clock
+    ///
+    /// Equivalent to [`RateLimiter::rate_limit`] with `now` read from the
+    /// [`Clock`].
+    ///
+    /// # Parameters
+    ///
+    /// - `key`: Unique identifier for the rate limit (e.g., user ID, API key)
+    /// - `max_burst`: Maximum number of requests allowed in a burst
+    /// - `count_per_period`: Total number of requests allowed per time period
+    /// - `period`: Time period in seconds
+    /// - `quantity`: Number of tokens to consume (typically 1)
+    ///
+    /// # Errors
+    ///
+    /// Same as [`RateLimiter::rate_limit`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use throttlecrab::{RateLimiter, PeriodicStore};
+    ///
+    /// let mut limiter = RateLimiter::new(PeriodicStore::new());
+    ///
+    /// // 10 burst, 100 per minute
+    /// let (allowed, result) = limiter.rate_limit_now("user:123", 10, 100, 60, 1).unwrap();
+    /// assert!(allowed);
+    /// assert_eq!(result.remaining, 9);
+    /// ```
+    pub fn rate_limit_now(
+        &mut self,
+        key: &str,
+        max_burst: i64,
+        count_per_period: i64,
+        period: i64,
+        quantity: i64,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let now = self.clock.now();
+        self.rate_limit(key, max_burst, count_per_period, period, quantity, now)
+    }
+
+    /// Check if a request is allowed under the rate limit at a given time
+    ///
+    /// Prefer [`RateLimiter::rate_limit_now`] unless the time comes from
+    /// elsewhere, e.g. a request timestamp.
     ///
     /// # Parameters
     ///
@@ -108,6 +200,203 @@ impl<S: Store> RateLimiter<S> {
         quantity: i64,
         now: SystemTime,
     ) -> Result<(bool, RateLimitResult), CellError> {
+        Gcra.rate_limit(
+            &mut self.store,
+            key,
+            &Quota::new(max_burst, count_per_period, period),
+            quantity,
+            now,
+        )
+    }
+
+    /// Check a request with another [`Algorithm`] against the limiter's store
+    ///
+    /// Algorithms keep their state under different store keys (see
+    /// [`Algorithm::state_keys`]), so a key should stick to one algorithm.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Algorithm::rate_limit`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use throttlecrab::{LeakyBucket, PeriodicStore, Quota, RateLimiter};
+    /// use std::time::SystemTime;
+    ///
+    /// let mut limiter = RateLimiter::new(PeriodicStore::new());
+    /// let quota = Quota::new(10, 100, 60);
+    ///
+    /// let (allowed, result) = limiter
+    ///     .rate_limit_with(&LeakyBucket, "user:123", &quota, 1, SystemTime::now())
+    ///     .unwrap();
+    /// assert!(allowed);
+    /// assert_eq!(result.remaining, 9);
+    /// ```
+    pub fn rate_limit_with<A: Algorithm>(
+        &mut self,
+        algorithm: &A,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        algorithm.rate_limit(&mut self.store, key, quota, quantity, now)
+    }
+
+    /// Like [`RateLimiter::rate_limit_with`], but without changing the store
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Algorithm::peek`].
+    pub fn peek_with<A: Algorithm>(
+        &self,
+        algorithm: &A,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        algorithm.peek(&self.store, key, quota, quantity, now)
+    }
+
+    /// Check whether a request would be allowed, without consuming tokens
+    ///
+    /// Computes the same decision as [`RateLimiter::rate_limit`] but leaves
+    /// the store untouched, so unknown keys are not created and known keys
+    /// keep their state. Useful for inspecting a key's current standing.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`RateLimiter::rate_limit`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use throttlecrab::{RateLimiter, PeriodicStore};
+    /// use std::time::SystemTime;
+    ///
+    /// let mut limiter = RateLimiter::new(PeriodicStore::new());
+    /// let now = SystemTime::now();
+    /// limiter.rate_limit("user:123", 10, 100, 60, 1, now).unwrap();
+    ///
+    /// let (allowed, result) = limiter.peek("user:123", 10, 100, 60, 1, now).unwrap();
+    /// assert!(allowed);
+    /// assert_eq!(result.remaining, 8);
+    ///
+    /// // Peeking did not consume anything
+    /// let (_, result) = limiter.rate_limit("user:123", 10, 100, 60, 1, now).unwrap();
+    /// assert_eq!(result.remaining, 8);
+    /// ```
+    pub fn peek(
+        &self,
+        key: &str,
+        max_burst: i64,
+        count_per_period: i64,
+        period: i64,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        Gcra.peek(
+            &self.store,
+            key,
+            &Quota::new(max_burst, count_per_period, period),
+            quantity,
+            now,
+        )
+    }
+}
+
+/// The Generic Cell Rate Algorithm as an [`Algorithm`]
+///
+/// Allows `max_burst` at once, refilled evenly at `count_per_period` per
+/// `period`. Stores the theoretical arrival time under the key itself.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gcra;
+
+impl Algorithm for Gcra {
+    fn rate_limit<S: Store + ?Sized>(
+        &self,
+        store: &mut S,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let gcra = Params::new(quota, quantity, now)?;
+
+        // Retry loop with limit to prevent stack overflow
+        let mut retries = 0;
+
+        loop {
+            let tat_val = store.get(key, now).map_err(CellError::Internal)?;
+            let decision = gcra.decide(tat_val, quantity);
+
+            if decision.allowed {
+                // Update the store with new TAT
+                let ttl = Duration::from_nanos(
+                    decision
+                        .new_tat
+                        .saturating_sub(gcra.now_ns)
+                        .saturating_add(gcra.delay_variation_tolerance_ns)
+                        as u64,
+                );
+
+                // Try to update - if it fails due to race condition, retry
+                if !algorithm::write(store, key, tat_val, decision.new_tat, ttl, now)? {
+                    retries += 1;
+                    if retries >= MAX_RETRIES {
+                        return Err(CellError::Internal("Max retries exceeded".into()));
+                    }
+                    continue;
+                }
+            }
+
+            return Ok((decision.allowed, gcra.result(&decision)));
+        }
+    }
+
+    fn peek<S: Store + ?Sized>(
+        &self,
+        store: &S,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let gcra = Params::new(quota, quantity, now)?;
+        let tat_val = store.get(key, now).map_err(CellError::Internal)?;
+        let decision = gcra.decide(tat_val, quantity);
+        Ok((decision.allowed, gcra.result(&decision)))
+    }
+}
+
+/// GCRA parameters for a single request, in nanoseconds
+struct Params {
+    limit: i64,
+    emission_interval_ns: i64,
+    delay_variation_tolerance_ns: i64,
+    now_ns: i64,
+}
+
+/// Outcome of applying a request to a stored TAT
+struct Decision {
+    allowed: bool,
+    /// TAT before the request
+    tat: i64,
+    /// TAT if the request is allowed
+    new_tat: i64,
+    /// Earliest time the request would be allowed
+    allow_at: i64,
+}
+
+impl Params {
+    fn new(quota: &Quota, quantity: i64, now: SystemTime) -> Result<Self, CellError> {
+        let Quota {
+            max_burst,
+            count_per_period,
+            period,
+        } = *quota;
         if quantity < 0 {
             return Err(CellError::NegativeQuantity(quantity));
         }
@@ -120,7 +409,6 @@ impl<S: Store> RateLimiter<S> {
         let rate = Rate::from_count_and_period(count_per_period, period);
         let emission_interval = rate.period();
         let delay_variation_tolerance = emission_interval * (max_burst - 1) as u32;
-        let limit = max_burst;
 
         // Convert time to nanoseconds, handling potential errors gracefully
         let now_ns = match now.duration_since(UNIX_EPOCH) {
@@ -143,109 +431,87 @@ impl<S: Store> RateLimiter<S> {
             }
         };
 
-        // Retry loop with limit to prevent stack overflow
-        const MAX_RETRIES: u32 = 10;
-        let mut retries = 0;
+        Ok(Params {
+            limit: max_burst,
+            emission_interval_ns: emission_interval.as_nanos() as i64,
+            delay_variation_tolerance_ns: delay_variation_tolerance.as_nanos() as i64,
+            now_ns,
+        })
+    }
 
-        loop {
-            let tat_val = self.store.get(key, now).map_err(CellError::Internal)?;
-
-            // Calculate the theoretical arrival time for this request
-            let emission_interval_ns = emission_interval.as_nanos() as i64;
-            let delay_variation_tolerance_ns = delay_variation_tolerance.as_nanos() as i64;
-
-            // Initialize TAT or get from store
-            let tat = if let Some(stored_tat) = tat_val {
-                // Use stored TAT but ensure it's not too far in the past
-                let min_tat = now_ns.saturating_sub(delay_variation_tolerance_ns);
-                stored_tat.max(min_tat)
-            } else {
-                // First request - start with TAT = now - emission_interval
-                // This accounts for the token we're about to use
-                now_ns.saturating_sub(emission_interval_ns)
-            };
-
-            // Calculate new TAT if this request is allowed
-            // Use saturating_mul to prevent overflow
-            let increment = emission_interval_ns.saturating_mul(quantity);
-            let new_tat = tat.saturating_add(increment);
-
-            // Check if request is allowed
-            let allow_at = new_tat.saturating_sub(delay_variation_tolerance_ns);
-            let allowed = now_ns >= allow_at;
-
-            if allowed {
-                // Update the store with new TAT
-                let ttl = Duration::from_nanos(
-                    new_tat
-                        .saturating_sub(now_ns)
-                        .saturating_add(delay_variation_tolerance_ns) as u64,
-                );
+    fn decide(&self, tat_val: Option<i64>, quantity: i64) -> Decision {
+        // Initialize TAT or get from store
+        let tat = if let Some(stored_tat) = tat_val {
+            // Use stored TAT but ensure it's not too far in the past
+            let min_tat = self
+                .now_ns
+                .saturating_sub(self.delay_variation_tolerance_ns);
+            stored_tat.max(min_tat)
+        } else {
+            // First request - start with TAT = now - emission_interval
+            // This accounts for the token we're about to use
+            self.now_ns.saturating_sub(self.emission_interval_ns)
+        };
 
-                // Try to update - if it fails due to race condition, retry
-                let success = if let Some(old_tat) = tat_val {
-                    self.store
-                        .compare_and_swap_with_ttl(key, old_tat, new_tat, ttl, now)
-                        .map_err(CellError::Internal)?
-                } else {
-                    // First time seeing this key
-                    self.store
-                        .set_if_not_exists_with_ttl(key, new_tat, ttl, now)
-                        .map_err(CellError::Internal)?
-                };
-
-                if !success {
-                    // Race condition - retry with limit
-                    retries += 1;
-                    if retries >= MAX_RETRIES {
-                        return Err(CellError::Internal("Max retries exceeded".into()));
-                    }
-                    continue;
-                }
-            }
+        // Calculate new TAT if this request is allowed
+        // Use saturating_mul to prevent overflow
+        let increment = self.emission_interval_ns.saturating_mul(quantity);
+        let new_tat = tat.saturating_add(increment);
+
+        // Check if request is allowed
+        let allow_at = new_tat.saturating_sub(self.delay_variation_tolerance_ns);
+
+        Decision {
+            allowed: self.now_ns >= allow_at,
+            tat,
+            new_tat,
+            allow_at,
+        }
+    }
+
+    fn result(&self, decision: &Decision) -> RateLimitResult {
+        let now_ns = self.now_ns;
+        let current_tat = if decision.allowed {
+            decision.new_tat
+        } else {
+            decision.tat
+        };
+
+        // Calculate remaining tokens AFTER this request
+        // Remaining = how many more tokens we can use before hitting the limit
+        // When TAT = now + tolerance, we've used all burst capacity
+        // When TAT = now - tolerance, we have full burst capacity
+
+        // Calculate the distance from TAT to the burst limit
+        // The burst limit is at now + delay_variation_tolerance
+        let burst_limit = now_ns + self.delay_variation_tolerance_ns;
+        let room_until_limit = burst_limit.saturating_sub(current_tat);
+
+        // Convert room to number of tokens
+        let remaining = if self.emission_interval_ns > 0 {
+            (room_until_limit / self.emission_interval_ns).max(0)
+        } else {
+            0
+        };
+
+        let reset_after = Duration::from_nanos(
+            current_tat
+                .saturating_sub(now_ns)
+                .saturating_add(self.delay_variation_tolerance_ns)
+                .max(0) as u64,
+        );
+
+        let retry_after = if decision.allowed {
+            Duration::ZERO
+        } else {
+            Duration::from_nanos(decision.allow_at.saturating_sub(now_ns).max(0) as u64)
+        };
 
-            // Calculate result
-            let current_tat = if allowed { new_tat } else { tat };
-
-            // Calculate remaining tokens AFTER this request
-            // Remaining = how many more tokens we can use before hitting the limit
-            // When TAT = now + tolerance, we've used all burst capacity
-            // When TAT = now - tolerance, we have full burst capacity
-
-            // Calculate the distance from TAT to the burst limit
-            // The burst limit is at now + delay_variation_tolerance
-            let burst_limit = now_ns + delay_variation_tolerance_ns;
-            let room_until_limit = burst_limit.saturating_sub(current_tat);
-
-            // Convert room to number of tokens
-            let remaining = if emission_interval_ns > 0 {
-                (room_until_limit / emission_interval_ns).max(0)
-            } else {
-                0
-            };
-
-            let reset_after = Duration::from_nanos(
-                current_tat
-                    .saturating_sub(now_ns)
-                    .saturating_add(delay_variation_tolerance_ns)
-                    .max(0) as u64,
-            );
-
-            let retry_after = if allowed {
-                Duration::ZERO
-            } else {
-                Duration::from_nanos(allow_at.saturating_sub(now_ns).max(0) as u64)
-            };
-
-            return Ok((
-                allowed,
-                RateLimitResult {
-                    limit,
-                    remaining,
-                    reset_after,
-                    retry_after,
-                },
-            ));
+        RateLimitResult {
+            limit: self.limit,
+            remaining,
+            reset_after,
+            retry_after,
         }
     }
 }
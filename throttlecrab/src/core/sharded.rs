@@ -0,0 +1,226 @@
+//! Thread-safe rate limiting over sharded stores
+//!
+//! [`RateLimiter`](super::RateLimiter) takes `&mut self`, so threads sharing
+//! one have to take turns behind a single lock. [`ConcurrentRateLimiter`]
+//! splits the keys across several stores, each behind its own lock, and
+//! takes `&self`: checks on keys in different shards run in parallel, and
+//! only checks that hash to the same shard wait for each other.
+//!
+//! Every store key an algorithm uses for a key (see
+//! [`Algorithm::state_keys`]) lives in the key's shard, so each decision is
+//! made under a single lock and stays exact.
+
+use super::{
+    CellError, RateLimitResult,
+    algorithm::{Algorithm, Quota},
+    clock::{Clock, SystemClock},
+    rate_limiter::Gcra,
+    store::Store,
+};
+use std::hash::{BuildHasher, RandomState};
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+
+/// Thread-safe GCRA rate limiter
+///
+/// The same checks as [`RateLimiter`](super::RateLimiter), callable through
+/// a shared reference, e.g. from an `Arc` handed to every worker thread.
+/// Keys are hashed to one of a fixed number of shards, each with its own
+/// [`Store`] behind a mutex. A few shards per core keeps contention low.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::thread;
+/// use throttlecrab::{ConcurrentRateLimiter, PeriodicStore};
+///
+/// // 16 shards sharing a capacity of 1M keys
+/// let limiter = Arc::new(ConcurrentRateLimiter::new(16, || {
+///     PeriodicStore::with_capacity(1_000_000 / 16)
+/// }));
+///
+/// let workers: Vec<_> = (0..4)
+///     .map(|i| {
+///         let limiter = Arc::clone(&limiter);
+///         thread::spawn(move || {
+///             let key = format!("user:{i}");
+///             limiter.rate_limit_now(&key, 10, 100, 60, 1).unwrap().0
+///         })
+///     })
+///     .collect();
+/// for worker in workers {
+///     assert!(worker.join().unwrap());
+/// }
+/// ```
+pub struct ConcurrentRateLimiter<S: Store, C: Clock = SystemClock> {
+    shards: Box<[Mutex<S>]>,
+    hasher: RandomState,
+    clock: C,
+}
+
+impl<S: Store> ConcurrentRateLimiter<S> {
+    /// Create a rate limiter with `shards` stores, each made by `store`
+    ///
+    /// Uses the [`SystemClock`] for
+    /// [`rate_limit_now`](ConcurrentRateLimiter::rate_limit_now). Keys are
+    /// spread evenly, so give each store `1 / shards` of the total capacity.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use throttlecrab::{AdaptiveStore, ConcurrentRateLimiter};
+    ///
+    /// let limiter = ConcurrentRateLimiter::new(8, || {
+    ///     AdaptiveStore::builder().capacity(100_000).build()
+    /// });
+    /// assert_eq!(limiter.shards(), 8);
+    /// ```
+    pub fn new(shards: usize, store: impl FnMut() -> S) -> Self {
+        Self::with_clock(shards, store, SystemClock)
+    }
+}
+
+impl<S: Store, C: Clock> ConcurrentRateLimiter<S, C> {
+    /// Create a rate limiter with `shards` stores and the specified clock
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn with_clock(shards: usize, mut store: impl FnMut() -> S, clock: C) -> Self {
+        assert!(
+            shards > 0,
+            "a concurrent rate limiter needs at least one shard"
+        );
+        ConcurrentRateLimiter {
+            shards: (0..shards).map(|_| Mutex::new(store())).collect(),
+            hasher: RandomState::new(),
+            clock,
+        }
+    }
+
+    /// Number of shards the keys are split across
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Get the clock used by
+    /// [`rate_limit_now`](ConcurrentRateLimiter::rate_limit_now)
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Lock the store holding `key`
+    ///
+    /// Allows maintenance operations like removing the key. Checks on every
+    /// key in the shard wait until the guard is dropped.
+    pub fn store(&self, key: &str) -> MutexGuard<'_, S> {
+        self.lock(self.shard(key))
+    }
+
+    /// Lock each store in turn and call `f` with it
+    ///
+    /// Useful for totals such as the number of tracked keys, or a cleanup
+    /// across all shards. Only one shard is locked at a time.
+    pub fn for_each_store(&self, mut f: impl FnMut(&mut S)) {
+        for shard in 0..self.shards.len() {
+            f(&mut self.lock(shard));
+        }
+    }
+
+    /// Check if a request is allowed under the rate limit, using the limiter's clock
+    ///
+    /// See [`RateLimiter::rate_limit_now`](super::RateLimiter::rate_limit_now).
+    ///
+    /// # Errors
+    ///
+    /// Same as [`ConcurrentRateLimiter::rate_limit`].
+    pub fn rate_limit_now(
+        &self,
+        key: &str,
+        max_burst: i64,
+        count_per_period: i64,
+        period: i64,
+        quantity: i64,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let now = self.clock.now();
+        self.rate_limit(key, max_burst, count_per_period, period, quantity, now)
+    }
+
+    /// Check if a request is allowed under the rate limit at a given time
+    ///
+    /// See [`RateLimiter::rate_limit`](super::RateLimiter::rate_limit).
+    ///
+    /// # Errors
+    ///
+    /// - [`CellError::NegativeQuantity`]: If quantity is negative
+    /// - [`CellError::InvalidRateLimit`]: If rate limit parameters are invalid
+    /// - [`CellError::Internal`]: If there's an internal error
+    pub fn rate_limit(
+        &self,
+        key: &str,
+        max_burst: i64,
+        count_per_period: i64,
+        period: i64,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        self.rate_limit_with(
+            &Gcra,
+            key,
+            &Quota::new(max_burst, count_per_period, period),
+            quantity,
+            now,
+        )
+    }
+
+    /// Check a request with another [`Algorithm`] against the key's store
+    ///
+    /// See [`RateLimiter::rate_limit_with`](super::RateLimiter::rate_limit_with).
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Algorithm::rate_limit`].
+    pub fn rate_limit_with<A: Algorithm>(
+        &self,
+        algorithm: &A,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        algorithm.rate_limit(&mut *self.store(key), key, quota, quantity, now)
+    }
+
+    /// Like [`ConcurrentRateLimiter::rate_limit_with`], but without changing the store
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Algorithm::peek`].
+    pub fn peek_with<A: Algorithm>(
+        &self,
+        algorithm: &A,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        algorithm.peek(&*self.store(key), key, quota, quantity, now)
+    }
+
+    fn shard(&self, key: &str) -> usize {
+        (self.hasher.hash_one(key) % self.shards.len() as u64) as usize
+    }
+
+    fn lock(&self, shard: usize) -> MutexGuard<'_, S> {
+        // A panic while holding the lock leaves the store consistent: every
+        // operation on it is a single insert or swap
+        self.shards[shard]
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
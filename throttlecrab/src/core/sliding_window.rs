@@ -0,0 +1,327 @@
+//! Sliding window counter rate limiting
+//!
+//! This module provides [`SlidingWindow`], an [`Algorithm`] that enforces
+//! "at most N requests in any window of `period` seconds" without GCRA's
+//! smoothing: a client may spend its whole allowance at once, then waits
+//! for earlier requests to slide out of the window. [`SlidingWindowLimiter`]
+//! wraps it with a store of its own.
+//!
+//! # Store Layout
+//!
+//! Time is cut into fixed windows of `period` seconds, and each key keeps
+//! one store entry per window holding the quantity allowed in it, under
+//! `"{key}\0{window}"` (see [`window_keys`]). The quantity in the sliding
+//! window ending now is estimated from the current and previous entries,
+//! weighting the previous one by the share of it the sliding window still
+//! covers. An entry expires at the end of the following window, once
+//! nothing reads it any more.
+
+use super::algorithm::{
+    self, Algorithm, MAX_RETRIES, Quota, ceil_div, check_quantity, nanos_since_epoch, to_duration,
+};
+use super::store::Store;
+use super::{CellError, RateLimitResult};
+use std::borrow::Cow;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+/// Store keys of `key`'s current and previous window at `now`
+///
+/// Useful for inspecting or persisting the entries a
+/// [`SlidingWindowLimiter`] wrote for a key.
+pub fn window_keys(key: &str, period: i64, now: SystemTime) -> [String; 2] {
+    let now_ns = now
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as i128);
+    let index = (now_ns / (period.max(1) as i128 * NANOS_PER_SEC)) as i64;
+    [entry_key(key, index), entry_key(key, index - 1)]
+}
+
+/// Prefix of the store keys of every window of `key`
+///
+/// Removing the entries under it, as well as `key` itself, resets a key
+/// whichever algorithm it was checked with.
+pub fn window_key_prefix(key: &str) -> String {
+    format!("{key}\0")
+}
+
+/// The key a store key belongs to: the key itself, or the key whose window
+/// it holds
+pub fn owner_key(store_key: &str) -> &str {
+    store_key.split_once('\0').map_or(store_key, |(key, _)| key)
+}
+
+pub(crate) fn entry_key(key: &str, window: i64) -> String {
+    format!("{key}\0{window}")
+}
+
+/// Sliding window counter rate limiter
+///
+/// Allows up to `limit` in any `period` seconds. Works with any [`Store`],
+/// including a borrowed one, so it can share a store with a
+/// [`RateLimiter`](crate::RateLimiter) as long as keys don't collide.
+///
+/// # Example
+///
+/// ```
+/// use std::time::SystemTime;
+/// use throttlecrab::{PeriodicStore, SlidingWindowLimiter};
+///
+/// let mut limiter = SlidingWindowLimiter::new(PeriodicStore::new());
+/// let now = SystemTime::now();
+///
+/// // No more than 3 requests in any 60 seconds, all usable at once
+/// for _ in 0..3 {
+///     let (allowed, _) = limiter.rate_limit("user:123", 3, 60, 1, now)?;
+///     assert!(allowed);
+/// }
+/// let (allowed, result) = limiter.rate_limit("user:123", 3, 60, 1, now)?;
+/// assert!(!allowed);
+/// assert!(result.retry_after.as_secs() > 0);
+/// # Ok::<(), throttlecrab::CellError>(())
+/// ```
+pub struct SlidingWindowLimiter<S: Store> {
+    store: S,
+}
+
+impl<S: Store> SlidingWindowLimiter<S> {
+    /// Create a sliding window limiter with the given store
+    pub fn new(store: S) -> Self {
+        SlidingWindowLimiter { store }
+    }
+
+    /// Get a shared reference to the underlying store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Get a mutable reference to the underlying store
+    pub fn store_mut(&mut self) -> &mut S {
+        &mut self.store
+    }
+
+    /// Check if a request is allowed under the limit at a given time
+    ///
+    /// # Parameters
+    ///
+    /// - `key`: Unique identifier for the rate limit (e.g., user ID, API key)
+    /// - `limit`: Most quantity allowed in any window of `period` seconds
+    /// - `period`: Window length in seconds
+    /// - `quantity`: Quantity to consume (typically 1)
+    /// - `now`: Current time for the rate limit check
+    ///
+    /// # Returns
+    ///
+    /// Whether the request is allowed and the key's state afterwards.
+    /// `reset_after` is the time until the window holds nothing.
+    ///
+    /// # Errors
+    ///
+    /// - [`CellError::NegativeQuantity`]: If quantity is negative
+    /// - [`CellError::InvalidRateLimit`]: If `limit` or `period` is not positive
+    /// - [`CellError::Internal`]: If the store fails or `now` is before the
+    ///   Unix epoch
+    pub fn rate_limit(
+        &mut self,
+        key: &str,
+        limit: i64,
+        period: i64,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let quota = Quota::new(limit, limit, period);
+        SlidingWindow.rate_limit(&mut self.store, key, &quota, quantity, now)
+    }
+
+    /// Check whether a request would be allowed, without consuming quantity
+    ///
+    /// Computes the same decision as [`SlidingWindowLimiter::rate_limit`]
+    /// but leaves the store untouched.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`SlidingWindowLimiter::rate_limit`].
+    pub fn peek(
+        &self,
+        key: &str,
+        limit: i64,
+        period: i64,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let quota = Quota::new(limit, limit, period);
+        SlidingWindow.peek(&self.store, key, &quota, quantity, now)
+    }
+}
+
+/// Sliding window counter as an [`Algorithm`]
+///
+/// Allows up to `count_per_period` in any `period` seconds; `max_burst` is
+/// not used. See the [module documentation](self) for the store layout.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlidingWindow;
+
+impl Algorithm for SlidingWindow {
+    fn rate_limit<S: Store + ?Sized>(
+        &self,
+        store: &mut S,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let window = Window::new(quota, quantity, now)?;
+        let current_key = entry_key(key, window.index);
+        let previous_key = entry_key(key, window.index - 1);
+
+        // Retry loop with limit to prevent stack overflow
+        let mut retries = 0;
+
+        loop {
+            let current = store.get(&current_key, now).map_err(CellError::Internal)?;
+            let previous = store.get(&previous_key, now).map_err(CellError::Internal)?;
+            let decision = window.decide(previous.unwrap_or(0), current.unwrap_or(0), quantity);
+
+            // An empty request leaves the window as it is
+            if decision.allowed && quantity > 0 {
+                let count = current.unwrap_or(0).saturating_add(quantity);
+                // Read as the previous window until the next one ends
+                let ttl = to_duration(2 * window.period_ns - window.elapsed_ns);
+                if !algorithm::write(store, &current_key, current, count, ttl, now)? {
+                    retries += 1;
+                    if retries >= MAX_RETRIES {
+                        return Err(CellError::Internal("Max retries exceeded".into()));
+                    }
+                    continue;
+                }
+            }
+
+            return Ok((decision.allowed, window.result(&decision)));
+        }
+    }
+
+    fn peek<S: Store + ?Sized>(
+        &self,
+        store: &S,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        let window = Window::new(quota, quantity, now)?;
+        let current = store
+            .get(&entry_key(key, window.index), now)
+            .map_err(CellError::Internal)?;
+        let previous = store
+            .get(&entry_key(key, window.index - 1), now)
+            .map_err(CellError::Internal)?;
+        let decision = window.decide(previous.unwrap_or(0), current.unwrap_or(0), quantity);
+        Ok((decision.allowed, window.result(&decision)))
+    }
+
+    fn state_keys<'a>(&self, key: &'a str, quota: &Quota, now: SystemTime) -> Vec<Cow<'a, str>> {
+        window_keys(key, quota.period, now)
+            .into_iter()
+            .map(Cow::Owned)
+            .collect()
+    }
+}
+
+/// The fixed window a request falls in, in nanoseconds
+pub(crate) struct Window {
+    /// `count_per_period` of the quota
+    pub(crate) limit: i64,
+    pub(crate) period_ns: i128,
+    /// Windows since the Unix epoch
+    pub(crate) index: i64,
+    /// Time since the window started
+    pub(crate) elapsed_ns: i128,
+}
+
+/// Outcome of applying a request to the stored counts
+struct Decision {
+    allowed: bool,
+    /// Previous window's count
+    previous: i128,
+    /// Current window's count, including the request if it is allowed
+    current: i128,
+    quantity: i128,
+}
+
+impl Window {
+    /// The window `now` falls in, for a request of `quantity`
+    pub(crate) fn new(quota: &Quota, quantity: i64, now: SystemTime) -> Result<Self, CellError> {
+        check_quantity(quantity)?;
+        if quota.count_per_period <= 0 || quota.period <= 0 {
+            return Err(CellError::InvalidRateLimit);
+        }
+
+        let now_ns = nanos_since_epoch(now)?;
+        let period_ns = quota.period as i128 * NANOS_PER_SEC;
+
+        Ok(Window {
+            limit: quota.count_per_period,
+            period_ns,
+            index: (now_ns / period_ns) as i64,
+            elapsed_ns: now_ns % period_ns,
+        })
+    }
+
+    /// Estimated quantity in the sliding window, scaled by `period_ns`
+    fn weighted(&self, previous: i128, current: i128) -> i128 {
+        previous * (self.period_ns - self.elapsed_ns) + current * self.period_ns
+    }
+
+    fn decide(&self, previous: i64, current: i64, quantity: i64) -> Decision {
+        let (previous, current, quantity) = (previous as i128, current as i128, quantity as i128);
+        let allowed =
+            self.weighted(previous, current + quantity) <= self.limit as i128 * self.period_ns;
+        Decision {
+            allowed,
+            previous,
+            current: if allowed { current + quantity } else { current },
+            quantity,
+        }
+    }
+
+    fn result(&self, decision: &Decision) -> RateLimitResult {
+        let limit = self.limit as i128;
+        let period_ns = self.period_ns;
+        let until_next = period_ns - self.elapsed_ns;
+
+        let room = limit * period_ns - self.weighted(decision.previous, decision.current);
+        let remaining = (room / period_ns).max(0) as i64;
+
+        let reset_after = if decision.current > 0 {
+            until_next + period_ns
+        } else if decision.previous > 0 {
+            until_next
+        } else {
+            0
+        };
+
+        let retry_after = if decision.allowed {
+            0
+        } else if decision.current + decision.quantity <= limit {
+            // Fits once enough of the previous window has slid out
+            let excess = decision.previous * until_next
+                - (limit - decision.current - decision.quantity) * period_ns;
+            ceil_div(excess, decision.previous)
+        } else if decision.quantity <= limit {
+            // Fits once enough of the current window has slid out
+            let excess = (decision.current + decision.quantity - limit) * period_ns;
+            until_next + ceil_div(excess, decision.current)
+        } else {
+            // Never fits; report when the window is empty
+            reset_after
+        };
+
+        RateLimitResult {
+            limit: self.limit,
+            remaining,
+            reset_after: to_duration(reset_after),
+            retry_after: to_duration(retry_after),
+        }
+    }
+}
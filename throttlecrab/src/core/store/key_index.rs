@@ -0,0 +1,122 @@
+use super::Shards;
+use std::collections::BTreeSet;
+use std::ops::Bound;
+
+/// One page of keys from a prefix scan
+///
+/// Returned by the stores' `keys_with_prefix`. Keys are in ascending byte
+/// order; pass `next_cursor` back to continue after the last key.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct KeyPage {
+    /// Matching keys, at most the requested limit
+    pub keys: Vec<String>,
+    /// Cursor for the next page, or `None` if this was the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Optional sorted index of a store's keys
+///
+/// Enabled with the store builders' `key_index`. Keeps a second, ordered
+/// copy of every key so prefix scans only visit matching keys, at the cost
+/// of the extra memory and an O(log n) insert for each new key. Without it,
+/// a prefix scan walks the whole store.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct KeyIndex {
+    keys: Option<BTreeSet<String>>,
+}
+
+impl KeyIndex {
+    pub(crate) const fn new() -> Self {
+        KeyIndex { keys: None }
+    }
+
+    pub(crate) fn enable(&mut self) {
+        self.keys.get_or_insert_with(BTreeSet::new);
+    }
+
+    /// Record a key written to the store
+    pub(crate) fn insert(&mut self, key: &str) {
+        if let Some(keys) = &mut self.keys
+            && !keys.contains(key)
+        {
+            keys.insert(key.to_string());
+        }
+    }
+
+    /// Forget a key removed from the store
+    pub(crate) fn remove(&mut self, key: &str) {
+        if let Some(keys) = &mut self.keys {
+            keys.remove(key);
+        }
+    }
+
+    /// Drop keys no longer in `data`, after a bulk removal
+    pub(crate) fn sync(&mut self, data: &Shards) {
+        if let Some(keys) = &mut self.keys
+            && keys.len() != data.len()
+        {
+            keys.retain(|key| data.contains_key(key));
+        }
+    }
+
+    /// Estimated heap bytes held by the index, 0 when disabled
+    ///
+    /// B-tree nodes are assumed about two thirds full.
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.keys.as_ref().map_or(0, |keys| {
+            keys.iter()
+                .map(|key| size_of::<String>() * 3 / 2 + key.capacity())
+                .sum()
+        })
+    }
+
+    /// Keys in `data` starting with `prefix` and sorting after `cursor`
+    pub(crate) fn page(
+        &self,
+        data: &Shards,
+        prefix: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> KeyPage {
+        // Always make progress, even when asked for an empty page
+        let limit = limit.max(1);
+        // Start at the prefix, or just after the cursor if that is later
+        let start = match cursor {
+            Some(cursor) if cursor >= prefix => Bound::Excluded(cursor),
+            _ => Bound::Included(prefix),
+        };
+        // One extra key tells whether another page follows
+        let mut keys: Vec<String> = match &self.keys {
+            Some(index) => index
+                .range::<str, _>((start, Bound::Unbounded))
+                .take_while(|key| key.starts_with(prefix))
+                .take(limit.saturating_add(1))
+                .cloned()
+                .collect(),
+            None => {
+                let after_start = |key: &str| match start {
+                    Bound::Excluded(cursor) => key > cursor,
+                    _ => true,
+                };
+                let mut matching: Vec<&String> = data
+                    .keys()
+                    .filter(|key| key.starts_with(prefix) && after_start(key))
+                    .collect();
+                matching.sort_unstable();
+                matching
+                    .into_iter()
+                    .take(limit.saturating_add(1))
+                    .cloned()
+                    .collect()
+            }
+        };
+
+        let next_cursor = if keys.len() > limit {
+            keys.truncate(limit);
+            keys.last().cloned()
+        } else {
+            None
+        };
+        KeyPage { keys, next_cursor }
+    }
+}
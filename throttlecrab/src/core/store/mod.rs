@@ -5,8 +5,13 @@
 //! - [`AdaptiveStore`]: Self-tuning cleanup intervals based on usage patterns
 //! - [`PeriodicStore`]: Fixed interval cleanup for predictable workloads
 //! - [`ProbabilisticStore`]: Random sampling cleanup for high-throughput scenarios
+//! - [`TimingWheelStore`]: Incremental cleanup by expiry, without full scans
+//! - `RedisStore` (`redis` feature): State kept in an external Redis, shared
+//!   between processes, with `AsyncRedisStore` as its async counterpart
 //!
 //! All stores implement the [`Store`] trait, allowing them to be used interchangeably.
+//! With the `serde` feature, the in-memory stores can export their entries
+//! as a [`StoreState`] and import them again, e.g. across restarts.
 
 use std::time::{Duration, SystemTime};
 
@@ -15,12 +20,30 @@ mod tests;
 
 mod adaptive_cleanup;
 mod fast_hasher;
+mod key_index;
 mod periodic;
 mod probabilistic;
+mod recency;
+#[cfg(feature = "redis")]
+mod redis_store;
+mod shards;
+#[cfg(feature = "serde")]
+mod state;
+mod timing_wheel;
+mod wheel;
 
 pub use adaptive_cleanup::{AdaptiveStore, AdaptiveStoreBuilder};
+pub(crate) use key_index::KeyIndex;
+pub use key_index::KeyPage;
 pub use periodic::{PeriodicStore, PeriodicStoreBuilder};
 pub use probabilistic::{ProbabilisticStore, ProbabilisticStoreBuilder};
+pub(crate) use recency::RecencyIndex;
+#[cfg(feature = "redis")]
+pub use redis_store::{AsyncRedisStore, RedisStore};
+pub(crate) use shards::{Entry, Shards};
+#[cfg(feature = "serde")]
+pub use state::{StateEntry, StoreState};
+pub use timing_wheel::{TimingWheelStore, TimingWheelStoreBuilder};
 
 #[cfg(test)]
 mod cleanup_test;
@@ -131,3 +154,170 @@ pub trait Store {
         now: SystemTime,
     ) -> Result<bool, String>;
 }
+
+/// A borrowed store is a store, so limiters can share one
+impl<T: Store + ?Sized> Store for &mut T {
+    fn compare_and_swap_with_ttl(
+        &mut self,
+        key: &str,
+        old: i64,
+        new: i64,
+        ttl: Duration,
+        now: SystemTime,
+    ) -> Result<bool, String> {
+        (**self).compare_and_swap_with_ttl(key, old, new, ttl, now)
+    }
+
+    fn get(&self, key: &str, now: SystemTime) -> Result<Option<i64>, String> {
+        (**self).get(key, now)
+    }
+
+    fn set_if_not_exists_with_ttl(
+        &mut self,
+        key: &str,
+        value: i64,
+        ttl: Duration,
+        now: SystemTime,
+    ) -> Result<bool, String> {
+        (**self).set_if_not_exists_with_ttl(key, value, ttl, now)
+    }
+}
+
+/// Longest TTL a multiplier can produce, to keep expiry times representable
+const MAX_SCALED_TTL: Duration = Duration::from_nanos(i64::MAX as u64);
+
+/// TTL adjustments a store applies before writing an entry
+///
+/// Configured through the store builders with `ttl_multiplier` and
+/// `max_ttl`. The multiplier is applied first, then the cap.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TtlPolicy {
+    multiplier: f64,
+    max: Option<Duration>,
+    /// Number of writes whose TTL was shortened by `max`
+    capped: u64,
+}
+
+impl TtlPolicy {
+    pub(crate) const fn new() -> Self {
+        TtlPolicy {
+            multiplier: 1.0,
+            max: None,
+            capped: 0,
+        }
+    }
+
+    pub(crate) fn set_multiplier(&mut self, multiplier: f64) {
+        assert!(
+            multiplier.is_finite() && multiplier > 0.0,
+            "TTL multiplier must be positive and finite, got {multiplier}"
+        );
+        self.multiplier = multiplier;
+    }
+
+    pub(crate) fn set_max(&mut self, max: Duration) {
+        self.max = Some(max);
+    }
+
+    pub(crate) fn capped(&self) -> u64 {
+        self.capped
+    }
+
+    /// Adjust `ttl` for a write, counting it if the cap applied
+    pub(crate) fn apply(&mut self, ttl: Duration) -> Duration {
+        let ttl = if self.multiplier == 1.0 {
+            ttl
+        } else {
+            Duration::try_from_secs_f64(ttl.as_secs_f64() * self.multiplier)
+                .map_or(MAX_SCALED_TTL, |scaled| scaled.min(MAX_SCALED_TTL))
+        };
+
+        match self.max {
+            Some(max) if ttl > max => {
+                self.capped += 1;
+                max
+            }
+            _ => ttl,
+        }
+    }
+}
+
+/// Estimated heap bytes held by a store's table and keys
+///
+/// Counts every slot of the hash tables at their current capacity,
+/// including the control byte each slot carries, plus each key's
+/// allocation. Walks every key; allocator overhead is not included.
+pub(crate) fn table_memory(data: &Shards) -> usize {
+    // Each table keeps at most 7/8 of its slots occupied
+    let slots = data.capacity() * 8 / 7;
+    let slot_size = size_of::<(String, Entry)>() + 1;
+    slots * slot_size + data.keys().map(String::capacity).sum::<usize>()
+}
+
+/// Remove up to `count` entries to make room
+///
+/// Takes the least recently used entries if `recency` is enabled, else
+/// those with the earliest expiry. Returns the number of entries removed.
+pub(crate) fn evict_entries(data: &mut Shards, recency: &mut RecencyIndex, count: usize) -> usize {
+    if !recency.is_enabled() {
+        return evict_soonest_expiring(data, count);
+    }
+
+    let mut evicted = 0;
+    while evicted < count {
+        let Some(key) = recency.pop_least_recent() else {
+            break;
+        };
+        if data.remove(&key).is_some() {
+            evicted += 1;
+        }
+    }
+    evicted
+}
+
+/// Estimated heap bytes one more entry adds to a store
+///
+/// Counts a hash table slot at the table's typical 2/3 load, the key and,
+/// with `track_recency`, the recency index's share. Used to turn a memory
+/// budget into a key count.
+pub fn estimated_entry_memory(key_len: usize, track_recency: bool) -> usize {
+    let slot = (size_of::<(String, Entry)>() + 1) * 3 / 2;
+    let recency = if track_recency {
+        recency::RECENCY_ENTRY_BYTES + key_len
+    } else {
+        0
+    };
+    slot + key_len + recency
+}
+
+/// Remove up to `count` entries with the earliest expiry
+///
+/// Shared by the store implementations to make room when a caller needs to
+/// bound the number of keys. Entries without an expiry are never evicted.
+/// Returns the number of entries removed.
+pub(crate) fn evict_soonest_expiring(data: &mut Shards, count: usize) -> usize {
+    if count == 0 {
+        return 0;
+    }
+
+    let mut expiries: Vec<SystemTime> = data.values().filter_map(|(_, expiry)| *expiry).collect();
+    if expiries.is_empty() {
+        return 0;
+    }
+
+    // Find the expiry of the count-th oldest entry without a full sort
+    let nth = count.min(expiries.len()) - 1;
+    let (_, cutoff, _) = expiries.select_nth_unstable(nth);
+    let cutoff = *cutoff;
+
+    let mut budget = count;
+    data.retain(|_, (_, expiry)| match expiry {
+        Some(exp) if budget > 0 && *exp <= cutoff => {
+            budget -= 1;
+            false
+        }
+        _ => true,
+    });
+
+    count - budget
+}
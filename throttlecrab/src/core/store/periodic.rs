@@ -1,11 +1,8 @@
-use super::Store;
+#[cfg(feature = "serde")]
+use super::StoreState;
+use super::{KeyIndex, KeyPage, RecencyIndex, Shards, Store, TtlPolicy, evict_entries};
 use std::time::{Duration, SystemTime};
 
-#[cfg(feature = "ahash")]
-use ahash::AHashMap as HashMap;
-#[cfg(not(feature = "ahash"))]
-use std::collections::HashMap;
-
 // Configuration constants
 const DEFAULT_CAPACITY: usize = 1000;
 const CAPACITY_OVERHEAD_FACTOR: f64 = 1.3;
@@ -37,13 +34,19 @@ const DEFAULT_CLEANUP_INTERVAL_SECS: u64 = 60;
 /// let mut limiter = RateLimiter::new(store);
 /// ```
 pub struct PeriodicStore {
-    data: HashMap<String, (i64, Option<SystemTime>)>,
+    data: Shards,
     // Track when next cleanup is needed
     next_cleanup: SystemTime,
     // Cleanup interval
     cleanup_interval: Duration,
     // Track number of expired entries
     expired_count: usize,
+    // TTL multiplier and cap
+    ttl: TtlPolicy,
+    // Optional sorted key index for prefix scans
+    keys: KeyIndex,
+    // Optional recency order for LRU eviction
+    recency: RecencyIndex,
 }
 
 /// Builder for configuring a PeriodicStore
@@ -64,6 +67,10 @@ pub struct PeriodicStore {
 pub struct PeriodicStoreBuilder {
     capacity: usize,
     cleanup_interval: Duration,
+    ttl: TtlPolicy,
+    keys: KeyIndex,
+    recency: RecencyIndex,
+    shards: usize,
 }
 
 impl PeriodicStore {
@@ -84,10 +91,13 @@ impl PeriodicStore {
     pub fn with_capacity(capacity: usize) -> Self {
         PeriodicStore {
             // Pre-allocate with overhead to avoid rehashing
-            data: HashMap::with_capacity((capacity as f64 * CAPACITY_OVERHEAD_FACTOR) as usize),
+            data: Shards::with_capacity((capacity as f64 * CAPACITY_OVERHEAD_FACTOR) as usize, 1),
             next_cleanup: SystemTime::now() + Duration::from_secs(DEFAULT_CLEANUP_INTERVAL_SECS),
             cleanup_interval: Duration::from_secs(DEFAULT_CLEANUP_INTERVAL_SECS),
             expired_count: 0,
+            ttl: TtlPolicy::new(),
+            keys: KeyIndex::new(),
+            recency: RecencyIndex::new(),
         }
     }
 
@@ -98,46 +108,198 @@ impl PeriodicStore {
         PeriodicStoreBuilder {
             capacity: DEFAULT_CAPACITY,
             cleanup_interval: Duration::from_secs(DEFAULT_CLEANUP_INTERVAL_SECS),
+            ttl: TtlPolicy::new(),
+            keys: KeyIndex::new(),
+            recency: RecencyIndex::new(),
+            shards: 1,
         }
     }
 
-    fn with_config(capacity: usize, cleanup_interval: Duration) -> Self {
+    fn with_config(
+        capacity: usize,
+        cleanup_interval: Duration,
+        ttl: TtlPolicy,
+        keys: KeyIndex,
+        recency: RecencyIndex,
+        shards: usize,
+    ) -> Self {
         PeriodicStore {
-            data: HashMap::with_capacity((capacity as f64 * CAPACITY_OVERHEAD_FACTOR) as usize),
+            data: Shards::with_capacity(
+                (capacity as f64 * CAPACITY_OVERHEAD_FACTOR) as usize,
+                shards,
+            ),
             next_cleanup: SystemTime::now() + cleanup_interval,
             cleanup_interval,
             expired_count: 0,
+            ttl,
+            keys,
+            recency,
         }
     }
 
-    #[cfg(test)]
+    /// Number of entries currently held, including expired entries that
+    /// have not been cleaned up yet
     pub fn len(&self) -> usize {
         self.data.len()
     }
 
-    #[cfg(test)]
+    /// Returns `true` if the store holds no entries
     pub fn is_empty(&self) -> bool {
         self.data.is_empty()
     }
 
+    /// Number of writes whose TTL was shortened by the configured
+    /// [`max_ttl`](PeriodicStoreBuilder::max_ttl)
+    pub fn ttl_capped(&self) -> u64 {
+        self.ttl.capped()
+    }
+
     #[cfg(test)]
     pub fn expired_count(&self) -> usize {
         self.expired_count
     }
 
+    /// Remove the entry for `key`
+    ///
+    /// Returns `true` if an entry was present.
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.keys.remove(key);
+        self.recency.remove(key);
+        self.data.remove(key).is_some()
+    }
+
+    /// Get the raw entry for `key`: its value and expiry
+    ///
+    /// Unlike [`Store::get`], expired entries that have not been cleaned up
+    /// yet are returned as well.
+    pub fn entry(&self, key: &str) -> Option<(i64, Option<SystemTime>)> {
+        self.data.get(key).copied()
+    }
+
+    /// Iterate over all entries as `(key, value, expiry)`
+    ///
+    /// Useful for persisting store state. Includes expired entries that have
+    /// not been cleaned up yet.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, i64, Option<SystemTime>)> {
+        self.data
+            .iter()
+            .map(|(key, (value, expiry))| (key.as_str(), *value, *expiry))
+    }
+
+    /// Insert an entry unconditionally, replacing any existing value
+    ///
+    /// Intended for restoring previously persisted state; rate limiting
+    /// itself goes through the [`Store`] trait methods.
+    pub fn insert(&mut self, key: &str, value: i64, expiry: Option<SystemTime>) {
+        self.keys.insert(key);
+        self.recency.touch(key);
+        self.data.insert(key.to_string(), (value, expiry));
+    }
+
+    /// Copy the entries live at `now`, for persisting with serde
+    ///
+    /// Restore them with [`import_state`](Self::import_state).
+    #[cfg(feature = "serde")]
+    pub fn export_state(&self, now: SystemTime) -> StoreState {
+        StoreState::from_entries(self.iter(), now)
+    }
+
+    /// Insert the entries of an exported `state` still live at `now`
+    ///
+    /// Existing entries for the same keys are replaced. Returns the number
+    /// of entries restored.
+    #[cfg(feature = "serde")]
+    pub fn import_state(&mut self, state: StoreState, now: SystemTime) -> usize {
+        state
+            .into_live(now)
+            .map(|entry| self.insert(&entry.key, entry.value, entry.expires_at))
+            .count()
+    }
+
+    /// List keys starting with `prefix`, one page at a time
+    ///
+    /// Returns up to `limit` keys in ascending order that sort after
+    /// `cursor`; pass the returned [`KeyPage::next_cursor`] to fetch the
+    /// next page. Includes expired entries that have not been cleaned up
+    /// yet. Only visits matching keys if the store was built with
+    /// [`key_index`](PeriodicStoreBuilder::key_index); otherwise every page scans
+    /// the whole store.
+    pub fn keys_with_prefix(&self, prefix: &str, cursor: Option<&str>, limit: usize) -> KeyPage {
+        self.keys.page(&self.data, prefix, cursor, limit)
+    }
+
+    /// Mark `key` as recently used without changing its value
+    ///
+    /// Keeps a key that is read but not written, e.g. one whose requests
+    /// are all denied, from being evicted first under
+    /// [`track_recency`](PeriodicStoreBuilder::track_recency). Does nothing for
+    /// missing keys or when recency is not tracked.
+    pub fn touch(&mut self, key: &str) {
+        if self.data.contains_key(key) {
+            self.recency.touch(key);
+        }
+    }
+
+    /// Estimated heap memory held by the store, in bytes
+    ///
+    /// Covers the hash table at its current capacity, the keys, and the
+    /// [`key_index`](PeriodicStoreBuilder::key_index) and
+    /// [`track_recency`](PeriodicStoreBuilder::track_recency) indexes if enabled. Walks every
+    /// key, so call it for reporting rather than on the request path.
+    pub fn memory_usage(&self) -> usize {
+        super::table_memory(&self.data) + self.keys.memory_usage() + self.recency.memory_usage()
+    }
+
+    /// Remove all expired entries immediately
+    ///
+    /// Runs a cleanup pass regardless of the configured interval and
+    /// reschedules the next periodic cleanup. Returns the number of
+    /// entries removed.
+    pub fn remove_expired(&mut self, now: SystemTime) -> usize {
+        let before_count = self.data.len();
+        self.data.retain(|_, (_, expiry)| {
+            if let Some(exp) = expiry {
+                *exp > now
+            } else {
+                true
+            }
+        });
+        self.keys.sync(&self.data);
+        self.recency.sync(&self.data);
+        self.expired_count = before_count.saturating_sub(self.data.len());
+        self.next_cleanup = now + self.cleanup_interval;
+        self.expired_count
+    }
+
+    /// Make room by removing up to `count` entries
+    ///
+    /// Expired entries are removed first. If that frees fewer than `count`
+    /// entries, the live entries closest to expiry are evicted next; for GCRA
+    /// state these are the keys that have been idle the longest relative to
+    /// their limits. With [`track_recency`](PeriodicStoreBuilder::track_recency)
+    /// enabled, the least recently used entries are evicted instead. Returns
+    /// the number of entries removed.
+    pub fn evict(&mut self, count: usize, now: SystemTime) -> usize {
+        let removed = self.remove_expired(now);
+        let evicted = evict_entries(
+            &mut self.data,
+            &mut self.recency,
+            count.saturating_sub(removed),
+        );
+        self.keys.sync(&self.data);
+        self.recency.sync(&self.data);
+        removed + evicted
+    }
+
     fn maybe_clean_expired(&mut self, now: SystemTime) {
-        // Clean periodically based on time
+        // Clean periodically based on time, one shard per pass so that each
+        // shard is still swept once per interval
         if now >= self.next_cleanup {
-            let before_count = self.data.len();
-            self.data.retain(|_, (_, expiry)| {
-                if let Some(exp) = expiry {
-                    *exp > now
-                } else {
-                    true
-                }
-            });
-            self.expired_count = before_count.saturating_sub(self.data.len());
-            self.next_cleanup = now + self.cleanup_interval;
+            let (_, removed) = self
+                .data
+                .sweep_expired(now, &mut self.keys, &mut self.recency);
+            self.expired_count = removed;
+            self.next_cleanup = now + self.cleanup_interval / self.data.count() as u32;
         }
     }
 }
@@ -163,7 +325,8 @@ impl Store for PeriodicStore {
         match self.data.get(key) {
             Some((_current, Some(expiry))) if *expiry <= now => Ok(false),
             Some((current, _)) if *current == old => {
-                let expiry = now + ttl;
+                let expiry = now + self.ttl.apply(ttl);
+                self.recency.touch(key);
                 self.data.insert(key.to_string(), (new, Some(expiry)));
                 Ok(true)
             }
@@ -195,13 +358,16 @@ impl Store for PeriodicStore {
             Some((_, None)) => Ok(false),
             Some((_, Some(_expiry))) => {
                 // Key is expired - insert the new value
-                let expiry = now + ttl;
+                let expiry = now + self.ttl.apply(ttl);
+                self.recency.touch(key);
                 self.data.insert(key.to_string(), (value, Some(expiry)));
                 Ok(true)
             }
             None => {
                 // Key doesn't exist
-                let expiry = now + ttl;
+                let expiry = now + self.ttl.apply(ttl);
+                self.keys.insert(key);
+                self.recency.touch(key);
                 self.data.insert(key.to_string(), (value, Some(expiry)));
                 Ok(true)
             }
@@ -214,6 +380,10 @@ impl Default for PeriodicStoreBuilder {
         Self {
             capacity: DEFAULT_CAPACITY,
             cleanup_interval: Duration::from_secs(DEFAULT_CLEANUP_INTERVAL_SECS),
+            ttl: TtlPolicy::new(),
+            keys: KeyIndex::new(),
+            recency: RecencyIndex::new(),
+            shards: 1,
         }
     }
 }
@@ -252,8 +422,115 @@ impl PeriodicStoreBuilder {
         self
     }
 
+    /// Multiply every entry's TTL by `multiplier`
+    ///
+    /// Values above 1.0 keep idle keys around longer, values below 1.0
+    /// reclaim memory sooner at the cost of forgetting state for keys that
+    /// are still within their period. Defaults to 1.0.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `multiplier` is not positive and finite.
+    pub fn ttl_multiplier(mut self, multiplier: f64) -> Self {
+        self.ttl.set_multiplier(multiplier);
+        self
+    }
+
+    /// Cap every entry's TTL at `max`
+    ///
+    /// Protects the store from entries that would otherwise live for years,
+    /// e.g. from a client requesting a ten year period. A capped key forgets
+    /// its state once the cap expires, so its limit is enforced less
+    /// strictly. Writes that hit the cap are counted by `ttl_capped`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use throttlecrab::PeriodicStore;
+    /// use std::time::Duration;
+    ///
+    /// let store = PeriodicStore::builder()
+    ///     .max_ttl(Duration::from_secs(24 * 60 * 60))
+    ///     .build();
+    /// ```
+    pub fn max_ttl(mut self, max: Duration) -> Self {
+        self.ttl.set_max(max);
+        self
+    }
+
+    /// Keep a sorted index of keys for [`keys_with_prefix`]
+    ///
+    /// Prefix scans then only visit matching keys instead of the whole
+    /// store, which matters for admin listings over millions of keys. The
+    /// index holds a second copy of every key and adds an O(log n) insert
+    /// for each new key. Disabled by default.
+    ///
+    /// [`keys_with_prefix`]: PeriodicStore::keys_with_prefix
+    pub fn key_index(mut self, enabled: bool) -> Self {
+        if enabled {
+            self.keys.enable();
+        } else {
+            self.keys = KeyIndex::new();
+        }
+        self
+    }
+
+    /// Track how recently each key was used, so [`evict`] removes the least
+    /// recently used entries instead of those closest to expiry
+    ///
+    /// Writes and [`touch`] mark a key as used. Costs a shared copy of every
+    /// key, about 100 bytes per key for the ordering and an O(log n) update
+    /// on every write. Disabled by default.
+    ///
+    /// [`evict`]: PeriodicStore::evict
+    /// [`touch`]: PeriodicStore::touch
+    pub fn track_recency(mut self, enabled: bool) -> Self {
+        if enabled {
+            self.recency.enable();
+        } else {
+            self.recency = RecencyIndex::new();
+        }
+        self
+    }
+
+    /// Split the table into `shards` independently sized hash maps
+    ///
+    /// A hash map that outgrows its capacity rehashes every entry at once,
+    /// which with millions of keys stalls the operation that triggered it.
+    /// With `n` shards, growth rehashes one shard, about 1/n of the keys,
+    /// and each cleanup pass sweeps a single shard in turn, so no operation
+    /// scans the whole table. Every key is hashed once more to pick its
+    /// shard. Defaults to 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use throttlecrab::PeriodicStore;
+    ///
+    /// let store = PeriodicStore::builder()
+    ///     .capacity(1_000_000)
+    ///     .shards(16)
+    ///     .build();
+    /// ```
+    pub fn shards(mut self, shards: usize) -> Self {
+        assert!(shards > 0, "store must have at least one shard");
+        self.shards = shards;
+        self
+    }
+
     /// Build the PeriodicStore with the configured settings
     pub fn build(self) -> PeriodicStore {
-        PeriodicStore::with_config(self.capacity, self.cleanup_interval)
+        PeriodicStore::with_config(
+            self.capacity,
+            self.cleanup_interval,
+            self.ttl,
+            self.keys,
+            self.recency,
+            self.shards,
+        )
     }
 }
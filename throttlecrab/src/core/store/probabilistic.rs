@@ -1,11 +1,8 @@
-use super::Store;
+#[cfg(feature = "serde")]
+use super::StoreState;
+use super::{KeyIndex, KeyPage, RecencyIndex, Shards, Store, TtlPolicy, evict_entries};
 use std::time::{Duration, SystemTime};
 
-#[cfg(feature = "ahash")]
-use ahash::AHashMap as HashMap;
-#[cfg(not(feature = "ahash"))]
-use std::collections::HashMap;
-
 // Configuration constants
 const DEFAULT_CAPACITY: usize = 1000;
 const CAPACITY_OVERHEAD_FACTOR: f64 = 1.3;
@@ -42,9 +39,12 @@ const PROBABILISTIC_CLEANUP_MODULO: u64 = 1000; // 0.1% chance
 /// Uses a deterministic pseudo-random approach based on operation count,
 /// ensuring uniform distribution of cleanup operations over time.
 pub struct ProbabilisticStore {
-    data: HashMap<String, (i64, Option<SystemTime>)>,
+    data: Shards,
     operations_count: u64,
     cleanup_probability: u64,
+    ttl: TtlPolicy,
+    keys: KeyIndex,
+    recency: RecencyIndex,
 }
 
 /// Builder for configuring a ProbabilisticStore
@@ -64,6 +64,10 @@ pub struct ProbabilisticStore {
 pub struct ProbabilisticStoreBuilder {
     capacity: usize,
     cleanup_probability: u64,
+    ttl: TtlPolicy,
+    keys: KeyIndex,
+    recency: RecencyIndex,
+    shards: usize,
 }
 
 impl ProbabilisticStore {
@@ -83,9 +87,12 @@ impl ProbabilisticStore {
     /// - `capacity`: Expected number of unique keys to track
     pub fn with_capacity(capacity: usize) -> Self {
         ProbabilisticStore {
-            data: HashMap::with_capacity((capacity as f64 * CAPACITY_OVERHEAD_FACTOR) as usize),
+            data: Shards::with_capacity((capacity as f64 * CAPACITY_OVERHEAD_FACTOR) as usize, 1),
             operations_count: 0,
             cleanup_probability: PROBABILISTIC_CLEANUP_MODULO,
+            ttl: TtlPolicy::new(),
+            keys: KeyIndex::new(),
+            recency: RecencyIndex::new(),
         }
     }
 
@@ -96,31 +103,190 @@ impl ProbabilisticStore {
         ProbabilisticStoreBuilder {
             capacity: DEFAULT_CAPACITY,
             cleanup_probability: PROBABILISTIC_CLEANUP_MODULO,
+            ttl: TtlPolicy::new(),
+            keys: KeyIndex::new(),
+            recency: RecencyIndex::new(),
+            shards: 1,
         }
     }
 
-    fn with_config(capacity: usize, cleanup_probability: u64) -> Self {
+    fn with_config(
+        capacity: usize,
+        cleanup_probability: u64,
+        ttl: TtlPolicy,
+        keys: KeyIndex,
+        recency: RecencyIndex,
+        shards: usize,
+    ) -> Self {
         ProbabilisticStore {
-            data: HashMap::with_capacity((capacity as f64 * CAPACITY_OVERHEAD_FACTOR) as usize),
+            data: Shards::with_capacity(
+                (capacity as f64 * CAPACITY_OVERHEAD_FACTOR) as usize,
+                shards,
+            ),
             operations_count: 0,
             cleanup_probability,
+            ttl,
+            keys,
+            recency,
+        }
+    }
+
+    /// Number of entries currently held, including expired entries that
+    /// have not been cleaned up yet
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns `true` if the store holds no entries
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Number of writes whose TTL was shortened by the configured
+    /// [`max_ttl`](ProbabilisticStoreBuilder::max_ttl)
+    pub fn ttl_capped(&self) -> u64 {
+        self.ttl.capped()
+    }
+
+    /// Remove the entry for `key`
+    ///
+    /// Returns `true` if an entry was present.
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.keys.remove(key);
+        self.recency.remove(key);
+        self.data.remove(key).is_some()
+    }
+
+    /// Get the raw entry for `key`: its value and expiry
+    ///
+    /// Unlike [`Store::get`], expired entries that have not been cleaned up
+    /// yet are returned as well.
+    pub fn entry(&self, key: &str) -> Option<(i64, Option<SystemTime>)> {
+        self.data.get(key).copied()
+    }
+
+    /// Iterate over all entries as `(key, value, expiry)`
+    ///
+    /// Useful for persisting store state. Includes expired entries that have
+    /// not been cleaned up yet.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, i64, Option<SystemTime>)> {
+        self.data
+            .iter()
+            .map(|(key, (value, expiry))| (key.as_str(), *value, *expiry))
+    }
+
+    /// Insert an entry unconditionally, replacing any existing value
+    ///
+    /// Intended for restoring previously persisted state; rate limiting
+    /// itself goes through the [`Store`] trait methods.
+    pub fn insert(&mut self, key: &str, value: i64, expiry: Option<SystemTime>) {
+        self.keys.insert(key);
+        self.recency.touch(key);
+        self.data.insert(key.to_string(), (value, expiry));
+    }
+
+    /// Copy the entries live at `now`, for persisting with serde
+    ///
+    /// Restore them with [`import_state`](Self::import_state).
+    #[cfg(feature = "serde")]
+    pub fn export_state(&self, now: SystemTime) -> StoreState {
+        StoreState::from_entries(self.iter(), now)
+    }
+
+    /// Insert the entries of an exported `state` still live at `now`
+    ///
+    /// Existing entries for the same keys are replaced. Returns the number
+    /// of entries restored.
+    #[cfg(feature = "serde")]
+    pub fn import_state(&mut self, state: StoreState, now: SystemTime) -> usize {
+        state
+            .into_live(now)
+            .map(|entry| self.insert(&entry.key, entry.value, entry.expires_at))
+            .count()
+    }
+
+    /// List keys starting with `prefix`, one page at a time
+    ///
+    /// Returns up to `limit` keys in ascending order that sort after
+    /// `cursor`; pass the returned [`KeyPage::next_cursor`] to fetch the
+    /// next page. Includes expired entries that have not been cleaned up
+    /// yet. Only visits matching keys if the store was built with
+    /// [`key_index`](ProbabilisticStoreBuilder::key_index); otherwise every page scans
+    /// the whole store.
+    pub fn keys_with_prefix(&self, prefix: &str, cursor: Option<&str>, limit: usize) -> KeyPage {
+        self.keys.page(&self.data, prefix, cursor, limit)
+    }
+
+    /// Mark `key` as recently used without changing its value
+    ///
+    /// Keeps a key that is read but not written, e.g. one whose requests
+    /// are all denied, from being evicted first under
+    /// [`track_recency`](ProbabilisticStoreBuilder::track_recency). Does nothing for
+    /// missing keys or when recency is not tracked.
+    pub fn touch(&mut self, key: &str) {
+        if self.data.contains_key(key) {
+            self.recency.touch(key);
         }
     }
 
+    /// Estimated heap memory held by the store, in bytes
+    ///
+    /// Covers the hash table at its current capacity, the keys, and the
+    /// [`key_index`](ProbabilisticStoreBuilder::key_index) and
+    /// [`track_recency`](ProbabilisticStoreBuilder::track_recency) indexes if enabled. Walks every
+    /// key, so call it for reporting rather than on the request path.
+    pub fn memory_usage(&self) -> usize {
+        super::table_memory(&self.data) + self.keys.memory_usage() + self.recency.memory_usage()
+    }
+
+    /// Remove all expired entries immediately
+    ///
+    /// Returns the number of entries removed.
+    pub fn remove_expired(&mut self, now: SystemTime) -> usize {
+        let before_count = self.data.len();
+        self.data.retain(|_, (_, expiry)| {
+            if let Some(exp) = expiry {
+                *exp > now
+            } else {
+                true
+            }
+        });
+        self.keys.sync(&self.data);
+        self.recency.sync(&self.data);
+        before_count - self.data.len()
+    }
+
+    /// Make room by removing up to `count` entries
+    ///
+    /// Expired entries are removed first. If that frees fewer than `count`
+    /// entries, the live entries closest to expiry are evicted next; for GCRA
+    /// state these are the keys that have been idle the longest relative to
+    /// their limits. With [`track_recency`](ProbabilisticStoreBuilder::track_recency)
+    /// enabled, the least recently used entries are evicted instead. Returns
+    /// the number of entries removed.
+    pub fn evict(&mut self, count: usize, now: SystemTime) -> usize {
+        let removed = self.remove_expired(now);
+        let evicted = evict_entries(
+            &mut self.data,
+            &mut self.recency,
+            count.saturating_sub(removed),
+        );
+        self.keys.sync(&self.data);
+        self.recency.sync(&self.data);
+        removed + evicted
+    }
+
     fn maybe_cleanup(&mut self, now: SystemTime) {
         self.operations_count += 1;
 
         // Simple pseudo-random using operations count
         // This gives uniform distribution over time while being deterministic
         let hash = self.operations_count.wrapping_mul(2654435761); // Prime multiplier
-        if hash.is_multiple_of(self.cleanup_probability) {
-            self.data.retain(|_, (_, expiry)| {
-                if let Some(exp) = expiry {
-                    *exp > now
-                } else {
-                    true
-                }
-            });
+        // Each pass sweeps one shard, so pass once per shard as often
+        let modulo = self.cleanup_probability.div_ceil(self.data.count() as u64);
+        if hash.is_multiple_of(modulo) {
+            self.data
+                .sweep_expired(now, &mut self.keys, &mut self.recency);
         }
     }
 }
@@ -145,7 +311,8 @@ impl Store for ProbabilisticStore {
         match self.data.get(key) {
             Some((_current, Some(expiry))) if *expiry <= now => Ok(false),
             Some((current, _)) if *current == old => {
-                let expiry = now + ttl;
+                let expiry = now + self.ttl.apply(ttl);
+                self.recency.touch(key);
                 self.data.insert(key.to_string(), (new, Some(expiry)));
                 Ok(true)
             }
@@ -175,7 +342,9 @@ impl Store for ProbabilisticStore {
             Some((_, Some(expiry))) if *expiry > now => Ok(false),
             Some((_, None)) => Ok(false),
             _ => {
-                let expiry = now + ttl;
+                let expiry = now + self.ttl.apply(ttl);
+                self.keys.insert(key);
+                self.recency.touch(key);
                 self.data.insert(key.to_string(), (value, Some(expiry)));
                 Ok(true)
             }
@@ -188,6 +357,10 @@ impl Default for ProbabilisticStoreBuilder {
         Self {
             capacity: DEFAULT_CAPACITY,
             cleanup_probability: PROBABILISTIC_CLEANUP_MODULO,
+            ttl: TtlPolicy::new(),
+            keys: KeyIndex::new(),
+            recency: RecencyIndex::new(),
+            shards: 1,
         }
     }
 }
@@ -226,8 +399,115 @@ impl ProbabilisticStoreBuilder {
         self
     }
 
+    /// Multiply every entry's TTL by `multiplier`
+    ///
+    /// Values above 1.0 keep idle keys around longer, values below 1.0
+    /// reclaim memory sooner at the cost of forgetting state for keys that
+    /// are still within their period. Defaults to 1.0.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `multiplier` is not positive and finite.
+    pub fn ttl_multiplier(mut self, multiplier: f64) -> Self {
+        self.ttl.set_multiplier(multiplier);
+        self
+    }
+
+    /// Cap every entry's TTL at `max`
+    ///
+    /// Protects the store from entries that would otherwise live for years,
+    /// e.g. from a client requesting a ten year period. A capped key forgets
+    /// its state once the cap expires, so its limit is enforced less
+    /// strictly. Writes that hit the cap are counted by `ttl_capped`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use throttlecrab::ProbabilisticStore;
+    /// use std::time::Duration;
+    ///
+    /// let store = ProbabilisticStore::builder()
+    ///     .max_ttl(Duration::from_secs(24 * 60 * 60))
+    ///     .build();
+    /// ```
+    pub fn max_ttl(mut self, max: Duration) -> Self {
+        self.ttl.set_max(max);
+        self
+    }
+
+    /// Keep a sorted index of keys for [`keys_with_prefix`]
+    ///
+    /// Prefix scans then only visit matching keys instead of the whole
+    /// store, which matters for admin listings over millions of keys. The
+    /// index holds a second copy of every key and adds an O(log n) insert
+    /// for each new key. Disabled by default.
+    ///
+    /// [`keys_with_prefix`]: ProbabilisticStore::keys_with_prefix
+    pub fn key_index(mut self, enabled: bool) -> Self {
+        if enabled {
+            self.keys.enable();
+        } else {
+            self.keys = KeyIndex::new();
+        }
+        self
+    }
+
+    /// Track how recently each key was used, so [`evict`] removes the least
+    /// recently used entries instead of those closest to expiry
+    ///
+    /// Writes and [`touch`] mark a key as used. Costs a shared copy of every
+    /// key, about 100 bytes per key for the ordering and an O(log n) update
+    /// on every write. Disabled by default.
+    ///
+    /// [`evict`]: ProbabilisticStore::evict
+    /// [`touch`]: ProbabilisticStore::touch
+    pub fn track_recency(mut self, enabled: bool) -> Self {
+        if enabled {
+            self.recency.enable();
+        } else {
+            self.recency = RecencyIndex::new();
+        }
+        self
+    }
+
+    /// Split the table into `shards` independently sized hash maps
+    ///
+    /// A hash map that outgrows its capacity rehashes every entry at once,
+    /// which with millions of keys stalls the operation that triggered it.
+    /// With `n` shards, growth rehashes one shard, about 1/n of the keys,
+    /// and each cleanup pass sweeps a single shard in turn, so no operation
+    /// scans the whole table. Every key is hashed once more to pick its
+    /// shard. Defaults to 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use throttlecrab::ProbabilisticStore;
+    ///
+    /// let store = ProbabilisticStore::builder()
+    ///     .capacity(1_000_000)
+    ///     .shards(16)
+    ///     .build();
+    /// ```
+    pub fn shards(mut self, shards: usize) -> Self {
+        assert!(shards > 0, "store must have at least one shard");
+        self.shards = shards;
+        self
+    }
+
     /// Build the ProbabilisticStore with the configured settings
     pub fn build(self) -> ProbabilisticStore {
-        ProbabilisticStore::with_config(self.capacity, self.cleanup_probability)
+        ProbabilisticStore::with_config(
+            self.capacity,
+            self.cleanup_probability,
+            self.ttl,
+            self.keys,
+            self.recency,
+            self.shards,
+        )
     }
 }
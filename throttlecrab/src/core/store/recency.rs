@@ -0,0 +1,104 @@
+use super::Shards;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Optional record of when each key was last used
+///
+/// Enabled with the store builders' `track_recency`. Orders keys by their
+/// last write or [`touch`](crate::PeriodicStore::touch) so `evict` can
+/// remove the least recently used ones. Costs a shared copy of every key,
+/// two map entries per key and an O(log n) update on every write.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RecencyIndex {
+    state: Option<Recency>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Recency {
+    /// Incremented on every use; a key's last tick orders it
+    tick: u64,
+    last_used: HashMap<Arc<str>, u64>,
+    by_tick: BTreeMap<u64, Arc<str>>,
+}
+
+impl RecencyIndex {
+    pub(crate) const fn new() -> Self {
+        RecencyIndex { state: None }
+    }
+
+    pub(crate) fn enable(&mut self) {
+        self.state.get_or_insert_with(Recency::default);
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.state.is_some()
+    }
+
+    /// Mark `key` as the most recently used
+    pub(crate) fn touch(&mut self, key: &str) {
+        let Some(state) = &mut self.state else {
+            return;
+        };
+        state.tick += 1;
+        let tick = state.tick;
+        match state.last_used.get_mut(key) {
+            Some(last) => {
+                let key = state
+                    .by_tick
+                    .remove(last)
+                    .expect("every tracked key has a tick");
+                *last = tick;
+                state.by_tick.insert(tick, key);
+            }
+            None => {
+                let key: Arc<str> = Arc::from(key);
+                state.last_used.insert(Arc::clone(&key), tick);
+                state.by_tick.insert(tick, key);
+            }
+        }
+    }
+
+    /// Forget a key removed from the store
+    pub(crate) fn remove(&mut self, key: &str) {
+        if let Some(state) = &mut self.state
+            && let Some(tick) = state.last_used.remove(key)
+        {
+            state.by_tick.remove(&tick);
+        }
+    }
+
+    /// Take the least recently used key out of the index
+    pub(crate) fn pop_least_recent(&mut self) -> Option<Arc<str>> {
+        let state = self.state.as_mut()?;
+        let (_, key) = state.by_tick.pop_first()?;
+        state.last_used.remove(&key);
+        Some(key)
+    }
+
+    /// Drop keys no longer in `data`, after a bulk removal
+    pub(crate) fn sync(&mut self, data: &Shards) {
+        if let Some(state) = &mut self.state
+            && state.last_used.len() != data.len()
+        {
+            state.last_used.retain(|key, _| data.contains_key(key));
+            let last_used = &state.last_used;
+            state.by_tick.retain(|_, key| last_used.contains_key(key));
+        }
+    }
+
+    /// Estimated heap bytes held by the index, 0 when disabled
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.state.as_ref().map_or(0, |state| {
+            state.last_used.len() * RECENCY_ENTRY_BYTES
+                + state.last_used.keys().map(|key| key.len()).sum::<usize>()
+        })
+    }
+}
+
+/// Bytes the recency index adds per key, besides the key itself
+///
+/// A hash table slot at about 2/3 load, a B-tree entry at about 2/3 fill
+/// and the reference counts of the shared key.
+pub(crate) const RECENCY_ENTRY_BYTES: usize = (size_of::<(Arc<str>, u64)>() + 1) * 3 / 2
+    + size_of::<(u64, Arc<str>)>() * 3 / 2
+    + 2 * size_of::<usize>();
@@ -0,0 +1,464 @@
+//! Redis-backed store for state shared between processes
+//!
+//! [`RedisStore`] keeps every entry in an external Redis instead of local
+//! memory, so several rate limiters, typically in different processes or on
+//! different machines, limit against exactly the same state. Each entry is a
+//! Redis string holding the value in decimal, written with a millisecond TTL
+//! so Redis expires it; compare-and-swap runs as a Lua script to stay atomic
+//! across writers.
+//!
+//! Every operation of [`RedisStore`] is a blocking round trip to Redis,
+//! bounded by the store's timeout. Expect each decision to take a network
+//! round trip or two rather than the tens of nanoseconds of the in-memory
+//! stores. [`AsyncRedisStore`] stores entries the same way as an
+//! [`AsyncStore`], for callers on a Tokio runtime that must not block.
+
+use super::Store;
+use crate::core::async_store::AsyncStore;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use redis::{Client, Connection, RedisResult, Script};
+use std::cell::RefCell;
+use std::time::{Duration, SystemTime};
+
+/// Set `KEYS[1]` to `ARGV[2]` with a TTL of `ARGV[3]` ms if it holds `ARGV[1]`
+const COMPARE_AND_SWAP: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    redis.call('SET', KEYS[1], ARGV[2], 'PX', ARGV[3])
+    return 1
+end
+return 0
+";
+
+/// Store keeping rate limit state in Redis
+///
+/// Keys are written under a prefix, `throttlecrab:` by default, so the
+/// store can share a Redis database with other data. A failed connection is
+/// dropped and opened again on the next operation; the operation itself
+/// fails with the Redis error.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::SystemTime;
+/// use throttlecrab::{RateLimiter, RedisStore};
+///
+/// let store = RedisStore::open("redis://127.0.0.1:6379")?.with_prefix("ratelimit:");
+/// let mut limiter = RateLimiter::new(store);
+///
+/// let (allowed, _) = limiter
+///     .rate_limit("user:123", 10, 100, 60, 1, SystemTime::now())
+///     .map_err(|e| e.to_string())?;
+/// # Ok::<(), String>(())
+/// ```
+pub struct RedisStore {
+    client: Client,
+    connection: RefCell<Option<Connection>>,
+    compare_and_swap: Script,
+    prefix: String,
+    timeout: Duration,
+}
+
+impl RedisStore {
+    /// Connect to the Redis at `url`, e.g. `redis://127.0.0.1:6379/0`
+    ///
+    /// Connects right away, so an unreachable server is reported here
+    /// rather than on the first rate limit check.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URL is invalid or the server cannot be
+    /// reached.
+    pub fn open(url: &str) -> Result<Self, String> {
+        let client = Client::open(url).map_err(|e| format!("Invalid Redis URL {url}: {e}"))?;
+        let store = RedisStore {
+            client,
+            connection: RefCell::new(None),
+            compare_and_swap: Script::new(COMPARE_AND_SWAP),
+            prefix: "throttlecrab:".to_string(),
+            timeout: Duration::from_secs(1),
+        };
+        let connection = store.connect()?;
+        *store.connection.borrow_mut() = Some(connection);
+        Ok(store)
+    }
+
+    /// Write keys under `prefix` instead of `throttlecrab:`
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Give up on a connection attempt or command after `timeout`
+    ///
+    /// Defaults to one second. Applies to connections opened afterwards.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        *self.connection.get_mut() = None;
+        self
+    }
+
+    /// Delete the entry for `key`, returning whether it existed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if Redis cannot be reached.
+    pub fn remove(&mut self, key: &str) -> Result<bool, String> {
+        let key = self.key(key);
+        self.with_connection(|connection| redis::cmd("DEL").arg(&key).query::<i64>(connection))
+            .map(|deleted| deleted > 0)
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    fn connect(&self) -> Result<Connection, String> {
+        let connect = || -> RedisResult<Connection> {
+            let connection = self.client.get_connection_with_timeout(self.timeout)?;
+            connection.set_read_timeout(Some(self.timeout))?;
+            connection.set_write_timeout(Some(self.timeout))?;
+            Ok(connection)
+        };
+        connect().map_err(|e| format!("Failed to connect to Redis: {e}"))
+    }
+
+    /// Run `command` on the connection, opening one if needed
+    fn with_connection<T>(
+        &self,
+        command: impl FnOnce(&mut Connection) -> RedisResult<T>,
+    ) -> Result<T, String> {
+        let mut slot = self.connection.borrow_mut();
+        let connection = match &mut *slot {
+            Some(connection) => connection,
+            None => slot.insert(self.connect()?),
+        };
+        command(connection).map_err(|e| {
+            // A broken or timed out connection may be out of step with the server
+            if e.is_io_error() || e.is_timeout() || e.is_unrecoverable_error() {
+                *slot = None;
+            }
+            format!("Redis error: {e}")
+        })
+    }
+}
+
+/// TTL in whole milliseconds, at least one since Redis rejects zero
+fn ttl_millis(ttl: Duration) -> u64 {
+    ttl.as_millis().clamp(1, i64::MAX as u128) as u64
+}
+
+impl Store for RedisStore {
+    fn compare_and_swap_with_ttl(
+        &mut self,
+        key: &str,
+        old: i64,
+        new: i64,
+        ttl: Duration,
+        _now: SystemTime,
+    ) -> Result<bool, String> {
+        let key = self.key(key);
+        let swapped = self.with_connection(|connection| {
+            self.compare_and_swap
+                .key(&key)
+                .arg(old)
+                .arg(new)
+                .arg(ttl_millis(ttl))
+                .invoke::<i64>(connection)
+        })?;
+        Ok(swapped == 1)
+    }
+
+    fn get(&self, key: &str, _now: SystemTime) -> Result<Option<i64>, String> {
+        let key = self.key(key);
+        self.with_connection(|connection| redis::cmd("GET").arg(&key).query(connection))
+    }
+
+    fn set_if_not_exists_with_ttl(
+        &mut self,
+        key: &str,
+        value: i64,
+        ttl: Duration,
+        _now: SystemTime,
+    ) -> Result<bool, String> {
+        let key = self.key(key);
+        let reply: Option<String> = self.with_connection(|connection| {
+            redis::cmd("SET")
+                .arg(&key)
+                .arg(value)
+                .arg("NX")
+                .arg("PX")
+                .arg(ttl_millis(ttl))
+                .query(connection)
+        })?;
+        Ok(reply.is_some())
+    }
+}
+
+/// Store keeping rate limit state in Redis, with asynchronous operations
+///
+/// Entries are compatible with [`RedisStore`]'s, so both can share a Redis.
+/// Clones share one multiplexed connection, which reconnects by itself
+/// after a failure; operations fail with the Redis error in the meantime.
+/// Requires a Tokio runtime.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::SystemTime;
+/// use throttlecrab::{AsyncRateLimiter, AsyncRedisStore};
+///
+/// # async fn check() -> Result<(), String> {
+/// let store = AsyncRedisStore::open("redis://127.0.0.1:6379").await?;
+/// let mut limiter = AsyncRateLimiter::new(store);
+///
+/// let (allowed, _) = limiter
+///     .rate_limit("user:123", 10, 100, 60, 1, SystemTime::now())
+///     .await
+///     .map_err(|e| e.to_string())?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct AsyncRedisStore {
+    connection: ConnectionManager,
+    compare_and_swap: Script,
+    prefix: String,
+}
+
+impl AsyncRedisStore {
+    /// Connect to the Redis at `url`, e.g. `redis://127.0.0.1:6379/0`
+    ///
+    /// Connects right away, so an unreachable server is reported here.
+    /// Connection attempts and commands give up after one second.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URL is invalid or the server cannot be
+    /// reached.
+    pub async fn open(url: &str) -> Result<Self, String> {
+        let client = Client::open(url).map_err(|e| format!("Invalid Redis URL {url}: {e}"))?;
+        let timeout = Duration::from_secs(1);
+        let config = ConnectionManagerConfig::new()
+            .set_connection_timeout(timeout)
+            .set_response_timeout(timeout);
+        let connection = ConnectionManager::new_with_config(client, config)
+            .await
+            .map_err(|e| format!("Failed to connect to Redis: {e}"))?;
+        Ok(AsyncRedisStore {
+            connection,
+            compare_and_swap: Script::new(COMPARE_AND_SWAP),
+            prefix: "throttlecrab:".to_string(),
+        })
+    }
+
+    /// Write keys under `prefix` instead of `throttlecrab:`
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Delete the entry for `key`, returning whether it existed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if Redis cannot be reached.
+    pub async fn remove(&mut self, key: &str) -> Result<bool, String> {
+        let deleted: i64 = redis::cmd("DEL")
+            .arg(self.key(key))
+            .query_async(&mut self.connection)
+            .await
+            .map_err(|e| format!("Redis error: {e}"))?;
+        Ok(deleted > 0)
+    }
+
+    /// Delete the entry for `key` and those of its windows, returning
+    /// whether any existed
+    ///
+    /// The window entries (see
+    /// [`window_key_prefix`](crate::core::sliding_window::window_key_prefix))
+    /// are found with `SCAN`, so this walks the whole keyspace.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if Redis cannot be reached.
+    pub async fn remove_with_windows(&mut self, key: &str) -> Result<bool, String> {
+        let pattern = format!(
+            "{}*",
+            glob_escape(&self.key(&crate::core::sliding_window::window_key_prefix(key)))
+        );
+        let mut keys = vec![self.key(key)];
+        let mut cursor = 0u64;
+        loop {
+            let (next, page): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(1000)
+                .query_async(&mut self.connection)
+                .await
+                .map_err(|e| format!("Redis error: {e}"))?;
+            keys.extend(page);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        let deleted: i64 = redis::cmd("DEL")
+            .arg(&keys)
+            .query_async(&mut self.connection)
+            .await
+            .map_err(|e| format!("Redis error: {e}"))?;
+        Ok(deleted > 0)
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+/// `key` with the characters special to `SCAN MATCH` patterns escaped
+fn glob_escape(key: &str) -> String {
+    let mut escaped = String::with_capacity(key.len());
+    for c in key.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl AsyncStore for AsyncRedisStore {
+    async fn compare_and_swap_with_ttl(
+        &mut self,
+        key: &str,
+        old: i64,
+        new: i64,
+        ttl: Duration,
+        _now: SystemTime,
+    ) -> Result<bool, String> {
+        let swapped: i64 = self
+            .compare_and_swap
+            .key(self.key(key))
+            .arg(old)
+            .arg(new)
+            .arg(ttl_millis(ttl))
+            .invoke_async(&mut self.connection)
+            .await
+            .map_err(|e| format!("Redis error: {e}"))?;
+        Ok(swapped == 1)
+    }
+
+    async fn get(&self, key: &str, _now: SystemTime) -> Result<Option<i64>, String> {
+        // Commands need the connection mutably; clones share it
+        let mut connection = self.connection.clone();
+        redis::cmd("GET")
+            .arg(self.key(key))
+            .query_async(&mut connection)
+            .await
+            .map_err(|e| format!("Redis error: {e}"))
+    }
+
+    async fn set_if_not_exists_with_ttl(
+        &mut self,
+        key: &str,
+        value: i64,
+        ttl: Duration,
+        _now: SystemTime,
+    ) -> Result<bool, String> {
+        let reply: Option<String> = redis::cmd("SET")
+            .arg(self.key(key))
+            .arg(value)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl_millis(ttl))
+            .query_async(&mut self.connection)
+            .await
+            .map_err(|e| format!("Redis error: {e}"))?;
+        Ok(reply.is_some())
+    }
+}
+
+impl std::fmt::Debug for AsyncRedisStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncRedisStore")
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+impl std::fmt::Debug for RedisStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisStore")
+            .field("client", &self.client)
+            .field("prefix", &self.prefix)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RateLimiter;
+
+    #[test]
+    fn test_open_errors() {
+        assert!(RedisStore::open("not a url").is_err());
+        // Nothing listens on port 1
+        let error = RedisStore::open("redis://127.0.0.1:1").unwrap_err();
+        assert!(error.contains("Failed to connect"), "{error}");
+    }
+
+    #[test]
+    fn test_ttl_millis() {
+        assert_eq!(ttl_millis(Duration::ZERO), 1);
+        assert_eq!(ttl_millis(Duration::from_micros(1500)), 1);
+        assert_eq!(ttl_millis(Duration::from_secs(60)), 60_000);
+    }
+
+    #[test]
+    fn test_glob_escape() {
+        assert_eq!(glob_escape("user:1\0"), "user:1\0");
+        assert_eq!(glob_escape("a*b?[c]\\"), "a\\*b\\?\\[c\\]\\\\");
+    }
+
+    #[test]
+    #[ignore = "needs a Redis server at THROTTLECRAB_TEST_REDIS_URL"]
+    fn test_redis_store() {
+        let url = std::env::var("THROTTLECRAB_TEST_REDIS_URL")
+            .unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+        let prefix = format!("throttlecrab-test-{}:", std::process::id());
+        let mut store = RedisStore::open(&url).unwrap().with_prefix(prefix);
+        let now = SystemTime::now();
+        let ttl = Duration::from_secs(60);
+
+        assert_eq!(store.get("a", now).unwrap(), None);
+        assert!(store.set_if_not_exists_with_ttl("a", 1, ttl, now).unwrap());
+        assert!(!store.set_if_not_exists_with_ttl("a", 2, ttl, now).unwrap());
+        assert!(
+            !store
+                .compare_and_swap_with_ttl("a", 2, 3, ttl, now)
+                .unwrap()
+        );
+        assert!(
+            store
+                .compare_and_swap_with_ttl("a", 1, 3, ttl, now)
+                .unwrap()
+        );
+        assert_eq!(store.get("a", now).unwrap(), Some(3));
+        assert!(store.remove("a").unwrap());
+        assert!(!store.remove("a").unwrap());
+
+        // Two limiters on the same Redis share their state
+        let other = RedisStore::open(&url)
+            .unwrap()
+            .with_prefix(store.prefix.clone());
+        let mut first = RateLimiter::new(store);
+        let mut second = RateLimiter::new(other);
+        assert!(first.rate_limit("b", 1, 1, 60, 1, now).unwrap().0);
+        assert!(!second.rate_limit("b", 1, 1, 60, 1, now).unwrap().0);
+        first.store_mut().remove("b").unwrap();
+    }
+}
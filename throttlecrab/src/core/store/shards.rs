@@ -0,0 +1,128 @@
+use super::{KeyIndex, RecencyIndex};
+use std::hash::BuildHasher;
+use std::time::SystemTime;
+
+#[cfg(feature = "ahash")]
+use ahash::{AHashMap as HashMap, RandomState};
+#[cfg(not(feature = "ahash"))]
+use std::collections::{HashMap, hash_map::RandomState};
+
+/// A stored value and its expiry
+pub(crate) type Entry = (i64, Option<SystemTime>);
+
+/// A store's table, split into independently sized hash maps
+///
+/// Configured with the store builders' `shards`. Each key lives in the
+/// shard its hash picks, so a growing table rehashes one shard at a time
+/// and cleanup can sweep the shards in turn instead of the whole table.
+/// With a single shard, the default, keys are not hashed an extra time.
+pub(crate) struct Shards {
+    maps: Vec<HashMap<String, Entry>>,
+    // Picks a key's shard; independent of the maps' own hashers so keys
+    // within a shard still spread over all of its buckets
+    hasher: RandomState,
+    // Shard swept by the next incremental cleanup pass
+    next_sweep: usize,
+}
+
+impl Shards {
+    /// Room for about `capacity` entries, split evenly over `shards` maps
+    pub(crate) fn with_capacity(capacity: usize, shards: usize) -> Self {
+        let shards = shards.max(1);
+        Shards {
+            maps: (0..shards)
+                .map(|_| HashMap::with_capacity(capacity.div_ceil(shards)))
+                .collect(),
+            hasher: RandomState::new(),
+            next_sweep: 0,
+        }
+    }
+
+    /// Number of shards
+    pub(crate) fn count(&self) -> usize {
+        self.maps.len()
+    }
+
+    fn shard(&self, key: &str) -> usize {
+        if self.maps.len() == 1 {
+            0
+        } else {
+            (BuildHasher::hash_one(&self.hasher, key) % self.maps.len() as u64) as usize
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.maps.iter().map(|map| map.len()).sum()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.maps.iter().all(|map| map.is_empty())
+    }
+
+    /// Entries the shards hold without growing, summed
+    pub(crate) fn capacity(&self) -> usize {
+        self.maps.iter().map(|map| map.capacity()).sum()
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&Entry> {
+        self.maps[self.shard(key)].get(key)
+    }
+
+    pub(crate) fn contains_key(&self, key: &str) -> bool {
+        self.maps[self.shard(key)].contains_key(key)
+    }
+
+    pub(crate) fn insert(&mut self, key: String, entry: Entry) -> Option<Entry> {
+        let shard = self.shard(&key);
+        self.maps[shard].insert(key, entry)
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) -> Option<Entry> {
+        let shard = self.shard(key);
+        self.maps[shard].remove(key)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&String, &Entry)> {
+        self.maps.iter().flat_map(|map| map.iter())
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &String> {
+        self.maps.iter().flat_map(|map| map.keys())
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &Entry> {
+        self.maps.iter().flat_map(|map| map.values())
+    }
+
+    /// Keep only the entries `f` returns `true` for, across every shard
+    pub(crate) fn retain(&mut self, mut f: impl FnMut(&String, &mut Entry) -> bool) {
+        for map in &mut self.maps {
+            map.retain(&mut f);
+        }
+    }
+
+    /// Remove expired entries from the next shard in turn
+    ///
+    /// Removed keys are dropped from `keys` and `recency` one by one, so a
+    /// pass only visits its shard rather than syncing whole indexes.
+    /// Returns the number of entries the shard held and the number removed.
+    pub(crate) fn sweep_expired(
+        &mut self,
+        now: SystemTime,
+        keys: &mut KeyIndex,
+        recency: &mut RecencyIndex,
+    ) -> (usize, usize) {
+        let shard = self.next_sweep;
+        self.next_sweep = (shard + 1) % self.maps.len();
+        let map = &mut self.maps[shard];
+
+        let swept = map.len();
+        let mut removed = 0;
+        for (key, _) in map.extract_if(|_, (_, expiry)| expiry.is_some_and(|exp| exp <= now)) {
+            keys.remove(&key);
+            recency.remove(&key);
+            removed += 1;
+        }
+        (swept, removed)
+    }
+}
@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// The live entries of a store, for persisting them across restarts
+///
+/// Returned by the in-memory stores' `export_state` and restored with
+/// their `import_state`. Any serde format works; expiries are absolute
+/// times, so entries that expire while the process is down are skipped on
+/// import.
+///
+/// # Example
+///
+/// ```
+/// use std::time::SystemTime;
+/// use throttlecrab::{PeriodicStore, RateLimiter};
+///
+/// let mut limiter = RateLimiter::new(PeriodicStore::new());
+/// let now = SystemTime::now();
+/// limiter.rate_limit("user:123", 10, 100, 60, 1, now)?;
+///
+/// let state = limiter.store().export_state(now);
+///
+/// // ...serialize `state`, restart, deserialize it...
+///
+/// let mut restored = PeriodicStore::new();
+/// assert_eq!(restored.import_state(state, now), 1);
+/// # Ok::<(), throttlecrab::CellError>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoreState {
+    /// Entries in no particular order
+    pub entries: Vec<StateEntry>,
+}
+
+/// One entry of a [`StoreState`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateEntry {
+    /// The store key, as the algorithm wrote it
+    pub key: String,
+    /// The stored value, e.g. a GCRA theoretical arrival time
+    pub value: i64,
+    /// When the entry expires, None if it never does
+    pub expires_at: Option<SystemTime>,
+}
+
+impl StoreState {
+    /// Collect the entries of a store's `iter` that are live at `now`
+    pub(crate) fn from_entries<'a>(
+        entries: impl Iterator<Item = (&'a str, i64, Option<SystemTime>)>,
+        now: SystemTime,
+    ) -> Self {
+        StoreState {
+            entries: entries
+                .filter(|(_, _, expiry)| is_live(*expiry, now))
+                .map(|(key, value, expires_at)| StateEntry {
+                    key: key.to_string(),
+                    value,
+                    expires_at,
+                })
+                .collect(),
+        }
+    }
+
+    /// The entries still live at `now`
+    pub(crate) fn into_live(self, now: SystemTime) -> impl Iterator<Item = StateEntry> {
+        self.entries
+            .into_iter()
+            .filter(move |entry| is_live(entry.expires_at, now))
+    }
+}
+
+fn is_live(expiry: Option<SystemTime>, now: SystemTime) -> bool {
+    expiry.is_none_or(|expiry| expiry > now)
+}
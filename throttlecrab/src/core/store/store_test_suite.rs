@@ -4,16 +4,17 @@
 mod tests {
     use crate::RateLimiter;
     use crate::core::store::*;
-    use crate::core::store::{AdaptiveStore, PeriodicStore, ProbabilisticStore};
+    use crate::core::store::{AdaptiveStore, PeriodicStore, ProbabilisticStore, TimingWheelStore};
     use std::time::{Duration, SystemTime};
 
     /// Macro to test all stores with a given test function
     macro_rules! test_all_stores {
         ($test_fn:expr) => {
-            // Removed: Standard, Arena, TimingWheel, BloomFilter, BTree, Heap, RawApi stores
+            // Removed: Standard, Arena, BloomFilter, BTree, Heap, RawApi stores
             $test_fn("Periodic", &mut PeriodicStore::with_capacity(100));
             $test_fn("Probabilistic", &mut ProbabilisticStore::with_capacity(100));
             $test_fn("Adaptive", &mut AdaptiveStore::with_capacity(100));
+            $test_fn("TimingWheel", &mut TimingWheelStore::with_capacity(100));
         };
     }
 
@@ -582,7 +583,7 @@ mod tests {
             assert_eq!(result.remaining, 0, "{name}: Should have exactly 1 token");
         }
 
-        // Test each store type (removed: Standard, Arena, TimingWheel, BloomFilter, BTree, Heap, RawApi)
+        // Test each store type (removed: Standard, Arena, BloomFilter, BTree, Heap, RawApi)
         test_rate_limiter(
             "Periodic",
             RateLimiter::new(PeriodicStore::with_capacity(100)),
@@ -595,5 +596,9 @@ mod tests {
             "Adaptive",
             RateLimiter::new(AdaptiveStore::with_capacity(100)),
         );
+        test_rate_limiter(
+            "TimingWheel",
+            RateLimiter::new(TimingWheelStore::with_capacity(100)),
+        );
     }
 }
@@ -1,4 +1,4 @@
-use super::{PeriodicStore, Store};
+use super::{AdaptiveStore, PeriodicStore, ProbabilisticStore, Store, TimingWheelStore};
 use std::time::{Duration, SystemTime};
 
 #[test]
@@ -112,3 +112,351 @@ fn test_memory_store_multiple_keys() {
         assert_eq!(value, Some(i * 10));
     }
 }
+
+#[test]
+fn test_store_remove() {
+    let mut store = ProbabilisticStore::new();
+    let now = SystemTime::now();
+
+    store
+        .set_if_not_exists_with_ttl("key1", 42, Duration::from_secs(60), now)
+        .unwrap();
+    assert_eq!(store.len(), 1);
+
+    assert!(store.remove("key1"));
+    assert!(!store.remove("key1"));
+    assert!(store.is_empty());
+    assert_eq!(store.get("key1", now).unwrap(), None);
+}
+
+#[test]
+fn test_store_entries_roundtrip() {
+    let mut source = AdaptiveStore::new();
+    let now = SystemTime::now();
+
+    source
+        .set_if_not_exists_with_ttl("key1", 1, Duration::from_secs(60), now)
+        .unwrap();
+    source
+        .set_if_not_exists_with_ttl("key2", 2, Duration::from_secs(120), now)
+        .unwrap();
+    assert_eq!(
+        source.entry("key1"),
+        Some((1, Some(now + Duration::from_secs(60))))
+    );
+    assert_eq!(source.entry("missing"), None);
+
+    // Copy the state into a different store type
+    let mut restored = PeriodicStore::new();
+    for (key, value, expiry) in source.iter() {
+        restored.insert(key, value, expiry);
+    }
+
+    assert_eq!(restored.len(), 2);
+    assert_eq!(restored.get("key2", now).unwrap(), Some(2));
+    assert_eq!(restored.entry("key2"), source.entry("key2"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_store_export_import_state() {
+    let now = SystemTime::now();
+    let mut source = PeriodicStore::new();
+    source.insert("live", 1, Some(now + Duration::from_secs(60)));
+    source.insert("forever", 2, None);
+    source.insert("expired", 3, Some(now - Duration::from_secs(1)));
+
+    let state = source.export_state(now);
+    assert_eq!(state.entries.len(), 2);
+    let json = serde_json::to_string(&state).unwrap();
+    let state: super::StoreState = serde_json::from_str(&json).unwrap();
+
+    let mut adaptive = AdaptiveStore::new();
+    assert_eq!(adaptive.import_state(state.clone(), now), 2);
+    assert_eq!(adaptive.entry("live"), source.entry("live"));
+    assert_eq!(adaptive.entry("forever"), Some((2, None)));
+    assert_eq!(adaptive.entry("expired"), None);
+
+    // Entries that expired while the state was stored are skipped
+    let mut probabilistic = ProbabilisticStore::new();
+    let later = now + Duration::from_secs(120);
+    assert_eq!(probabilistic.import_state(state, later), 1);
+    assert_eq!(probabilistic.entry("live"), None);
+    assert_eq!(probabilistic.entry("forever"), Some((2, None)));
+}
+
+#[test]
+fn test_store_remove_expired() {
+    let mut store = PeriodicStore::new();
+    let now = SystemTime::now();
+
+    store
+        .set_if_not_exists_with_ttl("short", 1, Duration::from_secs(1), now)
+        .unwrap();
+    store
+        .set_if_not_exists_with_ttl("long", 2, Duration::from_secs(3600), now)
+        .unwrap();
+
+    // Runs immediately, without waiting for the cleanup interval
+    let removed = store.remove_expired(now + Duration::from_secs(2));
+    assert_eq!(removed, 1);
+    assert_eq!(store.len(), 1);
+    assert_eq!(store.get("long", now).unwrap(), Some(2));
+}
+
+#[test]
+fn test_store_evict_prefers_expired_entries() {
+    let mut store = AdaptiveStore::new();
+    let now = SystemTime::now();
+
+    store
+        .set_if_not_exists_with_ttl("expired", 1, Duration::from_secs(1), now)
+        .unwrap();
+    for i in 0..5 {
+        store
+            .set_if_not_exists_with_ttl(&format!("live{i}"), i, Duration::from_secs(60), now)
+            .unwrap();
+    }
+
+    let later = now + Duration::from_secs(2);
+    assert_eq!(store.evict(1, later), 1);
+    assert_eq!(store.len(), 5);
+    assert_eq!(store.get("live0", later).unwrap(), Some(0));
+}
+
+#[test]
+fn test_store_evict_soonest_expiring() {
+    let mut store = PeriodicStore::new();
+    let now = SystemTime::now();
+
+    for i in 0..10u64 {
+        store
+            .set_if_not_exists_with_ttl(
+                &format!("key{i}"),
+                i as i64,
+                Duration::from_secs(10 + i),
+                now,
+            )
+            .unwrap();
+    }
+
+    assert_eq!(store.evict(3, now), 3);
+    assert_eq!(store.len(), 7);
+
+    // The three entries closest to expiry are gone
+    for i in 0..3 {
+        assert_eq!(store.get(&format!("key{i}"), now).unwrap(), None);
+    }
+    for i in 3..10 {
+        assert_eq!(store.get(&format!("key{i}"), now).unwrap(), Some(i));
+    }
+}
+
+#[test]
+fn test_store_evict_least_recently_used() {
+    let now = SystemTime::now();
+    let mut store = ProbabilisticStore::builder().track_recency(true).build();
+    for i in 0..5u64 {
+        // Later keys expire sooner, so expiry order would evict them first
+        store
+            .set_if_not_exists_with_ttl(&format!("key{i}"), 0, Duration::from_secs(60 - i), now)
+            .unwrap();
+    }
+    assert!(
+        store
+            .compare_and_swap_with_ttl("key0", 0, 1, Duration::from_secs(60), now)
+            .unwrap()
+    );
+    store.touch("key1");
+    assert!(store.remove("key2"));
+
+    assert_eq!(store.evict(2, now), 2);
+    assert_eq!(store.get("key3", now).unwrap(), None);
+    assert_eq!(store.get("key4", now).unwrap(), None);
+    assert_eq!(store.get("key0", now).unwrap(), Some(1));
+    assert_eq!(store.get("key1", now).unwrap(), Some(0));
+
+    // Expired entries still go first
+    let mut store = AdaptiveStore::builder().track_recency(true).build();
+    store
+        .set_if_not_exists_with_ttl("old", 0, Duration::from_secs(60), now)
+        .unwrap();
+    store
+        .set_if_not_exists_with_ttl("expired", 0, Duration::from_secs(1), now)
+        .unwrap();
+    let later = now + Duration::from_secs(2);
+    assert_eq!(store.evict(1, later), 1);
+    assert_eq!(store.get("old", later).unwrap(), Some(0));
+    assert_eq!(store.evict(1, later), 1);
+    assert!(store.is_empty());
+}
+
+#[test]
+fn test_store_keys_with_prefix_pagination() {
+    let now = SystemTime::now();
+    let mut scanned = PeriodicStore::new();
+    let mut indexed = PeriodicStore::builder().key_index(true).build();
+    for store in [&mut scanned, &mut indexed] {
+        for i in 0..5 {
+            store
+                .set_if_not_exists_with_ttl(
+                    &format!("tenant:acme:{i}"),
+                    i,
+                    Duration::from_secs(60),
+                    now,
+                )
+                .unwrap();
+        }
+        store
+            .set_if_not_exists_with_ttl("tenant:other:0", 0, Duration::from_secs(60), now)
+            .unwrap();
+    }
+
+    for store in [&scanned, &indexed] {
+        let page = store.keys_with_prefix("tenant:acme:", None, 2);
+        assert_eq!(page.keys, ["tenant:acme:0", "tenant:acme:1"]);
+        assert_eq!(page.next_cursor.as_deref(), Some("tenant:acme:1"));
+
+        let page = store.keys_with_prefix("tenant:acme:", page.next_cursor.as_deref(), 2);
+        assert_eq!(page.keys, ["tenant:acme:2", "tenant:acme:3"]);
+
+        let page = store.keys_with_prefix("tenant:acme:", page.next_cursor.as_deref(), 2);
+        assert_eq!(page.keys, ["tenant:acme:4"]);
+        assert_eq!(page.next_cursor, None);
+
+        assert!(store.keys_with_prefix("missing:", None, 10).keys.is_empty());
+    }
+}
+
+#[test]
+fn test_store_key_index_tracks_removals() {
+    let mut store = ProbabilisticStore::builder().key_index(true).build();
+    let now = SystemTime::now();
+
+    store
+        .set_if_not_exists_with_ttl("a:short", 1, Duration::from_secs(1), now)
+        .unwrap();
+    store
+        .set_if_not_exists_with_ttl("a:long", 2, Duration::from_secs(3600), now)
+        .unwrap();
+    store.insert("a:removed", 3, None);
+    assert_eq!(store.keys_with_prefix("a:", None, 10).keys.len(), 3);
+
+    assert!(store.remove("a:removed"));
+    store.remove_expired(now + Duration::from_secs(2));
+    assert_eq!(store.keys_with_prefix("a:", None, 10).keys, ["a:long"]);
+
+    let mut store = AdaptiveStore::builder().key_index(true).build();
+    for i in 0..4u64 {
+        store
+            .set_if_not_exists_with_ttl(&format!("b:{i}"), 0, Duration::from_secs(10 + i), now)
+            .unwrap();
+    }
+    assert_eq!(store.evict(2, now), 2);
+    assert_eq!(store.keys_with_prefix("b:", None, 10).keys, ["b:2", "b:3"]);
+}
+
+#[test]
+fn test_store_memory_usage() {
+    let mut store = PeriodicStore::builder().capacity(100).build();
+    let empty = store.memory_usage();
+    // The table is allocated up front for the configured capacity
+    assert!(empty > 0);
+
+    store.insert(&"k".repeat(1000), 1, None);
+    let with_key = store.memory_usage();
+    assert!(with_key >= empty + 1000);
+
+    // The key index holds a second copy of every key
+    let mut indexed = PeriodicStore::builder()
+        .capacity(100)
+        .key_index(true)
+        .build();
+    indexed.insert(&"k".repeat(1000), 1, None);
+    assert!(indexed.memory_usage() >= with_key + 1000);
+
+    // So does the recency index, plus its ordering
+    let mut tracked = PeriodicStore::builder()
+        .capacity(100)
+        .track_recency(true)
+        .build();
+    tracked.insert(&"k".repeat(1000), 1, None);
+    assert!(tracked.memory_usage() >= with_key + 1000);
+    assert!(super::estimated_entry_memory(1000, true) > super::estimated_entry_memory(1000, false));
+}
+
+#[test]
+fn test_timing_wheel_store_removes_expired_keys() {
+    let mut store = TimingWheelStore::new();
+    // Late in a one second tick, so each key's tick ends before the next expires
+    let now = SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_750);
+
+    for i in 0..100u64 {
+        store
+            .set_if_not_exists_with_ttl(&format!("key{i}"), 0, Duration::from_secs(1 + i), now)
+            .unwrap();
+    }
+    // Extended keys are filed again rather than removed
+    store
+        .compare_and_swap_with_ttl("key0", 0, 1, Duration::from_secs(3600), now)
+        .unwrap();
+
+    // Each write removes the keys that expired since the last one
+    for secs in 1..100 {
+        let later = now + Duration::from_secs(secs) + Duration::from_millis(1500);
+        store
+            .set_if_not_exists_with_ttl("trigger", 0, Duration::from_secs(3600), later)
+            .unwrap();
+        assert_eq!(store.len(), 101 - secs as usize, "after {secs}s");
+    }
+    assert_eq!(store.expired_count(), 99);
+    assert_eq!(store.entry("key0").map(|(value, _)| value), Some(1));
+}
+
+#[test]
+fn test_timing_wheel_store_cleanup_batch() {
+    let mut store = TimingWheelStore::builder().cleanup_batch(10).build();
+    let now = SystemTime::now();
+
+    for i in 0..100 {
+        store
+            .set_if_not_exists_with_ttl(&format!("key{i}"), i, Duration::from_secs(1), now)
+            .unwrap();
+    }
+
+    // A burst of expirations is worked off a batch per write
+    let later = now + Duration::from_secs(5);
+    for write in 1..=10 {
+        store
+            .set_if_not_exists_with_ttl(&format!("new{write}"), 0, Duration::from_secs(60), later)
+            .unwrap();
+        assert_eq!(store.len(), 100 - 10 * write + write);
+    }
+    assert_eq!(store.expired_count(), 100);
+
+    // Far beyond the wheel's span, the remaining keys are still removed
+    let much_later = now + Duration::from_secs(10 * 365 * 24 * 60 * 60);
+    store
+        .set_if_not_exists_with_ttl("last", 0, Duration::from_secs(60), much_later)
+        .unwrap();
+    assert_eq!(store.len(), 1);
+}
+
+#[test]
+fn test_timing_wheel_store_restored_entries_expire() {
+    let now = SystemTime::now();
+    let mut store = TimingWheelStore::new();
+    store.insert("restored", 1, Some(now + Duration::from_secs(10)));
+    store.insert("forever", 2, None);
+    // Removed and recreated keys are filed twice without harm
+    store.remove("restored");
+    store.insert("restored", 1, Some(now + Duration::from_secs(10)));
+
+    let later = now + Duration::from_secs(11);
+    store
+        .set_if_not_exists_with_ttl("trigger", 0, Duration::from_secs(60), later)
+        .unwrap();
+    assert_eq!(store.entry("restored"), None);
+    assert_eq!(store.entry("forever"), Some((2, None)));
+    assert_eq!(store.expired_count(), 1);
+}
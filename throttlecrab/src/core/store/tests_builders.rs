@@ -1,6 +1,8 @@
 #[cfg(test)]
 mod tests {
-    use crate::core::store::{AdaptiveStore, PeriodicStore, ProbabilisticStore, Store};
+    use crate::core::store::{
+        AdaptiveStore, PeriodicStore, ProbabilisticStore, Store, TimingWheelStore,
+    };
     use std::time::{Duration, SystemTime};
 
     #[test]
@@ -132,6 +134,39 @@ mod tests {
         assert_eq!(store.get("key4", now).unwrap(), Some(168));
     }
 
+    #[test]
+    fn test_timing_wheel_store_builder() {
+        let mut store = TimingWheelStore::builder()
+            .capacity(100_000)
+            .resolution(Duration::from_millis(100))
+            .cleanup_batch(16)
+            .shards(4)
+            .build();
+
+        let now = SystemTime::now();
+        let ttl = Duration::from_millis(250);
+
+        assert!(
+            store
+                .set_if_not_exists_with_ttl("wheel_key", 7, ttl, now)
+                .unwrap()
+        );
+        assert_eq!(store.get("wheel_key", now).unwrap(), Some(7));
+
+        // Removed within a tick of expiring
+        let later = now + Duration::from_millis(400);
+        store
+            .set_if_not_exists_with_ttl("other", 8, ttl, later)
+            .unwrap();
+        assert_eq!(store.entry("wheel_key"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "cleanup batch must be at least 1")]
+    fn test_timing_wheel_store_builder_zero_batch() {
+        TimingWheelStore::builder().cleanup_batch(0);
+    }
+
     #[test]
     fn test_store_builder_large_capacity() {
         // Test that builders handle large capacities correctly
@@ -161,4 +196,137 @@ mod tests {
                 .unwrap()
         );
     }
+
+    #[test]
+    fn test_store_builder_max_ttl() {
+        let now = SystemTime::now();
+        let day = Duration::from_secs(24 * 60 * 60);
+        let decade = Duration::from_secs(10 * 365 * 24 * 60 * 60);
+
+        let mut periodic = PeriodicStore::builder().max_ttl(day).build();
+        let mut probabilistic = ProbabilisticStore::builder().max_ttl(day).build();
+        let mut adaptive = AdaptiveStore::builder().max_ttl(day).build();
+
+        // Short TTLs are untouched, long ones expire at the cap
+        periodic
+            .set_if_not_exists_with_ttl("short", 1, Duration::from_secs(60), now)
+            .unwrap();
+        periodic
+            .set_if_not_exists_with_ttl("long", 1, decade, now)
+            .unwrap();
+        assert_eq!(
+            periodic.entry("short").unwrap().1,
+            Some(now + Duration::from_secs(60))
+        );
+        assert_eq!(periodic.entry("long").unwrap().1, Some(now + day));
+        assert!(
+            periodic
+                .compare_and_swap_with_ttl("long", 1, 2, decade, now)
+                .unwrap()
+        );
+        assert_eq!(periodic.ttl_capped(), 2);
+
+        probabilistic
+            .set_if_not_exists_with_ttl("long", 1, decade, now)
+            .unwrap();
+        assert_eq!(probabilistic.entry("long").unwrap().1, Some(now + day));
+        assert_eq!(probabilistic.ttl_capped(), 1);
+
+        adaptive
+            .set_if_not_exists_with_ttl("long", 1, decade, now)
+            .unwrap();
+        assert_eq!(adaptive.entry("long").unwrap().1, Some(now + day));
+        assert_eq!(adaptive.ttl_capped(), 1);
+    }
+
+    #[test]
+    fn test_store_builder_ttl_multiplier() {
+        let now = SystemTime::now();
+        let mut store = PeriodicStore::builder()
+            .ttl_multiplier(2.0)
+            .max_ttl(Duration::from_secs(100))
+            .build();
+
+        // The multiplier applies before the cap
+        store
+            .set_if_not_exists_with_ttl("a", 1, Duration::from_secs(30), now)
+            .unwrap();
+        store
+            .set_if_not_exists_with_ttl("b", 1, Duration::from_secs(60), now)
+            .unwrap();
+        assert_eq!(
+            store.entry("a").unwrap().1,
+            Some(now + Duration::from_secs(60))
+        );
+        assert_eq!(
+            store.entry("b").unwrap().1,
+            Some(now + Duration::from_secs(100))
+        );
+        assert_eq!(store.ttl_capped(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "TTL multiplier must be positive")]
+    fn test_store_builder_rejects_zero_ttl_multiplier() {
+        AdaptiveStore::builder().ttl_multiplier(0.0);
+    }
+
+    #[test]
+    fn test_store_builder_shards() {
+        let now = SystemTime::now();
+        let mut store = PeriodicStore::builder()
+            .shards(4)
+            .cleanup_interval(Duration::from_secs(60))
+            .key_index(true)
+            .track_recency(true)
+            .build();
+        for i in 0..1000 {
+            store
+                .set_if_not_exists_with_ttl(&format!("key{i}"), i, Duration::from_secs(1), now)
+                .unwrap();
+        }
+        assert_eq!(store.len(), 1000);
+        assert_eq!(store.get("key500", now).unwrap(), Some(500));
+
+        // Each pass sweeps one shard, and the next is due a quarter
+        // interval later
+        let mut at = now + Duration::from_secs(61);
+        store
+            .set_if_not_exists_with_ttl("live0", 0, Duration::from_secs(3600), at)
+            .unwrap();
+        assert!(store.len() > 500 && store.len() < 1000);
+        for i in 1..4 {
+            at += Duration::from_secs(15);
+            store
+                .set_if_not_exists_with_ttl(&format!("live{i}"), 0, Duration::from_secs(3600), at)
+                .unwrap();
+        }
+        assert_eq!(store.len(), 4);
+        // The indexes were kept in step
+        assert_eq!(store.keys_with_prefix("key", None, 10).keys.len(), 0);
+        assert_eq!(store.evict(1, at), 1);
+
+        let mut adaptive = AdaptiveStore::builder().shards(8).build();
+        let mut probabilistic = ProbabilisticStore::builder().shards(8).build();
+        for i in 0..100 {
+            let key = format!("key{i}");
+            adaptive
+                .set_if_not_exists_with_ttl(&key, i, Duration::from_secs(1), now)
+                .unwrap();
+            probabilistic
+                .set_if_not_exists_with_ttl(&key, i, Duration::from_secs(1), now)
+                .unwrap();
+        }
+        assert_eq!(adaptive.get("key42", now).unwrap(), Some(42));
+        assert_eq!(probabilistic.get("key42", now).unwrap(), Some(42));
+        let later = now + Duration::from_secs(2);
+        assert_eq!(adaptive.remove_expired(later), 100);
+        assert_eq!(probabilistic.remove_expired(later), 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one shard")]
+    fn test_store_builder_rejects_zero_shards() {
+        ProbabilisticStore::builder().shards(0);
+    }
 }
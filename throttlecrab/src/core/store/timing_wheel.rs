@@ -0,0 +1,534 @@
+#[cfg(feature = "serde")]
+use super::StoreState;
+use super::wheel::TimingWheel;
+use super::{Entry, KeyIndex, KeyPage, RecencyIndex, Shards, Store, TtlPolicy, evict_entries};
+use std::time::{Duration, SystemTime};
+
+// Configuration constants
+const DEFAULT_CAPACITY: usize = 1000;
+const CAPACITY_OVERHEAD_FACTOR: f64 = 1.3;
+const DEFAULT_RESOLUTION: Duration = Duration::from_secs(1);
+const DEFAULT_CLEANUP_BATCH: usize = 128;
+
+/// Incremental cleanup store implementation
+///
+/// This store files every key in a hierarchical timing wheel by its expiry,
+/// and each write removes the keys that have expired since the last one.
+/// No operation scans the table, so cleanup costs are spread evenly over
+/// the writes instead of arriving as periodic multi-millisecond pauses at
+/// large capacities.
+///
+/// # Features
+///
+/// - Removes expired entries as they expire, within one wheel tick
+/// - Bounded cleanup work per operation
+/// - Memory follows the number of live keys closely
+/// - Costs a second copy of every key, held by the wheel
+///
+/// # Example
+///
+/// ```
+/// use throttlecrab::{RateLimiter, TimingWheelStore};
+///
+/// let store = TimingWheelStore::builder()
+///     .capacity(1_000_000)
+///     .build();
+/// let mut limiter = RateLimiter::new(store);
+/// ```
+///
+/// # Cleanup Strategy
+///
+/// A key is filed once, when it is created. Writes that extend its expiry
+/// don't touch the wheel; when the key's slot comes up, it is removed if it
+/// has expired and filed again at its current expiry otherwise. Each write
+/// processes at most [`cleanup_batch`](TimingWheelStoreBuilder::cleanup_batch)
+/// keys, so a burst of expirations is worked off over several writes.
+pub struct TimingWheelStore {
+    data: Shards,
+    wheel: TimingWheel,
+    cleanup_batch: usize,
+    // Track number of expired entries
+    expired_count: usize,
+    ttl: TtlPolicy,
+    keys: KeyIndex,
+    recency: RecencyIndex,
+}
+
+/// Builder for configuring a TimingWheelStore
+///
+/// Provides a fluent interface for customizing the timing wheel store's behavior.
+///
+/// # Example
+///
+/// ```
+/// use throttlecrab::TimingWheelStore;
+/// use std::time::Duration;
+///
+/// let store = TimingWheelStore::builder()
+///     .capacity(100_000)
+///     .resolution(Duration::from_millis(100))
+///     .cleanup_batch(256)
+///     .build();
+/// ```
+pub struct TimingWheelStoreBuilder {
+    capacity: usize,
+    resolution: Duration,
+    cleanup_batch: usize,
+    ttl: TtlPolicy,
+    keys: KeyIndex,
+    recency: RecencyIndex,
+    shards: usize,
+}
+
+impl TimingWheelStore {
+    /// Create a new TimingWheelStore with default configuration
+    ///
+    /// Uses a default capacity of 1000 entries, a wheel resolution of one
+    /// second and a cleanup batch of 128 keys per operation.
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Create a new TimingWheelStore with specified capacity
+    ///
+    /// The store will allocate 30% more space to reduce hash collisions.
+    ///
+    /// # Parameters
+    ///
+    /// - `capacity`: Expected number of unique keys to track
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::builder().capacity(capacity).build()
+    }
+
+    /// Create a new builder for configuring a TimingWheelStore
+    ///
+    /// Provides fine-grained control over store configuration.
+    pub fn builder() -> TimingWheelStoreBuilder {
+        TimingWheelStoreBuilder::default()
+    }
+
+    fn with_config(
+        capacity: usize,
+        resolution: Duration,
+        cleanup_batch: usize,
+        ttl: TtlPolicy,
+        keys: KeyIndex,
+        recency: RecencyIndex,
+        shards: usize,
+    ) -> Self {
+        TimingWheelStore {
+            data: Shards::with_capacity(
+                (capacity as f64 * CAPACITY_OVERHEAD_FACTOR) as usize,
+                shards,
+            ),
+            wheel: TimingWheel::new(resolution),
+            cleanup_batch,
+            expired_count: 0,
+            ttl,
+            keys,
+            recency,
+        }
+    }
+
+    /// Number of entries currently held, including expired entries that
+    /// have not been cleaned up yet
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns `true` if the store holds no entries
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Number of writes whose TTL was shortened by the configured
+    /// [`max_ttl`](TimingWheelStoreBuilder::max_ttl)
+    pub fn ttl_capped(&self) -> u64 {
+        self.ttl.capped()
+    }
+
+    /// Number of expired entries removed by the incremental cleanup so far
+    pub fn expired_count(&self) -> usize {
+        self.expired_count
+    }
+
+    /// Remove the entry for `key`
+    ///
+    /// Returns `true` if an entry was present. The key stays filed in the
+    /// wheel until its slot comes up.
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.keys.remove(key);
+        self.recency.remove(key);
+        self.data.remove(key).is_some()
+    }
+
+    /// Get the raw entry for `key`: its value and expiry
+    ///
+    /// Unlike [`Store::get`], expired entries that have not been cleaned up
+    /// yet are returned as well.
+    pub fn entry(&self, key: &str) -> Option<(i64, Option<SystemTime>)> {
+        self.data.get(key).copied()
+    }
+
+    /// Iterate over all entries as `(key, value, expiry)`
+    ///
+    /// Useful for persisting store state. Includes expired entries that have
+    /// not been cleaned up yet.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, i64, Option<SystemTime>)> {
+        self.data
+            .iter()
+            .map(|(key, (value, expiry))| (key.as_str(), *value, *expiry))
+    }
+
+    /// Insert an entry unconditionally, replacing any existing value
+    ///
+    /// Intended for restoring previously persisted state; rate limiting
+    /// itself goes through the [`Store`] trait methods.
+    pub fn insert(&mut self, key: &str, value: i64, expiry: Option<SystemTime>) {
+        self.keys.insert(key);
+        self.recency.touch(key);
+        let previous = self.data.insert(key.to_string(), (value, expiry));
+        self.file(key, previous, expiry);
+    }
+
+    /// Copy the entries live at `now`, for persisting with serde
+    ///
+    /// Restore them with [`import_state`](Self::import_state).
+    #[cfg(feature = "serde")]
+    pub fn export_state(&self, now: SystemTime) -> StoreState {
+        StoreState::from_entries(self.iter(), now)
+    }
+
+    /// Insert the entries of an exported `state` still live at `now`
+    ///
+    /// Existing entries for the same keys are replaced. Returns the number
+    /// of entries restored.
+    #[cfg(feature = "serde")]
+    pub fn import_state(&mut self, state: StoreState, now: SystemTime) -> usize {
+        state
+            .into_live(now)
+            .map(|entry| self.insert(&entry.key, entry.value, entry.expires_at))
+            .count()
+    }
+
+    /// List keys starting with `prefix`, one page at a time
+    ///
+    /// Returns up to `limit` keys in ascending order that sort after
+    /// `cursor`; pass the returned [`KeyPage::next_cursor`] to fetch the
+    /// next page. Includes expired entries that have not been cleaned up
+    /// yet. Only visits matching keys if the store was built with
+    /// [`key_index`](TimingWheelStoreBuilder::key_index); otherwise every page scans
+    /// the whole store.
+    pub fn keys_with_prefix(&self, prefix: &str, cursor: Option<&str>, limit: usize) -> KeyPage {
+        self.keys.page(&self.data, prefix, cursor, limit)
+    }
+
+    /// Mark `key` as recently used without changing its value
+    ///
+    /// Keeps a key that is read but not written, e.g. one whose requests
+    /// are all denied, from being evicted first under
+    /// [`track_recency`](TimingWheelStoreBuilder::track_recency). Does nothing for
+    /// missing keys or when recency is not tracked.
+    pub fn touch(&mut self, key: &str) {
+        if self.data.contains_key(key) {
+            self.recency.touch(key);
+        }
+    }
+
+    /// Estimated heap memory held by the store, in bytes
+    ///
+    /// Covers the hash table at its current capacity, the keys, the wheel's
+    /// copies of the keys, and the
+    /// [`key_index`](TimingWheelStoreBuilder::key_index) and
+    /// [`track_recency`](TimingWheelStoreBuilder::track_recency) indexes if enabled. Walks every
+    /// key, so call it for reporting rather than on the request path.
+    pub fn memory_usage(&self) -> usize {
+        super::table_memory(&self.data)
+            + self.wheel.memory_usage()
+            + self.keys.memory_usage()
+            + self.recency.memory_usage()
+    }
+
+    /// Remove all expired entries immediately
+    ///
+    /// Scans the whole table rather than waiting for the wheel. Returns the
+    /// number of entries removed.
+    pub fn remove_expired(&mut self, now: SystemTime) -> usize {
+        let before_count = self.data.len();
+        self.data.retain(|_, (_, expiry)| {
+            if let Some(exp) = expiry {
+                *exp > now
+            } else {
+                true
+            }
+        });
+        self.keys.sync(&self.data);
+        self.recency.sync(&self.data);
+        before_count - self.data.len()
+    }
+
+    /// Make room by removing up to `count` entries
+    ///
+    /// Expired entries are removed first. If that frees fewer than `count`
+    /// entries, the live entries closest to expiry are evicted next; for GCRA
+    /// state these are the keys that have been idle the longest relative to
+    /// their limits. With [`track_recency`](TimingWheelStoreBuilder::track_recency)
+    /// enabled, the least recently used entries are evicted instead. Returns
+    /// the number of entries removed.
+    pub fn evict(&mut self, count: usize, now: SystemTime) -> usize {
+        let removed = self.remove_expired(now);
+        let evicted = evict_entries(
+            &mut self.data,
+            &mut self.recency,
+            count.saturating_sub(removed),
+        );
+        self.keys.sync(&self.data);
+        self.recency.sync(&self.data);
+        removed + evicted
+    }
+
+    /// File `key` in the wheel unless its previous entry already is
+    fn file(&mut self, key: &str, previous: Option<Entry>, expiry: Option<SystemTime>) {
+        if let Some(expiry) = expiry
+            && !matches!(previous, Some((_, Some(_))))
+        {
+            self.wheel.schedule(key.to_string(), expiry);
+        }
+    }
+
+    /// Remove up to `cleanup_batch` keys whose wheel slot has come up
+    fn clean_due(&mut self, now: SystemTime) {
+        for _ in 0..self.cleanup_batch {
+            let Some(key) = self.wheel.pop_due(now) else {
+                break;
+            };
+            match self.data.get(&key) {
+                Some((_, Some(expiry))) if *expiry <= now => {
+                    self.data.remove(&key);
+                    self.keys.remove(&key);
+                    self.recency.remove(&key);
+                    self.expired_count += 1;
+                }
+                // Extended since it was filed
+                Some((_, Some(expiry))) => {
+                    let expiry = *expiry;
+                    self.wheel.schedule(key, expiry);
+                }
+                // Removed, or replaced by an entry without expiry
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Default for TimingWheelStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Store for TimingWheelStore {
+    fn compare_and_swap_with_ttl(
+        &mut self,
+        key: &str,
+        old: i64,
+        new: i64,
+        ttl: Duration,
+        now: SystemTime,
+    ) -> Result<bool, String> {
+        self.clean_due(now);
+
+        match self.data.get(key) {
+            Some((_current, Some(expiry))) if *expiry <= now => Ok(false),
+            Some((current, _)) if *current == old => {
+                let expiry = now + self.ttl.apply(ttl);
+                self.recency.touch(key);
+                let previous = self.data.insert(key.to_string(), (new, Some(expiry)));
+                self.file(key, previous, Some(expiry));
+                Ok(true)
+            }
+            Some(_) => Ok(false),
+            None => Ok(false),
+        }
+    }
+
+    fn get(&self, key: &str, now: SystemTime) -> Result<Option<i64>, String> {
+        match self.data.get(key) {
+            Some((value, Some(expiry))) if *expiry > now => Ok(Some(*value)),
+            Some((value, None)) => Ok(Some(*value)),
+            _ => Ok(None),
+        }
+    }
+
+    fn set_if_not_exists_with_ttl(
+        &mut self,
+        key: &str,
+        value: i64,
+        ttl: Duration,
+        now: SystemTime,
+    ) -> Result<bool, String> {
+        self.clean_due(now);
+
+        match self.data.get(key) {
+            Some((_, Some(expiry))) if *expiry > now => Ok(false),
+            Some((_, None)) => Ok(false),
+            _ => {
+                let expiry = now + self.ttl.apply(ttl);
+                self.keys.insert(key);
+                self.recency.touch(key);
+                let previous = self.data.insert(key.to_string(), (value, Some(expiry)));
+                self.file(key, previous, Some(expiry));
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl Default for TimingWheelStoreBuilder {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_CAPACITY,
+            resolution: DEFAULT_RESOLUTION,
+            cleanup_batch: DEFAULT_CLEANUP_BATCH,
+            ttl: TtlPolicy::new(),
+            keys: KeyIndex::new(),
+            recency: RecencyIndex::new(),
+            shards: 1,
+        }
+    }
+}
+
+impl TimingWheelStoreBuilder {
+    /// Create a new builder with default settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the expected capacity (number of unique keys)
+    ///
+    /// The store will allocate 30% more space to reduce hash collisions.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Set the length of a wheel tick
+    ///
+    /// Entries are removed within one tick of expiring. Shorter ticks free
+    /// memory sooner; the wheel holds keys up to about 16.7 million ticks
+    /// ahead (194 days at the default of one second) and files keys further
+    /// out again when their slot comes up.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `resolution` is zero.
+    pub fn resolution(mut self, resolution: Duration) -> Self {
+        assert!(!resolution.is_zero(), "wheel resolution must be positive");
+        self.resolution = resolution;
+        self
+    }
+
+    /// Set the most keys a single operation takes out of the wheel
+    ///
+    /// Bounds the cleanup work added to any one write. When more keys than
+    /// this expire at once, the rest are removed by the following writes.
+    /// Defaults to 128.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch` is 0.
+    pub fn cleanup_batch(mut self, batch: usize) -> Self {
+        assert!(batch > 0, "cleanup batch must be at least 1");
+        self.cleanup_batch = batch;
+        self
+    }
+
+    /// Multiply every entry's TTL by `multiplier`
+    ///
+    /// Values above 1.0 keep idle keys around longer, values below 1.0
+    /// reclaim memory sooner at the cost of forgetting state for keys that
+    /// are still within their period. Defaults to 1.0.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `multiplier` is not positive and finite.
+    pub fn ttl_multiplier(mut self, multiplier: f64) -> Self {
+        self.ttl.set_multiplier(multiplier);
+        self
+    }
+
+    /// Cap every entry's TTL at `max`
+    ///
+    /// Protects the store from entries that would otherwise live for years,
+    /// e.g. from a client requesting a ten year period. A capped key forgets
+    /// its state once the cap expires, so its limit is enforced less
+    /// strictly. Writes that hit the cap are counted by `ttl_capped`.
+    pub fn max_ttl(mut self, max: Duration) -> Self {
+        self.ttl.set_max(max);
+        self
+    }
+
+    /// Keep a sorted index of keys for [`keys_with_prefix`]
+    ///
+    /// Prefix scans then only visit matching keys instead of the whole
+    /// store, which matters for admin listings over millions of keys. The
+    /// index holds a second copy of every key and adds an O(log n) insert
+    /// for each new key. Disabled by default.
+    ///
+    /// [`keys_with_prefix`]: TimingWheelStore::keys_with_prefix
+    pub fn key_index(mut self, enabled: bool) -> Self {
+        if enabled {
+            self.keys.enable();
+        } else {
+            self.keys = KeyIndex::new();
+        }
+        self
+    }
+
+    /// Track how recently each key was used, so [`evict`] removes the least
+    /// recently used entries instead of those closest to expiry
+    ///
+    /// Writes and [`touch`] mark a key as used. Costs a shared copy of every
+    /// key, about 100 bytes per key for the ordering and an O(log n) update
+    /// on every write. Disabled by default.
+    ///
+    /// [`evict`]: TimingWheelStore::evict
+    /// [`touch`]: TimingWheelStore::touch
+    pub fn track_recency(mut self, enabled: bool) -> Self {
+        if enabled {
+            self.recency.enable();
+        } else {
+            self.recency = RecencyIndex::new();
+        }
+        self
+    }
+
+    /// Split the table into `shards` independently sized hash maps
+    ///
+    /// A hash map that outgrows its capacity rehashes every entry at once,
+    /// which with millions of keys stalls the operation that triggered it.
+    /// With `n` shards, growth rehashes one shard, about 1/n of the keys.
+    /// Every key is hashed once more to pick its shard. Defaults to 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is 0.
+    pub fn shards(mut self, shards: usize) -> Self {
+        assert!(shards > 0, "store must have at least one shard");
+        self.shards = shards;
+        self
+    }
+
+    /// Build the TimingWheelStore with the configured settings
+    pub fn build(self) -> TimingWheelStore {
+        TimingWheelStore::with_config(
+            self.capacity,
+            self.resolution,
+            self.cleanup_batch,
+            self.ttl,
+            self.keys,
+            self.recency,
+            self.shards,
+        )
+    }
+}
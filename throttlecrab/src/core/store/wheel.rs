@@ -0,0 +1,166 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Bits of a tick each level of the wheel indexes
+const LEVEL_BITS: u32 = 6;
+const SLOTS: usize = 1 << LEVEL_BITS;
+const LEVELS: usize = 4;
+/// Ticks the wheel can hold a key for before it has to be filed again
+const SPAN: u64 = 1 << (LEVEL_BITS as usize * LEVELS);
+/// Ticks advanced per call to `pop_due`, so a long idle gap is caught up
+/// over several operations
+const MAX_STEPS: usize = SLOTS;
+
+/// Hierarchical timing wheel of keys by expiry
+///
+/// Each of the 4 levels has 64 slots, and a slot of level `l` spans 64^l
+/// ticks. A key is filed at the level where its expiry tick first differs
+/// from the current tick and moves down a level each time its slot comes
+/// up, so finding the keys due at a tick never looks at the others.
+///
+/// The wheel does not check for duplicates or track updates: callers look
+/// a due key up in their table, drop it if it has expired and file it
+/// again at its current expiry otherwise.
+pub(crate) struct TimingWheel {
+    resolution_nanos: u128,
+    /// Last tick processed; set by the first call to `pop_due`
+    current: Option<u64>,
+    levels: Vec<Vec<Vec<String>>>,
+    /// Keys filed at each level
+    level_len: [usize; LEVELS],
+    /// Keys whose slot came up, waiting for `pop_due`
+    due: Vec<String>,
+}
+
+impl TimingWheel {
+    /// An empty wheel whose ticks are `resolution` long
+    ///
+    /// # Panics
+    ///
+    /// Panics if `resolution` is zero.
+    pub(crate) fn new(resolution: Duration) -> Self {
+        assert!(!resolution.is_zero(), "wheel resolution must be positive");
+        TimingWheel {
+            resolution_nanos: resolution.as_nanos(),
+            current: None,
+            levels: (0..LEVELS)
+                .map(|_| (0..SLOTS).map(|_| Vec::new()).collect())
+                .collect(),
+            level_len: [0; LEVELS],
+            due: Vec::new(),
+        }
+    }
+
+    fn tick(&self, time: SystemTime, round_up: bool) -> u64 {
+        let nanos = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos());
+        let tick = if round_up {
+            nanos.div_ceil(self.resolution_nanos)
+        } else {
+            nanos / self.resolution_nanos
+        };
+        u64::try_from(tick).unwrap_or(u64::MAX)
+    }
+
+    /// File `key` to come up once `expiry` has passed
+    pub(crate) fn schedule(&mut self, key: String, expiry: SystemTime) {
+        // Round up, so the key is due no earlier than its expiry
+        let tick = self.tick(expiry, true);
+        match self.current {
+            Some(current) => self.file(key, tick.max(current + 1), current),
+            // No time to file against yet; the first `pop_due` returns it
+            None => self.due.push(key),
+        }
+    }
+
+    fn file(&mut self, key: String, tick: u64, current: u64) {
+        let level = ((63 - (tick ^ current).leading_zeros()) / LEVEL_BITS) as usize;
+        // The top level wraps around; a key further out than the wheel's
+        // span comes up early and is filed again from there
+        let level = level.min(LEVELS - 1);
+        self.levels[level][slot_of(tick, level)].push(key);
+        self.level_len[level] += 1;
+    }
+
+    /// Take the next key whose slot has come up by `now`
+    ///
+    /// Returns `None` once no key is due, or after advancing a bounded
+    /// number of ticks without finding one; later calls carry on from there.
+    pub(crate) fn pop_due(&mut self, now: SystemTime) -> Option<String> {
+        let now_tick = self.tick(now, false);
+        let mut current = *self.current.get_or_insert(now_tick);
+        if let Some(key) = self.due.pop() {
+            return Some(key);
+        }
+        if now_tick.saturating_sub(current) >= SPAN {
+            // Every slot has come up at least once
+            for level in 0..LEVELS {
+                self.take_level(level);
+            }
+            self.current = Some(now_tick);
+            return self.due.pop();
+        }
+
+        for _ in 0..MAX_STEPS {
+            if current >= now_tick {
+                break;
+            }
+            match self.level_len.iter().position(|&len| len > 0) {
+                None => current = now_tick,
+                Some(level) => {
+                    // Levels below `level` are empty, so skip straight to
+                    // the tick before its next slot comes up
+                    let skip_to = current | ((1 << (LEVEL_BITS as usize * level)) - 1);
+                    if skip_to > current {
+                        current = skip_to.min(now_tick);
+                        continue;
+                    }
+                    current += 1;
+                    self.take_tick(current);
+                    if !self.due.is_empty() {
+                        break;
+                    }
+                }
+            }
+        }
+        self.current = Some(current);
+        self.due.pop()
+    }
+
+    /// Move the keys whose slots come up at `tick` to `due`
+    fn take_tick(&mut self, tick: u64) {
+        for level in (1..LEVELS).rev() {
+            if tick.trailing_zeros() >= LEVEL_BITS * level as u32 {
+                self.take_slot(level, slot_of(tick, level));
+            }
+        }
+        self.take_slot(0, slot_of(tick, 0));
+    }
+
+    fn take_level(&mut self, level: usize) {
+        for slot in 0..SLOTS {
+            self.take_slot(level, slot);
+        }
+    }
+
+    fn take_slot(&mut self, level: usize, slot: usize) {
+        let keys = &mut self.levels[level][slot];
+        self.level_len[level] -= keys.len();
+        self.due.append(keys);
+    }
+
+    /// Estimated heap bytes held by the filed keys and the slots
+    pub(crate) fn memory_usage(&self) -> usize {
+        let slots = self.levels.iter().flatten().chain([&self.due]);
+        slots
+            .map(|keys| {
+                keys.capacity() * size_of::<String>()
+                    + keys.iter().map(String::capacity).sum::<usize>()
+            })
+            .sum()
+    }
+}
+
+fn slot_of(tick: u64, level: usize) -> usize {
+    ((tick >> (LEVEL_BITS as usize * level)) as usize) & (SLOTS - 1)
+}
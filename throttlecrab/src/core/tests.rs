@@ -1,5 +1,14 @@
-use super::{PeriodicStore, RateLimiter};
-use std::time::{Duration, SystemTime};
+use super::sliding_window::{owner_key, window_key_prefix, window_keys};
+use super::{
+    Algorithm, AsyncRateLimiter, AsyncStore, CellError, Clock, ConcurrencyLimiter,
+    ConcurrentRateLimiter, FixedWindow, Gcra, LeakyBucket, MockClock, PeriodicStore, Quota,
+    RateLimiter, SlidingWindow, SlidingWindowLimiter, SyncStore, WarmUp,
+};
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[test]
 fn test_basic_rate_limiting() {
@@ -692,3 +701,498 @@ fn test_rapid_time_changes() {
         assert!(result.is_ok());
     }
 }
+
+#[test]
+fn test_peek_does_not_consume() {
+    let mut limiter = RateLimiter::new(PeriodicStore::new());
+    let now = SystemTime::now();
+
+    // Peeking an unknown key reports a full burst and creates no entry
+    let (allowed, result) = limiter.peek("peek", 2, 6, 60, 1, now).unwrap();
+    assert!(allowed);
+    assert_eq!(result.remaining, 1);
+    assert!(limiter.store().is_empty());
+
+    limiter.rate_limit("peek", 2, 6, 60, 1, now).unwrap();
+    limiter.rate_limit("peek", 2, 6, 60, 1, now).unwrap();
+
+    // Peek matches what the next request would get
+    let (allowed, result) = limiter.peek("peek", 2, 6, 60, 1, now).unwrap();
+    assert!(!allowed);
+    assert_eq!(result.retry_after, Duration::from_secs(10));
+
+    let (allowed, next) = limiter.rate_limit("peek", 2, 6, 60, 1, now).unwrap();
+    assert!(!allowed);
+    assert_eq!(next.retry_after, result.retry_after);
+    assert_eq!(next.reset_after, result.reset_after);
+}
+
+#[test]
+fn test_concurrency_limiter_acquire_release() {
+    let mut limiter = ConcurrencyLimiter::new();
+    let now = SystemTime::now();
+    let ttl = Duration::from_secs(60);
+
+    let first = limiter.acquire("reports", 2, ttl, now).unwrap();
+    assert!(first.acquired());
+    assert_eq!(first.active, 1);
+    let second = limiter.acquire("reports", 2, ttl, now).unwrap();
+    assert_eq!(second.active, 2);
+    assert_ne!(first.lease_id, second.lease_id);
+
+    // At the limit, the wait is until the oldest lease expires
+    let denied = limiter
+        .acquire("reports", 2, ttl, now + Duration::from_secs(10))
+        .unwrap();
+    assert!(!denied.acquired());
+    assert_eq!(denied.limit, 2);
+    assert_eq!(denied.active, 2);
+    assert_eq!(denied.retry_after, Duration::from_secs(50));
+
+    // Other keys have their own slots
+    assert!(limiter.acquire("exports", 1, ttl, now).unwrap().acquired());
+
+    // Releasing frees a slot, once
+    let lease_id = first.lease_id.unwrap();
+    assert!(limiter.release("reports", lease_id, now));
+    assert!(!limiter.release("reports", lease_id, now));
+    assert!(!limiter.release("exports", second.lease_id.unwrap(), now));
+    assert!(limiter.acquire("reports", 2, ttl, now).unwrap().acquired());
+}
+
+#[test]
+fn test_concurrency_limiter_lease_expiry() {
+    let mut limiter = ConcurrencyLimiter::new();
+    let now = SystemTime::now();
+    let ttl = Duration::from_secs(30);
+
+    let lease = limiter.acquire("jobs", 1, ttl, now).unwrap();
+    assert!(!limiter.acquire("jobs", 1, ttl, now).unwrap().acquired());
+
+    // An expired lease no longer holds a slot and cannot be released
+    let later = now + ttl;
+    assert_eq!(limiter.store().active("jobs", later), 0);
+    assert!(limiter.acquire("jobs", 1, ttl, later).unwrap().acquired());
+    assert!(!limiter.release("jobs", lease.lease_id.unwrap(), later));
+
+    limiter.acquire("other", 3, ttl, now).unwrap();
+    assert_eq!(limiter.store().len(), 2);
+    assert_eq!(limiter.store_mut().remove_expired(later + ttl), 2);
+    assert!(limiter.store().is_empty());
+    assert_eq!(limiter.store().memory_usage(), 0);
+
+    assert!(matches!(
+        limiter.acquire("jobs", 0, ttl, now),
+        Err(CellError::InvalidRateLimit)
+    ));
+    assert!(matches!(
+        limiter.acquire("jobs", 1, Duration::ZERO, now),
+        Err(CellError::InvalidRateLimit)
+    ));
+}
+
+#[test]
+fn test_sliding_window_limit() {
+    let mut limiter = SlidingWindowLimiter::new(PeriodicStore::new());
+    // Start of a 60s window
+    let start = UNIX_EPOCH + Duration::from_secs(1_704_067_200);
+
+    // The whole limit is available at once
+    for remaining in (0..5).rev() {
+        let (allowed, result) = limiter.rate_limit("sw", 5, 60, 1, start).unwrap();
+        assert!(allowed);
+        assert_eq!(result.limit, 5);
+        assert_eq!(result.remaining, remaining);
+    }
+    let (allowed, result) = limiter.rate_limit("sw", 5, 60, 1, start).unwrap();
+    assert!(!allowed);
+    assert_eq!(result.remaining, 0);
+    // One request frees up once a fifth of the next window has passed
+    assert_eq!(result.retry_after, Duration::from_secs(72));
+    assert_eq!(result.reset_after, Duration::from_secs(120));
+
+    // Halfway through the next window, half of the previous window still counts
+    let later = start + Duration::from_secs(90);
+    let (allowed, result) = limiter.peek("sw", 5, 60, 1, later).unwrap();
+    assert!(allowed);
+    assert_eq!(result.remaining, 1);
+    for _ in 0..2 {
+        assert!(limiter.rate_limit("sw", 5, 60, 1, later).unwrap().0);
+    }
+    let (allowed, result) = limiter.rate_limit("sw", 5, 60, 1, later).unwrap();
+    assert!(!allowed);
+    assert_eq!(result.retry_after, Duration::from_secs(6));
+
+    // Two windows on, nothing counts
+    let (allowed, result) = limiter
+        .rate_limit("sw", 5, 60, 5, start + Duration::from_secs(180))
+        .unwrap();
+    assert!(allowed);
+    assert_eq!(result.remaining, 0);
+}
+
+#[test]
+fn test_sliding_window_store_layout() {
+    let mut store = PeriodicStore::new();
+    let now = UNIX_EPOCH + Duration::from_secs(1_704_067_230);
+
+    // A borrowed store can be shared with other limiters
+    let mut limiter = SlidingWindowLimiter::new(&mut store);
+    limiter.rate_limit("sw", 10, 60, 3, now).unwrap();
+    assert!(limiter.peek("sw", 10, 60, 0, now).unwrap().0);
+
+    let [current, previous] = window_keys("sw", 60, now);
+    assert_eq!(current, "sw\u{0}28401120");
+    assert_eq!(previous, "sw\u{0}28401119");
+    assert_eq!(store.len(), 1);
+    assert_eq!(store.entry(&current).map(|(count, _)| count), Some(3));
+    assert!(store.entry("sw").is_none());
+    let page = store.keys_with_prefix(&window_key_prefix("sw"), None, 10);
+    assert_eq!(page.keys, std::slice::from_ref(&current));
+    assert_eq!(owner_key(&current), "sw");
+    assert_eq!(owner_key("sw"), "sw");
+
+    // Peeking and empty requests write nothing
+    let mut limiter = SlidingWindowLimiter::new(PeriodicStore::new());
+    limiter.peek("sw", 10, 60, 1, now).unwrap();
+    limiter.rate_limit("sw", 10, 60, 0, now).unwrap();
+    assert_eq!(limiter.store().len(), 0);
+
+    assert!(matches!(
+        limiter.rate_limit("sw", 0, 60, 1, now),
+        Err(CellError::InvalidRateLimit)
+    ));
+    assert!(matches!(
+        limiter.rate_limit("sw", 10, 60, -1, now),
+        Err(CellError::NegativeQuantity(-1))
+    ));
+}
+
+#[test]
+fn test_fixed_window() {
+    let mut limiter = RateLimiter::new(PeriodicStore::new());
+    let quota = Quota::new(1, 3, 60);
+    // 15 seconds into a 60s window
+    let now = UNIX_EPOCH + Duration::from_secs(1_704_067_215);
+
+    // max_burst is not used; the whole window's count is available at once
+    for remaining in (0..3).rev() {
+        let (allowed, result) = limiter
+            .rate_limit_with(&FixedWindow, "fw", &quota, 1, now)
+            .unwrap();
+        assert!(allowed);
+        assert_eq!(result.limit, 3);
+        assert_eq!(result.remaining, remaining);
+        assert_eq!(result.reset_after, Duration::from_secs(45));
+    }
+    let (allowed, result) = limiter
+        .rate_limit_with(&FixedWindow, "fw", &quota, 1, now)
+        .unwrap();
+    assert!(!allowed);
+    assert_eq!(result.retry_after, Duration::from_secs(45));
+
+    // The next window starts empty
+    let next = now + Duration::from_secs(45);
+    let (allowed, result) = limiter
+        .peek_with(&FixedWindow, "fw", &quota, 3, next)
+        .unwrap();
+    assert!(allowed);
+    assert_eq!(result.remaining, 0);
+
+    let keys = FixedWindow.state_keys("fw", &quota, now);
+    assert_eq!(keys, [window_keys("fw", 60, now)[0].as_str()]);
+    assert!(limiter.store().entry(&keys[0]).is_some());
+}
+
+#[test]
+fn test_leaky_bucket() {
+    let mut store = PeriodicStore::new();
+    // Holds 4, drains 1 every 15 seconds
+    let quota = Quota::new(4, 4, 60);
+    let now = UNIX_EPOCH + Duration::from_secs(1_704_067_200);
+
+    for remaining in (0..4).rev() {
+        let (allowed, result) = LeakyBucket
+            .rate_limit(&mut store, "lb", &quota, 1, now)
+            .unwrap();
+        assert!(allowed);
+        assert_eq!(result.limit, 4);
+        assert_eq!(result.remaining, remaining);
+    }
+    let (allowed, result) = LeakyBucket
+        .rate_limit(&mut store, "lb", &quota, 1, now)
+        .unwrap();
+    assert!(!allowed);
+    assert_eq!(result.retry_after, Duration::from_secs(15));
+    assert_eq!(result.reset_after, Duration::from_secs(60));
+
+    // Draining frees one unit per 15 seconds
+    let later = now + Duration::from_secs(30);
+    let (allowed, result) = LeakyBucket.peek(&store, "lb", &quota, 2, later).unwrap();
+    assert!(allowed);
+    assert_eq!(result.remaining, 0);
+    let (allowed, result) = LeakyBucket.peek(&store, "lb", &quota, 3, later).unwrap();
+    assert!(!allowed);
+    assert_eq!(result.retry_after, Duration::from_secs(15));
+
+    // A request larger than the bucket never fits
+    let (allowed, _) = LeakyBucket.peek(&store, "lb", &quota, 5, later).unwrap();
+    assert!(!allowed);
+    assert!(matches!(
+        LeakyBucket.rate_limit(&mut store, "lb", &Quota::new(0, 4, 60), 1, now),
+        Err(CellError::InvalidRateLimit)
+    ));
+}
+
+#[test]
+fn test_algorithms_share_a_store() {
+    let mut limiter = RateLimiter::new(PeriodicStore::new());
+    let quota = Quota::new(2, 10, 60);
+    let now = SystemTime::now();
+
+    // The GCRA entry points and the Gcra algorithm are the same limit
+    limiter.rate_limit("k", 2, 10, 60, 1, now).unwrap();
+    let (allowed, result) = limiter.rate_limit_with(&Gcra, "k", &quota, 1, now).unwrap();
+    assert!(allowed);
+    assert_eq!(result.remaining, 0);
+    assert!(!limiter.rate_limit("k", 2, 10, 60, 1, now).unwrap().0);
+
+    // Window algorithms keep their state apart from the key's GCRA state
+    let (allowed, result) = limiter
+        .rate_limit_with(&FixedWindow, "k", &quota, 1, now)
+        .unwrap();
+    assert!(allowed);
+    assert_eq!(result.remaining, 9);
+    assert_eq!(limiter.store().len(), 2);
+}
+
+/// Drive a future that never waits on anything to completion
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+#[test]
+fn test_async_rate_limiter_matches_sync() {
+    let mut sync = RateLimiter::new(PeriodicStore::new());
+    let mut limiter = AsyncRateLimiter::new(SyncStore::new(PeriodicStore::new()));
+    let quota = Quota::new(3, 10, 60);
+    // On a window boundary, so the sliding window's keys don't roll over
+    let start = UNIX_EPOCH + Duration::from_secs(1_704_067_200);
+
+    for step in 0..6 {
+        let now = start + Duration::from_secs(step);
+        let expected = sync.rate_limit("gcra", 3, 10, 60, 1, now).unwrap();
+        let actual = block_on(limiter.rate_limit("gcra", 3, 10, 60, 1, now)).unwrap();
+        assert_eq!(actual.0, expected.0);
+        assert_eq!(actual.1.remaining, expected.1.remaining);
+
+        let expected = sync
+            .rate_limit_with(&SlidingWindow, "window", &quota, 2, now)
+            .unwrap();
+        let actual =
+            block_on(limiter.rate_limit_with(&SlidingWindow, "window", &quota, 2, now)).unwrap();
+        assert_eq!(actual.0, expected.0);
+        assert_eq!(actual.1.remaining, expected.1.remaining);
+    }
+
+    // Peeking leaves the store as it is
+    let (_, before) =
+        block_on(limiter.peek_with(&LeakyBucket, "bucket", &quota, 0, start)).unwrap();
+    assert!(
+        block_on(limiter.peek_with(&LeakyBucket, "bucket", &quota, 1, start))
+            .unwrap()
+            .0
+    );
+    assert_eq!(limiter.store().get_ref().len(), 2);
+    let (_, after) = block_on(limiter.peek_with(&LeakyBucket, "bucket", &quota, 0, start)).unwrap();
+    assert_eq!(before.remaining, after.remaining);
+}
+
+/// Store whose first writes lose the race to another writer
+struct Contended {
+    store: SyncStore<PeriodicStore>,
+    conflicts: u32,
+}
+
+impl Contended {
+    fn lose(&mut self) -> bool {
+        let lost = self.conflicts > 0;
+        self.conflicts = self.conflicts.saturating_sub(1);
+        lost
+    }
+}
+
+impl AsyncStore for Contended {
+    async fn compare_and_swap_with_ttl(
+        &mut self,
+        key: &str,
+        old: i64,
+        new: i64,
+        ttl: Duration,
+        now: SystemTime,
+    ) -> Result<bool, String> {
+        if self.lose() {
+            return Ok(false);
+        }
+        self.store
+            .compare_and_swap_with_ttl(key, old, new, ttl, now)
+            .await
+    }
+
+    async fn get(&self, key: &str, now: SystemTime) -> Result<Option<i64>, String> {
+        self.store.get(key, now).await
+    }
+
+    async fn set_if_not_exists_with_ttl(
+        &mut self,
+        key: &str,
+        value: i64,
+        ttl: Duration,
+        now: SystemTime,
+    ) -> Result<bool, String> {
+        if self.lose() {
+            return Ok(false);
+        }
+        self.store
+            .set_if_not_exists_with_ttl(key, value, ttl, now)
+            .await
+    }
+}
+
+#[test]
+fn test_async_rate_limiter_retries_conflicts() {
+    let now = SystemTime::now();
+    let mut limiter = AsyncRateLimiter::new(Contended {
+        store: SyncStore::new(PeriodicStore::new()),
+        conflicts: 3,
+    });
+    let (allowed, result) = block_on(limiter.rate_limit("k", 3, 10, 60, 1, now)).unwrap();
+    assert!(allowed);
+    assert_eq!(result.remaining, 2);
+
+    // A key that never stops changing gives up
+    limiter.store_mut().conflicts = u32::MAX;
+    assert!(matches!(
+        block_on(limiter.rate_limit("k", 3, 10, 60, 1, now)),
+        Err(CellError::Internal(_))
+    ));
+}
+
+#[test]
+fn test_concurrent_rate_limiter_matches_rate_limiter() {
+    let now = SystemTime::now();
+    let mut single = RateLimiter::new(PeriodicStore::new());
+    let concurrent = ConcurrentRateLimiter::new(4, PeriodicStore::new);
+    for i in 0..20 {
+        let key = format!("user:{}", i % 5);
+        let (expected, expected_result) = single.rate_limit(&key, 3, 10, 60, 1, now).unwrap();
+        let (allowed, result) = concurrent.rate_limit(&key, 3, 10, 60, 1, now).unwrap();
+        assert_eq!(allowed, expected);
+        assert_eq!(result.remaining, expected_result.remaining);
+    }
+
+    let mut keys = 0;
+    concurrent.for_each_store(|store| keys += store.len());
+    assert_eq!(keys, 5);
+    assert!(concurrent.store("user:0").remove("user:0"));
+}
+
+#[test]
+fn test_concurrent_rate_limiter_threads() {
+    let clock = MockClock::new();
+    let limiter = Arc::new(ConcurrentRateLimiter::with_clock(
+        8,
+        PeriodicStore::new,
+        clock.clone(),
+    ));
+
+    // 4 threads race for one key's burst of 100; exactly 100 win
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let limiter = Arc::clone(&limiter);
+            std::thread::spawn(move || {
+                (0..50)
+                    .filter(|_| limiter.rate_limit_now("shared", 100, 1, 3600, 1).unwrap().0)
+                    .count()
+            })
+        })
+        .collect();
+    let allowed: usize = workers.into_iter().map(|w| w.join().unwrap()).sum();
+    assert_eq!(allowed, 100);
+
+    let quota = Quota::new(100, 1, 3600);
+    let (allowed, _) = limiter
+        .peek_with(&Gcra, "shared", &quota, 1, clock.now())
+        .unwrap();
+    assert!(!allowed);
+}
+
+#[test]
+fn test_warm_up() {
+    let mut limiter = RateLimiter::new(PeriodicStore::new());
+    let algorithm = WarmUp::new(Duration::from_secs(100), 10).with(Gcra);
+    let quota = Quota::new(100, 100, 1);
+    let start = SystemTime::now();
+
+    // A new key gets 10% of its burst
+    let allowed = (0..20)
+        .filter(|_| {
+            limiter
+                .rate_limit_with(&algorithm, "new", &quota, 1, start)
+                .unwrap()
+                .0
+        })
+        .count();
+    assert_eq!(allowed, 10);
+
+    // Halfway through, 55%; at the end, the full quota
+    let half = start + Duration::from_secs(50);
+    let (_, result) = limiter
+        .peek_with(&algorithm, "new", &quota, 0, half)
+        .unwrap();
+    assert_eq!(result.limit, 55);
+    limiter
+        .rate_limit_with(&algorithm, "new", &quota, 1, half)
+        .unwrap();
+    let end = start + Duration::from_secs(100);
+    let (_, result) = limiter
+        .rate_limit_with(&algorithm, "new", &quota, 1, end)
+        .unwrap();
+    assert_eq!(result.limit, 100);
+
+    // A key left idle for the warm-up duration warms up again
+    let idle = end + Duration::from_secs(100);
+    let (_, result) = limiter
+        .rate_limit_with(&algorithm, "new", &quota, 1, idle)
+        .unwrap();
+    assert_eq!(result.limit, 10);
+
+    // Invalid warm-ups are rejected
+    let invalid = WarmUp::new(Duration::from_secs(100), 0).with(Gcra);
+    assert!(matches!(
+        limiter.rate_limit_with(&invalid, "new", &quota, 1, start),
+        Err(CellError::InvalidRateLimit)
+    ));
+}
+
+#[test]
+fn test_warm_up_async() {
+    let algorithm = WarmUp::new(Duration::from_secs(100), 10).with(Gcra);
+    let quota = Quota::new(100, 100, 1);
+    let now = SystemTime::now();
+    let mut limiter = AsyncRateLimiter::new(SyncStore::new(PeriodicStore::new()));
+
+    // The first-seen time is read and written like the algorithm's state
+    for (secs, limit) in [(0, 10), (50, 55), (100, 100)] {
+        let at = now + Duration::from_secs(secs);
+        let (_, result) =
+            block_on(limiter.rate_limit_with(&algorithm, "new", &quota, 1, at)).unwrap();
+        assert_eq!(result.limit, limit);
+    }
+}
@@ -0,0 +1,188 @@
+//! Gradual ramp-up for new keys
+//!
+//! After a cache flush or a restart every key is new, and every client gets
+//! its full burst at once. [`WarmingUp`] wraps an [`Algorithm`] so that a
+//! key first seen less than a [`WarmUp`]'s duration ago is limited with a
+//! reduced quota: `max_burst` and `count_per_period` start at
+//! `initial_percent` of their configured values and grow linearly to the
+//! full values over the duration.
+//!
+//! # Store Layout
+//!
+//! Besides the wrapped algorithm's state, one entry per key holds the time
+//! the key was first seen, in nanoseconds since the Unix epoch. Every
+//! check refreshes its TTL to the longer of the warm-up duration and the
+//! quota's period, so a key idle for longer than that warms up again.
+
+use super::algorithm::{Algorithm, Quota, nanos_since_epoch, to_duration};
+use super::store::Store;
+use super::{CellError, RateLimitResult};
+use std::borrow::Cow;
+use std::time::{Duration, SystemTime};
+
+/// How a new key's quota ramps up to its full value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WarmUp {
+    /// Time from a key's first check until it gets its full quota
+    pub duration: Duration,
+    /// Share of the quota a new key starts with, from 1 to 100
+    pub initial_percent: u8,
+}
+
+impl WarmUp {
+    /// Ramp up from `initial_percent` to the full quota over `duration`
+    pub fn new(duration: Duration, initial_percent: u8) -> Self {
+        WarmUp {
+            duration,
+            initial_percent,
+        }
+    }
+
+    /// Wrap `algorithm` to warm up new keys
+    pub fn with<A: Algorithm>(self, algorithm: A) -> WarmingUp<A> {
+        WarmingUp {
+            algorithm,
+            warm_up: self,
+        }
+    }
+
+    /// `quota` scaled for a key first seen `elapsed` ago
+    ///
+    /// Each scaled value is at least 1, so a new key is never locked out.
+    pub fn quota(&self, quota: &Quota, elapsed: Duration) -> Quota {
+        if elapsed >= self.duration {
+            return *quota;
+        }
+        let initial = self.initial_percent as i128;
+        let total = self.duration.as_nanos() as i128;
+        let elapsed = elapsed.as_nanos() as i128;
+        // Percent of the full quota, in parts per 100 * total
+        let share = initial * total + (100 - initial) * elapsed;
+        let scale = |value: i64| (value as i128 * share / (100 * total)).max(1) as i64;
+        Quota {
+            max_burst: scale(quota.max_burst),
+            count_per_period: scale(quota.count_per_period),
+            period: quota.period,
+        }
+    }
+
+    fn validate(&self) -> Result<(), CellError> {
+        if self.duration.is_zero() || !(1..=100).contains(&self.initial_percent) {
+            return Err(CellError::InvalidRateLimit);
+        }
+        Ok(())
+    }
+}
+
+/// An [`Algorithm`] limiting new keys with a reduced quota
+///
+/// Created with [`WarmUp::with`].
+///
+/// # Example
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+/// use throttlecrab::{Gcra, PeriodicStore, Quota, RateLimiter, WarmUp};
+///
+/// let mut limiter = RateLimiter::new(PeriodicStore::new());
+/// // New keys start with 10% of their quota, reaching 100% after 5 minutes
+/// let algorithm = WarmUp::new(Duration::from_secs(300), 10).with(Gcra);
+/// let quota = Quota::new(100, 1000, 60);
+///
+/// let (allowed, result) = limiter
+///     .rate_limit_with(&algorithm, "user:123", &quota, 1, SystemTime::now())
+///     .unwrap();
+/// assert!(allowed);
+/// assert_eq!(result.limit, 10);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WarmingUp<A> {
+    /// The algorithm deciding requests
+    pub algorithm: A,
+    /// How new keys ramp up
+    pub warm_up: WarmUp,
+}
+
+impl<A: Algorithm> WarmingUp<A> {
+    fn ttl(&self, quota: &Quota) -> Duration {
+        self.warm_up
+            .duration
+            .max(Duration::from_secs(quota.period.max(0) as u64))
+    }
+}
+
+impl<A: Algorithm> Algorithm for WarmingUp<A> {
+    fn rate_limit<S: Store + ?Sized>(
+        &self,
+        store: &mut S,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        self.warm_up.validate()?;
+        let marker = marker_key(key);
+        let now_ns = nanos_since_epoch(now)?;
+        let ttl = self.ttl(quota);
+
+        let first_seen = match store.get(&marker, now).map_err(CellError::Internal)? {
+            Some(first_seen) => {
+                // Keep the marker alive while the key is in use; losing a
+                // race here only means another check refreshed it
+                store
+                    .compare_and_swap_with_ttl(&marker, first_seen, first_seen, ttl, now)
+                    .map_err(CellError::Internal)?;
+                first_seen as i128
+            }
+            None => {
+                let first_seen = now_ns.min(i64::MAX as i128) as i64;
+                if store
+                    .set_if_not_exists_with_ttl(&marker, first_seen, ttl, now)
+                    .map_err(CellError::Internal)?
+                {
+                    first_seen as i128
+                } else {
+                    // Another check saw the key first
+                    store
+                        .get(&marker, now)
+                        .map_err(CellError::Internal)?
+                        .map_or(now_ns, i128::from)
+                }
+            }
+        };
+
+        let quota = self.warm_up.quota(quota, to_duration(now_ns - first_seen));
+        self.algorithm.rate_limit(store, key, &quota, quantity, now)
+    }
+
+    fn peek<S: Store + ?Sized>(
+        &self,
+        store: &S,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        self.warm_up.validate()?;
+        let now_ns = nanos_since_epoch(now)?;
+        let elapsed = store
+            .get(&marker_key(key), now)
+            .map_err(CellError::Internal)?
+            .map_or(Duration::ZERO, |first_seen| {
+                to_duration(now_ns - first_seen as i128)
+            });
+        let quota = self.warm_up.quota(quota, elapsed);
+        self.algorithm.peek(store, key, &quota, quantity, now)
+    }
+
+    fn state_keys<'a>(&self, key: &'a str, quota: &Quota, now: SystemTime) -> Vec<Cow<'a, str>> {
+        let mut keys = self.algorithm.state_keys(key, quota, now);
+        keys.push(Cow::Owned(marker_key(key)));
+        keys
+    }
+}
+
+/// Store key of the time `key` was first seen
+fn marker_key(key: &str) -> String {
+    format!("{key}\0warm_up")
+}
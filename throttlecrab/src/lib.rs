@@ -72,6 +72,19 @@
 //!     .build();
 //! ```
 //!
+//! ### [`TimingWheelStore`]
+//! Indexes keys by expiry and removes them as they expire, a few per
+//! operation. Best for large stores where cleanup scans cause latency spikes.
+//!
+//! ```
+//! use throttlecrab::TimingWheelStore;
+//!
+//! let store = TimingWheelStore::builder()
+//!     .capacity(5_000_000)
+//!     .cleanup_batch(256) // at most 256 expired keys per operation
+//!     .build();
+//! ```
+//!
 //! ## Common Use Cases
 //!
 //! ### API Rate Limiting
@@ -122,27 +135,144 @@
 //! - **`period`**: Time period in seconds
 //! - **`quantity`**: Number of tokens to consume (default: 1)
 //!
+//! ## Time Sources
+//!
+//! [`RateLimiter::rate_limit`] takes the current time explicitly.
+//! [`RateLimiter::rate_limit_now`] reads it from the limiter's [`Clock`]
+//! instead: [`SystemClock`] by default, [`MonotonicClock`] to ignore wall
+//! clock adjustments, or [`MockClock`] for deterministic tests.
+//!
+//! ```
+//! use std::time::Duration;
+//! use throttlecrab::{MockClock, PeriodicStore, RateLimiter};
+//!
+//! let clock = MockClock::new();
+//! let mut limiter = RateLimiter::with_clock(PeriodicStore::new(), clock.clone());
+//!
+//! // Exhaust a burst of 2, then wait for the next token
+//! limiter.rate_limit_now("user:123", 2, 60, 60, 2)?;
+//! let (allowed, result) = limiter.rate_limit_now("user:123", 2, 60, 60, 1)?;
+//! assert!(!allowed);
+//!
+//! clock.advance(result.retry_after);
+//! assert!(limiter.rate_limit_now("user:123", 2, 60, 60, 1)?.0);
+//! # Ok::<(), throttlecrab::CellError>(())
+//! ```
+//!
+//! ## Sliding Windows
+//!
+//! GCRA spreads a rate evenly over the period. For a plain "no more than N
+//! in any window" limit, use [`SlidingWindowLimiter`] instead; it takes
+//! the window's limit and length rather than a burst and a rate:
+//!
+//! ```
+//! use std::time::SystemTime;
+//! use throttlecrab::{PeriodicStore, SlidingWindowLimiter};
+//!
+//! let mut limiter = SlidingWindowLimiter::new(PeriodicStore::new());
+//!
+//! // At most 100 requests in any 60 seconds
+//! let (allowed, result) = limiter.rate_limit("user:123", 100, 60, 1, SystemTime::now())?;
+//! assert!(allowed);
+//! assert_eq!(result.remaining, 99);
+//! # Ok::<(), throttlecrab::CellError>(())
+//! ```
+//!
+//! ## Other Algorithms
+//!
+//! Every algorithm implements [`Algorithm`] and keeps its state in a store
+//! it is handed, so any of them can run against a [`RateLimiter`]'s store
+//! with [`RateLimiter::rate_limit_with`]: [`Gcra`] (the default),
+//! [`SlidingWindow`], [`FixedWindow`] and [`LeakyBucket`]. Implement the
+//! trait to plug in your own. [`WarmUp::with`] wraps any of them to give
+//! new keys a reduced quota that grows to the full one over a warm-up
+//! period.
+//!
+//! ```
+//! use std::time::SystemTime;
+//! use throttlecrab::{FixedWindow, PeriodicStore, Quota, RateLimiter};
+//!
+//! let mut limiter = RateLimiter::new(PeriodicStore::new());
+//!
+//! // 1000 requests per calendar hour
+//! let quota = Quota::new(1000, 1000, 3600);
+//! let (allowed, _) = limiter.rate_limit_with(&FixedWindow, "user:123", &quota, 1, SystemTime::now())?;
+//! assert!(allowed);
+//! # Ok::<(), throttlecrab::CellError>(())
+//! ```
+//!
+//! ## Concurrency Limiting
+//!
+//! [`ConcurrencyLimiter`] caps operations in flight rather than their rate.
+//! Each admitted operation holds a lease until it is released or its TTL
+//! runs out:
+//!
+//! ```
+//! use std::time::{Duration, SystemTime};
+//! use throttlecrab::ConcurrencyLimiter;
+//!
+//! let mut limiter = ConcurrencyLimiter::new();
+//!
+//! // At most 50 simultaneous exports, each holding its slot for up to a minute
+//! let lease = limiter.acquire("export", 50, Duration::from_secs(60), SystemTime::now())?;
+//! if let Some(lease_id) = lease.lease_id {
+//!     // ... run the export ...
+//!     limiter.release("export", lease_id, SystemTime::now());
+//! }
+//! # Ok::<(), throttlecrab::CellError>(())
+//! ```
+//!
 //! ## Thread Safety
 //!
-//! The rate limiter itself is not thread-safe. For concurrent access, wrap it in a mutex:
+//! [`RateLimiter`] takes `&mut self`, so sharing one between threads means
+//! wrapping it in a mutex, which serializes every check.
+//! [`ConcurrentRateLimiter`] splits keys across several stores, each behind
+//! its own lock, so threads can call it directly and checks on different
+//! keys rarely wait for each other:
 //!
 //! ```
-//! use std::sync::{Arc, Mutex};
-//! use throttlecrab::{RateLimiter, AdaptiveStore};
+//! use std::sync::Arc;
+//! use throttlecrab::{AdaptiveStore, ConcurrentRateLimiter};
+//!
+//! // 16 shards of up to 100,000 keys each
+//! let limiter = Arc::new(ConcurrentRateLimiter::new(16, || {
+//!     AdaptiveStore::builder().capacity(100_000).build()
+//! }));
 //!
-//! let limiter = Arc::new(Mutex::new(RateLimiter::new(AdaptiveStore::new())));
+//! let (allowed, _) = limiter.rate_limit_now("user:123", 10, 100, 60, 1)?;
+//! assert!(allowed);
+//! # Ok::<(), throttlecrab::CellError>(())
 //! ```
 //!
 //! ## Features
 //!
 //! - `ahash` (default): Use AHash for faster hashing
+//! - `types`: Request and response types shared with the server, with
+//!   conversions from and to [`RateLimitResult`] (see [`types`])
+//! - `redis`: `RedisStore` and `AsyncRedisStore`, keeping state in an
+//!   external Redis
+//! - `serde`: `export_state` and `import_state` on the in-memory stores,
+//!   to persist their entries as a `StoreState`
 
 pub mod core;
+#[cfg(feature = "types")]
+pub mod types;
+
+/// Version of this crate
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub use core::{
-    AdaptiveStore, AdaptiveStoreBuilder, CellError, PeriodicStore, PeriodicStoreBuilder,
-    ProbabilisticStore, ProbabilisticStoreBuilder, Rate, RateLimitResult, RateLimiter, Store,
+    AcquireResult, AdaptiveStore, AdaptiveStoreBuilder, Algorithm, AsyncRateLimiter, AsyncStore,
+    CellError, Clock, ConcurrencyLimiter, ConcurrentRateLimiter, FixedWindow, Gcra, KeyPage,
+    LeakyBucket, LeaseStore, MockClock, MonotonicClock, PeriodicStore, PeriodicStoreBuilder,
+    ProbabilisticStore, ProbabilisticStoreBuilder, Quota, Rate, RateLimitResult, RateLimiter,
+    SlidingWindow, SlidingWindowLimiter, Store, SyncStore, SystemClock, TimingWheelStore,
+    TimingWheelStoreBuilder, WarmUp, WarmingUp,
 };
+#[cfg(feature = "redis")]
+pub use core::{AsyncRedisStore, RedisStore};
+#[cfg(feature = "serde")]
+pub use core::{StateEntry, StoreState};
 
 // Re-export the store module so benchmarks can access it
 pub use crate::core::store;
@@ -0,0 +1,1078 @@
+//! Request and response types shared with the throttlecrab server
+//!
+//! Enabled by the `types` feature. These are the types the
+//! `throttlecrab-server` crate uses on every transport, so applications that
+//! embed the library and also talk to a server can use one set of types and
+//! the conversions below instead of writing their own glue:
+//!
+//! - [`ThrottleResponse`] from `(bool, RateLimitResult)`, the result of
+//!   [`RateLimiter::rate_limit`], and back with `TryFrom`
+//! - [`ValidationError`] from [`CellError`] with `TryFrom`, for the errors
+//!   that describe an invalid request
+//! - [`AcquireResponse`] from [`AcquireResult`], the result of
+//!   [`ConcurrencyLimiter::acquire`]
+//!
+//! # Validation
+//!
+//! [`ThrottleRequest::validate`] holds the one rule set every transport
+//! applies before a request reaches the store. Violations are reported as a
+//! [`ValidationError`] with a stable [`code`](ValidationError::code).
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::SystemTime;
+//! use throttlecrab::types::ThrottleResponse;
+//! use throttlecrab::{PeriodicStore, RateLimitResult, RateLimiter};
+//!
+//! let mut limiter = RateLimiter::new(PeriodicStore::new());
+//! let decision = limiter.rate_limit("user:123", 10, 100, 60, 1, SystemTime::now())?;
+//!
+//! let response = ThrottleResponse::from(decision);
+//! assert!(response.allowed);
+//!
+//! let (allowed, result): (bool, RateLimitResult) = response.try_into()?;
+//! assert!(allowed);
+//! assert_eq!(result.remaining, 9);
+//! # Ok::<(), throttlecrab::CellError>(())
+//! ```
+//!
+//! [`RateLimiter::rate_limit`]: crate::RateLimiter::rate_limit
+//! [`ConcurrencyLimiter::acquire`]: crate::ConcurrencyLimiter::acquire
+
+use crate::{
+    AcquireResult, Algorithm, CellError, FixedWindow, Gcra, LeakyBucket, Quota, RateLimitResult,
+    SlidingWindow, Store, WarmUp,
+};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Internal rate limit request structure
+///
+/// This is the common request format used by all transports
+/// after parsing their protocol-specific formats.
+///
+/// # Fields
+///
+/// - `key`: Unique identifier for the rate limit (e.g., "user:123", "api:endpoint").
+///   Shared rather than owned, so handing the request to the actor, metrics,
+///   event export and hooks never copies it
+/// - `max_burst`: Maximum tokens available at once (burst capacity)
+/// - `count_per_period`: Total tokens replenished per period
+/// - `period`: Time period in seconds for token replenishment
+/// - `quantity`: Number of tokens to consume (typically 1)
+/// - `timestamp`: Request timestamp for consistent rate limiting
+/// - `algorithm`: How the parameters are applied (see [`AlgorithmKind`])
+/// - `warm_up`: How a new key's quota ramps up, if it does (see [`WarmUp`])
+/// - `warn_percent`: Share of `max_burst` in use at which responses carry a
+///   warning, if any (see [`ThrottleResponse::with_warning`])
+#[derive(Debug, Clone)]
+pub struct ThrottleRequest {
+    /// The key to rate limit (e.g., "user:123", "ip:192.168.1.1")
+    pub key: Arc<str>,
+    /// Maximum burst capacity (tokens available at once)
+    pub max_burst: i64,
+    /// Tokens replenished per period
+    pub count_per_period: i64,
+    /// Period in seconds for token replenishment
+    pub period: i64,
+    /// Number of tokens to consume (default: 1)
+    pub quantity: i64,
+    /// Request timestamp for consistent rate limiting
+    pub timestamp: SystemTime,
+    /// Rate limiting algorithm to check the request with
+    pub algorithm: AlgorithmKind,
+    /// Reduced quota for new keys, growing to the full one
+    pub warm_up: Option<WarmUp>,
+    /// Percent of `max_burst` in use from which responses are warnings
+    pub warn_percent: Option<u8>,
+}
+
+/// Rate limiting algorithm a request is checked with
+///
+/// Written in `snake_case` on the wire, e.g. `"sliding_window"`. Each kind
+/// implements [`Algorithm`] by delegating to the algorithm it names, so a
+/// new algorithm needs a variant here and nothing else in the transports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlgorithmKind {
+    /// [`Gcra`]: `max_burst` at once, refilled at `count_per_period` per
+    /// `period`
+    #[default]
+    Gcra,
+    /// [`SlidingWindow`]: at most `count_per_period` in any `period`
+    /// seconds
+    SlidingWindow,
+    /// [`FixedWindow`]: at most `count_per_period` per window of `period`
+    /// seconds
+    FixedWindow,
+    /// [`LeakyBucket`]: a bucket of `max_burst` draining at
+    /// `count_per_period` per `period`
+    LeakyBucket,
+}
+
+impl AlgorithmKind {
+    /// Every algorithm, in declaration order
+    pub const ALL: [AlgorithmKind; 4] = [
+        AlgorithmKind::Gcra,
+        AlgorithmKind::SlidingWindow,
+        AlgorithmKind::FixedWindow,
+        AlgorithmKind::LeakyBucket,
+    ];
+
+    /// Name of the algorithm on the wire
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlgorithmKind::Gcra => "gcra",
+            AlgorithmKind::SlidingWindow => "sliding_window",
+            AlgorithmKind::FixedWindow => "fixed_window",
+            AlgorithmKind::LeakyBucket => "leaky_bucket",
+        }
+    }
+}
+
+impl Algorithm for AlgorithmKind {
+    fn rate_limit<S: Store + ?Sized>(
+        &self,
+        store: &mut S,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        match self {
+            AlgorithmKind::Gcra => Gcra.rate_limit(store, key, quota, quantity, now),
+            AlgorithmKind::SlidingWindow => {
+                SlidingWindow.rate_limit(store, key, quota, quantity, now)
+            }
+            AlgorithmKind::FixedWindow => FixedWindow.rate_limit(store, key, quota, quantity, now),
+            AlgorithmKind::LeakyBucket => LeakyBucket.rate_limit(store, key, quota, quantity, now),
+        }
+    }
+
+    fn peek<S: Store + ?Sized>(
+        &self,
+        store: &S,
+        key: &str,
+        quota: &Quota,
+        quantity: i64,
+        now: SystemTime,
+    ) -> Result<(bool, RateLimitResult), CellError> {
+        match self {
+            AlgorithmKind::Gcra => Gcra.peek(store, key, quota, quantity, now),
+            AlgorithmKind::SlidingWindow => SlidingWindow.peek(store, key, quota, quantity, now),
+            AlgorithmKind::FixedWindow => FixedWindow.peek(store, key, quota, quantity, now),
+            AlgorithmKind::LeakyBucket => LeakyBucket.peek(store, key, quota, quantity, now),
+        }
+    }
+
+    fn state_keys<'a>(&self, key: &'a str, quota: &Quota, now: SystemTime) -> Vec<Cow<'a, str>> {
+        match self {
+            AlgorithmKind::Gcra => Gcra.state_keys(key, quota, now),
+            AlgorithmKind::SlidingWindow => SlidingWindow.state_keys(key, quota, now),
+            AlgorithmKind::FixedWindow => FixedWindow.state_keys(key, quota, now),
+            AlgorithmKind::LeakyBucket => LeakyBucket.state_keys(key, quota, now),
+        }
+    }
+}
+
+impl fmt::Display for AlgorithmKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AlgorithmKind {
+    type Err = ValidationError;
+
+    fn from_str(name: &str) -> Result<Self, ValidationError> {
+        AlgorithmKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == name)
+            .ok_or_else(|| ValidationError::UnknownAlgorithm(name.to_string()))
+    }
+}
+
+/// Maximum key length in bytes
+pub const MAX_KEY_LENGTH: usize = 1024;
+
+impl ThrottleRequest {
+    /// A request for one token of `key`, made now and checked with
+    /// [`AlgorithmKind::Gcra`]
+    ///
+    /// The other fields can be set with struct update syntax.
+    pub fn new(
+        key: impl Into<Arc<str>>,
+        max_burst: i64,
+        count_per_period: i64,
+        period: i64,
+    ) -> Self {
+        ThrottleRequest {
+            key: key.into(),
+            max_burst,
+            count_per_period,
+            period,
+            quantity: 1,
+            timestamp: SystemTime::now(),
+            algorithm: AlgorithmKind::Gcra,
+            warm_up: None,
+            warn_percent: None,
+        }
+    }
+
+    /// The request's rate limit parameters, for an [`Algorithm`]
+    pub fn quota(&self) -> Quota {
+        Quota::new(self.max_burst, self.count_per_period, self.period)
+    }
+
+    /// Check the request against the rules shared by all transports
+    ///
+    /// - `key` must be non-empty and at most [`MAX_KEY_LENGTH`] bytes
+    /// - `count_per_period` and `period` must be positive, and so must
+    ///   `max_burst` for the algorithms that use it
+    /// - `quantity` must be between 0 and the algorithm's capacity, a larger
+    ///   quantity could never be allowed: `count_per_period` for the window
+    ///   algorithms, which ignore `max_burst`, and `max_burst` for the others
+    ///
+    /// # Errors
+    ///
+    /// Returns the first rule the request violates.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if self.key.is_empty() {
+            return Err(ValidationError::EmptyKey);
+        }
+        if self.key.len() > MAX_KEY_LENGTH {
+            return Err(ValidationError::KeyTooLong(self.key.len()));
+        }
+        let windowed = matches!(
+            self.algorithm,
+            AlgorithmKind::SlidingWindow | AlgorithmKind::FixedWindow
+        );
+        if !windowed && self.max_burst <= 0 {
+            return Err(ValidationError::InvalidMaxBurst(self.max_burst));
+        }
+        if self.count_per_period <= 0 {
+            return Err(ValidationError::InvalidCountPerPeriod(
+                self.count_per_period,
+            ));
+        }
+        if self.period <= 0 {
+            return Err(ValidationError::InvalidPeriod(self.period));
+        }
+        if self.quantity < 0 {
+            return Err(ValidationError::NegativeQuantity(self.quantity));
+        }
+        if windowed {
+            if self.quantity > self.count_per_period {
+                return Err(ValidationError::QuantityExceedsCount {
+                    quantity: self.quantity,
+                    count_per_period: self.count_per_period,
+                });
+            }
+        } else if self.quantity > self.max_burst {
+            return Err(ValidationError::QuantityExceedsBurst {
+                quantity: self.quantity,
+                max_burst: self.max_burst,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Request for a concurrency lease
+///
+/// The lease request counterpart of [`ThrottleRequest`], used by every
+/// transport's acquire operation.
+#[derive(Debug, Clone)]
+pub struct AcquireRequest {
+    /// The key whose concurrent operations are limited (e.g., "reports:org:42")
+    pub key: Arc<str>,
+    /// Maximum leases held on the key at once
+    pub max_concurrent: i64,
+    /// Seconds until the lease expires if it is not released
+    pub ttl: i64,
+    /// Request timestamp
+    pub timestamp: SystemTime,
+}
+
+impl AcquireRequest {
+    /// Check the request against the rules shared by all transports
+    ///
+    /// - `key` must be non-empty and at most [`MAX_KEY_LENGTH`] bytes
+    /// - `max_concurrent` and `ttl` must be positive
+    ///
+    /// # Errors
+    ///
+    /// Returns the first rule the request violates.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if self.key.is_empty() {
+            return Err(ValidationError::EmptyKey);
+        }
+        if self.key.len() > MAX_KEY_LENGTH {
+            return Err(ValidationError::KeyTooLong(self.key.len()));
+        }
+        if self.max_concurrent <= 0 {
+            return Err(ValidationError::InvalidMaxConcurrent(self.max_concurrent));
+        }
+        if self.ttl <= 0 {
+            return Err(ValidationError::InvalidTtl(self.ttl));
+        }
+        Ok(())
+    }
+}
+
+/// A request that breaks one of the validation rules
+///
+/// Transports can detect it with `anyhow::Error::downcast_ref` and report
+/// it as a client error together with its [`code`](Self::code).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The key is empty
+    EmptyKey,
+    /// The key is longer than [`MAX_KEY_LENGTH`] bytes
+    KeyTooLong(usize),
+    /// `max_burst` is zero or negative
+    InvalidMaxBurst(i64),
+    /// `count_per_period` is zero or negative
+    InvalidCountPerPeriod(i64),
+    /// `period` is zero or negative
+    InvalidPeriod(i64),
+    /// `quantity` is negative
+    NegativeQuantity(i64),
+    /// `quantity` is larger than `max_burst`
+    QuantityExceedsBurst {
+        /// Requested quantity
+        quantity: i64,
+        /// Burst capacity of the limit
+        max_burst: i64,
+    },
+    /// `quantity` is larger than `count_per_period`, for a window algorithm
+    QuantityExceedsCount {
+        /// Requested quantity
+        quantity: i64,
+        /// Requests allowed per window
+        count_per_period: i64,
+    },
+    /// `max_concurrent` is zero or negative
+    InvalidMaxConcurrent(i64),
+    /// `ttl` is zero or negative
+    InvalidTtl(i64),
+    /// The algorithm name is not one of [`AlgorithmKind`]
+    UnknownAlgorithm(String),
+    /// The key is longer than a limit set by the application
+    KeyExceedsLimit {
+        /// Length of the key in bytes
+        len: usize,
+        /// Longest key allowed
+        max: usize,
+    },
+    /// The key contains a character the application does not allow
+    InvalidKeyCharacter(char),
+    /// `period` is longer than a limit set by the application
+    PeriodTooLong {
+        /// Requested period in seconds
+        period: i64,
+        /// Longest period allowed
+        max: i64,
+    },
+    /// `quantity` is larger than a limit set by the application
+    QuantityTooLarge {
+        /// Requested quantity
+        quantity: i64,
+        /// Largest quantity allowed
+        max: i64,
+    },
+}
+
+impl ValidationError {
+    /// Stable, machine-readable identifier of the violated rule
+    pub fn code(&self) -> &'static str {
+        match self {
+            ValidationError::EmptyKey => "empty_key",
+            ValidationError::KeyTooLong(_) => "key_too_long",
+            ValidationError::InvalidMaxBurst(_) => "invalid_max_burst",
+            ValidationError::InvalidCountPerPeriod(_) => "invalid_count_per_period",
+            ValidationError::InvalidPeriod(_) => "invalid_period",
+            ValidationError::NegativeQuantity(_) => "negative_quantity",
+            ValidationError::QuantityExceedsBurst { .. } => "quantity_exceeds_burst",
+            ValidationError::QuantityExceedsCount { .. } => "quantity_exceeds_count",
+            ValidationError::InvalidMaxConcurrent(_) => "invalid_max_concurrent",
+            ValidationError::InvalidTtl(_) => "invalid_ttl",
+            ValidationError::UnknownAlgorithm(_) => "unknown_algorithm",
+            ValidationError::KeyExceedsLimit { .. } => "key_too_long",
+            ValidationError::InvalidKeyCharacter(_) => "invalid_key_character",
+            ValidationError::PeriodTooLong { .. } => "period_too_long",
+            ValidationError::QuantityTooLarge { .. } => "quantity_too_large",
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::EmptyKey => write!(f, "key must not be empty"),
+            ValidationError::KeyTooLong(len) => {
+                write!(f, "key must be at most {MAX_KEY_LENGTH} bytes, got {len}")
+            }
+            ValidationError::InvalidMaxBurst(value) => {
+                write!(f, "max_burst must be positive, got {value}")
+            }
+            ValidationError::InvalidCountPerPeriod(value) => {
+                write!(f, "count_per_period must be positive, got {value}")
+            }
+            ValidationError::InvalidPeriod(value) => {
+                write!(f, "period must be positive, got {value}")
+            }
+            ValidationError::NegativeQuantity(value) => {
+                write!(f, "quantity must not be negative, got {value}")
+            }
+            ValidationError::QuantityExceedsBurst {
+                quantity,
+                max_burst,
+            } => write!(
+                f,
+                "quantity {quantity} exceeds max_burst {max_burst} and can never be allowed"
+            ),
+            ValidationError::QuantityExceedsCount {
+                quantity,
+                count_per_period,
+            } => write!(
+                f,
+                "quantity {quantity} exceeds count_per_period {count_per_period} and can never be allowed"
+            ),
+            ValidationError::InvalidMaxConcurrent(value) => {
+                write!(f, "max_concurrent must be positive, got {value}")
+            }
+            ValidationError::InvalidTtl(value) => {
+                write!(f, "ttl must be positive, got {value}")
+            }
+            ValidationError::UnknownAlgorithm(name) => {
+                write!(f, "unknown algorithm: {name}")
+            }
+            ValidationError::KeyExceedsLimit { len, max } => {
+                write!(f, "key must be at most {max} bytes, got {len}")
+            }
+            ValidationError::InvalidKeyCharacter(c) => {
+                write!(f, "key must not contain {c:?}")
+            }
+            ValidationError::PeriodTooLong { period, max } => {
+                write!(f, "period must be at most {max}, got {period}")
+            }
+            ValidationError::QuantityTooLarge { quantity, max } => {
+                write!(f, "quantity must be at most {max}, got {quantity}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Rate limit response structure
+///
+/// This is the common response format returned by all transports
+/// after checking a rate limit.
+///
+/// # Response Interpretation
+///
+/// - If `allowed` is true: The request can proceed
+/// - If `allowed` is false: The request should be rejected
+///   - Check `retry_after` to know when to retry
+///   - Check `reset_after` to know when the bucket resets
+///
+/// `retry_after` is in whole seconds rounded up, like an HTTP `Retry-After`
+/// header and [`AcquireResponse::retry_after`], so a client waiting that long
+/// is never early. `reset_after` is in whole seconds rounded down.
+/// `retry_after_ms` and `reset_after_ms` give the same delays in
+/// milliseconds, rounded up.
+///
+/// `cacheable_until_ms` lets a client answer further requests for the key
+/// itself: until that time it may allow up to `remaining` of them without
+/// asking, then must ask again. See
+/// [`with_cacheable_until`](ThrottleResponse::with_cacheable_until).
+///
+/// # Example
+///
+/// ```json
+/// {
+///   "allowed": false,
+///   "limit": 10,
+///   "remaining": 0,
+///   "retry_after": 30,
+///   "reset_after": 60,
+///   "retry_after_ms": 30000,
+///   "reset_after_ms": 60000,
+///   "cacheable_until_ms": 0
+/// }
+/// ```
+///
+/// This response indicates the request was denied, no tokens remain,
+/// retry in 30 seconds, and the bucket fully resets in 60 seconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThrottleResponse {
+    /// Whether the request is allowed
+    pub allowed: bool,
+    /// Maximum burst capacity
+    pub limit: i64,
+    /// Tokens remaining in the bucket
+    pub remaining: i64,
+    /// Seconds until the bucket fully resets
+    pub reset_after: i64,
+    /// Seconds until the next request can be made, rounded up (0 if allowed)
+    pub retry_after: i64,
+    /// Milliseconds until the next request can be made, rounded up
+    ///
+    /// 0 in responses from servers that predate it.
+    #[serde(default)]
+    pub retry_after_ms: i64,
+    /// Milliseconds until the bucket fully resets, rounded up
+    ///
+    /// 0 in responses from servers that predate it.
+    #[serde(default)]
+    pub reset_after_ms: i64,
+    /// Until when the client may allow up to `remaining` requests for the
+    /// key itself, in milliseconds since the Unix epoch
+    ///
+    /// 0 if the decision must not be cached, and in responses from servers
+    /// that predate it.
+    #[serde(default)]
+    pub cacheable_until_ms: i64,
+    /// Whether the key has used up to its soft limit, the request's
+    /// warning threshold
+    ///
+    /// Only written when true.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub warning: bool,
+}
+
+impl ThrottleResponse {
+    /// Set [`cacheable_until_ms`](Self::cacheable_until_ms) for a decision
+    /// made under `quota` at `now`
+    ///
+    /// An allowed decision with tokens left may be cached for `remaining`
+    /// emission intervals (`period / count_per_period`), the time the
+    /// limit takes to grant that many tokens at its sustained rate. A
+    /// client spending at most `remaining` tokens locally within that time
+    /// never takes more than the server would have granted it. Denied
+    /// decisions, and allowed ones with no tokens left, are not cacheable.
+    pub fn with_cacheable_until(mut self, quota: &Quota, now: SystemTime) -> Self {
+        self.cacheable_until_ms = 0;
+        if !self.allowed || self.remaining <= 0 || quota.count_per_period <= 0 || quota.period <= 0
+        {
+            return self;
+        }
+        let interval_ns = quota.period as u128 * 1_000_000_000 / quota.count_per_period as u128;
+        let cacheable_ns = interval_ns.saturating_mul(self.remaining as u128);
+        let Ok(now) = now.duration_since(UNIX_EPOCH) else {
+            return self;
+        };
+        // Round down, so the client never caches past the exact time
+        let until_ms = (now.as_nanos() + cacheable_ns) / 1_000_000;
+        self.cacheable_until_ms = i64::try_from(until_ms).unwrap_or(i64::MAX);
+        self
+    }
+
+    /// Set [`warning`](Self::warning) if at least `warn_percent` of the
+    /// limit is in use, i.e. `limit - remaining` tokens
+    ///
+    /// Denials always count as in use. With no threshold, the response is
+    /// never a warning.
+    pub fn with_warning(mut self, warn_percent: Option<u8>) -> Self {
+        self.warning = warn_percent.is_some_and(|percent| {
+            let used = self.limit.saturating_sub(self.remaining.max(0)) as i128;
+            !self.allowed || used * 100 >= self.limit as i128 * i128::from(percent)
+        });
+        self
+    }
+}
+
+impl From<(bool, RateLimitResult)> for ThrottleResponse {
+    fn from((allowed, result): (bool, RateLimitResult)) -> Self {
+        ThrottleResponse {
+            allowed,
+            limit: result.limit,
+            remaining: result.remaining,
+            reset_after: result.reset_after.as_secs() as i64,
+            retry_after: result.retry_after.as_nanos().div_ceil(1_000_000_000) as i64,
+            retry_after_ms: result.retry_after.as_nanos().div_ceil(1_000_000) as i64,
+            reset_after_ms: result.reset_after.as_nanos().div_ceil(1_000_000) as i64,
+            cacheable_until_ms: 0,
+            warning: false,
+        }
+    }
+}
+
+impl TryFrom<ThrottleResponse> for (bool, RateLimitResult) {
+    type Error = CellError;
+
+    /// Convert a response back into the result of
+    /// [`RateLimiter::rate_limit`](crate::RateLimiter::rate_limit)
+    ///
+    /// `retry_after_ms` and `reset_after_ms` are used when set, as they are
+    /// more precise than `retry_after` and `reset_after`. Fails if a
+    /// duration is negative.
+    fn try_from(response: ThrottleResponse) -> Result<Self, CellError> {
+        let reset_after = if response.reset_after_ms > 0 {
+            Duration::from_millis(response.reset_after_ms as u64)
+        } else {
+            let secs = u64::try_from(response.reset_after).map_err(|_| {
+                CellError::Internal(format!("negative reset_after: {}", response.reset_after))
+            })?;
+            Duration::from_secs(secs)
+        };
+        let retry_after = if response.retry_after_ms > 0 {
+            Duration::from_millis(response.retry_after_ms as u64)
+        } else {
+            let secs = u64::try_from(response.retry_after).map_err(|_| {
+                CellError::Internal(format!("negative retry_after: {}", response.retry_after))
+            })?;
+            Duration::from_secs(secs)
+        };
+        Ok((
+            response.allowed,
+            RateLimitResult {
+                limit: response.limit,
+                remaining: response.remaining,
+                reset_after,
+                retry_after,
+            },
+        ))
+    }
+}
+
+/// Concurrency lease response structure
+///
+/// The common response format of every transport's acquire operation.
+///
+/// # Example
+///
+/// ```json
+/// {
+///   "acquired": true,
+///   "lease_id": 42,
+///   "limit": 50,
+///   "active": 12,
+///   "retry_after": 0
+/// }
+/// ```
+///
+/// Pass `lease_id` to the release operation once the work is done. A denied
+/// response has no `lease_id`, and `retry_after` tells when the oldest lease
+/// expires at the latest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AcquireResponse {
+    /// Whether a lease was granted
+    pub acquired: bool,
+    /// The granted lease, for releasing it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_id: Option<u64>,
+    /// Maximum leases held on the key at once
+    pub limit: i64,
+    /// Leases held on the key, including the one granted
+    pub active: i64,
+    /// Seconds until the oldest lease expires, rounded up (0 if acquired)
+    pub retry_after: i64,
+}
+
+impl From<AcquireResult> for AcquireResponse {
+    fn from(result: AcquireResult) -> Self {
+        AcquireResponse {
+            acquired: result.acquired(),
+            lease_id: result.lease_id,
+            limit: result.limit,
+            active: result.active,
+            retry_after: result.retry_after.as_nanos().div_ceil(1_000_000_000) as i64,
+        }
+    }
+}
+
+impl TryFrom<CellError> for ValidationError {
+    type Error = CellError;
+
+    /// Convert the library errors caused by the request itself
+    ///
+    /// [`CellError::NegativeQuantity`] maps to
+    /// [`ValidationError::NegativeQuantity`]. The other variants do not say
+    /// which rule was broken, or are not caused by the request, and are
+    /// returned unchanged.
+    fn try_from(error: CellError) -> Result<Self, CellError> {
+        match error {
+            CellError::NegativeQuantity(quantity) => {
+                Ok(ValidationError::NegativeQuantity(quantity))
+            }
+            other => Err(other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConcurrencyLimiter, PeriodicStore, RateLimiter};
+
+    #[test]
+    fn test_validate() {
+        let request = ThrottleRequest::new("user:1", 10, 100, 60);
+        assert_eq!(
+            (request.quantity, request.algorithm),
+            (1, AlgorithmKind::Gcra)
+        );
+        assert_eq!(request.validate(), Ok(()));
+
+        // Boundaries that are still valid
+        let valid = [
+            ThrottleRequest {
+                key: "k".repeat(MAX_KEY_LENGTH).into(),
+                ..request.clone()
+            },
+            ThrottleRequest {
+                quantity: 0,
+                ..request.clone()
+            },
+            ThrottleRequest {
+                quantity: 10,
+                ..request.clone()
+            },
+            ThrottleRequest {
+                max_burst: 1,
+                count_per_period: 1,
+                period: 1,
+                quantity: 1,
+                ..request.clone()
+            },
+            ThrottleRequest {
+                max_burst: i64::MAX,
+                count_per_period: i64::MAX,
+                period: i64::MAX,
+                ..request.clone()
+            },
+        ];
+        for request in valid {
+            assert_eq!(request.validate(), Ok(()), "{request:?}");
+        }
+
+        let invalid = [
+            (
+                ThrottleRequest {
+                    key: "".into(),
+                    ..request.clone()
+                },
+                "empty_key",
+            ),
+            (
+                ThrottleRequest {
+                    key: "k".repeat(MAX_KEY_LENGTH + 1).into(),
+                    ..request.clone()
+                },
+                "key_too_long",
+            ),
+            (
+                ThrottleRequest {
+                    max_burst: 0,
+                    ..request.clone()
+                },
+                "invalid_max_burst",
+            ),
+            (
+                ThrottleRequest {
+                    max_burst: -1,
+                    quantity: 0,
+                    ..request.clone()
+                },
+                "invalid_max_burst",
+            ),
+            (
+                ThrottleRequest {
+                    count_per_period: 0,
+                    ..request.clone()
+                },
+                "invalid_count_per_period",
+            ),
+            (
+                ThrottleRequest {
+                    count_per_period: i64::MIN,
+                    ..request.clone()
+                },
+                "invalid_count_per_period",
+            ),
+            (
+                ThrottleRequest {
+                    period: 0,
+                    ..request.clone()
+                },
+                "invalid_period",
+            ),
+            (
+                ThrottleRequest {
+                    period: -60,
+                    ..request.clone()
+                },
+                "invalid_period",
+            ),
+            (
+                ThrottleRequest {
+                    quantity: -1,
+                    ..request.clone()
+                },
+                "negative_quantity",
+            ),
+            (
+                ThrottleRequest {
+                    quantity: 11,
+                    ..request.clone()
+                },
+                "quantity_exceeds_burst",
+            ),
+            (
+                ThrottleRequest {
+                    quantity: i64::MAX,
+                    ..request.clone()
+                },
+                "quantity_exceeds_burst",
+            ),
+        ];
+        for (request, code) in invalid {
+            let err = request.validate().unwrap_err();
+            assert_eq!(err.code(), code, "{request:?}");
+            assert!(!err.to_string().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_validate_per_algorithm() {
+        for algorithm in AlgorithmKind::ALL {
+            let request = |max_burst, quantity| ThrottleRequest {
+                quantity,
+                algorithm,
+                ..ThrottleRequest::new("user:1", max_burst, 100, 60)
+            };
+            let windowed = matches!(
+                algorithm,
+                AlgorithmKind::SlidingWindow | AlgorithmKind::FixedWindow
+            );
+            assert_eq!(request(10, 10).validate(), Ok(()), "{algorithm}");
+
+            let (over_burst, over_count) = (request(10, 11), request(10, 101));
+            let no_burst = request(0, 1);
+            if windowed {
+                // max_burst is ignored: only count_per_period bounds quantity
+                assert_eq!(over_burst.validate(), Ok(()), "{algorithm}");
+                assert_eq!(no_burst.validate(), Ok(()), "{algorithm}");
+                assert_eq!(
+                    over_count.validate().unwrap_err().code(),
+                    "quantity_exceeds_count",
+                    "{algorithm}"
+                );
+            } else {
+                assert_eq!(
+                    over_burst.validate().unwrap_err().code(),
+                    "quantity_exceeds_burst",
+                    "{algorithm}"
+                );
+                assert_eq!(
+                    no_burst.validate().unwrap_err().code(),
+                    "invalid_max_burst",
+                    "{algorithm}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_algorithm_kind() {
+        for algorithm in AlgorithmKind::ALL {
+            assert_eq!(algorithm.to_string().parse(), Ok(algorithm));
+        }
+        assert_eq!(AlgorithmKind::default(), AlgorithmKind::Gcra);
+
+        let error = "token_bucket".parse::<AlgorithmKind>().unwrap_err();
+        assert_eq!(error.code(), "unknown_algorithm");
+        assert_eq!(error.to_string(), "unknown algorithm: token_bucket");
+    }
+
+    #[test]
+    fn test_validate_acquire() {
+        let request = AcquireRequest {
+            key: "reports".into(),
+            max_concurrent: 50,
+            ttl: 60,
+            timestamp: SystemTime::now(),
+        };
+        assert_eq!(request.validate(), Ok(()));
+
+        let invalid = [
+            (
+                AcquireRequest {
+                    key: "".into(),
+                    ..request.clone()
+                },
+                "empty_key",
+            ),
+            (
+                AcquireRequest {
+                    max_concurrent: 0,
+                    ..request.clone()
+                },
+                "invalid_max_concurrent",
+            ),
+            (
+                AcquireRequest {
+                    ttl: -1,
+                    ..request.clone()
+                },
+                "invalid_ttl",
+            ),
+        ];
+        for (request, code) in invalid {
+            assert_eq!(request.validate().unwrap_err().code(), code, "{request:?}");
+        }
+    }
+
+    #[test]
+    fn test_acquire_response_from_result() {
+        let mut limiter = ConcurrencyLimiter::new();
+        let now = SystemTime::now();
+        let ttl = Duration::from_millis(1_500);
+
+        let granted = AcquireResponse::from(limiter.acquire("jobs", 1, ttl, now).unwrap());
+        assert!(granted.acquired);
+        assert_eq!(granted.lease_id, Some(1));
+        assert_eq!(granted.retry_after, 0);
+
+        let denied = AcquireResponse::from(limiter.acquire("jobs", 1, ttl, now).unwrap());
+        assert!(!denied.acquired);
+        assert_eq!(denied.lease_id, None);
+        assert_eq!(denied.active, 1);
+        assert_eq!(denied.retry_after, 2);
+    }
+
+    #[test]
+    fn test_retry_after_rounds_up() {
+        let result = RateLimitResult {
+            limit: 10,
+            remaining: 0,
+            reset_after: Duration::from_millis(1_400),
+            retry_after: Duration::from_millis(400),
+        };
+        let response = ThrottleResponse::from((false, result.clone()));
+        assert_eq!(response.retry_after, 1);
+        assert_eq!(response.retry_after_ms, 400);
+        assert_eq!(response.reset_after, 1);
+
+        // The same as a lease's, and whole seconds stay as they are
+        let mut leases = ConcurrencyLimiter::new();
+        let now = SystemTime::now();
+        let ttl = Duration::from_millis(400);
+        leases.acquire("jobs", 1, ttl, now).unwrap();
+        let denied = AcquireResponse::from(leases.acquire("jobs", 1, ttl, now).unwrap());
+        assert_eq!(denied.retry_after, 1);
+        let whole = RateLimitResult {
+            retry_after: Duration::from_secs(2),
+            ..result
+        };
+        assert_eq!(ThrottleResponse::from((false, whole)).retry_after, 2);
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let mut limiter = RateLimiter::new(PeriodicStore::new());
+        let now = SystemTime::now();
+        limiter.rate_limit("user:1", 2, 60, 60, 2, now).unwrap();
+        let (allowed, result) = limiter.rate_limit("user:1", 2, 60, 60, 1, now).unwrap();
+        assert!(!allowed);
+
+        let response = ThrottleResponse::from((allowed, result.clone()));
+        assert_eq!(response.retry_after_ms, 1_000);
+        assert_eq!(response.reset_after_ms, 2_000);
+        let (round_allowed, round_result): (bool, RateLimitResult) =
+            response.clone().try_into().unwrap();
+        assert_eq!(round_allowed, allowed);
+        assert_eq!(round_result.limit, result.limit);
+        assert_eq!(round_result.remaining, result.remaining);
+        assert_eq!(round_result.retry_after, result.retry_after);
+        assert_eq!(round_result.reset_after, result.reset_after);
+
+        // Responses from older servers carry whole seconds only
+        let (_, from_old) = <(bool, RateLimitResult)>::try_from(ThrottleResponse {
+            retry_after_ms: 0,
+            reset_after_ms: 0,
+            ..response.clone()
+        })
+        .unwrap();
+        assert_eq!(from_old.retry_after, Duration::from_secs(1));
+        assert_eq!(from_old.reset_after, Duration::from_secs(2));
+
+        assert!(
+            <(bool, RateLimitResult)>::try_from(ThrottleResponse {
+                reset_after: -1,
+                reset_after_ms: 0,
+                ..response
+            })
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_cacheable_until() {
+        let mut limiter = RateLimiter::new(PeriodicStore::new());
+        let now = UNIX_EPOCH + Duration::from_secs(1_704_067_200);
+        let quota = Quota::new(10, 100, 60);
+
+        // 7 tokens left at 0.6s each
+        let decision = limiter.rate_limit("user:1", 10, 100, 60, 3, now).unwrap();
+        let response = ThrottleResponse::from(decision).with_cacheable_until(&quota, now);
+        assert_eq!(response.remaining, 7);
+        assert_eq!(response.cacheable_until_ms, 1_704_067_204_200);
+
+        // Nothing left to cache, and denials are never cacheable
+        let decision = limiter.rate_limit("user:1", 10, 100, 60, 7, now).unwrap();
+        let response = ThrottleResponse::from(decision).with_cacheable_until(&quota, now);
+        assert_eq!(response.cacheable_until_ms, 0);
+        let decision = limiter.rate_limit("user:1", 10, 100, 60, 1, now).unwrap();
+        assert!(!decision.0);
+        let response = ThrottleResponse::from(decision).with_cacheable_until(&quota, now);
+        assert_eq!(response.cacheable_until_ms, 0);
+    }
+
+    #[test]
+    fn test_warning() {
+        let mut limiter = RateLimiter::new(PeriodicStore::new());
+        let now = UNIX_EPOCH + Duration::from_secs(1_704_067_200);
+
+        // 7 of 10 tokens in use, below an 80% threshold
+        let decision = limiter.rate_limit("user:1", 10, 100, 60, 7, now).unwrap();
+        let response = ThrottleResponse::from(decision);
+        assert!(!response.clone().with_warning(Some(80)).warning);
+        assert!(response.clone().with_warning(Some(70)).warning);
+        assert!(!response.with_warning(None).warning);
+
+        let decision = limiter.rate_limit("user:1", 10, 100, 60, 1, now).unwrap();
+        assert!(
+            ThrottleResponse::from(decision)
+                .with_warning(Some(80))
+                .warning
+        );
+        let decision = limiter.rate_limit("user:1", 10, 100, 60, 5, now).unwrap();
+        assert!(!decision.0);
+        assert!(
+            ThrottleResponse::from(decision)
+                .with_warning(Some(100))
+                .warning
+        );
+    }
+
+    #[test]
+    fn test_validation_error_from_cell_error() {
+        assert!(matches!(
+            ValidationError::try_from(CellError::NegativeQuantity(-1)),
+            Ok(ValidationError::NegativeQuantity(-1))
+        ));
+        assert!(matches!(
+            ValidationError::try_from(CellError::InvalidRateLimit),
+            Err(CellError::InvalidRateLimit)
+        ));
+    }
+}